@@ -0,0 +1,244 @@
+//! Output formats & workflow generators for launcher tools like Alfred and
+//! Raycast so they can query spyglass w/o opening the search window.
+use serde::Serialize;
+
+use shared::response::SearchResults;
+
+/// Alfred Script Filter JSON format.
+/// See: https://www.alfredapp.com/help/workflows/inputs/script-filter/json/
+#[derive(Debug, Serialize)]
+pub struct AlfredResults {
+    pub items: Vec<AlfredItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AlfredItem {
+    pub uid: String,
+    pub title: String,
+    pub subtitle: String,
+    /// Passed along to the next action in the workflow, i.e. the URL to open.
+    pub arg: String,
+    pub quicklookurl: String,
+    pub valid: bool,
+}
+
+/// Mirrors the props of a Raycast `List.Item` so extensions can render the
+/// results directly.
+#[derive(Debug, Serialize)]
+pub struct RaycastResults {
+    pub items: Vec<RaycastItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RaycastItem {
+    pub id: String,
+    pub title: String,
+    pub subtitle: String,
+    pub url: String,
+    pub accessories: Vec<RaycastAccessory>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RaycastAccessory {
+    pub text: String,
+}
+
+pub fn to_alfred(results: &SearchResults) -> AlfredResults {
+    let mut items = Vec::new();
+    if let Some(answer) = &results.answer {
+        items.push(AlfredItem {
+            uid: "answer".into(),
+            title: answer.answer.clone(),
+            subtitle: answer.query.clone(),
+            arg: answer.answer.clone(),
+            quicklookurl: String::new(),
+            valid: true,
+        });
+    }
+
+    items.extend(results.results.iter().map(|result| AlfredItem {
+        uid: result.doc_id.clone(),
+        title: result.title.clone(),
+        subtitle: result.url.clone(),
+        arg: result.url.clone(),
+        quicklookurl: result.url.clone(),
+        valid: true,
+    }));
+
+    AlfredResults { items }
+}
+
+pub fn to_raycast(results: &SearchResults) -> RaycastResults {
+    let items = results
+        .results
+        .iter()
+        .map(|result| RaycastItem {
+            id: result.doc_id.clone(),
+            title: result.title.clone(),
+            subtitle: result.description.clone(),
+            url: result.url.clone(),
+            accessories: vec![RaycastAccessory {
+                text: result.domain.clone(),
+            }],
+        })
+        .collect();
+
+    RaycastResults { items }
+}
+
+/// Generates the `info.plist` for an Alfred workflow w/ a script filter that
+/// calls `cli_path` & opens the selected result in the browser.
+pub fn alfred_workflow(cli_path: &str, keyword: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>bundleid</key>
+    <string>fyi.spyglass.alfred</string>
+    <key>name</key>
+    <string>Spyglass</string>
+    <key>description</key>
+    <string>Search your Spyglass index</string>
+    <key>connections</key>
+    <dict>
+        <key>spyglass.search</key>
+        <array>
+            <dict>
+                <key>destinationuid</key>
+                <string>spyglass.open</string>
+                <key>modifiers</key>
+                <integer>0</integer>
+                <key>modifiersubtext</key>
+                <string></string>
+            </dict>
+        </array>
+    </dict>
+    <key>objects</key>
+    <array>
+        <dict>
+            <key>config</key>
+            <dict>
+                <key>keyword</key>
+                <string>{keyword}</string>
+                <key>script</key>
+                <string>{cli_path} search "$1" --format alfred</string>
+                <key>scriptargtype</key>
+                <integer>1</integer>
+                <key>type</key>
+                <integer>0</integer>
+                <key>withspace</key>
+                <true/>
+                <key>title</key>
+                <string>Search Spyglass</string>
+            </dict>
+            <key>type</key>
+            <string>alfred.workflow.input.scriptfilter</string>
+            <key>uid</key>
+            <string>spyglass.search</string>
+        </dict>
+        <dict>
+            <key>config</key>
+            <dict>
+                <key>browser</key>
+                <string></string>
+                <key>url</key>
+                <string>{{query}}</string>
+            </dict>
+            <key>type</key>
+            <string>alfred.workflow.action.openurl</string>
+            <key>uid</key>
+            <string>spyglass.open</string>
+        </dict>
+    </array>
+</dict>
+</plist>
+"#,
+        keyword = xml_escape(keyword),
+        cli_path = xml_escape(&shell_quote(cli_path)),
+    )
+}
+
+/// Generates a Raycast script command that runs a search through `cli_path`.
+pub fn raycast_script(cli_path: &str) -> String {
+    format!(
+        r#"#!/bin/bash
+
+# Required parameters:
+# @raycast.schemaVersion 1
+# @raycast.title Search Spyglass
+# @raycast.mode fullOutput
+# @raycast.packageName Spyglass
+
+# Optional parameters:
+# @raycast.argument1 {{ "type": "text", "placeholder": "Query" }}
+
+{} search "$1"
+"#,
+        shell_quote(cli_path)
+    )
+}
+
+/// Single-quote `text` for the shell, so paths w/ spaces, quotes, or `$`
+/// are passed through as is.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{alfred_workflow, raycast_script, to_alfred, to_raycast};
+    use shared::response::{SearchResult, SearchResults};
+
+    fn results() -> SearchResults {
+        SearchResults {
+            results: vec![SearchResult {
+                doc_id: "abc".into(),
+                domain: "example.com".into(),
+                title: "Example".into(),
+                description: "An example page".into(),
+                url: "https://example.com".into(),
+                score: 1.0,
+                is_archived: false,
+                snippet: None,
+                metadata: Default::default(),
+                favicon: None,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_alfred_format() {
+        let json = serde_json::to_value(to_alfred(&results())).unwrap();
+        assert_eq!(json["items"][0]["title"], "Example");
+        assert_eq!(json["items"][0]["arg"], "https://example.com");
+
+        let plist = alfred_workflow("/usr/local/bin/spyglass-cli", "sg");
+        assert!(plist.contains("<string>sg</string>"));
+        assert!(plist.contains(r#"'/usr/local/bin/spyglass-cli' search "$1" --format alfred"#));
+    }
+
+    #[test]
+    fn test_raycast_script() {
+        let script = raycast_script("/usr/local/bin/spyglass-cli");
+        assert!(script.contains("\n'/usr/local/bin/spyglass-cli' search \"$1\"\n"));
+
+        let script = raycast_script("/Users/me/it's \"$HOME\"/spyglass-cli");
+        assert!(script.contains(r#"'/Users/me/it'\''s "$HOME"/spyglass-cli' search"#));
+    }
+
+    #[test]
+    fn test_raycast_format() {
+        let json = serde_json::to_value(to_raycast(&results())).unwrap();
+        assert_eq!(json["items"][0]["url"], "https://example.com");
+        assert_eq!(json["items"][0]["accessories"][0]["text"], "example.com");
+    }
+}
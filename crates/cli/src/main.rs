@@ -0,0 +1,1131 @@
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use clap::{ArgEnum, Parser, Subcommand};
+use jsonrpc_core_client::{transports::ipc, TypedClient};
+use serde_json::Value;
+
+use shared::config::Config;
+use shared::request::{
+    BulkQueueParam, DeleteDomainParam, ExportFormat, ExportResultsParam, ListQueueParam,
+    QueueItemParam, RecentCrawlsParam, RemoveQueueItemsParam, SearchParam, UninstallLensParam,
+    DEFAULT_LIST_QUEUE_LIMIT, DEFAULT_RECENT_CRAWLS_LIMIT, DEFAULT_SEARCH_LIMIT,
+};
+use shared::response::{
+    AddQueueResult, AppStatus, CrawlLogEntry, DeleteDomainResult, DocumentResult, IndexStats,
+    LensResult, ListQueueResp, QueueItemStatus, ScoreExplanation, SearchExplanation, SearchResults,
+    TaskResult,
+};
+use shared::rpc::{authenticate, gen_ipc_path};
+
+mod launcher;
+
+/// Number of URLs sent to the server at a time by `import`.
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+/// Command line interface to a running spyglass server.
+#[derive(Debug, Parser)]
+#[clap(name = "spyglass-cli", version)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Search the index
+    Search {
+        query: String,
+        /// Limit search to one or more lenses
+        #[clap(short, long)]
+        lens: Vec<String>,
+        /// Number of results to skip
+        #[clap(long, default_value_t = 0)]
+        offset: usize,
+        /// Max number of results to show
+        #[clap(long, default_value_t = DEFAULT_SEARCH_LIMIT)]
+        limit: usize,
+        /// Match terms w/ typos in them
+        #[clap(long)]
+        fuzzy: bool,
+        /// Output results as JSON, same as `--format json`
+        #[clap(long)]
+        json: bool,
+        /// Output format, use `alfred` or `raycast` when calling from a launcher
+        #[clap(long, arg_enum, default_value = "plain")]
+        format: OutputFormat,
+    },
+    /// Show how each result for a search was scored
+    Explain {
+        query: String,
+        /// Limit search to one or more lenses
+        #[clap(short, long)]
+        lens: Vec<String>,
+        /// Max number of results to explain
+        #[clap(long, default_value_t = DEFAULT_SEARCH_LIMIT)]
+        limit: usize,
+        /// Output the explanation as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Write every result for a search to a file, as JSON Lines by default
+    Export {
+        query: String,
+        /// File to write the results to
+        out: PathBuf,
+        /// Limit search to one or more lenses
+        #[clap(short, long)]
+        lens: Vec<String>,
+        /// Write CSV instead of JSON Lines
+        #[clap(long)]
+        csv: bool,
+    },
+    /// Add a URL to the crawl queue
+    Add {
+        url: String,
+        /// Only follow links this many hops away from the URL
+        #[clap(long)]
+        max_depth: Option<u32>,
+        /// Crawled before queued URLs w/ a lower priority
+        #[clap(long)]
+        priority: Option<i32>,
+    },
+    /// Add URLs from a file to the crawl queue, one per line. Use `-` to read
+    /// from stdin
+    Import {
+        file: PathBuf,
+        /// Only follow links this many hops away from each URL
+        #[clap(long)]
+        max_depth: Option<u32>,
+        /// Crawled before queued URLs w/ a lower priority
+        #[clap(long)]
+        priority: Option<i32>,
+    },
+    /// Pause crawling a single domain or lens
+    Pause {
+        /// Domain, e.g. `example.com`
+        #[clap(long, conflicts_with = "lens", required_unless_present = "lens")]
+        domain: Option<String>,
+        /// Name of the lens
+        #[clap(long)]
+        lens: Option<String>,
+    },
+    /// Resume crawling a paused domain or lens
+    Resume {
+        /// Domain, e.g. `example.com`
+        #[clap(long, conflicts_with = "lens", required_unless_present = "lens")]
+        domain: Option<String>,
+        /// Name of the lens
+        #[clap(long)]
+        lens: Option<String>,
+    },
+    /// Crawl a URL or every known URL of a domain again, even if it was
+    /// fetched recently
+    Recrawl {
+        #[clap(conflicts_with = "domain", required_unless_present = "domain")]
+        url: Option<String>,
+        /// Domain, e.g. `example.com`
+        #[clap(long)]
+        domain: Option<String>,
+    },
+    /// Show a single document w/ its full text
+    Document {
+        doc_id: String,
+        /// Output the document as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Remove a domain's documents, queued URLs & fetch history
+    DeleteDomain {
+        /// Domain, e.g. `example.com`
+        domain: String,
+        /// Only show how much would be removed
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Show the current server status
+    Status {
+        /// Output status as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Show the most recent crawl attempts, newest first
+    Crawls {
+        /// Only show attempts for this domain
+        #[clap(long)]
+        domain: Option<String>,
+        /// Only show attempts that failed or were skipped
+        #[clap(long)]
+        errors: bool,
+        /// Max number of attempts to show
+        #[clap(long, default_value_t = DEFAULT_RECENT_CRAWLS_LIMIT)]
+        limit: u64,
+        /// Output attempts as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// List URLs in the crawl queue, most recently updated first
+    Queue {
+        /// Only show URLs w/ this status
+        #[clap(long, arg_enum)]
+        status: Option<QueueStatusArg>,
+        /// Only show URLs for this domain
+        #[clap(long)]
+        domain: Option<String>,
+        /// Max number of URLs to show
+        #[clap(long, default_value_t = DEFAULT_LIST_QUEUE_LIMIT)]
+        limit: u64,
+        /// Number of URLs to skip
+        #[clap(long, default_value_t = 0)]
+        offset: u64,
+        /// Output the queue as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Remove URLs from the crawl queue matching all of the given filters
+    Unqueue {
+        /// IDs of the queued URLs, as listed by `queue`
+        ids: Vec<i64>,
+        /// Only remove URLs for this domain
+        #[clap(long)]
+        domain: Option<String>,
+        /// Only remove URLs w/ this status
+        #[clap(long, arg_enum)]
+        status: Option<QueueStatusArg>,
+    },
+    /// List scheduled background tasks
+    Tasks {
+        /// Output tasks as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Show how much space the index takes up, per field
+    IndexStats {
+        /// Output stats as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Merge index segments in the background to speed up searches
+    Optimize,
+    /// Save the database & index to a single archive
+    Backup {
+        /// Archive to write, replaced if it already exists
+        out: PathBuf,
+    },
+    /// Replace the database & index w/ a backup, takes effect once the server
+    /// restarts
+    Restore { archive: PathBuf },
+    /// Manage lenses
+    Lens {
+        #[clap(subcommand)]
+        command: LensCommand,
+    },
+    /// Print the URL to open to add spyglass as a search engine in your browser
+    Opensearch,
+    /// Generate a workflow/script for a launcher tool
+    Workflow {
+        #[clap(arg_enum)]
+        launcher: Launcher,
+        /// Keyword used to trigger the search (Alfred only)
+        #[clap(long, default_value = "sg")]
+        keyword: String,
+        /// Write to this file instead of stdout
+        #[clap(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, ArgEnum)]
+enum OutputFormat {
+    Plain,
+    Json,
+    Alfred,
+    Raycast,
+}
+
+#[derive(Clone, Debug, PartialEq, ArgEnum)]
+enum QueueStatusArg {
+    Queued,
+    Processing,
+    Completed,
+    Failed,
+}
+
+impl From<QueueStatusArg> for QueueItemStatus {
+    fn from(status: QueueStatusArg) -> Self {
+        match status {
+            QueueStatusArg::Queued => QueueItemStatus::Queued,
+            QueueStatusArg::Processing => QueueItemStatus::Processing,
+            QueueStatusArg::Completed => QueueItemStatus::Completed,
+            QueueStatusArg::Failed => QueueItemStatus::Failed,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, ArgEnum)]
+enum Launcher {
+    Alfred,
+    Raycast,
+}
+
+#[derive(Debug, Subcommand)]
+enum LensCommand {
+    /// Install a lens from the lens directory by name, or from a URL
+    Install { name_or_url: String },
+    /// Remove an installed lens
+    Uninstall {
+        name: String,
+        /// Also remove the lens's queued & indexed pages
+        #[clap(long)]
+        purge: bool,
+    },
+    /// Update an installed lens to the latest version in the lens directory
+    Update { name: String },
+}
+
+async fn connect() -> anyhow::Result<TypedClient> {
+    let client: TypedClient = ipc::connect(gen_ipc_path())
+        .await
+        .map_err(|err| anyhow!("Unable to connect to spyglass server: {}", err))?;
+    authenticate(&client).await?;
+
+    Ok(client)
+}
+
+fn print_score_explanation(explanation: &ScoreExplanation, depth: usize) {
+    println!(
+        "{:indent$}{:.3} {}",
+        "",
+        explanation.value,
+        explanation.description,
+        indent = depth * 2
+    );
+    for detail in explanation.details.iter() {
+        print_score_explanation(detail, depth + 1);
+    }
+}
+
+async fn explain(params: SearchParam, json: bool) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let resp = client
+        .call_method::<(SearchParam,), SearchExplanation>("explain_search", "", (params,))
+        .await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&resp)?);
+        return Ok(());
+    }
+
+    println!("query: {}", resp.parsed_query);
+    for lens in resp.lenses.iter() {
+        println!(
+            "lens {} (boost {}): {}",
+            lens.name,
+            lens.boost,
+            lens.domains
+                .iter()
+                .chain(lens.urls.iter())
+                .cloned()
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+    println!("{} matching documents\n", resp.num_docs);
+
+    for result in resp.results.iter() {
+        println!("{}\n  {}", result.title, result.url);
+        println!(
+            "  score {:.3} = text {:.3} x lens {:.2} x recency {:.2}",
+            result.score, result.text_score, result.lens_boost, result.recency_boost
+        );
+        print_score_explanation(&result.explanation, 2);
+    }
+
+    Ok(())
+}
+
+async fn search(params: SearchParam, format: OutputFormat) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let offset = params.offset;
+    let resp = client
+        .call_method::<(SearchParam,), SearchResults>("search_docs", "", (params,))
+        .await?;
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&resp)?),
+        OutputFormat::Alfred => println!("{}", serde_json::to_string(&launcher::to_alfred(&resp))?),
+        OutputFormat::Raycast => {
+            println!("{}", serde_json::to_string(&launcher::to_raycast(&resp))?)
+        }
+        OutputFormat::Plain => {
+            if let Some(answer) = &resp.answer {
+                println!("= {}\n", answer.answer);
+            }
+
+            for result in resp.results.iter() {
+                println!("{}\n  {}", result.title, result.url);
+            }
+
+            if !resp.results.is_empty() {
+                println!(
+                    "\n{}-{} of {} results ({}ms)",
+                    offset + 1,
+                    offset + resp.results.len(),
+                    resp.meta.num_hits,
+                    resp.meta.wall_time_ms
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn export(
+    query: String,
+    out: PathBuf,
+    lenses: Vec<String>,
+    format: ExportFormat,
+) -> anyhow::Result<()> {
+    // The file is written by the server, which has its own working directory.
+    let out = std::env::current_dir()?.join(out);
+    let params = ExportResultsParam {
+        lenses,
+        query,
+        path: out.to_string_lossy().to_string(),
+        format,
+    };
+
+    let client = connect().await?;
+    let num_exported = client
+        .call_method::<(ExportResultsParam,), u64>("export_results", "", (params,))
+        .await?;
+    println!("Exported {} results to {}", num_exported, out.display());
+
+    Ok(())
+}
+
+async fn add(url: String, max_depth: Option<u32>, priority: Option<i32>) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let params = QueueItemParam {
+        url: url.clone(),
+        force_crawl: false,
+        max_depth,
+        priority,
+    };
+
+    client
+        .call_method::<(QueueItemParam,), String>("add_queue", "", (params,))
+        .await?;
+    println!("Added <{}> to the crawl queue", url);
+
+    Ok(())
+}
+
+async fn import(
+    file: PathBuf,
+    max_depth: Option<u32>,
+    priority: Option<i32>,
+) -> anyhow::Result<()> {
+    let contents = if file.as_os_str() == "-" {
+        let mut contents = String::new();
+        std::io::stdin().read_to_string(&mut contents)?;
+        contents
+    } else {
+        fs::read_to_string(&file)?
+    };
+    let urls: Vec<String> = contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect();
+
+    let client = connect().await?;
+    let mut num_added = 0;
+    for chunk in urls.chunks(IMPORT_BATCH_SIZE) {
+        let params = BulkQueueParam {
+            urls: chunk.to_vec(),
+            max_depth,
+            priority,
+        };
+        let results = client
+            .call_method::<(BulkQueueParam,), Vec<AddQueueResult>>("add_queue_bulk", "", (params,))
+            .await?;
+
+        for result in results {
+            match result.error {
+                Some(error) => eprintln!("Skipped <{}>: {}", result.url, error),
+                None => num_added += 1,
+            }
+        }
+    }
+    println!(
+        "Added {} of {} URLs to the crawl queue",
+        num_added,
+        urls.len()
+    );
+
+    Ok(())
+}
+
+async fn set_paused(
+    domain: Option<String>,
+    lens: Option<String>,
+    pause: bool,
+) -> anyhow::Result<()> {
+    let (method, target) = match (domain, lens) {
+        (Some(domain), _) => (
+            if pause {
+                "pause_domain"
+            } else {
+                "resume_domain"
+            },
+            domain,
+        ),
+        (None, Some(lens)) => (if pause { "pause_lens" } else { "resume_lens" }, lens),
+        (None, None) => return Err(anyhow!("Either a domain or lens is required")),
+    };
+
+    let client = connect().await?;
+    client
+        .call_method::<(String,), AppStatus>(method, "", (target.clone(),))
+        .await?;
+    println!(
+        "{} crawling {}",
+        if pause { "Paused" } else { "Resumed" },
+        target
+    );
+
+    Ok(())
+}
+
+async fn recrawl(url: Option<String>, domain: Option<String>) -> anyhow::Result<()> {
+    let (method, target) = match (url, domain) {
+        (Some(url), _) => ("recrawl_url", url),
+        (None, Some(domain)) => ("recrawl_domain", domain),
+        (None, None) => return Err(anyhow!("Either a URL or domain is required")),
+    };
+
+    let client = connect().await?;
+    let num_queued = client
+        .call_method::<(String,), u64>(method, "", (target.clone(),))
+        .await?;
+    println!("Queued {} URLs from {} for recrawling", num_queued, target);
+
+    Ok(())
+}
+
+async fn delete_domain(domain: String, dry_run: bool) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let param = DeleteDomainParam {
+        domain: domain.clone(),
+        dry_run,
+    };
+    let result = client
+        .call_method::<(DeleteDomainParam,), DeleteDomainResult>("delete_domain", "", (param,))
+        .await?;
+
+    println!(
+        "{} {}: {} documents, {} queued URLs, {} fetch history entries",
+        if dry_run {
+            "Would remove from"
+        } else {
+            "Removed from"
+        },
+        domain,
+        result.num_documents,
+        result.num_queued,
+        result.num_fetch_history
+    );
+
+    Ok(())
+}
+
+async fn document(doc_id: String, json: bool) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let doc = client
+        .call_method::<(String,), DocumentResult>("get_document", "", (doc_id,))
+        .await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&doc)?);
+    } else {
+        println!("{}", doc.title);
+        println!("{}", doc.url);
+        if let Some(updated_at) = &doc.updated_at {
+            println!("indexed: {}", updated_at);
+        }
+        if let Some(fetched_at) = &doc.last_fetched_at {
+            println!("fetched: {}", fetched_at);
+        }
+        if !doc.lenses.is_empty() {
+            println!("lenses: {}", doc.lenses.join(", "));
+        }
+        println!();
+        println!("{}", doc.content);
+    }
+
+    Ok(())
+}
+
+async fn status(json: bool) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let status = client
+        .call_method::<Value, AppStatus>("app_status", "", Value::Null)
+        .await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+    } else {
+        println!("documents: {}", status.num_docs);
+        println!(
+            "crawler: {}",
+            if status.is_paused {
+                "paused"
+            } else {
+                "running"
+            }
+        );
+        if !status.paused_domains.is_empty() {
+            println!("paused domains: {}", status.paused_domains.join(", "));
+        }
+        if !status.paused_lenses.is_empty() {
+            println!("paused lenses: {}", status.paused_lenses.join(", "));
+        }
+        println!("memory: {} MB", status.memory.rss / 1_000_000);
+        println!(
+            "pipeline: {} fetch / {} parse / {} index queued",
+            status.pipeline.fetch_queue, status.pipeline.parse_queue, status.pipeline.index_queue
+        );
+        println!(
+            "disk: {} index / {} db",
+            format_bytes(status.disk.index),
+            format_bytes(status.disk.db)
+        );
+        println!(
+            "errors: {} failed / {} in crawl log",
+            status.errors.num_failed, status.errors.num_logged
+        );
+        for lens in status.lenses.iter() {
+            println!("lens {}: {} documents", lens.name, lens.num_docs);
+        }
+        for domain in status.top_domains.iter() {
+            println!("domain {}: {} documents", domain.domain, domain.num_docs);
+        }
+    }
+
+    Ok(())
+}
+
+async fn crawls(param: RecentCrawlsParam, json: bool) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let entries = client
+        .call_method::<(RecentCrawlsParam,), Vec<CrawlLogEntry>>("recent_crawls", "", (param,))
+        .await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        for entry in entries.iter() {
+            let status = entry
+                .status
+                .map(|status| status.to_string())
+                .unwrap_or_else(|| "---".to_string());
+            println!(
+                "{} {} {} ({} ms, {})",
+                entry.crawled_at,
+                status,
+                entry.url,
+                entry.duration_ms,
+                format_bytes(entry.num_bytes)
+            );
+            if let Some(error) = &entry.error {
+                println!("  error: {}", error);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn queue(param: ListQueueParam, json: bool) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let offset = param.offset;
+    let resp = client
+        .call_method::<(ListQueueParam,), ListQueueResp>("list_queue", "", (param,))
+        .await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&resp)?);
+    } else {
+        for item in resp.items.iter() {
+            println!(
+                "{} {:?} {} ({})",
+                item.id, item.status, item.url, item.updated_at
+            );
+        }
+
+        if !resp.items.is_empty() {
+            println!(
+                "\n{}-{} of {} URLs",
+                offset + 1,
+                offset + resp.items.len() as u64,
+                resp.num_items
+            );
+        }
+        println!(
+            "queued: {} / processing: {} / completed: {} / failed: {}",
+            resp.counts.num_queued,
+            resp.counts.num_processing,
+            resp.counts.num_completed,
+            resp.counts.num_failed
+        );
+    }
+
+    Ok(())
+}
+
+async fn unqueue(param: RemoveQueueItemsParam) -> anyhow::Result<()> {
+    if param.ids.is_empty() && param.domain.is_none() && param.status.is_none() {
+        return Err(anyhow!("Either ids, a domain or a status is required"));
+    }
+
+    let client = connect().await?;
+    let num_removed = client
+        .call_method::<(RemoveQueueItemsParam,), u64>("remove_queue_items", "", (param,))
+        .await?;
+    println!("Removed {} URLs from the crawl queue", num_removed);
+
+    Ok(())
+}
+
+async fn tasks(json: bool) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let tasks = client
+        .call_method::<Value, Vec<TaskResult>>("list_tasks", "", Value::Null)
+        .await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&tasks)?);
+    } else {
+        for task in tasks.iter() {
+            println!("{} ({})", task.name, task.schedule);
+            println!("  next run: {}", task.next_run);
+            if let Some(last_run) = &task.last_run {
+                println!("  last run: {}", last_run);
+            }
+            if let Some(error) = &task.last_error {
+                println!("  last error: {}", error);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        bytes if bytes >= 1_000_000_000 => format!("{:.1} GB", bytes as f64 / 1e9),
+        bytes if bytes >= 1_000_000 => format!("{:.1} MB", bytes as f64 / 1e6),
+        bytes if bytes >= 1_000 => format!("{:.1} KB", bytes as f64 / 1e3),
+        bytes => format!("{} B", bytes),
+    }
+}
+
+async fn index_stats(json: bool) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let stats = client
+        .call_method::<Value, IndexStats>("index_stats", "", Value::Null)
+        .await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        println!(
+            "{} documents in {} segments, {} total",
+            stats.num_docs,
+            stats.num_segments,
+            format_bytes(stats.total_bytes)
+        );
+        println!("doc store: {} compressed", format_bytes(stats.store_bytes));
+        for field in stats.fields.iter() {
+            println!(
+                "  {:<12} {:>10} indexed {:>10} stored (est.)",
+                field.name,
+                format_bytes(field.indexed_bytes),
+                format_bytes(field.stored_bytes)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn optimize() -> anyhow::Result<()> {
+    let client = connect().await?;
+    client
+        .call_method::<Value, ()>("index_optimize", "", Value::Null)
+        .await?;
+    println!("Optimizing index in the background");
+
+    Ok(())
+}
+
+async fn backup(out: PathBuf) -> anyhow::Result<()> {
+    // The archive is written by the server, which has its own working directory.
+    let out = std::env::current_dir()?.join(out);
+    let client = connect().await?;
+    let size = client
+        .call_method::<(String,), u64>("create_backup", "", (out.to_string_lossy().to_string(),))
+        .await?;
+    println!("Backed up to {} ({} bytes)", out.display(), size);
+
+    Ok(())
+}
+
+async fn restore(archive: PathBuf) -> anyhow::Result<()> {
+    let archive = std::env::current_dir()?.join(archive);
+    let client = connect().await?;
+    client
+        .call_method::<(String,), ()>(
+            "restore_backup",
+            "",
+            (archive.to_string_lossy().to_string(),),
+        )
+        .await?;
+    println!(
+        "Restoring {}, restart spyglass to finish",
+        archive.display()
+    );
+
+    Ok(())
+}
+
+fn opensearch() -> anyhow::Result<()> {
+    let config = Config::new();
+    let token = Config::search_token()?;
+    println!(
+        "http://127.0.0.1:{}/search?token={}",
+        config.user_settings.http_api_port, token
+    );
+
+    Ok(())
+}
+
+async fn install_lens(name_or_url: String) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let lens = client
+        .call_method::<(String,), LensResult>("install_lens", "", (name_or_url,))
+        .await?;
+    println!("Installed lens {}", lens.title);
+
+    Ok(())
+}
+
+async fn uninstall_lens(param: UninstallLensParam) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let name = param.name.clone();
+    let num_removed = client
+        .call_method::<(UninstallLensParam,), u64>("uninstall_lens", "", (param,))
+        .await?;
+    println!(
+        "Uninstalled lens {}, removed {} documents",
+        name, num_removed
+    );
+
+    Ok(())
+}
+
+async fn update_lens(name: String) -> anyhow::Result<()> {
+    let client = connect().await?;
+    let updated = client
+        .call_method::<(String,), bool>("update_lens", "", (name.clone(),))
+        .await?;
+    if updated {
+        println!("Updated lens {}", name);
+    } else {
+        println!("Lens {} is already up to date", name);
+    }
+
+    Ok(())
+}
+
+fn workflow(launcher: Launcher, keyword: String, out: Option<PathBuf>) -> anyhow::Result<()> {
+    // Point the workflow at this binary
+    let cli_path = std::env::current_exe()?;
+    let cli_path = cli_path.to_string_lossy();
+
+    let contents = match launcher {
+        Launcher::Alfred => launcher::alfred_workflow(&cli_path, &keyword),
+        Launcher::Raycast => launcher::raycast_script(&cli_path),
+    };
+
+    match out {
+        Some(path) => {
+            fs::write(&path, contents)?;
+            println!("Wrote workflow to {}", path.display());
+        }
+        None => print!("{}", contents),
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Search {
+            query,
+            lens,
+            offset,
+            limit,
+            fuzzy,
+            json,
+            format,
+        } => {
+            let format = if json { OutputFormat::Json } else { format };
+            let params = SearchParam {
+                lenses: lens,
+                query,
+                offset,
+                limit,
+                // Otherwise defer to the user's settings
+                fuzzy: if fuzzy { Some(true) } else { None },
+                ..Default::default()
+            };
+            search(params, format).await
+        }
+        Command::Explain {
+            query,
+            lens,
+            limit,
+            json,
+        } => {
+            let params = SearchParam {
+                lenses: lens,
+                query,
+                limit,
+                ..Default::default()
+            };
+            explain(params, json).await
+        }
+        Command::Export {
+            query,
+            out,
+            lens,
+            csv,
+        } => {
+            let format = if csv {
+                ExportFormat::Csv
+            } else {
+                ExportFormat::JsonLines
+            };
+            export(query, out, lens, format).await
+        }
+        Command::Add {
+            url,
+            max_depth,
+            priority,
+        } => add(url, max_depth, priority).await,
+        Command::Import {
+            file,
+            max_depth,
+            priority,
+        } => import(file, max_depth, priority).await,
+        Command::Pause { domain, lens } => set_paused(domain, lens, true).await,
+        Command::Resume { domain, lens } => set_paused(domain, lens, false).await,
+        Command::Recrawl { url, domain } => recrawl(url, domain).await,
+        Command::Document { doc_id, json } => document(doc_id, json).await,
+        Command::DeleteDomain { domain, dry_run } => delete_domain(domain, dry_run).await,
+        Command::Status { json } => status(json).await,
+        Command::Crawls {
+            domain,
+            errors,
+            limit,
+            json,
+        } => {
+            let param = RecentCrawlsParam {
+                domain,
+                errors_only: errors,
+                limit,
+            };
+            crawls(param, json).await
+        }
+        Command::Queue {
+            status,
+            domain,
+            limit,
+            offset,
+            json,
+        } => {
+            let param = ListQueueParam {
+                status: status.map(QueueItemStatus::from),
+                domain,
+                limit,
+                offset,
+                ..Default::default()
+            };
+            queue(param, json).await
+        }
+        Command::Unqueue {
+            ids,
+            domain,
+            status,
+        } => {
+            let param = RemoveQueueItemsParam {
+                ids,
+                domain,
+                status: status.map(QueueItemStatus::from),
+            };
+            unqueue(param).await
+        }
+        Command::Tasks { json } => tasks(json).await,
+        Command::IndexStats { json } => index_stats(json).await,
+        Command::Optimize => optimize().await,
+        Command::Backup { out } => backup(out).await,
+        Command::Restore { archive } => restore(archive).await,
+        Command::Opensearch => opensearch(),
+        Command::Lens { command } => match command {
+            LensCommand::Install { name_or_url } => install_lens(name_or_url).await,
+            LensCommand::Uninstall { name, purge } => {
+                uninstall_lens(UninstallLensParam { name, purge }).await
+            }
+            LensCommand::Update { name } => update_lens(name).await,
+        },
+        Command::Workflow {
+            launcher,
+            keyword,
+            out,
+        } => workflow(launcher, keyword, out),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{Cli, Command, LensCommand, OutputFormat};
+    use clap::Parser;
+
+    #[test]
+    fn test_parse_search() {
+        let cli = Cli::try_parse_from([
+            "spyglass-cli",
+            "search",
+            "rust lang",
+            "--lens",
+            "wiki",
+            "--json",
+        ])
+        .unwrap();
+
+        match cli.command {
+            Command::Search {
+                query, lens, json, ..
+            } => {
+                assert_eq!(query, "rust lang");
+                assert_eq!(lens, vec!["wiki".to_string()]);
+                assert!(json);
+            }
+            _ => panic!("expected search command"),
+        }
+
+        let cli =
+            Cli::try_parse_from(["spyglass-cli", "search", "rust", "--format", "alfred"]).unwrap();
+        match cli.command {
+            Command::Search { format, .. } => assert_eq!(format, OutputFormat::Alfred),
+            _ => panic!("expected search command"),
+        }
+    }
+
+    #[test]
+    fn test_parse_pause() {
+        let cli =
+            Cli::try_parse_from(["spyglass-cli", "pause", "--domain", "example.com"]).unwrap();
+        match cli.command {
+            Command::Pause { domain, lens } => {
+                assert_eq!(domain, Some("example.com".to_string()));
+                assert_eq!(lens, None);
+            }
+            _ => panic!("expected pause command"),
+        }
+
+        // Exactly one of a domain or lens
+        assert!(Cli::try_parse_from(["spyglass-cli", "pause"]).is_err());
+        assert!(Cli::try_parse_from([
+            "spyglass-cli",
+            "resume",
+            "--domain",
+            "example.com",
+            "--lens",
+            "wiki",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_recrawl() {
+        let cli = Cli::try_parse_from(["spyglass-cli", "recrawl", "https://example.com/"]).unwrap();
+        match cli.command {
+            Command::Recrawl { url, domain } => {
+                assert_eq!(url, Some("https://example.com/".to_string()));
+                assert_eq!(domain, None);
+            }
+            _ => panic!("expected recrawl command"),
+        }
+
+        // Exactly one of a URL or domain
+        assert!(Cli::try_parse_from(["spyglass-cli", "recrawl"]).is_err());
+        assert!(Cli::try_parse_from([
+            "spyglass-cli",
+            "recrawl",
+            "https://example.com/",
+            "--domain",
+            "example.com",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_delete_domain() {
+        let cli =
+            Cli::try_parse_from(["spyglass-cli", "delete-domain", "example.com", "--dry-run"])
+                .unwrap();
+        match cli.command {
+            Command::DeleteDomain { domain, dry_run } => {
+                assert_eq!(domain, "example.com");
+                assert!(dry_run);
+            }
+            _ => panic!("expected delete-domain command"),
+        }
+    }
+
+    #[test]
+    fn test_parse_lens_uninstall() {
+        let cli =
+            Cli::try_parse_from(["spyglass-cli", "lens", "uninstall", "rust", "--purge"]).unwrap();
+        match cli.command {
+            Command::Lens {
+                command: LensCommand::Uninstall { name, purge },
+            } => {
+                assert_eq!(name, "rust");
+                assert!(purge);
+            }
+            _ => panic!("expected lens uninstall command"),
+        }
+    }
+
+    #[test]
+    fn test_parse_backup() {
+        let cli = Cli::try_parse_from(["spyglass-cli", "backup", "library.zip"]).unwrap();
+        match cli.command {
+            Command::Backup { out } => assert_eq!(out, PathBuf::from("library.zip")),
+            _ => panic!("expected backup command"),
+        }
+    }
+}
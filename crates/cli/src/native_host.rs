@@ -0,0 +1,183 @@
+//! Native messaging host for the spyglass browser extension. The browser
+//! launches this process & exchanges length-prefixed JSON messages over
+//! stdin/stdout, which we forward to the spyglass server.
+//!
+//! See: https://developer.chrome.com/docs/apps/nativeMessaging/
+use anyhow::anyhow;
+use jsonrpc_core_client::{transports::ipc, TypedClient};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use shared::request::{IndexPageParam, SearchParam};
+use shared::response::{SearchResult, SearchResults};
+use shared::rpc::{authenticate, gen_ipc_path};
+
+/// Messages sent to the browser are limited to 1 MB.
+const MAX_OUTGOING_BYTES: usize = 1024 * 1024;
+/// Refuse to buffer anything larger than this from the browser.
+const MAX_INCOMING_BYTES: usize = 16 * 1024 * 1024;
+/// Max number of results returned for omnibox searches.
+const MAX_SEARCH_RESULTS: usize = 5;
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    /// A page the user visited, along with its rendered text content.
+    AddPage {
+        url: String,
+        title: String,
+        #[serde(default)]
+        description: String,
+        content: String,
+    },
+    /// Search from the omnibox.
+    Search {
+        query: String,
+        #[serde(default)]
+        lenses: Vec<String>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Response {
+    Ok,
+    Error { message: String },
+    SearchResults { results: Vec<SearchResult> },
+}
+
+async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut len_bytes = [0u8; 4];
+    match reader.read_exact(&mut len_bytes).await {
+        Ok(_) => {}
+        // Browser closed the connection
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+
+    let len = u32::from_ne_bytes(len_bytes) as usize;
+    if len > MAX_INCOMING_BYTES {
+        return Err(anyhow!("message too large: {} bytes", len));
+    }
+
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf).await?;
+    Ok(Some(buf))
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    response: &Response,
+) -> anyhow::Result<()> {
+    let mut bytes = serde_json::to_vec(response)?;
+    if bytes.len() > MAX_OUTGOING_BYTES {
+        bytes = serde_json::to_vec(&Response::Error {
+            message: "response too large".into(),
+        })?;
+    }
+
+    writer
+        .write_all(&(bytes.len() as u32).to_ne_bytes())
+        .await?;
+    writer.write_all(&bytes).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+async fn handle_request(client: &TypedClient, request: Request) -> anyhow::Result<Response> {
+    match request {
+        Request::AddPage {
+            url,
+            title,
+            description,
+            content,
+        } => {
+            let page = IndexPageParam {
+                url,
+                title,
+                description,
+                content,
+            };
+
+            client
+                .call_method::<(IndexPageParam,), String>("index_page", "", (page,))
+                .await?;
+            Ok(Response::Ok)
+        }
+        Request::Search { query, lenses } => {
+            let params = SearchParam {
+                lenses,
+                query,
+                limit: MAX_SEARCH_RESULTS,
+                ..Default::default()
+            };
+            let resp = client
+                .call_method::<(SearchParam,), SearchResults>("search_docs", "", (params,))
+                .await?;
+
+            Ok(Response::SearchResults {
+                results: resp.results,
+            })
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let client: TypedClient = ipc::connect(gen_ipc_path())
+        .await
+        .map_err(|err| anyhow!("Unable to connect to spyglass server: {}", err))?;
+    authenticate(&client).await?;
+
+    let mut stdin = tokio::io::stdin();
+    let mut stdout = tokio::io::stdout();
+
+    while let Some(message) = read_message(&mut stdin).await? {
+        let response = match serde_json::from_slice::<Request>(&message) {
+            Ok(request) => match handle_request(&client, request).await {
+                Ok(response) => response,
+                Err(err) => Response::Error {
+                    message: err.to_string(),
+                },
+            },
+            Err(err) => Response::Error {
+                message: format!("Invalid message: {}", err),
+            },
+        };
+
+        write_message(&mut stdout, &response).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_message, write_message, Request, Response};
+
+    #[tokio::test]
+    async fn test_message_framing() {
+        let mut buf: Vec<u8> = Vec::new();
+        write_message(&mut buf, &Response::Ok).await.unwrap();
+
+        let mut reader = buf.as_slice();
+        let message = read_message(&mut reader).await.unwrap().unwrap();
+        assert_eq!(message, br#"{"type":"ok"}"#.to_vec());
+
+        // Nothing left to read
+        assert!(read_message(&mut reader).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let request: Request =
+            serde_json::from_str(r#"{"type": "search", "query": "rust"}"#).unwrap();
+        assert_eq!(
+            request,
+            Request::Search {
+                query: "rust".into(),
+                lenses: Vec::new()
+            }
+        );
+    }
+}
@@ -5,8 +5,8 @@ if (window.__TAURI__) {
     listen = window.__TAURI__.event.listen;
 }
 
-export async function deleteDoc(id) {
-    return await invoke('delete_doc', { id });
+export async function deleteDocument(docId) {
+    return await invoke('delete_document', { docId });
 }
 
 export async function delete_domain(domain) {
@@ -21,12 +21,20 @@ export async function network_change(isOffline) {
     return await invoke('network_change', { isOffline });
 }
 
+export async function plugin_settings(name) {
+    return await invoke('plugin_settings', { name });
+}
+
 export async function recrawl_domain(domain) {
     return await invoke('recrawl_domain', { domain });
 }
 
-export async function searchDocs(lenses, query) {
-    return await invoke('search_docs', { lenses, query });
+export async function run_command(command) {
+    return await invoke('run_command', { command });
+}
+
+export async function searchDocs(lenses, query, after) {
+    return await invoke('search_docs', { lenses, query, after });
 }
 
 export async function searchLenses(query) {
@@ -37,10 +45,26 @@ export async function openResult(url) {
     return await invoke('open_result', { url });
 }
 
+export async function openCached(docId) {
+    return await invoke('open_cached', { docId });
+}
+
 export async function resizeWindow(height) {
     return await invoke('resize_window', { height });
 }
 
+export async function set_plugin_settings(name, settings) {
+    return await invoke('set_plugin_settings', { name, settings });
+}
+
+export async function similar(docId) {
+    return await invoke('similar', { docId });
+}
+
 export async function toggle_plugin(name) {
     return await invoke('toggle_plugin', { name })
+}
+
+export async function uninstall_lens(name) {
+    return await invoke('uninstall_lens', { name });
 }
\ No newline at end of file
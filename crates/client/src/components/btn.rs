@@ -46,7 +46,9 @@ pub fn delete_btn(props: &DeleteButtonProps) -> Html {
         Callback::from(move |_| {
             let doc_id = doc_id.clone();
             spawn_local(async move {
-                let _ = crate::delete_doc(doc_id.clone()).await;
+                if let Err(err) = crate::delete_document(doc_id.clone()).await {
+                    log::error!("Unable to delete doc {}: {:?}", doc_id, err);
+                }
             });
         })
     };
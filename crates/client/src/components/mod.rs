@@ -5,10 +5,13 @@ pub mod lens;
 use yew::prelude::*;
 
 use btn::DeleteButton;
-use shared::response::{LensResult, SearchResult};
+use shared::command::PaletteCommand;
+use shared::response::{InlineAnswer, LensResult, PageMetadata, SearchResult};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ResultListType {
+    Answer,
+    Command,
     DocSearch,
     LensSearch,
 }
@@ -19,9 +22,53 @@ pub struct ResultListData {
     pub domain: Option<String>,
     pub title: String,
     pub description: String,
+    /// Byte ranges in the description to highlight, e.g. matched search terms
+    pub highlights: Vec<(usize, usize)>,
     pub url: Option<String>,
     pub score: f32,
     pub result_type: ResultListType,
+    /// Command to run when this result is selected, only relevant for command results
+    pub command: Option<PaletteCommand>,
+    /// Preview image, publish date, etc. for document results
+    pub metadata: PageMetadata,
+    /// Site icon as a data URI, for document results
+    pub favicon: Option<String>,
+}
+
+impl From<&PaletteCommand> for ResultListData {
+    fn from(x: &PaletteCommand) -> Self {
+        ResultListData {
+            id: x.label(),
+            description: x.description(),
+            highlights: Vec::new(),
+            domain: None,
+            result_type: ResultListType::Command,
+            score: 1.0,
+            title: x.label(),
+            url: None,
+            command: Some(x.clone()),
+            metadata: PageMetadata::default(),
+            favicon: None,
+        }
+    }
+}
+
+impl From<&InlineAnswer> for ResultListData {
+    fn from(x: &InlineAnswer) -> Self {
+        ResultListData {
+            id: x.query.clone(),
+            description: x.query.clone(),
+            highlights: Vec::new(),
+            domain: None,
+            result_type: ResultListType::Answer,
+            score: 1.0,
+            title: x.answer.clone(),
+            url: None,
+            command: None,
+            metadata: PageMetadata::default(),
+            favicon: None,
+        }
+    }
 }
 
 impl From<&LensResult> for ResultListData {
@@ -29,29 +76,128 @@ impl From<&LensResult> for ResultListData {
         ResultListData {
             id: x.title.clone(),
             description: x.description.clone(),
+            highlights: Vec::new(),
             domain: None,
             result_type: ResultListType::LensSearch,
             score: 1.0,
             title: x.title.clone(),
             url: None,
+            command: None,
+            metadata: PageMetadata::default(),
+            favicon: None,
         }
     }
 }
 
 impl From<&SearchResult> for ResultListData {
     fn from(x: &SearchResult) -> Self {
+        // Prefer showing why the document matched over the static description
+        let (description, highlights) = match &x.snippet {
+            Some(snippet) => (snippet.text.clone(), snippet.highlights.clone()),
+            None => (x.description.clone(), Vec::new()),
+        };
+
         ResultListData {
             id: x.doc_id.clone(),
-            description: x.description.clone(),
+            description,
+            highlights,
             domain: Some(x.domain.clone()),
             result_type: ResultListType::DocSearch,
             score: x.score,
             title: x.title.clone(),
             url: Some(x.url.clone()),
+            command: None,
+            metadata: x.metadata.clone(),
+            favicon: x.favicon.clone(),
         }
     }
 }
 
+/// How recently documents must've been indexed to show up in search results
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DateRange {
+    Any,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl DateRange {
+    pub const ALL: [DateRange; 5] = [
+        DateRange::Any,
+        DateRange::Day,
+        DateRange::Week,
+        DateRange::Month,
+        DateRange::Year,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DateRange::Any => "Any time",
+            DateRange::Day => "Past day",
+            DateRange::Week => "Past week",
+            DateRange::Month => "Past month",
+            DateRange::Year => "Past year",
+        }
+    }
+
+    /// Unix timestamp results must've been indexed after, if any
+    pub fn after(&self) -> Option<f64> {
+        let days = match self {
+            DateRange::Any => return None,
+            DateRange::Day => 1,
+            DateRange::Week => 7,
+            DateRange::Month => 30,
+            DateRange::Year => 365,
+        };
+
+        let now = (js_sys::Date::now() / 1000.0).floor();
+        Some(now - (days * 24 * 60 * 60) as f64)
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct DateRangeProps {
+    pub selected: DateRange,
+    pub on_select: Callback<DateRange>,
+}
+
+/// Render chips to narrow search results down by date
+#[function_component(DateRangeChips)]
+pub fn date_range_chips(props: &DateRangeProps) -> Html {
+    let chips = DateRange::ALL
+        .iter()
+        .map(|range| {
+            let styles = if *range == props.selected {
+                "bg-cyan-700 text-white"
+            } else {
+                "bg-neutral-700 text-neutral-400 hover:bg-neutral-600"
+            };
+
+            let onclick = {
+                let range = *range;
+                let on_select = props.on_select.clone();
+                Callback::from(move |_| on_select.emit(range))
+            };
+
+            html! {
+                <li>
+                    <button class={classes!("rounded-full", "px-3", "py-1", "text-xs", "mr-2", styles)} {onclick}>
+                        {range.label()}
+                    </button>
+                </li>
+            }
+        })
+        .collect::<Html>();
+
+    html! {
+        <ul class="flex bg-neutral-800 px-4 pb-2">
+            {chips}
+        </ul>
+    }
+}
+
 #[derive(Properties, PartialEq)]
 pub struct SelectLensProps {
     pub lens: Vec<String>,
@@ -79,6 +225,62 @@ pub fn selected_lens_list(props: &SelectLensProps) -> Html {
     }
 }
 
+/// Render `text` w/ the given byte ranges in bold. Ranges that are out of
+/// order or don't fall on a char boundary are ignored.
+fn highlight(text: &str, highlights: &[(usize, usize)]) -> Html {
+    let mut parts = Vec::new();
+    let mut last = 0;
+    for (start, end) in highlights.iter().copied() {
+        if start < last {
+            continue;
+        }
+
+        if let (Some(before), Some(matched)) = (text.get(last..start), text.get(start..end)) {
+            parts.push(html! { {before} });
+            parts.push(html! { <b class="text-white">{matched}</b> });
+            last = end;
+        }
+    }
+
+    parts.push(html! { {text.get(last..).unwrap_or_default()} });
+    parts.into_iter().collect::<Html>()
+}
+
+#[derive(Properties, PartialEq)]
+pub struct RelatedResultsProps {
+    pub results: Vec<SearchResult>,
+}
+
+/// Render documents similar to the selected result
+#[function_component(RelatedResults)]
+pub fn related_results(props: &RelatedResultsProps) -> Html {
+    if props.results.is_empty() {
+        return html! {};
+    }
+
+    let items = props
+        .results
+        .iter()
+        .map(|result| {
+            html! {
+                <li class="truncate py-1">
+                    <a href={result.url.clone()} target="_blank">
+                        <span class="text-white">{result.title.clone()}</span>
+                        <span class="text-cyan-400">{format!(" {}", result.domain)}</span>
+                    </a>
+                </li>
+            }
+        })
+        .collect::<Html>();
+
+    html! {
+        <div class="border-t border-neutral-600 bg-neutral-800 px-4 py-2 text-xs text-neutral-400">
+            <div class="pb-1">{"Related"}</div>
+            <ul>{items}</ul>
+        </div>
+    }
+}
+
 #[derive(Properties, PartialEq)]
 pub struct SearchResultProps {
     pub result: ResultListData,
@@ -119,13 +321,17 @@ pub fn search_result_component(props: &SearchResultProps) -> Html {
                     .trim_start_matches("https://")
                     .trim_start_matches(&domain);
 
+                let favicon = match &result.favicon {
+                    Some(favicon) => html! {
+                        <img class="w-3 inline align-middle" src={favicon.clone()} />
+                    },
+                    None => html! { <span></span> },
+                };
+
                 html! {
                     <div class="text-xs truncate">
                         <a href={url.clone()} target="_blank">
-                            <img
-                                class="w-3 inline align-middle"
-                                src={format!("https://icons.duckduckgo.com/ip3/{}.ico", domain.clone())}
-                            />
+                            {favicon}
                             <span class="align-middle text-cyan-400">{format!(" {}", domain.clone())}</span>
                             <span class="align-middle">{format!(" → {}", path)}</span>
                         </a>
@@ -135,16 +341,63 @@ pub fn search_result_component(props: &SearchResultProps) -> Html {
                 html! { <span></span> }
             };
 
+            // Publish date (w/o the time) & the kind of page, e.g. "Recipe"
+            let details: Vec<String> = result
+                .metadata
+                .published_at
+                .iter()
+                .map(|at| at.chars().take(10).collect())
+                .chain(result.metadata.schema_types.first().cloned())
+                .collect();
+            let details = if details.is_empty() {
+                html! { <span></span> }
+            } else {
+                html! {
+                    <div class="text-xs text-neutral-400 pb-1">{details.join(" · ")}</div>
+                }
+            };
+
+            let thumbnail = match &result.metadata.og_image {
+                Some(image) => html! {
+                    <img class="float-right w-16 h-16 ml-4 rounded object-cover" src={image.clone()} />
+                },
+                None => html! { <span></span> },
+            };
+
+            let cached_hint = if is_selected {
+                html! {
+                    <span class="float-right pr-2 text-xs text-neutral-400">{"⇧↵ cached copy"}</span>
+                }
+            } else {
+                html! { <span></span> }
+            };
+
             html! {
                 <div class={component_styles}>
                     <div class="float-right pl-4 mr-2 h-28">
                         <DeleteButton doc_id={result.id.clone()} />
                     </div>
+                    {cached_hint}
+                    {thumbnail}
                     {url_link}
                     <h2 class="text-lg truncate py-1">
                         {result.title.clone()}
                     </h2>
+                    {details}
                     <div class="text-sm leading-relaxed text-neutral-400 h-16 overflow-hidden text-ellipsis">
+                        {highlight(&result.description, &result.highlights)}
+                    </div>
+                </div>
+            }
+        }
+        ResultListType::Command => {
+            html! {
+                <div class={component_styles}>
+                    <h2 class="text-lg truncate py-1">
+                        <span class="text-cyan-400">{"> "}</span>
+                        {result.title.clone()}
+                    </h2>
+                    <div class="text-sm leading-relaxed text-neutral-400 overflow-hidden text-ellipsis">
                         {result.description.clone()}
                     </div>
                 </div>
@@ -1,2 +1,3 @@
+pub const COMMAND_PREFIX: &str = ">";
 pub const LENS_SEARCH_PREFIX: &str = "/";
 pub const MIN_CHARS: usize = 2;
@@ -5,10 +5,10 @@ use wasm_bindgen_futures::spawn_local;
 use web_sys::{Element, HtmlInputElement};
 use yew::prelude::*;
 
-use super::{invoke, open};
-use crate::components::ResultListData;
+use super::{invoke, open, open_cached, run_command};
+use crate::components::{ResultListData, ResultListType};
 use crate::constants;
-use crate::pages::{clear_results, show_doc_results, show_lens_results};
+use crate::pages::{clear_results, show_command_results, show_doc_results, show_lens_results};
 
 pub fn handle_global_key_down(
     event: &Event,
@@ -35,7 +35,23 @@ pub fn handle_global_key_down(
         selected_idx.set(new_idx);
     } else if event.key() == "Enter" {
         let selected: &ResultListData = (*search_results).get(*selected_idx).unwrap();
-        if let Some(url) = selected.url.clone() {
+        if selected.result_type == ResultListType::Answer {
+            // Nothing to open for instant answers
+        } else if selected.result_type == ResultListType::DocSearch && event.shift_key() {
+            // Shift+Enter opens the cached copy of the page
+            let doc_id = selected.id.clone();
+            spawn_local(async move {
+                if let Err(e) = open_cached(doc_id).await {
+                    log::error!("Error opening cached copy: {:?}", e);
+                }
+            });
+        } else if let Some(command) = selected.command.clone() {
+            spawn_local(async move {
+                if let Err(e) = run_command(JsValue::from_serde(&command).unwrap()).await {
+                    log::error!("Error running command: {:?}", e);
+                }
+            });
+        } else if let Some(url) = selected.url.clone() {
             spawn_local(async move {
                 open(url).await.unwrap();
             });
@@ -76,18 +92,31 @@ pub fn handle_global_key_down(
 
 pub fn handle_query_change(
     query: &str,
+    after: Option<f64>,
     node_ref: UseStateHandle<NodeRef>,
     lens: UseStateHandle<Vec<String>>,
     search_results: UseStateHandle<Vec<ResultListData>>,
     selected_idx: UseStateHandle<usize>,
 ) {
     let el = node_ref.cast::<Element>().unwrap();
+    if query.starts_with(constants::COMMAND_PREFIX) {
+        // show command palette
+        return show_command_results(search_results, el, selected_idx, query.to_string());
+    }
+
     if query.starts_with(constants::LENS_SEARCH_PREFIX) {
         // show lens search
         return show_lens_results(search_results, el, selected_idx, query.to_string());
     }
 
     if query.len() >= constants::MIN_CHARS {
-        show_doc_results(search_results, &lens, el, selected_idx, query.to_string())
+        show_doc_results(
+            search_results,
+            &lens,
+            el,
+            selected_idx,
+            query.to_string(),
+            after,
+        )
     }
 }
@@ -24,8 +24,8 @@ extern "C" {
 
 #[wasm_bindgen(module = "/public/glue.js")]
 extern "C" {
-    #[wasm_bindgen(js_name = "deleteDoc", catch)]
-    pub async fn delete_doc(id: String) -> Result<(), JsValue>;
+    #[wasm_bindgen(js_name = "deleteDocument", catch)]
+    pub async fn delete_document(doc_id: String) -> Result<(), JsValue>;
 
     #[wasm_bindgen(catch)]
     pub async fn delete_domain(domain: String) -> Result<(), JsValue>;
@@ -33,8 +33,15 @@ extern "C" {
     #[wasm_bindgen(catch)]
     pub async fn install_lens(download_url: String) -> Result<(), JsValue>;
 
+    #[wasm_bindgen(catch)]
+    pub async fn run_command(command: JsValue) -> Result<(), JsValue>;
+
     #[wasm_bindgen(js_name = "searchDocs", catch)]
-    pub async fn search_docs(lenses: JsValue, query: String) -> Result<JsValue, JsValue>;
+    pub async fn search_docs(
+        lenses: JsValue,
+        query: String,
+        after: Option<f64>,
+    ) -> Result<JsValue, JsValue>;
 
     #[wasm_bindgen(js_name = "searchLenses", catch)]
     pub async fn search_lenses(query: String) -> Result<JsValue, JsValue>;
@@ -42,17 +49,32 @@ extern "C" {
     #[wasm_bindgen(js_name = "openResult", catch)]
     pub async fn open(url: String) -> Result<(), JsValue>;
 
+    #[wasm_bindgen(js_name = "openCached", catch)]
+    pub async fn open_cached(doc_id: String) -> Result<(), JsValue>;
+
     #[wasm_bindgen(js_name = "resizeWindow", catch)]
     pub async fn resize_window(height: f64) -> Result<(), JsValue>;
 
     #[wasm_bindgen]
     pub async fn network_change(is_offline: bool);
 
+    #[wasm_bindgen(catch)]
+    pub async fn plugin_settings(name: &str) -> Result<JsValue, JsValue>;
+
     #[wasm_bindgen(catch)]
     pub async fn recrawl_domain(domain: String) -> Result<(), JsValue>;
 
+    #[wasm_bindgen(catch)]
+    pub async fn set_plugin_settings(name: &str, settings: JsValue) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(catch)]
+    pub async fn similar(doc_id: String) -> Result<JsValue, JsValue>;
+
     #[wasm_bindgen(catch)]
     pub async fn toggle_plugin(name: &str) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(catch)]
+    pub async fn uninstall_lens(name: String) -> Result<(), JsValue>;
 }
 
 #[derive(Clone, Routable, PartialEq)]
@@ -9,7 +9,7 @@ use yew::prelude::*;
 use crate::components::icons;
 use crate::listen;
 use crate::utils::RequestState;
-use crate::{install_lens, invoke};
+use crate::{install_lens, invoke, uninstall_lens};
 use shared::event::ClientEvent;
 use shared::response::InstallableLens;
 
@@ -111,6 +111,48 @@ pub fn install_btn(props: &InstallBtnProps) -> Html {
     }
 }
 
+#[derive(Properties, PartialEq)]
+pub struct UninstallBtnProps {
+    pub name: String,
+}
+
+#[function_component(UninstallButton)]
+pub fn uninstall_btn(props: &UninstallBtnProps) -> Html {
+    let is_uninstalling = use_state_eq(|| false);
+    let name = props.name.clone();
+
+    let onclick = {
+        let is_uninstalling = is_uninstalling.clone();
+        Callback::from(move |_| {
+            let name = name.clone();
+            is_uninstalling.set(true);
+            spawn_local(async move {
+                if let Err(e) = uninstall_lens(name.clone()).await {
+                    log::error!("error uninstalling lens: {} {:?}", name, e);
+                }
+            });
+        })
+    };
+
+    if *is_uninstalling {
+        html! {
+            <div class="flex flex-row text-red-400 text-sm">
+                <icons::RefreshIcon animate_spin={true} />
+                <div class="ml-2">{"Uninstalling"}</div>
+            </div>
+        }
+    } else {
+        html! {
+            <button
+                {onclick}
+                class="flex flex-row text-neutral-400 text-sm cursor-pointer hover:text-red-400">
+                <icons::TrashIcon />
+                <div class="ml-2">{"Uninstall"}</div>
+            </button>
+        }
+    }
+}
+
 #[function_component(Lens)]
 pub fn lens_component(props: &LensProps) -> Html {
     let component_styles: Vec<String> = vec![
@@ -125,10 +167,13 @@ pub fn lens_component(props: &LensProps) -> Html {
 
     let installed_el = if props.is_installed {
         html! {
-            <div class="flex flex-row text-green-400 text-sm">
-                <icons::BadgeCheckIcon />
-                <div class="ml-2">{"Installed"}</div>
-            </div>
+            <>
+                <div class="flex flex-row text-green-400 text-sm">
+                    <icons::BadgeCheckIcon />
+                    <div class="ml-2">{"Installed"}</div>
+                </div>
+                <UninstallButton name={result.title.clone()} />
+            </>
         }
     } else {
         html! { <InstallButton download_url={result.download_url.clone().unwrap()} /> }
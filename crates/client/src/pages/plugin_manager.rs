@@ -1,15 +1,18 @@
+use std::collections::HashMap;
+
 use shared::event::ClientEvent;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
 use yew::function_component;
 use yew::prelude::*;
 
-use shared::event::ClientInvoke;
-use shared::response::PluginResult;
+use shared::config::PluginSettingType;
+use shared::response::{PluginResult, PluginSettingResult};
 
 use crate::components::icons;
 use crate::utils::RequestState;
-use crate::{invoke, listen, toggle_plugin};
+use crate::{invoke, listen, plugin_settings, set_plugin_settings, toggle_plugin};
 
 fn fetch_installed_plugins(
     plugins_handle: UseStateHandle<Vec<PluginResult>>,
@@ -29,6 +32,132 @@ fn fetch_installed_plugins(
     });
 }
 
+#[derive(Properties, PartialEq)]
+pub struct PluginSettingsProps {
+    pub name: String,
+}
+
+/// Form for the settings a plugin declares in its manifest.
+#[function_component(PluginSettingsForm)]
+pub fn plugin_settings_form(props: &PluginSettingsProps) -> Html {
+    let settings: UseStateHandle<Vec<PluginSettingResult>> = use_state_eq(Vec::new);
+    let values: UseStateHandle<HashMap<String, String>> = use_state_eq(HashMap::new);
+    let save_status: UseStateHandle<Option<String>> = use_state_eq(|| None);
+
+    {
+        let settings = settings.clone();
+        let values = values.clone();
+        use_effect_with_deps(
+            move |name: &String| {
+                let name = name.clone();
+                spawn_local(async move {
+                    match plugin_settings(&name).await {
+                        Ok(results) => {
+                            let results: Vec<PluginSettingResult> =
+                                results.into_serde().unwrap_or_default();
+                            values.set(
+                                results
+                                    .iter()
+                                    .filter_map(|setting| {
+                                        setting
+                                            .value
+                                            .clone()
+                                            .map(|value| (setting.name.clone(), value))
+                                    })
+                                    .collect(),
+                            );
+                            settings.set(results);
+                        }
+                        Err(e) => log::error!("Error fetching plugin settings: {:?}", e),
+                    }
+                });
+                || ()
+            },
+            props.name.clone(),
+        );
+    }
+
+    if settings.is_empty() {
+        return html! {
+            <div class="pt-2 text-sm text-neutral-400">{"No settings for this plugin."}</div>
+        };
+    }
+
+    let fields = settings
+        .iter()
+        .map(|setting| {
+            let oninput = {
+                let values = values.clone();
+                let name = setting.name.clone();
+                Callback::from(move |e: InputEvent| {
+                    let input: HtmlInputElement = e.target_unchecked_into();
+                    let mut updated = (*values).clone();
+                    updated.insert(name.clone(), input.value());
+                    values.set(updated);
+                })
+            };
+
+            let input_type = if setting.schema.form_type == PluginSettingType::Secret {
+                "password"
+            } else {
+                "text"
+            };
+
+            let label = if setting.schema.required {
+                format!("{} *", setting.schema.label)
+            } else {
+                setting.schema.label.clone()
+            };
+
+            html! {
+                <label class="block pt-2 text-sm">
+                    <div class="text-neutral-400">{label}</div>
+                    <input
+                        type={input_type}
+                        class="w-full rounded bg-neutral-700 px-2 py-1 text-white"
+                        placeholder={setting.schema.default.clone().unwrap_or_default()}
+                        value={values.get(&setting.name).cloned().unwrap_or_default()}
+                        oninput={oninput}
+                    />
+                    <div class="text-xs text-neutral-500">{setting.schema.description.clone()}</div>
+                </label>
+            }
+        })
+        .collect::<Html>();
+
+    let onsave = {
+        let name = props.name.clone();
+        let values = values.clone();
+        let save_status = save_status.clone();
+        Callback::from(move |_| {
+            let name = name.clone();
+            let values = (*values).clone();
+            let save_status = save_status.clone();
+            spawn_local(async move {
+                match set_plugin_settings(&name, JsValue::from_serde(&values).unwrap()).await {
+                    Ok(_) => save_status.set(Some("Saved".into())),
+                    Err(e) => save_status.set(Some(format!(
+                        "Unable to save: {}",
+                        e.as_string().unwrap_or_default()
+                    ))),
+                }
+            });
+        })
+    };
+
+    html! {
+        <div class="pt-2 pr-4">
+            {fields}
+            <div class="pt-2 flex flex-row items-center gap-4">
+                <button onclick={onsave} class="text-sm text-cyan-400 cursor-pointer hover:text-white">
+                    {"Save"}
+                </button>
+                <div class="text-xs text-neutral-400">{save_status.as_ref().cloned().unwrap_or_default()}</div>
+            </div>
+        </div>
+    }
+}
+
 #[derive(Properties, PartialEq)]
 pub struct PluginProps {
     pub plugin: PluginResult,
@@ -64,12 +193,10 @@ pub fn plugin_comp(props: &PluginProps) -> Html {
         })
     };
 
+    let show_settings = use_state_eq(|| false);
     let on_edit_settings = {
-        Callback::from(move |_| {
-            spawn_local(async move {
-                let _ = invoke(&ClientInvoke::EditPluginSettings.to_string(), JsValue::NULL).await;
-            });
-        })
+        let show_settings = show_settings.clone();
+        Callback::from(move |_| show_settings.set(!*show_settings))
     };
 
     let toggle_button = html! {
@@ -108,6 +235,9 @@ pub fn plugin_comp(props: &PluginProps) -> Html {
                 {toggle_button}
                 {view_settings}
             </div>
+            if *show_settings {
+                <PluginSettingsForm name={plugin.title.clone()} />
+            }
         </div>
     }
 }
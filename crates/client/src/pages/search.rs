@@ -5,11 +5,15 @@ use wasm_bindgen_futures::spawn_local;
 use web_sys::{window, Element, HtmlElement, HtmlInputElement};
 use yew::prelude::*;
 
+use shared::command::{filter_commands, PaletteCommand};
 use shared::{event::ClientEvent, response};
 
-use crate::components::{ResultListData, SearchResultItem, SelectedLens};
+use crate::components::{
+    DateRange, DateRangeChips, RelatedResults, ResultListData, ResultListType, SearchResultItem,
+    SelectedLens,
+};
 use crate::events;
-use crate::{listen, resize_window, search_docs, search_lenses};
+use crate::{invoke, listen, resize_window, search_docs, search_lenses, similar};
 
 #[wasm_bindgen]
 extern "C" {
@@ -19,6 +23,8 @@ extern "C" {
 
 type TimeoutId = i32;
 const QUERY_DEBOUNCE_MS: u32 = 256;
+/// Max number of documents shown under "Related" for the selected result.
+const MAX_RELATED: usize = 3;
 
 #[function_component(SearchPage)]
 pub fn search_page() -> Html {
@@ -27,10 +33,14 @@ pub fn search_page() -> Html {
     // Current query string
     let query = use_state_eq(|| "".to_string());
     let query_ref = use_state_eq(NodeRef::default);
+    // Only show documents indexed w/in this range
+    let date_range = use_state_eq(|| DateRange::Any);
 
     // Search results + selected index
     let search_results = use_state_eq(Vec::new);
     let selected_idx = use_state_eq(|| 0);
+    // Documents similar to the selected result
+    let related = use_state_eq(Vec::new);
 
     let node_ref = use_state_eq(NodeRef::default);
     let query_debounce: UseStateHandle<Option<TimeoutId>> = use_state(|| None);
@@ -70,16 +80,18 @@ pub fn search_page() -> Html {
         let node_ref = node_ref.clone();
 
         use_effect_with_deps(
-            move |query| {
+            move |(query, date_range)| {
                 if let Some(timeout_id) = *query_debounce {
                     clear_timeout(timeout_id);
                     query_debounce.set(None);
                 }
 
                 let query = query.clone();
+                let after = date_range.after();
                 let handle = Timeout::new(QUERY_DEBOUNCE_MS, move || {
                     events::handle_query_change(
                         &query,
+                        after,
                         node_ref,
                         lens,
                         search_results,
@@ -91,7 +103,28 @@ pub fn search_page() -> Html {
                 query_debounce.set(Some(id));
                 || ()
             },
-            (*query).clone(),
+            ((*query).clone(), *date_range),
+        );
+    }
+
+    // Look up related documents for the selected result
+    {
+        let related = related.clone();
+        let node_ref = node_ref.clone();
+        let selected_doc = search_results
+            .get(*selected_idx)
+            .filter(|res: &&ResultListData| res.result_type == ResultListType::DocSearch)
+            .map(|res| res.id.clone());
+
+        use_effect_with_deps(
+            move |selected_doc| {
+                match selected_doc.clone() {
+                    Some(doc_id) => show_related(related, doc_id, node_ref),
+                    None => related.set(Vec::new()),
+                }
+                || ()
+            },
+            selected_doc,
         );
     }
 
@@ -104,11 +137,13 @@ pub fn search_page() -> Html {
         let query_ref = query_ref.clone();
         let results = search_results.clone();
         let selected_idx = selected_idx.clone();
+        let date_range = date_range.clone();
         // Reset query string, results list, etc when we receive a "clear_search"
         // event from tauri
         spawn_local(async move {
             let cb = Closure::wrap(Box::new(move || {
                 query.set("".to_string());
+                date_range.set(DateRange::Any);
                 results.set(Vec::new());
                 selected_idx.set(0);
                 lens.set(Vec::new());
@@ -182,6 +217,11 @@ pub fn search_page() -> Html {
         })
     };
 
+    let on_date_select = {
+        let date_range = date_range.clone();
+        Callback::from(move |range: DateRange| date_range.set(range))
+    };
+
     let onkeydown = {
         Callback::from(move |e: KeyboardEvent| {
             let key = e.key();
@@ -212,7 +252,9 @@ pub fn search_page() -> Html {
                     tabindex="-1"
                 />
             </div>
+            <DateRangeChips selected={*date_range} on_select={on_date_select} />
             <div>{ results }</div>
+            <RelatedResults results={(*related).clone()} />
         </div>
     }
 }
@@ -261,22 +303,69 @@ pub fn show_lens_results(
     })
 }
 
+pub fn show_command_results(
+    handle: UseStateHandle<Vec<ResultListData>>,
+    node: Element,
+    selected_idx: UseStateHandle<usize>,
+    query: String,
+) {
+    let query = query.strip_prefix('>').unwrap().to_string();
+    spawn_local(async move {
+        let mut commands = PaletteCommand::builtin();
+        // Add a toggle command for each installed plugin
+        match invoke("list_plugins", JsValue::NULL).await {
+            Ok(plugins) => {
+                let plugins: Vec<response::PluginResult> = plugins.into_serde().unwrap();
+                for plugin in plugins {
+                    commands.push(PaletteCommand::TogglePlugin(plugin.title));
+                }
+            }
+            Err(e) => log::error!("Error fetching plugins: {:?}", e),
+        }
+
+        let results = filter_commands(&query, &commands)
+            .iter()
+            .map(|x| x.into())
+            .collect::<Vec<ResultListData>>();
+
+        let max_idx = results.len().max(1) - 1;
+        if max_idx < *selected_idx {
+            selected_idx.set(max_idx);
+        }
+
+        handle.set(results);
+        spawn_local(async move {
+            resize_window(node.client_height() as f64).await.unwrap();
+        });
+    })
+}
+
 pub fn show_doc_results(
     handle: UseStateHandle<Vec<ResultListData>>,
     lenses: &[String],
     node: Element,
     selected_idx: UseStateHandle<usize>,
     query: String,
+    after: Option<f64>,
 ) {
     let lenses = lenses.to_owned();
     spawn_local(async move {
-        match search_docs(JsValue::from_serde(&lenses).unwrap(), query).await {
+        match search_docs(JsValue::from_serde(&lenses).unwrap(), query, after).await {
             Ok(results) => {
-                let results: Vec<response::SearchResult> = results.into_serde().unwrap();
+                let results: response::SearchResults = results.into_serde().unwrap();
+                // Show any instant answers above the rest of the results
+                let mut list = Vec::new();
+                if let Some(answer) = &results.answer {
+                    list.push(answer.into());
+                }
+
                 let results = results
+                    .results
                     .iter()
                     .map(|x| x.into())
                     .collect::<Vec<ResultListData>>();
+                list.extend(results);
+                let results = list;
 
                 let max_idx = results.len().max(1) - 1;
                 if max_idx < *selected_idx {
@@ -298,3 +387,30 @@ pub fn show_doc_results(
         }
     })
 }
+
+pub fn show_related(
+    handle: UseStateHandle<Vec<response::SearchResult>>,
+    doc_id: String,
+    node_ref: UseStateHandle<NodeRef>,
+) {
+    spawn_local(async move {
+        match similar(doc_id).await {
+            Ok(results) => {
+                let mut results: Vec<response::SearchResult> =
+                    results.into_serde().unwrap_or_default();
+                results.truncate(MAX_RELATED);
+                handle.set(results);
+
+                if let Some(node) = node_ref.cast::<Element>() {
+                    spawn_local(async move {
+                        resize_window(node.client_height() as f64).await.unwrap();
+                    });
+                }
+            }
+            Err(e) => {
+                log::error!("Unable to fetch related documents: {:?}", e);
+                handle.set(Vec::new());
+            }
+        }
+    })
+}
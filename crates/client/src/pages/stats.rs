@@ -6,7 +6,16 @@ use yew::prelude::*;
 
 use crate::components::{btn, icons};
 use crate::invoke;
-use shared::response::{CrawlStats, QueueStatus};
+use shared::response::{AppStatus, CrawlStats, DomainCrawlStats, QueueStatus};
+
+fn fetch_app_status(status_handle: UseStateHandle<Option<AppStatus>>) {
+    spawn_local(async move {
+        match invoke(ClientInvoke::GetAppStatus.as_ref(), JsValue::NULL).await {
+            Ok(results) => status_handle.set(results.into_serde().ok()),
+            Err(e) => log::info!("Error: {:?}", e),
+        }
+    });
+}
 
 fn fetch_crawl_stats(
     stats_handle: UseStateHandle<Vec<(String, QueueStatus)>>,
@@ -29,6 +38,122 @@ fn fetch_crawl_stats(
     });
 }
 
+fn format_number(num: u64) -> String {
+    let mut buf = Buffer::default();
+    buf.write_formatted(&num, &Locale::en);
+    buf.as_str().to_string()
+}
+
+fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, units[unit])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct CrawlDetailsProps {
+    stats: DomainCrawlStats,
+}
+
+/// Fetch counters for a domain, shown below its queue status.
+#[function_component(CrawlDetails)]
+fn crawl_details(props: &CrawlDetailsProps) -> Html {
+    let stats = &props.stats;
+    // e.g. 2022-09-28T17:02:11.581+00:00 -> 2022-09-28 17:02
+    let last_crawled = stats
+        .last_crawled_at
+        .as_ref()
+        .and_then(|at| at.get(..16))
+        .map(|at| at.replace('T', " "))
+        .unwrap_or_else(|| "never".into());
+
+    html! {
+        <div class="text-xs text-neutral-400 pt-2 flex flex-row gap-4">
+            <div>{format!("{} fetched", format_number(stats.num_fetched))}</div>
+            <div title={format!(
+                "{} client, {} server, {} network",
+                stats.num_client_errors, stats.num_server_errors, stats.num_network_errors
+            )}>
+                {format!("{} errors", format_number(stats.num_errors()))}
+            </div>
+            <div>{format!("{} downloaded", format_bytes(stats.bytes_downloaded))}</div>
+            <div>{format!("Last crawled: {}", last_crawled)}</div>
+        </div>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct OverviewProps {
+    status: AppStatus,
+}
+
+/// Index-wide numbers shown above the per-domain crawl status.
+#[function_component(Overview)]
+fn overview(props: &OverviewProps) -> Html {
+    let status = &props.status;
+    let lenses = status
+        .lenses
+        .iter()
+        .map(|lens| {
+            html! {
+                <div class="flex flex-row">
+                    <div class="flex-grow">{lens.name.clone()}</div>
+                    <div>{format_number(lens.num_docs)}</div>
+                </div>
+            }
+        })
+        .collect::<Html>();
+    let domains = status
+        .top_domains
+        .iter()
+        .map(|domain| {
+            html! {
+                <div class="flex flex-row">
+                    <div class="flex-grow">{domain.domain.clone()}</div>
+                    <div>{format_number(domain.num_docs)}</div>
+                </div>
+            }
+        })
+        .collect::<Html>();
+
+    html! {
+        <div class="p-4 px-8 text-xs">
+            <div class="flex flex-row gap-4 pb-4">
+                <div>{format!("{} documents", format_number(status.num_docs))}</div>
+                <div>{format!("Index: {}", format_bytes(status.disk.index))}</div>
+                <div>{format!("Database: {}", format_bytes(status.disk.db))}</div>
+                <div title="Queued URLs that ran out of retries / failed attempts in the crawl log">
+                    {format!(
+                        "{} failed, {} recent errors",
+                        format_number(status.errors.num_failed),
+                        format_number(status.errors.num_logged)
+                    )}
+                </div>
+            </div>
+            <div class="grid grid-cols-2 gap-8 text-neutral-400">
+                <div>
+                    <div class="pb-1 text-white">{"Documents per lens"}</div>
+                    {lenses}
+                </div>
+                <div>
+                    <div class="pb-1 text-white">{"Top domains"}</div>
+                    {domains}
+                </div>
+            </div>
+        </div>
+    }
+}
+
 #[derive(Properties, PartialEq)]
 pub struct LegendIconProps {
     pub color: String,
@@ -100,22 +225,31 @@ fn stats_bar(props: &StatsBarProps) -> Html {
 #[function_component(StatsPage)]
 pub fn stats_page() -> Html {
     let stats: UseStateHandle<Vec<(String, QueueStatus)>> = use_state_eq(Vec::new);
+    let app_status: UseStateHandle<Option<AppStatus>> = use_state_eq(|| None);
     let request_finished = use_state(|| false);
 
     if stats.is_empty() && !(*request_finished) {
         fetch_crawl_stats(stats.clone(), request_finished.clone());
+        fetch_app_status(app_status.clone());
     }
 
     let onclick = {
         let request_finished = request_finished.clone();
         let stats = stats.clone();
+        let app_status = app_status.clone();
         Callback::from(move |_| {
             request_finished.set(false);
             stats.set(Vec::new());
             fetch_crawl_stats(stats.clone(), request_finished.clone());
+            fetch_app_status(app_status.clone());
         })
     };
 
+    let overview = match &*app_status {
+        Some(status) => html! { <Overview status={status.clone()} /> },
+        None => html! {},
+    };
+
     let mut rendered = stats
         .iter()
         .map(|(domain, stats)| {
@@ -133,6 +267,7 @@ pub fn stats_page() -> Html {
                         <StatsBar count={stats.num_completed} total={total} color={"bg-lime-600"} />
                         <StatsBar count={stats.num_indexed} total={total} color={"bg-lime-800"} is_end={true} />
                     </div>
+                    <CrawlDetails stats={stats.crawl.clone()} />
                 </div>
             }
         })
@@ -179,6 +314,7 @@ pub fn stats_page() -> Html {
                 </div>
             </div>
             <div class="divide-y divide-neutral-600">
+                {overview}
                 {rendered}
             </div>
         </div>
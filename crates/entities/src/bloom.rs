@@ -0,0 +1,165 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, RwLock};
+
+/// Default number of URLs we expect to track before the false positive rate
+/// starts to degrade. Also the smallest filter `sized_for` creates.
+pub const DEFAULT_EXPECTED_ITEMS: usize = 1_000_000;
+pub const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+pub type SharedBloomFilter = Arc<RwLock<BloomFilter>>;
+
+/// A simple bloom filter used to quickly check whether we've seen a URL before
+/// without hitting the database. False positives are possible (an unseen URL may
+/// be reported as seen), false negatives are not.
+#[derive(Clone, Debug)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+    num_items: usize,
+    /// Number of items the filter was sized for.
+    capacity: usize,
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_EXPECTED_ITEMS, DEFAULT_FALSE_POSITIVE_RATE)
+    }
+}
+
+impl BloomFilter {
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> Self {
+        let capacity = expected_items.max(1);
+        let expected_items = capacity as f64;
+        let ln2 = std::f64::consts::LN_2;
+
+        // Optimal filter size & number of hash functions, see:
+        // https://en.wikipedia.org/wiki/Bloom_filter#Optimal_number_of_hash_functions
+        let num_bits = (-(expected_items * false_positive_rate.ln()) / (ln2 * ln2)).ceil() as u64;
+        let num_bits = num_bits.max(64);
+        let num_hashes = ((num_bits as f64 / expected_items) * ln2).round().max(1.0) as u32;
+
+        BloomFilter {
+            bits: vec![0; (num_bits / 64 + 1) as usize],
+            num_bits,
+            num_hashes,
+            num_items: 0,
+            capacity,
+        }
+    }
+
+    /// Filter w/ room for `num_items` to double before it needs to be rebuilt.
+    pub fn sized_for(num_items: usize) -> Self {
+        Self::new(
+            num_items.saturating_mul(2).max(DEFAULT_EXPECTED_ITEMS),
+            DEFAULT_FALSE_POSITIVE_RATE,
+        )
+    }
+
+    pub fn shared(self) -> SharedBloomFilter {
+        Arc::new(RwLock::new(self))
+    }
+
+    /// Number of items inserted into the filter.
+    pub fn len(&self) -> usize {
+        self.num_items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_items == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Past this point the false positive rate keeps going up, rebuild the
+    /// filter w/ `sized_for` instead.
+    pub fn is_over_capacity(&self) -> bool {
+        self.num_items > self.capacity
+    }
+
+    pub fn insert(&mut self, item: &str) {
+        for idx in self.indices(item) {
+            self.bits[(idx / 64) as usize] |= 1 << (idx % 64);
+        }
+        self.num_items += 1;
+    }
+
+    pub fn contains(&self, item: &str) -> bool {
+        self.indices(item)
+            .all(|idx| self.bits[(idx / 64) as usize] & (1 << (idx % 64)) != 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.iter_mut().for_each(|block| *block = 0);
+        self.num_items = 0;
+    }
+
+    /// Uses double hashing to generate `num_hashes` bit positions from two
+    /// base hashes.
+    fn indices(&self, item: &str) -> impl Iterator<Item = u64> {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        let h1 = hasher.finish();
+        // Hash again w/ the first hash mixed in to get a second, independent hash.
+        h1.hash(&mut hasher);
+        let h2 = hasher.finish() | 1;
+
+        let num_bits = self.num_bits;
+        (0..self.num_hashes as u64).map(move |idx| h1.wrapping_add(idx.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BloomFilter, DEFAULT_EXPECTED_ITEMS};
+
+    #[test]
+    fn test_insert_contains() {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        assert!(filter.is_empty());
+
+        filter.insert("https://example.com/");
+        filter.insert("https://example.com/blog");
+        assert_eq!(filter.len(), 2);
+
+        assert!(filter.contains("https://example.com/"));
+        assert!(filter.contains("https://example.com/blog"));
+        assert!(!filter.contains("https://example.com/about"));
+
+        filter.clear();
+        assert!(!filter.contains("https://example.com/"));
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let mut filter = BloomFilter::new(10_000, 0.01);
+        for idx in 0..10_000 {
+            filter.insert(&format!("https://example.com/{}", idx));
+        }
+
+        let false_positives = (10_000..20_000)
+            .filter(|idx| filter.contains(&format!("https://example.com/{}", idx)))
+            .count();
+        // Give ourselves some wiggle room over the target 1%
+        assert!(false_positives < 300);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut filter = BloomFilter::new(2, 0.01);
+        filter.insert("https://example.com/");
+        filter.insert("https://example.com/blog");
+        assert!(!filter.is_over_capacity());
+        filter.insert("https://example.com/about");
+        assert!(filter.is_over_capacity());
+
+        assert_eq!(BloomFilter::sized_for(0).capacity(), DEFAULT_EXPECTED_ITEMS);
+        assert_eq!(
+            BloomFilter::sized_for(DEFAULT_EXPECTED_ITEMS).capacity(),
+            DEFAULT_EXPECTED_ITEMS * 2
+        );
+    }
+}
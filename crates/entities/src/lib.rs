@@ -1,4 +1,6 @@
+pub mod bloom;
 pub mod models;
+pub mod normalize;
 pub mod regex;
 pub mod test;
 
@@ -0,0 +1,172 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::SimpleExpr;
+use sea_orm::{QueryOrder, QuerySelect, Set};
+
+/// A single crawl attempt, kept around for a while to debug pages that never
+/// make it into the index.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "crawl_log")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub domain: String,
+    pub url: String,
+    /// HTTP status, not set if we never got a response.
+    pub status: Option<i32>,
+    /// Time spent fetching the page.
+    pub duration_ms: i64,
+    /// Size of the fetched body, after decompression.
+    pub num_bytes: i64,
+    /// Why the attempt failed or was skipped, if it did.
+    pub error: Option<String>,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        panic!("No RelationDef")
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {
+    fn new() -> Self {
+        Self {
+            created_at: Set(chrono::Utc::now()),
+            ..ActiveModelTrait::default()
+        }
+    }
+}
+
+impl Model {
+    pub fn is_error(&self) -> bool {
+        self.error.is_some() || matches!(self.status, Some(status) if status >= 400)
+    }
+}
+
+/// A crawl attempt to add to the log, see `record`.
+#[derive(Clone, Debug, Default)]
+pub struct Attempt {
+    pub domain: String,
+    pub url: String,
+    pub status: Option<u16>,
+    pub duration_ms: u64,
+    pub num_bytes: u64,
+    pub error: Option<String>,
+}
+
+pub async fn record(db: &DatabaseConnection, attempt: Attempt) -> anyhow::Result<(), DbErr> {
+    let entry = ActiveModel {
+        domain: Set(attempt.domain),
+        url: Set(attempt.url),
+        status: Set(attempt.status.map(i32::from)),
+        duration_ms: Set(attempt.duration_ms as i64),
+        num_bytes: Set(attempt.num_bytes as i64),
+        error: Set(attempt.error),
+        ..Default::default()
+    };
+    entry.insert(db).await?;
+
+    Ok(())
+}
+
+/// Attempts that errored out or got an error status back.
+fn is_error_condition() -> SimpleExpr {
+    Column::Error
+        .is_not_null()
+        .or(Column::Status.gte(400))
+        .or(Column::Status.is_null())
+}
+
+/// Most recent attempts first, optionally only those for `domain` or ones
+/// that failed.
+pub async fn recent(
+    db: &DatabaseConnection,
+    domain: Option<&str>,
+    errors_only: bool,
+    limit: u64,
+) -> anyhow::Result<Vec<Model>, DbErr> {
+    let mut query = Entity::find();
+    if let Some(domain) = domain {
+        query = query.filter(Column::Domain.eq(domain));
+    }
+    if errors_only {
+        query = query.filter(is_error_condition());
+    }
+
+    query.order_by_desc(Column::Id).limit(limit).all(db).await
+}
+
+/// Number of failed attempts still in the log.
+pub async fn num_errors(db: &DatabaseConnection) -> anyhow::Result<u64, DbErr> {
+    let count = Entity::find()
+        .filter(is_error_condition())
+        .count(db)
+        .await?;
+
+    Ok(count as u64)
+}
+
+/// Remove attempts made before `before`, returns the number removed.
+pub async fn prune(db: &DatabaseConnection, before: DateTimeUtc) -> anyhow::Result<u64, DbErr> {
+    let res = Entity::delete_many()
+        .filter(Column::CreatedAt.lt(before))
+        .exec(db)
+        .await?;
+
+    Ok(res.rows_affected)
+}
+
+#[cfg(test)]
+mod test {
+    use sea_orm::prelude::*;
+    use sea_orm::{ActiveModelTrait, Set};
+
+    use super::Attempt;
+    use crate::models::crawl_log;
+    use crate::test::setup_test_db;
+
+    #[tokio::test]
+    async fn test_recent_and_prune() {
+        let db = setup_test_db().await;
+
+        let attempts = [
+            ("example.com", Some(200), None),
+            ("example.com", Some(404), None),
+            ("example.com", None, Some("timed out")),
+            ("rust-lang.org", Some(200), None),
+        ];
+        for (domain, status, error) in attempts {
+            let attempt = Attempt {
+                domain: domain.to_string(),
+                url: format!("https://{}/", domain),
+                status,
+                error: error.map(String::from),
+                ..Default::default()
+            };
+            crawl_log::record(&db, attempt).await.unwrap();
+        }
+
+        let recent = crawl_log::recent(&db, None, false, 3).await.unwrap();
+        assert_eq!(recent.len(), 3);
+        assert_eq!(recent[0].domain, "rust-lang.org");
+
+        let errors = crawl_log::recent(&db, Some("example.com"), true, 10)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|entry| entry.is_error()));
+        assert_eq!(crawl_log::num_errors(&db).await.unwrap(), 2);
+
+        // Backdate one entry past the retention period
+        let mut old: crawl_log::ActiveModel = recent[0].clone().into();
+        old.created_at = Set(chrono::Utc::now() - chrono::Duration::days(30));
+        old.update(&db).await.unwrap();
+
+        let before = chrono::Utc::now() - chrono::Duration::days(7);
+        assert_eq!(crawl_log::prune(&db, before).await.unwrap(), 1);
+        assert_eq!(crawl_log::Entity::find().count(&db).await.unwrap(), 3);
+    }
+}
@@ -1,21 +1,32 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 use regex::RegexSet;
 use sea_orm::entity::prelude::*;
 use sea_orm::sea_query::{OnConflict, SqliteQueryBuilder};
 use sea_orm::{
-    sea_query, ConnectionTrait, DbBackend, FromQueryResult, QuerySelect, QueryTrait, Set, Statement,
+    sea_query, Condition, ConnectionTrait, DbBackend, FromQueryResult, QueryOrder, QuerySelect,
+    QueryTrait, Set, Statement,
 };
 use serde::Serialize;
 use url::Url;
 
 use super::indexed_document;
+use crate::bloom::{BloomFilter, SharedBloomFilter};
+use crate::normalize::normalize;
 use crate::regex::{regex_for_domain, regex_for_prefix, regex_for_robots, WildcardType};
 use shared::config::{Lens, LensRule, Limit, UserSettings};
+use shared::response::{QueueCounts, QueueItemStatus};
 
-const MAX_RETRIES: u8 = 5;
 const BATCH_SIZE: usize = 10000;
+/// Delay before the first retry of a failed task, doubled w/ each retry.
+const RETRY_BACKOFF_SECS: i64 = 60;
+/// Cap on the delay between retries.
+const MAX_RETRY_BACKOFF_SECS: i64 = 60 * 60 * 24;
+/// Priority of URLs added by the user, e.g. through `add_queue`.
+pub const PRIORITY_USER: i32 = 100;
+/// Priority of lens seeds, crawled before the links found along the way.
+pub const PRIORITY_SEED: i32 = 50;
 
 #[derive(Debug, Clone, PartialEq, EnumIter, DeriveActiveEnum, Serialize)]
 #[sea_orm(rs_type = "String", db_type = "String(Some(1))")]
@@ -41,6 +52,28 @@ impl fmt::Display for CrawlStatus {
     }
 }
 
+impl From<CrawlStatus> for QueueItemStatus {
+    fn from(status: CrawlStatus) -> Self {
+        match status {
+            CrawlStatus::Queued => QueueItemStatus::Queued,
+            CrawlStatus::Processing => QueueItemStatus::Processing,
+            CrawlStatus::Completed => QueueItemStatus::Completed,
+            CrawlStatus::Failed => QueueItemStatus::Failed,
+        }
+    }
+}
+
+impl From<QueueItemStatus> for CrawlStatus {
+    fn from(status: QueueItemStatus) -> Self {
+        match status {
+            QueueItemStatus::Queued => CrawlStatus::Queued,
+            QueueItemStatus::Processing => CrawlStatus::Processing,
+            QueueItemStatus::Completed => CrawlStatus::Completed,
+            QueueItemStatus::Failed => CrawlStatus::Failed,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, EnumIter, DeriveActiveEnum, Serialize)]
 #[sea_orm(rs_type = "String", db_type = "String(Some(1))")]
 pub enum CrawlType {
@@ -83,14 +116,45 @@ pub struct Model {
     /// Number of retries for this task.
     #[sea_orm(default_value = 0)]
     pub num_retries: u8,
+    /// Failed tasks aren't retried until after this time.
+    pub next_retry_at: Option<DateTimeUtc>,
+    /// Tasks leased out to a remote worker are queued again if the worker
+    /// hasn't reported back by this time.
+    pub lease_expires_at: Option<DateTimeUtc>,
     /// Crawl Type
     pub crawl_type: CrawlType,
+    /// Number of links followed from the seed URL to get here.
+    #[sea_orm(default_value = 0)]
+    pub depth: i32,
+    /// Links are only followed while `depth` is below this. Inherited from
+    /// the seed URL, no limit when not set.
+    pub max_depth: Option<i32>,
+    /// Tasks w/ a higher priority are crawled first. Links found while
+    /// crawling are 0.
+    #[sea_orm(default_value = 0)]
+    pub priority: i32,
     /// When this was first added to the crawl queue.
     pub created_at: DateTimeUtc,
     /// When this task was last updated.
     pub updated_at: DateTimeUtc,
 }
 
+impl Model {
+    /// Settings for enqueuing the links found on this page, `None` if the
+    /// page is as deep as it's allowed to go.
+    pub fn link_settings(&self) -> Option<EnqueueSettings> {
+        if matches!(self.max_depth, Some(max_depth) if self.depth >= max_depth) {
+            return None;
+        }
+
+        Some(EnqueueSettings {
+            depth: self.depth + 1,
+            max_depth: self.max_depth,
+            ..Default::default()
+        })
+    }
+}
+
 #[derive(Copy, Clone, Debug, EnumIter)]
 pub enum Relation {}
 
@@ -163,6 +227,41 @@ pub struct QueueCountByStatus {
     pub status: String,
 }
 
+#[derive(Debug, FromQueryResult)]
+struct StatusCount {
+    count: i64,
+    status: String,
+}
+
+/// Number of tasks w/ each status, limited to `domain` if set.
+pub async fn status_counts(
+    db: &DatabaseConnection,
+    domain: Option<&str>,
+) -> anyhow::Result<QueueCounts, sea_orm::DbErr> {
+    let mut query = Entity::find()
+        .select_only()
+        .column(Column::Status)
+        .column_as(Column::Id.count(), "count")
+        .group_by(Column::Status);
+    if let Some(domain) = domain {
+        query = query.filter(Column::Domain.eq(domain));
+    }
+
+    let mut counts = QueueCounts::default();
+    for row in query.into_model::<StatusCount>().all(db).await? {
+        let count = row.count as u64;
+        match row.status.as_str() {
+            "Queued" => counts.num_queued += count,
+            "Processing" => counts.num_processing += count,
+            "Completed" => counts.num_completed += count,
+            "Failed" => counts.num_failed += count,
+            _ => {}
+        }
+    }
+
+    Ok(counts)
+}
+
 pub async fn num_queued(
     db: &DatabaseConnection,
     status: CrawlStatus,
@@ -177,6 +276,53 @@ pub async fn num_queued(
     Ok(res.unwrap().count as u64)
 }
 
+#[derive(FromQueryResult)]
+struct UrlOnly {
+    url: String,
+}
+
+/// Filter w/ every URL currently in the crawl queue & index, sized from the
+/// number of URLs w/ room to grow.
+pub async fn load_seen_urls(
+    db: &DatabaseConnection,
+) -> anyhow::Result<BloomFilter, sea_orm::DbErr> {
+    let queued = Entity::find()
+        .select_only()
+        .column(Column::Url)
+        .into_model::<UrlOnly>()
+        .all(db)
+        .await?;
+
+    let indexed = indexed_document::Entity::find()
+        .select_only()
+        .column(indexed_document::Column::Url)
+        .into_model::<UrlOnly>()
+        .all(db)
+        .await?;
+
+    let mut filter = BloomFilter::sized_for(queued.len() + indexed.len());
+    for entry in queued.iter().chain(indexed.iter()) {
+        filter.insert(&entry.url);
+    }
+
+    Ok(filter)
+}
+
+/// Replace the contents of `seen_urls` w/ a freshly loaded filter, e.g. once
+/// it's over capacity or after URLs were removed. URLs added in the meantime
+/// may be missing, which only means they're checked against the database.
+pub async fn rebuild_seen_urls(
+    db: &DatabaseConnection,
+    seen_urls: &SharedBloomFilter,
+) -> anyhow::Result<(), sea_orm::DbErr> {
+    let filter = load_seen_urls(db).await?;
+    if let Ok(mut seen_urls) = seen_urls.write() {
+        *seen_urls = filter;
+    }
+
+    Ok(())
+}
+
 fn gen_priority_values(items: &[String], is_prefix: bool) -> String {
     if items.is_empty() {
         "(\"\", 0)".to_string()
@@ -199,6 +345,41 @@ fn gen_priority_values(items: &[String], is_prefix: bool) -> String {
     }
 }
 
+/// Domains & URL prefixes whose tasks are left in the queue, e.g. while
+/// crawling them is paused. Domains may use `*` wildcards & prefixes ending
+/// in `$` only match that exact URL, same as in lenses.
+#[derive(Clone, Debug, Default)]
+pub struct SkipFilter {
+    pub domains: Vec<String>,
+    pub prefixes: Vec<String>,
+}
+
+impl SkipFilter {
+    /// `LIKE` patterns to skip, w/ the SQL for a `values` list of them.
+    fn patterns(items: &[String], is_prefix: bool) -> (String, Vec<Value>) {
+        if items.is_empty() {
+            return ("(\"\")".to_string(), Vec::new());
+        }
+
+        let values = items
+            .iter()
+            .map(|item| {
+                let pattern = if is_prefix {
+                    match item.strip_suffix('$') {
+                        Some(url) => url.to_string(),
+                        None => format!("{}%", item),
+                    }
+                } else {
+                    item.replace('*', "%")
+                };
+                pattern.into()
+            })
+            .collect();
+
+        (vec!["(?)"; items.len()].join(","), values)
+    }
+}
+
 fn gen_priority_sql(p_domains: &str, p_prefixes: &str, user_settings: UserSettings) -> Statement {
     Statement::from_sql_and_values(
         DbBackend::Sqlite,
@@ -301,28 +482,177 @@ pub async fn dequeue(
     return entity.one(db).await;
 }
 
-/// Add url to the crawl queue
-#[derive(PartialEq)]
+/// Grab a batch of tasks from the crawl queue & mark them as processing. Unlike
+/// `dequeue`, inflight limits are not checked here & are expected to be enforced
+/// by the caller.
+pub async fn dequeue_batch(
+    db: &DatabaseConnection,
+    user_settings: &UserSettings,
+    // Prioritized domains
+    p_domains: &[String],
+    // Prioritized prefixes
+    p_prefixes: &[String],
+    skip: &SkipFilter,
+    batch_size: u64,
+) -> anyhow::Result<Vec<Model>, sea_orm::DbErr> {
+    let prioritized_domains = gen_priority_values(p_domains, false);
+    let prioritized_prefixes = gen_priority_values(p_prefixes, true);
+    let (skipped_domains, mut values) = SkipFilter::patterns(&skip.domains, false);
+    let (skipped_prefixes, prefix_values) = SkipFilter::patterns(&skip.prefixes, true);
+    values.extend(prefix_values);
+
+    let statement = Statement::from_sql_and_values(
+        DbBackend::Sqlite,
+        &format!(
+            r#"WITH
+                p_domain(domain, priority) AS (values {}),
+                p_prefix(prefix, priority) AS (values {}),
+                skip_domain(domain) AS (values {}),
+                skip_prefix(prefix) AS (values {}), {}"#,
+            prioritized_domains,
+            prioritized_prefixes,
+            skipped_domains,
+            skipped_prefixes,
+            include_str!("sql/dequeue_batch.sqlx")
+        ),
+        values
+            .into_iter()
+            .chain([
+                user_settings.domain_crawl_limit.value().into(),
+                chrono::Utc::now().into(),
+                batch_size.into(),
+            ])
+            .collect(),
+    );
+
+    let tasks = Entity::find().from_raw_sql(statement).all(db).await?;
+    if tasks.is_empty() {
+        return Ok(tasks);
+    }
+
+    // Mark tasks as in-progress so they're not dequeued again.
+    let ids: Vec<i64> = tasks.iter().map(|task| task.id).collect();
+    Entity::update_many()
+        .col_expr(
+            Column::Status,
+            sea_query::Expr::value(sea_query::Value::String(Some(Box::new(
+                CrawlStatus::Processing.to_string(),
+            )))),
+        )
+        .col_expr(
+            Column::LeaseExpiresAt,
+            sea_query::Expr::value(Option::<DateTimeUtc>::None),
+        )
+        .filter(Column::Id.is_in(ids))
+        .exec(db)
+        .await?;
+
+    Ok(tasks)
+}
+
+/// Tasks leased out to a remote worker are queued again if the worker hasn't
+/// reported back by `expires_at`, see `requeue_expired_leases`.
+pub async fn set_lease(
+    db: &DatabaseConnection,
+    ids: &[i64],
+    expires_at: DateTimeUtc,
+) -> anyhow::Result<()> {
+    for chunk in ids.chunks(BATCH_SIZE) {
+        Entity::update_many()
+            .col_expr(
+                Column::LeaseExpiresAt,
+                sea_query::Expr::value(Some(expires_at)),
+            )
+            .filter(Column::Id.is_in(chunk.to_vec()))
+            .exec(db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Queue leased tasks again once their lease has expired, e.g. when the
+/// worker went away. Returns the number of tasks requeued.
+pub async fn requeue_expired_leases(
+    db: &DatabaseConnection,
+    now: DateTimeUtc,
+) -> anyhow::Result<u64> {
+    let res = Entity::update_many()
+        .col_expr(
+            Column::Status,
+            sea_query::Expr::value(sea_query::Value::String(Some(Box::new(
+                CrawlStatus::Queued.to_string(),
+            )))),
+        )
+        .col_expr(
+            Column::LeaseExpiresAt,
+            sea_query::Expr::value(Option::<DateTimeUtc>::None),
+        )
+        .filter(Column::Status.eq(CrawlStatus::Processing))
+        .filter(Column::LeaseExpiresAt.lt(now))
+        .exec(db)
+        .await?;
+
+    Ok(res.rows_affected)
+}
+
+/// Why a URL wasn't added to the crawl queue, see `enqueue_all`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SkipReason {
+    /// Not a URL we can crawl.
     Invalid,
+    /// On the block list or not in any of the lenses.
     Blocked,
+    /// Already queued or indexed.
     Duplicate,
 }
 
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipReason::Invalid => write!(f, "Invalid URL"),
+            SkipReason::Blocked => write!(f, "Blocked by the block list or lens rules"),
+            SkipReason::Duplicate => write!(f, "Already queued or indexed"),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct EnqueueSettings {
     pub crawl_type: CrawlType,
+    /// Filter of URLs we've already enqueued/indexed. When set, URLs that are
+    /// (probably) in the filter are skipped w/o checking the database.
+    pub seen_urls: Option<SharedBloomFilter>,
+    /// Depth of the new tasks, see `Model::depth`.
+    pub depth: i32,
+    pub max_depth: Option<i32>,
+    /// See `Model::priority`.
+    pub priority: i32,
+}
+
+impl EnqueueSettings {
+    /// Settings for the seed URLs of a lens, i.e. its domains & URL prefixes.
+    pub fn for_seeds_of(lens: &Lens) -> Self {
+        EnqueueSettings {
+            max_depth: lens.max_depth.map(|depth| depth as i32),
+            priority: PRIORITY_SEED,
+            ..Default::default()
+        }
+    }
 }
 
+/// Add `urls` to the crawl queue. Returns the URLs that were skipped & why,
+/// anything else was added.
 pub async fn enqueue_all(
     db: &DatabaseConnection,
     urls: &[String],
     lenses: &[Lens],
     settings: &UserSettings,
     overrides: &EnqueueSettings,
-) -> anyhow::Result<(), sea_orm::DbErr> {
+) -> anyhow::Result<HashMap<String, SkipReason>, sea_orm::DbErr> {
     let mut allow_list: Vec<String> = Vec::new();
     let mut skip_list: Vec<String> = Vec::new();
+    let mut skipped = HashMap::new();
 
     for domain in settings.block_list.iter() {
         skip_list.push(regex_for_domain(domain));
@@ -337,77 +667,120 @@ pub async fn enqueue_all(
     let allow_list = RegexSet::new(allow_list).expect("Unable to create allow list");
     let skip_list = RegexSet::new(skip_list).expect("Unable to create skip list");
 
-    // Ignore invalid URLs
-    let urls: Vec<String> = urls
-        .iter()
-        .filter_map(|url| {
-            if let Ok(mut parsed) = Url::parse(url) {
-                // Always ignore fragments, otherwise crawling
-                // https://wikipedia.org/Rust#Blah would be considered different than
-                // https://wikipedia.org/Rust
-                parsed.set_fragment(None);
-
-                let normalized = parsed.to_string();
-
-                // Ignore domains on blacklist
-                if skip_list.is_match(&normalized) {
-                    return None;
-                }
+    // Ignore invalid URLs, pairs of the URL as given & its normalized form.
+    let mut to_check: Vec<(String, String)> = Vec::with_capacity(urls.len());
+    for url in urls {
+        let mut parsed = match Url::parse(url) {
+            Ok(parsed) if parsed.host_str().is_some() => parsed,
+            _ => {
+                skipped.insert(url.clone(), SkipReason::Invalid);
+                continue;
+            }
+        };
 
-                // Should we crawl external links?
-                if settings.crawl_external_links {
-                    return Some(normalized);
-                }
+        // Otherwise crawling https://wikipedia.org/Rust#Blah or
+        // https://wikipedia.org/Rust?utm_source=foo would be considered
+        // different than https://wikipedia.org/Rust
+        normalize(&mut parsed);
+        let normalized = parsed.to_string();
+
+        // Ignore domains on blacklist. If external links are not allowed,
+        // only allow crawls specified in our lenses
+        let is_allowed = settings.crawl_external_links
+            || allow_list.is_empty()
+            || allow_list.is_match(&normalized);
+        if skip_list.is_match(&normalized) || !is_allowed {
+            skipped.insert(url.clone(), SkipReason::Blocked);
+            continue;
+        }
+
+        to_check.push((url.clone(), normalized));
+    }
 
-                // If external links are not allowed, only allow crawls specified
-                // in our lenses
-                if allow_list.is_empty() || allow_list.is_match(&normalized) {
-                    return Some(normalized);
+    // Skip anything we've (probably) seen before
+    if let Some(seen_urls) = &overrides.seen_urls {
+        if let Ok(seen_urls) = seen_urls.read() {
+            to_check.retain(|(url, normalized)| {
+                let is_seen = seen_urls.contains(normalized);
+                if is_seen {
+                    skipped.insert(url.clone(), SkipReason::Duplicate);
                 }
-            }
+                !is_seen
+            });
+        }
+    }
 
-            None
-        })
-        .collect();
+    if to_check.is_empty() {
+        return Ok(skipped);
+    }
+
+    // Ignore urls already indexed or queued, including other forms of the
+    // same URL (http vs https, trailing slashes).
+    let mut is_known: HashSet<String> = HashSet::with_capacity(to_check.len());
+    for chunk in to_check.chunks(BATCH_SIZE / 4) {
+        let variants: Vec<String> = chunk
+            .iter()
+            .flat_map(|(_, url)| indexed_document::url_variants(url))
+            .collect();
 
-    // Ignore urls already indexed
-    let mut is_indexed: HashSet<String> = HashSet::with_capacity(urls.len());
-    // Igore urls already indexed
-    for chunk in urls.chunks(BATCH_SIZE) {
-        let chunk = chunk.iter().map(|url| url.to_string()).collect::<Vec<_>>();
         for entry in indexed_document::Entity::find()
-            .filter(indexed_document::Column::Url.is_in(chunk.clone()))
+            .filter(indexed_document::Column::Url.is_in(variants.clone()))
+            .all(db)
+            .await?
+        {
+            is_known.insert(entry.url);
+        }
+
+        for entry in Entity::find()
+            .filter(Column::Url.is_in(variants))
             .all(db)
             .await?
-            .iter()
         {
-            is_indexed.insert(entry.url.to_string());
+            is_known.insert(entry.url);
         }
     }
 
-    let to_add: Vec<ActiveModel> = urls
-        .into_iter()
-        .filter_map(|url| {
-            let mut result = None;
-            if !is_indexed.contains(&url) {
-                if let Ok(parsed) = Url::parse(&url) {
-                    if let Some(domain) = parsed.host_str() {
-                        result = Some(ActiveModel {
-                            domain: Set(domain.to_string()),
-                            crawl_type: Set(overrides.crawl_type.clone()),
-                            url: Set(url.to_string()),
-                            ..Default::default()
-                        });
-                    }
-                }
+    // Either already indexed or about to be added to the queue, no need to check
+    // these again.
+    let mut is_over_capacity = false;
+    if let Some(seen_urls) = &overrides.seen_urls {
+        if let Ok(mut seen_urls) = seen_urls.write() {
+            for (_, url) in to_check.iter() {
+                seen_urls.insert(url);
             }
-            result
-        })
-        .collect();
+            is_over_capacity = seen_urls.is_over_capacity();
+        }
+    }
+
+    let mut to_add: Vec<ActiveModel> = Vec::with_capacity(to_check.len());
+    for (url, normalized) in to_check {
+        let variants = indexed_document::url_variants(&normalized);
+        if variants.iter().any(|variant| is_known.contains(variant)) {
+            skipped.insert(url, SkipReason::Duplicate);
+            continue;
+        }
 
-    if to_add.is_empty() {
-        return Ok(());
+        // Skip other forms of this URL later in the batch
+        is_known.insert(normalized.clone());
+        let domain = Url::parse(&normalized)
+            .ok()
+            .and_then(|parsed| parsed.host_str().map(|host| host.to_string()));
+        match domain {
+            Some(domain) => to_add.push(ActiveModel {
+                domain: Set(domain),
+                crawl_type: Set(overrides.crawl_type.clone()),
+                url: Set(normalized),
+                depth: Set(overrides.depth),
+                max_depth: Set(overrides.max_depth),
+                priority: Set(overrides.priority),
+                ..Default::default()
+            }),
+            None => {
+                skipped.insert(url, SkipReason::Invalid);
+            }
+        }
     }
+
     for to_add in to_add.chunks(BATCH_SIZE) {
         let owned = to_add.iter().map(|r| r.to_owned()).collect::<Vec<_>>();
 
@@ -430,7 +803,16 @@ pub async fn enqueue_all(
         }
     }
 
-    Ok(())
+    if is_over_capacity {
+        if let Some(seen_urls) = &overrides.seen_urls {
+            log::info!("seen URLs filter is over capacity, rebuilding");
+            if let Err(err) = rebuild_seen_urls(db, seen_urls).await {
+                log::error!("Unable to rebuild seen URLs: {}", err);
+            }
+        }
+    }
+
+    Ok(skipped)
 }
 
 pub async fn mark_done(
@@ -438,22 +820,190 @@ pub async fn mark_done(
     id: i64,
     status: CrawlStatus,
 ) -> anyhow::Result<()> {
+    if let Some(crawl) = Entity::find_by_id(id).one(db).await? {
+        let mut updated: ActiveModel = crawl.into();
+        updated.status = Set(status);
+        updated.num_retries = Set(0);
+        updated.next_retry_at = Set(None);
+        updated.lease_expires_at = Set(None);
+        updated.update(db).await?;
+    }
+
+    Ok(())
+}
+
+/// Time to wait before retrying a task that has failed `num_retries` times.
+fn retry_backoff(num_retries: u8) -> chrono::Duration {
+    let backoff = RETRY_BACKOFF_SECS.saturating_mul(1 << num_retries.min(32));
+    chrono::Duration::seconds(backoff.min(MAX_RETRY_BACKOFF_SECS))
+}
+
+/// Queue a failed task to be retried later w/ exponential backoff. Once it's
+/// been retried `max_retries` times it's marked as `Failed` for good & the
+/// task is returned.
+pub async fn mark_failed(
+    db: &DatabaseConnection,
+    id: i64,
+    max_retries: u8,
+) -> anyhow::Result<Option<Model>> {
     if let Some(crawl) = Entity::find_by_id(id).one(db).await? {
         let mut updated: ActiveModel = crawl.clone().into();
+        updated.lease_expires_at = Set(None);
 
-        // Bump up number of retries if this failed
-        if status == CrawlStatus::Failed && crawl.num_retries <= MAX_RETRIES {
-            updated.num_retries = Set(crawl.num_retries + 1);
-            // Queue again
+        if crawl.num_retries < max_retries {
             updated.status = Set(CrawlStatus::Queued);
+            updated.num_retries = Set(crawl.num_retries + 1);
+            updated.next_retry_at =
+                Set(Some(chrono::Utc::now() + retry_backoff(crawl.num_retries)));
         } else {
-            updated.status = Set(status);
+            log::warn!("giving up on <{}> after {} retries", crawl.url, max_retries);
+            updated.status = Set(CrawlStatus::Failed);
+            updated.next_retry_at = Set(None);
         }
 
-        updated.update(db).await?;
+        let updated = updated.update(db).await?;
+        if updated.status == CrawlStatus::Failed {
+            return Ok(Some(updated));
+        }
     }
 
-    Ok(())
+    Ok(None)
+}
+
+/// Mark tasks for these URLs as queued again, e.g. when their documents were
+/// lost from the index. Returns the number of tasks requeued.
+pub async fn requeue_urls(db: &DatabaseConnection, urls: &[String]) -> anyhow::Result<u64> {
+    let mut num_requeued = 0;
+    // Stay well under SQLite's limit on the number of bound variables.
+    for chunk in urls.chunks(500) {
+        let res = Entity::update_many()
+            .col_expr(
+                Column::Status,
+                sea_query::Expr::value(sea_query::Value::String(Some(Box::new(
+                    CrawlStatus::Queued.to_string(),
+                )))),
+            )
+            .col_expr(Column::NumRetries, sea_query::Expr::value(0))
+            .col_expr(
+                Column::NextRetryAt,
+                sea_query::Expr::value(Option::<DateTimeUtc>::None),
+            )
+            .filter(Column::Url.is_in(chunk.to_vec()))
+            .exec(db)
+            .await?;
+        num_requeued += res.rows_affected;
+    }
+
+    Ok(num_requeued)
+}
+
+/// Mark every task for `domain` as queued again, e.g. to force a recrawl.
+/// Tasks being crawled right now are left alone. Returns the number of tasks
+/// requeued.
+pub async fn requeue_domain(db: &DatabaseConnection, domain: &str) -> anyhow::Result<u64> {
+    let res = Entity::update_many()
+        .col_expr(
+            Column::Status,
+            sea_query::Expr::value(sea_query::Value::String(Some(Box::new(
+                CrawlStatus::Queued.to_string(),
+            )))),
+        )
+        .col_expr(Column::NumRetries, sea_query::Expr::value(0))
+        .col_expr(
+            Column::NextRetryAt,
+            sea_query::Expr::value(Option::<DateTimeUtc>::None),
+        )
+        .filter(Column::Domain.eq(domain))
+        .filter(Column::Status.ne(CrawlStatus::Processing))
+        .exec(db)
+        .await?;
+
+    Ok(res.rows_affected)
+}
+
+/// Queue up completed tasks that haven't been crawled since `before` so their
+/// content stays fresh. Returns the number of tasks requeued.
+pub async fn requeue_stale(db: &DatabaseConnection, before: DateTimeUtc) -> anyhow::Result<u64> {
+    let res = Entity::update_many()
+        .col_expr(
+            Column::Status,
+            sea_query::Expr::value(sea_query::Value::String(Some(Box::new(
+                CrawlStatus::Queued.to_string(),
+            )))),
+        )
+        .filter(Column::Status.eq(CrawlStatus::Completed))
+        .filter(Column::UpdatedAt.lt(before))
+        .exec(db)
+        .await?;
+
+    Ok(res.rows_affected)
+}
+
+/// Same as `requeue_stale`, limited to tasks for pages in `lens`.
+pub async fn requeue_stale_in_lens(
+    db: &DatabaseConnection,
+    lens: &Lens,
+    before: DateTimeUtc,
+) -> anyhow::Result<u64> {
+    let ruleset = create_ruleset_from_lens(lens);
+    if ruleset.allow_list.is_empty() {
+        return Ok(0);
+    }
+
+    let allow_list = RegexSet::new(ruleset.allow_list)?;
+    let skip_list = RegexSet::new(ruleset.skip_list)?;
+
+    // Narrow things down in SQL first, the regexes have the final say.
+    let mut in_lens = Condition::any();
+    for domain in lens.domains.iter() {
+        in_lens = in_lens.add(Column::Url.like(&format!("%://{}%", domain.replace('*', "%"))));
+    }
+    for prefix in lens.urls.iter() {
+        let prefix = prefix.trim_end_matches('$').replace('*', "%");
+        in_lens = in_lens.add(Column::Url.like(&format!("{}%", prefix)));
+    }
+
+    let mut num_requeued = 0;
+    let mut last_id = 0;
+    loop {
+        let batch = Entity::find()
+            .filter(Column::Status.eq(CrawlStatus::Completed))
+            .filter(Column::UpdatedAt.lt(before))
+            .filter(in_lens.clone())
+            .filter(Column::Id.gt(last_id))
+            .order_by_asc(Column::Id)
+            .limit(BATCH_SIZE as u64)
+            .all(db)
+            .await?;
+
+        last_id = match batch.last() {
+            Some(task) => task.id,
+            None => break,
+        };
+
+        let stale: Vec<i64> = batch
+            .into_iter()
+            .filter(|task| allow_list.is_match(&task.url) && !skip_list.is_match(&task.url))
+            .map(|task| task.id)
+            .collect();
+        if stale.is_empty() {
+            continue;
+        }
+
+        let res = Entity::update_many()
+            .col_expr(
+                Column::Status,
+                sea_query::Expr::value(sea_query::Value::String(Some(Box::new(
+                    CrawlStatus::Queued.to_string(),
+                )))),
+            )
+            .filter(Column::Id.is_in(stale))
+            .exec(db)
+            .await?;
+        num_requeued += res.rows_affected;
+    }
+
+    Ok(num_requeued)
 }
 
 /// Remove tasks from the crawl queue that match `rule`. Rule is expected
@@ -470,6 +1020,34 @@ pub async fn remove_by_rule(db: &DatabaseConnection, rule: &str) -> anyhow::Resu
     Ok(res.rows_affected)
 }
 
+/// Remove tasks matching all of the given filters, e.g. every failed task for
+/// a domain. Nothing is removed w/o at least one filter. Returns the number of
+/// tasks removed.
+pub async fn remove_tasks(
+    db: &DatabaseConnection,
+    ids: &[i64],
+    domain: Option<&str>,
+    status: Option<CrawlStatus>,
+) -> anyhow::Result<u64> {
+    if ids.is_empty() && domain.is_none() && status.is_none() {
+        return Ok(0);
+    }
+
+    let mut query = Entity::delete_many();
+    if !ids.is_empty() {
+        query = query.filter(Column::Id.is_in(ids.to_vec()));
+    }
+    if let Some(domain) = domain {
+        query = query.filter(Column::Domain.eq(domain));
+    }
+    if let Some(status) = status {
+        query = query.filter(Column::Status.eq(status));
+    }
+
+    let res = query.exec(db).await?;
+    Ok(res.rows_affected)
+}
+
 #[cfg(test)]
 mod test {
     use sea_orm::prelude::*;
@@ -478,11 +1056,15 @@ mod test {
 
     use shared::config::{Lens, LensRule, Limit, UserSettings};
 
+    use crate::bloom::BloomFilter;
     use crate::models::{crawl_queue, indexed_document};
     use crate::regex::{regex_for_robots, WildcardType};
     use crate::test::setup_test_db;
 
-    use super::{gen_priority_sql, gen_priority_values, EnqueueSettings};
+    use super::{
+        gen_priority_sql, gen_priority_values, retry_backoff, EnqueueSettings, SkipFilter,
+        SkipReason,
+    };
 
     #[tokio::test]
     async fn test_insert() {
@@ -595,25 +1177,384 @@ mod test {
     }
 
     #[tokio::test]
-    async fn test_dequeue_with_limit() {
-        let settings = UserSettings {
-            domain_crawl_limit: Limit::Finite(2),
-            ..Default::default()
-        };
+    async fn test_dequeue_batch() {
+        let settings = UserSettings::default();
         let db = setup_test_db().await;
-        let url: Vec<String> = vec!["https://oldschool.runescape.wiki/".into()];
-        let parsed = Url::parse(&url[0]).unwrap();
-        let prioritized = vec![];
-        let lens = Lens {
-            domains: vec!["oldschool.runescape.wiki".into()],
-            ..Default::default()
-        };
+        let urls: Vec<String> = vec![
+            "https://oldschool.runescape.wiki/".into(),
+            "https://oldschool.runescape.wiki/w/Worn_Equipment".into(),
+            "https://en.wikipedia.org/".into(),
+        ];
 
-        crawl_queue::enqueue_all(&db, &url, &[lens], &settings, &Default::default())
+        crawl_queue::enqueue_all(&db, &urls, &[], &settings, &Default::default())
             .await
             .unwrap();
-        let doc = indexed_document::ActiveModel {
-            domain: Set(parsed.host_str().unwrap().to_string()),
+
+        let batch = crawl_queue::dequeue_batch(&db, &settings, &[], &[], &Default::default(), 2)
+            .await
+            .unwrap();
+        assert_eq!(batch.len(), 2);
+
+        // Dequeued tasks should now be marked as processing
+        let batch = crawl_queue::dequeue_batch(&db, &settings, &[], &[], &Default::default(), 10)
+            .await
+            .unwrap();
+        assert_eq!(batch.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dequeue_batch_overlapping_lenses() {
+        let settings = UserSettings::default();
+        let db = setup_test_db().await;
+        let urls: Vec<String> = vec![
+            "https://oldschool.runescape.wiki/w/Worn_Equipment".into(),
+            "https://en.wikipedia.org/".into(),
+        ];
+
+        crawl_queue::enqueue_all(&db, &urls, &[], &settings, &Default::default())
+            .await
+            .unwrap();
+
+        // Two lenses covering the same domain & prefix
+        let p_domains = vec![
+            "oldschool.runescape.wiki".to_string(),
+            "*.runescape.wiki".to_string(),
+        ];
+        let p_prefixes = vec![
+            "https://oldschool.runescape.wiki/".to_string(),
+            "https://oldschool.runescape.wiki/w/".to_string(),
+        ];
+        let batch = crawl_queue::dequeue_batch(
+            &db,
+            &settings,
+            &p_domains,
+            &p_prefixes,
+            &Default::default(),
+            10,
+        )
+        .await
+        .unwrap();
+
+        let mut ids: Vec<i64> = batch.iter().map(|task| task.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(ids.len(), 2);
+        assert_eq!(batch[0].url, urls[0]);
+    }
+
+    #[tokio::test]
+    async fn test_dequeue_by_priority() {
+        let settings = UserSettings::default();
+        let db = setup_test_db().await;
+
+        let links = vec!["https://example.com/link".to_string()];
+        crawl_queue::enqueue_all(&db, &links, &[], &settings, &Default::default())
+            .await
+            .unwrap();
+
+        let added = vec!["https://example.com/added".to_string()];
+        let overrides = EnqueueSettings {
+            priority: crawl_queue::PRIORITY_USER,
+            ..Default::default()
+        };
+        crawl_queue::enqueue_all(&db, &added, &[], &settings, &overrides)
+            .await
+            .unwrap();
+
+        let batch = crawl_queue::dequeue_batch(&db, &settings, &[], &[], &Default::default(), 1)
+            .await
+            .unwrap();
+        assert_eq!(batch[0].url, added[0]);
+        assert_eq!(batch[0].priority, crawl_queue::PRIORITY_USER);
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_skip_reasons() {
+        let settings = UserSettings {
+            block_list: vec!["blocked.com".to_string()],
+            ..Default::default()
+        };
+        let db = setup_test_db().await;
+
+        let urls: Vec<String> = vec![
+            "https://example.com/".into(),
+            "https://example.com/#top".into(),
+            "https://blocked.com/".into(),
+            "not a url".into(),
+        ];
+        let skipped = crawl_queue::enqueue_all(&db, &urls, &[], &settings, &Default::default())
+            .await
+            .unwrap();
+        assert_eq!(skipped.len(), 3);
+        assert_eq!(skipped.get(&urls[1]), Some(&SkipReason::Duplicate));
+        assert_eq!(skipped.get(&urls[2]), Some(&SkipReason::Blocked));
+        assert_eq!(skipped.get(&urls[3]), Some(&SkipReason::Invalid));
+
+        // Already queued
+        let skipped =
+            crawl_queue::enqueue_all(&db, &urls[..1], &[], &settings, &Default::default())
+                .await
+                .unwrap();
+        assert_eq!(skipped.get(&urls[0]), Some(&SkipReason::Duplicate));
+        assert_eq!(crawl_queue::Entity::find().count(&db).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dequeue_skipped() {
+        let settings = UserSettings::default();
+        let db = setup_test_db().await;
+        let urls: Vec<String> = vec![
+            "https://en.wikipedia.org/".into(),
+            "https://docs.rs/".into(),
+            "https://oldschool.runescape.wiki/w/Worn_Equipment".into(),
+            "https://oldschool.runescape.wiki/w/Prayer".into(),
+        ];
+        crawl_queue::enqueue_all(&db, &urls, &[], &settings, &Default::default())
+            .await
+            .unwrap();
+
+        let skip = SkipFilter {
+            domains: vec!["*.wikipedia.org".into(), "docs.rs".into()],
+            prefixes: vec!["https://oldschool.runescape.wiki/w/Worn".into()],
+        };
+        let batch = crawl_queue::dequeue_batch(&db, &settings, &[], &[], &skip, 10)
+            .await
+            .unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].url, urls[3]);
+
+        // Still queued once they're no longer skipped
+        let batch = crawl_queue::dequeue_batch(&db, &settings, &[], &[], &Default::default(), 10)
+            .await
+            .unwrap();
+        assert_eq!(batch.len(), 3);
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        assert_eq!(retry_backoff(0).num_seconds(), 60);
+        assert_eq!(retry_backoff(3).num_seconds(), 480);
+        assert_eq!(retry_backoff(200).num_seconds(), 60 * 60 * 24);
+    }
+
+    #[tokio::test]
+    async fn test_mark_failed() {
+        let settings = UserSettings::default();
+        let db = setup_test_db().await;
+        let urls = vec!["https://oldschool.runescape.wiki/".to_string()];
+        crawl_queue::enqueue_all(&db, &urls, &[], &settings, &Default::default())
+            .await
+            .unwrap();
+
+        let task = crawl_queue::dequeue_batch(&db, &settings, &[], &[], &Default::default(), 1)
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert!(crawl_queue::mark_failed(&db, task.id, 1)
+            .await
+            .unwrap()
+            .is_none());
+
+        let task = crawl_queue::Entity::find_by_id(task.id)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(task.status, crawl_queue::CrawlStatus::Queued);
+        assert_eq!(task.num_retries, 1);
+        assert!(task.next_retry_at.unwrap() > chrono::Utc::now());
+        // Not retried until the backoff is up
+        let batch = crawl_queue::dequeue_batch(&db, &settings, &[], &[], &Default::default(), 1)
+            .await
+            .unwrap();
+        assert!(batch.is_empty());
+
+        // Out of retries
+        let failed = crawl_queue::mark_failed(&db, task.id, 1).await.unwrap();
+        assert_eq!(failed.unwrap().id, task.id);
+        let task = crawl_queue::Entity::find_by_id(task.id)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(task.status, crawl_queue::CrawlStatus::Failed);
+        assert_eq!(task.next_retry_at, None);
+    }
+
+    #[tokio::test]
+    async fn test_link_settings() {
+        let settings = UserSettings::default();
+        let db = setup_test_db().await;
+        let lens = Lens {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let urls = vec!["https://example.com/".to_string()];
+        crawl_queue::enqueue_all(
+            &db,
+            &urls,
+            &[],
+            &settings,
+            &EnqueueSettings::for_seeds_of(&lens),
+        )
+        .await
+        .unwrap();
+
+        let seed = crawl_queue::Entity::find().one(&db).await.unwrap().unwrap();
+        assert_eq!(seed.depth, 0);
+        assert_eq!(seed.max_depth, Some(1));
+
+        let links = seed.link_settings().unwrap();
+        let urls = vec!["https://example.com/docs".to_string()];
+        crawl_queue::enqueue_all(&db, &urls, &[], &settings, &links)
+            .await
+            .unwrap();
+
+        let link = crawl_queue::Entity::find()
+            .filter(crawl_queue::Column::Url.eq("https://example.com/docs"))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(link.depth, 1);
+        assert_eq!(link.max_depth, Some(1));
+        // Too deep to follow any further
+        assert!(link.link_settings().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_url_variants() {
+        let settings = UserSettings::default();
+        let db = setup_test_db().await;
+
+        let doc = indexed_document::ActiveModel {
+            domain: Set("example.com".into()),
+            url: Set("https://example.com/indexed".into()),
+            doc_id: Set("1".into()),
+            ..Default::default()
+        };
+        doc.save(&db).await.unwrap();
+
+        let urls: Vec<String> = vec![
+            "http://example.com/indexed/".into(),
+            "https://example.com/docs".into(),
+            "http://example.com/docs/".into(),
+        ];
+        crawl_queue::enqueue_all(&db, &urls, &[], &settings, &Default::default())
+            .await
+            .unwrap();
+        // Already queued in another form
+        crawl_queue::enqueue_all(
+            &db,
+            &["https://example.com/docs/".into()],
+            &[],
+            &settings,
+            &Default::default(),
+        )
+        .await
+        .unwrap();
+
+        let queued: Vec<String> = crawl_queue::Entity::find()
+            .all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|task| task.url)
+            .collect();
+        assert_eq!(queued, vec!["https://example.com/docs"]);
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_with_seen_urls() {
+        let settings = UserSettings::default();
+        let db = setup_test_db().await;
+
+        let mut filter = BloomFilter::default();
+        filter.insert("https://oldschool.runescape.wiki/");
+        let overrides = EnqueueSettings {
+            seen_urls: Some(filter.shared()),
+            ..Default::default()
+        };
+
+        let urls: Vec<String> = vec![
+            "https://oldschool.runescape.wiki/".into(),
+            "https://en.wikipedia.org/".into(),
+        ];
+        crawl_queue::enqueue_all(&db, &urls, &[], &settings, &overrides)
+            .await
+            .unwrap();
+
+        let queue = crawl_queue::Entity::find().all(&db).await.unwrap();
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].url, "https://en.wikipedia.org/");
+
+        // Newly enqueued URLs are added to the filter
+        let seen_urls = overrides.seen_urls.unwrap();
+        assert!(seen_urls
+            .read()
+            .unwrap()
+            .contains("https://en.wikipedia.org/"));
+
+        let filter = crawl_queue::load_seen_urls(&db).await.unwrap();
+        assert!(filter.contains("https://en.wikipedia.org/"));
+
+        // Removed URLs are forgotten once the filter is rebuilt
+        crawl_queue::Entity::delete_many().exec(&db).await.unwrap();
+        crawl_queue::rebuild_seen_urls(&db, &seen_urls)
+            .await
+            .unwrap();
+        assert!(!seen_urls
+            .read()
+            .unwrap()
+            .contains("https://en.wikipedia.org/"));
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_rebuilds_full_filter() {
+        let settings = UserSettings::default();
+        let db = setup_test_db().await;
+
+        let overrides = EnqueueSettings {
+            seen_urls: Some(BloomFilter::new(1, 0.01).shared()),
+            ..Default::default()
+        };
+        let urls: Vec<String> = vec![
+            "https://oldschool.runescape.wiki/".into(),
+            "https://en.wikipedia.org/".into(),
+        ];
+        crawl_queue::enqueue_all(&db, &urls, &[], &settings, &overrides)
+            .await
+            .unwrap();
+
+        let seen_urls = overrides.seen_urls.unwrap();
+        let seen_urls = seen_urls.read().unwrap();
+        assert!(!seen_urls.is_over_capacity());
+        assert!(seen_urls.capacity() > 1);
+        assert!(seen_urls.contains("https://en.wikipedia.org/"));
+    }
+
+    #[tokio::test]
+    async fn test_dequeue_with_limit() {
+        let settings = UserSettings {
+            domain_crawl_limit: Limit::Finite(2),
+            ..Default::default()
+        };
+        let db = setup_test_db().await;
+        let url: Vec<String> = vec!["https://oldschool.runescape.wiki/".into()];
+        let parsed = Url::parse(&url[0]).unwrap();
+        let prioritized = vec![];
+        let lens = Lens {
+            domains: vec!["oldschool.runescape.wiki".into()],
+            ..Default::default()
+        };
+
+        crawl_queue::enqueue_all(&db, &url, &[lens], &settings, &Default::default())
+            .await
+            .unwrap();
+        let doc = indexed_document::ActiveModel {
+            domain: Set(parsed.host_str().unwrap().to_string()),
             url: Set(url[0].clone()),
             doc_id: Set("docid".to_string()),
             ..Default::default()
@@ -682,4 +1623,255 @@ mod test {
         // but should now be denied
         assert!(block_list.is_match(invalid));
     }
+
+    #[tokio::test]
+    async fn test_requeue_urls() {
+        let db = setup_test_db().await;
+
+        let url = "https://oldschool.runescape.wiki/";
+        let crawl = crawl_queue::ActiveModel {
+            domain: Set("oldschool.runescape.wiki".to_string()),
+            url: Set(url.to_owned()),
+            status: Set(crawl_queue::CrawlStatus::Completed),
+            ..Default::default()
+        };
+        crawl.insert(&db).await.expect("Unable to insert");
+
+        let requeued = super::requeue_urls(&db, &[url.to_string()]).await.unwrap();
+        assert_eq!(requeued, 1);
+
+        let task = crawl_queue::Entity::find()
+            .filter(crawl_queue::Column::Url.eq(url))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(task.status, crawl_queue::CrawlStatus::Queued);
+    }
+
+    #[tokio::test]
+    async fn test_status_counts() {
+        let db = setup_test_db().await;
+
+        for (url, status) in [
+            ("https://example.com/", crawl_queue::CrawlStatus::Completed),
+            ("https://example.com/docs", crawl_queue::CrawlStatus::Failed),
+            ("https://example.com/blog", crawl_queue::CrawlStatus::Queued),
+            ("https://rust-lang.org/", crawl_queue::CrawlStatus::Queued),
+        ] {
+            let crawl = crawl_queue::ActiveModel {
+                domain: Set(Url::parse(url).unwrap().host_str().unwrap().to_string()),
+                url: Set(url.to_owned()),
+                status: Set(status),
+                ..Default::default()
+            };
+            crawl.insert(&db).await.expect("Unable to insert");
+        }
+
+        let counts = super::status_counts(&db, None).await.unwrap();
+        assert_eq!(counts.num_queued, 2);
+        assert_eq!(counts.total(), 4);
+
+        let counts = super::status_counts(&db, Some("example.com"))
+            .await
+            .unwrap();
+        assert_eq!(counts.num_queued, 1);
+        assert_eq!(counts.num_completed, 1);
+        assert_eq!(counts.num_failed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_remove_tasks() {
+        let db = setup_test_db().await;
+
+        let mut ids = Vec::new();
+        for (url, status) in [
+            ("https://example.com/", crawl_queue::CrawlStatus::Completed),
+            ("https://example.com/docs", crawl_queue::CrawlStatus::Failed),
+            ("https://example.com/blog", crawl_queue::CrawlStatus::Queued),
+            ("https://rust-lang.org/", crawl_queue::CrawlStatus::Failed),
+        ] {
+            let crawl = crawl_queue::ActiveModel {
+                domain: Set(Url::parse(url).unwrap().host_str().unwrap().to_string()),
+                url: Set(url.to_owned()),
+                status: Set(status),
+                ..Default::default()
+            };
+            ids.push(crawl.insert(&db).await.expect("Unable to insert").id);
+        }
+
+        // Nothing is removed w/o a filter
+        let removed = super::remove_tasks(&db, &[], None, None).await.unwrap();
+        assert_eq!(removed, 0);
+
+        let removed = super::remove_tasks(
+            &db,
+            &[],
+            Some("example.com"),
+            Some(crawl_queue::CrawlStatus::Failed),
+        )
+        .await
+        .unwrap();
+        assert_eq!(removed, 1);
+
+        let removed = super::remove_tasks(&db, &ids[..2], None, None)
+            .await
+            .unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(crawl_queue::Entity::find().count(&db).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_requeue_domain() {
+        let db = setup_test_db().await;
+
+        for (url, status) in [
+            ("https://example.com/", crawl_queue::CrawlStatus::Completed),
+            ("https://example.com/docs", crawl_queue::CrawlStatus::Failed),
+            (
+                "https://example.com/blog",
+                crawl_queue::CrawlStatus::Processing,
+            ),
+        ] {
+            let crawl = crawl_queue::ActiveModel {
+                domain: Set("example.com".to_string()),
+                url: Set(url.to_owned()),
+                status: Set(status),
+                num_retries: Set(3),
+                ..Default::default()
+            };
+            crawl.insert(&db).await.expect("Unable to insert");
+        }
+
+        // Tasks in progress are left alone
+        let requeued = super::requeue_domain(&db, "example.com").await.unwrap();
+        assert_eq!(requeued, 2);
+
+        let task = crawl_queue::Entity::find()
+            .filter(crawl_queue::Column::Url.eq("https://example.com/docs"))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(task.status, crawl_queue::CrawlStatus::Queued);
+        assert_eq!(task.num_retries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_requeue_stale() {
+        let db = setup_test_db().await;
+
+        let url = "https://oldschool.runescape.wiki/";
+        let crawl = crawl_queue::ActiveModel {
+            domain: Set("oldschool.runescape.wiki".to_string()),
+            url: Set(url.to_owned()),
+            status: Set(crawl_queue::CrawlStatus::Completed),
+            ..Default::default()
+        };
+        crawl.insert(&db).await.expect("Unable to insert");
+
+        // Crawled recently, nothing to do
+        let before = chrono::Utc::now() - chrono::Duration::days(1);
+        assert_eq!(super::requeue_stale(&db, before).await.unwrap(), 0);
+
+        let before = chrono::Utc::now() + chrono::Duration::days(1);
+        assert_eq!(super::requeue_stale(&db, before).await.unwrap(), 1);
+
+        let task = crawl_queue::Entity::find()
+            .filter(crawl_queue::Column::Url.eq(url))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(task.status, crawl_queue::CrawlStatus::Queued);
+    }
+
+    #[tokio::test]
+    async fn test_requeue_expired_leases() {
+        let db = setup_test_db().await;
+
+        for url in ["https://example.com/a", "https://example.com/b"] {
+            let crawl = crawl_queue::ActiveModel {
+                domain: Set("example.com".to_string()),
+                url: Set(url.to_owned()),
+                ..Default::default()
+            };
+            crawl.insert(&db).await.expect("Unable to insert");
+        }
+
+        let settings = UserSettings::default();
+        let batch = crawl_queue::dequeue_batch(&db, &settings, &[], &[], &Default::default(), 1)
+            .await
+            .unwrap();
+        let expires_at = chrono::Utc::now() + chrono::Duration::minutes(10);
+        super::set_lease(&db, &[batch[0].id], expires_at)
+            .await
+            .unwrap();
+
+        // Lease still valid
+        let now = chrono::Utc::now();
+        assert_eq!(super::requeue_expired_leases(&db, now).await.unwrap(), 0);
+
+        // Only the leased task goes back in the queue
+        let now = expires_at + chrono::Duration::seconds(1);
+        assert_eq!(super::requeue_expired_leases(&db, now).await.unwrap(), 1);
+        let task = crawl_queue::Entity::find_by_id(batch[0].id)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(task.status, crawl_queue::CrawlStatus::Queued);
+        assert_eq!(task.lease_expires_at, None);
+    }
+
+    #[tokio::test]
+    async fn test_requeue_stale_in_lens() {
+        let db = setup_test_db().await;
+
+        for url in [
+            "https://oldschool.runescape.wiki/",
+            "https://example.com/news",
+            "https://example.com/news/today",
+        ] {
+            let crawl = crawl_queue::ActiveModel {
+                domain: Set(Url::parse(url).unwrap().host_str().unwrap().to_string()),
+                url: Set(url.to_owned()),
+                status: Set(crawl_queue::CrawlStatus::Completed),
+                ..Default::default()
+            };
+            crawl.insert(&db).await.expect("Unable to insert");
+        }
+
+        let lens = Lens {
+            urls: vec!["https://example.com/news/".into()],
+            ..Default::default()
+        };
+        let before = chrono::Utc::now() + chrono::Duration::days(1);
+        assert_eq!(
+            super::requeue_stale_in_lens(&db, &lens, before)
+                .await
+                .unwrap(),
+            1
+        );
+
+        let queued = crawl_queue::Entity::find()
+            .filter(crawl_queue::Column::Status.eq(crawl_queue::CrawlStatus::Queued))
+            .all(&db)
+            .await
+            .unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].url, "https://example.com/news/today");
+
+        // Subdomains through a wildcard domain
+        let lens = Lens {
+            domains: vec!["*.runescape.wiki".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            super::requeue_stale_in_lens(&db, &lens, before)
+                .await
+                .unwrap(),
+            1
+        );
+    }
 }
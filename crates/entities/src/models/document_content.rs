@@ -0,0 +1,177 @@
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+
+/// Cleaned up content for an indexed page, kept around so the index can be
+/// rebuilt w/o having to recrawl everything.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "document_content")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    /// URL of the indexed page
+    #[sea_orm(unique)]
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    /// Extracted text, compressed.
+    pub content: Vec<u8>,
+    /// HTML stripped down for storage, compressed.
+    pub raw: Vec<u8>,
+    /// When this was first stored
+    pub created_at: DateTimeUtc,
+    /// When this was last updated
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        panic!("No RelationDef")
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {
+    fn new() -> Self {
+        Self {
+            created_at: Set(chrono::Utc::now()),
+            updated_at: Set(chrono::Utc::now()),
+            ..ActiveModelTrait::default()
+        }
+    }
+
+    // Triggered before insert / update
+    fn before_save(mut self, insert: bool) -> Result<Self, DbErr> {
+        if !insert {
+            self.updated_at = Set(chrono::Utc::now());
+        }
+
+        Ok(self)
+    }
+}
+
+impl Model {
+    /// Decompressed text content
+    pub fn content(&self) -> anyhow::Result<String> {
+        decompress(&self.content)
+    }
+
+    /// Decompressed HTML
+    pub fn raw(&self) -> anyhow::Result<String> {
+        decompress(&self.raw)
+    }
+}
+
+fn compress(text: &str) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    Ok(encoder.finish()?)
+}
+
+fn decompress(bytes: &[u8]) -> anyhow::Result<String> {
+    let mut text = String::new();
+    ZlibDecoder::new(bytes).read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Add or update the stored content for `url`.
+pub async fn upsert(
+    db: &DatabaseConnection,
+    url: &str,
+    title: &str,
+    description: &str,
+    content: &str,
+    raw: &str,
+) -> anyhow::Result<()> {
+    let existing = Entity::find().filter(Column::Url.eq(url)).one(db).await?;
+
+    let mut update: ActiveModel = match existing {
+        Some(model) => model.into(),
+        None => ActiveModel {
+            url: Set(url.to_string()),
+            ..Default::default()
+        },
+    };
+
+    update.title = Set(title.to_string());
+    update.description = Set(description.to_string());
+    update.content = Set(compress(content)?);
+    update.raw = Set(compress(raw)?);
+    update.save(db).await?;
+
+    Ok(())
+}
+
+/// Remove the stored content for these URLs.
+pub async fn remove_by_urls(db: &DatabaseConnection, urls: &[String]) -> anyhow::Result<u64> {
+    let mut removed = 0;
+    for chunk in urls.chunks(500) {
+        let res = Entity::delete_many()
+            .filter(Column::Url.is_in(chunk.to_vec()))
+            .exec(db)
+            .await?;
+        removed += res.rows_affected;
+
+        // Embeddings are computed from the stored content.
+        super::document_embedding::Entity::delete_many()
+            .filter(super::document_embedding::Column::Url.is_in(chunk.to_vec()))
+            .exec(db)
+            .await?;
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod test {
+    use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+
+    use crate::test::setup_test_db;
+
+    #[tokio::test]
+    async fn test_upsert() {
+        let db = setup_test_db().await;
+        let url = "https://example.com/article";
+
+        super::upsert(
+            &db,
+            url,
+            "Title",
+            "",
+            "first version",
+            "<p>first version</p>",
+        )
+        .await
+        .unwrap();
+        super::upsert(
+            &db,
+            url,
+            "Title",
+            "",
+            "second version",
+            "<p>second version</p>",
+        )
+        .await
+        .unwrap();
+
+        let stored = super::Entity::find()
+            .filter(super::Column::Url.eq(url))
+            .all(&db)
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].content().unwrap(), "second version");
+        assert_eq!(stored[0].raw().unwrap(), "<p>second version</p>");
+
+        let removed = super::remove_by_urls(&db, &[url.to_string()])
+            .await
+            .unwrap();
+        assert_eq!(removed, 1);
+    }
+}
@@ -0,0 +1,163 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::{DbBackend, Set, Statement};
+
+use super::document_content;
+
+/// Embedding of a document's stored content, used for semantic search.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "document_embedding")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    /// URL of the embedded page
+    #[sea_orm(unique)]
+    pub url: String,
+    /// Model used to compute the embedding.
+    pub model: String,
+    /// Little-endian f32s.
+    pub embedding: Vec<u8>,
+    /// When this was first computed
+    pub created_at: DateTimeUtc,
+    /// When this was last updated
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        panic!("No RelationDef")
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {
+    fn new() -> Self {
+        Self {
+            created_at: Set(chrono::Utc::now()),
+            updated_at: Set(chrono::Utc::now()),
+            ..ActiveModelTrait::default()
+        }
+    }
+
+    // Triggered before insert / update
+    fn before_save(mut self, insert: bool) -> Result<Self, DbErr> {
+        if !insert {
+            self.updated_at = Set(chrono::Utc::now());
+        }
+
+        Ok(self)
+    }
+}
+
+impl Model {
+    pub fn vector(&self) -> Vec<f32> {
+        from_bytes(&self.embedding)
+    }
+}
+
+fn to_bytes(vector: &[f32]) -> Vec<u8> {
+    vector
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+fn from_bytes(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+/// Add or update the embedding for `url`.
+pub async fn upsert(
+    db: &DatabaseConnection,
+    url: &str,
+    model: &str,
+    vector: &[f32],
+) -> anyhow::Result<(), DbErr> {
+    let existing = Entity::find().filter(Column::Url.eq(url)).one(db).await?;
+
+    let mut update: ActiveModel = match existing {
+        Some(existing) => existing.into(),
+        None => ActiveModel {
+            url: Set(url.to_string()),
+            ..Default::default()
+        },
+    };
+
+    update.model = Set(model.to_string());
+    update.embedding = Set(to_bytes(vector));
+    update.save(db).await?;
+
+    Ok(())
+}
+
+/// All the embeddings computed w/ `model`.
+pub async fn all_for_model(
+    db: &DatabaseConnection,
+    model: &str,
+) -> anyhow::Result<Vec<Model>, DbErr> {
+    Entity::find().filter(Column::Model.eq(model)).all(db).await
+}
+
+/// Stored content w/o an up to date embedding from `model`, either because
+/// there isn't one yet or the content changed since.
+pub async fn pending(
+    db: &DatabaseConnection,
+    model: &str,
+    limit: u64,
+) -> anyhow::Result<Vec<document_content::Model>, DbErr> {
+    let statement = Statement::from_sql_and_values(
+        DbBackend::Sqlite,
+        r#"SELECT document_content.*
+            FROM document_content
+            LEFT JOIN document_embedding
+                ON document_embedding.url = document_content.url
+                AND document_embedding.model = ?
+            WHERE document_embedding.id IS NULL
+                OR document_embedding.updated_at < document_content.updated_at
+            LIMIT ?"#,
+        vec![model.into(), limit.into()],
+    );
+
+    document_content::Entity::find()
+        .from_raw_sql(statement)
+        .all(db)
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::document_content;
+    use crate::test::setup_test_db;
+
+    #[tokio::test]
+    async fn test_pending() {
+        let db = setup_test_db().await;
+        for url in ["https://example.com/a", "https://example.com/b"] {
+            document_content::upsert(&db, url, "Title", "", "content", "")
+                .await
+                .unwrap();
+        }
+
+        let pending = super::pending(&db, "model", 10).await.unwrap();
+        assert_eq!(pending.len(), 2);
+
+        super::upsert(&db, "https://example.com/a", "model", &[0.5, -1.0])
+            .await
+            .unwrap();
+        let pending = super::pending(&db, "model", 10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].url, "https://example.com/b");
+
+        // Switching models means everything needs to be embedded again.
+        let pending = super::pending(&db, "other-model", 10).await.unwrap();
+        assert_eq!(pending.len(), 2);
+
+        let stored = super::all_for_model(&db, "model").await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].vector(), vec![0.5, -1.0]);
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::{OnConflict, SqliteQueryBuilder};
+use sea_orm::{ConnectionTrait, DbBackend, QueryTrait, Set, Statement};
+
+/// A document the user deleted, so syncing w/ other devices doesn't bring it
+/// back, see `libspyglass::sync`.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "document_tombstone")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    /// URL of the deleted document
+    #[sea_orm(unique)]
+    pub url: String,
+    /// When it was last deleted
+    pub deleted_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        panic!("No RelationDef")
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Record that the documents at these URLs were deleted just now.
+pub async fn record(db: &DatabaseConnection, urls: &[String]) -> anyhow::Result<()> {
+    let now = chrono::Utc::now();
+    for chunk in urls.chunks(500) {
+        let tombstones: Vec<ActiveModel> = chunk
+            .iter()
+            .map(|url| ActiveModel {
+                url: Set(url.to_string()),
+                deleted_at: Set(now),
+                ..Default::default()
+            })
+            .collect();
+
+        let (sql, values) = Entity::insert_many(tombstones)
+            .query()
+            .on_conflict(
+                OnConflict::column(Column::Url)
+                    .update_column(Column::DeletedAt)
+                    .to_owned(),
+            )
+            .build(SqliteQueryBuilder);
+
+        db.execute(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            &sql,
+            values.iter().map(|value| value.to_owned()).collect(),
+        ))
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// When each deleted document was deleted, keyed by URL.
+pub async fn all(db: &DatabaseConnection) -> anyhow::Result<HashMap<String, DateTimeUtc>> {
+    Ok(Entity::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|tombstone| (tombstone.url, tombstone.deleted_at))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::setup_test_db;
+
+    #[tokio::test]
+    async fn test_record() {
+        let db = setup_test_db().await;
+        let urls = vec!["https://example.com/a".to_string()];
+
+        super::record(&db, &urls).await.unwrap();
+        let first = super::all(&db).await.unwrap()[&urls[0]];
+
+        // Deleting again only moves the time forward
+        super::record(&db, &urls).await.unwrap();
+        let tombstones = super::all(&db).await.unwrap();
+        assert_eq!(tombstones.len(), 1);
+        assert!(tombstones[&urls[0]] >= first);
+    }
+}
@@ -0,0 +1,157 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::{ConnectionTrait, DbBackend, Set, Statement};
+
+/// Running totals of how crawling a domain has gone.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "domain_stats")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    #[sea_orm(unique)]
+    pub domain: String,
+    /// Pages fetched w/o an error, including unchanged (304) pages.
+    #[sea_orm(default_value = 0)]
+    pub num_fetched: i64,
+    /// 4xx responses
+    #[sea_orm(default_value = 0)]
+    pub num_client_errors: i64,
+    /// 5xx responses
+    #[sea_orm(default_value = 0)]
+    pub num_server_errors: i64,
+    /// Fetches that never got a response, e.g. timeouts & DNS failures.
+    #[sea_orm(default_value = 0)]
+    pub num_network_errors: i64,
+    /// Size of the fetched bodies, after decompression.
+    #[sea_orm(default_value = 0)]
+    pub bytes_downloaded: i64,
+    /// When we last tried to fetch something from this domain.
+    pub last_crawled_at: Option<DateTimeUtc>,
+    pub created_at: DateTimeUtc,
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        panic!("No RelationDef")
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {
+    fn new() -> Self {
+        Self {
+            created_at: Set(chrono::Utc::now()),
+            updated_at: Set(chrono::Utc::now()),
+            ..ActiveModelTrait::default()
+        }
+    }
+
+    // Triggered before insert / update
+    fn before_save(mut self, insert: bool) -> Result<Self, DbErr> {
+        if !insert {
+            self.updated_at = Set(chrono::Utc::now());
+        }
+
+        Ok(self)
+    }
+}
+
+/// Outcome of a single fetch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FetchOutcome {
+    /// Got a response w/ this status
+    Status(u16),
+    /// Never got a response
+    NetworkError,
+}
+
+/// Add a fetch to the stats for `domain`. Done in a single statement since
+/// the fetch workers update the same domain concurrently.
+pub async fn record(
+    db: &DatabaseConnection,
+    domain: &str,
+    outcome: FetchOutcome,
+    num_bytes: u64,
+) -> anyhow::Result<(), DbErr> {
+    let (fetched, client_errors, server_errors, network_errors) = match outcome {
+        FetchOutcome::Status(status) if (400..=499).contains(&status) => (0, 1, 0, 0),
+        FetchOutcome::Status(status) if status >= 500 => (0, 0, 1, 0),
+        FetchOutcome::Status(_) => (1, 0, 0, 0),
+        FetchOutcome::NetworkError => (0, 0, 0, 1),
+    };
+
+    let now = chrono::Utc::now();
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Sqlite,
+        r#"
+        INSERT INTO domain_stats (
+            domain, num_fetched, num_client_errors, num_server_errors,
+            num_network_errors, bytes_downloaded, last_crawled_at, created_at, updated_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (domain) DO UPDATE SET
+            num_fetched = num_fetched + excluded.num_fetched,
+            num_client_errors = num_client_errors + excluded.num_client_errors,
+            num_server_errors = num_server_errors + excluded.num_server_errors,
+            num_network_errors = num_network_errors + excluded.num_network_errors,
+            bytes_downloaded = bytes_downloaded + excluded.bytes_downloaded,
+            last_crawled_at = excluded.last_crawled_at,
+            updated_at = excluded.updated_at"#,
+        vec![
+            domain.into(),
+            fetched.into(),
+            client_errors.into(),
+            server_errors.into(),
+            network_errors.into(),
+            (num_bytes as i64).into(),
+            now.into(),
+            now.into(),
+            now.into(),
+        ],
+    ))
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use sea_orm::prelude::*;
+
+    use super::FetchOutcome;
+    use crate::models::domain_stats;
+    use crate::test::setup_test_db;
+
+    #[tokio::test]
+    async fn test_record() {
+        let db = setup_test_db().await;
+
+        let outcomes = [
+            (FetchOutcome::Status(200), 1024),
+            (FetchOutcome::Status(304), 0),
+            (FetchOutcome::Status(404), 128),
+            (FetchOutcome::Status(503), 0),
+            (FetchOutcome::NetworkError, 0),
+        ];
+        for (outcome, num_bytes) in outcomes {
+            domain_stats::record(&db, "example.com", outcome, num_bytes)
+                .await
+                .unwrap();
+        }
+
+        let stats = domain_stats::Entity::find()
+            .filter(domain_stats::Column::Domain.eq("example.com"))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stats.num_fetched, 2);
+        assert_eq!(stats.num_client_errors, 1);
+        assert_eq!(stats.num_server_errors, 1);
+        assert_eq!(stats.num_network_errors, 1);
+        assert_eq!(stats.bytes_downloaded, 1152);
+        assert!(stats.last_crawled_at.is_some());
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+
+use sea_orm::entity::prelude::*;
+use sea_orm::Set;
+
+/// Pages seen in a subscribed RSS/Atom feed, w/ the metadata from the feed.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "feed_entry")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    /// Feed the entry was found in
+    pub feed_url: String,
+    /// URL of the page the entry links to
+    #[sea_orm(unique)]
+    pub url: String,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// When the entry was published, according to the feed
+    pub published_at: Option<DateTimeUtc>,
+    /// When this was first seen
+    pub created_at: DateTimeUtc,
+    /// When this was last updated
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        panic!("No RelationDef")
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {
+    fn new() -> Self {
+        Self {
+            created_at: Set(chrono::Utc::now()),
+            updated_at: Set(chrono::Utc::now()),
+            ..ActiveModelTrait::default()
+        }
+    }
+
+    // Triggered before insert / update
+    fn before_save(mut self, insert: bool) -> Result<Self, DbErr> {
+        if !insert {
+            self.updated_at = Set(chrono::Utc::now());
+        }
+
+        Ok(self)
+    }
+}
+
+/// An entry parsed from a feed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeedItem {
+    pub url: String,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub published_at: Option<DateTimeUtc>,
+}
+
+/// Store the entries from `feed_url` we haven't seen before. Returns the
+/// URLs of the new entries.
+pub async fn add_new(
+    db: &DatabaseConnection,
+    feed_url: &str,
+    items: &[FeedItem],
+) -> anyhow::Result<Vec<String>, DbErr> {
+    let urls: Vec<String> = items.iter().map(|item| item.url.clone()).collect();
+    let mut seen: HashSet<String> = Entity::find()
+        .filter(Column::Url.is_in(urls))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|entry| entry.url)
+        .collect();
+
+    let mut new_urls = Vec::new();
+    for item in items {
+        // Also skips entries listed twice in the same feed.
+        if !seen.insert(item.url.clone()) {
+            continue;
+        }
+
+        let entry = ActiveModel {
+            feed_url: Set(feed_url.to_string()),
+            url: Set(item.url.clone()),
+            title: Set(item.title.clone()),
+            author: Set(item.author.clone()),
+            published_at: Set(item.published_at),
+            ..Default::default()
+        };
+        entry.insert(db).await?;
+        new_urls.push(item.url.clone());
+    }
+
+    Ok(new_urls)
+}
+
+pub async fn find_by_url(
+    db: &DatabaseConnection,
+    url: &str,
+) -> anyhow::Result<Option<Model>, DbErr> {
+    Entity::find().filter(Column::Url.eq(url)).one(db).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::FeedItem;
+    use crate::test::setup_test_db;
+
+    #[tokio::test]
+    async fn test_add_new() {
+        let db = setup_test_db().await;
+        let feed = "https://example.com/feed.xml";
+        let item = |url: &str| FeedItem {
+            url: url.to_string(),
+            author: Some("Jane".to_string()),
+            ..Default::default()
+        };
+
+        let new_urls = super::add_new(
+            &db,
+            feed,
+            &[item("https://example.com/a"), item("https://example.com/a")],
+        )
+        .await
+        .unwrap();
+        assert_eq!(new_urls, vec!["https://example.com/a"]);
+
+        let new_urls = super::add_new(
+            &db,
+            feed,
+            &[item("https://example.com/a"), item("https://example.com/b")],
+        )
+        .await
+        .unwrap();
+        assert_eq!(new_urls, vec!["https://example.com/b"]);
+
+        let entry = super::find_by_url(&db, "https://example.com/b")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.feed_url, feed);
+        assert_eq!(entry.author, Some("Jane".to_string()));
+    }
+}
@@ -1,5 +1,5 @@
 use sea_orm::entity::prelude::*;
-use sea_orm::Set;
+use sea_orm::{sea_query, Set};
 use serde::Serialize;
 use url::Url;
 
@@ -25,9 +25,18 @@ pub struct Model {
     pub hash: Option<String>,
     /// HTTP status when last fetching this page.
     pub status: u16,
-    /// Ignore this URL in the future.
+    /// The page asked not to be indexed, w/ a robots meta tag or
+    /// `X-Robots-Tag` header.
     #[sea_orm(default_value = false)]
     pub no_index: bool,
+    /// The page asked for its links not to be followed.
+    #[sea_orm(default_value = false)]
+    pub no_follow: bool,
+    /// ETag header from the last response, sent as `If-None-Match` on recrawls.
+    pub etag: Option<String>,
+    /// Last-Modified header from the last response, sent as
+    /// `If-Modified-Since` on recrawls.
+    pub last_modified: Option<String>,
     /// When this was first added to our fetch history
     pub created_at: DateTimeUtc,
     /// When this URL was last fetched.
@@ -74,12 +83,16 @@ pub async fn find_by_url(
         .await
 }
 
+/// Record a fetch of `domain` + `path`. A 304 (not modified) response keeps
+/// the hash & status of the last full fetch.
 pub async fn upsert(
     db: &DatabaseConnection,
     domain: &str,
     path: &str,
     hash: Option<String>,
     status: u16,
+    etag: Option<String>,
+    last_modified: Option<String>,
 ) -> anyhow::Result<Model, sea_orm::DbErr> {
     let history = Entity::find()
         .filter(Column::Domain.eq(domain))
@@ -90,9 +103,19 @@ pub async fn upsert(
     return match history {
         // Already exists, update
         Some(res) => {
+            let is_not_modified = status == 304;
+            // Servers don't always repeat the validators in a 304.
+            let etag = etag.or_else(|| res.etag.clone().filter(|_| is_not_modified));
+            let last_modified =
+                last_modified.or_else(|| res.last_modified.clone().filter(|_| is_not_modified));
+
             let mut model: ActiveModel = res.into();
-            model.hash = Set(hash.to_owned());
-            model.status = Set(status);
+            if !is_not_modified {
+                model.hash = Set(hash.to_owned());
+                model.status = Set(status);
+            }
+            model.etag = Set(etag);
+            model.last_modified = Set(last_modified);
             model.updated_at = Set(chrono::Utc::now());
             Ok(model.update(db).await?)
         }
@@ -103,6 +126,8 @@ pub async fn upsert(
                 path: Set(path.to_owned()),
                 hash: Set(hash.to_owned()),
                 status: Set(status),
+                etag: Set(etag),
+                last_modified: Set(last_modified),
                 ..Default::default()
             };
 
@@ -111,6 +136,51 @@ pub async fn upsert(
     };
 }
 
+/// Record the robots directives from the last full fetch of `domain` + `path`.
+pub async fn set_robots(
+    db: &DatabaseConnection,
+    domain: &str,
+    path: &str,
+    no_index: bool,
+    no_follow: bool,
+) -> anyhow::Result<(), sea_orm::DbErr> {
+    Entity::update_many()
+        .col_expr(Column::NoIndex, sea_query::Expr::value(no_index))
+        .col_expr(Column::NoFollow, sea_query::Expr::value(no_follow))
+        .filter(Column::Domain.eq(domain))
+        .filter(Column::Path.eq(path))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Forget fetching `url`, so it's fetched in full the next time it's crawled.
+pub async fn remove_by_url(db: &DatabaseConnection, url: &Url) -> anyhow::Result<u64, DbErr> {
+    let domain = match url.host_str() {
+        Some(domain) => domain.to_string(),
+        None => return Ok(0),
+    };
+
+    let res = Entity::delete_many()
+        .filter(Column::Domain.eq(domain))
+        .filter(Column::Path.eq(url.path()))
+        .exec(db)
+        .await?;
+
+    Ok(res.rows_affected)
+}
+
+/// Same as `remove_by_url` for every page fetched from `domain`.
+pub async fn remove_by_domain(db: &DatabaseConnection, domain: &str) -> anyhow::Result<u64, DbErr> {
+    let res = Entity::delete_many()
+        .filter(Column::Domain.eq(domain))
+        .exec(db)
+        .await?;
+
+    Ok(res.rows_affected)
+}
+
 #[cfg(test)]
 mod test {
     use sea_orm::prelude::*;
@@ -151,4 +221,89 @@ mod test {
         assert_eq!(res.path, path);
         assert_eq!(res.hash.unwrap(), hash);
     }
+
+    #[tokio::test]
+    async fn test_upsert_not_modified() {
+        let db = setup_test_db().await;
+        let domain = "example.com";
+
+        fetch_history::upsert(
+            &db,
+            domain,
+            "/",
+            Some("hash".to_string()),
+            200,
+            Some("\"abc\"".to_string()),
+            Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+        )
+        .await
+        .unwrap();
+
+        let res = fetch_history::upsert(&db, domain, "/", None, 304, None, None)
+            .await
+            .unwrap();
+        assert_eq!(res.status, 200);
+        assert_eq!(res.hash, Some("hash".to_string()));
+        assert_eq!(res.etag, Some("\"abc\"".to_string()));
+        assert_eq!(
+            res.last_modified,
+            Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string())
+        );
+
+        // Validators are dropped if the page no longer sends them
+        let res = fetch_history::upsert(&db, domain, "/", Some("new".to_string()), 200, None, None)
+            .await
+            .unwrap();
+        assert_eq!(res.hash, Some("new".to_string()));
+        assert_eq!(res.etag, None);
+    }
+
+    #[tokio::test]
+    async fn test_set_robots() {
+        let db = setup_test_db().await;
+        let domain = "example.com";
+        fetch_history::upsert(&db, domain, "/private", None, 200, None, None)
+            .await
+            .unwrap();
+        fetch_history::set_robots(&db, domain, "/private", true, false)
+            .await
+            .unwrap();
+
+        let url = url::Url::parse("https://example.com/private").unwrap();
+        let res = fetch_history::find_by_url(&db, &url)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(res.no_index);
+        assert!(!res.no_follow);
+    }
+
+    #[tokio::test]
+    async fn test_remove() {
+        let db = setup_test_db().await;
+        for (domain, path) in [
+            ("example.com", "/"),
+            ("example.com", "/docs"),
+            ("rust-lang.org", "/"),
+        ] {
+            fetch_history::upsert(&db, domain, path, None, 200, None, None)
+                .await
+                .unwrap();
+        }
+
+        let url = url::Url::parse("https://example.com/docs").unwrap();
+        assert_eq!(fetch_history::remove_by_url(&db, &url).await.unwrap(), 1);
+        assert!(fetch_history::find_by_url(&db, &url)
+            .await
+            .unwrap()
+            .is_none());
+
+        assert_eq!(
+            fetch_history::remove_by_domain(&db, "example.com")
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(fetch_history::Entity::find().count(&db).await.unwrap(), 1);
+    }
 }
@@ -1,5 +1,8 @@
 use sea_orm::entity::prelude::*;
-use sea_orm::{FromQueryResult, QuerySelect, Set};
+use sea_orm::sea_query::Expr;
+use sea_orm::{FromQueryResult, QueryOrder, QuerySelect, Set};
+use shared::response::PageMetadata;
+use url::Url;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
 #[sea_orm(table_name = "indexed_document")]
@@ -16,6 +19,23 @@ pub struct Model {
     pub created_at: DateTimeUtc,
     /// When this was last updated
     pub updated_at: DateTimeUtc,
+    /// Set when the page was dead & indexed from an archived snapshot instead.
+    pub archived_at: Option<DateTimeUtc>,
+    /// Hash of the extracted content, used to detect the same page reachable
+    /// from multiple URLs.
+    pub content_hash: Option<String>,
+    /// Language the page is written in, e.g. `en`.
+    pub lang: Option<String>,
+    /// Author, for pages found through a feed.
+    pub author: Option<String>,
+    /// When the page was published, from a feed or the page's own metadata.
+    pub published_at: Option<DateTimeUtc>,
+    /// OpenGraph metadata from the page, see `set_metadata`.
+    pub og_title: Option<String>,
+    pub og_description: Option<String>,
+    pub og_image: Option<String>,
+    /// Comma separated schema.org types, e.g. `Article,Person`.
+    pub schema_types: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
@@ -46,6 +66,23 @@ impl ActiveModelBehavior for ActiveModel {
     }
 }
 
+impl Model {
+    /// Metadata to show alongside the document in search results.
+    pub fn page_metadata(&self) -> PageMetadata {
+        PageMetadata {
+            og_title: self.og_title.clone(),
+            og_description: self.og_description.clone(),
+            og_image: self.og_image.clone(),
+            published_at: self.published_at.map(|at| at.to_rfc3339()),
+            schema_types: self
+                .schema_types
+                .as_deref()
+                .map(|types| types.split(',').map(String::from).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
 #[derive(Debug, FromQueryResult)]
 pub struct CountByDomain {
     pub count: i64,
@@ -66,6 +103,169 @@ pub async fn indexed_stats(
     Ok(res)
 }
 
+/// Domains w/ the most indexed documents, largest first.
+pub async fn top_domains(
+    db: &DatabaseConnection,
+    limit: u64,
+) -> anyhow::Result<Vec<CountByDomain>, sea_orm::DbErr> {
+    Entity::find()
+        .select_only()
+        .column_as(Column::Id.count(), "count")
+        .column(Column::Domain)
+        .group_by(Column::Domain)
+        .order_by_desc(Expr::cust("count"))
+        .limit(limit)
+        .into_model::<CountByDomain>()
+        .all(db)
+        .await
+}
+
+/// Forms of `url` treated as the same page, including `url` itself: the
+/// http/https versions w/ & w/o a trailing slash on the path.
+pub fn url_variants(url: &str) -> Vec<String> {
+    let parsed = match Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => parsed,
+        _ => return vec![url.to_string()],
+    };
+
+    let mut paths = vec![parsed.path().to_string()];
+    if parsed.path() != "/" {
+        match parsed.path().strip_suffix('/') {
+            Some(path) => paths.push(path.to_string()),
+            None => paths.push(format!("{}/", parsed.path())),
+        }
+    }
+
+    let mut variants = vec![url.to_string()];
+    for scheme in ["https", "http"] {
+        for path in paths.iter() {
+            let mut variant = parsed.clone();
+            if variant.set_scheme(scheme).is_ok() {
+                variant.set_path(path);
+                let variant = variant.to_string();
+                if !variants.contains(&variant) {
+                    variants.push(variant);
+                }
+            }
+        }
+    }
+
+    variants
+}
+
+/// Document indexed under `url` or one of its `url_variants`.
+pub async fn find_by_url_variants(
+    db: &DatabaseConnection,
+    url: &str,
+) -> anyhow::Result<Option<Model>, sea_orm::DbErr> {
+    let variants = url_variants(url);
+    let mut matches = Entity::find()
+        .filter(Column::Url.is_in(variants.clone()))
+        .all(db)
+        .await?;
+
+    // Prefer an exact match
+    matches.sort_by_key(|doc| variants.iter().position(|variant| *variant == doc.url));
+    Ok(matches.into_iter().next())
+}
+
+/// Find a document w/ the same content as `url` that was indexed under a
+/// different URL.
+pub async fn find_duplicate(
+    db: &DatabaseConnection,
+    content_hash: &str,
+    url: &str,
+) -> anyhow::Result<Option<Model>, sea_orm::DbErr> {
+    Entity::find()
+        .filter(Column::ContentHash.eq(content_hash))
+        .filter(Column::Url.ne(url))
+        .one(db)
+        .await
+}
+
+/// Flag whether the document for `url` was indexed from an archived snapshot.
+pub async fn set_archived(
+    db: &DatabaseConnection,
+    url: &str,
+    is_archived: bool,
+) -> anyhow::Result<(), sea_orm::DbErr> {
+    let archived_at = if is_archived {
+        Some(chrono::Utc::now())
+    } else {
+        None
+    };
+
+    Entity::update_many()
+        .col_expr(
+            Column::ArchivedAt,
+            sea_orm::sea_query::Expr::value(archived_at),
+        )
+        .filter(Column::Url.eq(url))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Set the author listed in the page's own metadata. Authors from a feed entry
+/// take precedence, so these are only filled in if not already known.
+pub async fn set_author(
+    db: &DatabaseConnection,
+    url: &str,
+    author: &str,
+) -> anyhow::Result<(), sea_orm::DbErr> {
+    Entity::update_many()
+        .col_expr(Column::Author, sea_orm::sea_query::Expr::value(author))
+        .filter(Column::Url.eq(url))
+        .filter(Column::Author.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Save the OpenGraph & schema.org metadata found on the page. Like authors,
+/// publish dates from a feed entry take precedence.
+pub async fn set_metadata(
+    db: &DatabaseConnection,
+    url: &str,
+    metadata: &PageMetadata,
+) -> anyhow::Result<(), sea_orm::DbErr> {
+    let schema_types = if metadata.schema_types.is_empty() {
+        None
+    } else {
+        Some(metadata.schema_types.join(","))
+    };
+
+    Entity::update_many()
+        .col_expr(Column::OgTitle, Expr::value(metadata.og_title.clone()))
+        .col_expr(
+            Column::OgDescription,
+            Expr::value(metadata.og_description.clone()),
+        )
+        .col_expr(Column::OgImage, Expr::value(metadata.og_image.clone()))
+        .col_expr(Column::SchemaTypes, Expr::value(schema_types))
+        .filter(Column::Url.eq(url))
+        .exec(db)
+        .await?;
+
+    let published_at = metadata
+        .published_at
+        .as_deref()
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok())
+        .map(|at| at.with_timezone(&chrono::Utc));
+    if let Some(published_at) = published_at {
+        Entity::update_many()
+            .col_expr(Column::PublishedAt, Expr::value(published_at))
+            .filter(Column::Url.eq(url))
+            .filter(Column::PublishedAt.is_null())
+            .exec(db)
+            .await?;
+    }
+
+    Ok(())
+}
+
 /// Remove documents from the indexed_document table that match `rule`. Rule is expected
 /// to be a SQL like statement.
 pub async fn remove_by_rule(db: &DatabaseConnection, rule: &str) -> anyhow::Result<Vec<String>> {
@@ -84,6 +284,16 @@ pub async fn remove_by_rule(db: &DatabaseConnection, rule: &str) -> anyhow::Resu
         .exec(db)
         .await?;
 
+    let _ = super::document_content::Entity::delete_many()
+        .filter(super::document_content::Column::Url.like(rule))
+        .exec(db)
+        .await?;
+
+    let _ = super::document_embedding::Entity::delete_many()
+        .filter(super::document_embedding::Column::Url.like(rule))
+        .exec(db)
+        .await?;
+
     if !removed.is_empty() {
         log::info!("removed {} docs due to '{}'", removed.len(), rule);
     }
@@ -93,7 +303,75 @@ pub async fn remove_by_rule(db: &DatabaseConnection, rule: &str) -> anyhow::Resu
 #[cfg(test)]
 mod test {
     use crate::test::setup_test_db;
-    use sea_orm::{ActiveModelTrait, Set};
+    use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, Set};
+    use shared::response::PageMetadata;
+
+    #[test]
+    fn test_url_variants() {
+        assert_eq!(
+            super::url_variants("https://example.com/docs/"),
+            vec![
+                "https://example.com/docs/",
+                "https://example.com/docs",
+                "http://example.com/docs/",
+                "http://example.com/docs",
+            ]
+        );
+        assert_eq!(
+            super::url_variants("http://example.com/?q=1"),
+            vec!["http://example.com/?q=1", "https://example.com/?q=1"]
+        );
+        assert_eq!(
+            super::url_variants("file:///tmp/notes.txt"),
+            vec!["file:///tmp/notes.txt"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_by_url_variants() {
+        let db = setup_test_db().await;
+        let doc = super::ActiveModel {
+            domain: Set("example.com".into()),
+            url: Set("http://example.com/docs/".into()),
+            doc_id: Set("1".into()),
+            ..Default::default()
+        };
+        doc.save(&db).await.unwrap();
+
+        let found = super::find_by_url_variants(&db, "https://example.com/docs")
+            .await
+            .unwrap();
+        assert_eq!(found.unwrap().doc_id, "1");
+
+        let found = super::find_by_url_variants(&db, "https://example.com/doc")
+            .await
+            .unwrap();
+        assert!(found.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_top_domains() {
+        let db = setup_test_db().await;
+        let urls = [
+            ("example.com", "https://example.com/a"),
+            ("example.com", "https://example.com/b"),
+            ("rust-lang.org", "https://rust-lang.org/"),
+        ];
+        for (idx, (domain, url)) in urls.iter().enumerate() {
+            let doc = super::ActiveModel {
+                domain: Set(domain.to_string()),
+                url: Set(url.to_string()),
+                doc_id: Set(idx.to_string()),
+                ..Default::default()
+            };
+            doc.save(&db).await.unwrap();
+        }
+
+        let top = super::top_domains(&db, 1).await.unwrap();
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].domain, "example.com");
+        assert_eq!(top[0].count, 2);
+    }
 
     #[tokio::test]
     async fn test_remove_by_rule() {
@@ -119,4 +397,60 @@ mod test {
             .unwrap();
         assert_eq!(removed.len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_set_metadata() {
+        let db = setup_test_db().await;
+        let url = "https://example.com/article";
+        let doc = super::ActiveModel {
+            domain: Set("example.com".into()),
+            url: Set(url.into()),
+            doc_id: Set("1".into()),
+            ..Default::default()
+        };
+        doc.save(&db).await.unwrap();
+
+        let metadata = PageMetadata {
+            og_title: Some("An article".into()),
+            og_image: Some("https://example.com/cover.png".into()),
+            published_at: Some("2022-10-01T12:00:00+00:00".into()),
+            schema_types: vec!["Article".into(), "Person".into()],
+            ..Default::default()
+        };
+        super::set_metadata(&db, url, &metadata).await.unwrap();
+
+        let doc = super::Entity::find()
+            .filter(super::Column::Url.eq(url))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(doc.schema_types, Some("Article,Person".to_string()));
+        assert_eq!(doc.page_metadata(), metadata);
+    }
+
+    #[tokio::test]
+    async fn test_find_duplicate() {
+        let db = setup_test_db().await;
+
+        let doc = super::ActiveModel {
+            domain: Set("example.com".into()),
+            url: Set("https://example.com/article".into()),
+            doc_id: Set("1".into()),
+            content_hash: Set(Some("abc123".into())),
+            ..Default::default()
+        };
+        doc.save(&db).await.unwrap();
+
+        let dupe = super::find_duplicate(&db, "abc123", "https://example.com/article?ref=feed")
+            .await
+            .unwrap();
+        assert_eq!(dupe.map(|doc| doc.doc_id), Some("1".to_string()));
+
+        // Reindexing the same URL isn't a duplicate.
+        let dupe = super::find_duplicate(&db, "abc123", "https://example.com/article")
+            .await
+            .unwrap();
+        assert!(dupe.is_none());
+    }
 }
@@ -86,13 +86,17 @@ pub async fn add_or_enable(
         .one(db)
         .await?;
 
-    // If it already exists & is not a plugin, simply enable it.
+    // If it already exists & is not a plugin, simply enable it & pick up any
+    // changes from an updated lens file.
     if let Some(existing) = exists {
         // TODO: This is super hacky, think about a long term way of storing
         // enabled/disabled lenses/plugins etc.
         if lens_type == LensType::Simple {
             let mut updated: ActiveModel = existing.clone().into();
             updated.is_enabled = Set(true);
+            updated.author = Set(author.to_owned());
+            updated.description = Set(description.map(String::from));
+            updated.version = Set(version.to_owned());
             updated.update(db).await?;
         }
 
@@ -115,3 +119,51 @@ pub async fn add_or_enable(
 
     Ok(true)
 }
+
+/// Remove a lens, e.g. after it's uninstalled. True if there was one to remove.
+pub async fn remove(db: &DatabaseConnection, name: &str) -> anyhow::Result<bool> {
+    let res = Entity::delete_many()
+        .filter(Column::Name.eq(name))
+        .exec(db)
+        .await?;
+
+    Ok(res.rows_affected > 0)
+}
+
+#[cfg(test)]
+mod test {
+    use sea_orm::EntityTrait;
+
+    use super::{add_or_enable, remove, Entity, LensType};
+    use crate::test::setup_test_db;
+
+    #[tokio::test]
+    async fn test_add_update_remove() {
+        let db = setup_test_db().await;
+
+        assert!(
+            add_or_enable(&db, "rust", "a5huynh", None, "1", LensType::Simple)
+                .await
+                .unwrap()
+        );
+        let description = "Rust docs".to_string();
+        assert!(!add_or_enable(
+            &db,
+            "rust",
+            "a5huynh",
+            Some(&description),
+            "2",
+            LensType::Simple
+        )
+        .await
+        .unwrap());
+
+        let lens = Entity::find().one(&db).await.unwrap().unwrap();
+        assert_eq!(lens.version, "2");
+        assert_eq!(lens.description, Some(description));
+
+        assert!(remove(&db, "rust").await.unwrap());
+        assert!(!remove(&db, "rust").await.unwrap());
+        assert_eq!(Entity::find().all(&db).await.unwrap().len(), 0);
+    }
+}
@@ -1,7 +1,16 @@
+use std::collections::{HashMap, HashSet};
+
 use sea_orm::entity::prelude::*;
-use sea_orm::Set;
+use sea_orm::sea_query::Expr;
+use sea_orm::{FromQueryResult, QuerySelect, Set};
 use url::Url;
 
+use crate::normalize::normalize_url;
+
+/// Max number of links inserted per statement.
+const BATCH_SIZE: usize = 1000;
+
+/// A link from one page to another, found while crawling `src_url`.
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
 #[sea_orm(table_name = "link")]
 pub struct Model {
@@ -30,23 +39,125 @@ impl ActiveModelBehavior for ActiveModel {
     }
 }
 
-pub async fn save_link(
+/// Replace the links found on `src` w/ `dsts`, e.g. after recrawling it.
+/// Invalid URLs & links back to `src` itself are skipped.
+pub async fn replace_outlinks(
     db: &DatabaseConnection,
-    src: &String,
-    dst: &String,
-) -> anyhow::Result<(), sea_orm::DbErr> {
-    let src_url = Url::parse(src).unwrap();
-    let dst_url = Url::parse(dst).unwrap();
-
-    let new_link = ActiveModel {
-        src_domain: Set(src_url.host_str().unwrap().to_owned()),
-        src_url: Set(src.to_owned()),
-        dst_domain: Set(dst_url.host_str().unwrap().to_owned()),
-        dst_url: Set(dst.to_owned()),
-        ..Default::default()
+    src: &str,
+    dsts: &[String],
+) -> anyhow::Result<(), DbErr> {
+    let src_domain = match Url::parse(src)
+        .ok()
+        .and_then(|url| url.host_str().map(String::from))
+    {
+        Some(domain) => domain,
+        None => return Ok(()),
     };
 
-    new_link.insert(db).await?;
+    Entity::delete_many()
+        .filter(Column::SrcUrl.eq(src))
+        .exec(db)
+        .await?;
+
+    let mut seen = HashSet::new();
+    let links: Vec<ActiveModel> = dsts
+        .iter()
+        .filter_map(|dst| normalize_url(dst))
+        .filter(|dst| dst != src && seen.insert(dst.clone()))
+        .filter_map(|dst| {
+            let dst_domain = Url::parse(&dst).ok()?.host_str()?.to_string();
+            Some(ActiveModel {
+                src_domain: Set(src_domain.clone()),
+                src_url: Set(src.to_string()),
+                dst_domain: Set(dst_domain),
+                dst_url: Set(dst),
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    for chunk in links.chunks(BATCH_SIZE) {
+        Entity::insert_many(chunk.to_vec()).exec(db).await?;
+    }
 
     Ok(())
 }
+
+#[derive(Debug, FromQueryResult)]
+struct BacklinkCount {
+    dst_url: String,
+    count: i64,
+}
+
+/// Number of pages on other domains linking to each of `urls`. URLs w/o any
+/// backlinks are left out.
+pub async fn backlink_counts(
+    db: &DatabaseConnection,
+    urls: &[String],
+) -> anyhow::Result<HashMap<String, u64>, DbErr> {
+    let counts = Entity::find()
+        .select_only()
+        .column(Column::DstUrl)
+        .column_as(Column::SrcUrl.count(), "count")
+        .filter(Column::DstUrl.is_in(urls.to_vec()))
+        .filter(Expr::cust("src_domain != dst_domain"))
+        .group_by(Column::DstUrl)
+        .into_model::<BacklinkCount>()
+        .all(db)
+        .await?;
+
+    Ok(counts
+        .into_iter()
+        .map(|count| (count.dst_url, count.count as u64))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use sea_orm::prelude::*;
+
+    use crate::models::link;
+    use crate::test::setup_test_db;
+
+    #[tokio::test]
+    async fn test_replace_outlinks() {
+        let db = setup_test_db().await;
+        let src = "https://example.com/";
+        let dsts = vec![
+            "https://example.com/docs".to_string(),
+            "https://example.com/docs#install".to_string(),
+            "https://example.com/".to_string(),
+            "https://rust-lang.org/".to_string(),
+        ];
+        link::replace_outlinks(&db, src, &dsts).await.unwrap();
+
+        let links = link::Entity::find().all(&db).await.unwrap();
+        assert_eq!(links.len(), 2);
+
+        // Recrawls replace the old links
+        let dsts = vec!["https://rust-lang.org/".to_string()];
+        link::replace_outlinks(&db, src, &dsts).await.unwrap();
+        let links = link::Entity::find().all(&db).await.unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].dst_domain, "rust-lang.org");
+    }
+
+    #[tokio::test]
+    async fn test_backlink_counts() {
+        let db = setup_test_db().await;
+        let target = "https://rust-lang.org/".to_string();
+        for src in [
+            "https://example.com/",
+            "https://example.com/blog",
+            "https://rust-lang.org/learn",
+        ] {
+            link::replace_outlinks(&db, src, &[target.clone()])
+                .await
+                .unwrap();
+        }
+
+        let counts = link::backlink_counts(&db, &[target.clone()]).await.unwrap();
+        // Links from the same domain don't count
+        assert_eq!(counts.get(&target), Some(&2));
+    }
+}
@@ -1,12 +1,23 @@
-use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+use sea_orm::{
+    ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbBackend, FromQueryResult,
+    Statement,
+};
 
 pub mod bootstrap_queue;
+pub mod crawl_log;
 pub mod crawl_queue;
+pub mod document_content;
+pub mod document_embedding;
+pub mod document_tombstone;
+pub mod domain_stats;
+pub mod feed_entry;
 pub mod fetch_history;
 pub mod indexed_document;
 pub mod lens;
 pub mod link;
 pub mod resource_rule;
+pub mod scheduled_task;
+pub mod search_history;
 
 use shared::config::Config;
 
@@ -17,10 +28,7 @@ pub async fn create_connection(
     let db_uri: String = if is_test {
         "sqlite::memory:".to_string()
     } else {
-        format!(
-            "sqlite://{}?mode=rwc",
-            config.data_dir().join("db.sqlite").to_str().unwrap()
-        )
+        format!("sqlite://{}?mode=rwc", config.db_file().to_str().unwrap())
     };
 
     // See https://www.sea-ql.org/SeaORM/docs/install-and-config/connection
@@ -28,7 +36,45 @@ pub async fn create_connection(
     let mut opt = ConnectOptions::new(db_uri);
     opt.max_connections(1).sqlx_logging(false);
 
-    Ok(Database::connect(opt).await?)
+    let db = Database::connect(opt).await?;
+    if !is_test {
+        // Write-ahead logging keeps the database consistent if we're killed
+        // mid-write, e.g. on power loss.
+        for pragma in ["PRAGMA journal_mode=WAL", "PRAGMA synchronous=NORMAL"] {
+            db.execute(Statement::from_string(DbBackend::Sqlite, pragma.into()))
+                .await?;
+        }
+    }
+
+    Ok(db)
+}
+
+/// Routine database upkeep, let SQLite refresh its query planner stats &
+/// fold the write-ahead log back into the database.
+pub async fn run_maintenance(db: &DatabaseConnection) -> anyhow::Result<()> {
+    for pragma in ["PRAGMA optimize", "PRAGMA wal_checkpoint(TRUNCATE)"] {
+        db.execute(Statement::from_string(DbBackend::Sqlite, pragma.into()))
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[derive(FromQueryResult)]
+struct DbSize {
+    size: i64,
+}
+
+/// Size of the database file in bytes, not counting the write-ahead log.
+pub async fn db_size(db: &DatabaseConnection) -> anyhow::Result<u64> {
+    let res = DbSize::find_by_statement(Statement::from_string(
+        DbBackend::Sqlite,
+        "SELECT page_count * page_size AS size FROM pragma_page_count(), pragma_page_size()".into(),
+    ))
+    .one(db)
+    .await?;
+
+    Ok(res.map(|row| row.size as u64).unwrap_or_default())
 }
 
 #[cfg(test)]
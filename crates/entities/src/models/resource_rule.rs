@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use sea_orm::entity::prelude::*;
 use sea_orm::Set;
 
@@ -10,6 +12,8 @@ pub struct Model {
     pub rule: String,
     pub no_index: bool,
     pub allow_crawl: bool,
+    /// Seconds to wait between requests to the domain, from robots.txt
+    pub crawl_delay: Option<i64>,
     pub created_at: DateTimeUtc,
     pub updated_at: DateTimeUtc,
 }
@@ -42,6 +46,24 @@ impl ActiveModelBehavior for ActiveModel {
     }
 }
 
+/// Crawl delays set by the robots.txt of each of `domains`, domains w/o one
+/// are left out.
+pub async fn crawl_delays(
+    db: &DatabaseConnection,
+    domains: &[String],
+) -> anyhow::Result<HashMap<String, i64>, DbErr> {
+    let rules = Entity::find()
+        .filter(Column::Domain.is_in(domains.to_vec()))
+        .filter(Column::CrawlDelay.is_not_null())
+        .all(db)
+        .await?;
+
+    Ok(rules
+        .into_iter()
+        .filter_map(|rule| rule.crawl_delay.map(|delay| (rule.domain, delay)))
+        .collect())
+}
+
 #[cfg(test)]
 mod test {
     use sea_orm::prelude::*;
@@ -76,4 +98,37 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_crawl_delays() -> anyhow::Result<(), sea_orm::DbErr> {
+        let db = setup_test_db().await;
+
+        let domain = "oldschool.runescape.wiki";
+        let no_delay = resource_rule::ActiveModel {
+            domain: Set(domain.to_owned()),
+            rule: Set("/".to_owned()),
+            no_index: Set(false),
+            allow_crawl: Set(true),
+            ..Default::default()
+        };
+        no_delay.insert(&db).await.expect("Unable to insert");
+
+        let delayed = resource_rule::ActiveModel {
+            domain: Set("example.com".to_owned()),
+            rule: Set("/".to_owned()),
+            no_index: Set(false),
+            allow_crawl: Set(true),
+            crawl_delay: Set(Some(5)),
+            ..Default::default()
+        };
+        delayed.insert(&db).await.expect("Unable to insert");
+
+        let delays =
+            resource_rule::crawl_delays(&db, &[domain.to_string(), "example.com".to_string()])
+                .await?;
+        assert_eq!(delays.len(), 1);
+        assert_eq!(delays.get("example.com"), Some(&5));
+
+        Ok(())
+    }
 }
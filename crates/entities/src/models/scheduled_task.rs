@@ -0,0 +1,159 @@
+use sea_orm::{entity::prelude::*, Set};
+use serde::Serialize;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "scheduled_task")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    /// Unique name of the task, e.g. "recrawl"
+    #[sea_orm(unique)]
+    pub name: String,
+    /// Cron expression for when this task runs.
+    pub schedule: String,
+    /// When this task will run next.
+    pub next_run_at: DateTimeUtc,
+    /// When this task last ran, if ever.
+    pub last_run_at: Option<DateTimeUtc>,
+    /// Error from the last run, if it failed.
+    pub last_error: Option<String>,
+    /// When this was first added
+    pub created_at: DateTimeUtc,
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        panic!("No RelationDef")
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {
+    fn new() -> Self {
+        Self {
+            created_at: Set(chrono::Utc::now()),
+            updated_at: Set(chrono::Utc::now()),
+            ..ActiveModelTrait::default()
+        }
+    }
+
+    // Triggered before insert / update
+    fn before_save(mut self, insert: bool) -> Result<Self, DbErr> {
+        if !insert {
+            self.updated_at = Set(chrono::Utc::now());
+        }
+
+        Ok(self)
+    }
+}
+
+/// Register a task, keeping its persisted next run time unless the schedule
+/// has changed. `next_run_at` is only used for new/changed schedules.
+pub async fn register(
+    db: &DatabaseConnection,
+    name: &str,
+    schedule: &str,
+    next_run_at: DateTimeUtc,
+) -> anyhow::Result<Model, sea_orm::DbErr> {
+    let existing = Entity::find().filter(Column::Name.eq(name)).one(db).await?;
+
+    match existing {
+        Some(task) if task.schedule == schedule => Ok(task),
+        Some(task) => {
+            let mut update: ActiveModel = task.into();
+            update.schedule = Set(schedule.to_string());
+            update.next_run_at = Set(next_run_at);
+            update.update(db).await
+        }
+        None => {
+            let new_task = ActiveModel {
+                name: Set(name.to_string()),
+                schedule: Set(schedule.to_string()),
+                next_run_at: Set(next_run_at),
+                ..Default::default()
+            };
+            new_task.insert(db).await
+        }
+    }
+}
+
+/// Tasks that should've run by `now`.
+pub async fn due(
+    db: &DatabaseConnection,
+    now: DateTimeUtc,
+) -> anyhow::Result<Vec<Model>, sea_orm::DbErr> {
+    Entity::find()
+        .filter(Column::NextRunAt.lte(now))
+        .all(db)
+        .await
+}
+
+/// Record a task run & when it should run next.
+pub async fn mark_run(
+    db: &DatabaseConnection,
+    task: Model,
+    error: Option<String>,
+    next_run_at: DateTimeUtc,
+) -> anyhow::Result<Model, sea_orm::DbErr> {
+    let mut update: ActiveModel = task.into();
+    update.last_run_at = Set(Some(chrono::Utc::now()));
+    update.last_error = Set(error);
+    update.next_run_at = Set(next_run_at);
+    update.update(db).await
+}
+
+/// Remove a task that's been disabled.
+pub async fn remove(db: &DatabaseConnection, name: &str) -> anyhow::Result<u64, sea_orm::DbErr> {
+    let res = Entity::delete_many()
+        .filter(Column::Name.eq(name))
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+
+    use crate::models::scheduled_task;
+    use crate::test::setup_test_db;
+
+    #[tokio::test]
+    async fn test_register_and_due() {
+        let db = setup_test_db().await;
+        let now = Utc::now();
+
+        let task = scheduled_task::register(&db, "recrawl", "@daily", now - Duration::hours(1))
+            .await
+            .unwrap();
+        scheduled_task::register(&db, "optimize", "@weekly", now + Duration::hours(1))
+            .await
+            .unwrap();
+
+        // Same schedule keeps the persisted run time
+        let same = scheduled_task::register(&db, "recrawl", "@daily", now + Duration::days(1))
+            .await
+            .unwrap();
+        assert_eq!(same.next_run_at, task.next_run_at);
+
+        let due = scheduled_task::due(&db, now).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].name, "recrawl");
+
+        let ran = scheduled_task::mark_run(&db, due[0].clone(), None, now + Duration::days(1))
+            .await
+            .unwrap();
+        assert!(ran.last_run_at.is_some());
+        assert!(scheduled_task::due(&db, now).await.unwrap().is_empty());
+
+        // Changed schedule resets the run time
+        let changed = scheduled_task::register(&db, "recrawl", "@hourly", now)
+            .await
+            .unwrap();
+        assert_eq!(changed.schedule, "@hourly");
+        assert_eq!(changed.next_run_at.timestamp(), now.timestamp());
+    }
+}
@@ -0,0 +1,180 @@
+use sea_orm::entity::prelude::*;
+use sea_orm::{QueryOrder, QuerySelect, Set};
+
+/// Searches made within this many seconds of a longer one starting w/ the
+/// same text are assumed to be the user still typing it.
+const TYPING_WINDOW_SECS: i64 = 30;
+/// Number of the most recent matches considered for suggestions.
+const MAX_CANDIDATES: u64 = 100;
+/// How quickly old searches stop counting for suggestions.
+const HALF_LIFE_DAYS: f64 = 7.0;
+
+/// Queries the user has searched for, used to suggest past searches.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "search_history")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    /// Query as typed, minus leading/trailing whitespace
+    #[sea_orm(unique)]
+    pub query: String,
+    /// Number of times this was searched for
+    pub count: i64,
+    /// When this was first searched for
+    pub created_at: DateTimeUtc,
+    /// When this was last searched for
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        panic!("No RelationDef")
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {
+    fn new() -> Self {
+        Self {
+            count: Set(0),
+            created_at: Set(chrono::Utc::now()),
+            updated_at: Set(chrono::Utc::now()),
+            ..ActiveModelTrait::default()
+        }
+    }
+
+    // Triggered before insert / update
+    fn before_save(mut self, insert: bool) -> Result<Self, DbErr> {
+        if !insert {
+            self.updated_at = Set(chrono::Utc::now());
+        }
+
+        Ok(self)
+    }
+}
+
+impl Model {
+    /// Searches made more often & more recently rank higher.
+    fn frecency(&self, now: DateTimeUtc) -> f64 {
+        let age_days = (now - self.updated_at).num_seconds().max(0) as f64 / 86_400.0;
+        self.count as f64 * 0.5_f64.powf(age_days / HALF_LIFE_DAYS)
+    }
+}
+
+/// Record a search for `query`. Searches for the start of `query` made just
+/// before it are dropped, they're left over from typing it out.
+pub async fn record(db: &DatabaseConnection, query: &str) -> anyhow::Result<(), DbErr> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(());
+    }
+
+    let typing_since = chrono::Utc::now() - chrono::Duration::seconds(TYPING_WINDOW_SECS);
+    let partials: Vec<i64> = Entity::find()
+        .filter(Column::Count.eq(1))
+        .filter(Column::UpdatedAt.gte(typing_since))
+        .all(db)
+        .await?
+        .into_iter()
+        .filter(|partial| partial.query.len() < query.len() && query.starts_with(&partial.query))
+        .map(|partial| partial.id)
+        .collect();
+    if !partials.is_empty() {
+        Entity::delete_many()
+            .filter(Column::Id.is_in(partials))
+            .exec(db)
+            .await?;
+    }
+
+    let existing = Entity::find()
+        .filter(Column::Query.eq(query))
+        .one(db)
+        .await?;
+    let mut update: ActiveModel = match existing {
+        Some(existing) => existing.into(),
+        None => ActiveModel {
+            query: Set(query.to_string()),
+            ..Default::default()
+        },
+    };
+
+    update.count = Set(*update.count.as_ref() + 1);
+    update.save(db).await?;
+
+    Ok(())
+}
+
+/// Up to `limit` past searches starting w/ `prefix`, best match first.
+pub async fn suggestions(
+    db: &DatabaseConnection,
+    prefix: &str,
+    limit: usize,
+) -> anyhow::Result<Vec<Model>, DbErr> {
+    let prefix = prefix.trim_start().to_lowercase();
+    let mut matches: Vec<Model> = Entity::find()
+        .filter(Column::Query.starts_with(&prefix))
+        .order_by_desc(Column::UpdatedAt)
+        .limit(MAX_CANDIDATES)
+        .all(db)
+        .await?
+        .into_iter()
+        // LIKE treats `%` & `_` as wildcards
+        .filter(|model| model.query.to_lowercase().starts_with(&prefix))
+        .collect();
+
+    let now = chrono::Utc::now();
+    matches.sort_by(|a, b| b.frecency(now).total_cmp(&a.frecency(now)));
+    matches.truncate(limit);
+
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::setup_test_db;
+
+    #[tokio::test]
+    async fn test_record() {
+        let db = setup_test_db().await;
+        for query in ["r", "ru", "rust", "rust", "  rust ", "python"] {
+            super::record(&db, query).await.unwrap();
+        }
+
+        let suggestions = super::suggestions(&db, "", 10).await.unwrap();
+        let queries: Vec<(&str, i64)> = suggestions
+            .iter()
+            .map(|model| (model.query.as_str(), model.count))
+            .collect();
+        // Partially typed searches are dropped
+        assert_eq!(queries, vec![("rust", 3), ("python", 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_suggestions() {
+        let db = setup_test_db().await;
+        for query in [
+            "Rust async",
+            "rust borrow",
+            "rust borrow",
+            "python",
+            "100%_done",
+        ] {
+            super::record(&db, query).await.unwrap();
+        }
+
+        let suggestions = super::suggestions(&db, "rust", 10).await.unwrap();
+        let queries: Vec<&str> = suggestions
+            .iter()
+            .map(|model| model.query.as_str())
+            .collect();
+        assert_eq!(queries, vec!["rust borrow", "Rust async"]);
+
+        let suggestions = super::suggestions(&db, "rust", 1).await.unwrap();
+        assert_eq!(suggestions.len(), 1);
+
+        let suggestions = super::suggestions(&db, "1_0", 10).await.unwrap();
+        assert!(suggestions.is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+//! URL normalization so the same page isn't queued/indexed under slightly
+//! different URLs. Parsing already lowercases the host, drops default ports &
+//! resolves dot segments, on top of that we strip fragments & tracking
+//! parameters and sort what's left of the query.
+use url::Url;
+
+/// Query parameters only used for analytics. Anything starting w/ `utm_` is
+/// stripped as well.
+const TRACKING_PARAMS: [&str; 11] = [
+    "_ga", "dclid", "fbclid", "gclid", "igshid", "mc_cid", "mc_eid", "msclkid", "ref_src",
+    "twclid", "yclid",
+];
+
+fn is_tracking_param(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    key.starts_with("utm_") || TRACKING_PARAMS.contains(&key.as_str())
+}
+
+/// Normalize a parsed URL in place.
+pub fn normalize(url: &mut Url) {
+    url.set_fragment(None);
+
+    if url.query().is_none() {
+        return;
+    }
+
+    let mut params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| !is_tracking_param(key))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    if params.is_empty() {
+        url.set_query(None);
+    } else {
+        // Stable, so repeated keys keep their order.
+        params.sort_by(|a, b| a.0.cmp(&b.0));
+        url.query_pairs_mut().clear().extend_pairs(params);
+    }
+}
+
+/// Normalized form of `url`, `None` if it's not a valid URL.
+pub fn normalize_url(url: &str) -> Option<String> {
+    let mut parsed = Url::parse(url).ok()?;
+    normalize(&mut parsed);
+    Some(parsed.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::normalize_url;
+
+    #[test]
+    fn test_normalize_url() {
+        let normalize = |url: &str| normalize_url(url).unwrap();
+
+        assert_eq!(
+            normalize("HTTPS://Example.COM:443/a/./b/../c#section"),
+            "https://example.com/a/c"
+        );
+        assert_eq!(
+            normalize("https://example.com/post?utm_source=rss&UTM_Medium=feed&fbclid=abc"),
+            "https://example.com/post"
+        );
+        assert_eq!(
+            normalize("https://example.com/search?q=rust&page=2&gclid=abc&lang=en"),
+            "https://example.com/search?lang=en&page=2&q=rust"
+        );
+        assert_eq!(normalize("https://example.com/?"), "https://example.com/");
+        assert_eq!(normalize_url("not a url"), None);
+    }
+}
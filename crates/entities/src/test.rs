@@ -2,8 +2,9 @@ use sea_orm::{ConnectionTrait, DatabaseConnection, Schema};
 use shared::config::Config;
 
 use crate::models::{
-    bootstrap_queue, crawl_queue, create_connection, fetch_history, indexed_document, lens, link,
-    resource_rule,
+    bootstrap_queue, crawl_log, crawl_queue, create_connection, document_content,
+    document_embedding, document_tombstone, domain_stats, feed_entry, fetch_history,
+    indexed_document, lens, link, resource_rule, scheduled_task, search_history,
 };
 
 #[allow(dead_code)]
@@ -45,6 +46,22 @@ async fn setup_schema(db: &DatabaseConnection) -> anyhow::Result<(), sea_orm::Db
         ),
     )
     .await?;
+    db.execute(
+        builder.build(
+            schema
+                .create_table_from_entity(document_content::Entity)
+                .if_not_exists(),
+        ),
+    )
+    .await?;
+    db.execute(
+        builder.build(
+            schema
+                .create_table_from_entity(document_embedding::Entity)
+                .if_not_exists(),
+        ),
+    )
+    .await?;
     db.execute(
         builder.build(
             schema
@@ -80,5 +97,58 @@ async fn setup_schema(db: &DatabaseConnection) -> anyhow::Result<(), sea_orm::Db
     )
     .await?;
 
+    db.execute(
+        builder.build(
+            schema
+                .create_table_from_entity(scheduled_task::Entity)
+                .if_not_exists(),
+        ),
+    )
+    .await?;
+
+    db.execute(
+        builder.build(
+            schema
+                .create_table_from_entity(search_history::Entity)
+                .if_not_exists(),
+        ),
+    )
+    .await?;
+    db.execute(
+        builder.build(
+            schema
+                .create_table_from_entity(domain_stats::Entity)
+                .if_not_exists(),
+        ),
+    )
+    .await?;
+
+    db.execute(
+        builder.build(
+            schema
+                .create_table_from_entity(feed_entry::Entity)
+                .if_not_exists(),
+        ),
+    )
+    .await?;
+
+    db.execute(
+        builder.build(
+            schema
+                .create_table_from_entity(crawl_log::Entity)
+                .if_not_exists(),
+        ),
+    )
+    .await?;
+
+    db.execute(
+        builder.build(
+            schema
+                .create_table_from_entity(document_tombstone::Entity)
+                .if_not_exists(),
+        ),
+    )
+    .await?;
+
     Ok(())
 }
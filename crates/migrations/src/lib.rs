@@ -4,6 +4,27 @@ mod m20220505_000001_create_table;
 mod m20220508_000001_lens_and_crawl_queue_update;
 mod m20220522_000001_bootstrap_queue_table;
 mod m20220718_000001_add_cols_to_lens;
+mod m20220805_000001_add_archived_at_to_indexed_document;
+mod m20220810_000001_scheduled_task_table;
+mod m20220901_000001_document_content_table;
+mod m20220905_000001_add_content_hash_to_indexed_document;
+mod m20220908_000001_add_lang_to_indexed_document;
+mod m20220912_000001_document_embedding_table;
+mod m20220915_000001_search_history_table;
+mod m20220918_000001_add_crawl_delay_to_resource_rules;
+mod m20220920_000001_feed_entry_table;
+mod m20220922_000001_add_validators_to_fetch_history;
+mod m20220924_000001_add_next_retry_at_to_crawl_queue;
+mod m20220926_000001_add_depth_to_crawl_queue;
+mod m20220928_000001_add_priority_to_crawl_queue;
+mod m20220930_000001_domain_stats_table;
+mod m20221002_000001_add_no_follow_to_fetch_history;
+mod m20221004_000001_add_indexes_to_link;
+mod m20221006_000001_add_page_metadata_to_indexed_document;
+mod m20221008_000001_crawl_log_table;
+mod m20221010_000001_add_indexes_to_crawl_queue;
+mod m20221012_000001_add_lease_expires_at_to_crawl_queue;
+mod m20221014_000001_document_tombstone_table;
 
 pub struct Migrator;
 
@@ -15,6 +36,27 @@ impl MigratorTrait for Migrator {
             Box::new(m20220508_000001_lens_and_crawl_queue_update::Migration),
             Box::new(m20220522_000001_bootstrap_queue_table::Migration),
             Box::new(m20220718_000001_add_cols_to_lens::Migration),
+            Box::new(m20220805_000001_add_archived_at_to_indexed_document::Migration),
+            Box::new(m20220810_000001_scheduled_task_table::Migration),
+            Box::new(m20220901_000001_document_content_table::Migration),
+            Box::new(m20220905_000001_add_content_hash_to_indexed_document::Migration),
+            Box::new(m20220908_000001_add_lang_to_indexed_document::Migration),
+            Box::new(m20220912_000001_document_embedding_table::Migration),
+            Box::new(m20220915_000001_search_history_table::Migration),
+            Box::new(m20220918_000001_add_crawl_delay_to_resource_rules::Migration),
+            Box::new(m20220920_000001_feed_entry_table::Migration),
+            Box::new(m20220922_000001_add_validators_to_fetch_history::Migration),
+            Box::new(m20220924_000001_add_next_retry_at_to_crawl_queue::Migration),
+            Box::new(m20220926_000001_add_depth_to_crawl_queue::Migration),
+            Box::new(m20220928_000001_add_priority_to_crawl_queue::Migration),
+            Box::new(m20220930_000001_domain_stats_table::Migration),
+            Box::new(m20221002_000001_add_no_follow_to_fetch_history::Migration),
+            Box::new(m20221004_000001_add_indexes_to_link::Migration),
+            Box::new(m20221006_000001_add_page_metadata_to_indexed_document::Migration),
+            Box::new(m20221008_000001_crawl_log_table::Migration),
+            Box::new(m20221010_000001_add_indexes_to_crawl_queue::Migration),
+            Box::new(m20221012_000001_add_lease_expires_at_to_crawl_queue::Migration),
+            Box::new(m20221014_000001_document_tombstone_table::Migration),
         ]
     }
 }
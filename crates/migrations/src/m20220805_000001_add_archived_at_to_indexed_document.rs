@@ -0,0 +1,31 @@
+use entities::models::indexed_document;
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220805_000001_add_archived_at_to_indexed_document"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Add archived_at column
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(indexed_document::Entity)
+                    .add_column(ColumnDef::new(Alias::new("archived_at")).timestamp())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, _: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
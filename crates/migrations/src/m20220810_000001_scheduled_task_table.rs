@@ -0,0 +1,40 @@
+use entities::sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220810_000001_scheduled_task_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let new_table = r#"
+            CREATE TABLE IF NOT EXISTS "scheduled_task" (
+                "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+                "name" text NOT NULL UNIQUE,
+                "schedule" text NOT NULL,
+                "next_run_at" text NOT NULL,
+                "last_run_at" text,
+                "last_error" text,
+                "created_at" text NOT NULL,
+                "updated_at" text NOT NULL);"#;
+
+        // Create scheduled task table
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                new_table.to_owned().to_string(),
+            ))
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, _: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+use entities::models::indexed_document;
+use entities::sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220905_000001_add_content_hash_to_indexed_document"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Add content_hash column
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(indexed_document::Entity)
+                    .add_column(ColumnDef::new(Alias::new("content_hash")).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Duplicates are looked up by hash every time a page is indexed.
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                r#"CREATE INDEX IF NOT EXISTS "idx-indexed_document-content_hash" ON "indexed_document" ("content_hash");"#
+                    .to_string(),
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, _: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
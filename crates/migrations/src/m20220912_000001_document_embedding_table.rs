@@ -0,0 +1,38 @@
+use entities::sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220912_000001_document_embedding_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let new_table = r#"
+            CREATE TABLE IF NOT EXISTS "document_embedding" (
+                "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+                "url" text NOT NULL UNIQUE,
+                "model" text NOT NULL,
+                "embedding" blob NOT NULL,
+                "created_at" text NOT NULL,
+                "updated_at" text NOT NULL);"#;
+
+        // Create document embedding table
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                new_table.to_owned().to_string(),
+            ))
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, _: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use entities::sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220915_000001_search_history_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let new_table = r#"
+            CREATE TABLE IF NOT EXISTS "search_history" (
+                "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+                "query" text NOT NULL UNIQUE,
+                "count" integer NOT NULL,
+                "created_at" text NOT NULL,
+                "updated_at" text NOT NULL);"#;
+
+        // Create search history table
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                new_table.to_owned().to_string(),
+            ))
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, _: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
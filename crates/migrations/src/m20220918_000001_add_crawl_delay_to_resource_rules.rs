@@ -0,0 +1,31 @@
+use entities::models::resource_rule;
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220918_000001_add_crawl_delay_to_resource_rules"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Add crawl_delay column
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(resource_rule::Entity)
+                    .add_column(ColumnDef::new(Alias::new("crawl_delay")).integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, _: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use entities::models::indexed_document;
+use entities::sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220920_000001_feed_entry_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let new_table = r#"
+            CREATE TABLE IF NOT EXISTS "feed_entry" (
+                "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+                "feed_url" text NOT NULL,
+                "url" text NOT NULL UNIQUE,
+                "title" text,
+                "author" text,
+                "published_at" text,
+                "created_at" text NOT NULL,
+                "updated_at" text NOT NULL);"#;
+
+        // Create feed entry table
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                new_table.to_owned().to_string(),
+            ))
+            .await?;
+
+        // Feed metadata is copied onto the page once it's indexed
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(indexed_document::Entity)
+                    .add_column(ColumnDef::new(Alias::new("author")).string())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(indexed_document::Entity)
+                    .add_column(ColumnDef::new(Alias::new("published_at")).timestamp())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, _: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+use entities::sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220930_000001_domain_stats_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let new_table = r#"
+            CREATE TABLE IF NOT EXISTS "domain_stats" (
+                "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+                "domain" text NOT NULL UNIQUE,
+                "num_fetched" integer NOT NULL DEFAULT 0,
+                "num_client_errors" integer NOT NULL DEFAULT 0,
+                "num_server_errors" integer NOT NULL DEFAULT 0,
+                "num_network_errors" integer NOT NULL DEFAULT 0,
+                "bytes_downloaded" integer NOT NULL DEFAULT 0,
+                "last_crawled_at" text,
+                "created_at" text NOT NULL,
+                "updated_at" text NOT NULL);"#;
+
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                new_table.to_owned().to_string(),
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, _: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+use entities::sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20221004_000001_add_indexes_to_link"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Links are looked up by the page they're on & the page they point to.
+        for sql in [
+            "CREATE INDEX IF NOT EXISTS \"idx-link-src_url\" ON \"link\" (\"src_url\");",
+            "CREATE INDEX IF NOT EXISTS \"idx-link-dst_url\" ON \"link\" (\"dst_url\");",
+        ] {
+            manager
+                .get_connection()
+                .execute(Statement::from_string(
+                    manager.get_database_backend(),
+                    sql.to_string(),
+                ))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, _: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use entities::models::indexed_document;
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20221006_000001_add_page_metadata_to_indexed_document"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only allows adding one column at a time
+        for column in ["og_title", "og_description", "og_image", "schema_types"] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(indexed_document::Entity)
+                        .add_column(ColumnDef::new(Alias::new(column)).string())
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, _: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use entities::sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20221008_000001_crawl_log_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let new_table = r#"
+            CREATE TABLE IF NOT EXISTS "crawl_log" (
+                "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+                "domain" text NOT NULL,
+                "url" text NOT NULL,
+                "status" integer,
+                "duration_ms" integer NOT NULL,
+                "num_bytes" integer NOT NULL,
+                "error" text,
+                "created_at" text NOT NULL);"#;
+
+        // Entries are listed by domain & pruned by age.
+        for sql in [
+            new_table,
+            "CREATE INDEX IF NOT EXISTS \"idx-crawl_log-domain\" ON \"crawl_log\" (\"domain\");",
+            "CREATE INDEX IF NOT EXISTS \"idx-crawl_log-created_at\" ON \"crawl_log\" (\"created_at\");",
+        ] {
+            manager
+                .get_connection()
+                .execute(Statement::from_string(
+                    manager.get_database_backend(),
+                    sql.to_string(),
+                ))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, _: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
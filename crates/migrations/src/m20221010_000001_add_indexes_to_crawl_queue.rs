@@ -0,0 +1,35 @@
+use entities::sea_orm::{ConnectionTrait, Statement};
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20221010_000001_add_indexes_to_crawl_queue"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The queue is listed by status & domain.
+        for sql in [
+            "CREATE INDEX IF NOT EXISTS \"idx-crawl_queue-status\" ON \"crawl_queue\" (\"status\");",
+            "CREATE INDEX IF NOT EXISTS \"idx-crawl_queue-domain\" ON \"crawl_queue\" (\"domain\");",
+        ] {
+            manager
+                .get_connection()
+                .execute(Statement::from_string(
+                    manager.get_database_backend(),
+                    sql.to_string(),
+                ))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, _: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
@@ -63,6 +63,7 @@ async fn main() -> Result<(), anyhow::Error> {
                         url.as_str(),
                         &scrape.content.unwrap(),
                         &scrape.raw.unwrap(),
+                        scrape.lang.as_deref(),
                     )
                     .unwrap()
                 };
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+
+/// Actions that can be run from the command palette, i.e. queries prefixed w/ `>`
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum PaletteCommand {
+    OpenLensManager,
+    OpenPluginManager,
+    OptimizeIndex,
+    Reindex,
+    TogglePause,
+    TogglePlugin(String),
+}
+
+impl PaletteCommand {
+    /// Commands that are always available, regardless of installed plugins/lenses.
+    pub fn builtin() -> Vec<PaletteCommand> {
+        vec![
+            PaletteCommand::TogglePause,
+            PaletteCommand::OpenLensManager,
+            PaletteCommand::OpenPluginManager,
+            PaletteCommand::Reindex,
+            PaletteCommand::OptimizeIndex,
+        ]
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            PaletteCommand::OpenLensManager => "Open lens manager".into(),
+            PaletteCommand::OpenPluginManager => "Open plugin manager".into(),
+            PaletteCommand::OptimizeIndex => "Optimize index".into(),
+            PaletteCommand::Reindex => "Reindex".into(),
+            PaletteCommand::TogglePause => "Pause/resume crawling".into(),
+            PaletteCommand::TogglePlugin(name) => format!("Toggle plugin: {}", name),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            PaletteCommand::OpenLensManager => "Manage & install lenses".into(),
+            PaletteCommand::OpenPluginManager => "Manage & configure plugins".into(),
+            PaletteCommand::OptimizeIndex => "Merge index segments to speed up searches".into(),
+            PaletteCommand::Reindex => "Recrawl & reindex all documents".into(),
+            PaletteCommand::TogglePause => "Toggle whether the crawler is running".into(),
+            PaletteCommand::TogglePlugin(name) => format!("Enable/disable the {} plugin", name),
+        }
+    }
+}
+
+/// Scores how well `query` fuzzy matches `target`. All characters in the query
+/// must appear in order in the target. Consecutive matches & matches at the start
+/// of a word are scored higher. Returns None if there is no match.
+pub fn fuzzy_score(query: &str, target: &str) -> Option<i64> {
+    let query = query.to_lowercase();
+    let mut query_chars = query.chars().filter(|c| !c.is_whitespace()).peekable();
+
+    let mut score = 0;
+    let mut last_match: Option<usize> = None;
+    let mut prev_char: Option<char> = None;
+    for (idx, ch) in target.to_lowercase().chars().enumerate() {
+        if let Some(&next) = query_chars.peek() {
+            if next == ch {
+                score += 1;
+                // Bonus for consecutive matches
+                if last_match.map(|last| last + 1 == idx).unwrap_or(false) {
+                    score += 5;
+                }
+                // Bonus for matching the start of a word
+                if prev_char
+                    .map(|prev| !prev.is_alphanumeric())
+                    .unwrap_or(true)
+                {
+                    score += 3;
+                }
+
+                last_match = Some(idx);
+                query_chars.next();
+            }
+        }
+
+        prev_char = Some(ch);
+    }
+
+    if query_chars.peek().is_some() {
+        None
+    } else {
+        Some(score)
+    }
+}
+
+/// Filter & sort commands by how well they match the query.
+pub fn filter_commands(query: &str, commands: &[PaletteCommand]) -> Vec<PaletteCommand> {
+    let mut matches: Vec<(i64, PaletteCommand)> = commands
+        .iter()
+        .filter_map(|cmd| fuzzy_score(query, &cmd.label()).map(|score| (score, cmd.clone())))
+        .collect();
+
+    // Highest scores first
+    matches.sort_by(|a, b| b.0.cmp(&a.0));
+    matches.into_iter().map(|(_, cmd)| cmd).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{filter_commands, fuzzy_score, PaletteCommand};
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("lm", "Open lens manager").is_some());
+        assert!(fuzzy_score("xyz", "Open lens manager").is_none());
+        // Empty queries match everything
+        assert_eq!(fuzzy_score("", "Reindex"), Some(0));
+        // Consecutive matches should score higher
+        assert!(fuzzy_score("rein", "Reindex") > fuzzy_score("rdex", "Reindex"));
+    }
+
+    #[test]
+    fn test_filter_commands() {
+        let mut commands = PaletteCommand::builtin();
+        commands.push(PaletteCommand::TogglePlugin("chrome-importer".into()));
+
+        let res = filter_commands("chrome", &commands);
+        assert_eq!(res.len(), 1);
+        assert_eq!(
+            res[0],
+            PaletteCommand::TogglePlugin("chrome-importer".into())
+        );
+
+        let res = filter_commands("pause", &commands);
+        assert_eq!(res[0], PaletteCommand::TogglePause);
+    }
+}
@@ -1,14 +1,85 @@
 use std::collections::HashMap;
 use std::fs;
+use std::io::{ErrorKind, Write};
 use std::path::PathBuf;
+use std::time::Duration;
 
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 pub const MAX_TOTAL_INFLIGHT: u32 = 100;
 pub const MAX_DOMAIN_INFLIGHT: u32 = 100;
-
+// The index writer splits its memory budget across indexing threads & each
+// thread requires at least a few MB.
+pub const MIN_WRITER_MEMORY_MB: u32 = 30;
+// Shorter half-lives would rank documents by little more than their age.
+pub const MIN_RECENCY_HALF_LIFE_DAYS: f32 = 1.0;
+/// Identifies the crawler to the sites it visits, see `UserSettings::user_agent`.
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    "spyglass/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/a5huynh/spyglass)"
+);
+/// Times to check for a token while another process is creating it.
+const API_TOKEN_ATTEMPTS: usize = 10;
+/// Lenses available to install, see `InstallableLens`.
+pub const LENS_DIRECTORY_INDEX_URL: &str =
+    "https://raw.githubusercontent.com/spyglass-search/lens-box/main/index.ron";
+
+/// Setting values the user has set, keyed by plugin name.
 pub type PluginSettings = HashMap<String, HashMap<String, String>>;
+
+/// A setting a plugin needs from the user, e.g. an API key or folder path.
+/// Declared in the `settings` of the plugin manifest.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct PluginSettingSchema {
+    /// Shown in the settings form.
+    pub label: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub form_type: PluginSettingType,
+    /// Used until the user sets a value.
+    #[serde(default)]
+    pub default: Option<String>,
+    /// The plugin won't be started w/o a value.
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum PluginSettingType {
+    Text,
+    /// A file or folder on the host.
+    Path,
+    /// Hidden in the settings form, e.g. API keys.
+    Secret,
+}
+
+impl Default for PluginSettingType {
+    fn default() -> Self {
+        PluginSettingType::Text
+    }
+}
+
+/// Credentials the crawler uses, keyed by domain. Subdomains use the
+/// credentials of their parent domain unless they have their own.
+pub type Credentials = HashMap<String, DomainCredentials>;
+
+/// How the crawler authenticates w/ a domain, e.g. to index an internal wiki.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DomainCredentials {
+    /// Sent in the `Cookie` header, e.g. a session cookie copied from the browser.
+    #[serde(default)]
+    pub cookies: HashMap<String, String>,
+    /// Sent as `Authorization: Bearer <token>`.
+    #[serde(default)]
+    pub bearer_token: Option<String>,
+    /// Any other headers, e.g. `X-Api-Key`.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
 #[derive(Clone, Debug)]
 pub struct Config {
     pub user_settings: UserSettings,
@@ -45,6 +116,29 @@ pub struct Lens {
     pub is_enabled: bool,
     #[serde(default)]
     pub rules: Vec<LensRule>,
+    /// Multiplies the score of results in this lens when searching w/ more
+    /// than one lens, e.g. `Some(2.0)` to rank it above the others.
+    #[serde(default)]
+    pub boost: Option<f32>,
+    /// RSS/Atom feeds polled for new pages, e.g. a blog's `/feed.xml`.
+    #[serde(default)]
+    pub feeds: Vec<String>,
+    /// Recrawl pages in this lens every this many days to keep them fresh,
+    /// on top of the global monthly recrawl.
+    #[serde(default)]
+    pub refresh_interval: Option<u32>,
+    /// Render pages w/ a headless browser before indexing them, for sites that
+    /// build their content w/ JS.
+    #[serde(default)]
+    pub render: bool,
+    /// Only follow links this many hops away from the lens's domains/URLs,
+    /// e.g. `Some(2)`. No limit when not set.
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+    /// Only follow links from this lens's pages to its own domains/URLs, even
+    /// if `crawl_external_links` is enabled.
+    #[serde(default)]
+    pub lens_links_only: bool,
 }
 
 impl Lens {
@@ -55,6 +149,11 @@ impl Lens {
     fn default_is_enabled() -> bool {
         true
     }
+
+    /// Score multiplier for this lens, 1.0 unless a positive boost is set.
+    pub fn boost(&self) -> f32 {
+        self.boost.filter(|boost| *boost > 0.0).unwrap_or(1.0)
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -78,6 +177,139 @@ impl Limit {
     }
 }
 
+/// Lets other spyglass instances crawl on our behalf.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WorkerApiSettings {
+    /// Port to listen on. Bound to all interfaces so workers on other
+    /// machines can connect.
+    pub port: u16,
+    /// Shared secret workers need to send along w/ each request.
+    pub token: String,
+}
+
+/// The instance a crawl worker pulls tasks from.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RemotePrimarySettings {
+    /// Base URL of the primary's worker API, e.g. `http://192.168.1.10:4665`
+    pub url: String,
+    /// Must match the token in the primary's `worker_api` settings.
+    pub token: String,
+}
+
+/// Where devices exchange sync snapshots.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum SyncTarget {
+    /// A folder shared between devices, e.g. through Dropbox or Syncthing.
+    Folder(PathBuf),
+    /// A self-hosted endpoint that stores snapshots, see `libspyglass::sync`.
+    Endpoint { url: String, token: String },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SyncSettings {
+    pub target: SyncTarget,
+    /// Include the cached copy of each page so other devices can index it
+    /// w/o recrawling. Makes snapshots a lot larger.
+    #[serde(default)]
+    pub include_pages: bool,
+}
+
+/// Proxy the crawler's requests go through.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProxySettings {
+    /// e.g. `http://proxy.corp.example.com:8080`, used for both HTTP & HTTPS.
+    pub url: String,
+    /// Sent as basic auth, if the proxy needs it.
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Hosts to connect to directly, e.g. `["localhost", ".corp.example.com"]`.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+/// Embeddings for semantic search, computed by an OpenAI-compatible
+/// `/v1/embeddings` endpoint, e.g. one served locally by Ollama.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SemanticSettings {
+    /// Base URL of the endpoint, e.g. `http://localhost:11434`
+    pub endpoint: String,
+    /// Embedding model to use, e.g. `nomic-embed-text`. Changing it
+    /// recomputes the embeddings of every document.
+    pub model: String,
+    /// Sent as a bearer token, if the endpoint needs one.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Number of documents embedded per request.
+    #[serde(default = "SemanticSettings::default_batch_size")]
+    pub batch_size: u32,
+}
+
+impl SemanticSettings {
+    fn default_batch_size() -> u32 {
+        16
+    }
+}
+
+/// How much a match in each part of a document counts towards its score.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct RankingSettings {
+    #[serde(default = "RankingSettings::default_title_boost")]
+    pub title_boost: f32,
+    #[serde(default = "RankingSettings::default_body_boost")]
+    pub body_boost: f32,
+    #[serde(default = "RankingSettings::default_url_boost")]
+    pub url_boost: f32,
+    /// Rank recently indexed documents a bit higher. The boost halves every
+    /// this many days, off when not set.
+    #[serde(default)]
+    pub recency_half_life_days: Option<f32>,
+}
+
+impl RankingSettings {
+    fn default_title_boost() -> f32 {
+        5.0
+    }
+
+    fn default_body_boost() -> f32 {
+        1.0
+    }
+
+    fn default_url_boost() -> f32 {
+        1.0
+    }
+}
+
+impl Default for RankingSettings {
+    fn default() -> Self {
+        RankingSettings {
+            title_boost: RankingSettings::default_title_boost(),
+            body_boost: RankingSettings::default_body_boost(),
+            url_boost: RankingSettings::default_url_boost(),
+            recency_half_life_days: None,
+        }
+    }
+}
+
+/// Which query terms also match words they're the start of, so results show
+/// up while a word is still being typed.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum PrefixMatch {
+    /// Only whole words match.
+    Off,
+    /// Only the last word, unless it's followed by a space.
+    LastTerm,
+    /// Every word in the query.
+    AllTerms,
+}
+
+impl Default for PrefixMatch {
+    fn default() -> Self {
+        PrefixMatch::LastTerm
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct UserSettings {
     /// Number of pages allowed per domain. Sub-domains are treated as
@@ -87,6 +319,32 @@ pub struct UserSettings {
     pub inflight_crawl_limit: Limit,
     /// Number of in-flight crawls allowed per domain.
     pub inflight_domain_limit: Limit,
+    /// Min. time (in ms) between requests to the same domain.
+    #[serde(default = "UserSettings::default_politeness_delay_ms")]
+    pub politeness_delay_ms: u64,
+    /// Override `politeness_delay_ms` for specific domains, e.g.
+    /// `{"wiki.example.com": 5000}` for a small server. A longer Crawl-delay
+    /// in the domain's robots.txt still wins.
+    #[serde(default)]
+    pub domain_politeness_delays_ms: HashMap<String, u64>,
+    /// Number of times a page that failed to crawl is retried, w/ a growing
+    /// delay in between, before it's marked as failed.
+    #[serde(default = "UserSettings::default_max_crawl_retries")]
+    pub max_crawl_retries: u8,
+    /// Max bytes per second downloaded by the crawler across all domains,
+    /// e.g. `Some(500000)` for ~500 KB/s. No limit when not set.
+    #[serde(default)]
+    pub crawl_bytes_per_sec: Option<u64>,
+    /// User-Agent sent w/ the crawler's requests.
+    #[serde(default = "UserSettings::default_user_agent")]
+    pub user_agent: String,
+    /// Override `user_agent` for specific domains, e.g. for sites that block
+    /// anything they don't recognize.
+    #[serde(default)]
+    pub domain_user_agents: HashMap<String, String>,
+    /// Number of days crawl attempts are kept in the crawl log.
+    #[serde(default = "UserSettings::default_crawl_log_retention_days")]
+    pub crawl_log_retention_days: u32,
     /// Should we run the setup wizard?
     pub run_wizard: bool,
     /// Domains explicitly allowed, regardless of what's in the blocklist.
@@ -102,6 +360,93 @@ pub struct UserSettings {
     /// Should we crawl links that don't match our lens rules?
     #[serde(default)]
     pub crawl_external_links: bool,
+    /// Memory budget (in MB) for the index writer. Larger budgets mean fewer,
+    /// larger segments are flushed to disk.
+    #[serde(default = "UserSettings::default_writer_memory_mb")]
+    pub index_writer_memory_mb: u32,
+    /// Commit pending index writes once this many documents have been added/removed.
+    #[serde(default = "UserSettings::default_commit_batch_size")]
+    pub index_commit_batch_size: u32,
+    /// Max number of seconds pending index writes will wait before being committed.
+    #[serde(default = "UserSettings::default_commit_interval_s")]
+    pub index_commit_interval_s: u64,
+    /// Port for the Elasticsearch-compatible search endpoint, only bound to
+    /// localhost. Disabled when not set.
+    #[serde(default)]
+    pub es_api_port: Option<u16>,
+    /// Max number of results shown in KRunner.
+    #[serde(default = "UserSettings::default_krunner_max_results")]
+    pub krunner_max_results: u32,
+    /// Only search from KRunner when the query starts w/ this prefix, e.g. "sg".
+    /// All queries are searched when not set.
+    #[serde(default)]
+    pub krunner_prefix: Option<String>,
+    /// Import local documents, mail, & notes from Spotlight on startup (macOS only).
+    #[serde(default)]
+    pub spotlight_import: bool,
+    /// OTLP collector to export tracing spans to, e.g. "http://localhost:4317".
+    /// Requires a build w/ the `otlp` feature.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    /// Max memory (in MB) the server should use. Crawling is paused & pending
+    /// index writes are flushed while over the limit. No limit when not set.
+    #[serde(default)]
+    pub memory_limit_mb: Option<u32>,
+    /// When a page is gone (404/410) or its domain is dead, index the latest
+    /// snapshot from the Wayback Machine instead.
+    #[serde(default)]
+    pub archive_fallback: bool,
+    /// Keep a copy of the raw HTML for each page so it can be viewed offline.
+    #[serde(default = "UserSettings::default_cache_pages")]
+    pub cache_pages: bool,
+    /// Port for the local HTTP server used to view cached pages & serve the
+    /// REST API, only bound to localhost.
+    #[serde(default = "UserSettings::default_http_api_port")]
+    pub http_api_port: u16,
+    /// Override the cron schedule of background tasks, keyed by task name,
+    /// e.g. `"recrawl": "0 */6 * * *"`. Times are in UTC.
+    #[serde(default)]
+    pub task_schedules: HashMap<String, String>,
+    /// Accept remote crawl workers. Disabled when not set.
+    #[serde(default)]
+    pub worker_api: Option<WorkerApiSettings>,
+    /// Run as a crawl worker for another instance instead of crawling locally.
+    #[serde(default)]
+    pub remote_primary: Option<RemotePrimarySettings>,
+    /// Replicate documents & lenses between devices. Disabled when not set.
+    #[serde(default)]
+    pub sync: Option<SyncSettings>,
+    /// Match search terms w/ a typo or two in them by default.
+    #[serde(default)]
+    pub fuzzy_search: bool,
+    /// Field boosts used when scoring search results, e.g.
+    /// `ranking: (title_boost: 2.0, body_boost: 1.0, url_boost: 1.0)`. Add
+    /// `recency_half_life_days: Some(30.0)` to favor recently indexed pages.
+    #[serde(default)]
+    pub ranking: RankingSettings,
+    /// Languages to stem words in, e.g. `["english", "german"]`, so "crawling"
+    /// also matches "crawled". Changing these rebuilds the index.
+    #[serde(default = "UserSettings::default_stemming_languages")]
+    pub stemming_languages: Vec<String>,
+    /// Words left out of the index & queries, e.g. `["der", "die", "das"]`. A
+    /// built-in list of common English words is used when not set, an empty
+    /// list keeps every word. Changing these rebuilds the index.
+    #[serde(default)]
+    pub stop_words: Option<Vec<String>>,
+    /// Match partial words while typing, see `PrefixMatch`.
+    #[serde(default)]
+    pub prefix_search: PrefixMatch,
+    /// Compute embeddings for documents so they can be searched by meaning
+    /// rather than keywords. Disabled when not set.
+    #[serde(default)]
+    pub semantic_search: Option<SemanticSettings>,
+    /// RSS/Atom feeds polled for new pages, in addition to the ones listed
+    /// in lenses.
+    #[serde(default)]
+    pub feeds: Vec<String>,
+    /// Send crawler requests through a proxy. Direct connections when not set.
+    #[serde(default)]
+    pub proxy: Option<ProxySettings>,
 }
 
 impl UserSettings {
@@ -113,6 +458,60 @@ impl UserSettings {
         "CmdOrCtrl+Shift+/".to_string()
     }
 
+    fn default_writer_memory_mb() -> u32 {
+        50
+    }
+
+    fn default_commit_batch_size() -> u32 {
+        500
+    }
+
+    fn default_commit_interval_s() -> u64 {
+        10
+    }
+
+    fn default_krunner_max_results() -> u32 {
+        5
+    }
+
+    fn default_politeness_delay_ms() -> u64 {
+        500
+    }
+
+    /// Min. time between requests to `domain`.
+    pub fn politeness_delay(&self, domain: &str) -> Duration {
+        let delay_ms = self
+            .domain_politeness_delays_ms
+            .get(domain)
+            .copied()
+            .unwrap_or(self.politeness_delay_ms);
+        Duration::from_millis(delay_ms)
+    }
+
+    fn default_user_agent() -> String {
+        DEFAULT_USER_AGENT.to_string()
+    }
+
+    fn default_crawl_log_retention_days() -> u32 {
+        7
+    }
+
+    fn default_max_crawl_retries() -> u8 {
+        5
+    }
+
+    fn default_cache_pages() -> bool {
+        true
+    }
+
+    fn default_stemming_languages() -> Vec<String> {
+        vec!["english".to_string()]
+    }
+
+    fn default_http_api_port() -> u16 {
+        4664
+    }
+
     pub fn constraint_limits(&mut self) {
         // Make sure crawler limits are reasonable
         match self.inflight_crawl_limit {
@@ -128,6 +527,21 @@ impl UserSettings {
                 self.inflight_domain_limit = Limit::Finite(limit.min(MAX_DOMAIN_INFLIGHT))
             }
         }
+
+        // Make sure the index writer has enough memory to work with
+        self.index_writer_memory_mb = self.index_writer_memory_mb.max(MIN_WRITER_MEMORY_MB);
+        self.index_commit_batch_size = self.index_commit_batch_size.max(1);
+        if let Some(semantic) = self.semantic_search.as_mut() {
+            semantic.batch_size = semantic.batch_size.max(1);
+        }
+
+        // Negative boosts would push matching documents below ones that don't match
+        self.ranking.title_boost = self.ranking.title_boost.max(0.0);
+        self.ranking.body_boost = self.ranking.body_boost.max(0.0);
+        self.ranking.url_boost = self.ranking.url_boost.max(0.0);
+        if let Some(half_life) = self.ranking.recency_half_life_days.as_mut() {
+            *half_life = half_life.max(MIN_RECENCY_HALF_LIFE_DAYS);
+        }
     }
 }
 
@@ -139,6 +553,13 @@ impl Default for UserSettings {
             inflight_crawl_limit: Limit::Finite(10),
             // Limit to 2 crawlers for a domain
             inflight_domain_limit: Limit::Finite(2),
+            politeness_delay_ms: UserSettings::default_politeness_delay_ms(),
+            domain_politeness_delays_ms: HashMap::new(),
+            max_crawl_retries: UserSettings::default_max_crawl_retries(),
+            crawl_bytes_per_sec: None,
+            user_agent: UserSettings::default_user_agent(),
+            domain_user_agents: HashMap::new(),
+            crawl_log_retention_days: UserSettings::default_crawl_log_retention_days(),
             // Not used at the moment
             run_wizard: false,
             allow_list: Vec::new(),
@@ -148,36 +569,172 @@ impl Default for UserSettings {
             // Where to store the metadata & index
             data_directory: UserSettings::default_data_dir(),
             crawl_external_links: false,
+            index_writer_memory_mb: UserSettings::default_writer_memory_mb(),
+            index_commit_batch_size: UserSettings::default_commit_batch_size(),
+            index_commit_interval_s: UserSettings::default_commit_interval_s(),
+            es_api_port: None,
+            krunner_max_results: UserSettings::default_krunner_max_results(),
+            krunner_prefix: None,
+            spotlight_import: false,
+            otlp_endpoint: None,
+            memory_limit_mb: None,
+            archive_fallback: false,
+            cache_pages: UserSettings::default_cache_pages(),
+            http_api_port: UserSettings::default_http_api_port(),
+            task_schedules: HashMap::new(),
+            worker_api: None,
+            remote_primary: None,
+            sync: None,
+            fuzzy_search: false,
+            ranking: RankingSettings::default(),
+            stemming_languages: UserSettings::default_stemming_languages(),
+            stop_words: None,
+            prefix_search: PrefixMatch::default(),
+            semantic_search: None,
+            feeds: Vec::new(),
+            proxy: None,
         }
     }
 }
 
 impl Config {
     pub fn save_plugin_settings(&self, settings: &PluginSettings) -> anyhow::Result<()> {
-        let prefs_path = self.plugin_settings_file();
+        for (name, values) in settings {
+            Self::save_settings_for_plugin(name, values)?;
+        }
+
+        Ok(())
+    }
+
+    /// Values the user has set for a plugin, empty if there are none.
+    pub fn load_settings_for_plugin(name: &str) -> anyhow::Result<HashMap<String, String>> {
+        let path = Self::plugin_settings_file(name);
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+
+        Ok(ron::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Only readable by the current user, settings can include API keys.
+    pub fn save_settings_for_plugin(
+        name: &str,
+        values: &HashMap<String, String>,
+    ) -> anyhow::Result<()> {
+        fs::create_dir_all(Self::plugin_settings_dir())?;
+        let path = Self::plugin_settings_file(name);
         fs::write(
-            prefs_path,
-            ron::ser::to_string_pretty(settings, Default::default())?,
-        )
-        .expect("Unable to save plugin settings file.");
+            &path,
+            ron::ser::to_string_pretty(values, Default::default())?,
+        )?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
 
         Ok(())
     }
 
     fn load_plugin_setings(&self) -> anyhow::Result<PluginSettings> {
-        let prefs_path = self.plugin_settings_file();
-        if prefs_path.exists() {
-            let settings: PluginSettings = ron::from_str(&fs::read_to_string(prefs_path)?)?;
-            return Ok(settings);
+        let mut settings = PluginSettings::new();
+        let dir = Self::plugin_settings_dir();
+        if dir.exists() {
+            for entry in fs::read_dir(dir)?.flatten() {
+                let path = entry.path();
+                if path.extension().and_then(|ext| ext.to_str()) != Some("ron") {
+                    continue;
+                }
+
+                if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                    settings.insert(name.to_string(), Self::load_settings_for_plugin(name)?);
+                }
+            }
         }
 
-        // Create default settings
-        let settings: PluginSettings = Default::default();
-        self.save_plugin_settings(&settings)?;
+        // Settings used to be in a single file in the plugins folder, move
+        // them over to their own files.
+        let legacy_path = self.legacy_plugin_settings_file();
+        if legacy_path.exists() {
+            let legacy: PluginSettings = ron::from_str(&fs::read_to_string(&legacy_path)?)?;
+            for (name, values) in legacy {
+                if !settings.contains_key(&name) {
+                    Self::save_settings_for_plugin(&name, &values)?;
+                    settings.insert(name, values);
+                }
+            }
+            fs::remove_file(legacy_path)?;
+        }
 
         Ok(settings)
     }
 
+    /// Credentials are kept out of the settings file so it can be shared w/o
+    /// leaking them. Empty if there's no credentials file. The file is plain
+    /// text, edited by hand & only as private as its permissions.
+    pub fn load_credentials() -> anyhow::Result<Credentials> {
+        let path = Self::credentials_file();
+        if !path.exists() {
+            return Ok(Credentials::new());
+        }
+
+        Ok(ron::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Token clients need to send w/ every RPC & HTTP API request, generated
+    /// the first time it's needed. Only readable by the current user.
+    pub fn api_token() -> anyhow::Result<String> {
+        Self::load_token(Self::api_token_file())
+    }
+
+    /// Read-only token for searching from a browser, only accepted by the
+    /// OpenSearch endpoints. It ends up in URLs & browser history, unlike
+    /// `api_token`.
+    pub fn search_token() -> anyhow::Result<String> {
+        Self::load_token(Self::search_token_file())
+    }
+
+    /// Reads the token at `path`, generating it if it doesn't exist yet.
+    fn load_token(path: PathBuf) -> anyhow::Result<String> {
+        fs::create_dir_all(Self::prefs_dir())?;
+
+        for _ in 0..API_TOKEN_ATTEMPTS {
+            match fs::read_to_string(&path) {
+                Ok(token) if !token.trim().is_empty() => return Ok(token.trim().to_string()),
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+
+            match options.open(&path) {
+                Ok(mut file) => {
+                    let token = Uuid::new_v4().simple().to_string();
+                    file.write_all(token.as_bytes())?;
+                    return Ok(token);
+                }
+                // Another process, e.g. the client starting alongside the
+                // backend, got there first. Read theirs once it's written.
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "Unable to read token from {}",
+            path.display()
+        ))
+    }
+
     fn load_user_settings() -> anyhow::Result<UserSettings> {
         let prefs_path = Self::prefs_file();
 
@@ -227,6 +784,36 @@ impl Config {
         self.data_dir().join("index")
     }
 
+    pub fn db_file(&self) -> PathBuf {
+        self.data_dir().join("db.sqlite")
+    }
+
+    /// Backup waiting to replace the database & index on the next startup.
+    pub fn restore_dir(&self) -> PathBuf {
+        self.data_dir().join("restore")
+    }
+
+    /// Written by the server on startup & removed on shutdown.
+    pub fn pid_file(&self) -> PathBuf {
+        self.data_dir().join("spyglass-server.pid")
+    }
+
+    /// Held by the running server, see `shared::instance`.
+    pub fn server_lock_file(&self) -> PathBuf {
+        self.data_dir().join("spyglass-server.lock")
+    }
+
+    /// Held by the running desktop app, see `shared::instance`.
+    pub fn app_lock_file(&self) -> PathBuf {
+        self.data_dir().join("spyglass-app.lock")
+    }
+
+    /// Periodically updated by the server so external tools (systemd, etc.)
+    /// can check whether it's still alive.
+    pub fn health_file(&self) -> PathBuf {
+        self.data_dir().join("spyglass-server.health")
+    }
+
     pub fn logs_dir() -> PathBuf {
         Self::default_data_dir().join("logs")
     }
@@ -242,11 +829,35 @@ impl Config {
         Self::prefs_dir().join("settings.ron")
     }
 
+    /// Per-domain credentials for the crawler, see `Credentials`.
+    pub fn credentials_file() -> PathBuf {
+        Self::prefs_dir().join("credentials.ron")
+    }
+
+    /// See `api_token`.
+    pub fn api_token_file() -> PathBuf {
+        Self::prefs_dir().join("api_token")
+    }
+
+    /// See `search_token`.
+    pub fn search_token_file() -> PathBuf {
+        Self::prefs_dir().join("search_token")
+    }
+
     pub fn plugins_dir(&self) -> PathBuf {
         self.data_dir().join("plugins")
     }
 
-    pub fn plugin_settings_file(&self) -> PathBuf {
+    /// Plugin settings set by the user, one file per plugin.
+    pub fn plugin_settings_dir() -> PathBuf {
+        Self::prefs_dir().join("plugins")
+    }
+
+    pub fn plugin_settings_file(name: &str) -> PathBuf {
+        Self::plugin_settings_dir().join(format!("{}.ron", name))
+    }
+
+    fn legacy_plugin_settings_file(&self) -> PathBuf {
         self.plugins_dir().join("settings.ron")
     }
 
@@ -254,6 +865,11 @@ impl Config {
         self.data_dir().join("lenses")
     }
 
+    /// Cached site icons, see `libspyglass::favicon`.
+    pub fn favicons_dir(&self) -> PathBuf {
+        self.data_dir().join("favicons")
+    }
+
     pub fn new() -> Self {
         let prefs_dir = Config::prefs_dir();
         fs::create_dir_all(&prefs_dir).expect("Unable to create config folder");
@@ -3,6 +3,8 @@ use strum_macros::{AsRefStr, Display};
 #[derive(AsRefStr, Display)]
 pub enum ClientEvent {
     ClearSearch,
+    /// Payload is the deep link another instance of the app was launched with.
+    DeepLink,
     FocusWindow,
     RefreshLensManager,
     RefreshPluginManager,
@@ -13,8 +15,8 @@ pub enum ClientEvent {
 pub enum ClientInvoke {
     #[strum(serialize = "escape")]
     Escape,
-    #[strum(serialize = "open_plugins_folder")]
-    EditPluginSettings,
+    #[strum(serialize = "app_status")]
+    GetAppStatus,
     #[strum(serialize = "crawl_stats")]
     GetCrawlStats,
     #[strum(serialize = "list_installed_lenses")]
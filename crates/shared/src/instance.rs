@@ -0,0 +1,215 @@
+//! Keeps a single instance of the server/app running. The first instance
+//! creates a lock file w/ the localhost port it listens on. Later instances
+//! find the lock, hand their activation off to the running instance & exit.
+//! A lock whose owner doesn't answer is left over from a crash & replaced.
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long to wait for a freshly created lock file to get its port.
+const LOCK_WRITE_RETRIES: usize = 10;
+const LOCK_WRITE_DELAY: Duration = Duration::from_millis(100);
+
+/// Why another instance was launched.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Activation {
+    /// Deep link the instance was launched with, e.g. `spyglass://search`.
+    pub deep_link: Option<String>,
+}
+
+pub enum Instance {
+    /// No other instance is running. The lock is held until dropped.
+    Primary(InstanceLock),
+    /// Another instance is running & has been handed the activation.
+    Secondary,
+}
+
+pub struct InstanceLock {
+    path: PathBuf,
+    listener: Option<TcpListener>,
+}
+
+impl InstanceLock {
+    /// Handle activations from other instances on a background thread.
+    pub fn listen<F>(&mut self, on_activate: F)
+    where
+        F: Fn(Activation) + Send + 'static,
+    {
+        let listener = match self.listener.take() {
+            Some(listener) => listener,
+            None => return,
+        };
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                match read_activation(&stream) {
+                    Ok(activation) => {
+                        let _ = (&stream).write_all(b"ok\n");
+                        on_activate(activation);
+                    }
+                    Err(err) => log::warn!("Invalid activation: {}", err),
+                }
+            }
+        });
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn read_activation(stream: &TcpStream) -> io::Result<Activation> {
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+}
+
+/// Port written to the lock file by the instance that owns it.
+fn read_port(path: &Path) -> Option<u16> {
+    for _ in 0..LOCK_WRITE_RETRIES {
+        let contents = fs::read_to_string(path).ok()?;
+        // pid & port, one per line
+        if let Some(port) = contents.lines().nth(1) {
+            return port.trim().parse().ok();
+        }
+
+        // Just created, the owner hasn't written it out yet.
+        std::thread::sleep(LOCK_WRITE_DELAY);
+    }
+
+    None
+}
+
+/// Hand `activation` off to the instance listening on `port`.
+fn send_activation(port: u16, activation: &Activation) -> io::Result<()> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+
+    let mut msg = serde_json::to_string(activation)?;
+    msg.push('\n');
+    stream.write_all(msg.as_bytes())?;
+
+    let mut resp = String::new();
+    BufReader::new(&stream).read_line(&mut resp)?;
+    if resp.trim() != "ok" {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "Unexpected response from running instance",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Become the primary instance, or hand `activation` off to the one that's
+/// already running.
+pub fn acquire(path: &Path, activation: &Activation) -> io::Result<Instance> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Second attempt is after clearing out a stale lock.
+    for _ in 0..2 {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+                let port = listener.local_addr()?.port();
+                writeln!(file, "{}\n{}", std::process::id(), port)?;
+
+                return Ok(Instance::Primary(InstanceLock {
+                    path: path.to_path_buf(),
+                    listener: Some(listener),
+                }));
+            }
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                if let Some(port) = read_port(path) {
+                    if send_activation(port, activation).is_ok() {
+                        return Ok(Instance::Secondary);
+                    }
+                }
+
+                log::warn!("Removing stale instance lock {}", path.display());
+                if let Err(err) = fs::remove_file(path) {
+                    if err.kind() != ErrorKind::NotFound {
+                        return Err(err);
+                    }
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(io::Error::new(
+        ErrorKind::Other,
+        "Unable to acquire instance lock",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::{acquire, Activation, Instance};
+
+    #[test]
+    fn test_single_instance() {
+        let path =
+            std::env::temp_dir().join(format!("spyglass-instance-{}.lock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut lock = match acquire(&path, &Activation::default()).unwrap() {
+            Instance::Primary(lock) => lock,
+            Instance::Secondary => panic!("Expected to be the primary instance"),
+        };
+
+        let (tx, rx) = mpsc::channel();
+        lock.listen(move |activation| {
+            let _ = tx.send(activation);
+        });
+
+        let activation = Activation {
+            deep_link: Some("spyglass://search".to_string()),
+        };
+        assert!(matches!(
+            acquire(&path, &activation).unwrap(),
+            Instance::Secondary
+        ));
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), activation);
+
+        // Lock is released on drop
+        drop(lock);
+        assert!(!path.exists());
+        assert!(matches!(
+            acquire(&path, &activation).unwrap(),
+            Instance::Primary(_)
+        ));
+    }
+
+    #[test]
+    fn test_stale_lock() {
+        let path = std::env::temp_dir().join(format!("spyglass-stale-{}.lock", std::process::id()));
+        // Nothing is listening on this port anymore
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        std::fs::write(&path, format!("1\n{}\n", port)).unwrap();
+
+        assert!(matches!(
+            acquire(&path, &Activation::default()).unwrap(),
+            Instance::Primary(_)
+        ));
+        assert!(!path.exists());
+    }
+}
@@ -1,5 +1,7 @@
+pub mod command;
 pub mod config;
 pub mod event;
+pub mod instance;
 pub mod request;
 pub mod response;
 pub mod rpc;
@@ -1,9 +1,88 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
+use crate::config::PrefixMatch;
+use crate::response::{PageMetadata, QueueItemStatus};
+
+/// Number of results returned when a search doesn't ask for a specific limit.
+pub const DEFAULT_SEARCH_LIMIT: usize = 5;
+
+/// How documents are matched against the query.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Match the words in the query.
+    Keyword,
+    /// Match documents similar in meaning to the query, requires the
+    /// `semantic_search` setting.
+    Semantic,
+    /// Blend keyword & semantic results.
+    Hybrid,
+}
+
+impl Default for SearchMode {
+    fn default() -> Self {
+        SearchMode::Keyword
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SearchParam {
     pub lenses: Vec<String>,
     pub query: String,
+    /// Number of results to skip, used to page through results.
+    #[serde(default)]
+    pub offset: usize,
+    /// Max number of results to return.
+    #[serde(default = "SearchParam::default_limit")]
+    pub limit: usize,
+    /// Match terms w/ typos in them. Uses the `fuzzy_search` user setting
+    /// when not set.
+    #[serde(default)]
+    pub fuzzy: Option<bool>,
+    /// Match partial words. Uses the `prefix_search` user setting when not set.
+    #[serde(default)]
+    pub prefix: Option<PrefixMatch>,
+    /// Only return documents indexed at or after this unix timestamp.
+    #[serde(default)]
+    pub after: Option<i64>,
+    /// Only return documents indexed before this unix timestamp.
+    #[serde(default)]
+    pub before: Option<i64>,
+    /// Also count the hits per domain & lens.
+    #[serde(default)]
+    pub facets: bool,
+    /// Only return documents in this language, e.g. `en`. Same as adding
+    /// `lang:en` to the query.
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// Falls back to keyword search when semantic search isn't enabled.
+    #[serde(default)]
+    pub mode: SearchMode,
+}
+
+impl SearchParam {
+    fn default_limit() -> usize {
+        DEFAULT_SEARCH_LIMIT
+    }
+}
+
+impl Default for SearchParam {
+    fn default() -> Self {
+        SearchParam {
+            lenses: Vec::new(),
+            query: String::new(),
+            offset: 0,
+            limit: DEFAULT_SEARCH_LIMIT,
+            fuzzy: None,
+            prefix: None,
+            after: None,
+            before: None,
+            facets: false,
+            lang: None,
+            mode: SearchMode::Keyword,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -11,13 +90,245 @@ pub struct SearchLensesParam {
     pub query: String,
 }
 
-#[derive(Debug, Deserialize)]
+/// A page that has already been fetched & rendered elsewhere (e.g. by the
+/// browser extension) and can be added directly to the index.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IndexPageParam {
+    pub url: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub content: String,
+}
+
+/// File format search results are exported in.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line.
+    JsonLines,
+    Csv,
+}
+
+impl Default for ExportFormat {
+    fn default() -> Self {
+        ExportFormat::JsonLines
+    }
+}
+
+/// Write all the results for a search to a file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExportResultsParam {
+    pub lenses: Vec<String>,
+    pub query: String,
+    /// File to write to, replaced if it already exists.
+    pub path: String,
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Update the settings of a plugin, an empty value resets a setting to its
+/// default.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetPluginSettingsParam {
+    pub name: String,
+    pub settings: HashMap<String, String>,
+}
+
+/// Remove an installed lens.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UninstallLensParam {
+    pub name: String,
+    /// Also remove the lens's queued & indexed pages, unless another lens
+    /// covers them too.
+    #[serde(default)]
+    pub purge: bool,
+}
+
+/// Remove everything we know about a domain, e.g. after blocklisting it.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteDomainParam {
+    pub domain: String,
+    /// Only count what would be removed.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct QueueItemParam {
     pub url: String,
+    #[serde(default)]
     pub force_crawl: bool,
+    /// Only follow links this many hops away from `url`. No limit when not set.
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+    /// Crawled before tasks w/ a lower priority. Defaults to ahead of lens
+    /// seeds & discovered links.
+    #[serde(default)]
+    pub priority: Option<i32>,
+}
+
+/// Add many URLs to the crawl queue at once, e.g. from an importer.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct BulkQueueParam {
+    pub urls: Vec<String>,
+    /// Applied to every URL, see `QueueItemParam`.
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+    #[serde(default)]
+    pub priority: Option<i32>,
+}
+
+/// Number of queued URLs returned when `ListQueueParam` doesn't ask for a
+/// specific limit.
+pub const DEFAULT_LIST_QUEUE_LIMIT: u64 = 100;
+
+/// Field the crawl queue is sorted by.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum QueueSort {
+    CreatedAt,
+    UpdatedAt,
+    Priority,
+    Url,
+}
+
+impl Default for QueueSort {
+    fn default() -> Self {
+        QueueSort::UpdatedAt
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ListQueueParam {
+    #[serde(default)]
+    pub status: Option<QueueItemStatus>,
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub sort: QueueSort,
+    /// Sorted in descending order unless set, e.g. most recently updated first.
+    #[serde(default)]
+    pub ascending: bool,
+    #[serde(default = "ListQueueParam::default_limit")]
+    pub limit: u64,
+    #[serde(default)]
+    pub offset: u64,
+}
+
+impl ListQueueParam {
+    fn default_limit() -> u64 {
+        DEFAULT_LIST_QUEUE_LIMIT
+    }
+}
+
+impl Default for ListQueueParam {
+    fn default() -> Self {
+        ListQueueParam {
+            status: None,
+            domain: None,
+            sort: QueueSort::default(),
+            ascending: false,
+            limit: DEFAULT_LIST_QUEUE_LIMIT,
+            offset: 0,
+        }
+    }
+}
+
+/// Drop URLs from the crawl queue matching all of the given filters. At least
+/// one filter is required.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RemoveQueueItemsParam {
+    #[serde(default)]
+    pub ids: Vec<i64>,
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub status: Option<QueueItemStatus>,
+}
+
+/// Number of crawl attempts returned when `RecentCrawlsParam` doesn't ask
+/// for a specific limit.
+pub const DEFAULT_RECENT_CRAWLS_LIMIT: u64 = 50;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RecentCrawlsParam {
+    /// Only show attempts for this domain.
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// Only show attempts that failed or were skipped.
+    #[serde(default)]
+    pub errors_only: bool,
+    #[serde(default = "RecentCrawlsParam::default_limit")]
+    pub limit: u64,
+}
+
+impl RecentCrawlsParam {
+    fn default_limit() -> u64 {
+        DEFAULT_RECENT_CRAWLS_LIMIT
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateStatusParam {
     pub toggle_pause: Option<bool>,
 }
+
+/// Sent by a remote crawl worker to ask for up to `limit` crawl tasks.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LeaseTasksParam {
+    pub limit: u64,
+}
+
+/// A page fetched & parsed by a remote crawl worker.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct CrawledPage {
+    /// URL that was actually fetched, e.g. the archived copy of a page.
+    pub fetch_url: String,
+    pub status: u16,
+    /// Canonical URL of the page.
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub content: Option<String>,
+    pub content_hash: Option<String>,
+    pub raw: Option<String>,
+    pub links: Vec<String>,
+    pub archived: bool,
+    /// Language the page is written in, e.g. `en`.
+    #[serde(default)]
+    pub lang: Option<String>,
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Cache validators from the response.
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    /// Size of the fetched body, for the domain stats.
+    #[serde(default)]
+    pub num_bytes: u64,
+    /// Time spent fetching the page, for the crawl log.
+    #[serde(default)]
+    pub duration_ms: u64,
+    /// Robots directives from the page, see `CrawlResult`.
+    #[serde(default)]
+    pub no_index: bool,
+    #[serde(default)]
+    pub no_follow: bool,
+    #[serde(default)]
+    pub metadata: PageMetadata,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum WorkerOutcome {
+    Crawled(CrawledPage),
+    /// Not allowed to crawl, e.g. due to robots.txt
+    Skipped,
+    Failed(String),
+}
+
+/// Sent by a remote crawl worker once it's done w/ a task.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WorkerResultParam {
+    pub task_id: i64,
+    pub outcome: WorkerOutcome,
+}
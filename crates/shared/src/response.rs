@@ -1,11 +1,32 @@
 use serde::{Deserialize, Serialize};
 
+use crate::config::PluginSettingSchema;
+
 #[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
 pub struct QueueStatus {
     pub num_queued: u64,
     pub num_processing: u64,
     pub num_completed: u64,
     pub num_indexed: u64,
+    #[serde(default)]
+    pub crawl: DomainCrawlStats,
+}
+
+/// How crawling a domain has gone so far. Times are RFC 3339 formatted.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct DomainCrawlStats {
+    pub num_fetched: u64,
+    pub num_client_errors: u64,
+    pub num_server_errors: u64,
+    pub num_network_errors: u64,
+    pub bytes_downloaded: u64,
+    pub last_crawled_at: Option<String>,
+}
+
+impl DomainCrawlStats {
+    pub fn num_errors(&self) -> u64 {
+        self.num_client_errors + self.num_server_errors + self.num_network_errors
+    }
 }
 
 impl QueueStatus {
@@ -14,10 +35,158 @@ impl QueueStatus {
     }
 }
 
+/// Status of a URL in the crawl queue.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum QueueItemStatus {
+    Queued,
+    Processing,
+    Completed,
+    Failed,
+}
+
+/// A URL in the crawl queue, see the `list_queue` RPC. Times are RFC 3339
+/// formatted.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct QueueItem {
+    pub id: i64,
+    pub domain: String,
+    pub url: String,
+    pub status: QueueItemStatus,
+    pub num_retries: u8,
+    pub next_retry_at: Option<String>,
+    pub depth: i32,
+    pub priority: i32,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Whether a URL sent to `add_queue_bulk` was added to the crawl queue, or
+/// why not.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct AddQueueResult {
+    pub url: String,
+    pub added: bool,
+    pub error: Option<String>,
+}
+
+/// Number of URLs in the crawl queue w/ each status.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct QueueCounts {
+    pub num_queued: u64,
+    pub num_processing: u64,
+    pub num_completed: u64,
+    pub num_failed: u64,
+}
+
+impl QueueCounts {
+    pub fn total(&self) -> u64 {
+        self.num_queued + self.num_processing + self.num_completed + self.num_failed
+    }
+}
+
+/// Pushed to `subscribe_status` subscribers as things change.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum StatusEvent {
+    /// Pages were crawled or queued.
+    CrawlProgress(QueueCounts),
+    /// Documents were added to or removed from the index.
+    IndexUpdated { num_docs: u64 },
+    /// Crawling was paused or resumed.
+    PauseToggled { is_paused: bool },
+    /// A plugin was enabled or disabled, or ran into an error.
+    Plugin {
+        name: String,
+        is_enabled: bool,
+        error: Option<String>,
+    },
+}
+
+/// A page of the crawl queue.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ListQueueResp {
+    pub items: Vec<QueueItem>,
+    /// Number of URLs matching the filters, across all pages.
+    pub num_items: u64,
+    /// Counts for the domain filter, regardless of the status filter.
+    pub counts: QueueCounts,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AppStatus {
     pub num_docs: u64,
     pub is_paused: bool,
+    /// Crawling is paused for just these domains & lenses.
+    #[serde(default)]
+    pub paused_domains: Vec<String>,
+    #[serde(default)]
+    pub paused_lenses: Vec<String>,
+    #[serde(default)]
+    pub memory: MemoryStats,
+    #[serde(default)]
+    pub pipeline: PipelineStatus,
+    #[serde(default)]
+    pub disk: DiskStats,
+    /// Number of indexed documents in each installed lens.
+    #[serde(default)]
+    pub lenses: Vec<LensDocCount>,
+    /// Domains w/ the most indexed documents, largest first.
+    #[serde(default)]
+    pub top_domains: Vec<DomainDocCount>,
+    #[serde(default)]
+    pub errors: CrawlErrorStats,
+}
+
+/// Disk usage, all sizes are in bytes.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct DiskStats {
+    pub index: u64,
+    pub db: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct LensDocCount {
+    pub name: String,
+    pub num_docs: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct DomainDocCount {
+    pub domain: String,
+    pub num_docs: u64,
+}
+
+/// Crawls that went wrong.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct CrawlErrorStats {
+    /// Queued URLs that ran out of retries.
+    pub num_failed: u64,
+    /// Failed attempts still in the crawl log.
+    pub num_logged: u64,
+}
+
+/// Number of jobs waiting on each stage of the crawl pipeline.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct PipelineStatus {
+    pub fetch_queue: u64,
+    pub parse_queue: u64,
+    pub index_queue: u64,
+}
+
+/// Memory usage, all sizes are in bytes.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct MemoryStats {
+    /// Resident set size of the server process.
+    pub rss: u64,
+    /// Memory budget of the index writer.
+    pub index_writer: u64,
+    /// Response bodies currently being crawled.
+    pub inflight_bodies: u64,
+    /// Linear memory used by plugin instances.
+    pub plugins: u64,
+    /// User configured limit, if any.
+    pub limit: Option<u64>,
+    /// Whether crawling is paused because we're over the limit.
+    pub is_throttled: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -45,6 +214,14 @@ pub struct LensResult {
     pub download_url: Option<String>,
 }
 
+/// A plugin setting & the value the user set for it, if any.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct PluginSettingResult {
+    pub name: String,
+    pub schema: PluginSettingSchema,
+    pub value: Option<String>,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
 pub struct PluginResult {
     pub author: String,
@@ -53,11 +230,117 @@ pub struct PluginResult {
     pub is_enabled: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// A crawl task handed out to a remote crawl worker.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct WorkerTask {
+    pub id: i64,
+    pub domain: String,
+    pub url: String,
+    /// Pull the page from the Internet Archive rather than the live site.
+    pub is_bootstrap: bool,
+}
+
+/// A single attempt at crawling a page, see the `recent_crawls` RPC. Times
+/// are RFC 3339 formatted.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct CrawlLogEntry {
+    pub domain: String,
+    pub url: String,
+    /// Not set if we never got a response.
+    pub status: Option<u16>,
+    pub duration_ms: u64,
+    pub num_bytes: u64,
+    pub error: Option<String>,
+    pub crawled_at: String,
+}
+
+/// What was removed by the `delete_domain` RPC, or would be on a dry run.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct DeleteDomainResult {
+    pub num_documents: u64,
+    pub num_queued: u64,
+    pub num_fetch_history: u64,
+}
+
+/// A background task run by the scheduler. Times are RFC 3339 formatted.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct TaskResult {
+    pub name: String,
+    pub schedule: String,
+    pub next_run: String,
+    pub last_run: Option<String>,
+    pub last_error: Option<String>,
+}
+
+/// On disk footprint of a single field, in bytes.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct FieldStats {
+    pub name: String,
+    /// Term dictionary, postings, positions, fast fields & norms.
+    pub indexed_bytes: u64,
+    /// Uncompressed size of the stored values, estimated from a sample of
+    /// documents.
+    pub stored_bytes: u64,
+}
+
+/// Index size broken down by field. Sizes are in bytes.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct IndexStats {
+    pub num_docs: u64,
+    pub num_segments: usize,
+    pub total_bytes: u64,
+    /// Compressed size of the doc store, shared by all stored fields.
+    pub store_bytes: u64,
+    pub fields: Vec<FieldStats>,
+}
+
+/// Type of instant answer shown above search results
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum InlineAnswerType {
+    Conversion,
+    Math,
+    Timestamp,
+}
+
+/// An instant answer computed directly from the query, e.g. a math expression
+/// or unit conversion.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct InlineAnswer {
+    pub answer_type: InlineAnswerType,
+    pub query: String,
+    pub answer: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct SearchMeta {
     pub query: String,
+    /// Number of documents in the index.
     pub num_docs: u64,
+    /// Total number of documents that matched, across all pages.
+    #[serde(default)]
+    pub num_hits: u64,
     pub wall_time_ms: u64,
+    #[serde(default)]
+    pub timings: SearchTimings,
+}
+
+/// Where the time in a search went, for debugging slow searches.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SearchTimings {
+    /// Parsing the query & applying filters.
+    pub parse_ms: u64,
+    /// Finding, scoring & counting the matching documents.
+    pub search_ms: u64,
+    /// Loading the returned documents along w/ their snippets & metadata.
+    pub fetch_ms: u64,
+}
+
+/// Part of a document's content around the terms that matched the query.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Snippet {
+    pub text: String,
+    /// Byte ranges of the matched terms within `text`.
+    pub highlights: Vec<(usize, usize)>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -68,12 +351,128 @@ pub struct SearchResult {
     pub description: String,
     pub url: String,
     pub score: f32,
+    /// Indexed from an archived snapshot since the original page is gone.
+    #[serde(default)]
+    pub is_archived: bool,
+    /// Content around the matched terms, if any of them were in the content.
+    #[serde(default)]
+    pub snippet: Option<Snippet>,
+    #[serde(default)]
+    pub metadata: PageMetadata,
+    /// Site icon as a data URI, if we have one for the domain.
+    #[serde(default)]
+    pub favicon: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Why a search returned what it did, see `explain_search`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct SearchExplanation {
+    /// Query text left after pulling out operators like `site:` & `lang:`.
+    pub query: String,
+    /// The query run against the index, in tantivy's debug format.
+    pub parsed_query: String,
+    pub sites: Vec<String>,
+    pub url_terms: Vec<String>,
+    pub langs: Vec<String>,
+    /// Applied lenses & what they filter on. Lenses that aren't installed
+    /// are left out.
+    pub lenses: Vec<LensFilter>,
+    /// Number of matching documents, across all pages.
+    pub num_docs: u64,
+    pub results: Vec<ResultExplanation>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct LensFilter {
+    pub name: String,
+    pub domains: Vec<String>,
+    pub urls: Vec<String>,
+    pub boost: f32,
+}
+
+/// How a result was scored. The final score is `text_score * lens_boost *
+/// recency_boost`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ResultExplanation {
+    pub doc_id: String,
+    pub url: String,
+    pub title: String,
+    pub score: f32,
+    pub text_score: f32,
+    pub lens_boost: f32,
+    pub recency_boost: f32,
+    /// Breakdown of the text score by query term.
+    pub explanation: ScoreExplanation,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ScoreExplanation {
+    pub value: f32,
+    pub description: String,
+    #[serde(default)]
+    pub details: Vec<ScoreExplanation>,
+}
+
+/// A single indexed document, see `get_document`. Dates are RFC 3339
+/// formatted.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct DocumentResult {
+    pub doc_id: String,
+    pub domain: String,
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    /// Full text content, as stored in the index.
+    pub content: String,
+    pub lang: Option<String>,
+    /// When the document was first indexed & last updated.
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    /// When the URL was last fetched.
+    pub last_fetched_at: Option<String>,
+    /// Set when indexed from an archived snapshot.
+    pub archived_at: Option<String>,
+    /// Installed lenses the document belongs to.
+    pub lenses: Vec<String>,
+    pub metadata: PageMetadata,
+}
+
+/// Metadata from a page's OpenGraph tags & schema.org markup, used to show
+/// richer results. Dates are RFC 3339 formatted.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct PageMetadata {
+    pub og_title: Option<String>,
+    pub og_description: Option<String>,
+    /// Absolute URL of the preview image.
+    pub og_image: Option<String>,
+    pub published_at: Option<String>,
+    /// schema.org types, e.g. `Article` or `Recipe`.
+    pub schema_types: Vec<String>,
+}
+
+/// Number of matching documents w/ a given facet value, e.g. a domain.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: u64,
+}
+
+/// Hits broken down by domain & lens, most hits first.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct SearchFacets {
+    pub domains: Vec<FacetCount>,
+    pub lenses: Vec<FacetCount>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct SearchResults {
+    #[serde(default)]
+    pub answer: Option<InlineAnswer>,
     pub results: Vec<SearchResult>,
     pub meta: SearchMeta,
+    /// Only set when asked for w/ `SearchParam::facets`.
+    #[serde(default)]
+    pub facets: Option<SearchFacets>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
@@ -1,9 +1,18 @@
 use jsonrpc_core::{BoxFuture, Result};
+use jsonrpc_core_client::TypedClient;
 use jsonrpc_derive::rpc;
+use jsonrpc_pubsub::{typed::Subscriber, SubscriptionId};
 
-use crate::request::{SearchLensesParam, SearchParam};
+use crate::config::Config;
+use crate::request::{
+    BulkQueueParam, DeleteDomainParam, ExportResultsParam, IndexPageParam, ListQueueParam,
+    QueueItemParam, RecentCrawlsParam, RemoveQueueItemsParam, SearchLensesParam, SearchParam,
+    SetPluginSettingsParam, UninstallLensParam,
+};
 use crate::response::{
-    AppStatus, CrawlStats, LensResult, PluginResult, SearchLensesResp, SearchResults,
+    AddQueueResult, AppStatus, CrawlLogEntry, CrawlStats, DeleteDomainResult, DocumentResult,
+    IndexStats, LensResult, ListQueueResp, PluginResult, PluginSettingResult, SearchExplanation,
+    SearchLensesResp, SearchResult, SearchResults, StatusEvent, TaskResult,
 };
 
 pub fn gen_ipc_path() -> String {
@@ -14,6 +23,19 @@ pub fn gen_ipc_path() -> String {
     }
 }
 
+/// Must be called w/ the API token before anything else on a connection.
+pub const AUTHENTICATE_METHOD: &str = "authenticate";
+
+/// Authenticates a new connection w/ the API token, see `Config::api_token`.
+pub async fn authenticate(client: &TypedClient) -> anyhow::Result<()> {
+    let token = Config::api_token()?;
+    client
+        .call_method::<(String,), bool>(AUTHENTICATE_METHOD, "bool", (token,))
+        .await?;
+
+    Ok(())
+}
+
 /// Rpc trait
 #[rpc]
 pub trait Rpc {
@@ -21,17 +43,52 @@ pub trait Rpc {
     #[rpc(name = "protocol_version")]
     fn protocol_version(&self) -> Result<String>;
 
+    #[rpc(name = "add_queue")]
+    fn add_queue(&self, queue_item: QueueItemParam) -> BoxFuture<Result<String>>;
+
+    #[rpc(name = "add_queue_bulk")]
+    fn add_queue_bulk(&self, param: BulkQueueParam) -> BoxFuture<Result<Vec<AddQueueResult>>>;
+
     #[rpc(name = "app_status")]
     fn app_status(&self) -> BoxFuture<Result<AppStatus>>;
 
     #[rpc(name = "crawl_stats")]
     fn crawl_stats(&self) -> BoxFuture<Result<CrawlStats>>;
 
-    #[rpc(name = "delete_doc")]
-    fn delete_doc(&self, id: String) -> BoxFuture<Result<()>>;
+    /// Snapshot the database & index into a zip archive at `path`. Returns
+    /// the size of the archive in bytes.
+    #[rpc(name = "create_backup")]
+    fn create_backup(&self, path: String) -> BoxFuture<Result<u64>>;
+
+    #[rpc(name = "delete_document")]
+    fn delete_document(&self, doc_id: String) -> BoxFuture<Result<()>>;
 
     #[rpc(name = "delete_domain")]
-    fn delete_domain(&self, domain: String) -> BoxFuture<Result<()>>;
+    fn delete_domain(&self, param: DeleteDomainParam) -> BoxFuture<Result<DeleteDomainResult>>;
+
+    /// Same as `search_docs`, but explains how each result was scored.
+    #[rpc(name = "explain_search")]
+    fn explain_search(&self, query: SearchParam) -> BoxFuture<Result<SearchExplanation>>;
+
+    #[rpc(name = "export_results")]
+    fn export_results(&self, param: ExportResultsParam) -> BoxFuture<Result<u64>>;
+
+    /// A single document w/ its full text, crawl timestamps & lenses.
+    #[rpc(name = "get_document")]
+    fn get_document(&self, doc_id: String) -> BoxFuture<Result<DocumentResult>>;
+
+    #[rpc(name = "index_page")]
+    fn index_page(&self, page: IndexPageParam) -> BoxFuture<Result<String>>;
+
+    #[rpc(name = "index_optimize")]
+    fn index_optimize(&self) -> BoxFuture<Result<()>>;
+
+    #[rpc(name = "index_stats")]
+    fn index_stats(&self) -> BoxFuture<Result<IndexStats>>;
+
+    /// Install a lens from a URL or, by name, from the lens directory.
+    #[rpc(name = "install_lens")]
+    fn install_lens(&self, name_or_url: String) -> BoxFuture<Result<LensResult>>;
 
     #[rpc(name = "list_installed_lenses")]
     fn list_installed_lenses(&self) -> BoxFuture<Result<Vec<LensResult>>>;
@@ -39,8 +96,50 @@ pub trait Rpc {
     #[rpc(name = "list_plugins")]
     fn list_plugins(&self) -> BoxFuture<Result<Vec<PluginResult>>>;
 
+    #[rpc(name = "list_queue")]
+    fn list_queue(&self, param: ListQueueParam) -> BoxFuture<Result<ListQueueResp>>;
+
+    #[rpc(name = "list_tasks")]
+    fn list_tasks(&self) -> BoxFuture<Result<Vec<TaskResult>>>;
+
+    #[rpc(name = "pause_domain")]
+    fn pause_domain(&self, domain: String) -> BoxFuture<Result<AppStatus>>;
+
+    #[rpc(name = "pause_lens")]
+    fn pause_lens(&self, name: String) -> BoxFuture<Result<AppStatus>>;
+
+    /// Settings a plugin takes & the values the user set for them.
+    #[rpc(name = "plugin_settings")]
+    fn plugin_settings(&self, name: String) -> BoxFuture<Result<Vec<PluginSettingResult>>>;
+
+    #[rpc(name = "query_suggestions")]
+    fn query_suggestions(&self, prefix: String) -> BoxFuture<Result<Vec<String>>>;
+
+    #[rpc(name = "recent_crawls")]
+    fn recent_crawls(&self, param: RecentCrawlsParam) -> BoxFuture<Result<Vec<CrawlLogEntry>>>;
+
     #[rpc(name = "recrawl_domain")]
-    fn recrawl_domain(&self, domain: String) -> BoxFuture<Result<()>>;
+    fn recrawl_domain(&self, domain: String) -> BoxFuture<Result<u64>>;
+
+    #[rpc(name = "recrawl_url")]
+    fn recrawl_url(&self, url: String) -> BoxFuture<Result<u64>>;
+
+    #[rpc(name = "reindex")]
+    fn reindex(&self) -> BoxFuture<Result<()>>;
+
+    #[rpc(name = "remove_queue_items")]
+    fn remove_queue_items(&self, param: RemoveQueueItemsParam) -> BoxFuture<Result<u64>>;
+
+    /// Replace the database & index w/ a backup from `create_backup`. Takes
+    /// effect the next time the server starts.
+    #[rpc(name = "restore_backup")]
+    fn restore_backup(&self, path: String) -> BoxFuture<Result<()>>;
+
+    #[rpc(name = "resume_domain")]
+    fn resume_domain(&self, domain: String) -> BoxFuture<Result<AppStatus>>;
+
+    #[rpc(name = "resume_lens")]
+    fn resume_lens(&self, name: String) -> BoxFuture<Result<AppStatus>>;
 
     #[rpc(name = "search_docs")]
     fn search_docs(&self, query: SearchParam) -> BoxFuture<Result<SearchResults>>;
@@ -48,9 +147,38 @@ pub trait Rpc {
     #[rpc(name = "search_lenses")]
     fn search_lenses(&self, query: SearchLensesParam) -> BoxFuture<Result<SearchLensesResp>>;
 
+    /// Save the settings of a plugin & restart it w/ them.
+    #[rpc(name = "set_plugin_settings")]
+    fn set_plugin_settings(&self, param: SetPluginSettingsParam) -> BoxFuture<Result<()>>;
+
+    /// Documents related to `doc_id`, most similar first.
+    #[rpc(name = "similar")]
+    fn similar(&self, doc_id: String) -> BoxFuture<Result<Vec<SearchResult>>>;
+
     #[rpc(name = "toggle_pause")]
     fn toggle_pause(&self) -> BoxFuture<Result<AppStatus>>;
 
     #[rpc(name = "toggle_plugin")]
     fn toggle_plugin(&self, name: String) -> BoxFuture<Result<()>>;
+
+    /// Returns the number of documents removed.
+    #[rpc(name = "uninstall_lens")]
+    fn uninstall_lens(&self, param: UninstallLensParam) -> BoxFuture<Result<u64>>;
+
+    /// False if the lens is already up to date.
+    #[rpc(name = "update_lens")]
+    fn update_lens(&self, name: String) -> BoxFuture<Result<bool>>;
+}
+
+/// Status updates pushed to clients as they happen, so they don't have to poll
+/// `app_status`.
+#[rpc(server)]
+pub trait StatusRpc {
+    type Metadata;
+
+    #[pubsub(subscription = "status", subscribe, name = "subscribe_status")]
+    fn subscribe_status(&self, meta: Self::Metadata, subscriber: Subscriber<StatusEvent>);
+
+    #[pubsub(subscription = "status", unsubscribe, name = "unsubscribe_status")]
+    fn unsubscribe_status(&self, meta: Option<Self::Metadata>, id: SubscriptionId) -> Result<bool>;
 }
@@ -22,6 +22,15 @@ macro_rules! register_plugin {
                 state.borrow_mut().update();
             })
         }
+
+        #[no_mangle]
+        pub fn on_event() {
+            if let Ok(event) = $crate::object_from_stdin::<$crate::PluginEventPayload>() {
+                STATE.with(|state| {
+                    state.borrow_mut().on_event(event);
+                })
+            }
+        }
     };
 }
 pub trait SpyglassPlugin {
@@ -30,12 +39,48 @@ pub trait SpyglassPlugin {
     fn load(&self);
     /// Request plugin for updates
     fn update(&self);
+    /// Called w/ the details of events the plugin subscribed to.
+    fn on_event(&self, _event: PluginEventPayload) {}
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
 pub enum PluginEvent {
     /// Check for updates at a fixed interval
     CheckUpdateInterval,
+    /// A document was added to or updated in the index
+    DocumentIndexed,
+    /// A crawl failed & won't be retried
+    CrawlFailed,
+    /// A lens was installed
+    LensInstalled,
+}
+
+/// Sent to `SpyglassPlugin::on_event` for events the plugin subscribed to.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum PluginEventPayload {
+    DocumentIndexed {
+        doc_id: String,
+        url: String,
+        title: String,
+    },
+    CrawlFailed {
+        url: String,
+        error: String,
+    },
+    LensInstalled {
+        name: String,
+    },
+}
+
+impl PluginEventPayload {
+    /// Event to subscribe to for this payload.
+    pub fn event(&self) -> PluginEvent {
+        match self {
+            PluginEventPayload::DocumentIndexed { .. } => PluginEvent::DocumentIndexed,
+            PluginEventPayload::CrawlFailed { .. } => PluginEvent::CrawlFailed,
+            PluginEventPayload::LensInstalled { .. } => PluginEvent::LensInstalled,
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -45,13 +90,96 @@ pub enum PluginCommandRequest {
     SqliteQuery { path: String, query: String },
 }
 
+/// Document added straight to the index, see `add_document`. Needs the
+/// `add_documents` permission in the plugin manifest.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PluginAddDocumentRequest {
+    /// Unique URL for the document, using the plugin name as the scheme,
+    /// e.g. `pocket-importer://item/123`.
+    pub url: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub content: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    /// RFC 3339 timestamp.
+    #[serde(default)]
+    pub published_at: Option<String>,
+    /// Detected from the content if not set.
+    #[serde(default)]
+    pub lang: Option<String>,
+}
+
+/// Read-only query against a SQLite file in the plugin data folder, see
+/// `sqlite_query`.
+#[derive(Deserialize, Serialize)]
+pub struct PluginSqliteQueryRequest {
+    /// Relative to the plugin data folder.
+    pub path: String,
+    pub query: String,
+    /// Bound to the `?` placeholders in the query.
+    #[serde(default)]
+    pub params: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum PluginSqliteValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl PluginSqliteValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PluginSqliteValue::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            PluginSqliteValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct PluginSqliteRows {
+    pub columns: Vec<String>,
+    /// Values are in the same order as `columns`.
+    pub rows: Vec<Vec<PluginSqliteValue>>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct PluginMountRequest {
     pub dst: String,
     pub src: String,
 }
 
+/// HTTP GET made by the host on behalf of a plugin, see `http_get`.
+#[derive(Deserialize, Serialize)]
+pub struct PluginFetchRequest {
+    pub url: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PluginFetchResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct PluginEnqueueRequest {
     pub urls: Vec<String>,
+    /// Priority of the new crawl tasks, defaults to that of discovered links.
+    #[serde(default)]
+    pub priority: Option<i32>,
 }
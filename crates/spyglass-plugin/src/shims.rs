@@ -1,7 +1,11 @@
 use serde::{de::DeserializeOwned, Serialize};
 use std::io;
 
-use crate::{PluginCommandRequest, PluginEnqueueRequest, PluginEvent, PluginMountRequest};
+use crate::{
+    PluginAddDocumentRequest, PluginCommandRequest, PluginEnqueueRequest, PluginEvent,
+    PluginFetchRequest, PluginFetchResponse, PluginMountRequest, PluginSqliteQueryRequest,
+    PluginSqliteRows,
+};
 
 pub fn subscribe(event: PluginEvent) {
     if object_to_stdout(&PluginCommandRequest::Subscribe(event)).is_ok() {
@@ -11,10 +15,32 @@ pub fn subscribe(event: PluginEvent) {
     }
 }
 
+/// Add / update a document in the index w/o going through the crawler, for
+/// data that doesn't live at a crawlable URL. Needs the `add_documents`
+/// permission in the plugin manifest. Returns the document id.
+pub fn add_document(doc: &PluginAddDocumentRequest) -> Result<String, String> {
+    object_to_stdout(doc).map_err(|err| err.to_string())?;
+
+    unsafe {
+        plugin_add_document();
+    }
+    object_from_stdin::<Result<String, String>>().map_err(|err| err.to_string())?
+}
+
 /// Add an item to the Spyglass crawl queue
 pub fn enqueue_all(urls: &[String]) {
+    enqueue(urls, None);
+}
+
+/// Add items to the crawl queue, crawled before tasks w/ a lower priority.
+pub fn enqueue_all_with_priority(urls: &[String], priority: i32) {
+    enqueue(urls, Some(priority));
+}
+
+fn enqueue(urls: &[String], priority: Option<i32>) {
     if object_to_stdout(&PluginEnqueueRequest {
         urls: urls.to_owned(),
+        priority,
     })
     .is_ok()
     {
@@ -24,6 +50,29 @@ pub fn enqueue_all(urls: &[String]) {
     }
 }
 
+/// HTTP GET a URL. Its host needs to be listed in the `fetch` permissions of
+/// the plugin manifest.
+pub fn http_get(url: &str) -> Result<PluginFetchResponse, String> {
+    http_get_with_headers(url, &[])
+}
+
+/// Same as `http_get`, w/ extra request headers, e.g. for API tokens.
+pub fn http_get_with_headers(
+    url: &str,
+    headers: &[(String, String)],
+) -> Result<PluginFetchResponse, String> {
+    object_to_stdout(&PluginFetchRequest {
+        url: url.to_string(),
+        headers: headers.to_owned(),
+    })
+    .map_err(|err| err.to_string())?;
+
+    unsafe {
+        plugin_fetch();
+    }
+    object_from_stdin::<Result<PluginFetchResponse, String>>().map_err(|err| err.to_string())?
+}
+
 /// List dir
 pub fn list_dir(path: &str) -> Result<Vec<String>, ron::Error> {
     if object_to_stdout(&PluginCommandRequest::ListDir(path.to_string())).is_ok() {
@@ -44,8 +93,30 @@ pub fn log(msg: String) {
     }
 }
 
+/// Run a read-only query against a SQLite file in the plugin data folder, e.g.
+/// browser history copied over w/ `sync_file`. `params` are bound to the `?`
+/// placeholders in the query.
+pub fn sqlite_query(
+    path: &str,
+    query: &str,
+    params: &[String],
+) -> Result<PluginSqliteRows, String> {
+    object_to_stdout(&PluginSqliteQueryRequest {
+        path: path.to_string(),
+        query: query.to_string(),
+        params: params.to_owned(),
+    })
+    .map_err(|err| err.to_string())?;
+
+    unsafe {
+        plugin_sqlite_query();
+    }
+    object_from_stdin::<Result<PluginSqliteRows, String>>().map_err(|err| err.to_string())?
+}
+
 /// Hacky workaround until rusqlite can compile to wasm easily.
-/// Path is expected to be rooted in the plugins data directory.
+/// Path is expected to be rooted in the plugins data directory. Only returns
+/// the first column, see `sqlite_query` for full rows.
 pub fn sqlite3_query(path: &str, query: &str) -> Result<Vec<String>, ron::Error> {
     if object_to_stdout(&PluginCommandRequest::SqliteQuery {
         path: path.to_string(),
@@ -60,7 +131,8 @@ pub fn sqlite3_query(path: &str, query: &str) -> Result<Vec<String>, ron::Error>
     Ok(Vec::new())
 }
 
-/// Adds / updates a file in the plugin VFS from the host.
+/// Adds / updates a file in the plugin VFS from the host. `dst` is the folder
+/// it's copied into, e.g. `/data` or `/data/profile`.
 pub fn sync_file(dst: String, src: String) {
     if object_to_stdout(&PluginMountRequest { dst, src }).is_ok() {
         unsafe {
@@ -71,9 +143,12 @@ pub fn sync_file(dst: String, src: String) {
 
 #[link(wasm_import_module = "spyglass")]
 extern "C" {
+    fn plugin_add_document();
     fn plugin_cmd();
     fn plugin_enqueue();
+    fn plugin_fetch();
     fn plugin_log();
+    fn plugin_sqlite_query();
     fn plugin_sync_file();
 }
 
@@ -0,0 +1,193 @@
+//! API token checks. RPC connections authenticate once w/ `authenticate`,
+//! HTTP requests send the token along w/ each request. Browser searches use a
+//! separate read-only token, see `has_search_token`.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use hyper::{Body, Request};
+use jsonrpc_core::futures::future::Either;
+use jsonrpc_core::{
+    Call, Error, ErrorCode, FutureOutput, FutureResponse, Metadata, Middleware, Output, Value,
+};
+use jsonrpc_pubsub::{PubSubMetadata, Session};
+
+use shared::rpc::AUTHENTICATE_METHOD;
+
+/// Per-connection RPC state.
+#[derive(Clone, Default)]
+pub struct RpcMeta {
+    session: Option<Arc<Session>>,
+    authenticated: Arc<AtomicBool>,
+}
+
+impl RpcMeta {
+    pub fn new(session: Arc<Session>) -> Self {
+        RpcMeta {
+            session: Some(session),
+            ..Default::default()
+        }
+    }
+}
+
+impl Metadata for RpcMeta {}
+
+impl PubSubMetadata for RpcMeta {
+    fn session(&self) -> Option<Arc<Session>> {
+        self.session.clone()
+    }
+}
+
+fn auth_error(message: &str) -> Error {
+    Error {
+        code: ErrorCode::ServerError(401),
+        message: message.into(),
+        data: None,
+    }
+}
+
+/// Handles `authenticate` & rejects everything else until the connection is
+/// authenticated.
+pub struct AuthMiddleware {
+    pub token: String,
+}
+
+impl Middleware<RpcMeta> for AuthMiddleware {
+    type Future = FutureResponse;
+    type CallFuture = FutureOutput;
+
+    fn on_call<F, X>(&self, call: Call, meta: RpcMeta, next: F) -> Either<Self::CallFuture, X>
+    where
+        F: Fn(Call, RpcMeta) -> X + Send + Sync,
+        X: std::future::Future<Output = Option<Output>> + Send + 'static,
+    {
+        let is_authenticated = meta.authenticated.load(Ordering::SeqCst);
+        match &call {
+            Call::MethodCall(method) if method.method == AUTHENTICATE_METHOD => {
+                let res = match method.params.clone().parse::<(String,)>() {
+                    Ok((token,)) if token_eq(&token, &self.token) => {
+                        meta.authenticated.store(true, Ordering::SeqCst);
+                        Ok(Value::Bool(true))
+                    }
+                    Ok(_) => Err(auth_error("Invalid API token")),
+                    Err(err) => Err(err),
+                };
+
+                let output = Output::from(res, method.id.clone(), method.jsonrpc);
+                Either::Left(Box::pin(async move { Some(output) }))
+            }
+            Call::MethodCall(method) if !is_authenticated => {
+                let output = Output::from(
+                    Err(auth_error("Not authenticated")),
+                    method.id.clone(),
+                    method.jsonrpc,
+                );
+                Either::Left(Box::pin(async move { Some(output) }))
+            }
+            // Notifications don't get a response, drop them.
+            Call::Notification(_) if !is_authenticated => {
+                Either::Left(Box::pin(async move { None }))
+            }
+            _ => Either::Right(next(call, meta)),
+        }
+    }
+}
+
+/// Compares w/o stopping at the first mismatch, so response times don't give
+/// away how much of a guess was right.
+fn token_eq(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Checks for the API token in the `Authorization: Bearer` header. The token
+/// is never accepted in the URL, see `has_search_token` for browser links.
+pub fn is_authorized(req: &Request<Body>, token: &str) -> bool {
+    req.headers()
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|value| token_eq(value, token))
+        .unwrap_or(false)
+}
+
+/// Checks for the read-only search token in the `token` query param, for
+/// links opened in a browser. Only the OpenSearch endpoints accept it.
+pub fn has_search_token(req: &Request<Body>, search_token: &str) -> bool {
+    url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+        .any(|(key, value)| key == "token" && token_eq(&value, search_token))
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::{Body, Request};
+    use jsonrpc_core::{MetaIoHandler, Value};
+
+    use super::{has_search_token, is_authorized, token_eq, AuthMiddleware, RpcMeta};
+
+    #[test]
+    fn test_auth_middleware() {
+        let mut io = MetaIoHandler::with_middleware(AuthMiddleware {
+            token: "secret".into(),
+        });
+        io.add_sync_method("ping", |_| Ok(Value::Bool(true)));
+
+        let meta = RpcMeta::default();
+        let ping = r#"{"jsonrpc":"2.0","method":"ping","id":1}"#;
+        let resp = io.handle_request_sync(ping, meta.clone()).unwrap();
+        assert!(resp.contains("Not authenticated"));
+
+        let auth = r#"{"jsonrpc":"2.0","method":"authenticate","params":["wrong"],"id":2}"#;
+        let resp = io.handle_request_sync(auth, meta.clone()).unwrap();
+        assert!(resp.contains("Invalid API token"));
+
+        let auth = r#"{"jsonrpc":"2.0","method":"authenticate","params":["secret"],"id":3}"#;
+        let resp = io.handle_request_sync(auth, meta.clone()).unwrap();
+        assert!(resp.contains(r#""result":true"#));
+
+        let resp = io.handle_request_sync(ping, meta).unwrap();
+        assert!(resp.contains(r#""result":true"#));
+        // Other connections still need to authenticate
+        let resp = io.handle_request_sync(ping, RpcMeta::default()).unwrap();
+        assert!(resp.contains("Not authenticated"));
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let req = Request::get("/api/status")
+            .header("authorization", "Bearer secret")
+            .body(Body::empty())
+            .unwrap();
+        assert!(is_authorized(&req, "secret"));
+        assert!(!is_authorized(&req, "other"));
+
+        // Never from the URL
+        let req = Request::get("/search?q=rust&token=secret")
+            .body(Body::empty())
+            .unwrap();
+        assert!(!is_authorized(&req, "secret"));
+    }
+
+    #[test]
+    fn test_has_search_token() {
+        let req = Request::get("/search?q=rust&token=search")
+            .body(Body::empty())
+            .unwrap();
+        assert!(has_search_token(&req, "search"));
+        assert!(!has_search_token(&req, "secret"));
+
+        let req = Request::get("/search?q=rust").body(Body::empty()).unwrap();
+        assert!(!has_search_token(&req, "search"));
+    }
+
+    #[test]
+    fn test_token_eq() {
+        assert!(token_eq("secret", "secret"));
+        assert!(!token_eq("secreT", "secret"));
+        assert!(!token_eq("secret1", "secret"));
+        assert!(!token_eq("", "secret"));
+    }
+}
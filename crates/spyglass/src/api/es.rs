@@ -0,0 +1,180 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::json;
+use tokio::sync::broadcast;
+
+use libspyglass::search::es::{self, EsSearchRequest};
+use libspyglass::state::AppState;
+use libspyglass::task::AppShutdown;
+
+use super::auth;
+use super::http::read_body;
+
+/// Version we report to clients, some libraries refuse to talk to anything
+/// older than 7.x.
+const ES_COMPAT_VERSION: &str = "7.10.0";
+/// Max size of a search request body.
+const MAX_REQUEST_BYTES: usize = 1024 * 1024;
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap_or_default()
+}
+
+fn error_response(status: StatusCode, reason: &str) -> Response<Body> {
+    json_response(
+        status,
+        json!({
+            "error": { "type": "query_parsing_exception", "reason": reason },
+            "status": status.as_u16(),
+        }),
+    )
+}
+
+/// Builds a search request from URI params (`q`, `size`, `from`) and the
+/// request body, if any. Body params take precedence.
+fn parse_search_request(query: Option<&str>, body: &[u8]) -> anyhow::Result<EsSearchRequest> {
+    let mut request: EsSearchRequest = if body.iter().all(|b| b.is_ascii_whitespace()) {
+        EsSearchRequest::default()
+    } else {
+        serde_json::from_slice(body)?
+    };
+
+    if let Some(query) = query {
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            match key.as_ref() {
+                "q" if request.query.is_none() => {
+                    request.query = Some(json!({ "query_string": { "query": value } }));
+                }
+                "size" if request.size.is_none() => request.size = value.parse().ok(),
+                "from" if request.from.is_none() => request.from = value.parse().ok(),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(request)
+}
+
+#[tracing::instrument(skip(state, api_token, req), fields(method = %req.method(), path = %req.uri().path()))]
+async fn handle(
+    state: AppState,
+    api_token: String,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    if !auth::is_authorized(&req, &api_token) {
+        return Ok(error_response(
+            StatusCode::UNAUTHORIZED,
+            "Missing or invalid API token",
+        ));
+    }
+
+    let path = req.uri().path().to_string();
+    let query = req.uri().query().map(|q| q.to_string());
+    let method = req.method().clone();
+
+    // Cluster info, used by most clients to check the connection.
+    if path == "/" && (method == Method::GET || method == Method::HEAD) {
+        return Ok(json_response(
+            StatusCode::OK,
+            json!({
+                "name": "spyglass",
+                "cluster_name": "spyglass",
+                "version": { "number": ES_COMPAT_VERSION },
+                "tagline": "You Know, for Search",
+            }),
+        ));
+    }
+
+    // Supports both `/_search` & `/<index>/_search`
+    if !path.ends_with("/_search") || !(method == Method::GET || method == Method::POST) {
+        return Ok(error_response(
+            StatusCode::NOT_FOUND,
+            "Unsupported endpoint",
+        ));
+    }
+
+    let body = match read_body(req.into_body(), MAX_REQUEST_BYTES).await {
+        Ok(body) => body,
+        Err(err) => return Ok(error_response(StatusCode::BAD_REQUEST, &err.to_string())),
+    };
+
+    let request = match parse_search_request(query.as_deref(), &body) {
+        Ok(request) => request,
+        Err(err) => return Ok(error_response(StatusCode::BAD_REQUEST, &err.to_string())),
+    };
+
+    match es::search(&state.index.index, &state.index.reader, &request) {
+        Ok(resp) => match serde_json::to_value(resp) {
+            Ok(body) => Ok(json_response(StatusCode::OK, body)),
+            Err(err) => Ok(error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &err.to_string(),
+            )),
+        },
+        Err(err) => Ok(error_response(StatusCode::BAD_REQUEST, &err.to_string())),
+    }
+}
+
+/// Serve the Elasticsearch-compatible `_search` endpoint on localhost until
+/// the app shuts down. Requests need to include `api_token` as a bearer token.
+pub async fn start_es_api(
+    state: AppState,
+    api_token: String,
+    port: u16,
+    mut shutdown_rx: broadcast::Receiver<AppShutdown>,
+) {
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let builder = match Server::try_bind(&addr) {
+        Ok(builder) => builder,
+        Err(err) => {
+            log::error!("Unable to start ES API on {}: {}", addr, err);
+            return;
+        }
+    };
+
+    let make_svc = make_service_fn(move |_conn| {
+        let state = state.clone();
+        let api_token = api_token.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(state.clone(), api_token.clone(), req)
+            }))
+        }
+    });
+
+    log::info!("ES API listening on http://{}", addr);
+    let server = builder.serve(make_svc).with_graceful_shutdown(async move {
+        let _ = shutdown_rx.recv().await;
+    });
+
+    if let Err(err) = server.await {
+        log::error!("ES API error: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_search_request;
+
+    #[test]
+    fn test_parse_search_request() {
+        let request = parse_search_request(Some("q=rust&size=5"), b"").unwrap();
+        assert_eq!(request.size, Some(5));
+        assert!(request.query.is_some());
+
+        let request =
+            parse_search_request(Some("size=5"), br#"{ "size": 20, "from": 10 }"#).unwrap();
+        assert_eq!(request.size, Some(20));
+        assert_eq!(request.from, Some(10));
+        assert!(request.query.is_none());
+
+        assert!(parse_search_request(None, b"{ invalid").is_err());
+    }
+}
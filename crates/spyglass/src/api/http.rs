@@ -0,0 +1,328 @@
+//! Local HTTP server for things that are easier to hand off to a browser than
+//! to push through JSON-RPC, e.g. viewing the cached copy of a page. Also
+//! serves the REST API, see `rest`, OpenSearch endpoints, see `opensearch`, &
+//! health checks/metrics, see `metrics`.
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::body::HttpBody;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use libspyglass::search::Searcher;
+use libspyglass::state::AppState;
+use libspyglass::task::AppShutdown;
+
+use super::{auth, metrics, opensearch, rest};
+
+const CACHE_PREFIX: &str = "/cache/";
+/// How long a link to a cached page stays valid, see `CacheGrants`.
+const CACHE_GRANT_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// One-time grants to view a cached page. Cached pages are opened in a browser,
+/// so links carry one of these instead of the API token.
+#[derive(Clone, Default)]
+pub(super) struct CacheGrants {
+    grants: Arc<Mutex<HashMap<String, (String, Instant)>>>,
+}
+
+impl CacheGrants {
+    /// Link to the cached copy of `doc_id` that can be opened once.
+    pub(super) fn link(&self, doc_id: &str) -> String {
+        let grant = Uuid::new_v4().simple().to_string();
+        if let Ok(mut grants) = self.grants.lock() {
+            grants.retain(|_, (_, issued)| issued.elapsed() < CACHE_GRANT_TTL);
+            grants.insert(grant.clone(), (doc_id.to_string(), Instant::now()));
+        }
+
+        format!("{}{}?grant={}", CACHE_PREFIX, doc_id, grant)
+    }
+
+    /// Uses up `grant`, true if it was issued for `doc_id` & hasn't expired.
+    fn redeem(&self, doc_id: &str, grant: &str) -> bool {
+        let granted = match self.grants.lock() {
+            Ok(mut grants) => grants.remove(grant),
+            Err(_) => None,
+        };
+
+        matches!(granted, Some((id, issued)) if id == doc_id && issued.elapsed() < CACHE_GRANT_TTL)
+    }
+}
+
+/// Reads a request body, giving up once it goes past `limit` bytes rather than
+/// buffering whatever the client sends.
+pub(super) async fn read_body(mut body: Body, limit: usize) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > limit {
+            return Err(anyhow::anyhow!("Request body is over {} bytes", limit));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(bytes)
+}
+
+pub(super) fn html_response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "text/html; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap_or_default()
+}
+
+pub(super) fn query_param(req: &Request<Body>, key: &str) -> Option<String> {
+    url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.to_string())
+}
+
+pub(super) fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Position right after the opening `tag`, e.g. `<head ...>`, if there is one.
+fn after_open_tag(html: &str, tag: &str) -> Option<usize> {
+    let lower = html.to_ascii_lowercase();
+    let needle = format!("<{}", tag);
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find(&needle) {
+        let start = from + pos;
+        let rest = &lower[start + needle.len()..];
+        // Make sure we matched `<head>` and not `<header>`
+        if rest.starts_with('>') || rest.starts_with(char::is_whitespace) {
+            return rest.find('>').map(|end| start + needle.len() + end + 1);
+        }
+        from = start + needle.len();
+    }
+
+    None
+}
+
+/// Wraps the cached HTML of a page w/ a banner pointing back to the original.
+/// Relative links & assets are resolved against the original URL.
+fn render_cached_page(url: &str, title: &str, description: &str, raw: &str) -> String {
+    let url = html_escape(url);
+    let banner = format!(
+        r#"<div style="all:initial;display:block;position:sticky;top:0;z-index:2147483647;padding:8px 12px;background:#1c1917;color:#f5f5f4;font:14px sans-serif;">Cached copy of <a style="color:#7dd3fc;" href="{url}">{url}</a></div>"#,
+        url = url
+    );
+
+    if raw.trim().is_empty() {
+        return format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title}</title></head><body>{banner}<h1>{title}</h1><p>{description}</p><p><em>No cached copy was stored for this page.</em></p></body></html>",
+            title = html_escape(title),
+            description = html_escape(description),
+            banner = banner
+        );
+    }
+
+    let mut page = raw.to_string();
+    // Insert the banner first so the <head> offset stays valid.
+    match after_open_tag(&page, "body") {
+        Some(pos) => page.insert_str(pos, &banner),
+        None => page.insert_str(0, &banner),
+    }
+
+    let base = format!(r#"<base href="{}">"#, url);
+    match after_open_tag(&page, "head") {
+        Some(pos) => page.insert_str(pos, &base),
+        None => page.insert_str(0, &base),
+    }
+
+    page
+}
+
+/// Cached pages are arbitrary HTML served from the same origin as the API. The
+/// sandbox gives them an opaque origin & keeps their scripts from running.
+fn cached_page_response(page: String) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "text/html; charset=utf-8")
+        .header("content-security-policy", "sandbox")
+        .header("referrer-policy", "no-referrer")
+        .body(Body::from(page))
+        .unwrap_or_default()
+}
+
+fn cached_page(state: &AppState, doc_id: &str) -> Option<String> {
+    let doc = Searcher::get_by_id(&state.index.reader, doc_id)?;
+    let fields = Searcher::doc_fields();
+    let text = |field| {
+        doc.get_first(field)
+            .and_then(|value| value.as_text())
+            .unwrap_or_default()
+    };
+
+    Some(render_cached_page(
+        text(fields.url),
+        text(fields.title),
+        text(fields.description),
+        text(fields.raw),
+    ))
+}
+
+#[tracing::instrument(skip(state, api_token, search_token, grants, req), fields(method = %req.method(), path = %req.uri().path()))]
+async fn handle(
+    state: AppState,
+    api_token: String,
+    search_token: String,
+    grants: CacheGrants,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    // Liveness probes generally can't send the token.
+    if req.uri().path() == metrics::HEALTH_PATH {
+        return Ok(metrics::health(&state).await);
+    }
+
+    // Cached pages are authorized by their grant rather than the token.
+    if req.method() == Method::GET && req.uri().path().starts_with(CACHE_PREFIX) {
+        let doc_id = req.uri().path().trim_start_matches(CACHE_PREFIX);
+        let grant = query_param(&req, "grant").unwrap_or_default();
+        if !grants.redeem(doc_id, &grant) {
+            return Ok(html_response(
+                StatusCode::UNAUTHORIZED,
+                "Unauthorized".into(),
+            ));
+        }
+
+        return Ok(match cached_page(&state, doc_id) {
+            Some(page) => cached_page_response(page),
+            None => html_response(StatusCode::NOT_FOUND, "Document not found".into()),
+        });
+    }
+
+    // Browsers can't send headers, so searches from one use the read-only
+    // search token in the URL instead.
+    let is_search = req.method() == Method::GET
+        && opensearch::is_search_path(req.uri().path())
+        && auth::has_search_token(&req, &search_token);
+    if !is_search && !auth::is_authorized(&req, &api_token) {
+        return Ok(html_response(
+            StatusCode::UNAUTHORIZED,
+            "Unauthorized".into(),
+        ));
+    }
+
+    let path = req.uri().path();
+    if path.starts_with(rest::API_PREFIX) {
+        return Ok(rest::handle(state, req).await);
+    }
+
+    if let Some(resp) = opensearch::handle(state.clone(), &search_token, &grants, &req).await {
+        return Ok(resp);
+    }
+
+    if let Some(resp) = metrics::handle(state.clone(), &req).await {
+        return Ok(resp);
+    }
+
+    // Hands out a one-time link to the cached copy, see `CacheGrants`.
+    if req.method() == Method::POST && path.starts_with(CACHE_PREFIX) {
+        let doc_id = path.trim_start_matches(CACHE_PREFIX);
+        return Ok(Response::builder()
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(Body::from(grants.link(doc_id)))
+            .unwrap_or_default());
+    }
+
+    Ok(html_response(StatusCode::NOT_FOUND, "Not found".into()))
+}
+
+/// Serve the local HTTP endpoints on localhost until the app shuts down.
+/// Requests need to include `api_token`, see `auth::is_authorized`, except for
+/// cached pages which use a one-time grant, see `CacheGrants`, & OpenSearch
+/// endpoints which also accept `search_token`.
+pub async fn start_http_api(
+    state: AppState,
+    api_token: String,
+    search_token: String,
+    port: u16,
+    mut shutdown_rx: broadcast::Receiver<AppShutdown>,
+) {
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let builder = match Server::try_bind(&addr) {
+        Ok(builder) => builder,
+        Err(err) => {
+            log::error!("Unable to start HTTP API on {}: {}", addr, err);
+            return;
+        }
+    };
+
+    let grants = CacheGrants::default();
+    let make_svc = make_service_fn(move |_conn| {
+        let state = state.clone();
+        let api_token = api_token.clone();
+        let search_token = search_token.clone();
+        let grants = grants.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(
+                    state.clone(),
+                    api_token.clone(),
+                    search_token.clone(),
+                    grants.clone(),
+                    req,
+                )
+            }))
+        }
+    });
+
+    log::info!("HTTP API listening on http://{}", addr);
+    let server = builder.serve(make_svc).with_graceful_shutdown(async move {
+        let _ = shutdown_rx.recv().await;
+    });
+
+    if let Err(err) = server.await {
+        log::error!("HTTP API error: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render_cached_page, CacheGrants};
+
+    #[test]
+    fn test_render_cached_page() {
+        let page = render_cached_page(
+            "https://example.com/a?b=1&c=2",
+            "Example",
+            "",
+            "<html><HEAD><title>Example</title></HEAD><header></header><body class=\"x\"><p>hi</p></body></html>",
+        );
+
+        assert!(page.contains(r#"<HEAD><base href="https://example.com/a?b=1&amp;c=2"><title>"#));
+        assert!(page.contains(r#"<body class="x"><div"#));
+        assert!(page.contains("<header></header>"));
+
+        let page = render_cached_page("https://example.com", "<Example>", "desc", "");
+        assert!(page.contains("<h1>&lt;Example&gt;</h1>"));
+        assert!(page.contains("No cached copy"));
+    }
+
+    #[test]
+    fn test_cache_grants() {
+        let grants = CacheGrants::default();
+        let link = grants.link("abc");
+        let grant = link.strip_prefix("/cache/abc?grant=").unwrap();
+
+        assert!(!grants.redeem("other", grant));
+        // Failed attempts use up the grant too
+        assert!(!grants.redeem("abc", grant));
+
+        let link = grants.link("abc");
+        let grant = link.strip_prefix("/cache/abc?grant=").unwrap();
+        assert!(grants.redeem("abc", grant));
+        assert!(!grants.redeem("abc", grant));
+        assert!(!grants.redeem("abc", ""));
+    }
+}
@@ -0,0 +1,287 @@
+//! Health check & Prometheus metrics for keeping an eye on always-on/headless
+//! instances. `/healthz` works w/o the API token so it can be used as a
+//! liveness probe, `/metrics` needs it, e.g. via Prometheus' `authorization`
+//! scrape setting.
+use std::fmt::Write;
+
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+use entities::models::crawl_queue::{self, CrawlStatus};
+use entities::sea_orm::{ConnectionTrait, DbBackend, Statement};
+use libspyglass::memory;
+use libspyglass::state::AppState;
+use shared::response::{MemoryStats, PipelineStatus, PluginResult};
+
+use super::route;
+
+pub const HEALTH_PATH: &str = "/healthz";
+const METRICS_PATH: &str = "/metrics";
+
+/// Snapshot of everything exported on `/metrics`.
+#[derive(Debug, Default)]
+struct Metrics {
+    /// Number of URLs in the crawl queue, by status.
+    crawl_queue: Vec<(String, u64)>,
+    pipeline: PipelineStatus,
+    num_docs: u64,
+    pending_writes: u64,
+    is_paused: bool,
+    memory: MemoryStats,
+    plugins: Vec<PluginResult>,
+}
+
+async fn collect(state: AppState) -> Metrics {
+    let mut crawl_queue = Vec::new();
+    for status in [
+        CrawlStatus::Queued,
+        CrawlStatus::Processing,
+        CrawlStatus::Completed,
+        CrawlStatus::Failed,
+    ] {
+        match crawl_queue::num_queued(&state.db, status.clone()).await {
+            Ok(count) => crawl_queue.push((status.to_string().to_lowercase(), count)),
+            Err(err) => log::error!("Unable to count {} URLs: {}", status, err),
+        }
+    }
+
+    let is_paused = state
+        .app_state
+        .get("paused")
+        .map(|paused| *paused == *"true")
+        .unwrap_or_default();
+
+    Metrics {
+        crawl_queue,
+        pipeline: state.pipeline.status(),
+        num_docs: state.index.reader.searcher().num_docs(),
+        pending_writes: state.index.num_pending() as u64,
+        is_paused,
+        memory: memory::stats(&state),
+        plugins: route::list_plugins(state).await.unwrap_or_default(),
+    }
+}
+
+/// Label values can't contain raw backslashes, quotes or newlines.
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+/// Writes the `# HELP` & `# TYPE` lines for a metric.
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Metrics in the Prometheus text exposition format.
+fn render(metrics: &Metrics) -> String {
+    let mut out = String::new();
+
+    describe(
+        &mut out,
+        "spyglass_crawl_queue",
+        "gauge",
+        "URLs in the crawl queue by status.",
+    );
+    for (status, count) in &metrics.crawl_queue {
+        let _ = writeln!(
+            out,
+            "spyglass_crawl_queue{{status=\"{}\"}} {}",
+            label_value(status),
+            count
+        );
+    }
+
+    let inflight = metrics
+        .crawl_queue
+        .iter()
+        .find(|(status, _)| status == "processing")
+        .map(|(_, count)| *count)
+        .unwrap_or_default();
+    describe(
+        &mut out,
+        "spyglass_crawls_inflight",
+        "gauge",
+        "Crawls currently being fetched, parsed or indexed.",
+    );
+    let _ = writeln!(out, "spyglass_crawls_inflight {}", inflight);
+
+    describe(
+        &mut out,
+        "spyglass_pipeline_queue",
+        "gauge",
+        "Crawls waiting on each stage of the crawl pipeline.",
+    );
+    for (stage, count) in [
+        ("fetch", metrics.pipeline.fetch_queue),
+        ("parse", metrics.pipeline.parse_queue),
+        ("index", metrics.pipeline.index_queue),
+    ] {
+        let _ = writeln!(
+            out,
+            "spyglass_pipeline_queue{{stage=\"{}\"}} {}",
+            stage, count
+        );
+    }
+
+    describe(
+        &mut out,
+        "spyglass_crawling_paused",
+        "gauge",
+        "1 if crawling is paused.",
+    );
+    let _ = writeln!(out, "spyglass_crawling_paused {}", metrics.is_paused as u8);
+
+    describe(
+        &mut out,
+        "spyglass_index_docs",
+        "gauge",
+        "Documents in the index.",
+    );
+    let _ = writeln!(out, "spyglass_index_docs {}", metrics.num_docs);
+
+    describe(
+        &mut out,
+        "spyglass_index_pending_writes",
+        "gauge",
+        "Index adds & deletes that have not been committed yet.",
+    );
+    let _ = writeln!(
+        out,
+        "spyglass_index_pending_writes {}",
+        metrics.pending_writes
+    );
+
+    describe(
+        &mut out,
+        "spyglass_memory_bytes",
+        "gauge",
+        "Memory usage in bytes.",
+    );
+    for (kind, bytes) in [
+        ("rss", metrics.memory.rss),
+        ("index_writer", metrics.memory.index_writer),
+        ("inflight_bodies", metrics.memory.inflight_bodies),
+        ("plugins", metrics.memory.plugins),
+    ] {
+        let _ = writeln!(out, "spyglass_memory_bytes{{kind=\"{}\"}} {}", kind, bytes);
+    }
+    if let Some(limit) = metrics.memory.limit {
+        describe(
+            &mut out,
+            "spyglass_memory_limit_bytes",
+            "gauge",
+            "User configured memory limit in bytes.",
+        );
+        let _ = writeln!(out, "spyglass_memory_limit_bytes {}", limit);
+    }
+
+    describe(
+        &mut out,
+        "spyglass_memory_throttled",
+        "gauge",
+        "1 if crawling is paused because memory usage is over the limit.",
+    );
+    let _ = writeln!(
+        out,
+        "spyglass_memory_throttled {}",
+        metrics.memory.is_throttled as u8
+    );
+
+    describe(
+        &mut out,
+        "spyglass_plugin_enabled",
+        "gauge",
+        "1 if the plugin is enabled.",
+    );
+    for plugin in &metrics.plugins {
+        let _ = writeln!(
+            out,
+            "spyglass_plugin_enabled{{plugin=\"{}\"}} {}",
+            label_value(&plugin.title),
+            plugin.is_enabled as u8
+        );
+    }
+
+    out
+}
+
+fn text_response(status: StatusCode, content_type: &str, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", content_type)
+        .body(Body::from(body))
+        .unwrap_or_default()
+}
+
+/// Ok as long as the database is reachable.
+pub async fn health(state: &AppState) -> Response<Body> {
+    let check = state
+        .db
+        .execute(Statement::from_string(
+            DbBackend::Sqlite,
+            "SELECT 1".to_string(),
+        ))
+        .await;
+
+    match check {
+        Ok(_) => text_response(StatusCode::OK, "text/plain", "ok".into()),
+        Err(err) => text_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "text/plain",
+            format!("database unavailable: {}", err),
+        ),
+    }
+}
+
+/// Handles `/metrics`, `None` for any other request.
+pub async fn handle(state: AppState, req: &Request<Body>) -> Option<Response<Body>> {
+    if req.method() != Method::GET || req.uri().path() != METRICS_PATH {
+        return None;
+    }
+
+    let metrics = collect(state).await;
+    Some(text_response(
+        StatusCode::OK,
+        "text/plain; version=0.0.4",
+        render(&metrics),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use shared::response::{MemoryStats, PluginResult};
+
+    use super::{render, Metrics};
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics {
+            crawl_queue: vec![("queued".into(), 10), ("processing".into(), 2)],
+            num_docs: 100,
+            memory: MemoryStats {
+                rss: 1024,
+                ..Default::default()
+            },
+            plugins: vec![PluginResult {
+                author: "spyglass".into(),
+                title: "local-\"file\"-indexer".into(),
+                description: "".into(),
+                is_enabled: true,
+            }],
+            ..Default::default()
+        };
+
+        let text = render(&metrics);
+        assert!(text.contains("# TYPE spyglass_crawl_queue gauge\n"));
+        assert!(text.contains("spyglass_crawl_queue{status=\"queued\"} 10\n"));
+        assert!(text.contains("spyglass_crawls_inflight 2\n"));
+        assert!(text.contains("spyglass_index_docs 100\n"));
+        assert!(text.contains("spyglass_memory_bytes{kind=\"rss\"} 1024\n"));
+        assert!(text.contains("spyglass_plugin_enabled{plugin=\"local-\\\"file\\\"-indexer\"} 1\n"));
+        // No limit configured
+        assert!(!text.contains("spyglass_memory_limit_bytes"));
+    }
+}
@@ -1,19 +1,48 @@
 extern crate jsonrpc_ipc_server;
 
-use jsonrpc_core::{BoxFuture, IoHandler, Result};
-use jsonrpc_ipc_server::{Server, ServerBuilder};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use jsonrpc_core::{BoxFuture, Error, ErrorCode, MetaIoHandler, Result};
+use jsonrpc_ipc_server::{RequestContext, Server, ServerBuilder};
+use jsonrpc_pubsub::{typed::Subscriber, PubSubHandler, Session, SubscriptionId};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
 
 use libspyglass::state::AppState;
 
-use shared::request::{SearchLensesParam, SearchParam};
-use shared::response::{AppStatus, CrawlStats, LensResult, SearchLensesResp, SearchResults};
-use shared::rpc::{gen_ipc_path, Rpc};
+use shared::config::Config;
+use shared::request::{
+    BulkQueueParam, DeleteDomainParam, ExportResultsParam, IndexPageParam, ListQueueParam,
+    QueueItemParam, RecentCrawlsParam, RemoveQueueItemsParam, SearchLensesParam, SearchParam,
+    SetPluginSettingsParam, UninstallLensParam,
+};
+use shared::response::{
+    AddQueueResult, AppStatus, CrawlLogEntry, CrawlStats, DeleteDomainResult, DocumentResult,
+    LensResult, ListQueueResp, PluginSettingResult, SearchExplanation, SearchLensesResp,
+    SearchResult, SearchResults, StatusEvent,
+};
+use shared::rpc::{gen_ipc_path, Rpc, StatusRpc};
+
+use auth::{AuthMiddleware, RpcMeta};
 
-mod response;
+mod auth;
+mod es;
+mod http;
+mod metrics;
+mod opensearch;
+mod rest;
 mod route;
+mod worker;
+
+pub use es::start_es_api;
+pub use http::start_http_api;
+pub use worker::start_worker_api;
 
 pub struct SpyglassRPC {
     state: AppState,
+    config: Config,
 }
 
 impl Rpc for SpyglassRPC {
@@ -21,6 +50,14 @@ impl Rpc for SpyglassRPC {
         Ok("version1".into())
     }
 
+    fn add_queue(&self, queue_item: QueueItemParam) -> BoxFuture<Result<String>> {
+        Box::pin(route::add_queue(self.state.clone(), queue_item))
+    }
+
+    fn add_queue_bulk(&self, param: BulkQueueParam) -> BoxFuture<Result<Vec<AddQueueResult>>> {
+        Box::pin(route::add_queue_bulk(self.state.clone(), param))
+    }
+
     fn app_status(&self) -> BoxFuture<Result<AppStatus>> {
         Box::pin(route::app_status(self.state.clone()))
     }
@@ -29,12 +66,52 @@ impl Rpc for SpyglassRPC {
         Box::pin(route::crawl_stats(self.state.clone()))
     }
 
-    fn delete_doc(&self, id: String) -> BoxFuture<Result<()>> {
-        Box::pin(route::delete_doc(self.state.clone(), id))
+    fn create_backup(&self, path: String) -> BoxFuture<Result<u64>> {
+        Box::pin(route::create_backup(
+            self.state.clone(),
+            self.config.clone(),
+            path,
+        ))
+    }
+
+    fn delete_document(&self, doc_id: String) -> BoxFuture<Result<()>> {
+        Box::pin(route::delete_document(self.state.clone(), doc_id))
+    }
+
+    fn delete_domain(&self, param: DeleteDomainParam) -> BoxFuture<Result<DeleteDomainResult>> {
+        Box::pin(route::delete_domain(self.state.clone(), param))
+    }
+
+    fn explain_search(&self, query: SearchParam) -> BoxFuture<Result<SearchExplanation>> {
+        Box::pin(route::explain_search(self.state.clone(), query))
+    }
+
+    fn export_results(&self, param: ExportResultsParam) -> BoxFuture<Result<u64>> {
+        Box::pin(route::export_results(self.state.clone(), param))
+    }
+
+    fn get_document(&self, doc_id: String) -> BoxFuture<Result<DocumentResult>> {
+        Box::pin(route::get_document(self.state.clone(), doc_id))
+    }
+
+    fn index_page(&self, page: IndexPageParam) -> BoxFuture<Result<String>> {
+        Box::pin(route::index_page(self.state.clone(), page))
+    }
+
+    fn index_optimize(&self) -> BoxFuture<Result<()>> {
+        Box::pin(route::index_optimize(self.state.clone()))
+    }
+
+    fn index_stats(&self) -> BoxFuture<Result<shared::response::IndexStats>> {
+        Box::pin(route::index_stats(self.state.clone()))
     }
 
-    fn delete_domain(&self, domain: String) -> BoxFuture<Result<()>> {
-        Box::pin(route::delete_domain(self.state.clone(), domain))
+    fn install_lens(&self, name_or_url: String) -> BoxFuture<Result<LensResult>> {
+        Box::pin(route::install_lens(
+            self.state.clone(),
+            self.config.clone(),
+            name_or_url,
+        ))
     }
 
     fn list_installed_lenses(&self) -> BoxFuture<Result<Vec<LensResult>>> {
@@ -45,10 +122,62 @@ impl Rpc for SpyglassRPC {
         Box::pin(route::list_plugins(self.state.clone()))
     }
 
-    fn recrawl_domain(&self, domain: String) -> BoxFuture<Result<()>> {
+    fn list_queue(&self, param: ListQueueParam) -> BoxFuture<Result<ListQueueResp>> {
+        Box::pin(route::list_queue(self.state.clone(), param))
+    }
+
+    fn list_tasks(&self) -> BoxFuture<Result<Vec<shared::response::TaskResult>>> {
+        Box::pin(route::list_tasks(self.state.clone()))
+    }
+
+    fn pause_domain(&self, domain: String) -> BoxFuture<Result<AppStatus>> {
+        Box::pin(route::pause_domain(self.state.clone(), domain))
+    }
+
+    fn pause_lens(&self, name: String) -> BoxFuture<Result<AppStatus>> {
+        Box::pin(route::pause_lens(self.state.clone(), name))
+    }
+
+    fn plugin_settings(&self, name: String) -> BoxFuture<Result<Vec<PluginSettingResult>>> {
+        Box::pin(route::plugin_settings(self.config.clone(), name))
+    }
+
+    fn query_suggestions(&self, prefix: String) -> BoxFuture<Result<Vec<String>>> {
+        Box::pin(route::query_suggestions(self.state.clone(), prefix))
+    }
+
+    fn recent_crawls(&self, param: RecentCrawlsParam) -> BoxFuture<Result<Vec<CrawlLogEntry>>> {
+        Box::pin(route::recent_crawls(self.state.clone(), param))
+    }
+
+    fn recrawl_domain(&self, domain: String) -> BoxFuture<Result<u64>> {
         Box::pin(route::recrawl_domain(self.state.clone(), domain))
     }
 
+    fn recrawl_url(&self, url: String) -> BoxFuture<Result<u64>> {
+        Box::pin(route::recrawl_url(self.state.clone(), url))
+    }
+
+    fn reindex(&self) -> BoxFuture<Result<()>> {
+        Box::pin(route::reindex(self.state.clone()))
+    }
+
+    fn remove_queue_items(&self, param: RemoveQueueItemsParam) -> BoxFuture<Result<u64>> {
+        Box::pin(route::remove_queue_items(self.state.clone(), param))
+    }
+
+    fn restore_backup(&self, path: String) -> BoxFuture<Result<()>> {
+        Box::pin(route::restore_backup(self.config.clone(), path))
+    }
+
+    fn resume_domain(&self, domain: String) -> BoxFuture<Result<AppStatus>> {
+        Box::pin(route::resume_domain(self.state.clone(), domain))
+    }
+
+    fn resume_lens(&self, name: String) -> BoxFuture<Result<AppStatus>> {
+        Box::pin(route::resume_lens(self.state.clone(), name))
+    }
+
     fn search_docs(&self, query: SearchParam) -> BoxFuture<Result<SearchResults>> {
         Box::pin(route::search(self.state.clone(), query))
     }
@@ -57,6 +186,18 @@ impl Rpc for SpyglassRPC {
         Box::pin(route::search_lenses(self.state.clone(), query))
     }
 
+    fn set_plugin_settings(&self, param: SetPluginSettingsParam) -> BoxFuture<Result<()>> {
+        Box::pin(route::set_plugin_settings(
+            self.state.clone(),
+            self.config.clone(),
+            param,
+        ))
+    }
+
+    fn similar(&self, doc_id: String) -> BoxFuture<Result<Vec<SearchResult>>> {
+        Box::pin(route::similar(self.state.clone(), doc_id))
+    }
+
     fn toggle_pause(&self) -> BoxFuture<Result<AppStatus>> {
         Box::pin(route::toggle_pause(self.state.clone()))
     }
@@ -64,21 +205,110 @@ impl Rpc for SpyglassRPC {
     fn toggle_plugin(&self, name: String) -> BoxFuture<Result<()>> {
         Box::pin(route::toggle_plugin(self.state.clone(), name))
     }
+
+    fn uninstall_lens(&self, param: UninstallLensParam) -> BoxFuture<Result<u64>> {
+        Box::pin(route::uninstall_lens(
+            self.state.clone(),
+            self.config.clone(),
+            param,
+        ))
+    }
+
+    fn update_lens(&self, name: String) -> BoxFuture<Result<bool>> {
+        Box::pin(route::update_lens(
+            self.state.clone(),
+            self.config.clone(),
+            name,
+        ))
+    }
+}
+
+/// Forwards status events to each `subscribe_status` subscriber.
+pub struct StatusSubscriptions {
+    state: AppState,
+    active: Arc<DashMap<SubscriptionId, JoinHandle<()>>>,
+    next_id: AtomicU64,
+}
+
+impl StatusRpc for StatusSubscriptions {
+    type Metadata = RpcMeta;
+
+    fn subscribe_status(&self, _: Self::Metadata, subscriber: Subscriber<StatusEvent>) {
+        let id = SubscriptionId::Number(self.next_id.fetch_add(1, Ordering::SeqCst));
+        let sink = match subscriber.assign_id(id.clone()) {
+            Ok(sink) => sink,
+            // Client went away before we could respond
+            Err(_) => return,
+        };
+
+        let mut events = self.state.status_events.subscribe();
+        let active = self.active.clone();
+        let sub_id = id.clone();
+        let handle = tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        if sink.notify(Ok(event)).is_err() {
+                            break;
+                        }
+                    }
+                    // Slow subscriber, skip what it missed.
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+
+            active.remove(&sub_id);
+        });
+
+        self.active.insert(id, handle);
+    }
+
+    fn unsubscribe_status(&self, _: Option<Self::Metadata>, id: SubscriptionId) -> Result<bool> {
+        match self.active.remove(&id) {
+            Some((_, handle)) => {
+                handle.abort();
+                Ok(true)
+            }
+            None => Err(Error {
+                code: ErrorCode::InvalidParams,
+                message: "Invalid subscription id".into(),
+                data: None,
+            }),
+        }
+    }
 }
 
-pub fn start_api_ipc(state: &AppState) -> anyhow::Result<Server, ()> {
+/// Only accepts requests on connections authenticated w/ `api_token`.
+pub fn start_api_ipc(
+    state: &AppState,
+    config: &Config,
+    api_token: &str,
+) -> anyhow::Result<Server, ()> {
     let endpoint = gen_ipc_path();
 
-    let mut io = IoHandler::new();
+    let mut io = PubSubHandler::new(MetaIoHandler::with_middleware(AuthMiddleware {
+        token: api_token.to_string(),
+    }));
     let rpc = SpyglassRPC {
         state: state.clone(),
+        config: config.clone(),
     };
     io.extend_with(rpc.to_delegate());
 
-    let server = ServerBuilder::new(io)
-        .start(&endpoint)
-        .map_err(|_| log::warn!("Couldn't open socket"))
-        .unwrap();
+    let status = StatusSubscriptions {
+        state: state.clone(),
+        active: Arc::new(DashMap::new()),
+        next_id: AtomicU64::new(0),
+    };
+    io.extend_with(status.to_delegate());
+
+    let server = ServerBuilder::with_meta_extractor(io, |context: &RequestContext| {
+        RpcMeta::new(Arc::new(Session::new(context.sender.clone())))
+    })
+    .start(&endpoint)
+    .map_err(|_| log::warn!("Couldn't open socket"))
+    .unwrap();
 
     log::info!("Started IPC server at {}", endpoint);
     Ok(server)
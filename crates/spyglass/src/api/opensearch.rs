@@ -0,0 +1,209 @@
+//! OpenSearch support so browsers can add spyglass as a search engine. Open
+//! `/search?token=<search token>` & the browser offers to add it, searches &
+//! typed suggestions then go through `/search` & `/suggest`. These links use
+//! the read-only search token, see `Config::search_token`, never the API token.
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde_json::json;
+
+use libspyglass::state::AppState;
+use shared::request::SearchParam;
+use shared::response::SearchResults;
+
+use super::http::{html_escape, html_response, query_param, CacheGrants};
+use super::route;
+
+const DESCRIPTION_PATH: &str = "/opensearch.xml";
+const SEARCH_PATH: &str = "/search";
+const SUGGEST_PATH: &str = "/suggest";
+
+/// Whether `path` is one of the endpoints accepting the search token.
+pub(super) fn is_search_path(path: &str) -> bool {
+    matches!(path, DESCRIPTION_PATH | SEARCH_PATH | SUGGEST_PATH)
+}
+
+/// Scheme & host the request was made to, so the links we hand out point
+/// back at the same port.
+fn base_url(req: &Request<Body>) -> String {
+    let host = req
+        .headers()
+        .get(hyper::header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or("127.0.0.1");
+    format!("http://{}", host)
+}
+
+fn description(base_url: &str, search_token: &str) -> String {
+    let base_url = html_escape(base_url);
+    let token = html_escape(search_token);
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/" xmlns:moz="http://www.mozilla.org/2006/browser/search/">
+  <ShortName>Spyglass</ShortName>
+  <Description>Search your Spyglass library</Description>
+  <InputEncoding>UTF-8</InputEncoding>
+  <Url type="text/html" method="get" template="{base_url}{search}?q={{searchTerms}}&amp;token={token}"/>
+  <Url type="application/x-suggestions+json" method="get" template="{base_url}{suggest}?q={{searchTerms}}&amp;token={token}"/>
+  <moz:SearchForm>{base_url}{search}?token={token}</moz:SearchForm>
+</OpenSearchDescription>
+"#,
+        base_url = base_url,
+        token = token,
+        search = SEARCH_PATH,
+        suggest = SUGGEST_PATH,
+    )
+}
+
+/// Search form & results, also advertises the description document. Links to
+/// cached pages use a one-time grant so the token never ends up in them.
+fn render_search_page(
+    search_token: &str,
+    grants: &CacheGrants,
+    query: &str,
+    results: Option<&SearchResults>,
+) -> String {
+    let token = html_escape(search_token);
+    let rendered = results
+        .map(|results| {
+            results
+                .results
+                .iter()
+                .map(|result| {
+                    format!(
+                        r#"<li><a href="{url}">{title}</a> <a class="cached" href="{cached}">cached</a><div class="url">{url}</div><p>{description}</p></li>"#,
+                        url = html_escape(&result.url),
+                        title = html_escape(&result.title),
+                        cached = html_escape(&grants.link(&result.doc_id)),
+                        description = html_escape(&result.description),
+                    )
+                })
+                .collect::<String>()
+        })
+        .unwrap_or_default();
+
+    format!(
+        r#"<!DOCTYPE html><html><head><meta charset="utf-8"><title>{title}</title><link rel="search" type="application/opensearchdescription+xml" title="Spyglass" href="{description}?token={token}"><style>body{{font:14px sans-serif;max-width:48rem;margin:2rem auto;background:#1c1917;color:#f5f5f4}}a{{color:#7dd3fc}}input{{width:80%;padding:4px}}ul{{list-style:none;padding:0}}li{{margin:1rem 0}}.url,.cached{{color:#a8a29e;font-size:12px}}</style></head><body><form action="{search}"><input name="q" value="{query}" autofocus><input type="hidden" name="token" value="{token}"></form><ul>{rendered}</ul></body></html>"#,
+        title = if query.is_empty() {
+            "Spyglass".to_string()
+        } else {
+            format!("{} - Spyglass", html_escape(query))
+        },
+        description = DESCRIPTION_PATH,
+        search = SEARCH_PATH,
+        query = html_escape(query),
+        token = token,
+        rendered = rendered,
+    )
+}
+
+/// Handles the OpenSearch endpoints, `None` for any other request.
+pub async fn handle(
+    state: AppState,
+    search_token: &str,
+    grants: &CacheGrants,
+    req: &Request<Body>,
+) -> Option<Response<Body>> {
+    if req.method() != Method::GET {
+        return None;
+    }
+
+    let query = query_param(req, "q").unwrap_or_default();
+    let resp = match req.uri().path() {
+        DESCRIPTION_PATH => Response::builder()
+            .header("content-type", "application/opensearchdescription+xml")
+            .body(Body::from(description(&base_url(req), search_token)))
+            .unwrap_or_default(),
+        SEARCH_PATH => {
+            let results = if query.trim().is_empty() {
+                None
+            } else {
+                let param = SearchParam {
+                    query: query.clone(),
+                    ..Default::default()
+                };
+                match route::search(state, param).await {
+                    Ok(results) => Some(results),
+                    Err(err) => {
+                        return Some(html_response(
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            html_escape(&err.message),
+                        ))
+                    }
+                }
+            };
+
+            html_response(
+                StatusCode::OK,
+                render_search_page(search_token, grants, &query, results.as_ref()),
+            )
+        }
+        SUGGEST_PATH => {
+            let suggestions = route::query_suggestions(state, query.clone())
+                .await
+                .unwrap_or_default();
+            Response::builder()
+                .header("content-type", "application/x-suggestions+json")
+                .body(Body::from(json!([query, suggestions]).to_string()))
+                .unwrap_or_default()
+        }
+        _ => return None,
+    };
+
+    Some(resp)
+}
+
+#[cfg(test)]
+mod test {
+    use shared::response::{SearchResult, SearchResults};
+
+    use super::{description, is_search_path, render_search_page, CacheGrants};
+
+    #[test]
+    fn test_description() {
+        let xml = description("http://127.0.0.1:4664", "secret");
+        assert!(xml.contains(
+            r#"template="http://127.0.0.1:4664/search?q={searchTerms}&amp;token=secret""#
+        ));
+        assert!(xml.contains(
+            r#"template="http://127.0.0.1:4664/suggest?q={searchTerms}&amp;token=secret""#
+        ));
+    }
+
+    #[test]
+    fn test_is_search_path() {
+        assert!(is_search_path("/search"));
+        assert!(is_search_path("/suggest"));
+        assert!(!is_search_path("/api/search"));
+        assert!(!is_search_path("/metrics"));
+    }
+
+    #[test]
+    fn test_render_search_page() {
+        let results = SearchResults {
+            results: vec![SearchResult {
+                doc_id: "abc".into(),
+                domain: "rust-lang.org".into(),
+                title: "<Rust>".into(),
+                description: "".into(),
+                url: "https://rust-lang.org".into(),
+                score: 1.0,
+                is_archived: false,
+                snippet: None,
+                metadata: Default::default(),
+                favicon: None,
+            }],
+            ..Default::default()
+        };
+
+        let page = render_search_page(
+            "secret",
+            &CacheGrants::default(),
+            "rust & co",
+            Some(&results),
+        );
+        assert!(page.contains(r#"href="/opensearch.xml?token=secret""#));
+        assert!(page.contains(r#"value="rust &amp; co""#));
+        assert!(page.contains("&lt;Rust&gt;"));
+        assert!(page.contains(r#"href="/cache/abc?grant="#));
+        assert!(!page.contains("/cache/abc?token="));
+    }
+}
@@ -0,0 +1,197 @@
+//! Plain JSON endpoints for the core operations under `/api/`, for scripts &
+//! tools that would rather `curl` than speak JSON-RPC. Requests need the API
+//! token from the prefs dir, e.g. `Authorization: Bearer <token>`.
+//!
+//! - `GET /api/search?q=<query>&lens=<name>&limit=<n>&offset=<n>`
+//! - `GET /api/search/explain`, same params as above
+//! - `GET /api/status` & `GET /api/stats`
+//! - `POST /api/queue` w/ a `QueueItemParam` body
+//! - `POST /api/queue/bulk` w/ a `BulkQueueParam` body
+//! - `GET /api/documents/<doc_id>` & `DELETE /api/documents/<doc_id>`
+//! - `DELETE /api/domains/<domain>?dry_run=true`
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+
+use libspyglass::state::AppState;
+use shared::request::{BulkQueueParam, DeleteDomainParam, QueueItemParam, SearchParam};
+
+use super::route;
+
+pub const API_PREFIX: &str = "/api/";
+
+#[derive(Debug, PartialEq)]
+enum ApiRoute {
+    Search,
+    ExplainSearch,
+    Status,
+    Stats,
+    Enqueue,
+    EnqueueBulk,
+    GetDocument(String),
+    DeleteDocument(String),
+    DeleteDomain(String),
+}
+
+fn api_route(method: &Method, path: &str) -> Option<ApiRoute> {
+    let path = path.strip_prefix(API_PREFIX)?.trim_end_matches('/');
+    let segments: Vec<&str> = path.split('/').collect();
+
+    let route = match (method, segments.as_slice()) {
+        (&Method::GET, ["search"]) => ApiRoute::Search,
+        (&Method::GET, ["search", "explain"]) => ApiRoute::ExplainSearch,
+        (&Method::GET, ["status"]) => ApiRoute::Status,
+        (&Method::GET, ["stats"]) => ApiRoute::Stats,
+        (&Method::POST, ["queue"]) => ApiRoute::Enqueue,
+        (&Method::POST, ["queue", "bulk"]) => ApiRoute::EnqueueBulk,
+        (&Method::GET, ["documents", id]) if !id.is_empty() => {
+            ApiRoute::GetDocument(id.to_string())
+        }
+        (&Method::DELETE, ["documents", id]) if !id.is_empty() => {
+            ApiRoute::DeleteDocument(id.to_string())
+        }
+        (&Method::DELETE, ["domains", domain]) if !domain.is_empty() => {
+            ApiRoute::DeleteDomain(domain.to_string())
+        }
+        _ => return None,
+    };
+
+    Some(route)
+}
+
+/// Builds a search from the URI params, `lens` can be repeated.
+fn parse_search_param(query: Option<&str>) -> SearchParam {
+    let mut param = SearchParam::default();
+    for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        match key.as_ref() {
+            "q" => param.query = value.to_string(),
+            "lens" => param.lenses.push(value.to_string()),
+            "limit" => param.limit = value.parse().unwrap_or(param.limit),
+            "offset" => param.offset = value.parse().unwrap_or(param.offset),
+            _ => {}
+        }
+    }
+
+    param
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap_or_default()
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, json!({ "error": message }))
+}
+
+/// Maps the result of an RPC route to a response, bad params become a 400.
+fn to_response<T: Serialize>(res: jsonrpc_core::Result<T>) -> Response<Body> {
+    match res {
+        Ok(value) => match serde_json::to_value(value) {
+            Ok(body) => json_response(StatusCode::OK, body),
+            Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()),
+        },
+        Err(err) => {
+            let status = match err.code {
+                jsonrpc_core::ErrorCode::InvalidParams
+                | jsonrpc_core::ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            error_response(status, &err.message)
+        }
+    }
+}
+
+async fn parse_body<T: DeserializeOwned>(req: Request<Body>) -> Result<T, Response<Body>> {
+    let body = hyper::body::to_bytes(req.into_body())
+        .await
+        .map_err(|err| error_response(StatusCode::BAD_REQUEST, &err.to_string()))?;
+
+    serde_json::from_slice(&body)
+        .map_err(|err| error_response(StatusCode::BAD_REQUEST, &err.to_string()))
+}
+
+pub async fn handle(state: AppState, req: Request<Body>) -> Response<Body> {
+    let route = match api_route(req.method(), req.uri().path()) {
+        Some(route) => route,
+        None => return error_response(StatusCode::NOT_FOUND, "Not found"),
+    };
+    let query = req.uri().query().map(|q| q.to_string());
+
+    match route {
+        ApiRoute::Search => {
+            let param = parse_search_param(query.as_deref());
+            to_response(route::search(state, param).await)
+        }
+        ApiRoute::ExplainSearch => {
+            let param = parse_search_param(query.as_deref());
+            to_response(route::explain_search(state, param).await)
+        }
+        ApiRoute::Status => to_response(route::app_status(state).await),
+        ApiRoute::Stats => to_response(route::crawl_stats(state).await),
+        ApiRoute::Enqueue => match parse_body::<QueueItemParam>(req).await {
+            Ok(param) => to_response(route::add_queue(state, param).await),
+            Err(resp) => resp,
+        },
+        ApiRoute::EnqueueBulk => match parse_body::<BulkQueueParam>(req).await {
+            Ok(param) => to_response(route::add_queue_bulk(state, param).await),
+            Err(resp) => resp,
+        },
+        ApiRoute::GetDocument(doc_id) => to_response(route::get_document(state, doc_id).await),
+        ApiRoute::DeleteDocument(doc_id) => {
+            to_response(route::delete_document(state, doc_id).await)
+        }
+        ApiRoute::DeleteDomain(domain) => {
+            let dry_run = url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+                .any(|(key, value)| key == "dry_run" && value == "true");
+            to_response(route::delete_domain(state, DeleteDomainParam { domain, dry_run }).await)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::Method;
+
+    use super::{api_route, parse_search_param, ApiRoute};
+
+    #[test]
+    fn test_api_route() {
+        assert_eq!(
+            api_route(&Method::GET, "/api/search"),
+            Some(ApiRoute::Search)
+        );
+        assert_eq!(
+            api_route(&Method::GET, "/api/search/explain"),
+            Some(ApiRoute::ExplainSearch)
+        );
+        assert_eq!(
+            api_route(&Method::POST, "/api/queue/bulk/"),
+            Some(ApiRoute::EnqueueBulk)
+        );
+        assert_eq!(
+            api_route(&Method::DELETE, "/api/domains/example.com"),
+            Some(ApiRoute::DeleteDomain("example.com".into()))
+        );
+        assert_eq!(
+            api_route(&Method::GET, "/api/documents/abc"),
+            Some(ApiRoute::GetDocument("abc".into()))
+        );
+        assert_eq!(api_route(&Method::POST, "/api/search"), None);
+        assert_eq!(api_route(&Method::DELETE, "/api/documents/"), None);
+        assert_eq!(api_route(&Method::GET, "/cache/abc"), None);
+    }
+
+    #[test]
+    fn test_parse_search_param() {
+        let param = parse_search_param(Some("q=rust+lang&lens=docs&lens=wiki&limit=5"));
+        assert_eq!(param.query, "rust lang");
+        assert_eq!(param.lenses, vec!["docs", "wiki"]);
+        assert_eq!(param.limit, 5);
+        assert_eq!(param.offset, 0);
+    }
+}
@@ -1,35 +1,82 @@
 use std::collections::HashMap;
+use std::fs;
+use std::time::Instant;
 
 use entities::models::crawl_queue::CrawlStatus;
 use entities::models::lens::LensType;
 use jsonrpc_core::{Error, ErrorCode, Result};
+use tantivy::{DocAddress, SnippetGenerator};
 use tracing::instrument;
 use url::Url;
 
+use shared::config::{Config, Lens};
 use shared::request;
 use shared::response::{
-    AppStatus, CrawlStats, LensResult, PluginResult, QueueStatus, SearchLensesResp, SearchMeta,
-    SearchResult, SearchResults,
+    AddQueueResult, AppStatus, CrawlErrorStats, CrawlLogEntry, CrawlStats, DeleteDomainResult,
+    DiskStats, DocumentResult, DomainCrawlStats, DomainDocCount, IndexStats, LensDocCount,
+    LensResult, ListQueueResp, PluginResult, PluginSettingResult, QueueItem, QueueStatus,
+    SearchLensesResp, SearchMeta, SearchResult, SearchResults, SearchTimings, TaskResult,
 };
 
-use entities::models::{crawl_queue, fetch_history, indexed_document, lens};
-use entities::sea_orm::{prelude::*, sea_query, QueryOrder, Set};
-use libspyglass::plugin::PluginCommand;
-use libspyglass::search::Searcher;
+use entities::models::{
+    crawl_log, crawl_queue, document_content, document_tombstone, domain_stats, fetch_history,
+    indexed_document, lens, scheduled_task, search_history,
+};
+use entities::normalize::normalize;
+use entities::sea_orm::{prelude::*, sea_query, QueryOrder, QuerySelect, Set};
+use libspyglass::backup;
+use libspyglass::fetch::HTTPClient;
+use libspyglass::memory;
+use libspyglass::plugin::{self, PluginCommand};
+use libspyglass::scraper::lang;
+use libspyglass::search::lens::{self as lens_files, DownloadedLens};
+use libspyglass::search::{
+    answer, export, extract_filters, semantic, stats, SearchOptions, Searcher,
+};
 use libspyglass::state::AppState;
+use libspyglass::task;
+use spyglass_plugin::PluginEventPayload;
 
-use super::response;
+/// Max number of search results returned in a single page.
+const MAX_SEARCH_LIMIT: usize = 100;
+/// How far into the results a search can page, each page collects everything
+/// before it.
+const MAX_SEARCH_OFFSET: usize = 10_000;
+/// Max number of queued URLs returned in a single page.
+const MAX_QUEUE_LIMIT: u64 = 1000;
+/// Max number of past searches suggested.
+const MAX_SUGGESTIONS: usize = 10;
+/// Max number of related documents returned by `similar`.
+const MAX_SIMILAR: usize = 10;
+/// Number of domains w/ the most documents listed in the app status.
+const NUM_TOP_DOMAINS: u64 = 10;
 
 /// Add url to queue
 #[instrument(skip(state))]
 pub async fn add_queue(state: AppState, queue_item: request::QueueItemParam) -> Result<String> {
     let db = &state.db;
 
-    let parsed = Url::parse(&queue_item.url).unwrap();
+    let mut url = match Url::parse(&queue_item.url) {
+        Ok(parsed) if parsed.host_str().is_some() => parsed,
+        _ => {
+            return Err(Error {
+                code: ErrorCode::InvalidParams,
+                message: format!("Invalid URL: {}", queue_item.url),
+                data: None,
+            })
+        }
+    };
+    // Stored the same way as everything else in the queue, so the same page
+    // isn't queued twice under different URLs.
+    normalize(&mut url);
+    let domain = url.host_str().unwrap_or_default().to_string();
+
     let new_task = crawl_queue::ActiveModel {
-        domain: Set(parsed.host_str().unwrap().to_string()),
-        url: Set(queue_item.url.to_owned()),
+        domain: Set(domain),
+        url: Set(url.to_string()),
         crawl_type: Set(crawl_queue::CrawlType::Normal),
+        max_depth: Set(queue_item.max_depth.map(|depth| depth as i32)),
+        priority: Set(queue_item.priority.unwrap_or(crawl_queue::PRIORITY_USER)),
         ..Default::default()
     };
 
@@ -43,19 +90,128 @@ pub async fn add_queue(state: AppState, queue_item: request::QueueItemParam) ->
     }
 }
 
+/// Add many URLs to the queue at once. Unlike `add_queue`, URLs on the
+/// block list or already queued/indexed are skipped, each w/ the reason why.
+#[instrument(skip(state, param))]
+pub async fn add_queue_bulk(
+    state: AppState,
+    param: request::BulkQueueParam,
+) -> Result<Vec<AddQueueResult>> {
+    let overrides = crawl_queue::EnqueueSettings {
+        max_depth: param.max_depth.map(|depth| depth as i32),
+        priority: param.priority.unwrap_or(crawl_queue::PRIORITY_USER),
+        ..Default::default()
+    };
+
+    let skipped = match crawl_queue::enqueue_all(
+        &state.db,
+        &param.urls,
+        &[],
+        &state.user_settings,
+        &overrides,
+    )
+    .await
+    {
+        Ok(skipped) => skipped,
+        Err(err) => {
+            return Err(Error {
+                code: ErrorCode::InternalError,
+                message: err.to_string(),
+                data: None,
+            })
+        }
+    };
+
+    Ok(param
+        .urls
+        .into_iter()
+        .map(|url| {
+            let error = skipped.get(&url).map(|reason| reason.to_string());
+            AddQueueResult {
+                url,
+                added: error.is_none(),
+                error,
+            }
+        })
+        .collect())
+}
+
 async fn _get_current_status(state: AppState) -> jsonrpc_core::Result<AppStatus> {
     // Grab crawler status
     let app_state = &state.app_state;
     let paused_status = app_state.get("paused").unwrap();
     let is_paused = *paused_status == *"true";
 
+    let mut paused_domains: Vec<String> = state
+        .paused_domains
+        .iter()
+        .map(|domain| domain.key().clone())
+        .collect();
+    paused_domains.sort();
+    let mut paused_lenses: Vec<String> = state
+        .paused_lenses
+        .iter()
+        .map(|name| name.key().clone())
+        .collect();
+    paused_lenses.sort();
+
     // Grab details about index
-    let index = state.index;
-    let reader = index.reader.searcher();
+    let reader = state.index.reader.searcher();
+    let disk = DiskStats {
+        index: state.index.disk_usage().unwrap_or_else(|err| {
+            log::error!("Unable to get index size: {}", err);
+            0
+        }),
+        db: entities::models::db_size(&state.db)
+            .await
+            .unwrap_or_else(|err| {
+                log::error!("Unable to get db size: {}", err);
+                0
+            }),
+    };
+
+    let lens_map: HashMap<String, Lens> = state
+        .lenses
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+    let mut lenses: Vec<LensDocCount> = lens_map
+        .keys()
+        .map(|name| LensDocCount {
+            name: name.clone(),
+            num_docs: state.index.num_docs_in_lens(&lens_map, name).unwrap_or(0) as u64,
+        })
+        .collect();
+    lenses.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let top_domains = indexed_document::top_domains(&state.db, NUM_TOP_DOMAINS)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|row| DomainDocCount {
+            domain: row.domain,
+            num_docs: row.count as u64,
+        })
+        .collect();
+
+    let errors = CrawlErrorStats {
+        num_failed: crawl_queue::num_queued(&state.db, CrawlStatus::Failed)
+            .await
+            .unwrap_or(0),
+        num_logged: crawl_log::num_errors(&state.db).await.unwrap_or(0),
+    };
 
     Ok(AppStatus {
         num_docs: reader.num_docs(),
         is_paused,
+        paused_domains,
+        paused_lenses,
+        memory: memory::stats(&state),
+        pipeline: state.pipeline.status(),
+        disk,
+        lenses,
+        top_domains,
+        errors,
     })
 }
 
@@ -101,6 +257,28 @@ pub async fn crawl_stats(state: AppState) -> jsonrpc_core::Result<CrawlStats> {
         entry.num_indexed += stat.count as u64;
     }
 
+    match domain_stats::Entity::find().all(&state.db).await {
+        Ok(stats) => {
+            for stat in stats {
+                let entry = by_domain
+                    .entry(stat.domain)
+                    .or_insert_with(QueueStatus::default);
+                entry.crawl = DomainCrawlStats {
+                    num_fetched: stat.num_fetched as u64,
+                    num_client_errors: stat.num_client_errors as u64,
+                    num_server_errors: stat.num_server_errors as u64,
+                    num_network_errors: stat.num_network_errors as u64,
+                    bytes_downloaded: stat.bytes_downloaded as u64,
+                    last_crawled_at: stat.last_crawled_at.map(|at| at.to_rfc3339()),
+                };
+            }
+        }
+        Err(err) => {
+            log::error!("domain_stats {:?}", err);
+            return Err(jsonrpc_core::Error::new(ErrorCode::InternalError));
+        }
+    }
+
     let by_domain = by_domain
         .into_iter()
         .filter(|(_, stats)| stats.total() >= 10)
@@ -109,49 +287,269 @@ pub async fn crawl_stats(state: AppState) -> jsonrpc_core::Result<CrawlStats> {
     Ok(CrawlStats { by_domain })
 }
 
-/// Remove a doc from the index
+/// A single document w/ its full text, crawl timestamps & the lenses it
+/// belongs to.
 #[instrument(skip(state))]
-pub async fn delete_doc(state: AppState, id: String) -> Result<()> {
+pub async fn get_document(state: AppState, doc_id: String) -> Result<DocumentResult> {
+    let doc = Searcher::get_by_id(&state.index.reader, &doc_id).ok_or_else(|| Error {
+        code: ErrorCode::InvalidParams,
+        message: format!("Document {} not found", doc_id),
+        data: None,
+    })?;
+
+    let fields = Searcher::doc_fields();
+    let text = |field| {
+        doc.get_first(field)
+            .and_then(|value| value.as_text())
+            .map(|value| value.to_string())
+    };
+    let url = text(fields.url).unwrap_or_default();
+
+    let indexed = indexed_document::Entity::find()
+        .filter(indexed_document::Column::DocId.eq(doc_id.clone()))
+        .one(&state.db)
+        .await
+        .map_err(|err| Error {
+            code: ErrorCode::InternalError,
+            message: err.to_string(),
+            data: None,
+        })?;
+
+    // Local files & such have no fetch history.
+    let last_fetched_at = match Url::parse(&url) {
+        Ok(parsed) if parsed.host_str().is_some() => fetch_history::find_by_url(&state.db, &parsed)
+            .await
+            .ok()
+            .flatten()
+            .map(|history| history.updated_at.to_rfc3339()),
+        _ => None,
+    };
+
+    let lenses: HashMap<String, Lens> = state
+        .lenses
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+
+    Ok(DocumentResult {
+        doc_id,
+        domain: text(fields.domain).unwrap_or_default(),
+        title: text(fields.title).unwrap_or_default(),
+        description: text(fields.description).unwrap_or_default(),
+        content: Searcher::content(&doc),
+        lang: text(fields.lang).filter(|lang| !lang.is_empty()),
+        created_at: indexed.as_ref().map(|doc| doc.created_at.to_rfc3339()),
+        updated_at: indexed.as_ref().map(|doc| doc.updated_at.to_rfc3339()),
+        last_fetched_at,
+        archived_at: indexed
+            .as_ref()
+            .and_then(|doc| doc.archived_at)
+            .map(|at| at.to_rfc3339()),
+        lenses: state.index.lenses_for_url(&lenses, &url),
+        metadata: indexed
+            .as_ref()
+            .map(|doc| doc.page_metadata())
+            .unwrap_or_default(),
+        url,
+    })
+}
+
+/// Documents related to `doc_id`, most similar first.
+#[instrument(skip(state))]
+pub async fn similar(state: AppState, doc_id: String) -> Result<Vec<SearchResult>> {
+    if Searcher::get_by_id(&state.index.reader, &doc_id).is_none() {
+        return Err(Error {
+            code: ErrorCode::InvalidParams,
+            message: format!("Document {} not found", doc_id),
+            data: None,
+        });
+    }
+
+    let docs = state
+        .index
+        .similar(&doc_id, MAX_SIMILAR)
+        .map_err(|err| Error {
+            code: ErrorCode::InternalError,
+            message: err.to_string(),
+            data: None,
+        })?;
+
+    Ok(_to_search_results(&state, docs, None).await)
+}
+
+/// Remove a doc from the index & database
+#[instrument(skip(state))]
+pub async fn delete_document(state: AppState, doc_id: String) -> Result<()> {
     if let Ok(mut writer) = state.index.writer.lock() {
-        if let Err(e) = Searcher::delete(&mut writer, &id) {
-            log::error!("Unable to delete doc {} due to {}", id, e);
+        if let Err(e) = Searcher::delete(&mut writer, &doc_id) {
+            log::error!("Unable to delete doc {} due to {}", doc_id, e);
         } else {
-            let _ = writer.commit();
+            state.index.mark_pending(1);
+        }
+    }
+
+    // Commit right away so the document no longer shows up in search results.
+    let _ = state.index.commit();
+
+    let indexed = indexed_document::Entity::find()
+        .filter(indexed_document::Column::DocId.eq(doc_id.clone()))
+        .one(&state.db)
+        .await;
+
+    match indexed {
+        Ok(Some(indexed)) => {
+            let urls = [indexed.url.clone()];
+            if let Err(err) = document_content::remove_by_urls(&state.db, &urls).await {
+                log::error!("Unable to remove stored content: {}", err);
+            }
+            state.vectors.remove(&urls);
+            // Keeps syncing from bringing it back.
+            if let Err(err) = document_tombstone::record(&state.db, &urls).await {
+                log::error!("Unable to record deleted document: {}", err);
+            }
+
+            if let Err(err) = indexed.delete(&state.db).await {
+                return Err(Error {
+                    code: ErrorCode::InternalError,
+                    message: err.to_string(),
+                    data: None,
+                });
+            }
+            state.refresh_seen_urls();
+        }
+        Ok(None) => log::debug!("No indexed_document for doc {}", doc_id),
+        Err(err) => {
+            return Err(Error {
+                code: ErrorCode::InternalError,
+                message: err.to_string(),
+                data: None,
+            })
         }
     }
 
     Ok(())
 }
 
-/// Remove a domain from crawl queue & index
+/// Remove a domain from the crawl queue, fetch history & index. W/ `dry_run`
+/// nothing is removed, only counted.
 #[instrument(skip(state))]
-pub async fn delete_domain(state: AppState, domain: String) -> Result<()> {
-    // Remove items from crawl queue
-    let res = crawl_queue::Entity::delete_many()
-        .filter(crawl_queue::Column::Domain.eq(domain.clone()))
-        .exec(&state.db)
-        .await;
-
-    if let Ok(res) = res {
-        log::info!("removed {} items from crawl queue", res.rows_affected);
+pub async fn delete_domain(
+    state: AppState,
+    param: request::DeleteDomainParam,
+) -> Result<DeleteDomainResult> {
+    let domain = param.domain;
+    match _delete_domain(&state, &domain, param.dry_run).await {
+        Ok(result) => {
+            if !param.dry_run {
+                log::info!("removed {}: {:?}", domain, result);
+                state.refresh_seen_urls();
+            }
+            Ok(result)
+        }
+        Err(err) => Err(Error {
+            code: ErrorCode::InternalError,
+            message: err.to_string(),
+            data: None,
+        }),
     }
+}
 
-    // Remove items from index
+async fn _delete_domain(
+    state: &AppState,
+    domain: &str,
+    dry_run: bool,
+) -> anyhow::Result<DeleteDomainResult, DbErr> {
+    let queued = crawl_queue::Entity::find().filter(crawl_queue::Column::Domain.eq(domain));
+    let history = fetch_history::Entity::find().filter(fetch_history::Column::Domain.eq(domain));
     let indexed = indexed_document::Entity::find()
         .filter(indexed_document::Column::Domain.eq(domain))
         .all(&state.db)
-        .await;
+        .await?;
 
-    if let (Ok(indexed), Ok(mut writer)) = (indexed, state.index.writer.lock()) {
-        for result in indexed {
-            let _ = Searcher::delete(&mut writer, &result.doc_id);
-            let _ = result.delete(&state.db);
+    if dry_run {
+        return Ok(DeleteDomainResult {
+            num_documents: indexed.len() as u64,
+            num_queued: queued.count(&state.db).await? as u64,
+            num_fetch_history: history.count(&state.db).await? as u64,
+        });
+    }
+
+    let num_queued = crawl_queue::Entity::delete_many()
+        .filter(crawl_queue::Column::Domain.eq(domain))
+        .exec(&state.db)
+        .await?
+        .rows_affected;
+
+    let num_fetch_history = fetch_history::Entity::delete_many()
+        .filter(fetch_history::Column::Domain.eq(domain))
+        .exec(&state.db)
+        .await?
+        .rows_affected;
+
+    // Remove items from index
+    if let Ok(mut writer) = state.index.writer.lock() {
+        for doc in indexed.iter() {
+            if Searcher::delete(&mut writer, &doc.doc_id).is_ok() {
+                state.index.mark_pending(1);
+            }
         }
+    }
+    let _ = state.index.commit();
 
-        let _ = writer.commit();
+    let urls: Vec<String> = indexed.iter().map(|doc| doc.url.clone()).collect();
+    if let Err(err) = document_content::remove_by_urls(&state.db, &urls).await {
+        log::error!("Unable to remove stored content: {}", err);
+    }
+    state.vectors.remove(&urls);
+    if let Err(err) = document_tombstone::record(&state.db, &urls).await {
+        log::error!("Unable to record deleted documents: {}", err);
     }
 
-    Ok(())
+    let num_documents = indexed_document::Entity::delete_many()
+        .filter(indexed_document::Column::Domain.eq(domain))
+        .exec(&state.db)
+        .await?
+        .rows_affected;
+
+    Ok(DeleteDomainResult {
+        num_documents,
+        num_queued,
+        num_fetch_history,
+    })
+}
+
+/// Index a page that was already fetched & rendered by a client, e.g. the
+/// browser extension, rather than crawling it ourselves.
+#[instrument(skip(state, page))]
+pub async fn index_page(state: AppState, page: request::IndexPageParam) -> Result<String> {
+    let url = match Url::parse(&page.url) {
+        Ok(url) => url,
+        Err(_) => {
+            return Err(Error {
+                code: ErrorCode::InvalidParams,
+                message: format!("Invalid URL: {}", page.url),
+                data: None,
+            })
+        }
+    };
+
+    match task::add_to_index(
+        &state,
+        &url,
+        &page.title,
+        &page.description,
+        &page.content,
+        "",
+        None,
+    )
+    .await
+    {
+        Ok(doc_id) => Ok(doc_id),
+        Err(err) => {
+            log::error!("Unable to index <{}>: {}", url, err);
+            Err(Error::new(ErrorCode::InternalError))
+        }
+    }
 }
 
 /// List of installed lenses
@@ -173,6 +571,219 @@ pub async fn list_installed_lenses(state: AppState) -> Result<Vec<LensResult>> {
     Ok(lenses)
 }
 
+fn lens_not_installed(name: &str) -> Error {
+    Error {
+        code: ErrorCode::InvalidParams,
+        message: format!("Lens {} is not installed", name),
+        data: None,
+    }
+}
+
+/// Download a lens from a URL or, by name, from the lens directory.
+async fn _download_lens(state: &AppState, name_or_url: &str) -> anyhow::Result<DownloadedLens> {
+    let client = HTTPClient::with_settings(&state.user_settings);
+    let url = match Url::parse(name_or_url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => url,
+        _ => {
+            let directory = lens_files::lens_directory(&client).await?;
+            let entry = directory
+                .into_iter()
+                .find(|entry| entry.name == name_or_url)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("No lens named {} in the lens directory", name_or_url)
+                })?;
+            Url::parse(&entry.download_url)?
+        }
+    };
+
+    lens_files::download_lens(&client, &url).await
+}
+
+/// Write the lens to the lens folder, replacing the installed version if any,
+/// & load it right away rather than waiting for the lens watcher.
+fn _save_lens(state: &AppState, config: &Config, downloaded: DownloadedLens) -> Result<()> {
+    let lenses_dir = config.lenses_dir();
+    let path = match lens_files::find_lens_file(&lenses_dir, &downloaded.lens.name) {
+        Some(path) => path,
+        None => {
+            // Lenses from different URLs can share a file name.
+            let path = lenses_dir.join(&downloaded.file_name);
+            if path.exists() {
+                return Err(Error {
+                    code: ErrorCode::InvalidParams,
+                    message: format!("{} is already used by another lens", downloaded.file_name),
+                    data: None,
+                });
+            }
+            path
+        }
+    };
+    fs::write(&path, &downloaded.contents).map_err(|err| Error {
+        code: ErrorCode::InternalError,
+        message: format!("Unable to write {}: {}", path.display(), err),
+        data: None,
+    })?;
+
+    let lens = downloaded.lens;
+    if lens.is_enabled {
+        state.lenses.insert(lens.name.clone(), lens.clone());
+        // Bootstrapping can take a while
+        let state = state.clone();
+        tokio::spawn(async move { lens_files::load_lens(&state, &lens).await });
+    }
+
+    Ok(())
+}
+
+/// Install a lens from a URL to its `.ron` file or, by name, from the lens
+/// directory.
+#[instrument(skip(state, config))]
+pub async fn install_lens(
+    state: AppState,
+    config: Config,
+    name_or_url: String,
+) -> Result<LensResult> {
+    let downloaded = _download_lens(&state, &name_or_url)
+        .await
+        .map_err(|err| Error {
+            code: ErrorCode::InvalidParams,
+            message: err.to_string(),
+            data: None,
+        })?;
+
+    let name = downloaded.lens.name.clone();
+    if lens_files::find_lens_file(&config.lenses_dir(), &name).is_some() {
+        return Err(Error {
+            code: ErrorCode::InvalidParams,
+            message: format!(
+                "Lens {} is already installed, use update_lens instead",
+                name
+            ),
+            data: None,
+        });
+    }
+
+    let result = LensResult {
+        author: downloaded.lens.author.clone(),
+        title: name.clone(),
+        description: downloaded.lens.description.clone().unwrap_or_default(),
+        ..Default::default()
+    };
+    _save_lens(&state, &config, downloaded)?;
+    log::info!("installed lens {}", name);
+    plugin::dispatch_event(&state, PluginEventPayload::LensInstalled { name }).await;
+
+    Ok(result)
+}
+
+/// Remove the lens's pages, unless another installed lens covers them too.
+/// Returns the number of documents removed.
+async fn _purge_lens(state: &AppState, removed: &Lens) -> u64 {
+    let others: Vec<Lens> = state
+        .lenses
+        .iter()
+        .map(|entry| entry.value().clone())
+        .collect();
+
+    let mut num_removed = 0;
+    for domain in removed.domains.iter() {
+        if others.iter().any(|lens| lens.domains.contains(domain)) {
+            continue;
+        }
+
+        match _delete_domain(state, domain, false).await {
+            Ok(result) => num_removed += result.num_documents,
+            Err(err) => log::error!("Unable to purge {}: {}", domain, err),
+        }
+    }
+
+    for prefix in removed.urls.iter() {
+        if others.iter().any(|lens| lens.urls.contains(prefix)) {
+            continue;
+        }
+
+        let prefix = prefix.trim_end_matches('$');
+        num_removed += lens_files::remove_matching(state, prefix).await as u64;
+    }
+
+    num_removed
+}
+
+/// Remove an installed lens & optionally its pages. Returns the number of
+/// documents removed.
+#[instrument(skip(state, config))]
+pub async fn uninstall_lens(
+    state: AppState,
+    config: Config,
+    param: request::UninstallLensParam,
+) -> Result<u64> {
+    let name = param.name;
+    let path = lens_files::find_lens_file(&config.lenses_dir(), &name)
+        .ok_or_else(|| lens_not_installed(&name))?;
+    let installed = fs::read_to_string(&path)
+        .ok()
+        .and_then(|contents| ron::from_str::<Lens>(&contents).ok());
+
+    fs::remove_file(&path).map_err(|err| Error {
+        code: ErrorCode::InternalError,
+        message: format!("Unable to remove {}: {}", path.display(), err),
+        data: None,
+    })?;
+
+    state.lenses.remove(&name);
+    state.paused_lenses.remove(&name);
+    if let Err(err) = lens::remove(&state.db, &name).await {
+        log::error!("Unable to remove lens {}: {}", name, err);
+    }
+
+    let num_removed = match (param.purge, installed) {
+        (true, Some(installed)) => {
+            let num_removed = _purge_lens(&state, &installed).await;
+            let _ = state.index.commit();
+            num_removed
+        }
+        _ => 0,
+    };
+
+    log::info!("uninstalled lens {}, removed {} docs", name, num_removed);
+    Ok(num_removed)
+}
+
+/// Update an installed lens to the latest version in the lens directory.
+/// Returns false if it's already up to date.
+#[instrument(skip(state, config))]
+pub async fn update_lens(state: AppState, config: Config, name: String) -> Result<bool> {
+    let path = lens_files::find_lens_file(&config.lenses_dir(), &name)
+        .ok_or_else(|| lens_not_installed(&name))?;
+    let installed = fs::read_to_string(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|contents| Ok(ron::from_str::<Lens>(&contents)?))
+        .map_err(|err| Error {
+            code: ErrorCode::InternalError,
+            message: format!("Unable to read {}: {}", path.display(), err),
+            data: None,
+        })?;
+
+    let downloaded = _download_lens(&state, &name).await.map_err(|err| Error {
+        code: ErrorCode::InternalError,
+        message: err.to_string(),
+        data: None,
+    })?;
+
+    if downloaded.lens.version == installed.version {
+        return Ok(false);
+    }
+
+    log::info!(
+        "updating lens {} from {} to {}",
+        name,
+        installed.version,
+        downloaded.lens.version
+    );
+    _save_lens(&state, &config, downloaded)?;
+    Ok(true)
+}
+
 pub async fn list_plugins(state: AppState) -> Result<Vec<PluginResult>> {
     let mut plugins = Vec::new();
     let result = lens::Entity::find()
@@ -194,14 +805,170 @@ pub async fn list_plugins(state: AppState) -> Result<Vec<PluginResult>> {
     Ok(plugins)
 }
 
-/// Show the list of URLs in the queue and their status
+/// Merge the index segments in the background, same as the scheduled
+/// `optimize_index` task. Returns right away, merging can take a while.
+#[instrument(skip(state))]
+pub async fn index_optimize(state: AppState) -> Result<()> {
+    tokio::spawn(async move {
+        // Flush pending writes so they're part of the merge.
+        if let Err(err) = state.index.commit() {
+            log::error!("Unable to commit before optimizing: {}", err);
+            return;
+        }
+
+        match state.index.optimize().await {
+            Ok(merged) => log::info!("merged {} index segments", merged),
+            Err(err) => log::error!("Unable to optimize index: {}", err),
+        }
+    });
+
+    Ok(())
+}
+
+/// Size of the index, broken down by field.
 #[instrument(skip(state))]
-pub async fn list_queue(state: AppState) -> Result<response::ListQueue> {
+pub async fn index_stats(state: AppState) -> Result<IndexStats> {
+    // Reads a sample of stored documents, keep it off the async runtime.
+    let index = state.index.clone();
+    let res = match tokio::task::spawn_blocking(move || stats::index_stats(&index)).await {
+        Ok(res) => res,
+        Err(err) => Err(err.into()),
+    };
+
+    res.map_err(|err| Error {
+        code: ErrorCode::InternalError,
+        message: err.to_string(),
+        data: None,
+    })
+}
+
+/// List scheduled background tasks & when they'll run next.
+#[instrument(skip(state))]
+pub async fn list_tasks(state: AppState) -> Result<Vec<TaskResult>> {
+    let tasks = scheduled_task::Entity::find()
+        .order_by_asc(scheduled_task::Column::NextRunAt)
+        .all(&state.db)
+        .await
+        .map_err(|err| Error {
+            code: ErrorCode::InternalError,
+            message: err.to_string(),
+            data: None,
+        })?;
+
+    Ok(tasks
+        .into_iter()
+        .map(|task| TaskResult {
+            name: task.name,
+            schedule: task.schedule,
+            next_run: task.next_run_at.to_rfc3339(),
+            last_run: task.last_run_at.map(|ts| ts.to_rfc3339()),
+            last_error: task.last_error,
+        })
+        .collect())
+}
+
+/// Show a page of URLs in the queue and their status
+#[instrument(skip(state))]
+pub async fn list_queue(state: AppState, param: request::ListQueueParam) -> Result<ListQueueResp> {
+    match _list_queue(&state, param).await {
+        Ok(resp) => Ok(resp),
+        Err(err) => Err(Error {
+            code: ErrorCode::InternalError,
+            message: err.to_string(),
+            data: None,
+        }),
+    }
+}
+
+async fn _list_queue(
+    state: &AppState,
+    param: request::ListQueueParam,
+) -> anyhow::Result<ListQueueResp, DbErr> {
     let db = &state.db;
-    let queue = crawl_queue::Entity::find().all(db).await;
 
-    match queue {
-        Ok(queue) => Ok(response::ListQueue { queue }),
+    let mut query = crawl_queue::Entity::find();
+    if let Some(domain) = &param.domain {
+        query = query.filter(crawl_queue::Column::Domain.eq(domain.clone()));
+    }
+    if let Some(status) = param.status {
+        query = query.filter(crawl_queue::Column::Status.eq(CrawlStatus::from(status)));
+    }
+
+    let num_items = query.clone().count(db).await? as u64;
+    let counts = crawl_queue::status_counts(db, param.domain.as_deref()).await?;
+
+    let column = match param.sort {
+        request::QueueSort::CreatedAt => crawl_queue::Column::CreatedAt,
+        request::QueueSort::UpdatedAt => crawl_queue::Column::UpdatedAt,
+        request::QueueSort::Priority => crawl_queue::Column::Priority,
+        request::QueueSort::Url => crawl_queue::Column::Url,
+    };
+    let order = if param.ascending {
+        sea_query::Order::Asc
+    } else {
+        sea_query::Order::Desc
+    };
+
+    let items = query
+        .order_by(column, order.clone())
+        // Keep pages stable when many tasks share the same value
+        .order_by(crawl_queue::Column::Id, order)
+        .limit(param.limit.min(MAX_QUEUE_LIMIT))
+        .offset(param.offset)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|task| QueueItem {
+            id: task.id,
+            domain: task.domain,
+            url: task.url,
+            status: task.status.into(),
+            num_retries: task.num_retries,
+            next_retry_at: task.next_retry_at.map(|at| at.to_rfc3339()),
+            depth: task.depth,
+            priority: task.priority,
+            created_at: task.created_at.to_rfc3339(),
+            updated_at: task.updated_at.to_rfc3339(),
+        })
+        .collect();
+
+    Ok(ListQueueResp {
+        items,
+        num_items,
+        counts,
+    })
+}
+
+/// Drop URLs from the queue, e.g. ones enqueued by mistake or the backlog
+/// of failed tasks.
+#[instrument(skip(state))]
+pub async fn remove_queue_items(
+    state: AppState,
+    param: request::RemoveQueueItemsParam,
+) -> Result<u64> {
+    if param.ids.is_empty() && param.domain.is_none() && param.status.is_none() {
+        return Err(Error {
+            code: ErrorCode::InvalidParams,
+            message: "Either ids, a domain or a status is required".to_string(),
+            data: None,
+        });
+    }
+
+    match crawl_queue::remove_tasks(
+        &state.db,
+        &param.ids,
+        param.domain.as_deref(),
+        param.status.map(CrawlStatus::from),
+    )
+    .await
+    {
+        Ok(num_removed) => {
+            log::info!("removed {} items from crawl queue", num_removed);
+            if num_removed > 0 {
+                state.refresh_seen_urls();
+            }
+            Ok(num_removed)
+        }
         Err(err) => Err(Error {
             code: ErrorCode::InternalError,
             message: err.to_string(),
@@ -210,15 +977,127 @@ pub async fn list_queue(state: AppState) -> Result<response::ListQueue> {
     }
 }
 
+/// Most recent crawl attempts, to see why pages never made it into the index.
+#[instrument(skip(state))]
+pub async fn recent_crawls(
+    state: AppState,
+    param: request::RecentCrawlsParam,
+) -> Result<Vec<CrawlLogEntry>> {
+    let entries = crawl_log::recent(
+        &state.db,
+        param.domain.as_deref(),
+        param.errors_only,
+        param.limit,
+    )
+    .await
+    .map_err(|err| Error {
+        code: ErrorCode::InternalError,
+        message: err.to_string(),
+        data: None,
+    })?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| CrawlLogEntry {
+            domain: entry.domain,
+            url: entry.url,
+            status: entry.status.map(|status| status as u16),
+            duration_ms: entry.duration_ms as u64,
+            num_bytes: entry.num_bytes as u64,
+            error: entry.error,
+            crawled_at: entry.created_at.to_rfc3339(),
+        })
+        .collect())
+}
+
+/// Force a fresh crawl of every known URL on `domain`. Fetch history is
+/// dropped so pages aren't skipped as recently fetched or unchanged. Returns
+/// the number of URLs queued.
 #[instrument(skip(state))]
-pub async fn recrawl_domain(state: AppState, domain: String) -> Result<()> {
+pub async fn recrawl_domain(state: AppState, domain: String) -> Result<u64> {
     log::info!("handling recrawl domain: {}", domain);
     let db = &state.db;
 
-    let _ = fetch_history::Entity::delete_many()
-        .filter(fetch_history::Column::Domain.eq(domain.clone()))
-        .exec(db)
-        .await;
+    if let Err(e) = fetch_history::remove_by_domain(db, &domain).await {
+        log::error!("Unable to clear fetch history for {}: {}", domain, e);
+    }
+
+    match crawl_queue::requeue_domain(db, &domain).await {
+        Ok(num_queued) => Ok(num_queued),
+        Err(e) => {
+            log::error!("Error recrawling domain {}: {}", domain, e);
+            Err(Error {
+                code: ErrorCode::InternalError,
+                message: e.to_string(),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Same as `recrawl_domain` for a single URL, which is added to the queue if
+/// we haven't seen it before.
+#[instrument(skip(state))]
+pub async fn recrawl_url(state: AppState, url: String) -> Result<u64> {
+    log::info!("handling recrawl url: {}", url);
+    let db = &state.db;
+
+    let mut parsed = match Url::parse(&url) {
+        Ok(parsed) if parsed.host_str().is_some() => parsed,
+        _ => {
+            return Err(Error {
+                code: ErrorCode::InvalidParams,
+                message: format!("Invalid URL: {}", url),
+                data: None,
+            })
+        }
+    };
+    // Match the task however it was queued, e.g. over http or w/o the
+    // trailing slash.
+    normalize(&mut parsed);
+    let url = parsed.to_string();
+
+    if let Err(e) = fetch_history::remove_by_url(db, &parsed).await {
+        log::error!("Unable to clear fetch history for {}: {}", url, e);
+    }
+
+    let variants = indexed_document::url_variants(&url);
+    let num_queued = match crawl_queue::requeue_urls(db, &variants).await {
+        Ok(num_queued) => num_queued,
+        Err(e) => {
+            return Err(Error {
+                code: ErrorCode::InternalError,
+                message: e.to_string(),
+                data: None,
+            })
+        }
+    };
+
+    if num_queued > 0 {
+        return Ok(num_queued);
+    }
+
+    add_queue(
+        state,
+        request::QueueItemParam {
+            url,
+            force_crawl: true,
+            max_depth: None,
+            priority: None,
+        },
+    )
+    .await
+    .map(|_| 1)
+}
+
+/// Recrawl & reindex everything in the crawl queue
+#[instrument(skip(state))]
+pub async fn reindex(state: AppState) -> Result<()> {
+    log::info!("handling reindex");
+    let db = &state.db;
+
+    // Clear out fetch history so that pages are not skipped as recently fetched.
+    let _ = fetch_history::Entity::delete_many().exec(db).await;
 
     let res = crawl_queue::Entity::update_many()
         .col_expr(
@@ -227,38 +1106,51 @@ pub async fn recrawl_domain(state: AppState, domain: String) -> Result<()> {
                 CrawlStatus::Queued.to_string(),
             )))),
         )
-        .filter(crawl_queue::Column::Domain.eq(domain.clone()))
+        .filter(crawl_queue::Column::Status.ne(CrawlStatus::Processing.to_string()))
         .exec(db)
         .await;
 
-    // Log out issues
-    if let Err(e) = res {
-        log::error!("Error recrawling domain {}: {}", domain, e);
+    match res {
+        Ok(res) => log::info!("requeued {} tasks", res.rows_affected),
+        Err(e) => log::error!("Error requeuing tasks for reindex: {}", e),
     }
 
     Ok(())
 }
 
-/// Search the user's indexed documents
-#[instrument(skip(state))]
-pub async fn search(state: AppState, search_req: request::SearchParam) -> Result<SearchResults> {
-    let fields = Searcher::doc_fields();
+/// Query text w/o operators & the options to search w/, defaults coming
+/// from the user settings.
+fn _search_options(state: &AppState, search_req: &request::SearchParam) -> (String, SearchOptions) {
+    let (query_string, filters) = extract_filters(&search_req.query);
+    let mut langs = filters.langs;
+    langs.extend(search_req.lang.as_deref().and_then(lang::normalize));
 
-    let index = state.index;
-    let searcher = index.reader.searcher();
+    let options = SearchOptions {
+        offset: search_req.offset.min(MAX_SEARCH_OFFSET),
+        limit: search_req.limit.min(MAX_SEARCH_LIMIT),
+        fuzzy: search_req.fuzzy.unwrap_or(state.user_settings.fuzzy_search),
+        prefix: search_req
+            .prefix
+            .unwrap_or(state.user_settings.prefix_search),
+        indexed_after: search_req.after,
+        indexed_before: search_req.before,
+        langs,
+        sites: filters.sites,
+        url_terms: filters.url_terms,
+    };
 
-    // Create a copy of the lenses for this search
-    let mut lenses = HashMap::new();
-    for entry in state.lenses.iter() {
-        lenses.insert(entry.key().clone(), entry.value().clone());
-    }
+    (query_string, options)
+}
 
-    let docs = Searcher::search_with_lens(
-        &lenses,
-        &index.reader,
-        &search_req.lenses,
-        &search_req.query,
-    );
+/// Read the stored fields for each of `docs`, along w/ a snippet if there's a
+/// generator & whatever we know about the page from the database.
+async fn _to_search_results(
+    state: &AppState,
+    docs: Vec<(f32, DocAddress)>,
+    snippets: Option<&SnippetGenerator>,
+) -> Vec<SearchResult> {
+    let fields = Searcher::doc_fields();
+    let searcher = state.index.reader.searcher();
 
     let mut results: Vec<SearchResult> = Vec::new();
     for (score, doc_addr) in docs {
@@ -277,18 +1169,349 @@ pub async fn search(state: AppState, search_req: request::SearchParam) -> Result
             description: description.as_text().unwrap().to_string(),
             url: url.as_text().unwrap().to_string(),
             score,
+            is_archived: false,
+            snippet: snippets.and_then(|generator| Searcher::snippet(generator, &retrieved)),
+            metadata: Default::default(),
+            favicon: state.favicons.get(domain.as_text().unwrap()),
         };
 
         results.push(result);
     }
 
+    // Flag results that were indexed from an archived snapshot & fill in the
+    // page metadata.
+    let doc_ids: Vec<String> = results.iter().map(|r| r.doc_id.clone()).collect();
+    let indexed = indexed_document::Entity::find()
+        .filter(indexed_document::Column::DocId.is_in(doc_ids))
+        .all(&state.db)
+        .await
+        .unwrap_or_default();
+    for result in results.iter_mut() {
+        if let Some(doc) = indexed.iter().find(|doc| doc.doc_id == result.doc_id) {
+            result.is_archived = doc.archived_at.is_some();
+            result.metadata = doc.page_metadata();
+        }
+    }
+
+    results
+}
+
+/// Search the user's indexed documents
+#[instrument(skip(state))]
+pub async fn search(state: AppState, search_req: request::SearchParam) -> Result<SearchResults> {
+    let started = Instant::now();
+
+    let index = &state.index;
+    let searcher = index.reader.searcher();
+
+    // Create a copy of the lenses for this search
+    let mut lenses = HashMap::new();
+    for entry in state.lenses.iter() {
+        lenses.insert(entry.key().clone(), entry.value().clone());
+    }
+
+    let (query_string, options) = _search_options(&state, &search_req);
+    let query = index.build_search_query(&lenses, &search_req.lenses, &query_string, &options);
+    let parse_time = started.elapsed();
+
+    let search_started = Instant::now();
+    let embedder = match search_req.mode {
+        request::SearchMode::Keyword => None,
+        _ => state.embedder.as_ref(),
+    };
+    let semantic_results = match embedder {
+        Some(embedder) => semantic::search(
+            &state,
+            embedder,
+            search_req.mode,
+            &lenses,
+            &search_req.lenses,
+            &query_string,
+            &options,
+        )
+        .await
+        .map_err(|err| log::error!("Semantic search failed, using keywords: {}", err))
+        .ok(),
+        None => None,
+    };
+    let (num_hits, docs) = semantic_results.unwrap_or_else(|| {
+        index.search_query(query.as_ref(), &lenses, &search_req.lenses, &options)
+    });
+    let facets = if search_req.facets {
+        match index.facet_counts(&lenses, &search_req.lenses, &query_string, &options) {
+            Ok(facets) => Some(facets),
+            Err(err) => {
+                log::error!("Unable to count facets: {}", err);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let search_time = search_started.elapsed();
+
+    let fetch_started = Instant::now();
+    let snippets = match index.snippet_generator(&query_string) {
+        Ok(generator) => Some(generator),
+        Err(err) => {
+            log::error!("Unable to create snippet generator: {}", err);
+            None
+        }
+    };
+
+    let results = _to_search_results(&state, docs, snippets.as_ref()).await;
+    let fetch_time = fetch_started.elapsed();
+
+    // Only count the first page, the rest is the same search.
+    if search_req.offset == 0 {
+        if let Err(err) = search_history::record(&state.db, &search_req.query).await {
+            log::error!("Unable to record search: {}", err);
+        }
+    }
+
+    let answer = answer::evaluate(&search_req.query);
+
     let meta = SearchMeta {
         query: search_req.query,
         num_docs: searcher.num_docs(),
-        wall_time_ms: 1000,
+        num_hits: num_hits as u64,
+        wall_time_ms: started.elapsed().as_millis() as u64,
+        timings: SearchTimings {
+            parse_ms: parse_time.as_millis() as u64,
+            search_ms: search_time.as_millis() as u64,
+            fetch_ms: fetch_time.as_millis() as u64,
+        },
+    };
+
+    Ok(SearchResults {
+        answer,
+        results,
+        meta,
+        facets,
+    })
+}
+
+/// Run a keyword search & explain how each result on the page was scored,
+/// along w/ how the query was parsed & which lens filters were applied.
+#[instrument(skip(state))]
+pub async fn explain_search(
+    state: AppState,
+    search_req: request::SearchParam,
+) -> Result<SearchExplanation> {
+    let fields = Searcher::doc_fields();
+    let index = &state.index;
+    let searcher = index.reader.searcher();
+
+    let mut lenses = HashMap::new();
+    for entry in state.lenses.iter() {
+        lenses.insert(entry.key().clone(), entry.value().clone());
+    }
+
+    let (query_string, options) = _search_options(&state, &search_req);
+    let query = index.build_search_query(&lenses, &search_req.lenses, &query_string, &options);
+    let (num_docs, docs) =
+        index.search_query(query.as_ref(), &lenses, &search_req.lenses, &options);
+
+    let mut results = Vec::new();
+    for (score, doc_addr) in docs {
+        let breakdown = index
+            .explain(query.as_ref(), &lenses, &search_req.lenses, doc_addr)
+            .map_err(|err| Error {
+                code: ErrorCode::InternalError,
+                message: err.to_string(),
+                data: None,
+            })?;
+
+        let doc = match searcher.doc(doc_addr) {
+            Ok(doc) => doc,
+            Err(err) => {
+                log::error!("Unable to read doc {:?}: {}", doc_addr, err);
+                continue;
+            }
+        };
+        let text = |field| {
+            doc.get_first(field)
+                .and_then(|value| value.as_text())
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        results.push(ResultExplanation {
+            doc_id: text(fields.id),
+            url: text(fields.url),
+            title: text(fields.title),
+            score,
+            text_score: breakdown.explanation.value,
+            lens_boost: breakdown.lens_boost,
+            recency_boost: breakdown.recency_boost,
+            explanation: breakdown.explanation,
+        });
+    }
+
+    let applied_lenses = search_req
+        .lenses
+        .iter()
+        .filter_map(|name| lenses.get(name))
+        .map(|lens| LensFilter {
+            name: lens.name.clone(),
+            domains: lens.domains.clone(),
+            urls: lens.urls.clone(),
+            boost: lens.boost(),
+        })
+        .collect();
+
+    Ok(SearchExplanation {
+        query: query_string,
+        parsed_query: format!("{:?}", query),
+        sites: options.sites,
+        url_terms: options.url_terms,
+        langs: options.langs,
+        lenses: applied_lenses,
+        num_docs: num_docs as u64,
+        results,
+    })
+}
+
+/// Write every result for a search to a file, returns the number written.
+#[instrument(skip(state))]
+pub async fn export_results(state: AppState, param: request::ExportResultsParam) -> Result<u64> {
+    let mut lenses = HashMap::new();
+    for entry in state.lenses.iter() {
+        lenses.insert(entry.key().clone(), entry.value().clone());
+    }
+
+    let (query_string, filters) = extract_filters(&param.query);
+    let options = SearchOptions {
+        fuzzy: state.user_settings.fuzzy_search,
+        langs: filters.langs,
+        sites: filters.sites,
+        url_terms: filters.url_terms,
+        ..Default::default()
     };
 
-    Ok(SearchResults { results, meta })
+    // Never clobber an existing file
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&param.path)
+        .map_err(|err| Error {
+            code: ErrorCode::InvalidParams,
+            message: format!("Unable to create {}: {}", param.path, err),
+            data: None,
+        })?;
+
+    // Reads every matching document, keep it off the async runtime.
+    let index = state.index.clone();
+    let applied_lens = param.lenses.clone();
+    let format = param.format;
+    let res = tokio::task::spawn_blocking(move || {
+        export::export(
+            &index,
+            &lenses,
+            &applied_lens,
+            &query_string,
+            &options,
+            format,
+            std::io::BufWriter::new(file),
+        )
+    })
+    .await;
+
+    let num_exported = match res {
+        Ok(Ok(num_exported)) => num_exported,
+        Ok(Err(err)) => {
+            return Err(Error {
+                code: ErrorCode::InternalError,
+                message: err.to_string(),
+                data: None,
+            })
+        }
+        Err(err) => {
+            return Err(Error {
+                code: ErrorCode::InternalError,
+                message: err.to_string(),
+                data: None,
+            })
+        }
+    };
+
+    log::info!("exported {} results to {}", num_exported, param.path);
+    Ok(num_exported as u64)
+}
+
+/// Snapshot the database & index into a zip archive at `path`, returns the
+/// size of the archive in bytes.
+#[instrument(skip(state, config))]
+pub async fn create_backup(state: AppState, config: Config, path: String) -> Result<u64> {
+    // Copies the whole library, keep it off the async runtime.
+    let index = state.index.clone();
+    let archive = std::path::PathBuf::from(&path);
+    let res = tokio::task::spawn_blocking(move || {
+        backup::create(&index, &config.db_file(), &config.index_dir(), &archive)
+    })
+    .await;
+
+    match res {
+        Ok(Ok(stats)) => {
+            log::info!(
+                "backed up {} index files to {} ({} bytes)",
+                stats.num_index_files,
+                path,
+                stats.size
+            );
+            Ok(stats.size)
+        }
+        Ok(Err(err)) => Err(Error {
+            code: ErrorCode::InternalError,
+            message: format!("Unable to create backup {}: {}", path, err),
+            data: None,
+        }),
+        Err(err) => Err(Error {
+            code: ErrorCode::InternalError,
+            message: err.to_string(),
+            data: None,
+        }),
+    }
+}
+
+/// Unpack a backup to replace the database & index w/ on the next startup.
+#[instrument(skip(config))]
+pub async fn restore_backup(config: Config, path: String) -> Result<()> {
+    let archive = std::path::PathBuf::from(&path);
+    let restore_dir = config.restore_dir();
+    let res =
+        tokio::task::spawn_blocking(move || backup::stage_restore(&archive, &restore_dir)).await;
+
+    match res {
+        Ok(Ok(_)) => {
+            log::info!("staged backup {}, restored on next startup", path);
+            Ok(())
+        }
+        Ok(Err(err)) => Err(Error {
+            code: ErrorCode::InvalidParams,
+            message: format!("Unable to restore backup {}: {}", path, err),
+            data: None,
+        }),
+        Err(err) => Err(Error {
+            code: ErrorCode::InternalError,
+            message: err.to_string(),
+            data: None,
+        }),
+    }
+}
+
+/// Past searches starting w/ `prefix`, most frequent & recent first.
+#[instrument(skip(state))]
+pub async fn query_suggestions(state: AppState, prefix: String) -> Result<Vec<String>> {
+    let suggestions = search_history::suggestions(&state.db, &prefix, MAX_SUGGESTIONS)
+        .await
+        .map_err(|err| Error {
+            code: ErrorCode::InternalError,
+            message: err.to_string(),
+            data: None,
+        })?;
+
+    Ok(suggestions.into_iter().map(|model| model.query).collect())
 }
 
 /// Search the user's installed lenses
@@ -338,6 +1561,118 @@ pub async fn toggle_pause(state: AppState) -> jsonrpc_core::Result<AppStatus> {
     _get_current_status(state.clone()).await
 }
 
+/// Stop crawling a single domain, w/o pausing everything else.
+#[instrument(skip(state))]
+pub async fn pause_domain(state: AppState, domain: String) -> jsonrpc_core::Result<AppStatus> {
+    let domain = domain.trim().to_lowercase();
+    if domain.is_empty() {
+        return Err(Error {
+            code: ErrorCode::InvalidParams,
+            message: "Invalid domain".to_string(),
+            data: None,
+        });
+    }
+
+    state.paused_domains.insert(domain);
+    _get_current_status(state).await
+}
+
+#[instrument(skip(state))]
+pub async fn resume_domain(state: AppState, domain: String) -> jsonrpc_core::Result<AppStatus> {
+    state.paused_domains.remove(&domain.trim().to_lowercase());
+    _get_current_status(state).await
+}
+
+/// Stop crawling the domains & URLs of a single lens.
+#[instrument(skip(state))]
+pub async fn pause_lens(state: AppState, name: String) -> jsonrpc_core::Result<AppStatus> {
+    if !state.lenses.contains_key(&name) {
+        return Err(Error {
+            code: ErrorCode::InvalidParams,
+            message: format!("Unknown lens: {}", name),
+            data: None,
+        });
+    }
+
+    state.paused_lenses.insert(name);
+    _get_current_status(state).await
+}
+
+#[instrument(skip(state))]
+pub async fn resume_lens(state: AppState, name: String) -> jsonrpc_core::Result<AppStatus> {
+    state.paused_lenses.remove(&name);
+    _get_current_status(state).await
+}
+
+/// Settings a plugin takes & the values the user set for them.
+#[instrument(skip(config))]
+pub async fn plugin_settings(config: Config, name: String) -> Result<Vec<PluginSettingResult>> {
+    let plugin = _find_plugin(&config, &name)?;
+    let values = Config::load_settings_for_plugin(&name).map_err(|err| Error {
+        code: ErrorCode::InternalError,
+        message: err.to_string(),
+        data: None,
+    })?;
+
+    Ok(plugin
+        .setting_schemas()
+        .into_iter()
+        .map(|(key, schema)| PluginSettingResult {
+            value: values.get(&key).cloned(),
+            name: key,
+            schema,
+        })
+        .collect())
+}
+
+/// Save the settings of a plugin & restart it so it picks them up.
+#[instrument(skip(state, config))]
+pub async fn set_plugin_settings(
+    state: AppState,
+    config: Config,
+    param: request::SetPluginSettingsParam,
+) -> Result<()> {
+    let plugin = _find_plugin(&config, &param.name)?;
+    let schemas = plugin.setting_schemas();
+    if let Some(unknown) = param
+        .settings
+        .keys()
+        .find(|key| !schemas.contains_key(*key))
+    {
+        return Err(Error {
+            code: ErrorCode::InvalidParams,
+            message: format!("Plugin {} has no setting {}", param.name, unknown),
+            data: None,
+        });
+    }
+
+    // Empty values go back to the default.
+    let values: HashMap<String, String> = param
+        .settings
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .collect();
+    Config::save_settings_for_plugin(&param.name, &values).map_err(|err| Error {
+        code: ErrorCode::InternalError,
+        message: format!("Unable to save settings: {}", err),
+        data: None,
+    })?;
+
+    if let Some(cmd_tx) = &*state.plugin_cmd_tx.lock().await {
+        let _ = cmd_tx.send(PluginCommand::Reload(param.name)).await;
+    }
+
+    Ok(())
+}
+
+fn _find_plugin(config: &Config, name: &str) -> Result<plugin::PluginConfig> {
+    plugin::find_plugin(config, name).ok_or_else(|| Error {
+        code: ErrorCode::InvalidParams,
+        message: format!("Plugin {} not found", name),
+        data: None,
+    })
+}
+
 #[instrument(skip(state))]
 pub async fn toggle_plugin(state: AppState, name: String) -> jsonrpc_core::Result<()> {
     // Find the plugin
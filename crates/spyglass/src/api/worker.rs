@@ -0,0 +1,140 @@
+//! API used by remote crawl workers, see `libspyglass::remote`.
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::json;
+use tokio::sync::broadcast;
+
+use libspyglass::remote::{self, LEASE_PATH, RESULT_PATH};
+use libspyglass::state::AppState;
+use libspyglass::task::AppShutdown;
+use shared::config::WorkerApiSettings;
+use shared::request::{LeaseTasksParam, WorkerResultParam};
+
+use super::auth::is_authorized;
+use super::http::read_body;
+
+/// Max size of a request body, results carry the parsed page content.
+const MAX_REQUEST_BYTES: usize = 32 * 1024 * 1024;
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap_or_default()
+}
+
+fn error_response(status: StatusCode, reason: &str) -> Response<Body> {
+    json_response(status, json!({ "error": reason }))
+}
+
+#[tracing::instrument(skip(state, token, req), fields(path = %req.uri().path()))]
+async fn handle(
+    state: AppState,
+    token: Arc<String>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    if !is_authorized(&req, &token) {
+        return Ok(error_response(StatusCode::UNAUTHORIZED, "Invalid token"));
+    }
+
+    let path = req.uri().path().to_string();
+    if req.method() != Method::POST || (path != LEASE_PATH && path != RESULT_PATH) {
+        return Ok(error_response(
+            StatusCode::NOT_FOUND,
+            "Unsupported endpoint",
+        ));
+    }
+
+    let body = match read_body(req.into_body(), MAX_REQUEST_BYTES).await {
+        Ok(body) => body,
+        Err(err) => return Ok(error_response(StatusCode::BAD_REQUEST, &err.to_string())),
+    };
+
+    if path == LEASE_PATH {
+        let param: LeaseTasksParam = match serde_json::from_slice(&body) {
+            Ok(param) => param,
+            Err(err) => return Ok(error_response(StatusCode::BAD_REQUEST, &err.to_string())),
+        };
+
+        return Ok(match remote::lease_tasks(&state, param.limit).await {
+            Ok(tasks) => json_response(StatusCode::OK, json!(tasks)),
+            Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()),
+        });
+    }
+
+    let param: WorkerResultParam = match serde_json::from_slice(&body) {
+        Ok(param) => param,
+        Err(err) => return Ok(error_response(StatusCode::BAD_REQUEST, &err.to_string())),
+    };
+
+    Ok(match remote::handle_result(&state, param).await {
+        Ok(()) => json_response(StatusCode::OK, json!({})),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, &err.to_string()),
+    })
+}
+
+/// Serve the worker API on all interfaces until the app shuts down.
+pub async fn start_worker_api(
+    state: AppState,
+    settings: WorkerApiSettings,
+    mut shutdown_rx: broadcast::Receiver<AppShutdown>,
+) {
+    if settings.token.is_empty() {
+        log::error!("Worker API requires a token, not starting");
+        return;
+    }
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], settings.port));
+    let builder = match Server::try_bind(&addr) {
+        Ok(builder) => builder,
+        Err(err) => {
+            log::error!("Unable to start worker API on {}: {}", addr, err);
+            return;
+        }
+    };
+
+    let token = Arc::new(settings.token);
+    let make_svc = make_service_fn(move |_conn| {
+        let state = state.clone();
+        let token = token.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(state.clone(), token.clone(), req)
+            }))
+        }
+    });
+
+    log::info!("Worker API listening on http://{}", addr);
+    let server = builder.serve(make_svc).with_graceful_shutdown(async move {
+        let _ = shutdown_rx.recv().await;
+    });
+
+    if let Err(err) = server.await {
+        log::error!("Worker API error: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::{Body, Request};
+
+    use super::is_authorized;
+
+    #[test]
+    fn test_is_authorized() {
+        let req = Request::builder()
+            .header("authorization", "Bearer secret")
+            .body(Body::empty())
+            .unwrap();
+        assert!(is_authorized(&req, "secret"));
+        assert!(!is_authorized(&req, "other"));
+
+        let req = Request::builder().body(Body::empty()).unwrap();
+        assert!(!is_authorized(&req, "secret"));
+    }
+}
@@ -0,0 +1,387 @@
+//! Backups of the database & index in a single zip archive, e.g. to move a
+//! library to another machine. Restoring replaces everything on disk, so the
+//! archive is only unpacked next to the data & swapped in on the next startup,
+//! before either store is opened.
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::search::{recovery, Searcher};
+
+/// Name of the database in the archive.
+const DB_ENTRY: &str = "db.sqlite";
+/// Index files are stored under this folder in the archive.
+const INDEX_DIR_ENTRY: &str = "index";
+/// Tantivy's list of committed segments, an index can't be opened w/o it.
+const INDEX_META_FILE: &str = "meta.json";
+/// Background merges can remove segment files while they're being copied,
+/// the snapshot is retried this many times before giving up.
+const MAX_SNAPSHOT_ATTEMPTS: usize = 3;
+/// Cap on the size of a single file unpacked from a backup.
+const MAX_RESTORE_ENTRY_BYTES: u64 = 16 * 1024 * 1024 * 1024;
+/// Cap on the size of everything unpacked from a backup.
+const MAX_RESTORE_BYTES: u64 = 64 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Default)]
+pub struct BackupStats {
+    /// Size of the archive in bytes.
+    pub size: u64,
+    /// Index files copied into the archive.
+    pub num_index_files: usize,
+}
+
+/// Snapshot the database & index into a zip archive at `archive`. Index
+/// writes are blocked while the snapshot is taken, so the two line up.
+pub fn create(
+    index: &Searcher,
+    db_file: &Path,
+    index_dir: &Path,
+    archive: &Path,
+) -> anyhow::Result<BackupStats> {
+    // Only replace an existing archive once the new one is complete.
+    let partial = archive.with_extension("partial");
+
+    let mut attempt = 1;
+    let mut stats = loop {
+        match snapshot(index, db_file, index_dir, &partial) {
+            Ok(stats) => break stats,
+            Err(err) if attempt < MAX_SNAPSHOT_ATTEMPTS && is_not_found(&err) => {
+                log::warn!("index changed during backup, retrying: {}", err);
+                attempt += 1;
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&partial);
+                return Err(err);
+            }
+        }
+    };
+
+    fs::rename(&partial, archive)?;
+    stats.size = fs::metadata(archive)?.len();
+    Ok(stats)
+}
+
+fn snapshot(
+    index: &Searcher,
+    db_file: &Path,
+    index_dir: &Path,
+    archive: &Path,
+) -> anyhow::Result<BackupStats> {
+    // Holding on to the writer keeps crawls, deletes, etc. from touching the
+    // index until the snapshot is done.
+    let mut writer = index
+        .writer
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Unable to get index lock"))?;
+    index.commit_writer(&mut writer)?;
+
+    let mut index_files = vec![PathBuf::from(INDEX_META_FILE)];
+    if index_dir.join(recovery::ANALYZER_FILE).exists() {
+        index_files.push(PathBuf::from(recovery::ANALYZER_FILE));
+    }
+    for segment in index.index.searchable_segment_metas()? {
+        index_files.extend(segment.list_files());
+    }
+
+    // Consistent copy of the database, even w/ other connections writing to it.
+    let db_snapshot = archive.with_extension("sqlite");
+    let _ = fs::remove_file(&db_snapshot);
+    Connection::open(db_file)?.execute(
+        "VACUUM INTO ?1",
+        [db_snapshot.to_string_lossy().to_string()],
+    )?;
+
+    let res = write_archive(archive, &db_snapshot, index_dir, &index_files);
+    let _ = fs::remove_file(&db_snapshot);
+    res?;
+
+    Ok(BackupStats {
+        size: 0,
+        num_index_files: index_files.len(),
+    })
+}
+
+fn write_archive(
+    archive: &Path,
+    db_snapshot: &Path,
+    index_dir: &Path,
+    index_files: &[PathBuf],
+) -> anyhow::Result<()> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(archive)?));
+    // Indexes easily go past the 4GB limit of plain zip files.
+    let options = FileOptions::default().large_file(true);
+
+    zip.start_file(
+        DB_ENTRY,
+        options.compression_method(CompressionMethod::Deflated),
+    )?;
+    io::copy(&mut File::open(db_snapshot)?, &mut zip)?;
+
+    // Segments are already compressed, not worth doing it again.
+    for file in index_files {
+        let name = format!("{}/{}", INDEX_DIR_ENTRY, file.to_string_lossy());
+        zip.start_file(name, options.compression_method(CompressionMethod::Stored))?;
+        io::copy(&mut File::open(index_dir.join(file))?, &mut zip)?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<io::Error>()
+        .map(|err| err.kind() == io::ErrorKind::NotFound)
+        .unwrap_or(false)
+}
+
+/// Unpack a backup into `restore_dir`, to be swapped in by
+/// `apply_pending_restore` on the next startup.
+pub fn stage_restore(archive: &Path, restore_dir: &Path) -> anyhow::Result<()> {
+    let mut zip = ZipArchive::new(BufReader::new(File::open(archive)?))?;
+    let index_meta = format!("{}/{}", INDEX_DIR_ENTRY, INDEX_META_FILE);
+    for required in [DB_ENTRY, index_meta.as_str()] {
+        if zip.by_name(required).is_err() {
+            return Err(anyhow::anyhow!(
+                "{} is not a backup, missing {}",
+                archive.display(),
+                required
+            ));
+        }
+    }
+
+    // Only replace a previously staged backup once this one is fully unpacked.
+    let partial = restore_dir.with_extension("partial");
+    if partial.exists() {
+        fs::remove_dir_all(&partial)?;
+    }
+    fs::create_dir_all(&partial)?;
+
+    if let Err(err) = unpack(&mut zip, &partial) {
+        let _ = fs::remove_dir_all(&partial);
+        return Err(err);
+    }
+
+    if restore_dir.exists() {
+        fs::remove_dir_all(restore_dir)?;
+    }
+    // Left behind if a previous restore crashed before cleaning up.
+    let _ = fs::remove_file(restore_marker(restore_dir));
+    fs::rename(&partial, restore_dir)?;
+    Ok(())
+}
+
+fn unpack<R: io::Read + io::Seek>(zip: &mut ZipArchive<R>, dest: &Path) -> anyhow::Result<()> {
+    let mut total: u64 = 0;
+    for idx in 0..zip.len() {
+        let mut entry = zip.by_index(idx)?;
+        // Skip anything that would end up outside the restore dir.
+        let path = match entry.enclosed_name() {
+            Some(name) if entry.is_file() => dest.join(name),
+            _ => continue,
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Sizes in the archive can't be trusted, stop reading at the cap.
+        let limit = MAX_RESTORE_ENTRY_BYTES.min(MAX_RESTORE_BYTES - total);
+        let written = io::copy(&mut (&mut entry).take(limit + 1), &mut File::create(&path)?)?;
+        if written > limit {
+            return Err(anyhow::anyhow!(
+                "backup is too large to restore, stopped at {}",
+                entry.name()
+            ));
+        }
+        total += written;
+    }
+
+    Ok(())
+}
+
+/// Exists while a staged backup is being swapped in, so an interrupted restore
+/// picks up where it left off instead of starting over.
+fn restore_marker(restore_dir: &Path) -> PathBuf {
+    restore_dir.with_extension("applying")
+}
+
+/// Replace the database & index w/ a backup staged by `stage_restore`. Needs
+/// to run before either is opened. Returns false if there was nothing to
+/// restore. Safe to run again if it was interrupted, each piece is only
+/// replaced while it's still staged.
+pub fn apply_pending_restore(
+    restore_dir: &Path,
+    db_file: &Path,
+    index_dir: &Path,
+) -> anyhow::Result<bool> {
+    let marker = restore_marker(restore_dir);
+    if !restore_dir.exists() {
+        let _ = fs::remove_file(&marker);
+        return Ok(false);
+    }
+
+    let staged_index = restore_dir.join(INDEX_DIR_ENTRY);
+    let staged_db = restore_dir.join(DB_ENTRY);
+    // Pieces can only be missing if a previous attempt already moved them.
+    if !marker.exists() {
+        if !staged_index.exists() || !staged_db.exists() {
+            log::warn!("Discarding incomplete restore {}", restore_dir.display());
+            fs::remove_dir_all(restore_dir)?;
+            return Ok(false);
+        }
+        File::create(&marker)?;
+    }
+
+    if staged_index.exists() {
+        if index_dir.exists() {
+            fs::remove_dir_all(index_dir)?;
+        }
+        fs::rename(&staged_index, index_dir)?;
+    }
+
+    if staged_db.exists() {
+        // Leftover WAL files would be replayed on top of the restored database.
+        for suffix in ["-wal", "-shm"] {
+            let mut path = db_file.as_os_str().to_owned();
+            path.push(suffix);
+            let _ = fs::remove_file(path);
+        }
+        fs::rename(&staged_db, db_file)?;
+    }
+
+    fs::remove_dir_all(restore_dir)?;
+    fs::remove_file(&marker)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use rusqlite::Connection;
+    use tantivy::Index;
+
+    use super::{apply_pending_restore, create, restore_marker, stage_restore};
+    use crate::search::{IndexPath, Searcher};
+
+    #[test]
+    fn test_backup_and_restore() {
+        let base = std::env::temp_dir().join(format!("spyglass-backup-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+
+        // Library to back up
+        let src = base.join("src");
+        std::fs::create_dir_all(src.join("index")).unwrap();
+        let db = Connection::open(src.join("db.sqlite")).unwrap();
+        db.execute_batch(
+            "CREATE TABLE indexed_document (url TEXT);
+             INSERT INTO indexed_document VALUES ('https://example.com');",
+        )
+        .unwrap();
+
+        let searcher = Searcher::with_index(&IndexPath::LocalPath(src.join("index")));
+        {
+            let mut writer = searcher.writer.lock().unwrap();
+            Searcher::add_document(
+                &mut writer,
+                "Example",
+                "",
+                "example.com",
+                "https://example.com",
+                "Example content",
+                "",
+                None,
+            )
+            .unwrap();
+        }
+        searcher.mark_pending(1);
+
+        let archive = base.join("backup.zip");
+        let stats = create(
+            &searcher,
+            &src.join("db.sqlite"),
+            &src.join("index"),
+            &archive,
+        )
+        .unwrap();
+        assert!(stats.size > 0);
+        assert!(stats.num_index_files > 1);
+        // Pending writes are part of the backup
+        assert_eq!(searcher.num_pending(), 0);
+
+        // Restore into an empty library
+        let dest = base.join("dest");
+        std::fs::create_dir_all(&dest).unwrap();
+        stage_restore(&archive, &dest.join("restore")).unwrap();
+        assert!(apply_pending_restore(
+            &dest.join("restore"),
+            &dest.join("db.sqlite"),
+            &dest.join("index")
+        )
+        .unwrap());
+        assert!(!dest.join("restore").exists());
+
+        let restored = Connection::open(dest.join("db.sqlite")).unwrap();
+        let num_rows: i64 = restored
+            .query_row("SELECT COUNT(*) FROM indexed_document", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(num_rows, 1);
+
+        let index = Index::open_in_dir(dest.join("index")).unwrap();
+        let reader = index.reader().unwrap();
+        assert_eq!(reader.searcher().num_docs(), 1);
+
+        // Nothing left to restore
+        assert!(!apply_pending_restore(
+            &dest.join("restore"),
+            &dest.join("db.sqlite"),
+            &dest.join("index")
+        )
+        .unwrap());
+
+        // Not a backup
+        assert!(stage_restore(&src.join("db.sqlite"), &dest.join("restore")).is_err());
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_interrupted_restore() {
+        let base = std::env::temp_dir().join(format!("spyglass-restore-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        let restore = base.join("restore");
+        let db = base.join("db.sqlite");
+        let index = base.join("index");
+
+        // Crashed after the index was swapped in, only the database is left
+        std::fs::create_dir_all(&index).unwrap();
+        std::fs::write(index.join("meta.json"), "restored").unwrap();
+        std::fs::write(&db, "old").unwrap();
+        std::fs::create_dir_all(&restore).unwrap();
+        std::fs::write(restore.join("db.sqlite"), "restored").unwrap();
+        std::fs::write(restore_marker(&restore), "").unwrap();
+
+        assert!(apply_pending_restore(&restore, &db, &index).unwrap());
+        assert_eq!(std::fs::read_to_string(&db).unwrap(), "restored");
+        assert_eq!(
+            std::fs::read_to_string(index.join("meta.json")).unwrap(),
+            "restored"
+        );
+        assert!(!restore.exists());
+        assert!(!restore_marker(&restore).exists());
+
+        // Incomplete & not in progress, discarded w/o touching anything
+        std::fs::create_dir_all(&restore).unwrap();
+        std::fs::write(restore.join("db.sqlite"), "partial").unwrap();
+        assert!(!apply_pending_restore(&restore, &db, &index).unwrap());
+        assert!(!restore.exists());
+        assert_eq!(std::fs::read_to_string(&db).unwrap(), "restored");
+        assert!(index.join("meta.json").exists());
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+}
@@ -0,0 +1,125 @@
+//! Authenticated crawling. Cookies, tokens & headers configured per domain in
+//! the credentials file (see `Config::credentials_file`) are added to every
+//! request to that domain. Redirects to another host aren't followed for
+//! these requests, see `HTTPClient::with_same_host_redirects`.
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, COOKIE};
+
+use shared::config::{Credentials, DomainCredentials};
+
+/// Credentials for `host`, falling back to the closest parent domain.
+pub fn find<'a>(credentials: &'a Credentials, host: &str) -> Option<&'a DomainCredentials> {
+    let mut domain = host;
+    loop {
+        if let Some(found) = credentials.get(domain) {
+            return Some(found);
+        }
+
+        domain = domain.split_once('.')?.1;
+    }
+}
+
+/// Request headers for a set of credentials. Invalid headers are skipped.
+pub fn headers(credentials: &DomainCredentials) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    for (name, value) in credentials.headers.iter() {
+        match (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            (Ok(name), Ok(value)) => {
+                headers.insert(name, value);
+            }
+            _ => log::warn!("Skipping invalid credentials header: {}", name),
+        }
+    }
+
+    if let Some(token) = &credentials.bearer_token {
+        match HeaderValue::from_str(&format!("Bearer {}", token)) {
+            Ok(value) => {
+                headers.insert(AUTHORIZATION, value);
+            }
+            Err(_) => log::warn!("Skipping invalid bearer token"),
+        }
+    }
+
+    if !credentials.cookies.is_empty() {
+        let mut cookies: Vec<String> = credentials
+            .cookies
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        cookies.sort();
+
+        match HeaderValue::from_str(&cookies.join("; ")) {
+            Ok(value) => {
+                headers.insert(COOKIE, value);
+            }
+            Err(_) => log::warn!("Skipping invalid cookies"),
+        }
+    }
+
+    // Keep these out of logs & debug output.
+    for value in headers.values_mut() {
+        value.set_sensitive(true);
+    }
+
+    headers
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use reqwest::header::{AUTHORIZATION, COOKIE};
+    use shared::config::{Credentials, DomainCredentials};
+
+    use super::{find, headers};
+
+    #[test]
+    fn test_find() {
+        let mut credentials = Credentials::new();
+        credentials.insert(
+            "example.com".to_string(),
+            DomainCredentials {
+                bearer_token: Some("parent".to_string()),
+                ..Default::default()
+            },
+        );
+        credentials.insert(
+            "wiki.example.com".to_string(),
+            DomainCredentials {
+                bearer_token: Some("wiki".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let token = |host: &str| find(&credentials, host).and_then(|c| c.bearer_token.clone());
+        assert_eq!(token("wiki.example.com"), Some("wiki".to_string()));
+        assert_eq!(token("docs.example.com"), Some("parent".to_string()));
+        assert_eq!(token("example.com"), Some("parent".to_string()));
+        assert_eq!(token("example.org"), None);
+    }
+
+    #[test]
+    fn test_headers() {
+        let credentials = DomainCredentials {
+            cookies: HashMap::from([
+                ("session".to_string(), "abc".to_string()),
+                ("csrf".to_string(), "123".to_string()),
+            ]),
+            bearer_token: Some("secret".to_string()),
+            headers: HashMap::from([
+                ("X-Api-Key".to_string(), "key".to_string()),
+                ("Bad Header".to_string(), "value".to_string()),
+            ]),
+        };
+
+        let headers = headers(&credentials);
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers[AUTHORIZATION], "Bearer secret");
+        assert_eq!(headers[COOKIE], "csrf=123; session=abc");
+        assert_eq!(headers["x-api-key"], "key");
+        assert!(headers[AUTHORIZATION].is_sensitive());
+    }
+}
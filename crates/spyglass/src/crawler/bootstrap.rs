@@ -126,6 +126,7 @@ pub async fn bootstrap(
     let mut count: usize = 0;
     let overrides = crawl_queue::EnqueueSettings {
         crawl_type: crawl_queue::CrawlType::Bootstrap,
+        ..crawl_queue::EnqueueSettings::for_seeds_of(lens)
     };
 
     // Stream pages of URLs from the CDX server & add them to our crawl queue.
@@ -157,8 +158,7 @@ pub async fn bootstrap(
             &[url.to_string()],
             &Vec::new(),
             settings,
-            // No overrides required
-            &Default::default(),
+            &crawl_queue::EnqueueSettings::for_seeds_of(lens),
         )
         .await?;
         count += 1;
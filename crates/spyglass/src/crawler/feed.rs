@@ -0,0 +1,181 @@
+//! Poll subscribed RSS/Atom feeds & queue up new entries for crawling, which
+//! is the easiest way to keep blogs & news sites fresh. Feeds are listed in
+//! lenses or the user settings, see `Job::PollFeeds` for the schedule.
+use std::collections::BTreeSet;
+
+use feed_rs::model::Entry;
+use reqwest::StatusCode;
+use url::Url;
+
+use entities::models::crawl_queue;
+use entities::models::feed_entry::{self, FeedItem};
+
+use crate::fetch::HTTPClient;
+use crate::state::AppState;
+
+/// Link to the page for an entry. Atom entries can link to several things
+/// (comments, the entry itself, etc.), prefer the alternate/plain link.
+fn entry_link(feed_url: &Url, entry: &Entry) -> Option<Url> {
+    let href = entry
+        .links
+        .iter()
+        .find(|link| link.rel.as_deref().map_or(true, |rel| rel == "alternate"))
+        .or_else(|| entry.links.first())
+        .map(|link| link.href.as_str())
+        // RSS guids are usually the permalink
+        .or_else(|| Some(entry.id.as_str()).filter(|id| id.starts_with("http")))?;
+
+    let url = feed_url.join(href.trim()).ok()?;
+    match url.scheme() {
+        "http" | "https" => Some(url),
+        _ => None,
+    }
+}
+
+/// Parse the entries in an RSS or Atom feed.
+pub fn parse(feed_url: &Url, body: &[u8]) -> anyhow::Result<Vec<FeedItem>> {
+    let feed = feed_rs::parser::parse(body)?;
+
+    let items = feed
+        .entries
+        .iter()
+        .filter_map(|entry| {
+            let url = entry_link(feed_url, entry)?;
+            // Entries don't always list an author, fall back to the feed's.
+            let author = entry
+                .authors
+                .first()
+                .or_else(|| feed.authors.first())
+                .map(|person| person.name.trim().to_string())
+                .filter(|name| !name.is_empty());
+
+            Some(FeedItem {
+                url: url.to_string(),
+                title: entry.title.as_ref().map(|title| title.content.clone()),
+                author,
+                published_at: entry.published.or(entry.updated),
+            })
+        })
+        .collect();
+
+    Ok(items)
+}
+
+/// Feeds listed in the user settings & enabled lenses.
+fn subscribed_feeds(state: &AppState) -> BTreeSet<String> {
+    let mut feeds: BTreeSet<String> = state.user_settings.feeds.iter().cloned().collect();
+    for lens in state.lenses.iter() {
+        if lens.is_enabled {
+            feeds.extend(lens.feeds.iter().cloned());
+        }
+    }
+
+    feeds
+}
+
+/// Fetch a feed & queue up its new entries. Returns the number of new entries.
+async fn poll_feed(state: &AppState, client: &HTTPClient, feed_url: &str) -> anyhow::Result<usize> {
+    let url = Url::parse(feed_url)?;
+    let res = client.get(&url).await?;
+    if res.status() != StatusCode::OK {
+        return Err(anyhow::anyhow!("Unexpected status: {}", res.status()));
+    }
+
+    let body = res.bytes().await?;
+    client.consume(body.len()).await;
+    let items = parse(&url, &body)?;
+    let new_urls = feed_entry::add_new(&state.db, feed_url, &items).await?;
+    if !new_urls.is_empty() {
+        // No lenses, entries are crawled even if they aren't in one.
+        crawl_queue::enqueue_all(
+            &state.db,
+            &new_urls,
+            &[],
+            &state.user_settings,
+            &Default::default(),
+        )
+        .await?;
+    }
+
+    Ok(new_urls.len())
+}
+
+/// Poll all the subscribed feeds. Returns the number of new entries.
+pub async fn poll_all(state: &AppState) -> usize {
+    let client = HTTPClient::with_settings(&state.user_settings);
+
+    let mut num_new = 0;
+    for feed_url in subscribed_feeds(state) {
+        match poll_feed(state, &client, &feed_url).await {
+            Ok(count) => {
+                log::debug!("found {} new entries in <{}>", count, feed_url);
+                num_new += count;
+            }
+            Err(err) => log::warn!("Unable to poll feed <{}>: {}", feed_url, err),
+        }
+    }
+
+    num_new
+}
+
+#[cfg(test)]
+mod test {
+    use url::Url;
+
+    use super::parse;
+
+    #[test]
+    fn test_parse_rss() {
+        let rss = r#"<?xml version="1.0"?>
+            <rss version="2.0">
+                <channel>
+                    <title>Example Blog</title>
+                    <link>https://example.com/</link>
+                    <item>
+                        <title>First post</title>
+                        <link>https://example.com/posts/first</link>
+                        <author>jane@example.com (Jane)</author>
+                        <pubDate>Mon, 19 Sep 2022 10:00:00 GMT</pubDate>
+                    </item>
+                    <item>
+                        <title>No link</title>
+                        <guid isPermaLink="true">https://example.com/posts/second</guid>
+                    </item>
+                </channel>
+            </rss>"#;
+
+        let feed_url = Url::parse("https://example.com/feed.xml").unwrap();
+        let items = parse(&feed_url, rss.as_bytes()).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].url, "https://example.com/posts/first");
+        assert_eq!(items[0].title, Some("First post".to_string()));
+        assert!(items[0].published_at.is_some());
+        assert_eq!(items[1].url, "https://example.com/posts/second");
+    }
+
+    #[test]
+    fn test_parse_atom() {
+        let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+                <title>Example Blog</title>
+                <id>urn:uuid:60a76c80-d399-11d9-b93C-0003939e0af6</id>
+                <updated>2022-09-19T10:00:00Z</updated>
+                <author><name>Jane</name></author>
+                <entry>
+                    <title>Relative link</title>
+                    <id>urn:uuid:1225c695-cfb8-4ebb-aaaa-80da344efa6a</id>
+                    <link rel="replies" href="/posts/relative#comments"/>
+                    <link rel="alternate" href="/posts/relative"/>
+                    <updated>2022-09-19T10:00:00Z</updated>
+                </entry>
+            </feed>"#;
+
+        let feed_url = Url::parse("https://example.com/atom.xml").unwrap();
+        let items = parse(&feed_url, atom.as_bytes()).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].url, "https://example.com/posts/relative");
+        // Falls back to the feed author & updated date
+        assert_eq!(items[0].author, Some("Jane".to_string()));
+        assert!(items[0].published_at.is_some());
+    }
+}
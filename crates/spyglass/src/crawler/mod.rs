@@ -1,26 +1,44 @@
 use std::collections::HashSet;
+use std::sync::Arc;
 
 use addr::parse_domain_name;
 use chrono::prelude::*;
 use chrono::Duration;
+use reqwest::header::{
+    HeaderMap, HeaderName, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
 use reqwest::StatusCode;
 use sha2::{Digest, Sha256};
 use url::{Host, Url};
 
-use entities::models::{crawl_queue, fetch_history};
+use entities::models::{crawl_queue, fetch_history, indexed_document};
+use entities::normalize::normalize_url;
 use entities::sea_orm::prelude::*;
 use entities::sea_orm::DatabaseConnection;
+use shared::config::{Config, Credentials, UserSettings};
+use shared::response::PageMetadata;
 
+pub mod auth;
 pub mod bootstrap;
+pub mod feed;
+pub mod pool;
+pub mod render;
 pub mod robots;
+pub mod sitemap;
+pub mod throttle;
 
 use crate::crawler::bootstrap::create_archive_url;
 use crate::fetch::HTTPClient;
-use crate::scraper::html_to_text;
-use robots::check_resource_rules;
+use crate::memory::InflightBytes;
+use crate::scraper::{html_to_text, parser, ScrapeResult, MAX_BODY_BYTES};
+use render::Renderer;
+use robots::{check_resource_rules, RobotsDirectives};
 
 // TODO: Make this configurable by domain
 const FETCH_DELAY_MS: i64 = 1000 * 60 * 60 * 24;
+const X_ROBOTS_TAG: &str = "x-robots-tag";
+/// Status of fetches that never got a response, e.g. timeouts.
+pub const NO_RESPONSE: u16 = 600;
 
 #[derive(Debug, Default, Clone)]
 pub struct CrawlResult {
@@ -38,6 +56,21 @@ pub struct CrawlResult {
     pub links: HashSet<String>,
     /// Raw HTML data.
     pub raw: Option<String>,
+    /// Content came from an archived snapshot rather than the live page.
+    pub archived: bool,
+    /// Language the page is written in, see `scraper::lang`.
+    pub lang: Option<String>,
+    /// Author from the page/document metadata, if listed.
+    pub author: Option<String>,
+    /// Cache validators from the response, see `fetch_history`.
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// The page asked not to be indexed/have its links followed, see
+    /// `robots::RobotsDirectives`.
+    pub no_index: bool,
+    pub no_follow: bool,
+    /// OpenGraph & schema.org metadata from the page.
+    pub metadata: PageMetadata,
 }
 
 impl CrawlResult {
@@ -46,6 +79,11 @@ impl CrawlResult {
         self.status >= 200 && self.status <= 299
     }
 
+    /// Page hasn't changed since we last fetched it.
+    pub fn is_not_modified(&self) -> bool {
+        self.status == 304
+    }
+
     pub fn is_bad_request(&self) -> bool {
         self.status >= 400 && self.status <= 499
     }
@@ -81,6 +119,16 @@ fn normalize_href(url: &str, href: &str) -> Option<String> {
 #[derive(Debug, Clone)]
 pub struct Crawler {
     pub client: HTTPClient,
+    /// Fetch dead pages from the Wayback Machine instead.
+    pub archive_fallback: bool,
+    /// Keep the raw HTML around so the page can be viewed offline.
+    pub cache_pages: bool,
+    pub renderer: Arc<Renderer>,
+    /// Cookies/headers sent to domains that need a login, see `auth`.
+    pub credentials: Arc<Credentials>,
+    /// Used instead of `client` for requests w/ credentials, doesn't follow
+    /// redirects that would send them to another host.
+    pub auth_client: HTTPClient,
 }
 
 impl Default for Crawler {
@@ -130,48 +178,296 @@ impl Crawler {
     pub fn new() -> Self {
         Crawler {
             client: HTTPClient::new(),
+            archive_fallback: false,
+            cache_pages: false,
+            renderer: Arc::new(Renderer::default()),
+            credentials: Arc::new(Credentials::new()),
+            auth_client: HTTPClient::with_same_host_redirects(&UserSettings::default()),
         }
     }
 
-    /// Fetches and parses the content of a page.
-    async fn crawl(&self, url: &Url) -> CrawlResult {
-        let url = url.clone();
-
-        // Fetch & store page data.
-        let res = self.client.get(&url).await;
-        if res.is_err() {
-            // Log out reason for failure.
-            log::warn!("Unable to fetch <{}> due to {}", &url, res.unwrap_err());
-            // Unable to connect to host
-            return CrawlResult {
-                // TODO: Have our own internal error codes we can refer too later on
-                status: 600_u16,
-                url: url.to_string(),
-                ..Default::default()
-            };
+    /// Crawler configured from the user settings & credentials file.
+    pub fn with_settings(settings: &UserSettings) -> Self {
+        let credentials = Config::load_credentials().unwrap_or_else(|err| {
+            log::error!("Invalid credentials file: {}", err);
+            Credentials::new()
+        });
+
+        Crawler {
+            client: HTTPClient::with_settings(settings),
+            archive_fallback: settings.archive_fallback,
+            cache_pages: settings.cache_pages,
+            credentials: Arc::new(credentials),
+            auth_client: HTTPClient::with_same_host_redirects(settings),
+            renderer: Arc::new(Renderer::with_settings(settings)),
+            ..Crawler::new()
         }
+    }
+
+    /// Fetches the raw content of a page. Bodies are only kept for successful
+    /// responses & are capped at `MAX_BODY_BYTES`.
+    pub async fn fetch(&self, url: &Url) -> FetchResult {
+        self.fetch_with_headers(url, HeaderMap::new()).await
+    }
 
-        let res = res.unwrap();
-        let status = res.status().as_u16();
-        if status == StatusCode::OK {
-            if let Ok(raw_body) = res.text().await {
-                let mut scrape_result = self.scrape_page(&url, &raw_body).await;
-                scrape_result.status = status;
-                return scrape_result;
+    /// Same as `fetch`, but the server can respond w/ a 304 & no body if the
+    /// page hasn't changed since it was last fetched.
+    pub async fn fetch_if_modified(
+        &self,
+        url: &Url,
+        history: &fetch_history::Model,
+    ) -> FetchResult {
+        let mut headers = HeaderMap::new();
+        let validators = [
+            (IF_NONE_MATCH, &history.etag),
+            (IF_MODIFIED_SINCE, &history.last_modified),
+        ];
+        for (name, value) in validators {
+            if let Some(value) = value.as_ref().and_then(|value| value.parse().ok()) {
+                headers.insert(name, value);
             }
         }
 
-        CrawlResult {
-            status,
-            url: url.to_string(),
+        self.fetch_with_headers(url, headers).await
+    }
+
+    #[tracing::instrument(skip(self, headers), fields(url = %url))]
+    async fn fetch_with_headers(&self, url: &Url, mut headers: HeaderMap) -> FetchResult {
+        let mut fetched = FetchResult {
+            // TODO: Have our own internal error codes we can refer too later on
+            status: NO_RESPONSE,
+            url: url.clone(),
+            body: None,
+            archived: false,
+            redirected_to: None,
+            content_type: None,
+            etag: None,
+            last_modified: None,
+            robots_tags: Vec::new(),
+            inflight: InflightBytes::default(),
+        };
+
+        let mut client = &self.client;
+        if let Some(credentials) = url
+            .host_str()
+            .and_then(|host| auth::find(&self.credentials, host))
+        {
+            headers.extend(auth::headers(credentials));
+            client = &self.auth_client;
+        }
+
+        let mut res = match client.get_with_headers(url, headers).await {
+            Ok(res) => res,
+            Err(err) => {
+                // Log out reason for failure.
+                log::warn!("Unable to fetch <{}> due to {}", url, err);
+                return fetched;
+            }
+        };
+
+        fetched.status = res.status().as_u16();
+        fetched.redirected_to = redirected_to(url, res.url());
+        if let Some(final_url) = &fetched.redirected_to {
+            log::debug!("<{}> redirected to <{}>", url, final_url);
+        }
+
+        let header = |name: HeaderName| {
+            res.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        fetched.content_type = header(CONTENT_TYPE);
+        fetched.etag = header(ETAG);
+        fetched.last_modified = header(LAST_MODIFIED);
+        fetched.robots_tags = res
+            .headers()
+            .get_all(X_ROBOTS_TAG)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+            .collect();
+
+        if fetched.status != StatusCode::OK {
+            return fetched;
+        }
+
+        // Skip pages that tell us upfront they're too large
+        if let Some(length) = res.content_length() {
+            if length as usize > MAX_BODY_BYTES {
+                log::warn!("Skipping <{}>, too large ({} bytes)", url, length);
+                return fetched;
+            }
+        }
+
+        let mut body = Vec::new();
+        let complete = loop {
+            match res.chunk().await {
+                Ok(Some(chunk)) => {
+                    if body.len() + chunk.len() > MAX_BODY_BYTES {
+                        log::warn!("Aborting <{}>: body exceeds {} bytes", url, MAX_BODY_BYTES);
+                        break false;
+                    }
+
+                    fetched.inflight.add(chunk.len());
+                    body.extend_from_slice(&chunk);
+                }
+                Ok(None) => break true,
+                Err(err) => {
+                    log::warn!("Unable to read body of <{}>: {}", url, err);
+                    break false;
+                }
+            }
+        };
+
+        // Counted after decompression, so we use at most this much. Waits
+        // once the body is read so it doesn't eat into the request timeout.
+        client.consume(body.len()).await;
+        if complete {
+            fetched.body = Some(body);
+        }
+
+        fetched
+    }
+
+    /// Parses a fetched page. This is CPU bound, so callers in an async
+    /// context should run it w/ `spawn_blocking`.
+    #[tracing::instrument(skip(self, fetched), fields(url = %fetched.url))]
+    pub fn parse(&self, fetched: FetchResult) -> CrawlResult {
+        let empty_result = CrawlResult {
+            status: fetched.status,
+            url: fetched.url.to_string(),
+            etag: fetched.etag.clone(),
+            last_modified: fetched.last_modified.clone(),
             ..Default::default()
+        };
+
+        let body = match &fetched.body {
+            Some(body) => body,
+            None => return empty_result,
+        };
+
+        let parser = match parser::find(fetched.content_type.as_deref(), &fetched.url, body) {
+            Some(parser) => parser,
+            None => {
+                log::info!(
+                    "Skipping <{}>, unsupported content type: {:?}",
+                    fetched.url,
+                    fetched.content_type
+                );
+                return empty_result;
+            }
+        };
+
+        let parse_result = match (parser.parse)(body) {
+            Ok(result) => result,
+            Err(err) => {
+                log::warn!(
+                    "Unable to parse <{}> as {}: {}",
+                    fetched.url,
+                    parser.name,
+                    err
+                );
+                return empty_result;
+            }
+        };
+
+        // Index redirected pages under the URL we ended up at, as long as it's
+        // on the same site.
+        let page_url = match &fetched.redirected_to {
+            Some(final_url) => Url::parse(&determine_canonical(&fetched.url, final_url))
+                .unwrap_or_else(|_| fetched.url.clone()),
+            None => fetched.url.clone(),
+        };
+
+        let robots = RobotsDirectives::from_headers(&fetched.robots_tags)
+            .merge(RobotsDirectives::from_meta(&parse_result.meta));
+
+        let mut result = self.to_crawl_result(&page_url, parse_result);
+        result.status = fetched.status;
+        result.archived = fetched.archived;
+        result.etag = fetched.etag.clone();
+        result.last_modified = fetched.last_modified.clone();
+        result.no_index = robots.no_index;
+        result.no_follow = robots.no_follow;
+        if robots.no_index {
+            result.content = None;
+        } else if self.cache_pages && parser.name == "html" {
+            // Cached copies are shown as a page, no point keeping other formats.
+            result.raw = Some(String::from_utf8_lossy(body).to_string());
+        }
+        if robots.no_follow {
+            result.links.clear();
+        }
+        result
+    }
+
+    /// Replace the body of a fetched page w/ its HTML after rendering it in a
+    /// headless browser. Other formats, failed fetches & pages that need
+    /// credentials are left as is.
+    pub async fn render(&self, page: &mut FetchResult) -> anyhow::Result<()> {
+        let body = match &page.body {
+            Some(body) if page.status == StatusCode::OK => body,
+            _ => return Ok(()),
+        };
+
+        let is_html = parser::find(page.content_type.as_deref(), &page.url, body)
+            .map(|parser| parser.name == "html")
+            .unwrap_or(false);
+        if !is_html {
+            return Ok(());
+        }
+
+        // The browser would send credentials along to every host the page
+        // loads from, keep the page as fetched w/ them instead.
+        let needs_auth = page
+            .url
+            .host_str()
+            .and_then(|host| auth::find(&self.credentials, host))
+            .is_some();
+        if needs_auth {
+            log::debug!("Not rendering <{}>, it needs credentials", page.url);
+            return Ok(());
         }
+
+        let renderer = self.renderer.clone();
+        let url = page.url.clone();
+        let html = tokio::task::spawn_blocking(move || renderer.render(&url)).await??;
+
+        self.client.consume(html.len()).await;
+        page.inflight.add(html.len());
+        page.body = Some(html.into_bytes());
+        Ok(())
+    }
+
+    /// Fetch the latest snapshot of `url` from the Wayback Machine, if there
+    /// is one.
+    pub async fn fetch_archived(&self, url: &str) -> Option<FetchResult> {
+        let archive_url = Url::parse(&create_archive_url(url)).ok()?;
+        let mut page = self.fetch(&archive_url).await;
+        if page.status != StatusCode::OK || page.body.is_none() {
+            return None;
+        }
+
+        log::info!("<{}> is gone, using archived snapshot", url);
+        page.archived = true;
+        Some(page)
     }
 
+    /// Fetches and parses the content of a page.
+    pub async fn crawl(&self, url: &Url) -> CrawlResult {
+        let fetched = self.fetch(url).await;
+        self.parse(fetched)
+    }
+
+    #[tracing::instrument(skip(self, raw_body), fields(url = %url))]
     pub async fn scrape_page(&self, url: &Url, raw_body: &str) -> CrawlResult {
         // Parse the html.
         let parse_result = html_to_text(raw_body);
+        self.to_crawl_result(url, parse_result)
+    }
 
+    fn to_crawl_result(&self, url: &Url, parse_result: ScrapeResult) -> CrawlResult {
         // Hash the body content, used to detect changes (eventually).
         let mut hasher = Sha256::new();
         hasher.update(&parse_result.content.as_bytes());
@@ -183,6 +479,13 @@ impl Crawler {
             None => url.to_string(),
         };
 
+        // Preview images are often relative to the page.
+        let mut metadata = parse_result.metadata;
+        metadata.og_image = metadata
+            .og_image
+            .and_then(|image| url.join(&image).ok())
+            .map(|image| image.to_string());
+
         CrawlResult {
             content_hash,
             content: Some(parse_result.content),
@@ -191,8 +494,12 @@ impl Crawler {
             title: parse_result.title,
             url: canonical_url,
             links: parse_result.links,
-            // No need to store the raw HTML for now.
-            raw: None, // Some(raw_body.to_string()),
+            // Filled in by the caller, if caching is enabled.
+            raw: None,
+            lang: parse_result.lang,
+            author: parse_result.meta.get("author").cloned(),
+            metadata,
+            ..Default::default()
         }
     }
 
@@ -205,37 +512,55 @@ impl Crawler {
         db: &DatabaseConnection,
         id: i64,
     ) -> anyhow::Result<Option<CrawlResult>, anyhow::Error> {
-        let crawl = crawl_queue::Entity::find_by_id(id).one(db).await?.unwrap();
-
-        // Modify bootstrapped URLs to pull from the Internet Archive
-        let fetch_url = if crawl.crawl_type == crawl_queue::CrawlType::Bootstrap {
-            create_archive_url(&crawl.url)
-        } else {
-            crawl.url.clone()
+        let job = match self.fetch_job(db, id).await? {
+            Some(job) => job,
+            None => return Ok(None),
         };
 
-        let url = Url::parse(&fetch_url).expect("Invalid URL");
+        let fetch_url = job.page.url.clone();
+        let result = self.parse_job(job);
+        record_fetch(db, &fetch_url, &result).await?;
 
-        // Break apart domain + path of the URL
-        let domain = url.host_str().expect("Invalid URL");
-        let mut path: String = url.path().to_string();
-        if let Some(query) = url.query() {
-            path = format!("{}?{}", path, query);
-        }
+        Ok(Some(result))
+    }
+
+    /// Fetch stage of a crawl job. Returns `None` if the job should be skipped,
+    /// e.g. if it was fetched recently or crawling is not allowed.
+    #[tracing::instrument(skip(self, db))]
+    pub async fn fetch_job(
+        &self,
+        db: &DatabaseConnection,
+        id: i64,
+    ) -> anyhow::Result<Option<FetchedJob>> {
+        let crawl = crawl_queue::Entity::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Unable to find crawl task: {}", id))?;
+
+        self.fetch_task(db, crawl).await
+    }
+
+    /// Same as `fetch_job` for a task that's already been loaded, e.g. one
+    /// handed to us by a remote primary.
+    #[tracing::instrument(skip(self, db, crawl), fields(url = %crawl.url))]
+    pub async fn fetch_task(
+        &self,
+        db: &DatabaseConnection,
+        crawl: crawl_queue::Model,
+    ) -> anyhow::Result<Option<FetchedJob>> {
+        let fetch_url = fetch_url_for(&crawl);
+        let url = Url::parse(&fetch_url)?;
 
         // Have we crawled this recently?
-        if let Some(history) = fetch_history::find_by_url(db, &url).await? {
-            let since_last_fetch = Utc::now() - history.updated_at;
-            if since_last_fetch < Duration::milliseconds(FETCH_DELAY_MS) {
-                log::trace!("Recently fetched, skipping");
-                return Ok(None);
-            }
+        if recently_fetched(db, &url).await? {
+            log::trace!("Recently fetched, skipping");
+            return Ok(None);
         }
 
         // Check for robots.txt of this domain
         // When looking at bootstrapped tasks, check the original URL
         if crawl.crawl_type == crawl_queue::CrawlType::Bootstrap {
-            let og_url = Url::parse(&crawl.url).unwrap();
+            let og_url = Url::parse(&crawl.url)?;
             if !check_resource_rules(db, &self.client, &og_url).await? {
                 return Ok(None);
             }
@@ -243,24 +568,62 @@ impl Crawler {
             return Ok(None);
         }
 
-        // Crawl & save the data
-        let mut result = self.crawl(&url).await;
-        if result.is_bad_request() {
-            log::warn!("issue fetching {} {:?}", result.status, result.url);
+        // Only ask for changes if the page is still indexed, otherwise a 304
+        // would keep it out of the index. Pages that asked not to be indexed
+        // stay out of it until they change.
+        let history = if crawl.crawl_type == crawl_queue::CrawlType::Normal {
+            fetch_history::find_by_url(db, &url).await?
+        } else {
+            None
+        };
+        let history = match history {
+            Some(history) if history.no_index || is_indexed(db, &crawl.url).await? => Some(history),
+            _ => None,
+        };
+
+        let mut page = match &history {
+            Some(history) => self.fetch_if_modified(&url, history).await,
+            None => self.fetch(&url).await,
+        };
+        // Page/domain is gone, see if there's an archived copy.
+        if self.archive_fallback
+            && crawl.crawl_type != crawl_queue::CrawlType::Bootstrap
+            && is_gone(page.status)
+        {
+            if let Some(archived) = self.fetch_archived(&crawl.url).await {
+                page = archived;
+            }
+        }
+
+        if page.status >= 400 && page.status <= 499 {
+            log::warn!("issue fetching {} {:?}", page.status, page.url.as_str());
         } else {
-            log::trace!("fetched {} {:?}", result.status, result.url);
+            log::trace!("fetched {} {:?}", page.status, page.url.as_str());
         }
 
+        Ok(Some(FetchedJob { task: crawl, page }))
+    }
+
+    /// Parse stage of a crawl job.
+    pub fn parse_job(&self, job: FetchedJob) -> CrawlResult {
+        let mut result = self.parse(job.page);
+
         // Check to see if a canonical URL was found, if not use the original
-        // bootstrapped URL
-        if crawl.crawl_type == crawl_queue::CrawlType::Bootstrap {
-            let parsed = Url::parse(&result.url).unwrap();
-            let domain = parsed.host_str().unwrap();
-            if domain == "web.archive.org" {
-                result.url = crawl.url.clone();
+        // bootstrapped/archived URL
+        if job.task.crawl_type == crawl_queue::CrawlType::Bootstrap || result.archived {
+            let is_archive = Url::parse(&result.url)
+                .ok()
+                .and_then(|parsed| parsed.host_str().map(|host| host == "web.archive.org"))
+                .unwrap_or(true);
+            if is_archive {
+                result.url = job.task.url.clone();
             }
         }
 
+        if let Some(url) = normalize_url(&result.url) {
+            result.url = url;
+        }
+
         // Normalize links from scrape result. If the links start with "/" they
         // should be appended to the current URL.
         let normalized_links = result
@@ -277,18 +640,113 @@ impl Crawler {
             result.description,
         );
 
-        // Update fetch history
-        fetch_history::upsert(
-            db,
-            domain,
-            &path,
-            result.content_hash.clone(),
-            result.status,
-        )
-        .await?;
+        result
+    }
+}
 
-        Ok(Some(result))
+/// URL to fetch for a crawl task. Bootstrapped URLs are pulled from the
+/// Internet Archive.
+pub fn fetch_url_for(crawl: &crawl_queue::Model) -> String {
+    if crawl.crawl_type == crawl_queue::CrawlType::Bootstrap {
+        create_archive_url(&crawl.url)
+    } else {
+        crawl.url.clone()
+    }
+}
+
+/// Was `url` fetched within the last `FETCH_DELAY_MS`?
+pub async fn recently_fetched(db: &DatabaseConnection, url: &Url) -> anyhow::Result<bool> {
+    Ok(match fetch_history::find_by_url(db, url).await? {
+        Some(history) => Utc::now() - history.updated_at < Duration::milliseconds(FETCH_DELAY_MS),
+        None => false,
+    })
+}
+
+async fn is_indexed(db: &DatabaseConnection, url: &str) -> anyhow::Result<bool> {
+    Ok(indexed_document::Entity::find()
+        .filter(indexed_document::Column::Url.eq(url))
+        .one(db)
+        .await?
+        .is_some())
+}
+
+/// Final URL of a request for `requested` if it was redirected somewhere else.
+/// Falling back from https to http isn't considered a redirect.
+fn redirected_to(requested: &Url, response: &Url) -> Option<Url> {
+    let is_same = requested.host_str() == response.host_str()
+        && requested.path() == response.path()
+        && requested.query() == response.query();
+
+    if is_same {
+        None
+    } else {
+        Some(response.clone())
+    }
+}
+
+/// Not found, gone, or we were unable to connect at all.
+fn is_gone(status: u16) -> bool {
+    status == 404 || status == 410 || status == 600
+}
+
+/// Raw response for a page, waiting to be parsed.
+#[derive(Debug)]
+pub struct FetchResult {
+    pub status: u16,
+    pub url: Url,
+    pub body: Option<Vec<u8>>,
+    /// Fetched from the Wayback Machine.
+    pub archived: bool,
+    /// Where we ended up after following redirects, if not at `url`.
+    pub redirected_to: Option<Url>,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Values of any `X-Robots-Tag` headers.
+    pub robots_tags: Vec<String>,
+    /// Keeps the body accounted for in the memory stats until it's parsed.
+    inflight: InflightBytes,
+}
+
+/// A crawl job that has been fetched & is waiting to be parsed.
+#[derive(Debug)]
+pub struct FetchedJob {
+    pub task: crawl_queue::Model,
+    pub page: FetchResult,
+}
+
+/// Update the fetch history for `fetch_url` w/ the result of the crawl.
+pub async fn record_fetch(
+    db: &DatabaseConnection,
+    fetch_url: &Url,
+    result: &CrawlResult,
+) -> anyhow::Result<()> {
+    // Break apart domain + path of the URL
+    let domain = fetch_url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("Invalid URL: {}", fetch_url))?;
+    let mut path: String = fetch_url.path().to_string();
+    if let Some(query) = fetch_url.query() {
+        path = format!("{}?{}", path, query);
+    }
+
+    fetch_history::upsert(
+        db,
+        domain,
+        &path,
+        result.content_hash.clone(),
+        result.status,
+        result.etag.clone(),
+        result.last_modified.clone(),
+    )
+    .await?;
+
+    // A 304 means the directives haven't changed either.
+    if !result.is_not_modified() {
+        fetch_history::set_robots(db, domain, &path, result.no_index, result.no_follow).await?;
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -298,7 +756,10 @@ mod test {
     use entities::sea_orm::{ActiveModelTrait, Set};
     use entities::test::setup_test_db;
 
-    use crate::crawler::{determine_canonical, normalize_href, Crawler};
+    use crate::crawler::{
+        determine_canonical, is_gone, normalize_href, redirected_to, Crawler, FetchResult,
+    };
+    use crate::memory::InflightBytes;
 
     use url::Url;
 
@@ -404,6 +865,44 @@ mod test {
         assert!(crawl_result.is_none());
     }
 
+    #[test]
+    fn test_parse_robots_directives() {
+        let crawler = Crawler::new();
+        let html = r#"<html><head><meta name="robots" content="noindex"></head>
+            <body><p>Secret <a href="/other">stuff</a></p></body></html>"#;
+        let fetched = |robots_tags: Vec<String>| FetchResult {
+            status: 200,
+            url: Url::parse("https://example.com/secret").unwrap(),
+            body: Some(html.as_bytes().to_vec()),
+            archived: false,
+            redirected_to: None,
+            content_type: Some("text/html".into()),
+            etag: None,
+            last_modified: None,
+            robots_tags,
+            inflight: InflightBytes::default(),
+        };
+
+        let result = crawler.parse(fetched(Vec::new()));
+        assert!(result.no_index);
+        assert!(result.content.is_none());
+        assert!(!result.no_follow);
+        assert_eq!(result.links.len(), 1);
+
+        let result = crawler.parse(fetched(vec!["nofollow".into()]));
+        assert!(result.no_index && result.no_follow);
+        assert!(result.links.is_empty());
+    }
+
+    #[test]
+    fn test_is_gone() {
+        assert!(is_gone(404));
+        assert!(is_gone(410));
+        assert!(is_gone(600));
+        assert!(!is_gone(200));
+        assert!(!is_gone(500));
+    }
+
     #[test]
     fn testnormalize_href() {
         let url = "https://example.com";
@@ -434,6 +933,20 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_redirected_to() {
+        let requested = Url::parse("https://example.com/docs").unwrap();
+
+        let response = Url::parse("http://example.com/docs").unwrap();
+        assert_eq!(redirected_to(&requested, &response), None);
+
+        let response = Url::parse("https://example.com/docs/").unwrap();
+        assert_eq!(redirected_to(&requested, &response), Some(response));
+
+        let response = Url::parse("https://www.example.com/docs").unwrap();
+        assert_eq!(redirected_to(&requested, &response), Some(response));
+    }
+
     #[test]
     fn test_determine_canonical() {
         // Test a correct override
@@ -0,0 +1,273 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::task::CrawlTask;
+
+/// In-memory per-domain queues used by the crawl manager to hand out tasks
+/// to the worker pool. Inflight & politeness limits are enforced here so that
+/// workers don't need to hit the database to figure out what to crawl next.
+#[derive(Debug)]
+pub struct DomainQueues {
+    queues: HashMap<String, VecDeque<CrawlTask>>,
+    /// Domains w/ queued tasks, in round-robin order.
+    order: VecDeque<String>,
+    inflight: HashMap<String, u32>,
+    last_fetch: HashMap<String, Instant>,
+    /// Domains w/ a politeness delay other than the default one.
+    domain_delays: HashMap<String, Duration>,
+    /// Crawl-delay set by a domain's robots.txt.
+    crawl_delays: HashMap<String, Duration>,
+    total_inflight: u32,
+    total_limit: u32,
+    domain_limit: u32,
+    politeness_delay: Duration,
+}
+
+impl DomainQueues {
+    pub fn new(total_limit: u32, domain_limit: u32, politeness_delay: Duration) -> Self {
+        DomainQueues {
+            queues: HashMap::new(),
+            order: VecDeque::new(),
+            inflight: HashMap::new(),
+            last_fetch: HashMap::new(),
+            domain_delays: HashMap::new(),
+            crawl_delays: HashMap::new(),
+            total_inflight: 0,
+            total_limit,
+            domain_limit,
+            politeness_delay,
+        }
+    }
+
+    /// Number of tasks waiting to be handed out.
+    pub fn len(&self) -> usize {
+        self.queues.values().map(|queue| queue.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn total_inflight(&self) -> u32 {
+        self.total_inflight
+    }
+
+    /// Use `delay` instead of the default politeness delay for `domain`.
+    pub fn set_politeness_delay(&mut self, domain: &str, delay: Duration) {
+        self.domain_delays.insert(domain.to_string(), delay);
+    }
+
+    /// Crawl-delay from `domain`'s robots.txt, used when it's longer than our
+    /// own politeness delay.
+    pub fn set_crawl_delay(&mut self, domain: &str, delay: Duration) {
+        if delay.is_zero() {
+            self.crawl_delays.remove(domain);
+        } else {
+            self.crawl_delays.insert(domain.to_string(), delay);
+        }
+    }
+
+    /// Min. time between requests to `domain`.
+    fn delay_for(&self, domain: &str) -> Duration {
+        let politeness_delay = self
+            .domain_delays
+            .get(domain)
+            .copied()
+            .unwrap_or(self.politeness_delay);
+        let crawl_delay = self.crawl_delays.get(domain).copied().unwrap_or_default();
+
+        politeness_delay.max(crawl_delay)
+    }
+
+    pub fn push(&mut self, task: CrawlTask) {
+        let queue = self.queues.entry(task.domain.clone()).or_default();
+        if queue.is_empty() && !self.order.contains(&task.domain) {
+            self.order.push_back(task.domain.clone());
+        }
+        queue.push_back(task);
+    }
+
+    /// Grab the next task that can be crawled right now, if any. Domains are
+    /// visited round-robin so a single large domain doesn't starve the rest.
+    pub fn next_ready(&mut self, now: Instant) -> Option<CrawlTask> {
+        if self.total_inflight >= self.total_limit {
+            return None;
+        }
+
+        for _ in 0..self.order.len() {
+            let domain = self.order.pop_front()?;
+
+            let inflight = self.inflight.get(&domain).copied().unwrap_or(0);
+            let delay = self.delay_for(&domain);
+            let is_polite = self
+                .last_fetch
+                .get(&domain)
+                .map(|last| now.duration_since(*last) >= delay)
+                .unwrap_or(true);
+
+            if inflight < self.domain_limit && is_polite {
+                if let Some(task) = self.queues.get_mut(&domain).and_then(|q| q.pop_front()) {
+                    *self.inflight.entry(domain.clone()).or_insert(0) += 1;
+                    self.total_inflight += 1;
+                    self.last_fetch.insert(domain.clone(), now);
+
+                    if self
+                        .queues
+                        .get(&domain)
+                        .map(|q| q.is_empty())
+                        .unwrap_or(true)
+                    {
+                        self.queues.remove(&domain);
+                    } else {
+                        self.order.push_back(domain);
+                    }
+
+                    return Some(task);
+                }
+            }
+
+            // Not ready yet, try again later.
+            self.order.push_back(domain);
+        }
+
+        None
+    }
+
+    /// Mark a task for `domain` as finished.
+    pub fn finish(&mut self, domain: &str) {
+        if let Some(inflight) = self.inflight.get_mut(domain) {
+            *inflight = inflight.saturating_sub(1);
+            if *inflight == 0 {
+                self.inflight.remove(domain);
+            }
+        }
+
+        self.total_inflight = self.total_inflight.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DomainQueues;
+    use crate::task::CrawlTask;
+    use std::time::{Duration, Instant};
+
+    fn task(id: i64, domain: &str) -> CrawlTask {
+        CrawlTask {
+            id,
+            domain: domain.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut queues = DomainQueues::new(10, 10, Duration::from_secs(0));
+        queues.push(task(1, "a.com"));
+        queues.push(task(2, "a.com"));
+        queues.push(task(3, "b.com"));
+
+        let now = Instant::now();
+        assert_eq!(queues.next_ready(now).unwrap().id, 1);
+        assert_eq!(queues.next_ready(now).unwrap().id, 3);
+        assert_eq!(queues.next_ready(now).unwrap().id, 2);
+        assert!(queues.next_ready(now).is_none());
+        assert_eq!(queues.total_inflight(), 3);
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut queues = DomainQueues::new(10, 1, Duration::from_secs(0));
+        queues.push(task(1, "a.com"));
+        queues.push(task(2, "a.com"));
+
+        let now = Instant::now();
+        assert_eq!(queues.next_ready(now).unwrap().id, 1);
+        // Domain limit reached
+        assert!(queues.next_ready(now).is_none());
+
+        queues.finish("a.com");
+        assert_eq!(queues.next_ready(now).unwrap().id, 2);
+        assert!(queues.is_empty());
+
+        // Total limit
+        let mut queues = DomainQueues::new(1, 10, Duration::from_secs(0));
+        queues.push(task(1, "a.com"));
+        queues.push(task(2, "b.com"));
+        assert!(queues.next_ready(now).is_some());
+        assert!(queues.next_ready(now).is_none());
+    }
+
+    #[test]
+    fn test_politeness() {
+        let mut queues = DomainQueues::new(10, 10, Duration::from_secs(1));
+        queues.push(task(1, "a.com"));
+        queues.push(task(2, "a.com"));
+
+        let now = Instant::now();
+        assert!(queues.next_ready(now).is_some());
+        assert!(queues.next_ready(now).is_none());
+        assert!(queues
+            .next_ready(now + Duration::from_millis(1500))
+            .is_some());
+    }
+
+    #[test]
+    fn test_crawl_delay() {
+        let mut queues = DomainQueues::new(10, 10, Duration::from_secs(1));
+        queues.set_crawl_delay("a.com", Duration::from_secs(5));
+        // Shorter than our own delay, ignored
+        queues.set_crawl_delay("b.com", Duration::from_millis(10));
+        for (id, domain) in [(1, "a.com"), (2, "a.com"), (3, "b.com"), (4, "b.com")] {
+            queues.push(task(id, domain));
+        }
+
+        let now = Instant::now();
+        assert_eq!(queues.next_ready(now).unwrap().id, 1);
+        assert_eq!(queues.next_ready(now).unwrap().id, 3);
+
+        let later = now + Duration::from_secs(2);
+        assert_eq!(queues.next_ready(later).unwrap().id, 4);
+        assert!(queues.next_ready(later).is_none());
+        assert_eq!(
+            queues.next_ready(now + Duration::from_secs(5)).unwrap().id,
+            2
+        );
+    }
+
+    #[test]
+    fn test_domain_politeness_delay() {
+        let mut queues = DomainQueues::new(10, 10, Duration::from_secs(1));
+        queues.set_politeness_delay("slow.com", Duration::from_secs(10));
+        queues.set_politeness_delay("fast.com", Duration::from_secs(0));
+        // robots.txt asks for longer
+        queues.set_politeness_delay("a.com", Duration::from_secs(2));
+        queues.set_crawl_delay("a.com", Duration::from_secs(5));
+
+        let domains = ["slow.com", "fast.com", "a.com"];
+        for (id, domain) in domains.iter().cycle().take(6).enumerate() {
+            queues.push(task(id as i64, domain));
+        }
+
+        let now = Instant::now();
+        for _ in 0..domains.len() {
+            assert!(queues.next_ready(now).is_some());
+        }
+        // Only the domain w/o a delay is ready right away
+        assert_eq!(queues.next_ready(now).unwrap().domain, "fast.com");
+        assert!(queues.next_ready(now + Duration::from_secs(4)).is_none());
+        assert_eq!(
+            queues
+                .next_ready(now + Duration::from_secs(5))
+                .unwrap()
+                .domain,
+            "a.com"
+        );
+        assert_eq!(
+            queues
+                .next_ready(now + Duration::from_secs(10))
+                .unwrap()
+                .domain,
+            "slow.com"
+        );
+    }
+}
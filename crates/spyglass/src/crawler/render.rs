@@ -0,0 +1,112 @@
+//! Renders pages in a headless browser before they're parsed, for sites that
+//! build their content client-side & would otherwise be indexed as empty
+//! pages. Only used for lenses w/ `render` set & requires the `headless`
+//! feature, which drives Chromium through the DevTools protocol.
+use std::fmt;
+
+use url::Url;
+
+#[cfg(feature = "headless")]
+use headless_chrome::{Browser, LaunchOptions, Tab};
+use shared::config::UserSettings;
+
+/// Shared browser instance, launched the first time a page is rendered.
+#[derive(Default)]
+pub struct Renderer {
+    #[cfg(feature = "headless")]
+    browser: std::sync::Mutex<Option<Browser>>,
+    /// Proxy & User-Agents, same as for regular fetches.
+    #[cfg(feature = "headless")]
+    settings: UserSettings,
+}
+
+impl fmt::Debug for Renderer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Renderer").finish()
+    }
+}
+
+impl Renderer {
+    /// Renderer configured w/ the proxy & User-Agent from the user settings.
+    #[cfg(feature = "headless")]
+    pub fn with_settings(settings: &UserSettings) -> Self {
+        Renderer {
+            browser: Default::default(),
+            settings: settings.clone(),
+        }
+    }
+
+    #[cfg(not(feature = "headless"))]
+    pub fn with_settings(_settings: &UserSettings) -> Self {
+        Renderer::default()
+    }
+
+    #[cfg(feature = "headless")]
+    fn launch(&self) -> anyhow::Result<Browser> {
+        log::info!("launching headless browser");
+        let proxy = self.settings.proxy.as_ref();
+        let bypass = proxy
+            .filter(|proxy| !proxy.no_proxy.is_empty())
+            .map(|proxy| format!("--proxy-bypass-list={}", proxy.no_proxy.join(";")));
+        if proxy.map_or(false, |proxy| proxy.username.is_some()) {
+            log::warn!("Proxy credentials aren't supported when rendering pages");
+        }
+
+        let mut options = LaunchOptions {
+            proxy_server: proxy.map(|proxy| proxy.url.as_str()),
+            ..Default::default()
+        };
+        if let Some(bypass) = &bypass {
+            options.args.push(std::ffi::OsStr::new(bypass));
+        }
+
+        Ok(Browser::new(options)?)
+    }
+
+    #[cfg(feature = "headless")]
+    fn load(&self, tab: &Tab, url: &Url) -> anyhow::Result<String> {
+        let user_agent = url
+            .host_str()
+            .and_then(|host| self.settings.domain_user_agents.get(host))
+            .unwrap_or(&self.settings.user_agent);
+        tab.set_user_agent(user_agent, None, None)?;
+
+        tab.navigate_to(url.as_str())?;
+        tab.wait_until_navigated()?;
+        Ok(tab.get_content()?)
+    }
+
+    /// HTML of `url` once it's done loading. Blocks while the page loads, so
+    /// callers in an async context should use `spawn_blocking`.
+    #[cfg(feature = "headless")]
+    pub fn render(&self, url: &Url) -> anyhow::Result<String> {
+        let mut browser = self
+            .browser
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Unable to get browser lock"))?;
+
+        // Relaunch the browser if it's not running or has crashed.
+        let tab = match browser.as_ref().map(|browser| browser.new_tab()) {
+            Some(Ok(tab)) => tab,
+            _ => {
+                let launched = self.launch()?;
+                let tab = launched.new_tab()?;
+                *browser = Some(launched);
+                tab
+            }
+        };
+        drop(browser);
+
+        // Close the tab whether or not the page loaded, or they pile up.
+        let html = self.load(&tab, url);
+        let _ = tab.close(true);
+        html
+    }
+
+    #[cfg(not(feature = "headless"))]
+    pub fn render(&self, _url: &Url) -> anyhow::Result<String> {
+        Err(anyhow::anyhow!(
+            "Built w/o headless rendering, enable the `headless` feature"
+        ))
+    }
+}
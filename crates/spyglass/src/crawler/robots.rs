@@ -2,17 +2,19 @@
 /// See the following for more details about robots.txt files:
 /// - https://developers.google.com/search/docs/advanced/robots/intro
 /// - https://www.robotstxt.org/robotstxt.html
-use regex::RegexSet;
+use regex::{Regex, RegexSet};
 use reqwest::StatusCode;
+use std::collections::HashMap;
 use std::convert::From;
 use url::Url;
 
 use entities::models::resource_rule;
 use entities::regex::{regex_for_robots, WildcardType};
 use entities::sea_orm::prelude::*;
-use entities::sea_orm::{DatabaseConnection, Set};
+use entities::sea_orm::{DatabaseConnection, Set, TransactionTrait};
 
 use crate::fetch::HTTPClient;
+use crate::scraper::parser;
 
 #[derive(Clone, Debug)]
 pub struct ParsedRule {
@@ -32,6 +34,8 @@ impl From<resource_rule::Model> for ParsedRule {
 }
 
 const BOT_AGENT_NAME: &str = "spyglass";
+/// How long a domain's robots.txt is cached before it's fetched again.
+const ROBOTS_CACHE_HOURS: i64 = 24;
 
 /// Convert a set of rules into a regex set for matching
 pub fn filter_set(rules: &[ParsedRule], allow: bool) -> RegexSet {
@@ -94,6 +98,209 @@ pub fn parse(domain: &str, txt: &str) -> Vec<ParsedRule> {
     rules
 }
 
+/// Crawl-delay for our user agent in seconds, if the robots.txt sets one.
+/// Fractional delays are rounded up.
+pub fn parse_crawl_delay(txt: &str) -> Option<i64> {
+    let mut user_agent: Option<String> = None;
+    for line in txt.split('\n') {
+        let (prefix, value) = match line.trim().split_once(':') {
+            Some((prefix, value)) => (prefix.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+
+        if prefix == "user-agent" {
+            user_agent = Some(value.to_string());
+        } else if prefix == "crawl-delay" {
+            let applies = user_agent
+                .as_ref()
+                .map(|agent| agent == "*" || agent == BOT_AGENT_NAME)
+                .unwrap_or(false);
+            if let (true, Ok(delay)) = (applies, value.parse::<f64>()) {
+                if delay > 0.0 {
+                    return Some(delay.ceil() as i64);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Sitemap URLs listed in a robots.txt. These apply regardless of user agent.
+pub fn parse_sitemaps(txt: &str) -> Vec<String> {
+    txt.split('\n')
+        .filter_map(|line| line.trim().split_once(':'))
+        .filter(|(prefix, _)| prefix.trim().eq_ignore_ascii_case("sitemap"))
+        .map(|(_, value)| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// Page level directives from `<meta name="robots">` tags & `X-Robots-Tag`
+/// headers. See https://developers.google.com/search/docs/advanced/robots/robots_meta_tag
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RobotsDirectives {
+    /// Don't add the page to the index.
+    pub no_index: bool,
+    /// Don't follow the links on the page.
+    pub no_follow: bool,
+}
+
+impl RobotsDirectives {
+    /// Parse a list of directives, e.g. `noindex, nofollow`. Directives can
+    /// be aimed at a single bot w/ a prefix (`googlebot: noindex`), those for
+    /// other bots are ignored.
+    pub fn parse(value: &str) -> Self {
+        let mut directives = RobotsDirectives::default();
+        let mut applies = true;
+        for token in value.split(',') {
+            let mut token = token.trim().to_lowercase();
+            if let Some((prefix, rest)) = token.clone().split_once(':') {
+                // Directives w/ a value, e.g. `max-snippet: 20`, aren't bot
+                // names.
+                let is_directive = prefix.contains('-') || prefix.contains('_');
+                if !is_directive {
+                    applies = prefix.trim() == BOT_AGENT_NAME;
+                    token = rest.trim().to_string();
+                }
+            }
+
+            if !applies {
+                continue;
+            }
+
+            match token.as_str() {
+                "noindex" => directives.no_index = true,
+                "nofollow" => directives.no_follow = true,
+                "none" => {
+                    directives.no_index = true;
+                    directives.no_follow = true;
+                }
+                _ => {}
+            }
+        }
+
+        directives
+    }
+
+    /// Directives from `X-Robots-Tag` headers, each header is parsed on its own.
+    pub fn from_headers(values: &[String]) -> Self {
+        values
+            .iter()
+            .map(|value| Self::parse(value))
+            .fold(Self::default(), Self::merge)
+    }
+
+    /// Directives from `<meta name="robots">` & `<meta name="spyglass">` tags.
+    pub fn from_meta(meta: &HashMap<String, String>) -> Self {
+        meta.iter()
+            .filter(|(name, _)| {
+                name.eq_ignore_ascii_case("robots") || name.eq_ignore_ascii_case(BOT_AGENT_NAME)
+            })
+            .map(|(_, value)| Self::parse(value))
+            .fold(Self::default(), Self::merge)
+    }
+
+    /// The most restrictive of both.
+    pub fn merge(self, other: Self) -> Self {
+        RobotsDirectives {
+            no_index: self.no_index || other.no_index,
+            no_follow: self.no_follow || other.no_follow,
+        }
+    }
+}
+
+/// Length of the part of `path` matched by `rule`, w/o the wildcard rules end
+/// in. Unlike the length of the regex, escaped characters only count once.
+fn match_len(rule: &ParsedRule, path: &str) -> usize {
+    let regex = rule.regex.strip_suffix(".*").unwrap_or(&rule.regex);
+    Regex::new(regex)
+        .ok()
+        .and_then(|regex| regex.find(path))
+        .map_or(0, |found| found.end() - found.start())
+}
+
+/// Whether `path` can be crawled. The most specific (longest) matching rule
+/// wins & allow rules win ties, same as the major search engines.
+pub fn is_allowed(rules: &[ParsedRule], path: &str) -> bool {
+    let set = match RegexSet::new(rules.iter().map(|rule| &rule.regex)) {
+        Ok(set) => set,
+        Err(err) => {
+            log::error!("Invalid robots.txt rule: {}", err);
+            return true;
+        }
+    };
+
+    set.matches(path)
+        .into_iter()
+        .map(|idx| &rules[idx])
+        .max_by_key(|rule| (match_len(rule, path), rule.allow_crawl))
+        .map(|rule| rule.allow_crawl)
+        .unwrap_or(true)
+}
+
+/// Fetch the robots.txt for `url`'s domain & replace the cached rules w/ it.
+/// `None` if it couldn't be fetched, e.g. the server is down.
+async fn fetch_rules(
+    db: &DatabaseConnection,
+    client: &HTTPClient,
+    url: &Url,
+    domain: &str,
+) -> anyhow::Result<Option<Vec<resource_rule::Model>>> {
+    let mut robots_url = url.clone();
+    robots_url.set_path("/robots.txt");
+    robots_url.set_query(None);
+
+    let (mut parsed_rules, crawl_delay) = match client.get(&robots_url).await {
+        Err(err) => {
+            log::error!("Unable to check robots.txt {}", err.to_string());
+            return Ok(None);
+        }
+        Ok(res) => match res.status() {
+            StatusCode::OK => {
+                let body = res.text().await?;
+                client.consume(body.len()).await;
+                (parse(domain, &body), parse_crawl_delay(&body))
+            }
+            // No robots.txt? Treat as an allow all
+            StatusCode::NOT_FOUND => (Vec::new(), None),
+            _ => return Ok(None),
+        },
+    };
+
+    // No rules? Treat as an allow all
+    if parsed_rules.is_empty() {
+        parsed_rules.push(ParsedRule {
+            domain: domain.to_owned(),
+            regex: "/".to_owned(),
+            allow_crawl: true,
+        });
+    }
+
+    // Swap the rules in one go, so a failure can't leave the domain w/o any.
+    let txn = db.begin().await?;
+    resource_rule::Entity::delete_many()
+        .filter(resource_rule::Column::Domain.eq(domain))
+        .exec(&txn)
+        .await?;
+
+    let mut rules = Vec::new();
+    for rule in parsed_rules {
+        let new_rule = resource_rule::ActiveModel {
+            domain: Set(rule.domain),
+            rule: Set(rule.regex),
+            no_index: Set(false),
+            allow_crawl: Set(rule.allow_crawl),
+            crawl_delay: Set(crawl_delay),
+            ..Default::default()
+        };
+        rules.push(new_rule.insert(&txn).await?);
+    }
+    txn.commit().await?;
+
+    Ok(Some(rules))
+}
+
 // Checks whether we're allow to crawl this url
 pub async fn check_resource_rules(
     db: &DatabaseConnection,
@@ -103,78 +310,29 @@ pub async fn check_resource_rules(
     let domain = url.host_str().unwrap();
     let path = url[url::Position::BeforePath..].to_string();
 
-    let rules = resource_rule::Entity::find()
+    let mut rules = resource_rule::Entity::find()
         .filter(resource_rule::Column::Domain.eq(domain))
         .all(db)
         .await?;
 
-    if rules.is_empty() {
-        log::info!("No rules found for <{}>, fetching robot.txt", domain);
-        let mut robots_url = url.clone();
-        robots_url.set_path("/robots.txt");
-
-        let res = client.get(&robots_url).await;
-        match res {
-            Err(err) => log::error!("Unable to check robots.txt {}", err.to_string()),
-            Ok(res) => {
-                match res.status() {
-                    StatusCode::OK => {
-                        let body = res.text().await.unwrap();
-
-                        let parsed_rules = parse(domain, &body);
-                        // No rules? Treat as an allow all
-                        if parsed_rules.is_empty() {
-                            let new_rule = resource_rule::ActiveModel {
-                                domain: Set(domain.to_owned()),
-                                rule: Set("/".to_owned()),
-                                no_index: Set(false),
-                                allow_crawl: Set(true),
-                                ..Default::default()
-                            };
-                            new_rule.insert(db).await?;
-                        } else {
-                            for rule in parsed_rules.iter() {
-                                let new_rule = resource_rule::ActiveModel {
-                                    domain: Set(rule.domain.to_owned()),
-                                    rule: Set(rule.regex.to_owned()),
-                                    no_index: Set(false),
-                                    allow_crawl: Set(rule.allow_crawl),
-                                    ..Default::default()
-                                };
-                                new_rule.insert(db).await?;
-                            }
-                        }
-                    }
-                    // No robots.txt? Treat as an allow all
-                    StatusCode::NOT_FOUND => {
-                        let new_rule = resource_rule::ActiveModel {
-                            domain: Set(domain.to_owned()),
-                            rule: Set("/".to_owned()),
-                            no_index: Set(false),
-                            allow_crawl: Set(true),
-                            ..Default::default()
-                        };
-                        new_rule.insert(db).await?;
-                    }
-                    _ => {}
-                }
-            }
+    let stale_before = chrono::Utc::now() - chrono::Duration::hours(ROBOTS_CACHE_HOURS);
+    let is_stale = rules.iter().any(|rule| rule.created_at < stale_before);
+    if rules.is_empty() || is_stale {
+        log::info!("No fresh rules found for <{}>, fetching robot.txt", domain);
+        // Stick w/ the old rules if the new ones can't be fetched.
+        if let Some(fetched) = fetch_rules(db, client, url, domain).await? {
+            rules = fetched;
         }
     }
 
-    // Check path against rules, if we find any matches that disallow, skip it
-    let rules_into: Vec<ParsedRule> = rules.iter().map(|x| x.to_owned().into()).collect();
-
-    let allow_filter = filter_set(&rules_into, true);
-    let disallow_filter = filter_set(&rules_into, false);
-
-    if (allow_filter.is_empty() || !allow_filter.is_match(&path)) && disallow_filter.is_match(&path)
-    {
+    // Check path against rules, if the best match disallows, skip it
+    let rules_into: Vec<ParsedRule> = rules.into_iter().map(ParsedRule::from).collect();
+    if !is_allowed(&rules_into, &path) {
         log::info!("Unable to crawl `{}` due to rule", url.as_str());
         return Ok(false);
     }
 
-    // Check the content-type of the URL, only crawl HTML pages for now
+    // Check the content-type of the URL, only crawl formats we can parse
     match client.head(url).await {
         Err(err) => {
             log::info!("Unable to check content-type: {}", err.to_string());
@@ -187,8 +345,8 @@ pub async fn check_resource_rules(
             } else {
                 let value = headers.get(http::header::CONTENT_TYPE).unwrap();
                 let value = value.to_str().unwrap();
-                if !value.to_string().contains(&"text/html") {
-                    log::info!("Unable to crawl: content-type =/= text/html");
+                if parser::find(Some(value), url, &[]).is_none() {
+                    log::info!("Unable to crawl: content-type {} not supported", value);
                     return Ok(false);
                 }
             }
@@ -200,7 +358,10 @@ pub async fn check_resource_rules(
 
 #[cfg(test)]
 mod test {
-    use super::{check_resource_rules, filter_set, parse, ParsedRule};
+    use super::{
+        check_resource_rules, filter_set, is_allowed, parse, parse_crawl_delay, parse_sitemaps,
+        ParsedRule, RobotsDirectives,
+    };
     use crate::crawler::Crawler;
 
     use entities::models::resource_rule;
@@ -208,6 +369,7 @@ mod test {
     use entities::sea_orm::{ActiveModelTrait, Set};
     use entities::test::setup_test_db;
     use regex::Regex;
+    use std::collections::HashMap;
 
     #[test]
     fn test_parse() {
@@ -282,6 +444,51 @@ mod test {
         assert_eq!(disallow.is_match("/Belt_transport_system"), false);
     }
 
+    #[test]
+    fn test_parse_crawl_delay() {
+        let robots_txt = "User-agent: googlebot\nCrawl-delay: 1\n\nUser-agent: *\nDisallow: /private\nCrawl-delay: 2.5\n";
+        assert_eq!(parse_crawl_delay(robots_txt), Some(3));
+
+        let robots_txt = "User-agent: googlebot\nCrawl-delay: 10\n";
+        assert_eq!(parse_crawl_delay(robots_txt), None);
+
+        let robots_txt = include_str!("../../../../fixtures/robots/crates_io.txt");
+        assert_eq!(parse_crawl_delay(robots_txt), None);
+    }
+
+    #[test]
+    fn test_parse_sitemaps() {
+        let robots_txt = include_str!("../../../../fixtures/robots/oldschool_runescape_wiki.txt");
+        assert_eq!(
+            parse_sitemaps(robots_txt),
+            vec!["https://oldschool.runescape.wiki/images/sitemaps/index.xml"]
+        );
+
+        // Not to be confused w/ rules for sitemap paths
+        let robots_txt = include_str!("../../../../fixtures/robots/reddit_com.txt");
+        assert!(parse_sitemaps(robots_txt).is_empty());
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let robots_txt =
+            "User-agent: *\nDisallow: /wiki/\nAllow: /wiki/Main_Page\nDisallow: /api\n";
+        let rules = parse("example.com", robots_txt);
+
+        assert!(is_allowed(&rules, "/about"));
+        assert!(is_allowed(&rules, "/wiki/Main_Page"));
+        assert!(!is_allowed(&rules, "/wiki/Special:Random"));
+        assert!(!is_allowed(&rules, "/api?action=query"));
+        assert!(is_allowed(&[], "/anything"));
+
+        // Compared by how much of the path they match, not the regex length
+        let rules = parse(
+            "example.com",
+            "User-agent: *\nDisallow: /zzzzzzz\nAllow: /a-b-c\n",
+        );
+        assert!(!is_allowed(&rules, "/zzzzzzz/a-b-c"));
+    }
+
     #[tokio::test]
     async fn test_check_resource_rules() {
         let crawler = Crawler::new();
@@ -309,4 +516,35 @@ mod test {
 
         assert_eq!(res, true);
     }
+
+    #[test]
+    fn test_robots_directives() {
+        let directives = RobotsDirectives::parse("noindex, nofollow");
+        assert!(directives.no_index);
+        assert!(directives.no_follow);
+
+        let directives = RobotsDirectives::parse("NONE");
+        assert!(directives.no_index && directives.no_follow);
+
+        let directives = RobotsDirectives::parse("max-snippet: 20, nofollow");
+        assert!(!directives.no_index);
+        assert!(directives.no_follow);
+
+        // Only directives aimed at us
+        let directives = RobotsDirectives::parse("googlebot: noindex");
+        assert_eq!(directives, RobotsDirectives::default());
+        let directives = RobotsDirectives::from_headers(&[
+            "googlebot: nofollow".to_string(),
+            "spyglass: noindex".to_string(),
+        ]);
+        assert!(directives.no_index);
+        assert!(!directives.no_follow);
+
+        let mut meta = HashMap::new();
+        meta.insert("Robots".to_string(), "noindex".to_string());
+        meta.insert("description".to_string(), "nofollow".to_string());
+        let directives = RobotsDirectives::from_meta(&meta);
+        assert!(directives.no_index);
+        assert!(!directives.no_follow);
+    }
 }
@@ -0,0 +1,244 @@
+//! Seed the crawl queue from a domain's sitemaps so pages that aren't linked
+//! to (or are linked to from deep within the site) are crawled right away.
+//! See https://www.sitemaps.org/protocol.html for the format.
+use std::collections::{HashSet, VecDeque};
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use regex::Regex;
+use reqwest::StatusCode;
+use url::Url;
+
+use entities::models::{bootstrap_queue, crawl_queue};
+use entities::sea_orm::DatabaseConnection;
+use shared::config::{Lens, UserSettings};
+
+use super::robots;
+use crate::fetch::HTTPClient;
+use crate::scraper::unescape_xml;
+
+/// Max number of sitemap files fetched for a single domain, large sites split
+/// their sitemaps up into many files via sitemap indexes.
+const MAX_SITEMAPS: usize = 50;
+/// Max size of a single sitemap file, same as the limit in the protocol.
+const MAX_SITEMAP_BYTES: u64 = 50 * 1024 * 1024;
+
+/// Contents of a single sitemap file.
+#[derive(Debug, Default, PartialEq)]
+pub struct Sitemap {
+    /// Pages listed in a `<urlset>`
+    pub urls: Vec<String>,
+    /// Other sitemaps listed in a `<sitemapindex>`
+    pub sitemaps: Vec<String>,
+}
+
+/// Parse a sitemap or sitemap index.
+pub fn parse(xml: &str) -> Sitemap {
+    let loc = Regex::new(r"(?s)<loc>\s*(?:<!\[CDATA\[)?(.*?)(?:\]\]>)?\s*</loc>")
+        .expect("Invalid loc regex");
+
+    let locs: Vec<String> = loc
+        .captures_iter(xml)
+        .map(|cap| unescape_xml(cap[1].trim()))
+        .filter(|loc| !loc.is_empty())
+        .collect();
+
+    if xml.contains("<sitemapindex") {
+        Sitemap {
+            sitemaps: locs,
+            ..Default::default()
+        }
+    } else {
+        Sitemap {
+            urls: locs,
+            ..Default::default()
+        }
+    }
+}
+
+/// Sitemap body as text, unzipping gzipped sitemaps (e.g. `sitemap.xml.gz`).
+fn decode(bytes: &[u8]) -> anyhow::Result<String> {
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut xml = String::new();
+        GzDecoder::new(bytes)
+            .take(MAX_SITEMAP_BYTES)
+            .read_to_string(&mut xml)?;
+        Ok(xml)
+    } else {
+        Ok(String::from_utf8_lossy(bytes).to_string())
+    }
+}
+
+async fn fetch_bytes(client: &HTTPClient, url: &Url) -> Option<Vec<u8>> {
+    match client.get(url).await {
+        Ok(res) if res.status() == StatusCode::OK => match res.bytes().await {
+            Ok(bytes) => {
+                client.consume(bytes.len()).await;
+                Some(bytes.to_vec())
+            }
+            Err(err) => {
+                log::warn!("Unable to read <{}>: {}", url, err);
+                None
+            }
+        },
+        Ok(res) => {
+            log::debug!("No sitemap at <{}>: {}", url, res.status());
+            None
+        }
+        Err(err) => {
+            log::warn!("Unable to fetch <{}>: {}", url, err);
+            None
+        }
+    }
+}
+
+/// All the page URLs on `domain` listed in its sitemaps. Sitemaps are found
+/// through the robots.txt, falling back to `/sitemap.xml`.
+pub async fn fetch_urls(client: &HTTPClient, domain: &str) -> anyhow::Result<Vec<String>> {
+    let base = Url::parse(&format!("https://{}", domain))?;
+
+    let mut to_fetch: VecDeque<Url> = VecDeque::new();
+    if let Some(body) = fetch_bytes(client, &base.join("/robots.txt")?).await {
+        for sitemap in robots::parse_sitemaps(&String::from_utf8_lossy(&body)) {
+            if let Ok(url) = Url::parse(&sitemap) {
+                to_fetch.push_back(url);
+            }
+        }
+    }
+
+    if to_fetch.is_empty() {
+        to_fetch.push_back(base.join("/sitemap.xml")?);
+    }
+
+    let mut seen: HashSet<Url> = HashSet::new();
+    let mut urls: HashSet<String> = HashSet::new();
+    while let Some(sitemap_url) = to_fetch.pop_front() {
+        if seen.len() >= MAX_SITEMAPS {
+            log::warn!("Too many sitemaps for {}, skipping the rest", domain);
+            break;
+        }
+
+        if !seen.insert(sitemap_url.clone()) {
+            continue;
+        }
+
+        let body = match fetch_bytes(client, &sitemap_url).await {
+            Some(body) => body,
+            None => continue,
+        };
+
+        let sitemap = match decode(&body) {
+            Ok(xml) => parse(&xml),
+            Err(err) => {
+                log::warn!("Unable to decode <{}>: {}", sitemap_url, err);
+                continue;
+            }
+        };
+
+        to_fetch.extend(
+            sitemap
+                .sitemaps
+                .iter()
+                .filter_map(|sitemap| Url::parse(sitemap).ok()),
+        );
+
+        // Sitemaps can only list pages on their own domain.
+        urls.extend(sitemap.urls.into_iter().filter(|url| {
+            Url::parse(url)
+                .map(|url| url.host_str() == Some(domain))
+                .unwrap_or(false)
+        }));
+    }
+
+    Ok(urls.into_iter().collect())
+}
+
+/// Add the URLs in `domain`'s sitemaps to the crawl queue, once per domain.
+/// Returns the number of URLs found.
+pub async fn seed_from_sitemaps(
+    lens: &Lens,
+    db: &DatabaseConnection,
+    user_settings: &UserSettings,
+    domain: &str,
+) -> anyhow::Result<usize> {
+    // Tracked alongside bootstraps so it's only done once.
+    let seed_url = format!("https://{}/sitemap.xml", domain);
+    if bootstrap_queue::has_seed_url(db, &seed_url).await? {
+        return Ok(0);
+    }
+
+    let client = HTTPClient::with_settings(user_settings);
+    let urls = fetch_urls(&client, domain).await?;
+    log::info!("found {} urls in sitemaps for {}", urls.len(), domain);
+
+    crawl_queue::enqueue_all(
+        db,
+        &urls,
+        &[lens.clone()],
+        user_settings,
+        &crawl_queue::EnqueueSettings::for_seeds_of(lens),
+    )
+    .await?;
+    bootstrap_queue::enqueue(db, &seed_url, urls.len() as i64).await?;
+
+    Ok(urls.len())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::{decode, parse, Sitemap};
+
+    #[test]
+    fn test_parse_urlset() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <url>
+                    <loc>https://example.com/</loc>
+                    <lastmod>2022-09-01</lastmod>
+                </url>
+                <url><loc>https://example.com/search?q=a&amp;page=2</loc></url>
+                <url><loc><![CDATA[https://example.com/cdata]]></loc></url>
+            </urlset>"#;
+
+        assert_eq!(
+            parse(xml),
+            Sitemap {
+                urls: vec![
+                    "https://example.com/".into(),
+                    "https://example.com/search?q=a&page=2".into(),
+                    "https://example.com/cdata".into(),
+                ],
+                sitemaps: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_index() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <sitemap><loc>https://example.com/sitemap-1.xml.gz</loc></sitemap>
+                <sitemap><loc>https://example.com/sitemap-2.xml.gz</loc></sitemap>
+            </sitemapindex>"#;
+
+        let sitemap = parse(xml);
+        assert!(sitemap.urls.is_empty());
+        assert_eq!(sitemap.sitemaps.len(), 2);
+    }
+
+    #[test]
+    fn test_decode_gzip() {
+        let xml = "<urlset><url><loc>https://example.com/</loc></url></urlset>";
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(xml.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        assert_eq!(decode(&gzipped).unwrap(), xml);
+        assert_eq!(decode(xml.as_bytes()).unwrap(), xml);
+    }
+}
@@ -0,0 +1,124 @@
+//! Caps the bandwidth used by the crawler, shared across all fetch workers.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+
+/// Budgets handed out by `Throttle::shared`, one per limit.
+static SHARED: Lazy<Mutex<HashMap<u64, Arc<Throttle>>>> = Lazy::new(Default::default);
+
+struct Bucket {
+    /// Bytes we can read right away, negative when over budget.
+    available: f64,
+    last_refill: Instant,
+}
+
+/// Token bucket that refills at `bytes_per_sec`, allowing bursts of up to a
+/// second's worth of bytes.
+pub struct Throttle {
+    bytes_per_sec: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl std::fmt::Debug for Throttle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Throttle")
+            .field("bytes_per_sec", &self.bytes_per_sec)
+            .finish()
+    }
+}
+
+impl Throttle {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let bytes_per_sec = bytes_per_sec.max(1) as f64;
+        Throttle {
+            bytes_per_sec,
+            bucket: Mutex::new(Bucket {
+                available: bytes_per_sec,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Process-wide budget for `bytes_per_sec`, so every client built from
+    /// the same settings draws from the one bucket.
+    pub fn shared(bytes_per_sec: u64) -> Arc<Throttle> {
+        let mut shared = match SHARED.lock() {
+            Ok(shared) => shared,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        shared
+            .entry(bytes_per_sec)
+            .or_insert_with(|| Arc::new(Throttle::new(bytes_per_sec)))
+            .clone()
+    }
+
+    /// Take `bytes` out of the budget, returning how long to wait before
+    /// reading any more.
+    fn reserve(&self, bytes: usize, now: Instant) -> Duration {
+        let mut bucket = match self.bucket.lock() {
+            Ok(bucket) => bucket,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        bucket.last_refill = now;
+        bucket.available =
+            (bucket.available + elapsed.as_secs_f64() * self.bytes_per_sec).min(self.bytes_per_sec);
+        bucket.available -= bytes as f64;
+
+        if bucket.available < 0.0 {
+            Duration::from_secs_f64(-bucket.available / self.bytes_per_sec)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Account for `bytes` read, sleeping if we're over budget.
+    pub async fn consume(&self, bytes: usize) {
+        let wait = self.reserve(bytes, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use super::Throttle;
+
+    #[test]
+    fn test_shared() {
+        assert!(Arc::ptr_eq(
+            &Throttle::shared(1234),
+            &Throttle::shared(1234)
+        ));
+        assert!(!Arc::ptr_eq(
+            &Throttle::shared(1234),
+            &Throttle::shared(4321)
+        ));
+    }
+
+    #[test]
+    fn test_reserve() {
+        let throttle = Throttle::new(1000);
+        let start = Instant::now();
+
+        // Starts w/ a second's worth of budget
+        assert_eq!(throttle.reserve(1000, start), Duration::ZERO);
+        assert_eq!(throttle.reserve(500, start), Duration::from_millis(500));
+        // Other readers queue up behind the debt
+        assert_eq!(throttle.reserve(500, start), Duration::from_secs(1));
+
+        // Refills over time
+        let later = start + Duration::from_secs(1);
+        assert_eq!(throttle.reserve(500, later), Duration::from_millis(500));
+        let much_later = later + Duration::from_secs(10);
+        assert_eq!(throttle.reserve(1000, much_later), Duration::ZERO);
+    }
+}
@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::sync::broadcast;
+
+use libspyglass::state::AppState;
+use libspyglass::task::AppShutdown;
+
+const HEALTH_CHECK_INTERVAL_S: u64 = 15;
+
+/// Holds the pid file for the lifetime of the server, removing it on drop.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        if let Ok(existing) = fs::read_to_string(path) {
+            log::warn!(
+                "Found existing pid file w/ pid {}, server may not have shut down cleanly",
+                existing.trim()
+            );
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, std::process::id().to_string())?;
+
+        Ok(PidFile {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Waits for a ctrl-c or, on unix systems, a SIGTERM/SIGHUP so service managers
+/// like systemd can stop the server gracefully.
+pub async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = signal(SignalKind::terminate())?;
+        let mut sighup = signal(SignalKind::hangup())?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res,
+            _ = sigterm.recv() => Ok(()),
+            _ = sighup.recv() => Ok(()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await
+    }
+}
+
+/// Periodically writes out the current server status to `path`. The file is
+/// removed once the server shuts down.
+pub async fn health_task(
+    state: AppState,
+    path: PathBuf,
+    mut shutdown_rx: broadcast::Receiver<AppShutdown>,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(HEALTH_CHECK_INTERVAL_S));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown_rx.recv() => {
+                let _ = fs::remove_file(&path);
+                return;
+            }
+        }
+
+        let is_paused = state
+            .app_state
+            .get("paused")
+            .map(|paused| *paused == "true")
+            .unwrap_or(false);
+
+        let health = format!(
+            "pid={}\nstatus={}\nnum_docs={}\nupdated_at={}\n",
+            std::process::id(),
+            if is_paused { "paused" } else { "running" },
+            state.index.reader.searcher().num_docs(),
+            Utc::now().to_rfc3339(),
+        );
+
+        if let Err(err) = fs::write(&path, health) {
+            log::error!("Unable to write health file: {}", err);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! GNOME Shell search provider, shows results in the Activities overview.
+//! See: https://developer.gnome.org/documentation/tutorials/search-provider.html
+use std::collections::HashMap;
+
+use tokio::sync::broadcast;
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::{dbus_interface, Connection, ConnectionBuilder};
+
+use crate::state::AppState;
+use crate::task::AppShutdown;
+
+pub const BUS_NAME: &str = "fyi.spyglass.SearchProvider";
+pub const OBJECT_PATH: &str = "/fyi/spyglass/SearchProvider";
+/// GNOME only shows a handful of results per provider.
+const MAX_RESULTS: usize = 5;
+
+pub struct SearchProvider {
+    state: AppState,
+}
+
+impl SearchProvider {
+    fn search(&self, terms: &[String]) -> Vec<String> {
+        let query = terms.join(" ");
+        if query.trim().is_empty() {
+            return Vec::new();
+        }
+
+        super::search(&self.state, &query, MAX_RESULTS)
+            .into_iter()
+            .map(|result| result.doc_id)
+            .collect()
+    }
+}
+
+#[dbus_interface(name = "org.gnome.Shell.SearchProvider2")]
+impl SearchProvider {
+    fn get_initial_result_set(&self, terms: Vec<String>) -> Vec<String> {
+        self.search(&terms)
+    }
+
+    fn get_subsearch_result_set(
+        &self,
+        _previous_results: Vec<String>,
+        terms: Vec<String>,
+    ) -> Vec<String> {
+        // Searches are cheap enough that we can just re-run them.
+        self.search(&terms)
+    }
+
+    fn get_result_metas(&self, identifiers: Vec<String>) -> Vec<HashMap<String, OwnedValue>> {
+        identifiers
+            .iter()
+            .filter_map(|doc_id| super::get_result(&self.state, doc_id))
+            .map(|result| {
+                let mut meta = HashMap::new();
+                meta.insert("id".to_string(), Value::from(result.doc_id).into());
+                meta.insert("name".to_string(), Value::from(result.title).into());
+                meta.insert(
+                    "description".to_string(),
+                    Value::from(format!("{} - {}", result.url, result.description)).into(),
+                );
+                meta
+            })
+            .collect()
+    }
+
+    fn activate_result(&self, identifier: String, _terms: Vec<String>, _timestamp: u32) {
+        if let Some(result) = super::get_result(&self.state, &identifier) {
+            super::open_url(&result.url);
+        }
+    }
+
+    fn launch_search(&self, terms: Vec<String>, _timestamp: u32) {
+        // Open the top result, there's no standalone results page to show.
+        if let Some(doc_id) = self.search(&terms).first() {
+            self.activate_result(doc_id.clone(), terms, 0);
+        }
+    }
+}
+
+async fn connect(state: AppState) -> zbus::Result<Connection> {
+    ConnectionBuilder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, SearchProvider { state })?
+        .build()
+        .await
+}
+
+/// Registers the search provider on the session bus & keeps it alive until
+/// the app shuts down.
+pub async fn start(state: AppState, mut shutdown_rx: broadcast::Receiver<AppShutdown>) {
+    let _conn = match connect(state).await {
+        Ok(conn) => conn,
+        Err(err) => {
+            log::error!("Unable to register GNOME search provider: {}", err);
+            return;
+        }
+    };
+
+    log::info!("GNOME search provider registered as {}", BUS_NAME);
+    let _ = shutdown_rx.recv().await;
+}
@@ -0,0 +1,128 @@
+//! KRunner integration for KDE Plasma.
+//! See: https://develop.kde.org/docs/plasma/krunner/
+use std::collections::HashMap;
+
+use tokio::sync::broadcast;
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::{dbus_interface, Connection, ConnectionBuilder};
+
+use crate::state::AppState;
+use crate::task::AppShutdown;
+
+pub const BUS_NAME: &str = "fyi.spyglass.KRunner";
+pub const OBJECT_PATH: &str = "/fyi/spyglass/KRunner";
+/// `Plasma::QueryMatch::PossibleMatch`
+const POSSIBLE_MATCH: i32 = 30;
+const ICON: &str = "internet-web-browser";
+
+/// (id, text, icon, type, relevance, properties)
+type RemoteMatch = (
+    String,
+    String,
+    String,
+    i32,
+    f64,
+    HashMap<String, OwnedValue>,
+);
+/// (id, text, icon)
+type RemoteAction = (String, String, String);
+
+pub struct Runner {
+    state: AppState,
+}
+
+/// Returns the actual search query if the runner should handle it.
+fn strip_trigger<'a>(query: &'a str, prefix: Option<&str>) -> Option<&'a str> {
+    let query = match prefix {
+        Some(prefix) if !prefix.is_empty() => query.strip_prefix(prefix)?,
+        _ => query,
+    };
+
+    let query = query.trim();
+    if query.is_empty() {
+        None
+    } else {
+        Some(query)
+    }
+}
+
+#[dbus_interface(name = "org.kde.krunner1")]
+impl Runner {
+    fn actions(&self) -> Vec<RemoteAction> {
+        Vec::new()
+    }
+
+    #[dbus_interface(name = "Match")]
+    fn match_query(&self, query: String) -> Vec<RemoteMatch> {
+        let settings = &self.state.user_settings;
+        let query = match strip_trigger(&query, settings.krunner_prefix.as_deref()) {
+            Some(query) => query,
+            None => return Vec::new(),
+        };
+
+        let results = super::search(&self.state, query, settings.krunner_max_results as usize);
+        let max_score = results
+            .iter()
+            .map(|result| result.score)
+            .fold(f32::EPSILON, f32::max);
+
+        results
+            .into_iter()
+            .map(|result| {
+                let mut properties = HashMap::new();
+                properties.insert("subtext".to_string(), Value::from(result.url).into());
+
+                (
+                    result.doc_id,
+                    result.title,
+                    ICON.to_string(),
+                    POSSIBLE_MATCH,
+                    (result.score / max_score) as f64,
+                    properties,
+                )
+            })
+            .collect()
+    }
+
+    fn run(&self, match_id: String, _action_id: String) {
+        if let Some(result) = super::get_result(&self.state, &match_id) {
+            super::open_url(&result.url);
+        }
+    }
+}
+
+async fn connect(state: AppState) -> zbus::Result<Connection> {
+    ConnectionBuilder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Runner { state })?
+        .build()
+        .await
+}
+
+/// Registers the runner on the session bus & keeps it alive until the app
+/// shuts down.
+pub async fn start(state: AppState, mut shutdown_rx: broadcast::Receiver<AppShutdown>) {
+    let _conn = match connect(state).await {
+        Ok(conn) => conn,
+        Err(err) => {
+            log::error!("Unable to register KRunner: {}", err);
+            return;
+        }
+    };
+
+    log::info!("KRunner registered as {}", BUS_NAME);
+    let _ = shutdown_rx.recv().await;
+}
+
+#[cfg(test)]
+mod test {
+    use super::strip_trigger;
+
+    #[test]
+    fn test_strip_trigger() {
+        assert_eq!(strip_trigger("rust lang", None), Some("rust lang"));
+        assert_eq!(strip_trigger("sg rust lang", Some("sg")), Some("rust lang"));
+        assert_eq!(strip_trigger("rust lang", Some("sg")), None);
+        assert_eq!(strip_trigger("sg ", Some("sg")), None);
+    }
+}
@@ -0,0 +1,78 @@
+//! Desktop search integrations exposed over D-Bus (Linux only).
+use std::collections::HashMap;
+
+use shared::response::SearchResult;
+use tantivy::Document;
+
+use crate::search::{extract_filters, SearchOptions, Searcher};
+use crate::state::AppState;
+
+#[cfg(feature = "gnome")]
+pub mod gnome;
+#[cfg(feature = "krunner")]
+pub mod krunner;
+
+fn get_text(doc: &Document, field: tantivy::schema::Field) -> String {
+    doc.get_first(field)
+        .and_then(|value| value.as_text())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn to_result(doc: &Document, score: f32) -> SearchResult {
+    let fields = Searcher::doc_fields();
+    SearchResult {
+        doc_id: get_text(doc, fields.id),
+        domain: get_text(doc, fields.domain),
+        title: get_text(doc, fields.title),
+        description: get_text(doc, fields.description),
+        url: get_text(doc, fields.url),
+        score,
+        is_archived: false,
+        snippet: None,
+        metadata: Default::default(),
+        favicon: None,
+    }
+}
+
+/// Run a search across all lenses, returning at most `limit` results.
+pub fn search(state: &AppState, query: &str, limit: usize) -> Vec<SearchResult> {
+    let lenses: HashMap<_, _> = state
+        .lenses
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+
+    let searcher = state.index.reader.searcher();
+    let (query, filters) = extract_filters(query);
+    let options = SearchOptions {
+        limit,
+        fuzzy: state.user_settings.fuzzy_search,
+        prefix: state.user_settings.prefix_search,
+        langs: filters.langs,
+        sites: filters.sites,
+        url_terms: filters.url_terms,
+        ..Default::default()
+    };
+    let (_, docs) = state.index.search_with_lens(&lenses, &[], &query, &options);
+    docs.into_iter()
+        .filter_map(|(score, doc_addr)| {
+            searcher
+                .doc(doc_addr)
+                .ok()
+                .map(|doc| to_result(&doc, score))
+        })
+        .collect()
+}
+
+/// Look up a single result by document id.
+pub fn get_result(state: &AppState, doc_id: &str) -> Option<SearchResult> {
+    Searcher::get_by_id(&state.index.reader, doc_id).map(|doc| to_result(&doc, 0.0))
+}
+
+/// Open a URL w/ the user's default application.
+pub fn open_url(url: &str) {
+    if let Err(err) = std::process::Command::new("xdg-open").arg(url).spawn() {
+        log::error!("Unable to open <{}>: {}", url, err);
+    }
+}
@@ -0,0 +1,212 @@
+//! Site icons shown next to search results. Icons are fetched once per domain
+//! while crawling & cached on disk under the data dir.
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use dashmap::DashSet;
+use url::Url;
+
+use crate::fetch::HTTPClient;
+
+/// Icons larger than this are skipped, they're shown at 16px anyways.
+const MAX_FAVICON_BYTES: usize = 100 * 1024;
+/// How long before an icon, or the lack of one, is fetched again.
+const FAVICON_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Saved in place of an icon for domains that don't have one.
+const NO_ICON_EXT: &str = "none";
+/// File extensions icons are saved under & their content types.
+const IMAGE_TYPES: [(&str, &str); 5] = [
+    ("ico", "image/x-icon"),
+    ("png", "image/png"),
+    ("gif", "image/gif"),
+    ("jpg", "image/jpeg"),
+    ("svg", "image/svg+xml"),
+];
+
+pub struct FaviconCache {
+    dir: PathBuf,
+    /// Domains w/ a fetch in progress, so each is only fetched once.
+    pending: DashSet<String>,
+}
+
+impl FaviconCache {
+    pub fn new(dir: PathBuf) -> Self {
+        FaviconCache {
+            dir,
+            pending: DashSet::new(),
+        }
+    }
+
+    fn path(&self, domain: &str, ext: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", domain, ext))
+    }
+
+    /// Saved icon for `domain` & its extension, or `NO_ICON_EXT` if we know
+    /// it doesn't have one.
+    fn cached(&self, domain: &str) -> Option<(PathBuf, &'static str)> {
+        IMAGE_TYPES
+            .iter()
+            .map(|(ext, _)| *ext)
+            .chain([NO_ICON_EXT])
+            .map(|ext| (self.path(domain, ext), ext))
+            .find(|(path, _)| path.exists())
+    }
+
+    /// Icon for `domain` as a data URI, ready to be used as an image source.
+    pub fn get(&self, domain: &str) -> Option<String> {
+        if !is_valid_domain(domain) {
+            return None;
+        }
+
+        let (path, ext) = self.cached(domain)?;
+        let (_, content_type) = IMAGE_TYPES.iter().find(|(known, _)| *known == ext)?;
+        let icon = std::fs::read(path).ok()?;
+        Some(format!(
+            "data:{};base64,{}",
+            content_type,
+            base64::encode(icon)
+        ))
+    }
+
+    /// Whether `domain` needs its icon fetched, i.e. it's never been fetched
+    /// or was fetched more than `FAVICON_TTL` ago.
+    pub fn is_stale(&self, domain: &str) -> bool {
+        if !is_valid_domain(domain) || self.pending.contains(domain) {
+            return false;
+        }
+
+        let fetched_at = self
+            .cached(domain)
+            .and_then(|(path, _)| path.metadata().ok())
+            .and_then(|meta| meta.modified().ok());
+        match fetched_at {
+            Some(fetched_at) => SystemTime::now()
+                .duration_since(fetched_at)
+                .map(|age| age > FAVICON_TTL)
+                .unwrap_or(false),
+            None => true,
+        }
+    }
+
+    /// Fetch & save the icon for `domain`, replacing any previous one.
+    pub async fn fetch(&self, client: &HTTPClient, domain: &str) -> anyhow::Result<()> {
+        if !self.is_stale(domain) || !self.pending.insert(domain.to_string()) {
+            return Ok(());
+        }
+
+        let icon = fetch_icon(client, domain).await;
+        let res = self.save(domain, icon.as_deref());
+        self.pending.remove(domain);
+        res
+    }
+
+    fn save(&self, domain: &str, icon: Option<&[u8]>) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        if let Some((path, _)) = self.cached(domain) {
+            std::fs::remove_file(path)?;
+        }
+
+        match icon.and_then(|icon| image_ext(icon).map(|ext| (icon, ext))) {
+            Some((icon, ext)) => std::fs::write(self.path(domain, ext), icon)?,
+            None => std::fs::write(self.path(domain, NO_ICON_EXT), [])?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Domains are used as file names, so anything unusual is skipped.
+fn is_valid_domain(domain: &str) -> bool {
+    !domain.is_empty()
+        && !domain.starts_with('.')
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+async fn fetch_icon(client: &HTTPClient, domain: &str) -> Option<Vec<u8>> {
+    let url = Url::parse(&format!("https://{}/favicon.ico", domain)).ok()?;
+    let mut resp = match client.get(&url).await {
+        Ok(resp) if resp.status().is_success() => resp,
+        Ok(_) => return None,
+        Err(err) => {
+            log::debug!("Unable to fetch favicon for {}: {}", domain, err);
+            return None;
+        }
+    };
+
+    // Skip icons that tell us upfront they're too large
+    if resp.content_length().unwrap_or(0) as usize > MAX_FAVICON_BYTES {
+        return None;
+    }
+
+    let mut icon = Vec::new();
+    let complete = loop {
+        match resp.chunk().await {
+            Ok(Some(chunk)) if icon.len() + chunk.len() <= MAX_FAVICON_BYTES => {
+                icon.extend_from_slice(&chunk)
+            }
+            Ok(Some(_)) | Err(_) => break false,
+            Ok(None) => break true,
+        }
+    };
+
+    client.consume(icon.len()).await;
+    complete.then_some(icon)
+}
+
+/// Servers often send icons w/ a generic content type, so we go by the
+/// file signature instead. `None` if it's not an image we know.
+fn image_ext(icon: &[u8]) -> Option<&'static str> {
+    if icon.starts_with(b"\x00\x00\x01\x00") {
+        Some("ico")
+    } else if icon.starts_with(b"\x89PNG") {
+        Some("png")
+    } else if icon.starts_with(b"GIF8") {
+        Some("gif")
+    } else if icon.starts_with(b"\xFF\xD8\xFF") {
+        Some("jpg")
+    } else if String::from_utf8_lossy(&icon[..icon.len().min(1024)]).contains("<svg") {
+        Some("svg")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{image_ext, FaviconCache};
+
+    #[test]
+    fn test_image_ext() {
+        assert_eq!(image_ext(b"\x89PNG\r\n\x1a\n"), Some("png"));
+        assert_eq!(image_ext(b"\x00\x00\x01\x00\x01\x00"), Some("ico"));
+        assert_eq!(
+            image_ext(b"<?xml version=\"1.0\"?><svg></svg>"),
+            Some("svg")
+        );
+        assert_eq!(image_ext(b"<html><body>Not found</body></html>"), None);
+    }
+
+    #[test]
+    fn test_cache() {
+        let dir = std::env::temp_dir().join(format!("favicons-{}", uuid::Uuid::new_v4()));
+        let cache = FaviconCache::new(dir.clone());
+        assert!(cache.is_stale("example.com"));
+        assert!(!cache.is_stale("../example.com"));
+
+        cache.save("example.com", Some(&b"\x89PNG"[..])).unwrap();
+        assert!(!cache.is_stale("example.com"));
+        assert_eq!(
+            cache.get("example.com"),
+            Some("data:image/png;base64,iVBORw==".to_string())
+        );
+
+        // Sites w/o an icon aren't fetched again right away either
+        cache.save("example.com", Some(&b"Not found"[..])).unwrap();
+        assert!(!cache.is_stale("example.com"));
+        assert_eq!(cache.get("example.com"), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
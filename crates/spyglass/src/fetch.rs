@@ -1,15 +1,29 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use anyhow::Result;
 use http::StatusCode;
-use reqwest::{Client, Error, Response};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, COOKIE, USER_AGENT};
+use reqwest::redirect::Policy;
+use reqwest::{Client, ClientBuilder, Error, NoProxy, Proxy, Response};
 use url::Url;
 
-static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
+use shared::config::{ProxySettings, UserSettings, DEFAULT_USER_AGENT};
+
+use crate::crawler::throttle::Throttle;
+
+/// Max number of redirects followed for a single request.
+const MAX_REDIRECTS: usize = 10;
 
 /// A wrapper around reqwest that for HTTP related queries that handles retries,
 /// downgrading from HTTPS -> HTTP, 429 too many requests, etc.
 #[derive(Clone, Debug)]
 pub struct HTTPClient {
     client: Client,
+    /// User-Agents to send instead of the default for specific domains.
+    domain_user_agents: Arc<HashMap<String, String>>,
+    /// Bandwidth budget shared by every client, no limit when not set.
+    throttle: Option<Arc<Throttle>>,
 }
 
 impl Default for HTTPClient {
@@ -18,29 +32,147 @@ impl Default for HTTPClient {
     }
 }
 
+fn to_proxy(settings: &ProxySettings) -> anyhow::Result<Proxy> {
+    let mut proxy = Proxy::all(&settings.url)?;
+    if let Some(username) = &settings.username {
+        proxy = proxy.basic_auth(username, settings.password.as_deref().unwrap_or_default());
+    }
+
+    Ok(proxy.no_proxy(NoProxy::from_string(&settings.no_proxy.join(","))))
+}
+
+fn base_builder(user_agent: &str, proxy: Option<&ProxySettings>) -> ClientBuilder {
+    let mut builder = Client::builder().user_agent(user_agent);
+    if let Some(settings) = proxy {
+        match to_proxy(settings) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            // Better to crawl w/o than not at all.
+            Err(err) => log::error!("Invalid proxy <{}>: {}", settings.url, err),
+        }
+    }
+
+    builder
+}
+
+/// Builder w/ the User-Agent & proxy from the user settings, for requests
+/// that don't go through `HTTPClient`.
+pub fn client_builder(settings: &UserSettings) -> ClientBuilder {
+    base_builder(&settings.user_agent, settings.proxy.as_ref())
+}
+
 impl HTTPClient {
     pub fn new() -> Self {
-        let client = reqwest::Client::builder()
-            .user_agent(APP_USER_AGENT)
+        Self::build(DEFAULT_USER_AGENT, None, Policy::limited(MAX_REDIRECTS))
+    }
+
+    /// Client configured w/ the User-Agent & proxy, if any, from the user
+    /// settings.
+    pub fn with_settings(settings: &UserSettings) -> Self {
+        Self::with_redirect_policy(settings, Policy::limited(MAX_REDIRECTS))
+    }
+
+    /// Same as `with_settings`, but only follows redirects to URLs `allow`
+    /// accepts, e.g. to keep plugins to the hosts they have permission for.
+    pub fn with_redirect_filter<F>(settings: &UserSettings, allow: F) -> Self
+    where
+        F: Fn(&Url) -> bool + Send + Sync + 'static,
+    {
+        let policy = Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if allow(attempt.url()) {
+                attempt.follow()
+            } else {
+                let err = format!("redirect to {} is not allowed", attempt.url());
+                attempt.error(err)
+            }
+        });
+
+        Self::with_redirect_policy(settings, policy)
+    }
+
+    /// Same as `with_settings`, but stops at redirects to another host or to
+    /// plain HTTP, for requests w/ credentials that shouldn't follow them.
+    pub fn with_same_host_redirects(settings: &UserSettings) -> Self {
+        let policy = Policy::custom(|attempt| {
+            let same_host = attempt
+                .previous()
+                .first()
+                .map(|first| first.host_str() == attempt.url().host_str())
+                .unwrap_or(true);
+
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if same_host && attempt.url().scheme() == "https" {
+                attempt.follow()
+            } else {
+                attempt.stop()
+            }
+        });
+
+        Self::with_redirect_policy(settings, policy)
+    }
+
+    fn with_redirect_policy(settings: &UserSettings, redirect: Policy) -> Self {
+        let mut client = Self::build(&settings.user_agent, settings.proxy.as_ref(), redirect);
+        client.domain_user_agents = Arc::new(settings.domain_user_agents.clone());
+        client.throttle = settings.crawl_bytes_per_sec.map(Throttle::shared);
+        client
+    }
+
+    fn build(user_agent: &str, proxy: Option<&ProxySettings>, redirect: Policy) -> Self {
+        let client = base_builder(user_agent, proxy)
+            .redirect(redirect)
             // TODO: Make configurable
             .timeout(std::time::Duration::from_secs(30))
             .build()
             .expect("Unable to create reqwest client");
+        HTTPClient {
+            client,
+            domain_user_agents: Arc::new(HashMap::new()),
+            throttle: None,
+        }
+    }
+
+    /// Accounts for `bytes` read from a response, waiting if we're over the
+    /// bandwidth budget. Call it once the body has been read so the wait
+    /// doesn't count against the request timeout.
+    pub async fn consume(&self, bytes: usize) {
+        if let Some(throttle) = &self.throttle {
+            throttle.consume(bytes).await;
+        }
+    }
+
+    /// Adds the User-Agent override for the domain of `url`, if there is one.
+    fn with_user_agent(&self, url: &Url, mut headers: HeaderMap) -> HeaderMap {
+        let user_agent = url
+            .host_str()
+            .and_then(|host| self.domain_user_agents.get(host))
+            .and_then(|user_agent| HeaderValue::from_str(user_agent).ok());
+        if let Some(user_agent) = user_agent {
+            headers.insert(USER_AGENT, user_agent);
+        }
 
-        HTTPClient { client }
+        headers
     }
 
     pub async fn head(&self, url: &Url) -> Result<Response, Error> {
         let mut url = url.clone();
 
+        let headers = self.with_user_agent(&url, HeaderMap::new());
         url.set_scheme("https")
             .expect("Unable to set scheme to HTTPS");
-        let mut res = self.client.head(url.clone()).send().await;
+        let mut res = self
+            .client
+            .head(url.clone())
+            .headers(headers.clone())
+            .send()
+            .await;
         if let Err(e) = &res {
             if e.is_request() {
                 url.set_scheme("http")
                     .expect("Unable to set scheme to HTTP");
-                res = self.client.head(url).send().await;
+                res = self.client.head(url).headers(headers).send().await;
             }
         }
 
@@ -48,17 +180,30 @@ impl HTTPClient {
     }
 
     pub async fn get(&self, url: &Url) -> Result<Response, Error> {
+        self.get_with_headers(url, HeaderMap::new()).await
+    }
+
+    /// Same as `get`, w/ extra request headers, e.g. for conditional requests.
+    pub async fn get_with_headers(&self, url: &Url, headers: HeaderMap) -> Result<Response, Error> {
         let mut url = url.clone();
+        let headers = self.with_user_agent(&url, headers);
+        let request = |url: Url| self.client.get(url).headers(headers.clone()).send();
+
+        // Never send credentials in the clear. Credential headers, e.g. an
+        // `X-Api-Key`, are marked sensitive, see `crawler::auth`.
+        let is_authenticated = headers.contains_key(AUTHORIZATION)
+            || headers.contains_key(COOKIE)
+            || headers.values().any(|value| value.is_sensitive());
 
         // Attempt HTTPS first, if that fails switch to HTTP
         url.set_scheme("https")
             .expect("Unable to set scheme to HTTPS");
-        let mut res = self.client.get(url.clone()).send().await;
+        let mut res = request(url.clone()).await;
         if let Err(e) = &res {
-            if e.is_request() {
+            if e.is_request() && !is_authenticated {
                 url.set_scheme("http")
                     .expect("Unable to set scheme to HTTP");
-                res = self.client.get(url.clone()).send().await;
+                res = request(url.clone()).await;
             }
         }
 
@@ -68,7 +213,7 @@ impl HTTPClient {
                 if status == StatusCode::TOO_MANY_REQUESTS {
                     // Probably overkill, but if this becomes a problem we can revisit
                     tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                    res = self.client.get(url).send().await;
+                    res = request(url).await;
                 }
             }
         }
@@ -79,9 +224,43 @@ impl HTTPClient {
 
 #[cfg(test)]
 mod test {
-    use super::HTTPClient;
+    use super::{to_proxy, HTTPClient};
+    use reqwest::header::{HeaderMap, USER_AGENT};
+    use shared::config::{ProxySettings, UserSettings};
     use url::Url;
 
+    #[test]
+    fn test_to_proxy() {
+        let mut settings = ProxySettings {
+            url: "http://proxy.example.com:8080".to_string(),
+            username: Some("jane".to_string()),
+            password: None,
+            no_proxy: vec!["localhost".to_string()],
+        };
+        assert!(to_proxy(&settings).is_ok());
+
+        settings.url = "not a proxy".to_string();
+        assert!(to_proxy(&settings).is_err());
+    }
+
+    #[test]
+    fn test_with_user_agent() {
+        let mut settings = UserSettings::default();
+        settings
+            .domain_user_agents
+            .insert("example.com".to_string(), "Mozilla/5.0".to_string());
+        let client = HTTPClient::with_settings(&settings);
+
+        let url = Url::parse("https://example.com/docs").unwrap();
+        let headers = client.with_user_agent(&url, HeaderMap::new());
+        assert_eq!(headers.get(USER_AGENT).unwrap(), "Mozilla/5.0");
+
+        // Everything else uses the client's default
+        let url = Url::parse("https://docs.example.com/").unwrap();
+        let headers = client.with_user_agent(&url, HeaderMap::new());
+        assert!(headers.get(USER_AGENT).is_none());
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_http_switch() {
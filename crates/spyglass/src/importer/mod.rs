@@ -0,0 +1,3 @@
+//! Importers pull in content the user already has on their machine, without
+//! needing to crawl it.
+pub mod spotlight;
@@ -0,0 +1,245 @@
+//! Imports local documents, mail, & notes from Spotlight's metadata store
+//! using the `mdfind` & `mdls` command line tools (macOS only).
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use url::Url;
+
+use entities::models::indexed_document;
+use entities::sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+
+use crate::state::AppState;
+use crate::task;
+
+/// Max number of items imported per kind, Spotlight can easily return
+/// hundreds of thousands of files.
+const MAX_ITEMS_PER_KIND: usize = 5000;
+/// Metadata attributes we request from `mdls`.
+const ATTRIBUTES: [&str; 5] = [
+    "kMDItemDisplayName",
+    "kMDItemTitle",
+    "kMDItemSubject",
+    "kMDItemDescription",
+    "kMDItemComment",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpotlightKind {
+    Documents,
+    Mail,
+    Notes,
+}
+
+impl SpotlightKind {
+    pub fn all() -> Vec<SpotlightKind> {
+        vec![
+            SpotlightKind::Documents,
+            SpotlightKind::Mail,
+            SpotlightKind::Notes,
+        ]
+    }
+
+    /// Spotlight query used to find items of this kind.
+    pub fn query(&self) -> &'static str {
+        match self {
+            SpotlightKind::Documents => {
+                "kMDItemContentTypeTree == \"public.text\" || kMDItemContentTypeTree == \"com.adobe.pdf\" || kMDItemContentTypeTree == \"public.composite-content\""
+            }
+            SpotlightKind::Mail => "kMDItemContentType == \"com.apple.mail.emlx\"",
+            SpotlightKind::Notes => "kMDItemContentType == \"com.apple.notes.note\"",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SpotlightItem {
+    pub path: PathBuf,
+    pub title: String,
+    pub snippet: String,
+}
+
+/// Parses the NUL separated output of `mdfind -0`.
+fn parse_mdfind(output: &[u8]) -> Vec<PathBuf> {
+    output
+        .split(|b| *b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(String::from_utf8_lossy(path).to_string()))
+        .collect()
+}
+
+/// Parses `mdls` output, e.g. `kMDItemTitle = "Quarterly Report"`. Missing
+/// attributes are reported as `(null)` & skipped. Multi-line values (arrays)
+/// are ignored since we only request single value attributes.
+fn parse_mdls(output: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    for line in output.lines() {
+        let (key, value) = match line.split_once(" = ") {
+            Some(pair) => pair,
+            None => continue,
+        };
+
+        let value = value.trim();
+        if value == "(null)" || value == "(" {
+            continue;
+        }
+
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value)
+            .replace("\\\"", "\"");
+
+        if !value.is_empty() {
+            attributes.insert(key.trim().to_string(), value);
+        }
+    }
+
+    attributes
+}
+
+fn to_item(path: &Path, attributes: &HashMap<String, String>) -> SpotlightItem {
+    let title = ["kMDItemTitle", "kMDItemSubject", "kMDItemDisplayName"]
+        .iter()
+        .find_map(|key| attributes.get(*key))
+        .cloned()
+        .or_else(|| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        })
+        .unwrap_or_default();
+
+    let snippet = ["kMDItemDescription", "kMDItemComment"]
+        .iter()
+        .find_map(|key| attributes.get(*key))
+        .cloned()
+        .unwrap_or_default();
+
+    SpotlightItem {
+        path: path.to_path_buf(),
+        title,
+        snippet,
+    }
+}
+
+async fn find(kind: SpotlightKind) -> anyhow::Result<Vec<PathBuf>> {
+    let output = Command::new("mdfind")
+        .arg("-0")
+        .arg(kind.query())
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "mdfind failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(parse_mdfind(&output.stdout))
+}
+
+async fn metadata(path: &Path) -> anyhow::Result<SpotlightItem> {
+    let mut cmd = Command::new("mdls");
+    for attribute in ATTRIBUTES {
+        cmd.arg("-name").arg(attribute);
+    }
+
+    let output = cmd.arg(path).output().await?;
+    let attributes = parse_mdls(&String::from_utf8_lossy(&output.stdout));
+    Ok(to_item(path, &attributes))
+}
+
+/// Import items of `kind` that haven't been indexed yet, returning the
+/// number of items added.
+#[tracing::instrument(skip(state))]
+pub async fn import(state: &AppState, kind: SpotlightKind) -> anyhow::Result<usize> {
+    let paths = find(kind).await?;
+
+    let mut added = 0;
+    // Already indexed items don't count towards the limit, otherwise the same
+    // first batch would be checked on every run & nothing new added.
+    let mut num_new = 0;
+    for path in paths {
+        let url = match Url::from_file_path(&path) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+
+        let is_indexed = indexed_document::Entity::find()
+            .filter(indexed_document::Column::Url.eq(url.as_str()))
+            .one(&state.db)
+            .await?
+            .is_some();
+        if is_indexed {
+            continue;
+        }
+
+        if num_new >= MAX_ITEMS_PER_KIND {
+            break;
+        }
+        num_new += 1;
+
+        let item = match metadata(&path).await {
+            Ok(item) => item,
+            Err(err) => {
+                log::warn!("Unable to read metadata for {}: {}", path.display(), err);
+                continue;
+            }
+        };
+
+        // Include the path so items can be found by folder/file name.
+        let content = format!("{}\n{}\n{}", item.title, path.display(), item.snippet);
+        match task::add_to_index(state, &url, &item.title, &item.snippet, &content, "", None).await
+        {
+            Ok(_) => added += 1,
+            Err(err) => log::warn!("Unable to index {}: {}", path.display(), err),
+        }
+    }
+
+    Ok(added)
+}
+
+/// Import everything Spotlight knows about that we care about.
+pub async fn import_all(state: AppState) {
+    for kind in SpotlightKind::all() {
+        match import(&state, kind).await {
+            Ok(added) => log::info!("Imported {} {:?} items from Spotlight", added, kind),
+            Err(err) => log::error!("Unable to import {:?} from Spotlight: {}", kind, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_mdfind, parse_mdls, to_item};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_parse_mdfind() {
+        let paths = parse_mdfind(b"/Users/alice/a.txt\0/Users/alice/b c.pdf\0");
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("/Users/alice/a.txt"),
+                PathBuf::from("/Users/alice/b c.pdf")
+            ]
+        );
+        assert!(parse_mdfind(b"").is_empty());
+    }
+
+    #[test]
+    fn test_parse_mdls() {
+        let output = "kMDItemComment     = (null)\nkMDItemDescription = \"Numbers for \\\"Q3\\\"\"\nkMDItemDisplayName = \"report.pdf\"\nkMDItemTitle       = \"Quarterly Report\"\n";
+        let attributes = parse_mdls(output);
+        assert!(!attributes.contains_key("kMDItemComment"));
+
+        let item = to_item(Path::new("/Users/alice/report.pdf"), &attributes);
+        assert_eq!(item.title, "Quarterly Report");
+        assert_eq!(item.snippet, "Numbers for \"Q3\"");
+
+        // Falls back to the file name
+        let item = to_item(Path::new("/Users/alice/notes.txt"), &parse_mdls(""));
+        assert_eq!(item.title, "notes.txt");
+    }
+}
@@ -1,10 +1,21 @@
 #[macro_use]
 extern crate html5ever;
 
+pub mod backup;
 pub mod crawler;
+#[cfg(all(target_os = "linux", feature = "zbus"))]
+pub mod dbus;
+pub mod favicon;
 pub mod fetch;
+pub mod importer;
+pub mod memory;
+pub mod pipeline;
 pub mod plugin;
+pub mod remote;
+pub mod scheduler;
 pub mod scraper;
 pub mod search;
 pub mod state;
+pub mod status;
+pub mod sync;
 pub mod task;
@@ -1,44 +1,24 @@
 extern crate notify;
 //use crate::importer::FirefoxImporter;
-use std::io;
-use std::time::Duration;
-use tokio::signal;
 use tokio::sync::{broadcast, mpsc};
 use tracing_log::LogTracer;
-use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter};
 
 use entities::models::{crawl_queue, lens};
-use libspyglass::plugin;
 use libspyglass::state::AppState;
 use libspyglass::task::{self, AppShutdown};
+use libspyglass::{backup, pipeline, plugin, remote};
 use migration::{Migrator, MigratorTrait};
 use shared::config::Config;
+use shared::instance::{self, Activation, Instance};
 
 mod api;
+mod daemon;
+mod telemetry;
 
-use crate::api::start_api_ipc;
+use crate::api::{start_api_ipc, start_es_api, start_http_api, start_worker_api};
+use crate::daemon::PidFile;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let file_appender = tracing_appender::rolling::daily(Config::logs_dir(), "server.log");
-    let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
-
-    let subscriber = tracing_subscriber::registry()
-        .with(
-            EnvFilter::from_default_env()
-                .add_directive(tracing::Level::INFO.into())
-                .add_directive("tantivy=WARN".parse().expect("Invalid EnvFilter"))
-                .add_directive(
-                    "wasmer_compiler_cranelift=WARN"
-                        .parse()
-                        .expect("Invalid EnvFilter"),
-                ),
-        )
-        .with(fmt::Layer::new().with_writer(io::stdout))
-        .with(fmt::Layer::new().with_ansi(false).with_writer(non_blocking));
-
-    tracing::subscriber::set_global_default(subscriber).expect("Unable to set a global subscriber");
-    LogTracer::init()?;
-
     let config = Config::new();
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -46,6 +26,52 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build()
         .expect("Unable to create tokio runtime");
 
+    let _telemetry_guard = {
+        // The OTLP exporter needs a runtime to spawn its background task on.
+        let _rt_guard = rt.enter();
+        telemetry::init(
+            &config.user_settings,
+            telemetry::trace_dump_path(std::env::args()),
+        )
+        .expect("Unable to set a global subscriber")
+    };
+    LogTracer::init()?;
+    telemetry::check_settings(&config.user_settings);
+
+    // Two servers would fight over the same index & database.
+    let _instance_lock = match instance::acquire(&config.server_lock_file(), &Activation::default())
+    {
+        Ok(Instance::Primary(mut lock)) => {
+            lock.listen(|_| log::info!("Another server tried to start, ignoring"));
+            Some(lock)
+        }
+        Ok(Instance::Secondary) => {
+            log::info!("spyglass server is already running, exiting");
+            return Ok(());
+        }
+        Err(err) => {
+            log::warn!("Unable to check for a running server: {}", err);
+            None
+        }
+    };
+
+    // Swap in a backup staged by `restore_backup` before anything opens the
+    // database or index.
+    match backup::apply_pending_restore(
+        &config.restore_dir(),
+        &config.db_file(),
+        &config.index_dir(),
+    ) {
+        Ok(true) => log::info!("Restored backup"),
+        Ok(false) => {}
+        // Don't run on top of a half restored library, the restore is picked
+        // up again on the next start.
+        Err(err) => {
+            log::error!("Unable to restore backup: {}", err);
+            return Ok(());
+        }
+    }
+
     // Initialize/Load user preferences
     let mut state = rt.block_on(AppState::new(&config));
 
@@ -60,15 +86,33 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    // Let service managers & other tools know we're running.
+    let _pid_file = match PidFile::create(&config.pid_file()) {
+        Ok(pid_file) => Some(pid_file),
+        Err(err) => {
+            log::warn!("Unable to write pid file: {}", err);
+            None
+        }
+    };
+
+    // Clients need this to talk to any of our APIs
+    let api_token = match Config::api_token() {
+        Ok(token) => token,
+        Err(err) => {
+            log::error!("Unable to load API token - {:?}", err);
+            return Ok(());
+        }
+    };
+
     // Start IPC server
-    let server = start_api_ipc(&state).expect("Unable to start IPC server");
-    rt.block_on(start_backend(&mut state, &config));
+    let server = start_api_ipc(&state, &config, &api_token).expect("Unable to start IPC server");
+    rt.block_on(start_backend(&mut state, &config, &api_token));
     server.close();
 
     Ok(())
 }
 
-async fn start_backend(state: &mut AppState, config: &Config) {
+async fn start_backend(state: &mut AppState, config: &Config, api_token: &str) {
     // TODO: Implement user-friendly start-up wizard
     // if state.config.user_settings.run_wizard {
     //     // Import data from Firefox
@@ -92,6 +136,15 @@ async fn start_backend(state: &mut AppState, config: &Config) {
             .try_into()
             .expect("Unable to parse inflight_crawl_limit"),
     );
+    // Workers notify the scheduler when a task is finished
+    let (crawl_done_tx, crawl_done_rx) = mpsc::channel(
+        state
+            .user_settings
+            .inflight_crawl_limit
+            .value()
+            .try_into()
+            .expect("Unable to parse inflight_crawl_limit"),
+    );
 
     // Channel for shutdown listeners
     let (shutdown_tx, _) = broadcast::channel::<AppShutdown>(16);
@@ -111,55 +164,127 @@ async fn start_backend(state: &mut AppState, config: &Config) {
         shutdown_tx.subscribe(),
     ));
 
-    // Crawl scheduler
-    let manager_handle = tokio::spawn(task::manager_task(
+    let crawl_handles = match state.user_settings.remote_primary.clone() {
+        // Crawl on behalf of another instance
+        Some(primary) => vec![tokio::spawn(remote::worker_task(
+            state.clone(),
+            primary,
+            shutdown_tx.subscribe(),
+        ))],
+        None => vec![
+            // Crawl scheduler
+            tokio::spawn(task::manager_task(
+                state.clone(),
+                crawl_queue_tx,
+                crawl_done_rx,
+                shutdown_tx.subscribe(),
+            )),
+            // Crawlers
+            tokio::spawn(pipeline::run(state.clone(), crawl_queue_rx, crawl_done_tx)),
+        ],
+    };
+
+    // Clean up crew. Commit anything added to the index in batches
+    let commit_handle = tokio::spawn(task::commit_task(state.clone(), shutdown_tx.subscribe()));
+
+    // Plugin server
+    let pm_handle = tokio::spawn(plugin::plugin_manager(
         state.clone(),
-        crawl_queue_tx,
+        config.clone(),
+        plugin_cmd_tx.clone(),
+        plugin_cmd_rx,
+        shutdown_tx.subscribe(),
+    ));
+
+    // Reload plugins as they're rebuilt
+    let plugin_watcher_handle = tokio::spawn(plugin::plugin_watcher(
+        config.clone(),
+        plugin_cmd_tx.clone(),
         shutdown_tx.subscribe(),
     ));
 
-    // Crawlers
-    let worker_handle = tokio::spawn(task::worker_task(
+    // Elasticsearch-compatible search endpoint
+    if let Some(port) = state.user_settings.es_api_port {
+        tokio::spawn(start_es_api(
+            state.clone(),
+            api_token.to_string(),
+            port,
+            shutdown_tx.subscribe(),
+        ));
+    }
+
+    // Let remote crawl workers pull from our queue
+    if let Some(settings) = state.user_settings.worker_api.clone() {
+        tokio::spawn(start_worker_api(
+            state.clone(),
+            settings,
+            shutdown_tx.subscribe(),
+        ));
+    }
+
+    // Cached page viewer & REST API
+    match Config::search_token() {
+        Ok(search_token) => {
+            tokio::spawn(start_http_api(
+                state.clone(),
+                api_token.to_string(),
+                search_token,
+                state.user_settings.http_api_port,
+                shutdown_tx.subscribe(),
+            ));
+        }
+        Err(err) => log::error!("Unable to load search token - {:?}", err),
+    }
+
+    // Desktop search integrations
+    #[cfg(all(target_os = "linux", feature = "gnome"))]
+    tokio::spawn(libspyglass::dbus::gnome::start(
+        state.clone(),
+        shutdown_tx.subscribe(),
+    ));
+    #[cfg(all(target_os = "linux", feature = "krunner"))]
+    tokio::spawn(libspyglass::dbus::krunner::start(
         state.clone(),
-        crawl_queue_rx,
         shutdown_tx.subscribe(),
     ));
 
-    // Clean up crew. Commit anything added to the index in the last 10s
-    {
-        let state = state.clone();
-        let _ = tokio::spawn(async move {
-            let mut interval = tokio::time::interval(Duration::from_secs(10));
-
-            loop {
-                interval.tick().await;
-                if let Err(err) = state
-                    .index
-                    .writer
-                    .lock()
-                    .expect("Unable to get index lock")
-                    .commit()
-                {
-                    log::error!("commit loop error: {:?}", err);
-                }
-            }
-        });
+    #[cfg(target_os = "macos")]
+    if state.user_settings.spotlight_import {
+        tokio::spawn(libspyglass::importer::spotlight::import_all(state.clone()));
     }
 
-    // Plugin server
-    let pm_handle = tokio::spawn(plugin::plugin_manager(
+    // Periodic background tasks
+    let scheduler_handle = tokio::spawn(libspyglass::scheduler::run(
         state.clone(),
         config.clone(),
-        plugin_cmd_tx.clone(),
-        plugin_cmd_rx,
+        shutdown_tx.subscribe(),
+    ));
+
+    // Keep an eye on memory usage
+    let memory_handle = tokio::spawn(libspyglass::memory::monitor_task(
+        state.clone(),
+        shutdown_tx.subscribe(),
+    ));
+
+    // Push status changes to subscribers
+    let status_handle = tokio::spawn(libspyglass::status::monitor_task(
+        state.clone(),
+        shutdown_tx.subscribe(),
+    ));
+
+    // Health check file
+    let health_handle = tokio::spawn(daemon::health_task(
+        state.clone(),
+        config.health_file(),
         shutdown_tx.subscribe(),
     ));
 
     // Gracefully handle shutdowns
-    match signal::ctrl_c().await {
+    match daemon::shutdown_signal().await {
         Ok(()) => {
             lens_watcher_handle.abort();
             pm_handle.abort();
+            plugin_watcher_handle.abort();
             log::warn!("Shutdown request received");
             shutdown_tx
                 .send(AppShutdown::Now)
@@ -173,5 +298,20 @@ async fn start_backend(state: &mut AppState, config: &Config) {
         }
     }
 
-    let _ = tokio::join!(manager_handle, worker_handle);
+    let _ = tokio::join!(
+        futures::future::join_all(crawl_handles),
+        commit_handle,
+        health_handle,
+        memory_handle,
+        scheduler_handle,
+        status_handle
+    );
+
+    // Crawls that were in-flight may have finished after the commit task
+    // shut down, make sure their writes aren't lost.
+    if state.index.num_pending() > 0 {
+        if let Err(err) = state.index.commit() {
+            log::error!("Unable to commit pending writes: {:?}", err);
+        }
+    }
 }
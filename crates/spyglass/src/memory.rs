@@ -0,0 +1,172 @@
+//! Keeps track of how much memory we're using & sheds load when we go over
+//! the user configured limit.
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+
+use shared::response::MemoryStats;
+
+use crate::state::AppState;
+use crate::task::AppShutdown;
+
+/// How often memory usage is checked.
+const CHECK_INTERVAL_S: u64 = 5;
+/// Once throttled, resume when usage drops below this fraction of the limit
+/// so we don't flip flop around the limit.
+const RESUME_RATIO: f64 = 0.9;
+
+/// Total size of the response bodies currently being crawled.
+static INFLIGHT_BODY_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Tracks the bytes read for a single response body, releasing them when
+/// dropped.
+#[derive(Debug, Default)]
+pub struct InflightBytes {
+    bytes: usize,
+}
+
+impl InflightBytes {
+    pub fn add(&mut self, bytes: usize) {
+        self.bytes += bytes;
+        INFLIGHT_BODY_BYTES.fetch_add(bytes, Ordering::SeqCst);
+    }
+}
+
+impl Drop for InflightBytes {
+    fn drop(&mut self) {
+        INFLIGHT_BODY_BYTES.fetch_sub(self.bytes, Ordering::SeqCst);
+    }
+}
+
+pub fn inflight_body_bytes() -> u64 {
+    INFLIGHT_BODY_BYTES.load(Ordering::SeqCst) as u64
+}
+
+/// Resident set size of the current process, if the platform supports it.
+pub fn rss_bytes() -> Option<u64> {
+    memory_stats::memory_stats().map(|stats| stats.physical_mem as u64)
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryMonitor {
+    rss: AtomicU64,
+    plugins: AtomicU64,
+    throttled: AtomicBool,
+}
+
+impl MemoryMonitor {
+    /// Is crawling paused due to memory pressure?
+    pub fn is_throttled(&self) -> bool {
+        self.throttled.load(Ordering::SeqCst)
+    }
+
+    pub fn set_plugin_bytes(&self, bytes: u64) {
+        self.plugins.store(bytes, Ordering::SeqCst);
+    }
+}
+
+fn should_throttle(rss: u64, limit: u64, is_throttled: bool) -> bool {
+    if is_throttled {
+        rss as f64 >= limit as f64 * RESUME_RATIO
+    } else {
+        rss >= limit
+    }
+}
+
+fn limit_bytes(state: &AppState) -> Option<u64> {
+    state
+        .user_settings
+        .memory_limit_mb
+        .map(|limit| limit as u64 * 1_000_000)
+}
+
+pub fn stats(state: &AppState) -> MemoryStats {
+    let monitor = &state.memory;
+    MemoryStats {
+        rss: monitor.rss.load(Ordering::SeqCst),
+        index_writer: state.user_settings.index_writer_memory_mb as u64 * 1_000_000,
+        inflight_bodies: inflight_body_bytes(),
+        plugins: monitor.plugins.load(Ordering::SeqCst),
+        limit: limit_bytes(state),
+        is_throttled: monitor.is_throttled(),
+    }
+}
+
+/// Periodically checks memory usage. When over the limit, crawling is paused
+/// (see `task::manager_task`) and pending index writes are flushed.
+pub async fn monitor_task(state: AppState, mut shutdown_rx: broadcast::Receiver<AppShutdown>) {
+    log::info!("memory monitor started");
+
+    let limit = limit_bytes(&state);
+    let mut interval = tokio::time::interval(Duration::from_secs(CHECK_INTERVAL_S));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown_rx.recv() => {
+                log::info!("🛑 Shutting down memory monitor");
+                return;
+            }
+        }
+
+        let rss = match rss_bytes() {
+            Some(rss) => rss,
+            None => continue,
+        };
+        state.memory.rss.store(rss, Ordering::SeqCst);
+
+        let limit = match limit {
+            Some(limit) => limit,
+            None => continue,
+        };
+
+        let was_throttled = state.memory.is_throttled();
+        let is_throttled = should_throttle(rss, limit, was_throttled);
+        state.memory.throttled.store(is_throttled, Ordering::SeqCst);
+
+        if is_throttled {
+            if !was_throttled {
+                log::warn!(
+                    "Memory usage ({} MB) over limit ({} MB), pausing crawls",
+                    rss / 1_000_000,
+                    limit / 1_000_000
+                );
+            }
+
+            // Flush the writer to free up its buffers.
+            if state.index.num_pending() > 0 {
+                if let Err(err) = state.index.commit() {
+                    log::error!("Unable to flush index writer: {}", err);
+                }
+            }
+        } else if was_throttled {
+            log::info!(
+                "Memory usage back to {} MB, resuming crawls",
+                rss / 1_000_000
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{inflight_body_bytes, should_throttle, InflightBytes};
+
+    #[test]
+    fn test_should_throttle() {
+        assert!(!should_throttle(50, 100, false));
+        assert!(should_throttle(100, 100, false));
+        // Stay throttled until we're comfortably under the limit.
+        assert!(should_throttle(95, 100, true));
+        assert!(!should_throttle(80, 100, true));
+    }
+
+    #[test]
+    fn test_inflight_bytes() {
+        // Other tests may be crawling at the same time, so only check that
+        // our bytes are accounted for.
+        let mut inflight = InflightBytes::default();
+        inflight.add(1024);
+        assert!(inflight_body_bytes() >= 1024);
+    }
+}
@@ -0,0 +1,488 @@
+//! Crawl tasks flow through three stages, connected by bounded channels &
+//! each w/ its own pool of workers:
+//! * fetch: network bound, downloads the page.
+//! * parse: CPU bound, extracts content & links from the page.
+//! * index: adds the page to the index & enqueues any new links.
+//!
+//! A slow stage fills up its queue & applies backpressure to the stage before
+//! it, rather than stalling every worker.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{mpsc, Mutex};
+use url::Url;
+
+use entities::models::domain_stats::{self, FetchOutcome};
+use entities::models::{crawl_log, crawl_queue, indexed_document, link};
+use entities::sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use shared::config::Lens;
+use shared::response::PipelineStatus;
+use spyglass_plugin::PluginEventPayload;
+
+use crate::crawler::{record_fetch, CrawlResult, Crawler, FetchedJob, NO_RESPONSE};
+use crate::plugin;
+use crate::state::AppState;
+use crate::task::{add_to_index, links_to_follow, needs_render, remove_from_index, Command};
+
+/// Number of fetch workers to spin up per CPU.
+const FETCH_WORKERS_PER_CPU: usize = 4;
+/// Writes to the index are serialized anyways, no need for many workers.
+const INDEX_WORKERS: usize = 2;
+/// Max number of jobs waiting in between stages.
+const STAGE_QUEUE_SIZE: usize = 64;
+
+type SharedReceiver<T> = Arc<Mutex<mpsc::Receiver<T>>>;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Stage {
+    Fetch,
+    Parse,
+    Index,
+}
+
+/// Number of jobs waiting on each stage.
+#[derive(Debug, Default)]
+pub struct PipelineStats {
+    fetch: AtomicUsize,
+    parse: AtomicUsize,
+    index: AtomicUsize,
+}
+
+impl PipelineStats {
+    fn counter(&self, stage: Stage) -> &AtomicUsize {
+        match stage {
+            Stage::Fetch => &self.fetch,
+            Stage::Parse => &self.parse,
+            Stage::Index => &self.index,
+        }
+    }
+
+    /// A job was sent to `stage`.
+    pub fn queued(&self, stage: Stage) {
+        self.counter(stage).fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// A job was picked up by a `stage` worker.
+    pub fn dequeued(&self, stage: Stage) {
+        // Saturate at 0 in case a job was sent w/o being counted.
+        let _ = self
+            .counter(stage)
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |num| {
+                Some(num.saturating_sub(1))
+            });
+    }
+
+    pub fn status(&self) -> PipelineStatus {
+        PipelineStatus {
+            fetch_queue: self.fetch.load(Ordering::SeqCst) as u64,
+            parse_queue: self.parse.load(Ordering::SeqCst) as u64,
+            index_queue: self.index.load(Ordering::SeqCst) as u64,
+        }
+    }
+}
+
+/// Add a fetch to the crawl stats of `domain`.
+pub async fn record_domain_stats(
+    state: &AppState,
+    domain: &str,
+    outcome: FetchOutcome,
+    num_bytes: u64,
+) {
+    if let Err(err) = domain_stats::record(&state.db, domain, outcome, num_bytes).await {
+        log::error!("Unable to update stats for {}: {}", domain, err);
+    }
+}
+
+/// Reason logged for tasks `Crawler::fetch_job` skips.
+pub const SKIPPED_REASON: &str = "Skipped, not allowed by robots.txt or fetched recently";
+
+/// Add an attempt at crawling `task` to the crawl log. Fetches that never got
+/// a response are logged w/o a status.
+pub async fn record_attempt(
+    state: &AppState,
+    task: &crawl_queue::Model,
+    status: Option<u16>,
+    duration: Duration,
+    num_bytes: u64,
+    error: Option<String>,
+) {
+    let (status, error) = match status {
+        Some(NO_RESPONSE) => (None, error.or_else(|| Some("No response".to_string()))),
+        status => (status, error),
+    };
+
+    let attempt = crawl_log::Attempt {
+        domain: task.domain.clone(),
+        url: task.url.clone(),
+        status,
+        duration_ms: duration.as_millis() as u64,
+        num_bytes,
+        error,
+    };
+    if let Err(err) = crawl_log::record(&state.db, attempt).await {
+        log::error!("Unable to log crawl of <{}>: {}", task.url, err);
+    }
+}
+
+pub struct ParsedJob {
+    pub task: crawl_queue::Model,
+    pub fetch_url: Url,
+    pub result: CrawlResult,
+}
+
+async fn fetch_worker(
+    state: AppState,
+    crawler: Crawler,
+    queue: SharedReceiver<Command>,
+    parse_tx: mpsc::Sender<FetchedJob>,
+    done_tx: mpsc::Sender<String>,
+) {
+    loop {
+        let next_cmd = queue.lock().await.recv().await;
+        let task = match next_cmd {
+            Some(Command::Fetch(task)) => task,
+            None => break,
+        };
+        state.pipeline.dequeued(Stage::Fetch);
+
+        let started = Instant::now();
+        let fetched = crawler.fetch_job(&state.db, task.id).await;
+        let duration = started.elapsed();
+        match fetched {
+            Ok(Some(mut job)) => {
+                let num_bytes = job.page.body.as_ref().map_or(0, |body| body.len());
+                let outcome = FetchOutcome::Status(job.page.status);
+                record_domain_stats(&state, &task.domain, outcome, num_bytes as u64).await;
+                let status = Some(job.page.status);
+                record_attempt(&state, &job.task, status, duration, num_bytes as u64, None).await;
+
+                if state.favicons.is_stale(&task.domain) {
+                    let favicons = state.favicons.clone();
+                    let client = crawler.client.clone();
+                    let domain = task.domain.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = favicons.fetch(&client, &domain).await {
+                            log::warn!("Unable to save favicon for {}: {}", domain, err);
+                        }
+                    });
+                }
+
+                if !job.page.archived && needs_render(&state, &job.page.url) {
+                    if let Err(err) = crawler.render(&mut job.page).await {
+                        log::warn!("Unable to render <{}>: {}", job.page.url, err);
+                    }
+                }
+
+                state.pipeline.queued(Stage::Parse);
+                if parse_tx.send(job).await.is_err() {
+                    break;
+                }
+            }
+            Ok(None) => {
+                // Failed to grab robots.txt or crawling is not allowed
+                if let Ok(Some(task)) = crawl_queue::Entity::find_by_id(task.id)
+                    .one(&state.db)
+                    .await
+                {
+                    let reason = Some(SKIPPED_REASON.to_string());
+                    record_attempt(&state, &task, None, duration, 0, reason).await;
+                }
+
+                if let Err(e) =
+                    crawl_queue::mark_done(&state.db, task.id, crawl_queue::CrawlStatus::Completed)
+                        .await
+                {
+                    log::error!("Unable to mark task as finished: {}", e);
+                }
+            }
+            Err(err) => {
+                log::error!("Unable to crawl id: {} - {:?}", task.id, err);
+                record_domain_stats(&state, &task.domain, FetchOutcome::NetworkError, 0).await;
+                if let Ok(Some(task)) = crawl_queue::Entity::find_by_id(task.id)
+                    .one(&state.db)
+                    .await
+                {
+                    let reason = Some(err.to_string());
+                    record_attempt(&state, &task, None, duration, 0, reason).await;
+                }
+                // mark crawl as failed
+                mark_failed(&state, task.id, &err.to_string()).await;
+            }
+        }
+
+        // Network activity for this domain is done, let the manager know.
+        let _ = done_tx.send(task.domain).await;
+    }
+}
+
+/// Mark the crawl as failed & let plugins know once it won't be retried.
+pub(crate) async fn mark_failed(state: &AppState, task_id: i64, error: &str) {
+    match crawl_queue::mark_failed(&state.db, task_id, state.user_settings.max_crawl_retries).await
+    {
+        Ok(Some(task)) => {
+            let event = PluginEventPayload::CrawlFailed {
+                url: task.url,
+                error: error.to_string(),
+            };
+            plugin::dispatch_event(state, event).await;
+        }
+        Ok(None) => {}
+        Err(e) => log::error!("Unable to mark task as failed: {}", e),
+    }
+}
+
+async fn parse_worker(
+    state: AppState,
+    crawler: Crawler,
+    queue: SharedReceiver<FetchedJob>,
+    index_tx: mpsc::Sender<ParsedJob>,
+) {
+    loop {
+        let next_job = queue.lock().await.recv().await;
+        let job = match next_job {
+            Some(job) => job,
+            None => break,
+        };
+        state.pipeline.dequeued(Stage::Parse);
+
+        let task = job.task.clone();
+        let fetch_url = job.page.url.clone();
+        let crawler = crawler.clone();
+        let result = match tokio::task::spawn_blocking(move || crawler.parse_job(job)).await {
+            Ok(result) => result,
+            Err(err) => {
+                log::error!("Unable to parse <{}>: {}", fetch_url, err);
+                mark_failed(&state, task.id, &err.to_string()).await;
+                continue;
+            }
+        };
+
+        state.pipeline.queued(Stage::Index);
+        let parsed = ParsedJob {
+            task,
+            fetch_url,
+            result,
+        };
+
+        if index_tx.send(parsed).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Index a parsed page & queue up any links found in it.
+#[tracing::instrument(skip(state, job), fields(url = %job.fetch_url))]
+pub async fn handle_parsed(state: &AppState, job: ParsedJob) {
+    let ParsedJob {
+        task,
+        fetch_url,
+        result: crawl_result,
+    } = job;
+
+    if let Err(err) = record_fetch(&state.db, &fetch_url, &crawl_result).await {
+        log::error!("Unable to update fetch history: {}", err);
+    }
+
+    // Update job status
+    // We consider 400s complete in this case since we manage to hit the server
+    // successfully but nothing useful was returned. Unchanged pages are left
+    // as-is in the index.
+    if crawl_result.is_success() || crawl_result.is_not_modified() || crawl_result.is_bad_request()
+    {
+        let _ =
+            crawl_queue::mark_done(&state.db, task.id, crawl_queue::CrawlStatus::Completed).await;
+    } else {
+        let error = format!("HTTP {}", crawl_result.status);
+        mark_failed(state, task.id, &error).await;
+    }
+
+    // Unchanged pages (304s) don't come w/ their links, keep the old ones.
+    let save_links = crawl_result.is_success();
+    let links: Vec<String> = crawl_result.links.into_iter().collect();
+    if save_links {
+        if let Err(err) = link::replace_outlinks(&state.db, &crawl_result.url, &links).await {
+            log::error!("Unable to save links for <{}>: {}", crawl_result.url, err);
+        }
+    }
+
+    // Add all valid, non-duplicate, non-indexed links found to crawl queue,
+    // unless we're already as far from the seed URL as we're allowed to go.
+    if let Some(link_settings) = task.link_settings() {
+        let to_enqueue = match Url::parse(&crawl_result.url) {
+            Ok(page_url) => links_to_follow(state, &page_url, links),
+            Err(_) => links,
+        };
+
+        let lenses: Vec<Lens> = state
+            .lenses
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+
+        if let Err(err) = crawl_queue::enqueue_all(
+            &state.db,
+            &to_enqueue,
+            &lenses,
+            &state.user_settings,
+            &crawl_queue::EnqueueSettings {
+                seen_urls: Some(state.seen_urls.clone()),
+                ..link_settings
+            },
+        )
+        .await
+        {
+            log::error!("error enqueuing all: {}", err);
+        }
+    }
+
+    // Drop pages that were indexed before they asked not to be.
+    if crawl_result.no_index {
+        let doc = indexed_document::Entity::find()
+            .filter(indexed_document::Column::Url.eq(crawl_result.url.as_str()))
+            .one(&state.db)
+            .await;
+        if let Ok(Some(doc)) = doc {
+            log::info!("Removing <{}> from the index, marked as noindex", doc.url);
+            if let Err(err) = remove_from_index(state, doc).await {
+                log::error!("Unable to remove <{}>: {}", crawl_result.url, err);
+            }
+        }
+    }
+
+    // Add / update search index w/ crawl result.
+    if let Some(content) = crawl_result.content {
+        let url = match Url::parse(&crawl_result.url) {
+            Ok(url) => url,
+            Err(err) => {
+                log::error!("Invalid crawl URL <{}>: {}", crawl_result.url, err);
+                return;
+            }
+        };
+
+        if let Err(err) = add_to_index(
+            state,
+            &url,
+            &crawl_result.title.unwrap_or_default(),
+            &crawl_result.description.unwrap_or_default(),
+            &content,
+            &crawl_result.raw.unwrap_or_default(),
+            crawl_result.lang.as_deref(),
+        )
+        .await
+        {
+            log::error!("Unable to index <{}>: {}", url, err);
+            return;
+        }
+
+        if let Err(err) =
+            indexed_document::set_archived(&state.db, url.as_str(), crawl_result.archived).await
+        {
+            log::error!("Unable to update archived status for <{}>: {}", url, err);
+        }
+
+        if let Some(author) = &crawl_result.author {
+            if let Err(err) = indexed_document::set_author(&state.db, url.as_str(), author).await {
+                log::error!("Unable to update author for <{}>: {}", url, err);
+            }
+        }
+
+        if let Err(err) =
+            indexed_document::set_metadata(&state.db, url.as_str(), &crawl_result.metadata).await
+        {
+            log::error!("Unable to update metadata for <{}>: {}", url, err);
+        }
+    }
+}
+
+async fn index_worker(state: AppState, queue: SharedReceiver<ParsedJob>) {
+    loop {
+        let next_job = queue.lock().await.recv().await;
+        match next_job {
+            Some(job) => {
+                state.pipeline.dequeued(Stage::Index);
+                handle_parsed(&state, job).await;
+            }
+            None => break,
+        }
+    }
+}
+
+/// Spins up the workers for each stage. Stages shut down in order once the
+/// manager hangs up, so anything already fetched still makes it into the
+/// index.
+pub async fn run(state: AppState, queue: mpsc::Receiver<Command>, done_tx: mpsc::Sender<String>) {
+    let num_cpus = std::thread::available_parallelism()
+        .map(|num| num.get())
+        .unwrap_or(1);
+    let num_fetchers = (num_cpus * FETCH_WORKERS_PER_CPU)
+        .min(state.user_settings.inflight_crawl_limit.value() as usize)
+        .max(1);
+    log::info!(
+        "starting crawl pipeline w/ {} fetch, {} parse, {} index workers",
+        num_fetchers,
+        num_cpus,
+        INDEX_WORKERS
+    );
+
+    let crawler = Crawler::with_settings(&state.user_settings);
+    let (parse_tx, parse_rx) = mpsc::channel(STAGE_QUEUE_SIZE);
+    let (index_tx, index_rx) = mpsc::channel(STAGE_QUEUE_SIZE);
+
+    let queue = Arc::new(Mutex::new(queue));
+    let parse_rx = Arc::new(Mutex::new(parse_rx));
+    let index_rx = Arc::new(Mutex::new(index_rx));
+
+    let mut handles = Vec::new();
+    for _ in 0..num_fetchers {
+        handles.push(tokio::spawn(fetch_worker(
+            state.clone(),
+            crawler.clone(),
+            queue.clone(),
+            parse_tx.clone(),
+            done_tx.clone(),
+        )));
+    }
+
+    for _ in 0..num_cpus {
+        handles.push(tokio::spawn(parse_worker(
+            state.clone(),
+            crawler.clone(),
+            parse_rx.clone(),
+            index_tx.clone(),
+        )));
+    }
+
+    for _ in 0..INDEX_WORKERS {
+        handles.push(tokio::spawn(index_worker(state.clone(), index_rx.clone())));
+    }
+
+    // Only the workers should hold on to the senders, otherwise the later
+    // stages never see the earlier ones hang up.
+    drop(parse_tx);
+    drop(index_tx);
+
+    futures::future::join_all(handles).await;
+    log::info!("🛑 Shutting down crawl pipeline");
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PipelineStats, Stage};
+
+    #[test]
+    fn test_pipeline_stats() {
+        let stats = PipelineStats::default();
+        stats.queued(Stage::Fetch);
+        stats.queued(Stage::Fetch);
+        stats.queued(Stage::Index);
+        stats.dequeued(Stage::Fetch);
+        // Never goes below zero
+        stats.dequeued(Stage::Parse);
+
+        let status = stats.status();
+        assert_eq!(status.fetch_queue, 1);
+        assert_eq!(status.parse_queue, 0);
+        assert_eq!(status.index_queue, 1);
+    }
+}
@@ -1,15 +1,35 @@
-use rusqlite::Connection;
-use std::path::Path;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use std::path::{Component, Path, PathBuf};
 use tokio::sync::mpsc::Sender;
+use url::Url;
 use wasmer::{Exports, Function, Store};
 use wasmer_wasi::WasiEnv;
 
 use super::{
     wasi_read, wasi_read_string, wasi_write, PluginCommand, PluginConfig, PluginEnv, PluginId,
 };
+use crate::fetch::HTTPClient;
 use crate::state::AppState;
-use entities::models::crawl_queue::enqueue_all;
-use spyglass_plugin::{PluginCommandRequest, PluginEnqueueRequest, PluginMountRequest};
+use crate::task;
+use entities::models::crawl_queue::{enqueue_all, EnqueueSettings};
+use entities::models::indexed_document;
+use entities::normalize::normalize;
+use shared::response::PageMetadata;
+use spyglass_plugin::{
+    PluginAddDocumentRequest, PluginCommandRequest, PluginEnqueueRequest, PluginFetchRequest,
+    PluginFetchResponse, PluginMountRequest, PluginSqliteQueryRequest, PluginSqliteRows,
+    PluginSqliteValue,
+};
+
+/// Max size of a response body handed to a plugin.
+const MAX_FETCH_BYTES: usize = 10 * 1024 * 1024;
+/// Max rows returned by a single SQLite query.
+const MAX_SQLITE_ROWS: usize = 100_000;
+/// Max size of the content of a document added by a plugin.
+const MAX_DOCUMENT_BYTES: usize = 4 * 1024 * 1024;
 
 pub fn register_exports(
     plugin_id: PluginId,
@@ -20,15 +40,26 @@ pub fn register_exports(
     env: &WasiEnv,
 ) -> Exports {
     let mut exports = Exports::new();
+    // Redirects can't be used to get around the permissions.
+    let permissions = plugin.permissions.clone();
+    let fetch_client = HTTPClient::with_redirect_filter(&state.user_settings, move |url| {
+        permissions.can_fetch(url)
+    });
     let env = PluginEnv {
         id: plugin_id,
         name: plugin.name.clone(),
         app_state: state.clone(),
         data_dir: plugin.data_folder(),
+        permissions: plugin.permissions.clone(),
         wasi_env: env.clone(),
         cmd_writer: cmd_writer.clone(),
+        fetch_client,
     };
 
+    exports.insert(
+        "plugin_add_document",
+        Function::new_native_with_env(store, env.clone(), plugin_add_document),
+    );
     exports.insert(
         "plugin_cmd",
         Function::new_native_with_env(store, env.clone(), plugin_cmd),
@@ -37,10 +68,18 @@ pub fn register_exports(
         "plugin_enqueue",
         Function::new_native_with_env(store, env.clone(), plugin_enqueue),
     );
+    exports.insert(
+        "plugin_fetch",
+        Function::new_native_with_env(store, env.clone(), plugin_fetch),
+    );
     exports.insert(
         "plugin_log",
         Function::new_native_with_env(store, env.clone(), plugin_log),
     );
+    exports.insert(
+        "plugin_sqlite_query",
+        Function::new_native_with_env(store, env.clone(), plugin_sqlite_query),
+    );
     exports.insert(
         "plugin_sync_file",
         Function::new_native_with_env(store, env, plugin_sync_file),
@@ -48,6 +87,73 @@ pub fn register_exports(
     exports
 }
 
+/// Adds a document straight to the index, if the plugin has permission to,
+/// see `PluginPermissions::can_add_document`. Always responds w/ a
+/// `Result<String, String>` w/ the document id.
+pub(crate) fn plugin_add_document(env: &PluginEnv) {
+    let res = match wasi_read::<PluginAddDocumentRequest>(&env.wasi_env) {
+        Ok(doc) => match Url::parse(&doc.url) {
+            Ok(url) if !env.permissions.can_add_document(&env.name, &url) => {
+                Err(format!("Not allowed to add {}", doc.url))
+            }
+            Ok(_) if doc.content.len() > MAX_DOCUMENT_BYTES => {
+                Err(format!("{} is too large", doc.url))
+            }
+            Ok(_) => {
+                log::info!("<{}> adding document {}", env.name, doc.url);
+                tokio::task::block_in_place(|| {
+                    tokio::runtime::Handle::current().block_on(add_document(&env.app_state, &doc))
+                })
+                .map_err(|err| err.to_string())
+            }
+            Err(err) => Err(format!("Invalid URL {}: {}", doc.url, err)),
+        },
+        Err(err) => Err(format!("Invalid document: {}", err)),
+    };
+
+    if let Err(err) = &res {
+        log::warn!("<{}> unable to add document: {}", env.name, err);
+    }
+
+    if let Err(e) = wasi_write(&env.wasi_env, &res) {
+        log::error!("<{}> unable to send add document response: {}", env.id, e);
+    }
+}
+
+async fn add_document(state: &AppState, doc: &PluginAddDocumentRequest) -> anyhow::Result<String> {
+    let mut url = Url::parse(&doc.url)?;
+    if doc.content.trim().is_empty() {
+        return Err(anyhow::anyhow!("{} has no content", url));
+    }
+
+    let doc_id = task::add_to_index(
+        state,
+        &url,
+        &doc.title,
+        &doc.description,
+        &doc.content,
+        "",
+        doc.lang.as_deref(),
+    )
+    .await?;
+
+    // Metadata is saved under the same URL the document was indexed under.
+    normalize(&mut url);
+    if let Some(author) = &doc.author {
+        indexed_document::set_author(&state.db, url.as_str(), author).await?;
+    }
+
+    if doc.published_at.is_some() {
+        let metadata = PageMetadata {
+            published_at: doc.published_at.clone(),
+            ..Default::default()
+        };
+        indexed_document::set_metadata(&state.db, url.as_str(), &metadata).await?;
+    }
+
+    Ok(doc_id)
+}
+
 pub(crate) fn plugin_cmd(env: &PluginEnv) {
     if let Ok(cmd) = wasi_read::<PluginCommandRequest>(&env.wasi_env) {
         match cmd {
@@ -81,24 +187,211 @@ pub(crate) fn plugin_cmd(env: &PluginEnv) {
                 });
             }
             PluginCommandRequest::SqliteQuery { path, query } => {
-                let path = env.data_dir.join(path);
-                if let Ok(conn) = Connection::open(path) {
-                    let stmt = conn.prepare(&query);
-                    if let Ok(mut stmt) = stmt {
-                        let results =
-                            stmt.query_map([], |row| Ok(row.get::<usize, String>(0).unwrap()));
-
-                        if let Ok(results) = results {
-                            let collected = results.map(|x| x.unwrap()).collect::<Vec<String>>();
-                            if let Err(e) = wasi_write(&env.wasi_env, &collected) {
-                                log::error!("{}", e);
-                            }
-                        }
+                // Older version of `plugin_sqlite_query`, only returns the text
+                // in the first column.
+                let request = PluginSqliteQueryRequest {
+                    path,
+                    query,
+                    params: Vec::new(),
+                };
+                let collected = match sqlite_query(&env.data_dir, &request) {
+                    Ok(results) => results
+                        .rows
+                        .into_iter()
+                        .filter_map(|row| match row.into_iter().next() {
+                            Some(PluginSqliteValue::Text(text)) => Some(text),
+                            _ => None,
+                        })
+                        .collect::<Vec<String>>(),
+                    Err(e) => {
+                        log::warn!("<{}> unable to query {}: {}", env.name, request.path, e);
+                        Vec::new()
                     }
+                };
+
+                if let Err(e) = wasi_write(&env.wasi_env, &collected) {
+                    log::error!("{}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Read-only query against a SQLite file in the plugin data folder. Always
+/// responds w/ a `Result<PluginSqliteRows, String>`.
+pub(crate) fn plugin_sqlite_query(env: &PluginEnv) {
+    let res = match wasi_read::<PluginSqliteQueryRequest>(&env.wasi_env) {
+        Ok(request) => sqlite_query(&env.data_dir, &request).map_err(|err| err.to_string()),
+        Err(err) => Err(format!("Invalid query request: {}", err)),
+    };
+
+    if let Err(err) = &res {
+        log::warn!("<{}> unable to run query: {}", env.name, err);
+    }
+
+    if let Err(e) = wasi_write(&env.wasi_env, &res) {
+        log::error!("<{}> unable to send query results: {}", env.id, e);
+    }
+}
+
+/// Host path for a `path` the plugin sees, `None` if it's outside of the
+/// plugin data folder. Plugins see their data folder as /data.
+fn data_path(data_dir: &Path, path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    let path = path.strip_prefix("/data").unwrap_or(path);
+    if path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        Some(data_dir.join(path))
+    } else {
+        None
+    }
+}
+
+fn sqlite_query(
+    data_dir: &Path,
+    request: &PluginSqliteQueryRequest,
+) -> anyhow::Result<PluginSqliteRows> {
+    let path = data_path(data_dir, &request.path)
+        .ok_or_else(|| anyhow::anyhow!("{} is not in the plugin data folder", request.path))?;
+    if !path.is_file() {
+        return Err(anyhow::anyhow!("{} does not exist", request.path));
+    }
+
+    // Synced files are copies, immutable skips locking & the WAL, which may
+    // not have been copied over.
+    let mut uri = Url::from_file_path(&path)
+        .map_err(|_| anyhow::anyhow!("Invalid path {}", path.display()))?;
+    uri.set_query(Some("immutable=1"));
+    let conn = Connection::open_with_flags(
+        uri.as_str(),
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+    )?;
+    // Read-only connections can still ATTACH other files on the host or write
+    // them w/ VACUUM INTO, only allow plain queries.
+    conn.authorizer(Some(|ctx: AuthContext<'_>| match ctx.action {
+        AuthAction::Select
+        | AuthAction::Read { .. }
+        | AuthAction::Function { .. }
+        | AuthAction::Recursive => Authorization::Allow,
+        _ => Authorization::Deny,
+    }));
+
+    let mut stmt = conn.prepare(&request.query)?;
+    if !stmt.readonly() {
+        return Err(anyhow::anyhow!("only read-only queries are allowed"));
+    }
+    let columns: Vec<String> = stmt
+        .column_names()
+        .into_iter()
+        .map(|name| name.to_string())
+        .collect();
+
+    let mut rows = Vec::new();
+    let mut results = stmt.query(rusqlite::params_from_iter(request.params.iter()))?;
+    while let Some(row) = results.next()? {
+        if rows.len() >= MAX_SQLITE_ROWS {
+            return Err(anyhow::anyhow!(
+                "query returned more than {} rows",
+                MAX_SQLITE_ROWS
+            ));
+        }
+
+        let mut values = Vec::with_capacity(columns.len());
+        for idx in 0..columns.len() {
+            values.push(match row.get_ref(idx)? {
+                ValueRef::Null => PluginSqliteValue::Null,
+                ValueRef::Integer(value) => PluginSqliteValue::Integer(value),
+                ValueRef::Real(value) => PluginSqliteValue::Real(value),
+                ValueRef::Text(text) => {
+                    PluginSqliteValue::Text(String::from_utf8_lossy(text).to_string())
                 }
+                ValueRef::Blob(blob) => PluginSqliteValue::Blob(blob.to_vec()),
+            });
+        }
+        rows.push(values);
+    }
+
+    Ok(PluginSqliteRows { columns, rows })
+}
+
+/// HTTP GET on behalf of the plugin, limited to the hosts in the `fetch`
+/// permissions of its manifest. Always responds w/ a
+/// `Result<PluginFetchResponse, String>` so the plugin isn't left waiting.
+pub(crate) fn plugin_fetch(env: &PluginEnv) {
+    let res = match wasi_read::<PluginFetchRequest>(&env.wasi_env) {
+        Ok(request) => match Url::parse(&request.url) {
+            Ok(url) if env.permissions.can_fetch(&url) => {
+                log::info!("<{}> fetching {}", env.name, url);
+                // Host functions are sync, wait for the request w/o stalling
+                // the rest of the runtime.
+                tokio::task::block_in_place(|| {
+                    tokio::runtime::Handle::current().block_on(fetch(
+                        &env.fetch_client,
+                        &url,
+                        &request.headers,
+                    ))
+                })
             }
+            Ok(url) => Err(format!("{} is not in the plugin's fetch permissions", url)),
+            Err(err) => Err(format!("Invalid URL {}: {}", request.url, err)),
+        },
+        Err(err) => Err(format!("Invalid fetch request: {}", err)),
+    };
+
+    if let Err(err) = &res {
+        log::warn!("<{}> unable to fetch: {}", env.name, err);
+    }
+
+    if let Err(e) = wasi_write(&env.wasi_env, &res) {
+        log::error!("<{}> unable to send fetch response: {}", env.id, e);
+    }
+}
+
+async fn fetch(
+    client: &HTTPClient,
+    url: &Url,
+    headers: &[(String, String)],
+) -> Result<PluginFetchResponse, String> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|err| err.to_string())?;
+        let value = HeaderValue::from_str(value).map_err(|err| err.to_string())?;
+        header_map.insert(name, value);
+    }
+
+    let mut res = client
+        .get_with_headers(url, header_map)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let status = res.status().as_u16();
+    let headers = res
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| {
+            value
+                .to_str()
+                .ok()
+                .map(|value| (name.to_string(), value.to_string()))
+        })
+        .collect();
+
+    let mut body = Vec::new();
+    while let Some(chunk) = res.chunk().await.map_err(|err| err.to_string())? {
+        if body.len() + chunk.len() > MAX_FETCH_BYTES {
+            return Err(format!("response is larger than {} bytes", MAX_FETCH_BYTES));
         }
+        body.extend_from_slice(&chunk);
     }
+    client.consume(body.len()).await;
+
+    Ok(PluginFetchResponse {
+        status,
+        headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    })
 }
 
 pub(crate) fn plugin_log(env: &PluginEnv) {
@@ -117,11 +410,21 @@ pub(crate) fn plugin_sync_file(env: &PluginEnv) {
             mount_request.src
         );
 
+        // `dst` is the folder the file is copied into, e.g. /data/chrome/Default
+        let dst_dir = match data_path(&env.data_dir, &mount_request.dst) {
+            Some(dst_dir) => dst_dir,
+            None => {
+                log::error!("{} is not in the plugin data folder", mount_request.dst);
+                return;
+            }
+        };
+
         let src = Path::new(&mount_request.src);
         if let Some(file_name) = src.file_name() {
-            let dst = &env.data_dir.join(file_name);
+            let dst = dst_dir.join(file_name);
             // Attempt to mount directory
-            if let Err(e) = std::fs::copy(mount_request.src, &dst) {
+            if let Err(e) = std::fs::create_dir_all(&dst_dir).and_then(|_| std::fs::copy(src, &dst))
+            {
                 log::error!("Unable to copy into plugin data dir: {}", e);
             }
         } else {
@@ -143,7 +446,11 @@ pub(crate) fn plugin_enqueue(env: &PluginEnv) {
                 &request.urls,
                 &[],
                 &state.user_settings,
-                &Default::default(),
+                &EnqueueSettings {
+                    seen_urls: Some(state.seen_urls.clone()),
+                    priority: request.priority.unwrap_or_default(),
+                    ..Default::default()
+                },
             )
             .await
             {
@@ -152,3 +459,138 @@ pub(crate) fn plugin_enqueue(env: &PluginEnv) {
         });
     }
 }
+
+#[cfg(test)]
+mod test {
+    use rusqlite::Connection;
+    use spyglass_plugin::{PluginSqliteQueryRequest, PluginSqliteValue};
+
+    use entities::models::indexed_document;
+    use entities::sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+    use entities::test::setup_test_db;
+    use shared::config::UserSettings;
+    use spyglass_plugin::PluginAddDocumentRequest;
+
+    use std::path::Path;
+
+    use super::{add_document, data_path, sqlite_query};
+    use crate::state::AppState;
+
+    #[tokio::test]
+    async fn test_add_document() {
+        let db = setup_test_db().await;
+        let state = AppState::for_test(db, &UserSettings::default());
+
+        let doc = PluginAddDocumentRequest {
+            url: "https://getpocket.com/read/1234".into(),
+            title: "Saved article".into(),
+            content: "Something worth reading later".into(),
+            author: Some("Alice".into()),
+            published_at: Some("2022-08-01T12:00:00Z".into()),
+            ..Default::default()
+        };
+        let doc_id = add_document(&state, &doc).await.unwrap();
+
+        let indexed = indexed_document::Entity::find()
+            .filter(indexed_document::Column::DocId.eq(doc_id))
+            .one(&state.db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(indexed.url, "https://getpocket.com/read/1234");
+        assert_eq!(indexed.author.as_deref(), Some("Alice"));
+        assert!(indexed.published_at.is_some());
+        assert_eq!(state.index.num_pending(), 1);
+
+        // Nothing to index
+        let empty = PluginAddDocumentRequest {
+            url: "https://getpocket.com/read/5678".into(),
+            ..Default::default()
+        };
+        assert!(add_document(&state, &empty).await.is_err());
+    }
+
+    #[test]
+    fn test_data_path() {
+        let data_dir = Path::new("/plugins/chrome-importer/data");
+        assert_eq!(data_path(data_dir, "/data"), Some(data_dir.to_path_buf()));
+        assert_eq!(
+            data_path(data_dir, "/data/brave/Profile 1"),
+            Some(data_dir.join("brave/Profile 1"))
+        );
+        assert_eq!(
+            data_path(data_dir, "History"),
+            Some(data_dir.join("History"))
+        );
+        assert_eq!(data_path(data_dir, "/data/../settings.ron"), None);
+        assert_eq!(data_path(data_dir, "/etc/passwd"), None);
+    }
+
+    #[test]
+    fn test_sqlite_query() {
+        let data_dir =
+            std::env::temp_dir().join(format!("spyglass-plugin-sqlite-{}", std::process::id()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let db = Connection::open(data_dir.join("History")).unwrap();
+        db.execute_batch(
+            "CREATE TABLE urls (url TEXT, title TEXT, visit_count INTEGER);
+             INSERT INTO urls VALUES ('https://example.com', 'Example', 3);
+             INSERT INTO urls VALUES ('https://example.org', NULL, 1);",
+        )
+        .unwrap();
+        drop(db);
+
+        let query = |path: &str, query: &str, params: &[&str]| {
+            sqlite_query(
+                &data_dir,
+                &PluginSqliteQueryRequest {
+                    path: path.into(),
+                    query: query.into(),
+                    params: params.iter().map(|param| param.to_string()).collect(),
+                },
+            )
+        };
+
+        let res = query(
+            "/data/History",
+            "SELECT url, title, visit_count FROM urls WHERE visit_count > ? ORDER BY url",
+            &["0"],
+        )
+        .unwrap();
+        assert_eq!(res.columns, vec!["url", "title", "visit_count"]);
+        assert_eq!(res.rows.len(), 2);
+        assert_eq!(
+            res.rows[0],
+            vec![
+                PluginSqliteValue::Text("https://example.com".into()),
+                PluginSqliteValue::Text("Example".into()),
+                PluginSqliteValue::Integer(3),
+            ]
+        );
+        assert_eq!(res.rows[1][1], PluginSqliteValue::Null);
+
+        // Read-only
+        assert!(query("History", "DELETE FROM urls", &[]).is_err());
+        // Only files in the data folder
+        assert!(query("../History", "SELECT * FROM urls", &[]).is_err());
+        assert!(query("Missing", "SELECT 1", &[]).is_err());
+
+        // No reaching outside the data folder through SQL either
+        let outside = data_dir.with_extension("outside.db");
+        Connection::open(&outside)
+            .unwrap()
+            .execute_batch("CREATE TABLE secrets (value TEXT)")
+            .unwrap();
+        let attach = format!("ATTACH DATABASE '{}' AS other", outside.display());
+        assert!(query("History", &attach, &[]).is_err());
+        let vacuum = format!(
+            "VACUUM INTO '{}'",
+            data_dir.with_extension("copy.db").display()
+        );
+        assert!(query("History", &vacuum, &[]).is_err());
+        assert!(!data_dir.with_extension("copy.db").exists());
+
+        let _ = std::fs::remove_file(&outside);
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+}
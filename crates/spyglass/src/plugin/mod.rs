@@ -1,25 +1,34 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::io::Read;
-use std::path::PathBuf;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use dashmap::DashMap;
 use entities::sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tokio::sync::{broadcast, mpsc};
+use url::Url;
 use wasmer::{Instance, Module, Store, WasmerEnv};
 use wasmer_wasi::{Pipe, WasiEnv, WasiState};
 
 use entities::models::lens;
-use shared::config::Config;
-use spyglass_plugin::{consts::env, PluginEvent};
+use shared::config::{Config, PluginSettingSchema, PluginSettingType};
+use shared::response::StatusEvent;
+use spyglass_plugin::{consts::env, PluginEvent, PluginEventPayload};
 
+use crate::fetch::HTTPClient;
 use crate::state::AppState;
 use crate::task::AppShutdown;
 
 mod exports;
+mod watcher;
+
+pub use watcher::plugin_watcher;
+
+/// How often the plugin manager checks for plugins due for an update.
+const UPDATE_CHECK_INTERVAL_S: u64 = 60;
 
 #[derive(Clone, Deserialize, Serialize, PartialEq)]
 pub enum PluginType {
@@ -30,6 +39,45 @@ pub enum PluginType {
     Lens,
 }
 
+/// What a plugin is allowed to access outside of its data folder, from the
+/// `permissions` in its manifest.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PluginPermissions {
+    /// Hosts the plugin can make HTTP requests to, subdomains included, e.g.
+    /// `getpocket.com` or `api.github.com`.
+    #[serde(default)]
+    pub fetch: Vec<String>,
+    /// Whether the plugin can add documents straight to the index, see
+    /// `can_add_document`.
+    #[serde(default)]
+    pub add_documents: bool,
+}
+
+impl PluginPermissions {
+    pub fn can_fetch(&self, url: &Url) -> bool {
+        if !matches!(url.scheme(), "http" | "https") {
+            return false;
+        }
+
+        let host = match url.host_str() {
+            Some(host) => host.to_lowercase(),
+            None => return false,
+        };
+
+        self.fetch.iter().any(|allowed| {
+            let allowed = allowed.to_lowercase();
+            host == allowed || host.ends_with(&format!(".{}", allowed))
+        })
+    }
+
+    /// Documents added by a plugin need to use its name as the URL scheme,
+    /// e.g. `pocket-importer://item/123`, so they can't replace documents
+    /// crawled from the web or added by another plugin.
+    pub fn can_add_document(&self, plugin_name: &str, url: &Url) -> bool {
+        self.add_documents && url.scheme() == plugin_name.to_lowercase()
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 pub struct PluginConfig {
     pub name: String,
@@ -39,9 +87,21 @@ pub struct PluginConfig {
     #[serde(default)]
     pub path: Option<PathBuf>,
     pub plugin_type: PluginType,
+    /// Setting values passed to the plugin as environment variables, see
+    /// `apply_settings`.
+    #[serde(default)]
     pub user_settings: HashMap<String, String>,
+    /// Settings the plugin needs from the user, keyed by environment variable.
+    #[serde(default)]
+    pub settings: HashMap<String, PluginSettingSchema>,
     #[serde(default)]
     pub is_enabled: bool,
+    #[serde(default)]
+    pub permissions: PluginPermissions,
+    /// Minutes between calls to the plugin's `update`, e.g. to re-sync
+    /// browser history. By default `update` only runs when the plugin starts.
+    #[serde(default)]
+    pub update_interval: Option<u64>,
 }
 
 impl PluginConfig {
@@ -53,6 +113,78 @@ impl PluginConfig {
             .expect("Unable to find parent plugin directory")
             .join("data")
     }
+
+    /// Every setting the plugin takes. Plain `user_settings` entries are
+    /// treated as optional text settings.
+    pub fn setting_schemas(&self) -> BTreeMap<String, PluginSettingSchema> {
+        let mut schemas: BTreeMap<String, PluginSettingSchema> = self
+            .settings
+            .iter()
+            .map(|(key, schema)| (key.clone(), schema.clone()))
+            .collect();
+
+        for (key, default) in &self.user_settings {
+            schemas
+                .entry(key.clone())
+                .or_insert_with(|| PluginSettingSchema {
+                    label: key.clone(),
+                    description: String::new(),
+                    form_type: PluginSettingType::Text,
+                    default: Some(default.clone()),
+                    required: false,
+                });
+        }
+
+        schemas
+    }
+
+    /// Override the defaults from the manifest w/ the values the user set,
+    /// ignoring any the plugin doesn't take. Only meant for a freshly loaded
+    /// manifest.
+    pub fn apply_settings(&mut self, values: &HashMap<String, String>) {
+        for (key, schema) in self.setting_schemas() {
+            let value = values
+                .get(&key)
+                .filter(|value| !value.is_empty())
+                .or(schema.default.as_ref())
+                .cloned();
+
+            match value {
+                Some(value) => self.user_settings.insert(key, value),
+                None => self.user_settings.remove(&key),
+            };
+        }
+    }
+
+    /// Required settings w/o a value.
+    pub fn missing_settings(&self) -> Vec<String> {
+        let mut missing: Vec<String> = self
+            .settings
+            .iter()
+            .filter(|(key, schema)| {
+                schema.required
+                    && self
+                        .user_settings
+                        .get(*key)
+                        .map(|value| value.is_empty())
+                        .unwrap_or(true)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        missing.sort();
+        missing
+    }
+
+    /// Whether `update` should be called again, based on `update_interval`.
+    pub fn is_update_due(&self, last_update: Option<Instant>, now: Instant) -> bool {
+        match (self.update_interval.filter(|mins| *mins > 0), last_update) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(mins), Some(last_update)) => {
+                now.duration_since(last_update) >= Duration::from_secs(mins * 60)
+            }
+        }
+    }
 }
 
 type PluginId = usize;
@@ -64,6 +196,12 @@ pub enum PluginCommand {
     Initialize(PluginConfig),
     // Request queued items from plugin
     RequestQueue(PluginId),
+    /// Request queued items from plugins whose `update_interval` has passed
+    ScheduledUpdates,
+    /// Reload a plugin's manifest, WASM module & saved settings
+    Reload(String),
+    /// Send an event to the plugins subscribed to it
+    DispatchEvent(PluginEventPayload),
     Subscribe(PluginId, PluginEvent),
 }
 
@@ -78,10 +216,14 @@ pub(crate) struct PluginEnv {
     app_state: AppState,
     /// Where the plugin stores data
     data_dir: PathBuf,
+    /// Granted in the plugin manifest
+    permissions: PluginPermissions,
     /// wasi connection for communications
     wasi_env: WasiEnv,
     /// host specific requests
     cmd_writer: mpsc::Sender<PluginCommand>,
+    /// Used for `plugin_fetch`, only follows redirects allowed by `permissions`
+    fetch_client: HTTPClient,
 }
 
 #[derive(Clone)]
@@ -89,11 +231,28 @@ struct PluginInstance {
     id: PluginId,
     config: PluginConfig,
     instance: Instance,
+    /// Used to pass event payloads to the plugin
+    wasi_env: WasiEnv,
+}
+
+impl PluginInstance {
+    /// Pass `event` to the plugin's `on_event`. Plugins built before events
+    /// were added don't export it, so nothing is written for them.
+    fn send_event(&self, event: &PluginEventPayload) -> anyhow::Result<()> {
+        let func = self.instance.exports.get_function("on_event")?;
+        wasi_write(&self.wasi_env, event)?;
+        func.call(&[])?;
+        Ok(())
+    }
 }
 
 #[derive(Default)]
 struct PluginManager {
     check_update_subs: HashSet<PluginId>,
+    /// Plugins subscribed to each lifecycle event.
+    event_subs: HashMap<PluginEvent, HashSet<PluginId>>,
+    /// When `update` was last called for each plugin.
+    last_update: HashMap<PluginId, Instant>,
     plugins: DashMap<PluginId, PluginInstance>,
 }
 
@@ -107,6 +266,50 @@ impl PluginManager {
 
         None
     }
+
+    /// Drop all of the plugin's subscriptions.
+    pub fn unsubscribe(&mut self, plugin_id: PluginId) {
+        self.check_update_subs.remove(&plugin_id);
+        for subs in self.event_subs.values_mut() {
+            subs.remove(&plugin_id);
+        }
+    }
+
+    /// Call the plugin's `update`, e.g. to queue up new items to crawl.
+    fn request_update(&mut self, state: &AppState, plugin_id: PluginId) {
+        let plugin = match self.plugins.get(&plugin_id) {
+            Some(plugin) => plugin,
+            None => {
+                log::error!("Unable to find plugin id: {}", plugin_id);
+                return;
+            }
+        };
+
+        if !plugin.config.is_enabled {
+            return;
+        }
+
+        self.last_update.insert(plugin_id, Instant::now());
+        if let Ok(func) = plugin.instance.exports.get_function("update") {
+            if let Err(e) = func.call(&[]) {
+                log::error!("update failed: {}", e);
+                state.publish(StatusEvent::Plugin {
+                    name: plugin.config.name.clone(),
+                    is_enabled: true,
+                    error: Some(e.to_string()),
+                });
+            }
+        }
+    }
+
+    /// Total size of the linear memory of all plugin instances, in bytes.
+    pub fn memory_usage(&self) -> u64 {
+        self.plugins
+            .iter()
+            .filter_map(|plugin| plugin.instance.exports.get_memory("memory").ok())
+            .map(|memory| memory.data_size())
+            .sum()
+    }
 }
 
 /// Manages plugin events
@@ -118,21 +321,21 @@ pub async fn plugin_manager(
     mut cmd_queue: mpsc::Receiver<PluginCommand>,
     mut shutdown_rx: broadcast::Receiver<AppShutdown>,
 ) {
-    let mut config = config.clone();
     log::info!("plugin manager started");
     let mut manager = PluginManager::default();
 
     // Initial load, send some basic configuration to the plugins
-    plugin_load(&state, &mut config, &cmd_writer).await;
-
-    // Subscribe plugins check for updates every hour
-    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+    plugin_load(&state, &config, &cmd_writer).await;
 
+    // Subscribed plugins are checked for updates by the scheduler, see
+    // `scheduler::Job::PluginUpdates`. Plugins w/ an `update_interval` are
+    // checked here.
+    let mut update_check = tokio::time::interval(Duration::from_secs(UPDATE_CHECK_INTERVAL_S));
     loop {
         // Wait for next command / handle shutdown responses
         let next_cmd = tokio::select! {
             res = cmd_queue.recv() => res,
-            _ = interval.tick() => Some(PluginCommand::CheckForUpdate),
+            _ = update_check.tick() => Some(PluginCommand::ScheduledUpdates),
             _ = shutdown_rx.recv() => {
                 log::info!("🛑 Shutting down worker");
                 return;
@@ -140,12 +343,12 @@ pub async fn plugin_manager(
         };
 
         match next_cmd {
-            // Queue update checks for subscribed plugins
+            // Run update checks for subscribed plugins. Called directly, since
+            // sending to our own queue can block once it's full.
             Some(PluginCommand::CheckForUpdate) => {
-                for plugin_id in &manager.check_update_subs {
-                    let _ = cmd_writer
-                        .send(PluginCommand::RequestQueue(*plugin_id))
-                        .await;
+                let subs: Vec<PluginId> = manager.check_update_subs.iter().copied().collect();
+                for plugin_id in subs {
+                    manager.request_update(&state, plugin_id);
                 }
             }
             Some(PluginCommand::DisablePlugin(plugin_name)) => {
@@ -153,8 +356,13 @@ pub async fn plugin_manager(
                 if let Some(plugin) = manager.find_by_name(plugin_name) {
                     if let Some(mut instance) = manager.plugins.get_mut(&plugin.id) {
                         instance.config.is_enabled = false;
-                        manager.check_update_subs.remove(&plugin.id);
+                        state.publish(StatusEvent::Plugin {
+                            name: plugin.config.name.clone(),
+                            is_enabled: false,
+                            error: None,
+                        });
                     }
+                    manager.unsubscribe(plugin.id);
                 }
             }
             Some(PluginCommand::EnablePlugin(plugin_name)) => {
@@ -162,6 +370,11 @@ pub async fn plugin_manager(
                 if let Some(plugin) = manager.find_by_name(plugin_name) {
                     if let Some(mut instance) = manager.plugins.get_mut(&plugin.id) {
                         instance.config.is_enabled = true;
+                        state.publish(StatusEvent::Plugin {
+                            name: plugin.config.name.clone(),
+                            is_enabled: true,
+                            error: None,
+                        });
                         // Re-initialize plugin
                         let _ = cmd_writer
                             .send(PluginCommand::Initialize(instance.config.clone()))
@@ -172,13 +385,14 @@ pub async fn plugin_manager(
             Some(PluginCommand::Initialize(plugin)) => {
                 let plugin_id = manager.plugins.len();
                 match plugin_init(plugin_id, &state, &cmd_writer, &plugin).await {
-                    Ok(instance) => {
+                    Ok((instance, wasi_env)) => {
                         manager.plugins.insert(
                             plugin_id,
                             PluginInstance {
                                 id: plugin_id,
                                 config: plugin.clone(),
-                                instance: instance.clone(),
+                                instance,
+                                wasi_env,
                             },
                         );
 
@@ -188,39 +402,138 @@ pub async fn plugin_manager(
                                 .await;
                         }
                     }
-                    Err(e) => log::error!("Unable to init plugin <{}>: {}", plugin.name, e),
+                    Err(e) => {
+                        log::error!("Unable to init plugin <{}>: {}", plugin.name, e);
+                        state.publish(StatusEvent::Plugin {
+                            name: plugin.name.clone(),
+                            is_enabled: plugin.is_enabled,
+                            error: Some(e.to_string()),
+                        });
+                    }
                 }
             }
             Some(PluginCommand::RequestQueue(plugin_id)) => {
-                if let Some(plugin) = manager.plugins.get(&plugin_id) {
-                    if plugin.config.is_enabled {
-                        if let Ok(func) = plugin.instance.exports.get_function("update") {
-                            if let Err(e) = func.call(&[]) {
-                                log::error!("update failed: {}", e);
-                            }
+                manager.request_update(&state, plugin_id);
+            }
+            Some(PluginCommand::ScheduledUpdates) => {
+                let now = Instant::now();
+                let due: Vec<PluginId> = manager
+                    .plugins
+                    .iter()
+                    .filter(|plugin| {
+                        plugin.config.is_enabled
+                            && plugin
+                                .config
+                                .is_update_due(manager.last_update.get(&plugin.id).copied(), now)
+                    })
+                    .map(|plugin| plugin.id)
+                    .collect();
+
+                for plugin_id in due {
+                    manager.request_update(&state, plugin_id);
+                }
+            }
+            Some(PluginCommand::Reload(plugin_name)) => {
+                log::info!("reloading plugin <{}>", plugin_name);
+                let mut plugin = match find_plugin(&config, &plugin_name) {
+                    Some(plugin) => plugin,
+                    None => {
+                        log::error!("Unable to find plugin <{}>", plugin_name);
+                        continue;
+                    }
+                };
+
+                match Config::load_settings_for_plugin(&plugin_name) {
+                    Ok(values) => plugin.apply_settings(&values),
+                    Err(e) => log::error!("Unable to load settings for <{}>: {}", plugin_name, e),
+                }
+                plugin.is_enabled = is_enabled(&state, &plugin_name).await;
+
+                // Plugins only read their settings on startup. Reuse the id of
+                // the running instance, if any, the new one subscribes again
+                // when it starts.
+                let plugin_id = match manager.find_by_name(plugin_name) {
+                    Some(existing) => existing.id,
+                    None => manager.plugins.len(),
+                };
+                match plugin_init(plugin_id, &state, &cmd_writer, &plugin).await {
+                    Ok((instance, wasi_env)) => {
+                        // Only drop the old subscriptions once there's a new
+                        // instance, a broken build keeps the old one running.
+                        manager.unsubscribe(plugin_id);
+                        manager.last_update.remove(&plugin_id);
+                        manager.plugins.insert(
+                            plugin_id,
+                            PluginInstance {
+                                id: plugin_id,
+                                config: plugin.clone(),
+                                instance,
+                                wasi_env,
+                            },
+                        );
+                        state.publish(StatusEvent::Plugin {
+                            name: plugin.name.clone(),
+                            is_enabled: plugin.is_enabled,
+                            error: None,
+                        });
+
+                        if plugin.is_enabled {
+                            let _ = cmd_writer
+                                .send(PluginCommand::RequestQueue(plugin_id))
+                                .await;
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("Unable to init plugin <{}>: {}", plugin.name, e);
+                        state.publish(StatusEvent::Plugin {
+                            name: plugin.name.clone(),
+                            is_enabled: plugin.is_enabled,
+                            error: Some(e.to_string()),
+                        });
+                    }
+                }
+            }
+            Some(PluginCommand::DispatchEvent(event)) => {
+                let subs = manager
+                    .event_subs
+                    .get(&event.event())
+                    .cloned()
+                    .unwrap_or_default();
+                for plugin_id in subs {
+                    if let Some(plugin) = manager.plugins.get(&plugin_id) {
+                        if !plugin.config.is_enabled {
+                            continue;
+                        }
+
+                        if let Err(e) = plugin.send_event(&event) {
+                            log::error!("<{}> unable to handle event: {}", plugin.config.name, e);
                         }
                     }
-                } else {
-                    log::error!("Unable to find plugin id: {}", plugin_id);
                 }
             }
             Some(PluginCommand::Subscribe(plugin_id, event)) => match event {
                 PluginEvent::CheckUpdateInterval => {
                     manager.check_update_subs.insert(plugin_id);
                 }
+                event => {
+                    manager
+                        .event_subs
+                        .entry(event)
+                        .or_default()
+                        .insert(plugin_id);
+                }
             },
             // Nothing to do
             _ => tokio::time::sleep(tokio::time::Duration::from_secs(1)).await,
         }
+
+        // Plugins may have grown their memory while handling the command.
+        state.memory.set_plugin_bytes(manager.memory_usage());
     }
 }
 
 // Loop through plugins found in the plugins directory, enabling
-pub async fn plugin_load(
-    state: &AppState,
-    config: &mut Config,
-    cmds: &mpsc::Sender<PluginCommand>,
-) {
+pub async fn plugin_load(state: &AppState, config: &Config, cmds: &mpsc::Sender<PluginCommand>) {
     log::info!("🔌 loading plugins");
 
     let plugins_dir = config.plugins_dir();
@@ -242,23 +555,10 @@ pub async fn plugin_load(
                     Ok(plug) => {
                         let mut plug = plug.clone();
                         plug.path = Some(path.join("main.wasm"));
-                        // If any user settings are found, override default ones
-                        // from plugin config file.
-                        let user_settings = config
-                            .plugin_settings
-                            .entry(plug.name.clone())
-                            .or_insert_with(HashMap::new);
-
-                        // Loop through plugin settings and use any user overrides found.
-                        for (key, value) in plug.user_settings.iter_mut() {
-                            let user_override = user_settings
-                                .entry(key.to_string())
-                                .or_insert_with(|| value.to_string());
-                            *value = user_override.to_string();
+                        // Values the user set override the defaults from the manifest.
+                        if let Some(values) = config.plugin_settings.get(&plug.name) {
+                            plug.apply_settings(values);
                         }
-                        // Update the user settings file in case any new setting entries
-                        // were added.
-                        let _ = config.save_plugin_settings(&config.plugin_settings);
 
                         // Enable plugins that are lenses, this is the only type right so technically they
                         // all will be enabled as a lens.
@@ -280,15 +580,7 @@ pub async fn plugin_load(
                             }
                         }
 
-                        // Is this plugin enabled?
-                        let lens_config = lens::Entity::find()
-                            .filter(lens::Column::Name.eq(plug.name.clone()))
-                            .one(&state.db)
-                            .await;
-
-                        if let Ok(Some(lens_config)) = lens_config {
-                            plug.is_enabled = lens_config.is_enabled;
-                        }
+                        plug.is_enabled = is_enabled(state, &plug.name).await;
 
                         if cmds
                             .send(PluginCommand::Initialize(plug.clone()))
@@ -306,12 +598,51 @@ pub async fn plugin_load(
     }
 }
 
+/// Let plugins subscribed to the event know about it. Sent from its own task
+/// so callers, including plugins adding documents, never wait on the plugin
+/// manager.
+pub async fn dispatch_event(state: &AppState, event: PluginEventPayload) {
+    if let Some(cmd_tx) = state.plugin_cmd_tx.lock().await.clone() {
+        tokio::spawn(async move {
+            let _ = cmd_tx.send(PluginCommand::DispatchEvent(event)).await;
+        });
+    }
+}
+
+/// Whether the user has the plugin enabled.
+async fn is_enabled(state: &AppState, name: &str) -> bool {
+    let lens_config = lens::Entity::find()
+        .filter(lens::Column::Name.eq(name))
+        .one(&state.db)
+        .await;
+
+    matches!(lens_config, Ok(Some(lens_config)) if lens_config.is_enabled)
+}
+
+/// Load the manifest of the plugin in `dir`.
+pub fn read_manifest(dir: &Path) -> anyhow::Result<PluginConfig> {
+    let contents = fs::read_to_string(dir.join("manifest.ron"))?;
+    let mut plugin = ron::from_str::<PluginConfig>(&contents)?;
+    plugin.path = Some(dir.join("main.wasm"));
+    Ok(plugin)
+}
+
+/// Manifest of an installed plugin, w/o the user's settings applied.
+pub fn find_plugin(config: &Config, name: &str) -> Option<PluginConfig> {
+    fs::read_dir(config.plugins_dir())
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| read_manifest(&entry.path()).ok())
+        .find(|plugin| plugin.name == name)
+}
+
 pub async fn plugin_init(
     plugin_id: PluginId,
     state: &AppState,
     cmd_writer: &mpsc::Sender<PluginCommand>,
     plugin: &PluginConfig,
-) -> anyhow::Result<Instance> {
+) -> anyhow::Result<(Instance, WasiEnv)> {
     if plugin.path.is_none() {
         // Nothing to do if theres no WASM file to load.
         return Err(anyhow::Error::msg(format!(
@@ -320,6 +651,14 @@ pub async fn plugin_init(
         )));
     }
 
+    let missing = plugin.missing_settings();
+    if plugin.is_enabled && !missing.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Missing required settings: {}",
+            missing.join(", ")
+        ));
+    }
+
     // Make sure data folder exists
     std::fs::create_dir_all(plugin.data_folder()).expect("Unable to create plugin data folder");
 
@@ -353,7 +692,7 @@ pub async fn plugin_init(
         .env(env::HOST_HOME_DIR, home_dir)
         .env(env::HOST_OS, std::env::consts::OS)
         // Load user settings as environment variables
-        .envs(user_settings.iter())
+        .envs(user_settings.iter().filter(|(_, value)| !value.is_empty()))
         // Override stdin/out with pipes for comms
         .stdin(Box::new(input))
         .stdout(Box::new(output))
@@ -376,7 +715,7 @@ pub async fn plugin_init(
         start.call(&[])?;
     }
 
-    Ok(instance)
+    Ok((instance, wasi_env))
 }
 
 // --------------------------------------------------------------------------------
@@ -418,3 +757,122 @@ fn wasi_read<T: DeserializeOwned>(env: &WasiEnv) -> anyhow::Result<T> {
 fn wasi_write(env: &WasiEnv, obj: &(impl Serialize + ?Sized)) -> anyhow::Result<()> {
     wasi_write_string(env, &ron::to_string(&obj)?)
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+    use url::Url;
+
+    use super::{PluginConfig, PluginPermissions};
+
+    #[test]
+    fn test_can_fetch() {
+        let permissions = PluginPermissions {
+            fetch: vec!["getpocket.com".into(), "API.github.com".into()],
+            ..Default::default()
+        };
+
+        let can_fetch = |url: &str| permissions.can_fetch(&Url::parse(url).unwrap());
+        assert!(can_fetch("https://getpocket.com/v3/get"));
+        assert!(can_fetch("https://www.getpocket.com/v3/get"));
+        assert!(can_fetch("http://api.github.com/user/starred"));
+        assert!(!can_fetch("https://github.com/"));
+        assert!(!can_fetch("https://notgetpocket.com/"));
+        assert!(!can_fetch("file:///etc/passwd"));
+        // Nothing allowed by default
+        assert!(
+            !PluginPermissions::default().can_fetch(&Url::parse("https://example.com").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_can_add_document() {
+        let permissions = PluginPermissions {
+            add_documents: true,
+            ..Default::default()
+        };
+
+        let can_add =
+            |url: &str| permissions.can_add_document("Pocket-Importer", &Url::parse(url).unwrap());
+        assert!(can_add("pocket-importer://item/123"));
+        assert!(!can_add("https://getpocket.com/read/123"));
+        assert!(!can_add("chrome-importer://item/123"));
+        // Nothing allowed by default
+        assert!(!PluginPermissions::default().can_add_document(
+            "pocket-importer",
+            &Url::parse("pocket-importer://1").unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_apply_settings() {
+        let mut plugin: PluginConfig = ron::from_str(
+            r#"(
+                name: "pocket-importer",
+                author: "spyglass",
+                description: "",
+                version: "1",
+                plugin_type: Lens,
+                user_settings: { "POCKET_TAG": "spyglass" },
+                settings: {
+                    "POCKET_API_KEY": (label: "API key", form_type: Secret, required: true),
+                    "POCKET_SINCE": (label: "Since", default: Some("0")),
+                },
+            )"#,
+        )
+        .unwrap();
+        plugin.is_enabled = true;
+
+        plugin.apply_settings(&HashMap::new());
+        assert_eq!(plugin.user_settings.get("POCKET_TAG").unwrap(), "spyglass");
+        assert_eq!(plugin.user_settings.get("POCKET_SINCE").unwrap(), "0");
+        assert_eq!(
+            plugin.missing_settings(),
+            vec!["POCKET_API_KEY".to_string()]
+        );
+
+        let values = HashMap::from([
+            ("POCKET_API_KEY".to_string(), "secret".to_string()),
+            ("POCKET_SINCE".to_string(), "".to_string()),
+            ("UNKNOWN".to_string(), "ignored".to_string()),
+        ]);
+        plugin.apply_settings(&values);
+        assert_eq!(
+            plugin.user_settings.get("POCKET_API_KEY").unwrap(),
+            "secret"
+        );
+        // Empty values fall back to the default
+        assert_eq!(plugin.user_settings.get("POCKET_SINCE").unwrap(), "0");
+        assert!(!plugin.user_settings.contains_key("UNKNOWN"));
+        assert!(plugin.missing_settings().is_empty());
+    }
+
+    #[test]
+    fn test_is_update_due() {
+        let mut plugin: PluginConfig = ron::from_str(
+            r#"(
+                name: "chrome-importer",
+                author: "spyglass",
+                description: "",
+                version: "1",
+                plugin_type: Lens,
+            )"#,
+        )
+        .unwrap();
+
+        let last_update = Instant::now();
+        let later = |mins: u64| last_update + Duration::from_secs(mins * 60);
+        // No interval, only updated on startup
+        assert!(!plugin.is_update_due(None, later(10)));
+        assert!(!plugin.is_update_due(Some(last_update), later(10)));
+
+        plugin.update_interval = Some(30);
+        assert!(plugin.is_update_due(None, later(0)));
+        assert!(!plugin.is_update_due(Some(last_update), later(10)));
+        assert!(plugin.is_update_due(Some(last_update), later(30)));
+
+        plugin.update_interval = Some(0);
+        assert!(!plugin.is_update_due(None, later(0)));
+    }
+}
@@ -0,0 +1,147 @@
+//! Reloads plugins when their WASM module or manifest changes, so plugins can
+//! be iterated on w/o restarting the app.
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::{broadcast, mpsc};
+
+use shared::config::Config;
+
+use super::{read_manifest, PluginCommand};
+use crate::task::AppShutdown;
+
+/// Builds write the module in several steps, wait for things to settle
+/// before reloading.
+const DEBOUNCE_MS: u64 = 500;
+/// Files that trigger a reload. Plugins write to their data folder all the
+/// time, so anything else is ignored.
+const WATCHED_FILES: [&str; 2] = ["main.wasm", "manifest.ron"];
+
+/// Plugin folder `path` belongs to, if it's one of the watched files.
+fn plugin_dir_for(plugins_dir: &Path, path: &Path) -> Option<PathBuf> {
+    let file_name = path.file_name()?.to_str()?;
+    if !WATCHED_FILES.contains(&file_name) {
+        return None;
+    }
+
+    let dir = path.parent()?;
+    if dir.parent()? != plugins_dir {
+        return None;
+    }
+
+    Some(dir.to_path_buf())
+}
+
+/// Watches the plugins folder & asks the plugin manager to reload plugins
+/// that changed.
+pub async fn plugin_watcher(
+    config: Config,
+    cmd_writer: mpsc::Sender<PluginCommand>,
+    mut shutdown_rx: broadcast::Receiver<AppShutdown>,
+) {
+    log::info!("👀 plugin watcher started");
+
+    let (tx, mut rx) = mpsc::channel(16);
+    let mut watcher = RecommendedWatcher::new(move |res| {
+        futures::executor::block_on(async {
+            tx.send(res).await.expect("Unable to send FS event");
+        })
+    })
+    .expect("Unable to watch plugin directory");
+
+    let plugins_dir = config.plugins_dir();
+    let _ = watcher.watch(&plugins_dir, RecursiveMode::Recursive);
+
+    loop {
+        let event = tokio::select! {
+            res = rx.recv() => res,
+            _ = shutdown_rx.recv() => {
+                log::info!("🛑 Shutting down plugin watcher");
+                return;
+            }
+        };
+
+        let mut changed = HashSet::new();
+        let mut next = event;
+        // Collect everything that changes until the folder is quiet again.
+        while let Some(event) = next {
+            match event {
+                Ok(event)
+                    if matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                    ) =>
+                {
+                    changed.extend(
+                        event
+                            .paths
+                            .iter()
+                            .filter_map(|path| plugin_dir_for(&plugins_dir, path)),
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("watch error: {:?}", e),
+            }
+
+            next = tokio::time::timeout(Duration::from_millis(DEBOUNCE_MS), rx.recv())
+                .await
+                .ok()
+                .flatten();
+        }
+
+        for dir in changed {
+            // Removed plugins stay loaded until the next restart.
+            match read_manifest(&dir) {
+                Ok(plugin) => {
+                    log::info!("<{}> changed, reloading", plugin.name);
+                    let _ = cmd_writer.send(PluginCommand::Reload(plugin.name)).await;
+                }
+                Err(e) => log::warn!("Unable to read plugin in {}: {}", dir.display(), e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::plugin_dir_for;
+
+    #[test]
+    fn test_plugin_dir_for() {
+        let plugins_dir = Path::new("/spyglass/plugins");
+        assert_eq!(
+            plugin_dir_for(plugins_dir, Path::new("/spyglass/plugins/test/main.wasm")),
+            Some(plugins_dir.join("test"))
+        );
+        assert_eq!(
+            plugin_dir_for(
+                plugins_dir,
+                Path::new("/spyglass/plugins/test/manifest.ron")
+            ),
+            Some(plugins_dir.join("test"))
+        );
+        // Data written by the plugin itself
+        assert_eq!(
+            plugin_dir_for(
+                plugins_dir,
+                Path::new("/spyglass/plugins/test/data/Bookmarks")
+            ),
+            None
+        );
+        assert_eq!(
+            plugin_dir_for(
+                plugins_dir,
+                Path::new("/spyglass/plugins/test/data/main.wasm")
+            ),
+            None
+        );
+        assert_eq!(
+            plugin_dir_for(plugins_dir, Path::new("/spyglass/plugins/settings.ron")),
+            None
+        );
+    }
+}
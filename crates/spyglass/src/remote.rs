@@ -0,0 +1,409 @@
+//! Offload crawling to other spyglass instances. Workers lease tasks from the
+//! primary's worker API, fetch & parse the pages, then send the results back
+//! to be indexed by the primary.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use tokio::sync::broadcast;
+use url::Url;
+
+use entities::models::crawl_queue;
+use entities::models::domain_stats::FetchOutcome;
+use entities::sea_orm::EntityTrait;
+use shared::config::RemotePrimarySettings;
+use shared::request::{CrawledPage, LeaseTasksParam, WorkerOutcome, WorkerResultParam};
+use shared::response::WorkerTask;
+
+use crate::crawler::{fetch_url_for, recently_fetched, CrawlResult, Crawler};
+use crate::pipeline::{
+    self, handle_parsed, record_attempt, record_domain_stats, ParsedJob, SKIPPED_REASON,
+};
+use crate::state::AppState;
+use crate::task::{lens_priorities, needs_render, paused_tasks, AppShutdown};
+
+/// Max number of tasks handed out in a single lease.
+const MAX_LEASE_SIZE: u64 = 100;
+/// How long a worker has to report back before its tasks are handed out again.
+const LEASE_TIMEOUT_S: i64 = 30 * 60;
+/// How long to wait before asking the primary for more work when there's
+/// nothing to do or it's unreachable.
+const IDLE_POLL_S: u64 = 10;
+
+pub const LEASE_PATH: &str = "/worker/lease";
+pub const RESULT_PATH: &str = "/worker/result";
+
+/// Hand out up to `limit` tasks to a remote worker. Tasks are marked as
+/// processing & queued again if the worker doesn't report back before the
+/// lease expires, see `crawl_queue::requeue_expired_leases`.
+pub async fn lease_tasks(state: &AppState, limit: u64) -> anyhow::Result<Vec<WorkerTask>> {
+    let (prioritized_domains, prioritized_prefixes) = lens_priorities(state);
+    let tasks = crawl_queue::dequeue_batch(
+        &state.db,
+        &state.user_settings,
+        &prioritized_domains,
+        &prioritized_prefixes,
+        &paused_tasks(state),
+        limit.min(MAX_LEASE_SIZE),
+    )
+    .await?;
+
+    let ids: Vec<i64> = tasks.iter().map(|task| task.id).collect();
+    let expires_at = Utc::now() + chrono::Duration::seconds(LEASE_TIMEOUT_S);
+    crawl_queue::set_lease(&state.db, &ids, expires_at).await?;
+
+    // The whole batch is already dequeued, so deal w/ each task instead of
+    // bailing out & leaving the rest stuck as processing.
+    let mut leased = Vec::new();
+    for task in tasks {
+        match should_lease(state, &task).await {
+            Ok(true) => leased.push(WorkerTask {
+                id: task.id,
+                is_bootstrap: task.crawl_type == crawl_queue::CrawlType::Bootstrap,
+                domain: task.domain,
+                url: task.url,
+            }),
+            Ok(false) => {
+                if let Err(err) =
+                    crawl_queue::mark_done(&state.db, task.id, crawl_queue::CrawlStatus::Completed)
+                        .await
+                {
+                    log::error!("Unable to mark task {} as done: {}", task.id, err);
+                }
+            }
+            Err(err) => {
+                log::warn!("Unable to lease task {}: {}", task.id, err);
+                pipeline::mark_failed(state, task.id, &err.to_string()).await;
+            }
+        }
+    }
+
+    Ok(leased)
+}
+
+/// No need to send a worker out for something we just fetched.
+async fn should_lease(state: &AppState, task: &crawl_queue::Model) -> anyhow::Result<bool> {
+    let fetch_url = Url::parse(&fetch_url_for(task))?;
+    Ok(!recently_fetched(&state.db, &fetch_url).await?)
+}
+
+fn to_crawl_result(page: CrawledPage) -> CrawlResult {
+    CrawlResult {
+        content_hash: page.content_hash,
+        content: page.content,
+        description: page.description,
+        status: page.status,
+        title: page.title,
+        url: page.url,
+        links: page.links.into_iter().collect(),
+        raw: page.raw,
+        archived: page.archived,
+        lang: page.lang,
+        author: page.author,
+        etag: page.etag,
+        last_modified: page.last_modified,
+        no_index: page.no_index,
+        no_follow: page.no_follow,
+        metadata: page.metadata,
+    }
+}
+
+/// Handle the result of a task leased out w/ `lease_tasks`.
+pub async fn handle_result(state: &AppState, result: WorkerResultParam) -> anyhow::Result<()> {
+    let task = crawl_queue::Entity::find_by_id(result.task_id)
+        .one(&state.db)
+        .await?;
+    // Only accept results for tasks that are still leased out.
+    let task = match task {
+        Some(task) if task.status == crawl_queue::CrawlStatus::Processing => task,
+        _ => return Err(anyhow::anyhow!("Unknown task: {}", result.task_id)),
+    };
+
+    match result.outcome {
+        WorkerOutcome::Crawled(page) => {
+            let outcome = FetchOutcome::Status(page.status);
+            record_domain_stats(state, &task.domain, outcome, page.num_bytes).await;
+            let duration = Duration::from_millis(page.duration_ms);
+            record_attempt(
+                state,
+                &task,
+                Some(page.status),
+                duration,
+                page.num_bytes,
+                None,
+            )
+            .await;
+            let job = ParsedJob {
+                task,
+                fetch_url: Url::parse(&page.fetch_url)?,
+                result: to_crawl_result(page),
+            };
+            handle_parsed(state, job).await;
+        }
+        WorkerOutcome::Skipped => {
+            let reason = Some(SKIPPED_REASON.to_string());
+            record_attempt(state, &task, None, Duration::ZERO, 0, reason).await;
+            crawl_queue::mark_done(
+                &state.db,
+                result.task_id,
+                crawl_queue::CrawlStatus::Completed,
+            )
+            .await?;
+        }
+        WorkerOutcome::Failed(err) => {
+            log::warn!("Remote crawl of task {} failed: {}", result.task_id, err);
+            record_domain_stats(state, &task.domain, FetchOutcome::NetworkError, 0).await;
+            record_attempt(state, &task, None, Duration::ZERO, 0, Some(err.clone())).await;
+            pipeline::mark_failed(state, result.task_id, &err).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetch & parse a leased task. Robots rules are checked & cached in the
+/// worker's own database.
+async fn crawl(state: &AppState, crawler: &Crawler, task: WorkerTask) -> WorkerOutcome {
+    let crawl_type = if task.is_bootstrap {
+        crawl_queue::CrawlType::Bootstrap
+    } else {
+        crawl_queue::CrawlType::Normal
+    };
+
+    let model = crawl_queue::Model {
+        id: task.id,
+        domain: task.domain,
+        url: task.url,
+        status: crawl_queue::CrawlStatus::Processing,
+        num_retries: 0,
+        next_retry_at: None,
+        lease_expires_at: None,
+        crawl_type,
+        // Only matters to the primary, which enqueues the links.
+        depth: 0,
+        max_depth: None,
+        priority: 0,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+
+    let started = Instant::now();
+    let fetched = crawler.fetch_task(&state.db, model).await;
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let mut job = match fetched {
+        Ok(Some(job)) => job,
+        Ok(None) => return WorkerOutcome::Skipped,
+        Err(err) => return WorkerOutcome::Failed(err.to_string()),
+    };
+
+    if !job.page.archived && needs_render(state, &job.page.url) {
+        if let Err(err) = crawler.render(&mut job.page).await {
+            log::warn!("Unable to render <{}>: {}", job.page.url, err);
+        }
+    }
+
+    let fetch_url = job.page.url.to_string();
+    let num_bytes = job.page.body.as_ref().map_or(0, |body| body.len() as u64);
+    let crawler = crawler.clone();
+    match tokio::task::spawn_blocking(move || crawler.parse_job(job)).await {
+        Ok(result) => WorkerOutcome::Crawled(CrawledPage {
+            fetch_url,
+            status: result.status,
+            url: result.url,
+            title: result.title,
+            description: result.description,
+            content: result.content,
+            content_hash: result.content_hash,
+            raw: result.raw,
+            links: result.links.into_iter().collect(),
+            archived: result.archived,
+            lang: result.lang,
+            author: result.author,
+            etag: result.etag,
+            last_modified: result.last_modified,
+            num_bytes,
+            duration_ms,
+            no_index: result.no_index,
+            no_follow: result.no_follow,
+            metadata: result.metadata,
+        }),
+        Err(err) => WorkerOutcome::Failed(err.to_string()),
+    }
+}
+
+/// Talks to the primary's worker API.
+struct PrimaryClient {
+    client: reqwest::Client,
+    settings: RemotePrimarySettings,
+}
+
+impl PrimaryClient {
+    async fn post<T: serde::Serialize>(&self, path: &str, body: &T) -> anyhow::Result<Vec<u8>> {
+        let url = format!("{}{}", self.settings.url.trim_end_matches('/'), path);
+        let resp = self
+            .client
+            .post(url)
+            .bearer_auth(&self.settings.token)
+            .header("content-type", "application/json")
+            .body(serde_json::to_vec(body)?)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(resp.bytes().await?.to_vec())
+    }
+
+    async fn lease(&self, limit: u64) -> anyhow::Result<Vec<WorkerTask>> {
+        let body = self.post(LEASE_PATH, &LeaseTasksParam { limit }).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    async fn send_result(&self, result: &WorkerResultParam) -> anyhow::Result<()> {
+        self.post(RESULT_PATH, result).await?;
+        Ok(())
+    }
+}
+
+/// Crawl each domain's tasks one after the other, w/ the usual delay in
+/// between, while different domains are crawled concurrently.
+async fn crawl_batch(
+    state: &AppState,
+    crawler: &Crawler,
+    primary: &PrimaryClient,
+    tasks: Vec<WorkerTask>,
+) {
+    let mut by_domain: HashMap<String, Vec<WorkerTask>> = HashMap::new();
+    for task in tasks {
+        by_domain.entry(task.domain.clone()).or_default().push(task);
+    }
+
+    let domains = by_domain.into_values().map(|tasks| async move {
+        for (idx, task) in tasks.into_iter().enumerate() {
+            if idx > 0 {
+                tokio::time::sleep(state.user_settings.politeness_delay(&task.domain)).await;
+            }
+
+            let task_id = task.id;
+            let outcome = crawl(state, crawler, task).await;
+            let result = WorkerResultParam { task_id, outcome };
+            if let Err(err) = primary.send_result(&result).await {
+                log::error!("Unable to send result for task {}: {}", task_id, err);
+            }
+        }
+    });
+
+    futures::future::join_all(domains).await;
+}
+
+/// Pulls tasks from the primary & crawls them until the app shuts down.
+pub async fn worker_task(
+    state: AppState,
+    settings: RemotePrimarySettings,
+    mut shutdown_rx: broadcast::Receiver<AppShutdown>,
+) {
+    log::info!("crawl worker started, primary: {}", settings.url);
+
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            log::error!("Unable to create crawl worker client: {}", err);
+            return;
+        }
+    };
+
+    let primary = PrimaryClient { client, settings };
+    let crawler = Crawler::with_settings(&state.user_settings);
+    let limit = state.user_settings.inflight_crawl_limit.value() as u64;
+
+    loop {
+        let tasks = tokio::select! {
+            res = primary.lease(limit) => res,
+            _ = shutdown_rx.recv() => break,
+        };
+
+        let tasks = match tasks {
+            Ok(tasks) if !tasks.is_empty() => tasks,
+            Ok(_) => Vec::new(),
+            Err(err) => {
+                log::warn!("Unable to lease tasks from primary: {}", err);
+                Vec::new()
+            }
+        };
+
+        if tasks.is_empty() {
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(IDLE_POLL_S)) => continue,
+                _ = shutdown_rx.recv() => break,
+            }
+        }
+
+        // Let the current batch finish so the primary isn't left waiting on
+        // tasks that will never be reported back.
+        crawl_batch(&state, &crawler, &primary, tasks).await;
+    }
+
+    log::info!("🛑 Shutting down crawl worker");
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use entities::models::crawl_queue;
+    use entities::sea_orm::{ActiveModelTrait, EntityTrait, Set};
+    use entities::test::setup_test_db;
+    use shared::config::UserSettings;
+    use shared::request::{WorkerOutcome, WorkerResultParam};
+
+    use super::{handle_result, lease_tasks};
+    use crate::state::AppState;
+
+    #[tokio::test]
+    async fn test_lease_and_result() {
+        let db = setup_test_db().await;
+        let state = AppState::for_test(db, &UserSettings::default());
+
+        let task = crawl_queue::ActiveModel {
+            domain: Set("example.com".to_string()),
+            url: Set("https://example.com/".to_string()),
+            ..Default::default()
+        };
+        task.insert(&state.db).await.unwrap();
+
+        let leased = lease_tasks(&state, 10).await.unwrap();
+        assert_eq!(leased.len(), 1);
+        assert_eq!(leased[0].url, "https://example.com/");
+        assert!(!leased[0].is_bootstrap);
+        // Already leased out
+        assert!(lease_tasks(&state, 10).await.unwrap().is_empty());
+
+        let task = crawl_queue::Entity::find_by_id(leased[0].id)
+            .one(&state.db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(task.lease_expires_at.unwrap() > Utc::now());
+
+        let result = WorkerResultParam {
+            task_id: leased[0].id,
+            outcome: WorkerOutcome::Skipped,
+        };
+        handle_result(&state, result).await.unwrap();
+
+        let task = crawl_queue::Entity::find_by_id(leased[0].id)
+            .one(&state.db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(task.status, crawl_queue::CrawlStatus::Completed);
+
+        // Results for tasks that aren't leased are rejected.
+        let result = WorkerResultParam {
+            task_id: leased[0].id,
+            outcome: WorkerOutcome::Skipped,
+        };
+        assert!(handle_result(&state, result).await.is_err());
+    }
+}
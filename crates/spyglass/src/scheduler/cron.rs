@@ -0,0 +1,225 @@
+//! Minimal cron expression support. Handles the standard five fields
+//! (minute, hour, day of month, month, day of week) w/ `*`, lists, ranges &
+//! steps, plus the `@hourly`, `@daily`, `@weekly` & `@monthly` shortcuts.
+//! All times are in UTC.
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, Utc};
+
+/// Don't look further than this for the next run, e.g. for `0 0 31 2 *`.
+const MAX_LOOKAHEAD_DAYS: i64 = 366 * 4;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    expr: String,
+    minutes: BTreeSet<u32>,
+    hours: BTreeSet<u32>,
+    days: BTreeSet<u32>,
+    months: BTreeSet<u32>,
+    weekdays: BTreeSet<u32>,
+    /// Like cron, when both day fields are restricted either one matching is
+    /// enough.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> anyhow::Result<BTreeSet<u32>> {
+    let mut values = BTreeSet::new();
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>()?),
+            None => (part, 1),
+        };
+
+        if step == 0 {
+            return Err(anyhow::anyhow!("Invalid step in '{}'", part));
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (start.parse()?, end.parse()?)
+        } else {
+            let start = range.parse()?;
+            // `5/15` means every 15 starting at 5
+            if part.contains('/') {
+                (start, max)
+            } else {
+                (start, start)
+            }
+        };
+
+        if start < min || end > max || start > end {
+            return Err(anyhow::anyhow!("'{}' out of range ({}-{})", part, min, max));
+        }
+
+        values.extend((start..=end).step_by(step as usize));
+    }
+
+    Ok(values)
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        let expr = expr.trim();
+        let expanded = match expr {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            _ => expr,
+        };
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(anyhow::anyhow!(
+                "Expected 5 fields in cron expression '{}'",
+                expr
+            ));
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // Both 0 & 7 are Sunday
+        if weekdays.remove(&7) {
+            weekdays.insert(0);
+        }
+
+        Ok(Schedule {
+            expr: expr.to_string(),
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+impl Schedule {
+    fn matches_day(&self, time: &DateTime<Utc>) -> bool {
+        let day = self.days.contains(&time.day());
+        let weekday = self
+            .weekdays
+            .contains(&time.weekday().num_days_from_sunday());
+
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day,
+            (true, false) => weekday,
+            (false, false) => day || weekday,
+        }
+    }
+
+    /// Next time this schedule fires, strictly after `after`.
+    pub fn next_after(&self, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = start + Duration::days(MAX_LOOKAHEAD_DAYS);
+
+        let mut time = start;
+        while time < limit {
+            if !self.months.contains(&time.month()) {
+                // Skip to the start of next month
+                let (year, month) = if time.month() == 12 {
+                    (time.year() + 1, 1)
+                } else {
+                    (time.year(), time.month() + 1)
+                };
+                time = Utc.ymd(year, month, 1).and_hms(0, 0, 0);
+                continue;
+            }
+
+            if !self.matches_day(&time) {
+                time = Utc
+                    .ymd(time.year(), time.month(), time.day())
+                    .and_hms(0, 0, 0)
+                    + Duration::days(1);
+                continue;
+            }
+
+            if !self.hours.contains(&time.hour()) {
+                time = time.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+
+            if !self.minutes.contains(&time.minute()) {
+                time = time + Duration::minutes(1);
+                continue;
+            }
+
+            return Some(time);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Schedule;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_parse() {
+        assert!("* * * * *".parse::<Schedule>().is_ok());
+        assert!("*/15 0-6 1,15 * 1-5".parse::<Schedule>().is_ok());
+        assert!("@daily".parse::<Schedule>().is_ok());
+
+        assert!("* * * *".parse::<Schedule>().is_err());
+        assert!("60 * * * *".parse::<Schedule>().is_err());
+        assert!("*/0 * * * *".parse::<Schedule>().is_err());
+        assert!("5-1 * * * *".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        // Wednesday
+        let now = Utc.ymd(2022, 8, 10).and_hms(10, 30, 15);
+
+        let every_15: Schedule = "*/15 * * * *".parse().unwrap();
+        assert_eq!(
+            every_15.next_after(&now),
+            Some(Utc.ymd(2022, 8, 10).and_hms(10, 45, 0))
+        );
+
+        let daily: Schedule = "0 3 * * *".parse().unwrap();
+        assert_eq!(
+            daily.next_after(&now),
+            Some(Utc.ymd(2022, 8, 11).and_hms(3, 0, 0))
+        );
+
+        // Sundays
+        let weekly: Schedule = "@weekly".parse().unwrap();
+        assert_eq!(
+            weekly.next_after(&now),
+            Some(Utc.ymd(2022, 8, 14).and_hms(0, 0, 0))
+        );
+
+        let new_year: Schedule = "0 0 1 1 *".parse().unwrap();
+        assert_eq!(
+            new_year.next_after(&now),
+            Some(Utc.ymd(2023, 1, 1).and_hms(0, 0, 0))
+        );
+
+        // Strictly after
+        let at = Utc.ymd(2022, 8, 11).and_hms(3, 0, 0);
+        assert_eq!(
+            daily.next_after(&at),
+            Some(Utc.ymd(2022, 8, 12).and_hms(3, 0, 0))
+        );
+
+        let never: Schedule = "0 0 31 2 *".parse().unwrap();
+        assert_eq!(never.next_after(&now), None);
+    }
+}
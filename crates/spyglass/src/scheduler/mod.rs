@@ -0,0 +1,321 @@
+//! Central scheduler for periodic background work. Each task runs on a cron
+//! schedule & its next run time is persisted, so anything missed while the
+//! app was closed runs on the next start.
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use tokio::sync::broadcast;
+
+use entities::models::{crawl_log, crawl_queue, run_maintenance, scheduled_task};
+use shared::config::{Config, Lens, UserSettings};
+
+use crate::crawler::feed;
+use crate::plugin::PluginCommand;
+use crate::search::semantic;
+use crate::state::AppState;
+use crate::sync;
+use crate::task::AppShutdown;
+
+pub mod cron;
+pub use self::cron::Schedule;
+
+/// How often we check for tasks that are due.
+const CHECK_INTERVAL_S: u64 = 30;
+/// Completed crawls older than this are queued up again by the recrawl task.
+const RECRAWL_AFTER_DAYS: i64 = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Job {
+    /// Requeue pages that haven't been crawled in a while.
+    Recrawl,
+    /// SQLite upkeep & pruning the crawl log.
+    DbMaintenance,
+    /// Merge index segments.
+    OptimizeIndex,
+    /// Ask subscribed plugins for new items.
+    PluginUpdates,
+    /// Exchange documents & lenses w/ other devices, if enabled.
+    Sync,
+    /// Compute embeddings for new & updated documents, if semantic search
+    /// is enabled.
+    EmbedDocuments,
+    /// Check subscribed RSS/Atom feeds for new entries.
+    PollFeeds,
+    /// Requeue pages in lenses w/ a `refresh_interval` that are due.
+    RefreshLenses,
+    /// Requeue tasks leased to remote workers that never reported back.
+    ExpireLeases,
+}
+
+impl Job {
+    pub const ALL: [Job; 9] = [
+        Job::Recrawl,
+        Job::DbMaintenance,
+        Job::OptimizeIndex,
+        Job::PluginUpdates,
+        Job::Sync,
+        Job::EmbedDocuments,
+        Job::PollFeeds,
+        Job::RefreshLenses,
+        Job::ExpireLeases,
+    ];
+
+    /// Name used to persist the task & override its schedule in the settings.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Job::Recrawl => "recrawl",
+            Job::DbMaintenance => "db_maintenance",
+            Job::OptimizeIndex => "optimize_index",
+            Job::PluginUpdates => "plugin_updates",
+            Job::Sync => "sync",
+            Job::EmbedDocuments => "embed_documents",
+            Job::PollFeeds => "poll_feeds",
+            Job::RefreshLenses => "refresh_lenses",
+            Job::ExpireLeases => "expire_leases",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Job> {
+        Job::ALL.into_iter().find(|job| job.name() == name)
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        match self {
+            Job::Recrawl => "0 4 * * *",
+            Job::DbMaintenance => "30 3 * * *",
+            Job::OptimizeIndex => "0 5 * * 0",
+            Job::PluginUpdates => "@hourly",
+            Job::Sync => "*/15 * * * *",
+            Job::EmbedDocuments => "*/10 * * * *",
+            Job::PollFeeds => "*/30 * * * *",
+            Job::RefreshLenses => "@hourly",
+            Job::ExpireLeases => "*/5 * * * *",
+        }
+    }
+
+    fn is_enabled(&self, settings: &UserSettings) -> bool {
+        match self {
+            Job::Sync => settings.sync.is_some(),
+            Job::EmbedDocuments => settings.semantic_search.is_some(),
+            Job::ExpireLeases => settings.worker_api.is_some(),
+            _ => true,
+        }
+    }
+}
+
+/// Schedule for `job`, using the one from the user settings if it's valid.
+fn schedule_for(settings: &UserSettings, job: Job) -> Schedule {
+    if let Some(expr) = settings.task_schedules.get(job.name()) {
+        match expr.parse() {
+            Ok(schedule) => return schedule,
+            Err(err) => log::warn!(
+                "Invalid schedule for <{}>, using default: {}",
+                job.name(),
+                err
+            ),
+        }
+    }
+
+    job.default_schedule()
+        .parse()
+        .expect("Invalid default schedule")
+}
+
+#[tracing::instrument(skip(state, config))]
+async fn run_job(state: &AppState, config: &Config, job: Job) -> anyhow::Result<()> {
+    match job {
+        Job::Recrawl => {
+            let before = Utc::now() - chrono::Duration::days(RECRAWL_AFTER_DAYS);
+            let requeued = crawl_queue::requeue_stale(&state.db, before).await?;
+            log::info!("requeued {} stale pages", requeued);
+        }
+        Job::DbMaintenance => {
+            let retention = state.user_settings.crawl_log_retention_days as i64;
+            let before = Utc::now() - chrono::Duration::days(retention);
+            let pruned = crawl_log::prune(&state.db, before).await?;
+            log::info!("pruned {} old crawl log entries", pruned);
+            run_maintenance(&state.db).await?;
+        }
+        Job::OptimizeIndex => {
+            // Flush pending writes so they're part of the merge.
+            state.index.commit()?;
+            let merged = state.index.optimize().await?;
+            log::info!("merged {} index segments", merged);
+        }
+        Job::PluginUpdates => {
+            if let Some(cmd_tx) = state.plugin_cmd_tx.lock().await.as_ref() {
+                cmd_tx
+                    .send(PluginCommand::CheckForUpdate)
+                    .await
+                    .map_err(|_| anyhow::anyhow!("Plugin manager is not running"))?;
+            }
+        }
+        Job::Sync => {
+            if let Some(settings) = &state.user_settings.sync {
+                let stats = sync::run(state, config, settings).await?;
+                log::info!("synced w/ other devices: {:?}", stats);
+            }
+        }
+        Job::EmbedDocuments => {
+            if let Some(settings) = &state.user_settings.semantic_search {
+                let embedded = semantic::embed_pending(state, settings).await?;
+                log::info!("embedded {} documents", embedded);
+            }
+        }
+        Job::PollFeeds => {
+            let num_new = feed::poll_all(state).await;
+            log::info!("found {} new feed entries", num_new);
+        }
+        Job::RefreshLenses => {
+            let lenses: Vec<Lens> = state
+                .lenses
+                .iter()
+                .filter(|lens| lens.is_enabled)
+                .map(|lens| lens.value().clone())
+                .collect();
+
+            for lens in lenses {
+                if let Some(days) = lens.refresh_interval.filter(|days| *days > 0) {
+                    let before = Utc::now() - chrono::Duration::days(days as i64);
+                    let requeued =
+                        crawl_queue::requeue_stale_in_lens(&state.db, &lens, before).await?;
+                    log::info!("requeued {} stale pages in <{}>", requeued, lens.name);
+                }
+            }
+        }
+        Job::ExpireLeases => {
+            let requeued = crawl_queue::requeue_expired_leases(&state.db, Utc::now()).await?;
+            log::info!("requeued {} tasks w/ expired leases", requeued);
+        }
+    }
+
+    Ok(())
+}
+
+/// Persist the current schedule for each job, keeping the next run time of
+/// any job whose schedule hasn't changed. Disabled jobs are removed.
+async fn register_jobs(state: &AppState) {
+    let now = Utc::now();
+    for job in Job::ALL {
+        if !job.is_enabled(&state.user_settings) {
+            if let Err(err) = scheduled_task::remove(&state.db, job.name()).await {
+                log::error!("Unable to remove task <{}>: {}", job.name(), err);
+            }
+            continue;
+        }
+
+        let schedule = schedule_for(&state.user_settings, job);
+        let next_run = match schedule.next_after(&now) {
+            Some(next_run) => next_run,
+            None => {
+                log::warn!("Schedule for <{}> never runs, skipping", job.name());
+                continue;
+            }
+        };
+
+        if let Err(err) =
+            scheduled_task::register(&state.db, job.name(), &schedule.to_string(), next_run).await
+        {
+            log::error!("Unable to register task <{}>: {}", job.name(), err);
+        }
+    }
+}
+
+async fn run_due(state: &AppState, config: &Config) -> anyhow::Result<()> {
+    let now = Utc::now();
+    for task in scheduled_task::due(&state.db, now).await? {
+        // Tasks from an older version that no longer exist.
+        let job = match Job::from_name(&task.name) {
+            Some(job) => job,
+            None => continue,
+        };
+
+        // Skip it rather than holding up every other task.
+        let schedule: Schedule = match task.schedule.parse() {
+            Ok(schedule) => schedule,
+            Err(err) => {
+                log::error!("Invalid schedule for <{}>: {}", task.name, err);
+                continue;
+            }
+        };
+        let start = Instant::now();
+        let error = match run_job(state, config, job).await {
+            Ok(()) => {
+                log::info!("ran <{}> in {:?}", job.name(), start.elapsed());
+                None
+            }
+            Err(err) => {
+                log::error!("Task <{}> failed: {}", job.name(), err);
+                Some(err.to_string())
+            }
+        };
+
+        // Base the next run off the current time so a long running task or
+        // a missed run doesn't fire repeatedly to catch up.
+        let next_run = schedule
+            .next_after(&Utc::now())
+            .unwrap_or_else(|| Utc::now() + chrono::Duration::days(365));
+        scheduled_task::mark_run(&state.db, task, error, next_run).await?;
+    }
+
+    Ok(())
+}
+
+/// Runs scheduled tasks as they come due until the app shuts down.
+pub async fn run(
+    state: AppState,
+    config: Config,
+    mut shutdown_rx: broadcast::Receiver<AppShutdown>,
+) {
+    log::info!("scheduler started");
+    register_jobs(&state).await;
+
+    let mut interval = tokio::time::interval(Duration::from_secs(CHECK_INTERVAL_S));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown_rx.recv() => {
+                log::info!("🛑 Shutting down scheduler");
+                return;
+            }
+        }
+
+        if let Err(err) = run_due(&state, &config).await {
+            log::error!("Unable to run scheduled tasks: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use shared::config::{Config, UserSettings};
+
+    use super::{schedule_for, Job};
+
+    #[test]
+    fn test_schedule_for() {
+        let mut settings = UserSettings::default();
+        // All the defaults should be valid
+        for job in Job::ALL {
+            assert_eq!(
+                schedule_for(&settings, job).to_string(),
+                job.default_schedule()
+            );
+        }
+
+        settings.task_schedules = HashMap::from([
+            ("recrawl".to_string(), "0 */6 * * *".to_string()),
+            ("optimize_index".to_string(), "not a schedule".to_string()),
+        ]);
+        assert_eq!(
+            schedule_for(&settings, Job::Recrawl).to_string(),
+            "0 */6 * * *"
+        );
+        assert_eq!(
+            schedule_for(&settings, Job::OptimizeIndex).to_string(),
+            Job::OptimizeIndex.default_schedule()
+        );
+    }
+}
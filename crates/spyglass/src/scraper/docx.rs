@@ -0,0 +1,79 @@
+//! Word documents. A docx file is a zip of XML files, the text lives in
+//! `word/document.xml` & the title/author in `docProps/core.xml`.
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use zip::ZipArchive;
+
+use super::parser::{read_zip_entry, xml_tag};
+use super::{text_result, unescape_xml, ScrapeResult};
+
+static RUNS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?s)<w:t(?:\s[^>]*)?>(.*?)</w:t>|</w:p>|<w:tab/>|<w:br/>")
+        .expect("Invalid docx regex")
+});
+
+/// Text runs in the document body, w/ paragraphs separated by a space.
+fn document_text(xml: &str) -> String {
+    let mut text = String::new();
+    for cap in RUNS.captures_iter(xml) {
+        match cap.get(1) {
+            Some(run) => text.push_str(&unescape_xml(run.as_str())),
+            None => text.push(' '),
+        }
+    }
+
+    text
+}
+
+pub fn parse(body: &[u8]) -> anyhow::Result<ScrapeResult> {
+    let mut archive = ZipArchive::new(Cursor::new(body))?;
+    let document = read_zip_entry(&mut archive, "word/document.xml")?;
+
+    let mut meta = HashMap::new();
+    // Metadata is optional
+    if let Ok(core) = read_zip_entry(&mut archive, "docProps/core.xml") {
+        for (tag, name) in [("dc:title", "title"), ("dc:creator", "author")] {
+            if let Some(value) = xml_tag(&core, tag) {
+                meta.insert(name.to_string(), value);
+            }
+        }
+    }
+
+    Ok(text_result(&document_text(&document), meta))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+
+    use zip::write::{FileOptions, ZipWriter};
+
+    use super::parse;
+
+    #[test]
+    fn test_parse() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("word/document.xml", FileOptions::default())
+            .unwrap();
+        zip.write_all(
+            br#"<w:document><w:body>
+                <w:p><w:r><w:t>Quarterly</w:t></w:r><w:r><w:t xml:space="preserve"> report</w:t></w:r></w:p>
+                <w:p><w:r><w:t>Profits &amp; losses</w:t></w:r></w:p>
+            </w:body></w:document>"#,
+        )
+        .unwrap();
+        zip.start_file("docProps/core.xml", FileOptions::default())
+            .unwrap();
+        zip.write_all(b"<cp:coreProperties><dc:title>Q3</dc:title><dc:creator>Jane</dc:creator></cp:coreProperties>")
+            .unwrap();
+        let docx = zip.finish().unwrap().into_inner();
+
+        let result = parse(&docx).unwrap();
+        assert_eq!(result.title, Some("Q3".to_string()));
+        assert_eq!(result.meta.get("author"), Some(&"Jane".to_string()));
+        assert_eq!(result.content, "Quarterly report Profits & losses");
+    }
+}
@@ -0,0 +1,131 @@
+//! EPUB books. An epub is a zip of XHTML chapters, listed in reading order in
+//! the package (OPF) file that `META-INF/container.xml` points to.
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use zip::ZipArchive;
+
+use super::parser::{read_zip_entry, xml_tag};
+use super::{html_to_text, text_result, ScrapeResult};
+
+static ATTR: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"\s([\w:.-]+)\s*=\s*["']([^"']*)["']"#).expect("Invalid attr regex"));
+static ITEM_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<(?:opf:)?item\s[^>]*>").expect("Invalid item regex"));
+static ITEMREF_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<(?:opf:)?itemref\s[^>]*>").expect("Invalid itemref regex"));
+static ROOTFILE_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<rootfile\s[^>]*>").expect("Invalid rootfile regex"));
+
+/// Value of `attr` in a single XML tag.
+fn attr(tag: &str, attr: &str) -> Option<String> {
+    ATTR.captures_iter(tag)
+        .find(|cap| &cap[1] == attr)
+        .map(|cap| cap[2].to_string())
+}
+
+/// Paths of the chapters in reading order, relative to the root of the zip.
+fn spine(opf_path: &str, opf: &str) -> Vec<String> {
+    let manifest: HashMap<String, String> = ITEM_TAG
+        .find_iter(opf)
+        .filter_map(|tag| Some((attr(tag.as_str(), "id")?, attr(tag.as_str(), "href")?)))
+        .collect();
+
+    // Chapter paths are relative to the package file.
+    let base = match opf_path.rfind('/') {
+        Some(idx) => &opf_path[..=idx],
+        None => "",
+    };
+
+    ITEMREF_TAG
+        .find_iter(opf)
+        .filter_map(|tag| attr(tag.as_str(), "idref"))
+        .filter_map(|idref| manifest.get(&idref))
+        .map(|href| format!("{}{}", base, href))
+        .collect()
+}
+
+pub fn parse(body: &[u8]) -> anyhow::Result<ScrapeResult> {
+    let mut archive = ZipArchive::new(Cursor::new(body))?;
+
+    let container = read_zip_entry(&mut archive, "META-INF/container.xml")?;
+    let opf_path = ROOTFILE_TAG
+        .find(&container)
+        .and_then(|tag| attr(tag.as_str(), "full-path"))
+        .ok_or_else(|| anyhow::anyhow!("No package file in container"))?;
+    let opf = read_zip_entry(&mut archive, &opf_path)?;
+
+    let mut meta = HashMap::new();
+    for (tag, name) in [("dc:title", "title"), ("dc:creator", "author")] {
+        if let Some(value) = xml_tag(&opf, tag) {
+            meta.insert(name.to_string(), value);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut chapters = Vec::new();
+    for path in spine(&opf_path, &opf) {
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+
+        match read_zip_entry(&mut archive, &path) {
+            Ok(xhtml) => chapters.push(html_to_text(&xhtml).content),
+            Err(err) => log::debug!("Unable to read chapter {}: {}", path, err),
+        }
+    }
+
+    Ok(text_result(&chapters.join(" "), meta))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+
+    use zip::write::{FileOptions, ZipWriter};
+
+    use super::parse;
+
+    #[test]
+    fn test_parse() {
+        let files: [(&str, &str); 4] = [
+            (
+                "META-INF/container.xml",
+                r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+            ),
+            (
+                "OEBPS/content.opf",
+                r#"<package>
+                    <metadata><dc:title>The Book</dc:title><dc:creator opf:role="aut">Jane</dc:creator></metadata>
+                    <manifest>
+                        <item id="ch2" href="text/ch2.xhtml" media-type="application/xhtml+xml"/>
+                        <item href="text/ch1.xhtml" id="ch1" media-type="application/xhtml+xml"/>
+                    </manifest>
+                    <spine><itemref idref="ch1"/><itemref idref="ch2"/></spine>
+                </package>"#,
+            ),
+            (
+                "OEBPS/text/ch1.xhtml",
+                "<html><body><h1>Chapter One</h1><p>It begins.</p></body></html>",
+            ),
+            (
+                "OEBPS/text/ch2.xhtml",
+                "<html><body><p>It ends.</p></body></html>",
+            ),
+        ];
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        let epub = zip.finish().unwrap().into_inner();
+
+        let result = parse(&epub).unwrap();
+        assert_eq!(result.title, Some("The Book".to_string()));
+        assert_eq!(result.meta.get("author"), Some(&"Jane".to_string()));
+        assert_eq!(result.content, "Chapter One It begins. It ends.");
+    }
+}
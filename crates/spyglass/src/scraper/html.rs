@@ -5,10 +5,11 @@ use std::collections::HashMap;
 
 use ego_tree::{NodeId, NodeRef, Tree};
 
-use html5ever::driver::{self, ParseOpts};
+use html5ever::driver::{self, ParseOpts, Parser};
 use html5ever::tendril::StrTendril;
 use html5ever::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
-use html5ever::{Attribute, ExpandedName, QualName};
+use html5ever::{Attribute, ExpandedName, LocalName, QualName};
+use tendril::stream::Utf8LossyDecoder;
 use tendril::TendrilSink;
 
 use crate::scraper::element::{Comment, Doctype, Element, Node, ProcessingInstruction, Text};
@@ -33,6 +34,12 @@ impl Html {
         parser.one(html)
     }
 
+    /// Parser that can be fed raw bytes as they come in, rather than requiring
+    /// the entire document up front.
+    pub fn streaming_parser() -> Utf8LossyDecoder<Parser<Self>> {
+        driver::parse_document(Self::new(), ParseOpts::default()).from_utf8()
+    }
+
     fn html(&self) -> Option<NodeRef<Node>> {
         let root = self.tree.root();
         for child in root.children() {
@@ -87,6 +94,60 @@ impl Html {
         None
     }
 
+    /// Language declared w/ the `lang` attribute on the `<html>` tag.
+    pub fn lang(&self) -> Option<String> {
+        let lang_key = QualName::new(None, ns!(), local_name!("lang"));
+        let root = self.html()?;
+        let element = root.value().as_element()?;
+        element
+            .attrs
+            .get(&lang_key)
+            .map(|lang| lang.to_string())
+            .filter(|lang| !lang.trim().is_empty())
+    }
+
+    /// Contents of `<script type="application/ld+json">` tags, i.e. schema.org
+    /// metadata in JSON-LD form.
+    pub fn json_ld(&self) -> Vec<String> {
+        let type_key = QualName::new(None, ns!(), local_name!("type"));
+        self.tree
+            .root()
+            .descendants()
+            .filter(|node| {
+                node.value().as_element().map_or(false, |element| {
+                    element.name() == "script"
+                        && element.attrs.get(&type_key).map_or(false, |script_type| {
+                            script_type
+                                .trim()
+                                .eq_ignore_ascii_case("application/ld+json")
+                        })
+                })
+            })
+            .map(|node| {
+                node.children()
+                    .filter_map(|child| child.value().as_text().map(|text| text.to_string()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Values of `itemtype` attributes, i.e. schema.org metadata in microdata
+    /// form.
+    pub fn item_types(&self) -> Vec<String> {
+        let itemtype_key = QualName::new(None, ns!(), LocalName::from("itemtype"));
+        self.tree
+            .root()
+            .descendants()
+            .filter_map(|node| {
+                let element = node.value().as_element()?;
+                element
+                    .attrs
+                    .get(&itemtype_key)
+                    .map(|value| value.to_string())
+            })
+            .collect()
+    }
+
     pub fn link_tags(&self) -> HashMap<String, String> {
         let mut map = HashMap::new();
         self._find_link(&mut map);
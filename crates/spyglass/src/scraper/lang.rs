@@ -0,0 +1,158 @@
+//! Detects the language a page is written in. Languages are identified by
+//! their ISO 639-1 code (e.g. `en`, `de`) where there is one.
+
+/// ISO 639-3 codes used by whatlang & their ISO 639-1 equivalents.
+const ISO_639_1: [(&str, &str); 69] = [
+    ("afr", "af"),
+    ("aka", "ak"),
+    ("amh", "am"),
+    ("ara", "ar"),
+    ("aze", "az"),
+    ("bel", "be"),
+    ("ben", "bn"),
+    ("bul", "bg"),
+    ("cat", "ca"),
+    ("ces", "cs"),
+    ("cmn", "zh"),
+    ("dan", "da"),
+    ("deu", "de"),
+    ("ell", "el"),
+    ("eng", "en"),
+    ("epo", "eo"),
+    ("est", "et"),
+    ("fin", "fi"),
+    ("fra", "fr"),
+    ("guj", "gu"),
+    ("heb", "he"),
+    ("hin", "hi"),
+    ("hrv", "hr"),
+    ("hun", "hu"),
+    ("hye", "hy"),
+    ("ind", "id"),
+    ("ita", "it"),
+    ("jav", "jv"),
+    ("jpn", "ja"),
+    ("kan", "kn"),
+    ("kat", "ka"),
+    ("khm", "km"),
+    ("kor", "ko"),
+    ("lat", "la"),
+    ("lav", "lv"),
+    ("lit", "lt"),
+    ("mal", "ml"),
+    ("mar", "mr"),
+    ("mkd", "mk"),
+    ("mya", "my"),
+    ("nep", "ne"),
+    ("nld", "nl"),
+    ("nob", "nb"),
+    ("ori", "or"),
+    ("pan", "pa"),
+    ("pes", "fa"),
+    ("pol", "pl"),
+    ("por", "pt"),
+    ("ron", "ro"),
+    ("rus", "ru"),
+    ("sin", "si"),
+    ("slk", "sk"),
+    ("slv", "sl"),
+    ("sna", "sn"),
+    ("spa", "es"),
+    ("srp", "sr"),
+    ("swe", "sv"),
+    ("tam", "ta"),
+    ("tel", "te"),
+    ("tgl", "tl"),
+    ("tha", "th"),
+    ("tuk", "tk"),
+    ("tur", "tr"),
+    ("ukr", "uk"),
+    ("urd", "ur"),
+    ("uzb", "uz"),
+    ("vie", "vi"),
+    ("yid", "yi"),
+    ("zul", "zu"),
+];
+
+/// Min. number of characters needed before we trust the detected language.
+const MIN_DETECT_LENGTH: usize = 32;
+
+/// Normalize a language tag (e.g. `en-US`, `EN`, `eng`) to the code stored in
+/// the index. `None` if it isn't a language tag at all.
+pub fn normalize(tag: &str) -> Option<String> {
+    let primary = tag
+        .trim()
+        .split(|c| c == '-' || c == '_')
+        .next()?
+        .to_lowercase();
+
+    if !(2..=3).contains(&primary.len()) || !primary.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let code = ISO_639_1
+        .iter()
+        .find(|(iso3, _)| *iso3 == primary)
+        .map(|(_, iso1)| iso1.to_string())
+        .unwrap_or(primary);
+
+    Some(code)
+}
+
+/// Language of a page. The `lang` attribute set by the page wins, otherwise
+/// it's guessed from the text content.
+pub fn detect(declared: Option<&str>, content: &str) -> Option<String> {
+    if let Some(code) = declared.and_then(normalize) {
+        return Some(code);
+    }
+
+    if content.chars().take(MIN_DETECT_LENGTH).count() < MIN_DETECT_LENGTH {
+        return None;
+    }
+
+    let info = whatlang::detect(content)?;
+    if !info.is_reliable() {
+        return None;
+    }
+
+    normalize(info.lang().code())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{detect, normalize};
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("en"), Some("en".into()));
+        assert_eq!(normalize("en-US"), Some("en".into()));
+        assert_eq!(normalize("DE_at"), Some("de".into()));
+        assert_eq!(normalize("deu"), Some("de".into()));
+        assert_eq!(normalize("haw"), Some("haw".into()));
+        assert_eq!(normalize(""), None);
+        assert_eq!(normalize("english"), None);
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(Some("de-DE"), "Anything at all"), Some("de".into()));
+        assert_eq!(
+            detect(
+                None,
+                "Der schnelle braune Fuchs springt über den faulen Hund, \
+                 während die Katze auf dem Dach in der Sonne schläft."
+            ),
+            Some("de".into())
+        );
+        assert_eq!(
+            detect(
+                None,
+                "The quick brown fox jumps over the lazy dog while the cat \
+                 sleeps in the sun on the roof."
+            ),
+            Some("en".into())
+        );
+        // Not enough to go on.
+        assert_eq!(detect(None, "Hallo"), None);
+    }
+}
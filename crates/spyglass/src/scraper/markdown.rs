@@ -0,0 +1,52 @@
+//! Markdown documents, e.g. READMEs & docs served straight from a repo.
+use pulldown_cmark::{html, Event, Parser, Tag};
+
+use super::{html_to_text, ScrapeResult};
+
+/// Text of the first heading, used as the title.
+fn first_heading(markdown: &str) -> Option<String> {
+    let mut heading: Option<String> = None;
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::Heading(..)) => heading = Some(String::new()),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = heading.as_mut() {
+                    heading.push_str(&text);
+                }
+            }
+            Event::End(Tag::Heading(..)) => break,
+            _ => {}
+        }
+    }
+
+    heading
+        .map(|heading| heading.trim().to_string())
+        .filter(|heading| !heading.is_empty())
+}
+
+/// Render the document to HTML & scrape that, so links are picked up too.
+pub fn parse(body: &[u8]) -> anyhow::Result<ScrapeResult> {
+    let markdown = String::from_utf8_lossy(body);
+
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, Parser::new(&markdown));
+
+    let mut result = html_to_text(&rendered);
+    result.title = first_heading(&markdown);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+
+    #[test]
+    fn test_parse() {
+        let markdown =
+            "Intro\n\n# Getting `spyglass` running\n\nSee the [docs](/docs/install.md).\n";
+        let result = parse(markdown.as_bytes()).unwrap();
+        assert_eq!(result.title, Some("Getting spyglass running".to_string()));
+        assert!(result.content.contains("See the docs"));
+        assert!(result.links.contains("/docs/install.md"));
+    }
+}
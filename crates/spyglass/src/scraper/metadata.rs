@@ -0,0 +1,131 @@
+//! OpenGraph & schema.org metadata, used to show more than a title &
+//! description for a result.
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde_json::Value;
+use shared::response::PageMetadata;
+
+/// Pull the page metadata out of its meta tags & any schema.org markup, in
+/// either JSON-LD (`json_ld`) or microdata (`item_types`) form.
+pub fn extract(
+    meta: &HashMap<String, String>,
+    json_ld: &[String],
+    item_types: &[String],
+) -> PageMetadata {
+    let objects: Vec<Value> = json_ld
+        .iter()
+        .filter_map(|script| serde_json::from_str(script).ok())
+        .flat_map(flatten)
+        .collect();
+
+    let ld_types = objects.iter().flat_map(|obj| match obj.get("@type") {
+        Some(Value::String(schema_type)) => vec![schema_type.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(|t| t.as_str()).collect(),
+        _ => Vec::new(),
+    });
+    let micro_types = item_types.iter().flat_map(|types| types.split_whitespace());
+
+    let mut schema_types: Vec<String> = Vec::new();
+    for name in ld_types.chain(micro_types).filter_map(type_name) {
+        if !schema_types.contains(&name) {
+            schema_types.push(name);
+        }
+    }
+
+    let published_at = meta
+        .get("article:published_time")
+        .and_then(|date| parse_date(date))
+        .or_else(|| {
+            objects
+                .iter()
+                .find_map(|obj| parse_date(obj.get("datePublished")?.as_str()?))
+        });
+
+    let tag = |name: &str| {
+        meta.get(name)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    PageMetadata {
+        og_title: tag("og:title"),
+        og_description: tag("og:description"),
+        og_image: tag("og:image"),
+        published_at: published_at.map(|at| at.to_rfc3339()),
+        schema_types,
+    }
+}
+
+/// JSON-LD can be a single object, a list of them or a `@graph` of them.
+fn flatten(value: Value) -> Vec<Value> {
+    match value {
+        Value::Array(items) => items.into_iter().flat_map(flatten).collect(),
+        Value::Object(mut obj) => match obj.remove("@graph") {
+            Some(graph) => flatten(graph),
+            None => vec![Value::Object(obj)],
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// `https://schema.org/Article` -> `Article`, types from other vocabularies
+/// are skipped.
+fn type_name(schema_type: &str) -> Option<String> {
+    let name = match schema_type.trim().rsplit_once('/') {
+        Some((vocab, name)) if vocab.ends_with("schema.org") => name,
+        Some(_) => return None,
+        None => schema_type.trim(),
+    };
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Dates are usually RFC 3339, but plenty of pages leave off the time.
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    let date = date.trim();
+    DateTime::parse_from_rfc3339(date)
+        .map(|at| at.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            let day = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+            Some(Utc.from_utc_datetime(&day.and_hms_opt(0, 0, 0)?))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::extract;
+
+    #[test]
+    fn test_extract() {
+        let meta = HashMap::from([
+            ("og:title".to_string(), "Why YC".to_string()),
+            ("og:image".to_string(), " ".to_string()),
+        ]);
+        let json_ld = vec![
+            r#"{"@context": "https://schema.org", "@graph": [
+                {"@type": "Article", "datePublished": "2009-08-01"},
+                {"@type": ["Person", "https://example.com/Founder"]}
+            ]}"#
+            .to_string(),
+            "not json".to_string(),
+        ];
+        let item_types = vec!["https://schema.org/Article http://schema.org/Review".to_string()];
+
+        let metadata = extract(&meta, &json_ld, &item_types);
+        assert_eq!(metadata.og_title, Some("Why YC".to_string()));
+        assert_eq!(metadata.og_image, None);
+        assert_eq!(
+            metadata.published_at,
+            Some("2009-08-01T00:00:00+00:00".to_string())
+        );
+        assert_eq!(metadata.schema_types, vec!["Article", "Person", "Review"]);
+    }
+}
@@ -1,17 +1,78 @@
 #![allow(dead_code)]
 
+mod docx;
 mod element;
+mod epub;
 mod html;
+pub mod lang;
+mod markdown;
+pub mod metadata;
+pub mod parser;
+mod pdf;
 
 use ego_tree::NodeRef;
+use html5ever::driver::Parser;
 use html5ever::QualName;
+use shared::response::PageMetadata;
 use std::collections::{HashMap, HashSet};
+use tendril::stream::Utf8LossyDecoder;
+use tendril::{ByteTendril, TendrilSink};
 use url::Url;
 
 use crate::scraper::element::Node;
 use crate::scraper::html::Html;
 
 const DEFAULT_DESC_LENGTH: usize = 256;
+/// Max number of bytes we'll read from a page before giving up on it.
+pub const MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+/// Max length of extracted text content, anything past this is dropped.
+pub const MAX_CONTENT_LENGTH: usize = 512 * 1024;
+
+/// Incrementally parses an HTML document as chunks are received, enforcing a
+/// hard cap on the number of bytes read so huge pages are never fully buffered.
+pub struct StreamingScraper {
+    parser: Utf8LossyDecoder<Parser<Html>>,
+    bytes_read: usize,
+    max_bytes: usize,
+}
+
+impl Default for StreamingScraper {
+    fn default() -> Self {
+        Self::new(MAX_BODY_BYTES)
+    }
+}
+
+impl StreamingScraper {
+    pub fn new(max_bytes: usize) -> Self {
+        StreamingScraper {
+            parser: Html::streaming_parser(),
+            bytes_read: 0,
+            max_bytes,
+        }
+    }
+
+    pub fn bytes_read(&self) -> usize {
+        self.bytes_read
+    }
+
+    /// Feed the next chunk of the document to the parser.
+    pub fn feed(&mut self, chunk: &[u8]) -> anyhow::Result<()> {
+        self.bytes_read += chunk.len();
+        if self.bytes_read > self.max_bytes {
+            return Err(anyhow::anyhow!(
+                "page exceeds max size of {} bytes",
+                self.max_bytes
+            ));
+        }
+
+        self.parser.process(ByteTendril::from_slice(chunk));
+        Ok(())
+    }
+
+    pub fn finish(self) -> ScrapeResult {
+        scrape(self.parser.finish())
+    }
+}
 
 #[derive(Debug)]
 pub struct ScrapeResult {
@@ -23,6 +84,10 @@ pub struct ScrapeResult {
     pub links: HashSet<String>,
     /// Index should use this URL instead of the one that lead to the content.
     pub canonical_url: Option<Url>,
+    /// Language the page is written in, see `lang::detect`.
+    pub lang: Option<String>,
+    /// OpenGraph & schema.org metadata, see `metadata::extract`.
+    pub metadata: PageMetadata,
 }
 
 /// Walk the DOM and grab all the p nodes
@@ -72,6 +137,11 @@ fn filter_text_nodes(root: &NodeRef<Node>, doc: &mut String, links: &mut HashSet
     let role_key = QualName::new(None, ns!(), local_name!("role"));
 
     for child in root.children() {
+        // Stop extracting once we've hit the content cap.
+        if doc.len() >= MAX_CONTENT_LENGTH {
+            return;
+        }
+
         let node = child.value();
         if node.is_text() {
             doc.push_str(node.as_text().unwrap());
@@ -120,18 +190,119 @@ fn filter_text_nodes(root: &NodeRef<Node>, doc: &mut String, links: &mut HashSet
     }
 }
 
+fn truncate_to_char_boundary(text: &mut String, max_len: usize) {
+    if text.len() <= max_len {
+        return;
+    }
+
+    let mut idx = max_len;
+    while !text.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    text.truncate(idx);
+}
+
+/// First `DEFAULT_DESC_LENGTH` words-ish of the content.
+fn summarize(content: &str) -> String {
+    content
+        .split(' ')
+        .take(DEFAULT_DESC_LENGTH)
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Builds the result for formats that boil down to text & some metadata, e.g.
+/// PDFs. The title & author are pulled from `meta`.
+fn text_result(text: &str, meta: HashMap<String, String>) -> ScrapeResult {
+    // Collapse line breaks & indentation like we do for HTML.
+    let mut content = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    truncate_to_char_boundary(&mut content, MAX_CONTENT_LENGTH);
+
+    ScrapeResult {
+        title: meta.get("title").cloned(),
+        description: summarize(&content),
+        lang: lang::detect(None, &content),
+        content,
+        meta,
+        links: HashSet::new(),
+        canonical_url: None,
+        metadata: PageMetadata::default(),
+    }
+}
+
+/// Replace the predefined XML entities.
+pub fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
 /// Filters a DOM tree into a text document used for indexing
 pub fn html_to_text(doc: &str) -> ScrapeResult {
-    let parsed = Html::parse(doc);
+    scrape(Html::parse(doc))
+}
+
+/// Start of the next `<script>` tag at or after `from`, skipping tags that
+/// only share the prefix, e.g. `<scripts>`.
+fn find_script_tag(lower: &str, from: usize) -> Option<usize> {
+    let mut from = from;
+    while let Some(idx) = lower[from..].find("<script") {
+        let start = from + idx;
+        let rest = &lower[start + "<script".len()..];
+        if rest.is_empty() || rest.starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace())
+        {
+            return Some(start);
+        }
+        from = start + "<script".len();
+    }
+
+    None
+}
+
+/// Drops the parts of a page that aren't needed to show a cached copy, i.e.
+/// scripts & comments, so they don't take up space in the index.
+pub fn strip_for_storage(html: &str) -> String {
+    // ASCII lowercasing keeps byte offsets the same as the original.
+    let lower = html.to_ascii_lowercase();
+    let mut stripped = String::with_capacity(html.len());
+
+    let mut pos = 0;
+    loop {
+        let script = find_script_tag(&lower, pos);
+        let comment = lower[pos..].find("<!--").map(|idx| pos + idx);
+        let (start, end_tag) = match (script, comment) {
+            (Some(script), Some(comment)) if comment < script => (comment, "-->"),
+            (Some(script), _) => (script, "</script>"),
+            (None, Some(comment)) => (comment, "-->"),
+            (None, None) => break,
+        };
+
+        stripped.push_str(&html[pos..start]);
+        // Unterminated, a browser would ignore the rest of the page too.
+        pos = match lower[start..].find(end_tag) {
+            Some(idx) => start + idx + end_tag.len(),
+            None => html.len(),
+        };
+    }
+
+    stripped.push_str(&html[pos..]);
+    stripped
+}
+
+fn scrape(parsed: Html) -> ScrapeResult {
     let root = parsed.tree.root();
     // Meta tags
     let meta = parsed.meta();
     let link_tags = parsed.link_tags();
+    let metadata = metadata::extract(&meta, &parsed.json_ld(), &parsed.item_types());
     // Content
     let title = parsed.title();
     let mut content = String::from("");
     let mut links = HashSet::new();
     filter_text_nodes(&root, &mut content, &mut links);
+    truncate_to_char_boundary(&mut content, MAX_CONTENT_LENGTH);
     content = content.trim().to_string();
 
     let mut description = if meta.contains_key("description") {
@@ -152,12 +323,7 @@ pub fn html_to_text(doc: &str) -> ScrapeResult {
             description = text.unwrap_or(&String::from("")).trim().to_owned()
         } else if !content.is_empty() {
             // Still nothing? Grab the first 256 words-ish
-            description = content
-                .split(' ')
-                .into_iter()
-                .take(DEFAULT_DESC_LENGTH)
-                .collect::<Vec<&str>>()
-                .join(" ")
+            description = summarize(&content)
         }
     }
 
@@ -174,19 +340,23 @@ pub fn html_to_text(doc: &str) -> ScrapeResult {
         _ => None,
     };
 
+    let lang = lang::detect(parsed.lang().as_deref(), &content);
+
     ScrapeResult {
         canonical_url,
         content,
         description,
+        lang,
         links,
         meta,
+        metadata,
         title,
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::scraper::html_to_text;
+    use crate::scraper::{html_to_text, strip_for_storage, StreamingScraper, MAX_CONTENT_LENGTH};
 
     #[test]
     fn test_html_to_text() {
@@ -223,4 +393,46 @@ mod test {
         assert_eq!(doc.title.unwrap(), "Why YC");
         assert_eq!(doc.description, "March 2006, rev August 2009 Yesterday one of the founders we funded asked me why we started Y Combinator.  Or more precisely, he asked if we'd started YC mainly for fun. Kind of, but not quite.  It is enormously fun to be able to work with Rtm and Trevor again.  I missed that after we sold Viaweb, and for all the years after I always had a background process running, looking for something we could do together.  There is definitely an aspect of a band reunion to Y Combinator.  Every couple days I slip and call it \"Viaweb.\" Viaweb we started very explicitly to make money.  I was sick of living from one freelance project to the next, and decided to just work as hard as I could till I'd made enough to solve the problem once and for all.  Viaweb was sometimes fun, but it wasn't designed for fun, and mostly it wasn't.  I'd be surprised if any startup is. All startups are mostly schleps. The real reason we started Y Combinator is neither selfish nor virtuous.  We didn't start it mainly to make money; we have no idea what our average returns might be, and won't know for years.  Nor did we start YC mainly to help out young would-be founders, though we do like the idea, and comfort ourselves occasionally with the thought that if all our investments tank, we will thus have been doing something unselfish.  (It's oddly nondeterministic.) The real");
     }
+
+    #[test]
+    fn test_streaming_scraper() {
+        let html = include_str!("../../../../fixtures/html/raw.html");
+        let mut scraper = StreamingScraper::default();
+        for chunk in html.as_bytes().chunks(1024) {
+            scraper.feed(chunk).unwrap();
+        }
+
+        let doc = scraper.finish();
+        let expected = html_to_text(html);
+        assert_eq!(doc.title, expected.title);
+        assert_eq!(doc.content, expected.content);
+        assert_eq!(doc.links, expected.links);
+    }
+
+    #[test]
+    fn test_size_caps() {
+        let mut scraper = StreamingScraper::new(10);
+        assert!(scraper.feed(b"<html>").is_ok());
+        assert!(scraper.feed(b"<body></body></html>").is_err());
+
+        let html = format!(
+            "<html><body><p>{}</p></body></html>",
+            "a".repeat(MAX_CONTENT_LENGTH * 2)
+        );
+        let doc = html_to_text(&html);
+        assert_eq!(doc.content.len(), MAX_CONTENT_LENGTH);
+    }
+
+    #[test]
+    fn test_strip_for_storage() {
+        let html = r#"<html><head><SCRIPT src="a.js"></script><title>Hi</title><!-- tracking --></head><body><scripts>keep</scripts><p>café</p><script>if (a < b) { x = "<!--"; }</script></body></html>"#;
+        assert_eq!(
+            strip_for_storage(html),
+            "<html><head><title>Hi</title></head><body><scripts>keep</scripts><p>café</p></body></html>"
+        );
+
+        assert_eq!(strip_for_storage("<p>a</p><!-- unterminated"), "<p>a</p>");
+        assert_eq!(strip_for_storage("no tags"), "no tags");
+        assert_eq!(strip_for_storage(""), "");
+    }
 }
@@ -0,0 +1,246 @@
+//! Picks the parser for a document based on its content type or, for servers
+//! that don't send a useful one, its file extension.
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use url::Url;
+use zip::ZipArchive;
+
+use super::{docx, epub, markdown, pdf, text_result, unescape_xml, ScrapeResult, StreamingScraper};
+
+/// Parses a document body into text for indexing.
+pub type ParseFn = fn(&[u8]) -> anyhow::Result<ScrapeResult>;
+
+pub struct Parser {
+    pub name: &'static str,
+    /// Content types handled, w/o any parameters (e.g. `; charset=utf-8`).
+    pub content_types: &'static [&'static str],
+    /// File extensions handled, w/o the leading dot.
+    pub extensions: &'static [&'static str],
+    pub parse: ParseFn,
+}
+
+/// Max size of a file in a zip based format once decompressed, so a zip bomb
+/// can't use up all the memory.
+const MAX_ZIP_ENTRY_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Plain text is also what servers tend to send for formats they don't know
+/// about, so it's only used if nothing more specific matches.
+const TEXT: Parser = Parser {
+    name: "text",
+    content_types: &["text/plain"],
+    extensions: &["txt", "text", "log"],
+    parse: parse_text,
+};
+
+const HTML: Parser = Parser {
+    name: "html",
+    content_types: &["text/html", "application/xhtml+xml"],
+    extensions: &["html", "htm", "xhtml"],
+    parse: parse_html,
+};
+
+const PDF: Parser = Parser {
+    name: "pdf",
+    content_types: &["application/pdf"],
+    extensions: &["pdf"],
+    parse: pdf::parse,
+};
+
+static PARSERS: [Parser; 6] = [
+    HTML,
+    PDF,
+    Parser {
+        name: "markdown",
+        content_types: &["text/markdown", "text/x-markdown"],
+        extensions: &["md", "markdown"],
+        parse: markdown::parse,
+    },
+    Parser {
+        name: "docx",
+        content_types: &["application/vnd.openxmlformats-officedocument.wordprocessingml.document"],
+        extensions: &["docx"],
+        parse: docx::parse,
+    },
+    Parser {
+        name: "epub",
+        content_types: &["application/epub+zip"],
+        extensions: &["epub"],
+        parse: epub::parse,
+    },
+    TEXT,
+];
+
+fn parse_html(body: &[u8]) -> anyhow::Result<ScrapeResult> {
+    let mut scraper = StreamingScraper::default();
+    scraper.feed(body)?;
+    Ok(scraper.finish())
+}
+
+fn parse_text(body: &[u8]) -> anyhow::Result<ScrapeResult> {
+    Ok(text_result(&String::from_utf8_lossy(body), HashMap::new()))
+}
+
+/// Find the parser for a document. `body` is used to sniff out PDFs served
+/// w/ a generic content type & may be empty if it's not fetched yet. Returns
+/// `None` if the format isn't supported.
+pub fn find(content_type: Option<&str>, url: &Url, body: &[u8]) -> Option<&'static Parser> {
+    let content_type = content_type.map(|content_type| {
+        content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase()
+    });
+
+    let by_type = content_type.as_ref().and_then(|content_type| {
+        PARSERS
+            .iter()
+            .find(|parser| parser.content_types.contains(&content_type.as_str()))
+    });
+    if let Some(parser) = by_type.filter(|parser| parser.name != TEXT.name) {
+        return Some(parser);
+    }
+
+    let extension = Path::new(url.path())
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    let by_extension = extension.and_then(|ext| {
+        PARSERS
+            .iter()
+            .find(|parser| parser.extensions.contains(&ext.as_str()))
+    });
+
+    by_extension.or(by_type).or_else(|| {
+        if body.starts_with(b"%PDF-") {
+            Some(&PDF)
+        } else if content_type.is_none() {
+            // Assume it's a page, which is what we've always done.
+            Some(&HTML)
+        } else {
+            None
+        }
+    })
+}
+
+/// Read a file from a zip based format, e.g. docx & epub. Entries larger than
+/// `MAX_ZIP_ENTRY_BYTES` once decompressed are rejected.
+pub(super) fn read_zip_entry(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    name: &str,
+) -> anyhow::Result<String> {
+    let mut contents = Vec::new();
+    archive
+        .by_name(name)?
+        .take(MAX_ZIP_ENTRY_BYTES + 1)
+        .read_to_end(&mut contents)?;
+    if contents.len() as u64 > MAX_ZIP_ENTRY_BYTES {
+        return Err(anyhow::anyhow!(
+            "{} is larger than {} bytes",
+            name,
+            MAX_ZIP_ENTRY_BYTES
+        ));
+    }
+
+    Ok(String::from_utf8(contents)?)
+}
+
+/// Opening tags, w/ the tag name as the first group.
+static OPEN_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<([\w:.-]+)(?:\s[^>]*)?>").expect("Invalid tag regex"));
+
+/// Text of the first `<tag>` element in an XML document, e.g. `dc:title`.
+pub(super) fn xml_tag(xml: &str, tag: &str) -> Option<String> {
+    let close = format!("</{}>", tag);
+    OPEN_TAG
+        .captures_iter(xml)
+        .filter(|cap| &cap[1] == tag)
+        .find_map(|cap| {
+            let start = cap.get(0)?.end();
+            let end = xml[start..].find(&close)?;
+            Some(&xml[start..start + end])
+        })
+        .map(|text| unescape_xml(text.trim()))
+        .filter(|text| !text.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use url::Url;
+
+    use std::io::{Cursor, Write};
+
+    use zip::write::{FileOptions, ZipWriter};
+    use zip::ZipArchive;
+
+    use super::{find, read_zip_entry, xml_tag, MAX_ZIP_ENTRY_BYTES};
+
+    fn parser_for(content_type: Option<&str>, url: &str, body: &[u8]) -> Option<&'static str> {
+        let url = Url::parse(url).unwrap();
+        find(content_type, &url, body).map(|parser| parser.name)
+    }
+
+    #[test]
+    fn test_find() {
+        let page = "https://example.com/docs/";
+        assert_eq!(
+            parser_for(Some("text/html; charset=utf-8"), page, b""),
+            Some("html")
+        );
+        assert_eq!(parser_for(None, page, b""), Some("html"));
+        assert_eq!(parser_for(Some("application/pdf"), page, b""), Some("pdf"));
+        assert_eq!(parser_for(Some("image/png"), page, b""), None);
+
+        // Generic content types fall back to the extension or file signature
+        let readme = "https://example.com/README.md";
+        assert_eq!(
+            parser_for(Some("text/plain"), readme, b""),
+            Some("markdown")
+        );
+        let notes = "https://example.com/notes.txt";
+        assert_eq!(parser_for(Some("text/plain"), notes, b""), Some("text"));
+        let book = "https://example.com/book.EPUB";
+        assert_eq!(
+            parser_for(Some("application/octet-stream"), book, b""),
+            Some("epub")
+        );
+        let download = "https://example.com/download?id=1";
+        let octet_stream = Some("application/octet-stream");
+        assert_eq!(
+            parser_for(octet_stream, download, b"%PDF-1.7\n"),
+            Some("pdf")
+        );
+        assert_eq!(parser_for(octet_stream, download, b""), None);
+    }
+
+    #[test]
+    fn test_xml_tag() {
+        let xml = r#"<metadata><dc:title id="t">Rust &amp; You</dc:title><dc:creator/></metadata>"#;
+        assert_eq!(xml_tag(xml, "dc:title"), Some("Rust & You".to_string()));
+        assert_eq!(xml_tag(xml, "dc:creator"), None);
+    }
+
+    #[test]
+    fn test_read_zip_entry() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("small.xml", FileOptions::default()).unwrap();
+        zip.write_all(b"<doc/>").unwrap();
+        // Compresses down to almost nothing
+        zip.start_file("bomb.xml", FileOptions::default()).unwrap();
+        let zeros = vec![0; 1024 * 1024];
+        for _ in 0..=MAX_ZIP_ENTRY_BYTES / zeros.len() as u64 {
+            zip.write_all(&zeros).unwrap();
+        }
+        let body = zip.finish().unwrap().into_inner();
+
+        let mut archive = ZipArchive::new(Cursor::new(body.as_slice())).unwrap();
+        assert_eq!(read_zip_entry(&mut archive, "small.xml").unwrap(), "<doc/>");
+        assert!(read_zip_entry(&mut archive, "bomb.xml").is_err());
+        assert!(read_zip_entry(&mut archive, "missing.xml").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+//! Text & metadata extraction for PDFs, which are indexed like any other page.
+use std::collections::HashMap;
+
+use lopdf::{Document, Object};
+
+use super::{text_result, ScrapeResult};
+
+/// Text strings in a PDF are either UTF-16BE w/ a BOM or PDFDocEncoding, which
+/// is close enough to Latin-1 for our purposes.
+fn decode_text(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xfe, 0xff]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        bytes.iter().map(|&b| b as char).collect()
+    }
+}
+
+/// Title, author, etc. from the document information dictionary.
+fn info(doc: &Document) -> HashMap<String, String> {
+    let mut info = HashMap::new();
+
+    let dict = match doc.trailer.get(b"Info") {
+        Ok(Object::Reference(id)) => doc.get_object(*id).and_then(|obj| obj.as_dict()),
+        Ok(obj) => obj.as_dict(),
+        Err(err) => Err(err),
+    };
+
+    if let Ok(dict) = dict {
+        for (key, name) in [(&b"Title"[..], "title"), (&b"Author"[..], "author")] {
+            if let Ok(Object::String(bytes, _)) = dict.get(key) {
+                let value = decode_text(bytes).trim().to_string();
+                if !value.is_empty() {
+                    info.insert(name.to_string(), value);
+                }
+            }
+        }
+    }
+
+    info
+}
+
+/// Extract the text & metadata from a PDF.
+pub fn parse(body: &[u8]) -> anyhow::Result<ScrapeResult> {
+    let doc = Document::load_mem(body)?;
+
+    // Page by page so one malformed page doesn't lose the rest of the text.
+    let mut text = String::new();
+    for page in doc.get_pages().into_keys() {
+        match doc.extract_text(&[page]) {
+            Ok(page_text) => text.push_str(&page_text),
+            Err(err) => log::debug!("Unable to extract text from page {}: {}", page, err),
+        }
+    }
+
+    Ok(text_result(&text, info(&doc)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::decode_text;
+
+    #[test]
+    fn test_decode_text() {
+        assert_eq!(decode_text(b"User Guide"), "User Guide");
+        assert_eq!(
+            decode_text(&[0xfe, 0xff, 0x00, 0x47, 0x00, 0x75, 0x00, 0xef, 0x00, 0x64, 0x00, 0x65]),
+            "Guïde"
+        );
+    }
+}
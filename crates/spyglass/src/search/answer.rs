@@ -0,0 +1,507 @@
+/// Instant answers for launcher-style queries such as math expressions
+/// ("2 * (3 + 4)"), unit conversions ("10 km to mi") & unix timestamps.
+use chrono::{TimeZone, Utc};
+use shared::response::{InlineAnswer, InlineAnswerType};
+
+/// Attempt to generate an inline answer for the query, if any.
+pub fn evaluate(query: &str) -> Option<InlineAnswer> {
+    let query = query.trim();
+    if query.is_empty() {
+        return None;
+    }
+
+    if let Some(answer) = eval_timestamp(query) {
+        return Some(InlineAnswer {
+            answer_type: InlineAnswerType::Timestamp,
+            query: query.to_string(),
+            answer,
+        });
+    }
+
+    if let Some(answer) = eval_conversion(query) {
+        return Some(InlineAnswer {
+            answer_type: InlineAnswerType::Conversion,
+            query: query.to_string(),
+            answer,
+        });
+    }
+
+    if let Some(value) = eval_math(query) {
+        return Some(InlineAnswer {
+            answer_type: InlineAnswerType::Math,
+            query: query.to_string(),
+            answer: format_number(value),
+        });
+    }
+
+    None
+}
+
+/// Format a number w/ at most 6 decimal places & no trailing zeros.
+fn format_number(value: f64) -> String {
+    let formatted = format!("{:.6}", value);
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+    if formatted == "-0" {
+        "0".to_string()
+    } else {
+        formatted.to_string()
+    }
+}
+
+// --------------------------------------------------------------------------------
+// Timestamps
+// --------------------------------------------------------------------------------
+
+/// Treat 10 digit numbers as a unix timestamp in seconds & 13 digit numbers
+/// as a unix timestamp in milliseconds.
+fn eval_timestamp(query: &str) -> Option<String> {
+    if !query.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let value: i64 = query.parse().ok()?;
+    let datetime = match query.len() {
+        10 => Utc.timestamp_opt(value, 0).single()?,
+        13 => Utc.timestamp_millis_opt(value).single()?,
+        _ => return None,
+    };
+
+    Some(datetime.to_rfc2822())
+}
+
+// --------------------------------------------------------------------------------
+// Unit conversions
+// --------------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Dimension {
+    Data,
+    Length,
+    Mass,
+    Temperature,
+}
+
+struct Unit {
+    names: &'static [&'static str],
+    dimension: Dimension,
+    /// Multiplier to convert into the base unit for this dimension. Unused for
+    /// temperatures.
+    factor: f64,
+}
+
+const UNITS: &[Unit] = &[
+    // Length, base unit is meters
+    Unit {
+        names: &["mm", "millimeter", "millimeters"],
+        dimension: Dimension::Length,
+        factor: 0.001,
+    },
+    Unit {
+        names: &["cm", "centimeter", "centimeters"],
+        dimension: Dimension::Length,
+        factor: 0.01,
+    },
+    Unit {
+        names: &["m", "meter", "meters"],
+        dimension: Dimension::Length,
+        factor: 1.0,
+    },
+    Unit {
+        names: &["km", "kilometer", "kilometers"],
+        dimension: Dimension::Length,
+        factor: 1000.0,
+    },
+    Unit {
+        names: &["in", "inch", "inches"],
+        dimension: Dimension::Length,
+        factor: 0.0254,
+    },
+    Unit {
+        names: &["ft", "foot", "feet"],
+        dimension: Dimension::Length,
+        factor: 0.3048,
+    },
+    Unit {
+        names: &["yd", "yard", "yards"],
+        dimension: Dimension::Length,
+        factor: 0.9144,
+    },
+    Unit {
+        names: &["mi", "mile", "miles"],
+        dimension: Dimension::Length,
+        factor: 1609.344,
+    },
+    // Mass, base unit is grams
+    Unit {
+        names: &["mg", "milligram", "milligrams"],
+        dimension: Dimension::Mass,
+        factor: 0.001,
+    },
+    Unit {
+        names: &["g", "gram", "grams"],
+        dimension: Dimension::Mass,
+        factor: 1.0,
+    },
+    Unit {
+        names: &["kg", "kilogram", "kilograms"],
+        dimension: Dimension::Mass,
+        factor: 1000.0,
+    },
+    Unit {
+        names: &["oz", "ounce", "ounces"],
+        dimension: Dimension::Mass,
+        factor: 28.349523125,
+    },
+    Unit {
+        names: &["lb", "lbs", "pound", "pounds"],
+        dimension: Dimension::Mass,
+        factor: 453.59237,
+    },
+    // Data, base unit is bytes
+    Unit {
+        names: &["b", "byte", "bytes"],
+        dimension: Dimension::Data,
+        factor: 1.0,
+    },
+    Unit {
+        names: &["kb", "kilobyte", "kilobytes"],
+        dimension: Dimension::Data,
+        factor: 1024.0,
+    },
+    Unit {
+        names: &["mb", "megabyte", "megabytes"],
+        dimension: Dimension::Data,
+        factor: 1024.0 * 1024.0,
+    },
+    Unit {
+        names: &["gb", "gigabyte", "gigabytes"],
+        dimension: Dimension::Data,
+        factor: 1024.0 * 1024.0 * 1024.0,
+    },
+    Unit {
+        names: &["tb", "terabyte", "terabytes"],
+        dimension: Dimension::Data,
+        factor: 1024.0 * 1024.0 * 1024.0 * 1024.0,
+    },
+    // Temperatures are handled separately
+    Unit {
+        names: &["c", "celsius"],
+        dimension: Dimension::Temperature,
+        factor: 1.0,
+    },
+    Unit {
+        names: &["f", "fahrenheit"],
+        dimension: Dimension::Temperature,
+        factor: 1.0,
+    },
+    Unit {
+        names: &["k", "kelvin"],
+        dimension: Dimension::Temperature,
+        factor: 1.0,
+    },
+];
+
+fn find_unit(name: &str) -> Option<&'static Unit> {
+    let name = name.to_lowercase();
+    UNITS
+        .iter()
+        .find(|unit| unit.names.contains(&name.as_str()))
+}
+
+fn to_celsius(value: f64, unit: &Unit) -> f64 {
+    match unit.names[0] {
+        "f" => (value - 32.0) * 5.0 / 9.0,
+        "k" => value - 273.15,
+        _ => value,
+    }
+}
+
+fn from_celsius(value: f64, unit: &Unit) -> f64 {
+    match unit.names[0] {
+        "f" => value * 9.0 / 5.0 + 32.0,
+        "k" => value + 273.15,
+        _ => value,
+    }
+}
+
+/// Parses queries in the form of "<value> <unit> to|in <unit>"
+fn eval_conversion(query: &str) -> Option<String> {
+    let query = query.to_lowercase();
+    let (src, dst) = query
+        .split_once(" to ")
+        .or_else(|| query.split_once(" in "))?;
+
+    let src = src.trim();
+    let dst_name = dst.trim();
+
+    // Split the value from the unit, e.g. "10km" or "10 km"
+    let split_idx = src.find(|c: char| c.is_alphabetic())?;
+    let (value, src_name) = src.split_at(split_idx);
+    let value: f64 = value.trim().parse().ok()?;
+
+    let src_unit = find_unit(src_name.trim())?;
+    let dst_unit = find_unit(dst_name)?;
+    if src_unit.dimension != dst_unit.dimension {
+        return None;
+    }
+
+    let converted = if src_unit.dimension == Dimension::Temperature {
+        from_celsius(to_celsius(value, src_unit), dst_unit)
+    } else {
+        value * src_unit.factor / dst_unit.factor
+    };
+
+    Some(format!(
+        "{} {} = {} {}",
+        format_number(value),
+        src_unit.names[0],
+        format_number(converted),
+        dst_unit.names[0]
+    ))
+}
+
+// --------------------------------------------------------------------------------
+// Math expressions
+// --------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Num(f64),
+    Op(char),
+    LParen,
+    RParen,
+}
+
+fn tokenize(query: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = query.chars().collect();
+
+    let mut idx = 0;
+    while idx < chars.len() {
+        let c = chars[idx];
+        match c {
+            ' ' => {}
+            '0'..='9' | '.' => {
+                let start = idx;
+                while idx + 1 < chars.len()
+                    && (chars[idx + 1].is_ascii_digit() || chars[idx + 1] == '.')
+                {
+                    idx += 1;
+                }
+
+                let num: String = chars[start..=idx].iter().collect();
+                tokens.push(Token::Num(num.parse().ok()?));
+            }
+            '+' | '-' | '*' | '/' | '^' | '%' => tokens.push(Token::Op(c)),
+            'x' | '×' => tokens.push(Token::Op('*')),
+            '÷' => tokens.push(Token::Op('/')),
+            '(' => tokens.push(Token::LParen),
+            ')' => tokens.push(Token::RParen),
+            _ => return None,
+        }
+        idx += 1;
+    }
+
+    Some(tokens)
+}
+
+/// Max levels of parens, exponents, & negations the parser descends into.
+const MAX_EXPR_DEPTH: usize = 32;
+
+/// Simple recursive descent parser for arithmetic expressions.
+struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl ExprParser {
+    /// Runs `parse` one level deeper, `None` past `MAX_EXPR_DEPTH` so deeply
+    /// nested input can't overflow the stack.
+    fn nested(&mut self, parse: fn(&mut Self) -> Option<f64>) -> Option<f64> {
+        if self.depth >= MAX_EXPR_DEPTH {
+            return None;
+        }
+
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Option<f64> {
+        let mut value = self.term()?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            if op != '+' && op != '-' {
+                break;
+            }
+
+            self.next();
+            let rhs = self.term()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+        }
+
+        Some(value)
+    }
+
+    // term := factor (('*' | '/' | '%') factor)*
+    fn term(&mut self) -> Option<f64> {
+        let mut value = self.factor()?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            if op != '*' && op != '/' && op != '%' {
+                break;
+            }
+
+            self.next();
+            let rhs = self.factor()?;
+            value = match op {
+                '*' => value * rhs,
+                '/' => value / rhs,
+                _ => value % rhs,
+            };
+        }
+
+        Some(value)
+    }
+
+    // factor := unary ('^' factor)?
+    fn factor(&mut self) -> Option<f64> {
+        let base = self.unary()?;
+        if let Some(Token::Op('^')) = self.peek() {
+            self.next();
+            let exp = self.nested(Self::factor)?;
+            return Some(base.powf(exp));
+        }
+
+        Some(base)
+    }
+
+    // unary := '-' unary | atom
+    fn unary(&mut self) -> Option<f64> {
+        if let Some(Token::Op('-')) = self.peek() {
+            self.next();
+            return self.nested(Self::unary).map(|val| -val);
+        }
+
+        self.atom()
+    }
+
+    // atom := number | '(' expr ')'
+    fn atom(&mut self) -> Option<f64> {
+        match self.next()? {
+            Token::Num(num) => Some(num),
+            Token::LParen => {
+                let value = self.nested(Self::expr)?;
+                match self.next()? {
+                    Token::RParen => Some(value),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+fn eval_math(query: &str) -> Option<f64> {
+    let tokens = tokenize(query)?;
+
+    // Only treat this as an expression if there is something to calculate,
+    // otherwise searching for "2022" would return a calculator result.
+    let has_number = tokens.iter().any(|t| matches!(t, Token::Num(_)));
+    let has_op = tokens.iter().any(|t| matches!(t, Token::Op(_)));
+    if !has_number || !has_op {
+        return None;
+    }
+
+    let mut parser = ExprParser {
+        tokens,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.expr()?;
+    // Make sure we've consumed the entire query
+    if parser.pos != parser.tokens.len() || !value.is_finite() {
+        return None;
+    }
+
+    Some(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{eval_conversion, eval_math, eval_timestamp, evaluate};
+    use shared::response::InlineAnswerType;
+
+    #[test]
+    fn test_eval_math() {
+        assert_eq!(eval_math("1 + 2"), Some(3.0));
+        assert_eq!(eval_math("2 * (3 + 4)"), Some(14.0));
+        assert_eq!(eval_math("2^3^2"), Some(512.0));
+        assert_eq!(eval_math("-4 + 10 / 4"), Some(-1.5));
+        assert_eq!(eval_math("10 % 3"), Some(1.0));
+        // Not expressions
+        assert_eq!(eval_math("2022"), None);
+        assert_eq!(eval_math("rust 2021"), None);
+        assert_eq!(eval_math("(1 + 2"), None);
+        assert_eq!(eval_math("1 / 0"), None);
+        // Too deeply nested
+        let nested = |depth: usize| format!("{}1 + 1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(eval_math(&nested(8)), Some(2.0));
+        assert_eq!(eval_math(&nested(10_000)), None);
+        assert_eq!(eval_math(&format!("{}1 + 1", "-".repeat(10_000))), None);
+    }
+
+    #[test]
+    fn test_eval_conversion() {
+        assert_eq!(
+            eval_conversion("10 km to mi"),
+            Some("10 km = 6.213712 mi".to_string())
+        );
+        assert_eq!(
+            eval_conversion("100 C in F"),
+            Some("100 c = 212 f".to_string())
+        );
+        assert_eq!(
+            eval_conversion("2gb to mb"),
+            Some("2 gb = 2048 mb".to_string())
+        );
+        // Mismatched dimensions
+        assert_eq!(eval_conversion("10 km to kg"), None);
+        assert_eq!(eval_conversion("how to cook"), None);
+    }
+
+    #[test]
+    fn test_eval_timestamp() {
+        assert_eq!(
+            eval_timestamp("1650000000"),
+            Some("Fri, 15 Apr 2022 05:20:00 +0000".to_string())
+        );
+        assert_eq!(
+            eval_timestamp("1650000000000"),
+            Some("Fri, 15 Apr 2022 05:20:00 +0000".to_string())
+        );
+        assert_eq!(eval_timestamp("2022"), None);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let answer = evaluate("3 * 3").unwrap();
+        assert_eq!(answer.answer_type, InlineAnswerType::Math);
+        assert_eq!(answer.answer, "9");
+
+        assert!(evaluate("rust programming").is_none());
+    }
+}
@@ -0,0 +1,451 @@
+//! Translates the subset of the Elasticsearch query DSL we support into tantivy
+//! queries so existing tools can query the local index.
+//!
+//! Supported queries: `match_all`, `match`, `match_phrase`, `multi_match`,
+//! `query_string`, `simple_query_string`, `term`, `terms`, `prefix` & `bool`.
+use std::time::Instant;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tantivy::collector::{Count, TopDocs};
+use tantivy::query::{AllQuery, BooleanQuery, Occur, Query, QueryParser, RegexQuery, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Term};
+use tantivy::{Index, IndexReader};
+
+use super::{DocFields, Searcher};
+
+/// Name reported as the `_index` for each hit.
+pub const INDEX_NAME: &str = "spyglass";
+const DEFAULT_SIZE: usize = 10;
+const MAX_SIZE: usize = 1000;
+/// How deep into the results `from` & `size` can reach, same as ES's default
+/// `index.max_result_window`.
+const MAX_RESULT_WINDOW: usize = 10_000;
+/// How deep `bool` queries can be nested.
+const MAX_BOOL_DEPTH: usize = 16;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EsSearchRequest {
+    pub query: Option<Value>,
+    pub size: Option<usize>,
+    pub from: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EsSearchResponse {
+    pub took: u64,
+    pub timed_out: bool,
+    #[serde(rename = "_shards")]
+    pub shards: EsShards,
+    pub hits: EsHits,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EsShards {
+    pub total: u32,
+    pub successful: u32,
+    pub skipped: u32,
+    pub failed: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EsHits {
+    pub total: EsTotal,
+    pub max_score: Option<f32>,
+    pub hits: Vec<EsHit>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EsTotal {
+    pub value: usize,
+    pub relation: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EsHit {
+    #[serde(rename = "_index")]
+    pub index: String,
+    #[serde(rename = "_id")]
+    pub id: String,
+    #[serde(rename = "_score")]
+    pub score: f32,
+    #[serde(rename = "_source")]
+    pub source: EsSource,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EsSource {
+    pub domain: String,
+    pub title: String,
+    pub description: String,
+    pub url: String,
+}
+
+fn field_by_name(fields: &DocFields, name: &str) -> anyhow::Result<Field> {
+    match name {
+        "_id" | "id" => Ok(fields.id),
+        "domain" => Ok(fields.domain),
+        "title" => Ok(fields.title),
+        "description" => Ok(fields.description),
+        "content" => Ok(fields.content),
+        "url" => Ok(fields.url),
+        _ => Err(anyhow!("Unknown field: {}", name)),
+    }
+}
+
+/// Fields searched when a query doesn't specify any.
+fn default_fields(fields: &DocFields) -> Vec<Field> {
+    vec![fields.title, fields.description, fields.content]
+}
+
+fn parse_fields(fields: &DocFields, value: Option<&Value>) -> anyhow::Result<Vec<Field>> {
+    let names: Vec<&str> = match value {
+        Some(Value::Array(names)) => names.iter().filter_map(|name| name.as_str()).collect(),
+        Some(Value::String(name)) => vec![name.as_str()],
+        _ => Vec::new(),
+    };
+
+    // Strip any boosts (e.g. "title^2") & wildcards
+    let names: Vec<&str> = names
+        .into_iter()
+        .map(|name| name.split('^').next().unwrap_or_default())
+        .filter(|name| !name.is_empty() && *name != "*")
+        .collect();
+
+    if names.is_empty() {
+        return Ok(default_fields(fields));
+    }
+
+    names
+        .into_iter()
+        .map(|name| field_by_name(fields, name))
+        .collect()
+}
+
+/// Queries are expected to be objects w/ a single key, e.g. `{ "match": { .. } }`
+fn single_entry(value: &Value) -> anyhow::Result<(&String, &Value)> {
+    value
+        .as_object()
+        .and_then(|obj| obj.iter().next())
+        .ok_or_else(|| anyhow!("Expected an object w/ a single key: {}", value))
+}
+
+/// Handles both the short (`{ "title": "text" }`) and long form
+/// (`{ "title": { "query": "text" } }`) of field queries.
+fn field_and_value<'a>(value: &'a Value, key: &str) -> anyhow::Result<(&'a String, String)> {
+    let (field, inner) = single_entry(value)?;
+    let text = match inner {
+        Value::Object(obj) => obj.get(key).cloned().unwrap_or(Value::Null),
+        other => other.clone(),
+    };
+
+    let text = match text {
+        Value::String(text) => text,
+        Value::Number(num) => num.to_string(),
+        Value::Bool(val) => val.to_string(),
+        _ => return Err(anyhow!("Missing value for field {}", field)),
+    };
+
+    Ok((field, text))
+}
+
+fn parse_text(index: &Index, fields: Vec<Field>, text: &str) -> anyhow::Result<Box<dyn Query>> {
+    QueryParser::for_index(index, fields)
+        .parse_query(text)
+        .map_err(|err| anyhow!("Unable to parse query `{}`: {:?}", text, err))
+}
+
+fn term_query(field: Field, text: &str) -> Box<dyn Query> {
+    Box::new(TermQuery::new(
+        Term::from_field_text(field, text),
+        IndexRecordOption::WithFreqs,
+    ))
+}
+
+fn as_list(value: Option<&Value>) -> Vec<&Value> {
+    match value {
+        Some(Value::Array(values)) => values.iter().collect(),
+        Some(value) => vec![value],
+        None => Vec::new(),
+    }
+}
+
+fn parse_bool(
+    index: &Index,
+    obj: &Map<String, Value>,
+    depth: usize,
+) -> anyhow::Result<Box<dyn Query>> {
+    let mut queries: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+
+    // Filters don't affect scoring in ES but are required to match, which is
+    // close enough to a "must" for our purposes.
+    for (key, occur) in [
+        ("must", Occur::Must),
+        ("filter", Occur::Must),
+        ("should", Occur::Should),
+        ("must_not", Occur::MustNot),
+    ] {
+        for query in as_list(obj.get(key)) {
+            queries.push((occur, parse_clause(index, query, depth + 1)?));
+        }
+    }
+
+    // A boolean query w/ only negative clauses matches nothing in tantivy
+    if queries.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+        queries.push((Occur::Must, Box::new(AllQuery)));
+    }
+
+    Ok(Box::new(BooleanQuery::new(queries)))
+}
+
+/// Convert an ES query DSL object into a tantivy query.
+pub fn parse_query(index: &Index, value: &Value) -> anyhow::Result<Box<dyn Query>> {
+    parse_clause(index, value, 0)
+}
+
+/// `depth` is the number of `bool` queries this clause is nested in.
+fn parse_clause(index: &Index, value: &Value, depth: usize) -> anyhow::Result<Box<dyn Query>> {
+    let fields = Searcher::doc_fields();
+    let (query_type, body) = single_entry(value)?;
+
+    match query_type.as_str() {
+        "match_all" => Ok(Box::new(AllQuery)),
+        "match" => {
+            let (field, text) = field_and_value(body, "query")?;
+            parse_text(index, vec![field_by_name(&fields, field)?], &text)
+        }
+        "match_phrase" => {
+            let (field, text) = field_and_value(body, "query")?;
+            let phrase = format!("\"{}\"", text.replace('"', ""));
+            parse_text(index, vec![field_by_name(&fields, field)?], &phrase)
+        }
+        "multi_match" | "query_string" | "simple_query_string" => {
+            let text = body
+                .get("query")
+                .and_then(|query| query.as_str())
+                .ok_or_else(|| anyhow!("{} requires a query", query_type))?;
+
+            let field_names = body.get("fields").or_else(|| body.get("default_field"));
+            parse_text(index, parse_fields(&fields, field_names)?, text)
+        }
+        "term" => {
+            let (field, text) = field_and_value(body, "value")?;
+            Ok(term_query(field_by_name(&fields, field)?, &text))
+        }
+        "terms" => {
+            let (field, values) = single_entry(body)?;
+            let field = field_by_name(&fields, field)?;
+            let queries = as_list(Some(values))
+                .into_iter()
+                .filter_map(|value| value.as_str())
+                .map(|text| (Occur::Should, term_query(field, text)))
+                .collect();
+
+            Ok(Box::new(BooleanQuery::new(queries)))
+        }
+        "prefix" => {
+            let (field, text) = field_and_value(body, "value")?;
+            let pattern = format!("{}.*", regex::escape(&text));
+            Ok(Box::new(RegexQuery::from_pattern(
+                &pattern,
+                field_by_name(&fields, field)?,
+            )?))
+        }
+        "bool" => {
+            let obj = body
+                .as_object()
+                .ok_or_else(|| anyhow!("bool query must be an object"))?;
+            if depth >= MAX_BOOL_DEPTH {
+                return Err(anyhow!("bool queries nested too deep"));
+            }
+            parse_bool(index, obj, depth)
+        }
+        _ => Err(anyhow!("Unsupported query type: {}", query_type)),
+    }
+}
+
+/// Run an ES style search request against the index.
+#[tracing::instrument(skip(index, reader))]
+pub fn search(
+    index: &Index,
+    reader: &IndexReader,
+    request: &EsSearchRequest,
+) -> anyhow::Result<EsSearchResponse> {
+    let start = Instant::now();
+    let fields = Searcher::doc_fields();
+    let searcher = reader.searcher();
+
+    let query = match &request.query {
+        Some(query) => parse_query(index, query)?,
+        None => Box::new(AllQuery),
+    };
+
+    let size = request.size.unwrap_or(DEFAULT_SIZE).min(MAX_SIZE);
+    let from = request.from.unwrap_or(0).min(MAX_RESULT_WINDOW - size);
+
+    let (top_docs, total) = if size == 0 {
+        (Vec::new(), searcher.search(&*query, &Count)?)
+    } else {
+        searcher.search(
+            &*query,
+            &(TopDocs::with_limit(size).and_offset(from), Count),
+        )?
+    };
+
+    let get_text = |doc: &tantivy::Document, field: Field| -> String {
+        doc.get_first(field)
+            .and_then(|value| value.as_text())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let mut hits = Vec::new();
+    for (score, doc_addr) in top_docs {
+        let doc = searcher.doc(doc_addr)?;
+        hits.push(EsHit {
+            index: INDEX_NAME.to_string(),
+            id: get_text(&doc, fields.id),
+            score,
+            source: EsSource {
+                domain: get_text(&doc, fields.domain),
+                title: get_text(&doc, fields.title),
+                description: get_text(&doc, fields.description),
+                url: get_text(&doc, fields.url),
+            },
+        });
+    }
+
+    let max_score = hits.iter().map(|hit| hit.score).reduce(f32::max);
+
+    Ok(EsSearchResponse {
+        took: start.elapsed().as_millis() as u64,
+        timed_out: false,
+        shards: EsShards {
+            total: 1,
+            successful: 1,
+            skipped: 0,
+            failed: 0,
+        },
+        hits: EsHits {
+            total: EsTotal {
+                value: total,
+                relation: "eq".to_string(),
+            },
+            max_score,
+            hits,
+        },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_query, search, EsSearchRequest, MAX_BOOL_DEPTH};
+    use crate::search::{IndexPath, Searcher};
+    use serde_json::json;
+
+    fn build_index() -> Searcher {
+        let searcher = Searcher::with_index(&IndexPath::Memory);
+        {
+            let mut writer = searcher.writer.lock().unwrap();
+            Searcher::add_document(
+                &mut writer,
+                "Rust (programming language)",
+                "Rust is a multi-paradigm programming language",
+                "en.wikipedia.org",
+                "https://en.wikipedia.org/wiki/Rust_(programming_language)",
+                "Rust is a multi-paradigm, general-purpose programming language",
+                "",
+                None,
+            )
+            .unwrap();
+
+            Searcher::add_document(
+                &mut writer,
+                "Rust",
+                "Iron oxide",
+                "example.com",
+                "https://example.com/rust",
+                "Rust is an iron oxide, a usually reddish-brown oxide",
+                "",
+                None,
+            )
+            .unwrap();
+            writer.commit().unwrap();
+        }
+        searcher.reader.reload().unwrap();
+        searcher
+    }
+
+    fn run(searcher: &Searcher, request: serde_json::Value) -> super::EsSearchResponse {
+        let request: EsSearchRequest = serde_json::from_value(request).unwrap();
+        search(&searcher.index, &searcher.reader, &request).unwrap()
+    }
+
+    #[test]
+    fn test_match_queries() {
+        let searcher = build_index();
+
+        let resp = run(&searcher, json!({ "query": { "match_all": {} } }));
+        assert_eq!(resp.hits.total.value, 2);
+
+        let resp = run(
+            &searcher,
+            json!({ "query": { "match": { "content": "programming" } } }),
+        );
+        assert_eq!(resp.hits.total.value, 1);
+        assert_eq!(resp.hits.hits[0].source.domain, "en.wikipedia.org");
+
+        let resp = run(
+            &searcher,
+            json!({ "query": { "multi_match": { "query": "oxide", "fields": ["title", "content"] } } }),
+        );
+        assert_eq!(resp.hits.total.value, 1);
+    }
+
+    #[test]
+    fn test_bool_query() {
+        let searcher = build_index();
+
+        let resp = run(
+            &searcher,
+            json!({
+                "query": {
+                    "bool": {
+                        "must": { "match": { "content": "rust" } },
+                        "must_not": [{ "term": { "domain": "example.com" } }]
+                    }
+                },
+                "size": 5
+            }),
+        );
+        assert_eq!(resp.hits.total.value, 1);
+        assert_eq!(resp.hits.hits[0].source.domain, "en.wikipedia.org");
+
+        let request: EsSearchRequest =
+            serde_json::from_value(json!({ "query": { "fuzzy": {} } })).unwrap();
+        assert!(search(&searcher.index, &searcher.reader, &request).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let searcher = build_index();
+
+        // Paging past the result window
+        let resp = run(
+            &searcher,
+            json!({ "query": { "match_all": {} }, "from": usize::MAX, "size": usize::MAX }),
+        );
+        assert_eq!(resp.hits.total.value, 2);
+        assert!(resp.hits.hits.is_empty());
+
+        let mut query = json!({ "match_all": {} });
+        for _ in 0..MAX_BOOL_DEPTH {
+            query = json!({ "bool": { "must": query } });
+        }
+        assert!(parse_query(&searcher.index, &query).is_ok());
+
+        let query = json!({ "bool": { "must": query } });
+        assert!(parse_query(&searcher.index, &query).is_err());
+    }
+}
@@ -0,0 +1,281 @@
+//! Write out every document matching a search, for use outside the app.
+use std::collections::HashMap;
+use std::io::Write;
+
+use chrono::{TimeZone, Utc};
+use serde::Serialize;
+use tantivy::Document;
+
+use shared::config::Lens;
+use shared::request::ExportFormat;
+
+use super::{SearchOptions, Searcher};
+
+/// Columns written for each result, in order.
+const CSV_HEADER: [&str; 9] = [
+    "doc_id",
+    "url",
+    "title",
+    "description",
+    "domain",
+    "lang",
+    "indexed_at",
+    "score",
+    "snippet",
+];
+
+#[derive(Debug, Default, Serialize)]
+struct ExportedResult {
+    doc_id: String,
+    url: String,
+    title: String,
+    description: String,
+    domain: String,
+    lang: Option<String>,
+    /// RFC 3339 timestamp
+    indexed_at: Option<String>,
+    score: f32,
+    snippet: Option<String>,
+}
+
+impl ExportedResult {
+    fn from_doc(doc: &Document, score: f32, snippet: Option<String>) -> Self {
+        let fields = Searcher::doc_fields();
+        let text = |field| {
+            doc.get_first(field)
+                .and_then(|value| value.as_text())
+                .map(|value| value.to_string())
+        };
+
+        ExportedResult {
+            doc_id: text(fields.id).unwrap_or_default(),
+            url: text(fields.url).unwrap_or_default(),
+            title: text(fields.title).unwrap_or_default(),
+            description: text(fields.description).unwrap_or_default(),
+            domain: text(fields.domain).unwrap_or_default(),
+            lang: text(fields.lang),
+            indexed_at: doc
+                .get_first(fields.indexed_at)
+                .and_then(|value| value.as_i64())
+                .and_then(|ts| Utc.timestamp_opt(ts, 0).single())
+                .map(|ts| ts.to_rfc3339()),
+            score,
+            snippet,
+        }
+    }
+
+    fn csv_row(&self) -> String {
+        let score = self.score.to_string();
+        let columns: [&str; 9] = [
+            self.doc_id.as_str(),
+            &self.url,
+            &self.title,
+            &self.description,
+            &self.domain,
+            self.lang.as_deref().unwrap_or_default(),
+            self.indexed_at.as_deref().unwrap_or_default(),
+            &score,
+            self.snippet.as_deref().unwrap_or_default(),
+        ];
+
+        columns.map(csv_field).join(",")
+    }
+}
+
+/// Quote a CSV field if needed, doubling any quotes inside it.
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Results fetched from the index at a time, so large exports don't hold
+/// every hit in memory.
+const BATCH_SIZE: usize = 1000;
+
+/// Write all the results for `query_string` to `out` in `format`, best match
+/// first. `offset` & `limit` in `options` are ignored. Returns the number of
+/// results written.
+pub fn export<W: Write>(
+    index: &Searcher,
+    lenses: &HashMap<String, Lens>,
+    applied_lens: &[String],
+    query_string: &str,
+    options: &SearchOptions,
+    format: ExportFormat,
+    out: W,
+) -> anyhow::Result<usize> {
+    export_in_batches(
+        index,
+        lenses,
+        applied_lens,
+        query_string,
+        options,
+        format,
+        out,
+        BATCH_SIZE,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn export_in_batches<W: Write>(
+    index: &Searcher,
+    lenses: &HashMap<String, Lens>,
+    applied_lens: &[String],
+    query_string: &str,
+    options: &SearchOptions,
+    format: ExportFormat,
+    mut out: W,
+    batch_size: usize,
+) -> anyhow::Result<usize> {
+    let snippets = index.snippet_generator(query_string).ok();
+
+    if format == ExportFormat::Csv {
+        writeln!(out, "{}", CSV_HEADER.join(","))?;
+    }
+
+    let mut num_exported = 0;
+    loop {
+        let batch_options = SearchOptions {
+            offset: num_exported,
+            limit: batch_size,
+            ..options.clone()
+        };
+        let (_, docs) = index.search_with_lens(lenses, applied_lens, query_string, &batch_options);
+        let searcher = index.reader.searcher();
+
+        let batch_len = docs.len();
+        for (score, doc_addr) in docs.into_iter().take(batch_size) {
+            let doc = searcher.doc(doc_addr)?;
+            let snippet = snippets
+                .as_ref()
+                .and_then(|generator| Searcher::snippet(generator, &doc))
+                .map(|snippet| snippet.text);
+            let result = ExportedResult::from_doc(&doc, score, snippet);
+
+            match format {
+                ExportFormat::JsonLines => writeln!(out, "{}", serde_json::to_string(&result)?)?,
+                ExportFormat::Csv => writeln!(out, "{}", result.csv_row())?,
+            }
+            num_exported += 1;
+        }
+
+        if batch_len < batch_size {
+            break;
+        }
+    }
+
+    out.flush()?;
+    Ok(num_exported)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use shared::request::ExportFormat;
+
+    use super::{csv_field, export, export_in_batches};
+    use crate::search::{IndexPath, SearchOptions, Searcher};
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_export() {
+        let searcher = Searcher::with_index(&IndexPath::Memory);
+        {
+            let mut writer = searcher.writer.lock().unwrap();
+            for idx in 0..3 {
+                Searcher::add_document(
+                    &mut writer,
+                    &format!("Rust, part {}", idx),
+                    "",
+                    "example.com",
+                    &format!("https://example.com/rust/{}", idx),
+                    "Rust borrow checker",
+                    "",
+                    Some("en"),
+                )
+                .unwrap();
+            }
+            writer.commit().unwrap();
+        }
+        searcher.reader.reload().unwrap();
+
+        let lenses = HashMap::new();
+        let options = SearchOptions {
+            limit: 1,
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        let num_exported = export(
+            &searcher,
+            &lenses,
+            &[],
+            "borrow",
+            &options,
+            ExportFormat::JsonLines,
+            &mut out,
+        )
+        .unwrap();
+        // Not limited to a single page
+        assert_eq!(num_exported, 3);
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["lang"], "en");
+        assert_eq!(lines[0]["domain"], "example.com");
+
+        let mut out = Vec::new();
+        export(
+            &searcher,
+            &lenses,
+            &[],
+            "borrow",
+            &options,
+            ExportFormat::Csv,
+            &mut out,
+        )
+        .unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 4);
+        assert!(rows[0].starts_with("doc_id,url,title"));
+        assert!(rows[1].contains(",\"Rust, part "));
+
+        // Paging through the hits doesn't skip or repeat any
+        let mut out = Vec::new();
+        let num_exported = export_in_batches(
+            &searcher,
+            &lenses,
+            &[],
+            "borrow",
+            &options,
+            ExportFormat::JsonLines,
+            &mut out,
+            2,
+        )
+        .unwrap();
+        assert_eq!(num_exported, 3);
+        let mut urls: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .map(|line| line["url"].as_str().unwrap().to_string())
+            .collect();
+        urls.sort();
+        urls.dedup();
+        assert_eq!(urls.len(), 3);
+    }
+}
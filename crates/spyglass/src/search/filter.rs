@@ -0,0 +1,308 @@
+//! Cached lens filters. A lens only restricts which documents can match, it
+//! doesn't contribute to the score, so it's evaluated once per segment into a
+//! set of doc ids & reused. Segments are immutable, a cached filter stays valid
+//! until its segment is merged away.
+//!
+//! This is also why lenses share a single index rather than each getting
+//! their own. Lenses overlap, a page can be in several of them, & their rules
+//! change w/ every lens update, so per-lens indices would mean duplicating &
+//! shuffling documents between them. W/ the filter cached, a lens-scoped
+//! search only scores documents in the lens, which gets most of the benefit.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use tantivy::query::{Explanation, Query, Scorer, Weight};
+use tantivy::schema::Term;
+use tantivy::{DocId, DocSet, Score, SegmentId, SegmentReader, TantivyError, TERMINATED};
+
+/// Max number of distinct lens combinations to keep filters around for.
+const MAX_CACHED_FILTERS: usize = 64;
+
+/// Doc ids within a single segment that pass a filter.
+pub struct DocBits {
+    words: Vec<u64>,
+}
+
+impl DocBits {
+    pub fn with_max_doc(max_doc: DocId) -> Self {
+        DocBits {
+            words: vec![0; (max_doc as usize + 63) / 64],
+        }
+    }
+
+    pub fn insert(&mut self, doc: DocId) {
+        let (word, bit) = (doc as usize / 64, doc % 64);
+        if let Some(word) = self.words.get_mut(word) {
+            *word |= 1 << bit;
+        }
+    }
+
+    pub fn contains(&self, doc: DocId) -> bool {
+        let (word, bit) = (doc as usize / 64, doc % 64);
+        self.words
+            .get(word)
+            .map(|word| word & (1 << bit) != 0)
+            .unwrap_or(false)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+}
+
+/// A filter evaluated for every segment of a searcher.
+#[derive(Clone, Default)]
+pub struct SegmentFilters(HashMap<SegmentId, Arc<DocBits>>);
+
+impl SegmentFilters {
+    /// Docs in `reader` that pass the filter. Segments the filter wasn't
+    /// evaluated for match nothing.
+    pub fn for_segment(&self, reader: &SegmentReader) -> Arc<DocBits> {
+        self.0
+            .get(&reader.segment_id())
+            .cloned()
+            .unwrap_or_else(|| Arc::new(DocBits::with_max_doc(0)))
+    }
+}
+
+impl fmt::Debug for SegmentFilters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SegmentFilters")
+            .field("segments", &self.0.len())
+            .finish()
+    }
+}
+
+/// Filters keyed by the lens rules they were built from.
+#[derive(Clone, Default)]
+pub struct LensFilterCache {
+    filters: Arc<Mutex<HashMap<String, HashMap<SegmentId, Arc<DocBits>>>>>,
+}
+
+impl LensFilterCache {
+    /// Filter for `key` across all the segments of `searcher`. `build_query`
+    /// is only called when there are segments we haven't seen yet.
+    pub fn get_or_build<F>(
+        &self,
+        searcher: &tantivy::Searcher,
+        key: &str,
+        build_query: F,
+    ) -> anyhow::Result<SegmentFilters>
+    where
+        F: FnOnce() -> Box<dyn Query>,
+    {
+        let mut filters = self
+            .filters
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Unable to get lens filter lock"))?;
+
+        if filters.len() >= MAX_CACHED_FILTERS && !filters.contains_key(key) {
+            filters.clear();
+        }
+
+        let segments = filters.entry(key.to_string()).or_default();
+        // Forget anything that's been merged away.
+        let alive: HashSet<SegmentId> = searcher
+            .segment_readers()
+            .iter()
+            .map(|reader| reader.segment_id())
+            .collect();
+        segments.retain(|segment_id, _| alive.contains(segment_id));
+
+        let mut build_query = Some(build_query);
+        let mut weight: Option<Box<dyn Weight>> = None;
+        for reader in searcher.segment_readers() {
+            if segments.contains_key(&reader.segment_id()) {
+                continue;
+            }
+
+            if let Some(build_query) = build_query.take() {
+                weight = Some(build_query().weight(searcher, false)?);
+            }
+
+            let weight = match &weight {
+                Some(weight) => weight,
+                None => continue,
+            };
+
+            let mut bits = DocBits::with_max_doc(reader.max_doc());
+            weight.for_each(reader, &mut |doc, _| bits.insert(doc))?;
+            segments.insert(reader.segment_id(), Arc::new(bits));
+        }
+
+        Ok(SegmentFilters(segments.clone()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.filters
+            .lock()
+            .map(|filters| filters.len())
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Restricts `query` to the documents that pass `filters`. Unlike wrapping
+/// both in a `BooleanQuery`, the inner query keeps its top-k pruning (e.g.
+/// block-max WAND for term unions), filtered out docs are simply skipped.
+#[derive(Clone, Debug)]
+pub struct FilteredQuery {
+    query: Box<dyn Query>,
+    filters: SegmentFilters,
+}
+
+impl FilteredQuery {
+    pub fn new(query: Box<dyn Query>, filters: SegmentFilters) -> Self {
+        FilteredQuery { query, filters }
+    }
+}
+
+impl Query for FilteredQuery {
+    fn weight(
+        &self,
+        searcher: &tantivy::Searcher,
+        scoring_enabled: bool,
+    ) -> tantivy::Result<Box<dyn Weight>> {
+        Ok(Box::new(FilteredWeight {
+            weight: self.query.weight(searcher, scoring_enabled)?,
+            filters: self.filters.clone(),
+        }))
+    }
+
+    fn query_terms(&self, terms: &mut BTreeMap<Term, bool>) {
+        self.query.query_terms(terms);
+    }
+}
+
+struct FilteredWeight {
+    weight: Box<dyn Weight>,
+    filters: SegmentFilters,
+}
+
+impl Weight for FilteredWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        let scorer = self.weight.scorer(reader, boost)?;
+        Ok(Box::new(FilteredScorer::new(
+            scorer,
+            self.filters.for_segment(reader),
+        )))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        if !self.filters.for_segment(reader).contains(doc) {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Document #({}) does not pass the lens filter",
+                doc
+            )));
+        }
+
+        self.weight.explain(reader, doc)
+    }
+
+    fn for_each_pruning(
+        &self,
+        threshold: Score,
+        reader: &SegmentReader,
+        callback: &mut dyn FnMut(DocId, Score) -> Score,
+    ) -> tantivy::Result<()> {
+        let bits = self.filters.for_segment(reader);
+        if bits.is_empty() {
+            return Ok(());
+        }
+
+        // Skipping a doc leaves the threshold as is, so anything the inner
+        // query prunes would never have made it into the top-k anyways.
+        let mut threshold = threshold;
+        self.weight
+            .for_each_pruning(threshold, reader, &mut |doc, score| {
+                if bits.contains(doc) {
+                    threshold = callback(doc, score);
+                }
+                threshold
+            })
+    }
+}
+
+struct FilteredScorer {
+    scorer: Box<dyn Scorer>,
+    bits: Arc<DocBits>,
+}
+
+impl FilteredScorer {
+    fn new(scorer: Box<dyn Scorer>, bits: Arc<DocBits>) -> Self {
+        let mut filtered = FilteredScorer { scorer, bits };
+        filtered.skip_filtered();
+        filtered
+    }
+
+    /// Move forward to the first doc that passes the filter.
+    fn skip_filtered(&mut self) -> DocId {
+        let mut doc = self.scorer.doc();
+        while doc != TERMINATED && !self.bits.contains(doc) {
+            doc = self.scorer.advance();
+        }
+
+        doc
+    }
+}
+
+impl DocSet for FilteredScorer {
+    fn advance(&mut self) -> DocId {
+        self.scorer.advance();
+        self.skip_filtered()
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        self.scorer.seek(target);
+        self.skip_filtered()
+    }
+
+    fn doc(&self) -> DocId {
+        self.scorer.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.scorer.size_hint().min(self.bits.len() as u32)
+    }
+}
+
+impl Scorer for FilteredScorer {
+    fn score(&mut self) -> Score {
+        self.scorer.score()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DocBits;
+
+    #[test]
+    fn test_doc_bits() {
+        let mut bits = DocBits::with_max_doc(130);
+        assert!(bits.is_empty());
+
+        bits.insert(0);
+        bits.insert(64);
+        bits.insert(129);
+        // Out of range, ignored
+        bits.insert(1000);
+
+        assert!(bits.contains(0));
+        assert!(bits.contains(64));
+        assert!(bits.contains(129));
+        assert!(!bits.contains(1));
+        assert!(!bits.contains(1000));
+        assert_eq!(bits.len(), 3);
+    }
+}
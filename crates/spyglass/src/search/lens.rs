@@ -1,14 +1,26 @@
 use std::fs;
+use std::path::{Path, PathBuf};
 
 use entities::models::{bootstrap_queue, crawl_queue, indexed_document, lens};
 use entities::regex::{regex_for_robots, WildcardType};
 use migration::sea_orm::DatabaseConnection;
-use shared::config::{Config, Lens, LensRule, UserSettings};
+use shared::config::{Config, Lens, LensRule, UserSettings, LENS_DIRECTORY_INDEX_URL};
+use shared::response::InstallableLens;
+use url::Url;
 
-use crate::crawler::bootstrap;
+use crate::crawler::{bootstrap, sitemap};
+use crate::fetch::HTTPClient;
 use crate::search::Searcher;
 use crate::state::AppState;
 
+/// A lens file downloaded from the lens directory or elsewhere.
+pub struct DownloadedLens {
+    /// Always a single path component, see `lens_file_name`.
+    pub file_name: String,
+    pub contents: String,
+    pub lens: Lens,
+}
+
 /// Check if we've already bootstrapped a prefix / otherwise add it to the queue.
 async fn check_and_bootstrap(
     lens: &Lens,
@@ -60,94 +72,183 @@ pub async fn read_lenses(state: &AppState, config: &Config) -> anyhow::Result<()
     Ok(())
 }
 
+/// Installed lens file w/ a lens named `name`, if any.
+pub fn find_lens_file(lenses_dir: &Path, name: &str) -> Option<PathBuf> {
+    fs::read_dir(lenses_dir).ok()?.flatten().find_map(|entry| {
+        let path = entry.path();
+        if !path.is_file() || path.extension().unwrap_or_default() != "ron" {
+            return None;
+        }
+
+        let lens = ron::from_str::<Lens>(&fs::read_to_string(&path).ok()?).ok()?;
+        (lens.name == name).then(|| path)
+    })
+}
+
+/// Lenses available to install from the lens directory.
+pub async fn lens_directory(client: &HTTPClient) -> anyhow::Result<Vec<InstallableLens>> {
+    let url = Url::parse(LENS_DIRECTORY_INDEX_URL)?;
+    let contents = client.get(&url).await?.error_for_status()?.text().await?;
+    Ok(ron::from_str(&contents)?)
+}
+
+/// Download & parse the lens at `url`.
+pub async fn download_lens(client: &HTTPClient, url: &Url) -> anyhow::Result<DownloadedLens> {
+    let contents = client.get(url).await?.error_for_status()?.text().await?;
+    let lens = ron::from_str::<Lens>(&contents)
+        .map_err(|err| anyhow::anyhow!("<{}> is not a valid lens: {}", url, err))?;
+
+    let file_name = url
+        .path_segments()
+        .and_then(|segments| segments.last())
+        .filter(|name| name.ends_with(".ron"))
+        .and_then(lens_file_name)
+        .or_else(|| lens_file_name(&lens.name))
+        .ok_or_else(|| anyhow::anyhow!("<{}> has no usable file name", url))?;
+
+    Ok(DownloadedLens {
+        file_name,
+        contents,
+        lens,
+    })
+}
+
+/// File name to save a lens as, w/ anything but letters, numbers, `-` & `_`
+/// replaced so it can't point outside the lenses folder. `None` if nothing's
+/// left.
+fn lens_file_name(name: &str) -> Option<String> {
+    let stem: String = name
+        .strip_suffix(".ron")
+        .unwrap_or(name)
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    (!stem.trim_matches('_').is_empty()).then(|| format!("{}.ron", stem))
+}
+
+/// Remove crawl tasks & indexed documents w/ URLs matching a robots.txt style
+/// rule. Returns the number of documents removed.
+pub async fn remove_matching(state: &AppState, rule: &str) -> usize {
+    let rule_like = match regex_for_robots(rule, WildcardType::Database) {
+        Some(rule_like) => rule_like,
+        None => return 0,
+    };
+
+    // Remove matching crawl tasks
+    let _ = crawl_queue::remove_by_rule(&state.db, &rule_like).await;
+    // Remove matching indexed documents
+    match indexed_document::remove_by_rule(&state.db, &rule_like).await {
+        Ok(doc_ids) => {
+            if let Ok(mut writer) = state.index.writer.lock() {
+                for doc_id in doc_ids.iter() {
+                    let res = Searcher::delete(&mut writer, doc_id);
+                    if let Err(err) = res {
+                        log::error!("Unable to remove docs: {:?}", err);
+                    } else {
+                        state.index.mark_pending(1);
+                    }
+                }
+            }
+            doc_ids.len()
+        }
+        Err(e) => {
+            log::error!("Unable to remove docs: {:?}", e);
+            0
+        }
+    }
+}
+
 /// Loop through lenses in the AppState. Update our internal db & bootstrap anything
 /// that hasn't been bootstrapped.
 pub async fn load_lenses(state: AppState) {
-    let mut new_lenses: Vec<Lens> = Vec::new();
-    for entry in state.lenses.iter() {
-        let lens = entry.value();
-        // Have we added this lens to the database?
-        match lens::add_or_enable(
-            &state.db,
-            &lens.name,
-            &lens.author,
-            lens.description.as_ref(),
-            &lens.version,
-            lens::LensType::Simple,
-        )
-        .await
-        {
-            Ok(is_new) => {
-                log::info!("loaded lens {}, new? {}", lens.name, is_new);
-                new_lenses.push(lens.clone());
-            }
-            Err(e) => log::error!("error loading lens {}", e),
+    let lenses: Vec<Lens> = state
+        .lenses
+        .iter()
+        .map(|entry| entry.value().clone())
+        .collect();
+
+    for lens in lenses.iter() {
+        load_lens(&state, lens).await;
+    }
+
+    log::info!("✅ finished lens checks")
+}
+
+/// Add the lens to our internal db & bootstrap anything that hasn't been
+/// bootstrapped.
+pub async fn load_lens(state: &AppState, lens: &Lens) {
+    // Have we added this lens to the database?
+    match lens::add_or_enable(
+        &state.db,
+        &lens.name,
+        &lens.author,
+        lens.description.as_ref(),
+        &lens.version,
+        lens::LensType::Simple,
+    )
+    .await
+    {
+        Ok(is_new) => log::info!("loaded lens {}, new? {}", lens.name, is_new),
+        Err(e) => {
+            log::error!("error loading lens {}", e);
+            return;
         }
     }
 
-    // Bootstrap lenses.
     // Check & bootstrap will go through domains/prefixes and bootstrap a crawl queue
     // if we have not already done so.
-    for lens in new_lenses {
-        for domain in lens.domains.iter() {
-            let seed_url = format!("https://{}", domain);
-            check_and_bootstrap(&lens, &state.db, &state.user_settings, &seed_url).await;
+    for domain in lens.domains.iter() {
+        let seed_url = format!("https://{}", domain);
+        check_and_bootstrap(lens, &state.db, &state.user_settings, &seed_url).await;
+
+        if let Err(err) =
+            sitemap::seed_from_sitemaps(lens, &state.db, &state.user_settings, domain).await
+        {
+            log::warn!("unable to read sitemaps for {}: {}", domain, err);
         }
+    }
 
-        for prefix in lens.urls.iter() {
-            // Handle singular URL matches
-            if prefix.ends_with('$') {
-                // Remove the '$' suffix and add to the crawl queue
-                let url = prefix.strip_suffix('$').unwrap();
-                if let Err(err) = crawl_queue::enqueue_all(
-                    &state.db,
-                    &[url.to_owned()],
-                    &Vec::new(),
-                    &state.user_settings,
-                    &Default::default(),
-                )
-                .await
-                {
-                    log::warn!("unable to enqueue <{}> due to {}", prefix, err)
-                }
-            } else {
-                check_and_bootstrap(&lens, &state.db, &state.user_settings, prefix).await;
+    for prefix in lens.urls.iter() {
+        // Handle singular URL matches
+        if prefix.ends_with('$') {
+            // Remove the '$' suffix and add to the crawl queue
+            let url = prefix.strip_suffix('$').unwrap();
+            if let Err(err) = crawl_queue::enqueue_all(
+                &state.db,
+                &[url.to_owned()],
+                &Vec::new(),
+                &state.user_settings,
+                &crawl_queue::EnqueueSettings::for_seeds_of(lens),
+            )
+            .await
+            {
+                log::warn!("unable to enqueue <{}> due to {}", prefix, err)
             }
+        } else {
+            check_and_bootstrap(lens, &state.db, &state.user_settings, prefix).await;
         }
+    }
 
-        // Rules will go through and remove crawl tasks AND indexed_documents that match.
-        for rule in lens.rules.iter() {
-            match rule {
-                LensRule::SkipURL(rule_str) => {
-                    if let Some(rule_like) = regex_for_robots(rule_str, WildcardType::Database) {
-                        // Remove matching crawl tasks
-                        let _ = crawl_queue::remove_by_rule(&state.db, &rule_like).await;
-                        // Remove matching indexed documents
-                        match indexed_document::remove_by_rule(&state.db, &rule_like).await {
-                            Ok(doc_ids) => {
-                                if let Ok(mut writer) = state.index.writer.lock() {
-                                    for doc_id in doc_ids {
-                                        let res = Searcher::delete(&mut writer, &doc_id);
-                                        if let Err(err) = res {
-                                            log::error!("Unable to remove docs: {:?}", err);
-                                        }
-                                    }
-                                }
-                            }
-                            Err(e) => log::error!("Unable to remove docs: {:?}", e),
-                        }
-                    }
-                }
+    // Rules will go through and remove crawl tasks AND indexed_documents that match.
+    for rule in lens.rules.iter() {
+        match rule {
+            LensRule::SkipURL(rule_str) => {
+                remove_matching(state, rule_str).await;
             }
         }
     }
-
-    log::info!("✅ finished lens checks")
 }
 
 #[cfg(test)]
 mod test {
-    use super::check_and_bootstrap;
+    use super::{check_and_bootstrap, lens_file_name};
     use entities::models::bootstrap_queue;
     use entities::test::setup_test_db;
     use shared::config::UserSettings;
@@ -161,4 +262,17 @@ mod test {
         bootstrap_queue::enqueue(&db, test, 10).await.unwrap();
         assert!(!check_and_bootstrap(&Default::default(), &db, &settings, &test).await);
     }
+
+    #[test]
+    fn test_lens_file_name() {
+        assert_eq!(lens_file_name("wiki.ron"), Some("wiki.ron".to_string()));
+        assert_eq!(lens_file_name("My Lens"), Some("My_Lens.ron".to_string()));
+        assert_eq!(
+            lens_file_name("../../.bashrc"),
+            Some("_______bashrc.ron".to_string())
+        );
+        assert_eq!(lens_file_name("..%2F.ron"), Some("___2F.ron".to_string()));
+        assert_eq!(lens_file_name("/"), None);
+        assert_eq!(lens_file_name(""), None);
+    }
 }
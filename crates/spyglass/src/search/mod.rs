@@ -2,23 +2,105 @@
 use std::collections::HashMap;
 use std::fmt::{Debug, Error, Formatter};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
-use tantivy::collector::TopDocs;
+use tantivy::collector::{Count, FacetCollector, TopDocs};
 use tantivy::directory::MmapDirectory;
-use tantivy::query::{Occur, Query, QueryParser, TermQuery};
+use tantivy::fastfield::FastFieldReader;
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, MoreLikeThisQuery, Occur, Query, QueryClone, QueryParser,
+    TermQuery,
+};
+use tantivy::store::Compressor;
+use tantivy::tokenizer::TextAnalyzer;
+use tantivy::SnippetGenerator;
 use tantivy::{schema::*, DocAddress};
-use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy};
+use tantivy::{DocId, Index, IndexReader, IndexSettings, IndexWriter, ReloadPolicy, SegmentReader};
 use uuid::Uuid;
 
+pub mod answer;
+pub mod es;
+pub mod export;
+pub mod filter;
 pub mod lens;
 mod query;
-use crate::search::query::build_query;
-use shared::config::Lens;
+pub mod recovery;
+pub mod semantic;
+pub mod stats;
+pub mod tokenizer;
+use crate::scraper::html_to_text;
+use crate::search::filter::{DocBits, FilteredQuery, LensFilterCache, SegmentFilters};
+use crate::search::query::{
+    build_date_query, build_lens_query, build_query, build_site_query, build_url_query,
+    lens_filter_key,
+};
+pub use crate::search::query::{extract_filters, QueryFilters};
+use shared::config::{Lens, PrefixMatch, RankingSettings, UserSettings};
+use shared::request::DEFAULT_SEARCH_LIMIT;
+use shared::response::{FacetCount, ScoreExplanation, SearchFacets, Snippet};
+
+/// Max number of characters in a result snippet.
+const SNIPPET_LENGTH: usize = 200;
+/// Max. extra score a document indexed just now gets w/ recency ranking on,
+/// 0.5 being 50% on top of its text score.
+const RECENCY_WEIGHT: Score = 0.5;
+
+/// Words shorter than this are left out when looking for similar documents.
+const SIMILAR_MIN_WORD_LENGTH: usize = 3;
+/// Max number of terms from a document used to find similar ones.
+const SIMILAR_MAX_QUERY_TERMS: usize = 25;
 
 type Score = f32;
 type SearchResult = (Score, DocAddress);
 
+/// Multipliers applied on top of the text score, see `Searcher::explain`.
+pub struct ScoreBreakdown {
+    pub lens_boost: Score,
+    pub recency_boost: Score,
+    /// Tantivy's breakdown of the text score.
+    pub explanation: ScoreExplanation,
+}
+
+/// Everything about a search besides the query & lenses.
+#[derive(Clone, Debug)]
+pub struct SearchOptions {
+    /// Number of results to skip.
+    pub offset: usize,
+    /// Max number of results to return.
+    pub limit: usize,
+    /// Allow for typos in the query terms.
+    pub fuzzy: bool,
+    /// Which query terms also match words they're the start of.
+    pub prefix: PrefixMatch,
+    /// Only match documents indexed at or after this unix timestamp.
+    pub indexed_after: Option<i64>,
+    /// Only match documents indexed before this unix timestamp.
+    pub indexed_before: Option<i64>,
+    /// Only match documents in one of these languages, none means any.
+    pub langs: Vec<String>,
+    /// Only match documents on one of these domains or their subdomains.
+    pub sites: Vec<String>,
+    /// Only match documents w/ all of these in their URL.
+    pub url_terms: Vec<String>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            offset: 0,
+            limit: DEFAULT_SEARCH_LIMIT,
+            fuzzy: false,
+            prefix: PrefixMatch::Off,
+            indexed_after: None,
+            indexed_before: None,
+            langs: Vec::new(),
+            sites: Vec::new(),
+            url_terms: Vec::new(),
+        }
+    }
+}
+
 pub enum IndexPath {
     // Directory
     LocalPath(PathBuf),
@@ -31,6 +113,17 @@ pub struct Searcher {
     pub index: Index,
     pub reader: IndexReader,
     pub writer: Arc<Mutex<IndexWriter>>,
+    /// Number of adds/deletes since the last commit.
+    pub pending_writes: Arc<AtomicUsize>,
+    /// Set while segments are being merged.
+    pub is_optimizing: Arc<AtomicBool>,
+    /// Exists on disk while there are uncommitted writes, used to detect
+    /// unclean shutdowns. Not used for in-memory indexes.
+    pub uncommitted_marker: Option<PathBuf>,
+    /// Lens filters evaluated against the current segments.
+    pub lens_filters: LensFilterCache,
+    /// Field boosts used to score results.
+    pub ranking: RankingSettings,
 }
 
 impl Debug for Searcher {
@@ -48,7 +141,17 @@ pub struct DocFields {
     pub description: Field,
     pub title: Field,
     pub url: Field,
+    /// Tokenized URL so matches on parts of it count towards the score.
+    pub url_text: Field,
     pub raw: Field,
+    /// Text of documents w/o a raw page, e.g. from plugins. Stored only.
+    pub text: Field,
+    /// Unix timestamp of when the document was added to the index.
+    pub indexed_at: Field,
+    /// Domain as a facet, e.g. `/en.wikipedia.org`, used to count hits per domain.
+    pub domain_facet: Field,
+    /// Language the document is written in, e.g. `en`. See `scraper::lang`.
+    pub lang: Field,
 }
 
 type QueryVec = Vec<(Occur, Box<dyn Query>)>;
@@ -60,23 +163,40 @@ impl Searcher {
         // - full-text search and
         // - to retrieve the document after the search
         //
-        // TEXT:    Means the field should be tokenized and indexed, along with its term
-        //          frequency and term positions.
+        // text:    Means the field should be tokenized (w/ our own tokenizer, see
+        //          `tokenizer`) and indexed, along with its term frequency and term
+        //          positions.
         // STRING:  Means the field will be untokenized and indexed unlike above
         //
         // STORED:  Means that the field will also be saved in a compressed, row oriented
         //          key-value store. This store is useful to reconstruct the documents that
         //          were selected during the search phase.
+        let text = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(tokenizer::TOKENIZER_NAME)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+
         schema_builder.add_text_field("id", STRING | STORED);
         schema_builder.add_text_field("domain", STRING | STORED);
 
-        schema_builder.add_text_field("title", TEXT | STORED);
-        schema_builder.add_text_field("description", TEXT | STORED);
+        schema_builder.add_text_field("title", text.clone().set_stored());
+        schema_builder.add_text_field("description", text.clone().set_stored());
         schema_builder.add_text_field("url", STRING | STORED);
-        // Indexed but don't store for retreival
-        schema_builder.add_text_field("content", TEXT);
+        schema_builder.add_text_field("url_text", text.clone());
+        // Not stored, it's most of the index & can be extracted from the raw
+        // page when needed, see `Searcher::content`.
+        schema_builder.add_text_field("content", text);
         // Stored but not indexed
         schema_builder.add_text_field("raw", STORED);
+        // Content of documents w/o a raw page to extract it from
+        schema_builder.add_text_field("text", STORED);
+        // Unix timestamp, used to filter by date
+        schema_builder.add_i64_field("indexed_at", INDEXED | STORED | FAST);
+        // Used to count hits per domain
+        schema_builder.add_facet_field("domain_facet", FacetOptions::default());
+        // Used to filter by language
+        schema_builder.add_text_field("lang", STRING | STORED);
 
         schema_builder.build()
     }
@@ -97,7 +217,12 @@ impl Searcher {
             description: schema.get_field("description").unwrap(),
             title: schema.get_field("title").unwrap(),
             url: schema.get_field("url").unwrap(),
+            url_text: schema.get_field("url_text").unwrap(),
             raw: schema.get_field("raw").unwrap(),
+            text: schema.get_field("text").unwrap(),
+            indexed_at: schema.get_field("indexed_at").unwrap(),
+            domain_facet: schema.get_field("domain_facet").unwrap(),
+            lang: schema.get_field("lang").unwrap(),
         }
     }
 
@@ -123,19 +248,48 @@ impl Searcher {
     }
 
     pub fn with_index(index_path: &IndexPath) -> Self {
-        let schema = Searcher::schema();
-        let index = match index_path {
+        Self::with_settings(index_path, &UserSettings::default())
+    }
+
+    /// Open/create an index w/ the writer memory budget, ranking, stemming
+    /// languages & stop words from the user's settings.
+    pub fn with_settings(index_path: &IndexPath, settings: &UserSettings) -> Self {
+        // Raw pages make up most of the doc store, brotli does a lot better
+        // on HTML than the default lz4. Only applies to newly created indexes,
+        // existing ones keep the settings they were created with.
+        let builder = Index::builder()
+            .schema(Searcher::schema())
+            .settings(IndexSettings {
+                docstore_compression: Compressor::Brotli,
+                ..Default::default()
+            });
+
+        let (index, uncommitted_marker) = match index_path {
             IndexPath::LocalPath(path) => {
                 let dir = MmapDirectory::open(path).unwrap();
-                Index::open_or_create(dir, schema).unwrap()
+                (
+                    builder.open_or_create(dir).unwrap(),
+                    Some(path.join(recovery::UNCOMMITTED_MARKER)),
+                )
             }
-            IndexPath::Memory => Index::create_in_ram(schema),
+            IndexPath::Memory => (builder.create_in_ram().unwrap(), None),
         };
+        let languages = tokenizer::languages(&settings.stemming_languages);
+        let stop_words = tokenizer::stop_words(&settings.stop_words);
+        tokenizer::register(&index, &languages, &stop_words);
+        if let IndexPath::LocalPath(path) = index_path {
+            // Lets us tell whether the index needs rebuilding when the languages
+            // or stop words change.
+            let analyzer_id = tokenizer::analyzer_id(&languages, &stop_words);
+            if let Err(err) = std::fs::write(path.join(recovery::ANALYZER_FILE), analyzer_id) {
+                log::warn!("Unable to write analyzer id: {}", err);
+            }
+        }
 
         // Should only be one writer at a time. This single IndexWriter is already
         // multithreaded.
         let writer = index
-            .writer(50_000_000)
+            .writer(settings.index_writer_memory_mb as usize * 1_000_000)
             .expect("Unable to create index_writer");
 
         // For a search server you will typically create on reader for the entire
@@ -150,9 +304,158 @@ impl Searcher {
             index,
             reader,
             writer: Arc::new(Mutex::new(writer)),
+            pending_writes: Arc::new(AtomicUsize::new(0)),
+            is_optimizing: Arc::new(AtomicBool::new(false)),
+            uncommitted_marker,
+            lens_filters: LensFilterCache::default(),
+            ranking: settings.ranking.clone(),
+        }
+    }
+
+    /// Analyzer the full-text fields were indexed w/, queries need to be run
+    /// through the same one to match.
+    fn analyzer(&self) -> TextAnalyzer {
+        self.index
+            .tokenizers()
+            .get(tokenizer::TOKENIZER_NAME)
+            .unwrap_or_else(|| tokenizer::analyzer(&[], &[]))
+    }
+
+    /// Record adds/deletes that have not been committed yet.
+    pub fn mark_pending(&self, num_writes: usize) {
+        let prev = self.pending_writes.fetch_add(num_writes, Ordering::SeqCst);
+        if prev == 0 && num_writes > 0 {
+            if let Some(marker) = &self.uncommitted_marker {
+                if let Err(err) = std::fs::write(marker, b"") {
+                    log::warn!("Unable to write uncommitted marker: {}", err);
+                }
+            }
+        }
+    }
+
+    pub fn num_pending(&self) -> usize {
+        self.pending_writes.load(Ordering::SeqCst)
+    }
+
+    /// Commit any pending writes to the index. Returns the number of writes
+    /// that were committed.
+    #[tracing::instrument(skip(self))]
+    pub fn commit(&self) -> anyhow::Result<usize> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Unable to get index lock"))?;
+        self.commit_writer(&mut writer)
+    }
+
+    /// Same as `commit`, for when the writer lock is already held.
+    pub fn commit_writer(&self, writer: &mut IndexWriter) -> anyhow::Result<usize> {
+        let pending = self.pending_writes.swap(0, Ordering::SeqCst);
+        if let Err(err) = writer.commit() {
+            // Nothing was committed, keep track of the pending writes.
+            self.mark_pending(pending);
+            return Err(err.into());
+        }
+
+        // Everything is safely on disk.
+        if let Some(marker) = &self.uncommitted_marker {
+            let _ = std::fs::remove_file(marker);
+        }
+
+        Ok(pending)
+    }
+
+    /// Merge all the searchable segments into one. Returns the number of
+    /// segments that were merged, 0 if a merge is already running.
+    #[tracing::instrument(skip(self))]
+    pub async fn optimize(&self) -> anyhow::Result<usize> {
+        if self.is_optimizing.swap(true, Ordering::SeqCst) {
+            log::info!("index is already being optimized");
+            return Ok(0);
+        }
+
+        let res = self.merge_segments().await;
+        self.is_optimizing.store(false, Ordering::SeqCst);
+        res
+    }
+
+    async fn merge_segments(&self) -> anyhow::Result<usize> {
+        let segment_ids = self.index.searchable_segment_ids()?;
+        if segment_ids.len() < 2 {
+            return Ok(0);
         }
+
+        // Only hold on to the lock long enough to kick off the merge.
+        let merge = {
+            let mut writer = self
+                .writer
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Unable to get index lock"))?;
+            writer.merge(&segment_ids)
+        };
+
+        merge.await?;
+        Ok(segment_ids.len())
     }
 
+    /// Generates snippets w/ the terms in `query_string` highlighted.
+    pub fn snippet_generator(&self, query_string: &str) -> tantivy::Result<SnippetGenerator> {
+        let fields = Searcher::doc_fields();
+        let query = build_query(
+            Searcher::doc_fields(),
+            &self.ranking,
+            &self.analyzer(),
+            query_string,
+            false,
+            PrefixMatch::Off,
+        );
+
+        let mut generator =
+            SnippetGenerator::create(&self.reader.searcher(), &query, fields.content)?;
+        generator.set_max_num_chars(SNIPPET_LENGTH);
+        Ok(generator)
+    }
+
+    /// Text content of `doc`. Content isn't stored in the index, so it's
+    /// extracted from the raw page again, if there is one.
+    pub fn content(doc: &Document) -> String {
+        let fields = Searcher::doc_fields();
+        let text = |field| {
+            doc.get_first(field)
+                .and_then(|value| value.as_text())
+                .unwrap_or_default()
+        };
+
+        match text(fields.raw) {
+            "" => text(fields.text).to_string(),
+            raw => html_to_text(raw).content,
+        }
+    }
+
+    /// Snippet of the content in `doc`, `None` if none of the query terms
+    /// were found in it.
+    pub fn snippet(generator: &SnippetGenerator, doc: &Document) -> Option<Snippet> {
+        let snippet = generator.snippet(&Searcher::content(doc));
+        if snippet.highlighted().is_empty() {
+            return None;
+        }
+
+        // CJK bigrams overlap, merge them into a single highlight.
+        let mut highlights: Vec<(usize, usize)> = Vec::new();
+        for range in snippet.highlighted() {
+            match highlights.last_mut() {
+                Some((_, end)) if range.start <= *end => *end = (*end).max(range.end),
+                _ => highlights.push((range.start, range.end)),
+            }
+        }
+
+        Some(Snippet {
+            text: snippet.fragment().to_string(),
+            highlights,
+        })
+    }
+
+    #[tracing::instrument(skip(writer, title, description, content, raw))]
     pub fn add_document(
         writer: &mut IndexWriter,
         title: &str,
@@ -161,6 +464,7 @@ impl Searcher {
         url: &str,
         content: &str,
         raw: &str,
+        lang: Option<&str>,
     ) -> tantivy::Result<String> {
         let fields = Searcher::doc_fields();
 
@@ -171,13 +475,23 @@ impl Searcher {
         doc.add_text(fields.domain, domain);
         doc.add_text(fields.id, &doc_id);
         doc.add_text(fields.raw, raw);
+        if raw.is_empty() {
+            doc.add_text(fields.text, content);
+        }
         doc.add_text(fields.title, title);
         doc.add_text(fields.url, url);
+        doc.add_text(fields.url_text, url);
+        doc.add_i64(fields.indexed_at, chrono::Utc::now().timestamp());
+        doc.add_facet(fields.domain_facet, Facet::from_path(vec![domain]));
+        if let Some(lang) = lang {
+            doc.add_text(fields.lang, lang);
+        }
         writer.add_document(doc)?;
 
         Ok(doc_id)
     }
 
+    #[tracing::instrument(skip(index, reader))]
     pub fn search(index: &Index, reader: &IndexReader, query_string: &str) -> Vec<SearchResult> {
         let fields = Searcher::doc_fields();
         let searcher = reader.searcher();
@@ -201,36 +515,459 @@ impl Searcher {
         top_docs.into_iter().collect()
     }
 
-    pub fn search_with_lens(
+    /// Query for `query_string` w/ the date range from `options`, restricted to
+    /// the applied lenses.
+    pub fn build_search_query(
+        &self,
         lenses: &HashMap<String, Lens>,
-        reader: &IndexReader,
         applied_lens: &[String],
         query_string: &str,
-    ) -> Vec<SearchResult> {
+        options: &SearchOptions,
+    ) -> Box<dyn Query> {
+        let query = Box::new(build_query(
+            Searcher::doc_fields(),
+            &self.ranking,
+            &self.analyzer(),
+            query_string,
+            options.fuzzy,
+            options.prefix,
+        ));
+
+        self.apply_filters(lenses, applied_lens, options, query)
+    }
+
+    /// Restrict `query` to the applied lenses & the filters in `options`.
+    fn apply_filters(
+        &self,
+        lenses: &HashMap<String, Lens>,
+        applied_lens: &[String],
+        options: &SearchOptions,
+        mut query: Box<dyn Query>,
+    ) -> Box<dyn Query> {
         let fields = Searcher::doc_fields();
-        let searcher = reader.searcher();
 
-        let query = build_query(fields, lenses, applied_lens, query_string);
+        // Dates only filter, they don't contribute to the score.
+        if let Some(date_query) =
+            build_date_query(&fields, options.indexed_after, options.indexed_before)
+        {
+            query = Box::new(BooleanQuery::new(vec![
+                (Occur::Must, query),
+                (
+                    Occur::Must,
+                    Box::new(BoostQuery::new(Box::new(date_query), 0.0)),
+                ),
+            ]));
+        }
 
-        let top_docs = searcher
-            .search(&query, &TopDocs::with_limit(5))
-            .expect("Unable to execute query");
+        // Same for `site:` & `inurl:` operators.
+        let url_filters = [
+            build_site_query(&fields, &options.sites),
+            build_url_query(&fields, &self.analyzer(), &options.url_terms),
+        ];
+        for url_query in url_filters.into_iter().flatten() {
+            query = Box::new(BooleanQuery::new(vec![
+                (Occur::Must, query),
+                (
+                    Occur::Must,
+                    Box::new(BoostQuery::new(Box::new(url_query), 0.0)),
+                ),
+            ]));
+        }
+
+        if !options.langs.is_empty() {
+            let lang_query: QueryVec = options
+                .langs
+                .iter()
+                .map(|lang| {
+                    let term_query: Box<dyn Query> = Box::new(TermQuery::new(
+                        Term::from_field_text(fields.lang, lang),
+                        IndexRecordOption::Basic,
+                    ));
+                    (Occur::Should, term_query)
+                })
+                .collect();
+
+            query = Box::new(BooleanQuery::new(vec![
+                (Occur::Must, query),
+                (
+                    Occur::Must,
+                    Box::new(BoostQuery::new(
+                        Box::new(BooleanQuery::new(lang_query)),
+                        0.0,
+                    )),
+                ),
+            ]));
+        }
+
+        match lens_filter_key(lenses, applied_lens) {
+            None => query,
+            Some(key) => Box::new(FilteredQuery::new(
+                query,
+                self.lens_filter(lenses, applied_lens, &key),
+            )),
+        }
+    }
+
+    /// Number of documents in the lens named `name`, 0 if the lens has no
+    /// domains or URLs to match on.
+    pub fn num_docs_in_lens(
+        &self,
+        lenses: &HashMap<String, Lens>,
+        name: &str,
+    ) -> anyhow::Result<usize> {
+        let applied_lens = [name.to_string()];
+        let key = match lens_filter_key(lenses, &applied_lens) {
+            Some(key) => key,
+            None => return Ok(0),
+        };
+
+        let query = FilteredQuery::new(
+            Box::new(AllQuery),
+            self.lens_filter(lenses, &applied_lens, &key),
+        );
+        Ok(self.reader.searcher().search(&query, &Count)?)
+    }
+
+    /// Top `limit` documents sharing the most distinctive terms w/ the
+    /// document `doc_id`, not including the document itself. Empty if there's
+    /// no such document.
+    pub fn similar(&self, doc_id: &str, limit: usize) -> tantivy::Result<Vec<SearchResult>> {
+        let fields = Searcher::doc_fields();
+        let doc = match Searcher::get_by_id(&self.reader, doc_id) {
+            Some(doc) => doc,
+            None => return Ok(Vec::new()),
+        };
+
+        // Only the text the user would read, not the URL, raw HTML, etc.
+        let mut doc_fields: Vec<(Field, Vec<Value>)> = [fields.title, fields.description]
+            .into_iter()
+            .map(|field| (field, doc.get_all(field).cloned().collect()))
+            .collect();
+        doc_fields.push((fields.content, vec![Value::Str(Searcher::content(&doc))]));
+        let similar = MoreLikeThisQuery::builder()
+            .with_min_doc_frequency(1)
+            .with_min_term_frequency(1)
+            .with_min_word_length(SIMILAR_MIN_WORD_LENGTH)
+            .with_max_query_terms(SIMILAR_MAX_QUERY_TERMS)
+            .with_document_fields(doc_fields);
+
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, Box::new(similar) as Box<dyn Query>),
+            (
+                Occur::MustNot,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(fields.id, doc_id),
+                    IndexRecordOption::Basic,
+                )),
+            ),
+        ]);
+
+        self.reader
+            .searcher()
+            .search(&query, &TopDocs::with_limit(limit.max(1)))
+    }
+
+    /// Names of the lenses `url` belongs to, sorted.
+    pub fn lenses_for_url(&self, lenses: &HashMap<String, Lens>, url: &str) -> Vec<String> {
+        let urls = [url.to_string()];
+        let mut names: Vec<String> = lenses
+            .keys()
+            .filter(|name| {
+                let applied_lens = [name.to_string()];
+                lens_filter_key(lenses, &applied_lens).is_some()
+                    && self
+                        .filter_urls(lenses, &applied_lens, &urls, &SearchOptions::default())
+                        .map(|matches| !matches.is_empty())
+                        .unwrap_or(false)
+            })
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Size of the index segments on disk, in bytes.
+    pub fn disk_usage(&self) -> anyhow::Result<u64> {
+        Ok(self.reader.searcher().space_usage()?.total() as u64)
+    }
+
+    /// Documents for `urls` that pass the applied lenses & the filters in
+    /// `options`, keyed by URL.
+    pub fn filter_urls(
+        &self,
+        lenses: &HashMap<String, Lens>,
+        applied_lens: &[String],
+        urls: &[String],
+        options: &SearchOptions,
+    ) -> tantivy::Result<HashMap<String, DocAddress>> {
+        if urls.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let fields = Searcher::doc_fields();
+        let url_query: QueryVec = urls
+            .iter()
+            .map(|url| {
+                let term_query: Box<dyn Query> = Box::new(TermQuery::new(
+                    Term::from_field_text(fields.url, url),
+                    IndexRecordOption::Basic,
+                ));
+                (Occur::Should, term_query)
+            })
+            .collect();
+        let query = self.apply_filters(
+            lenses,
+            applied_lens,
+            options,
+            Box::new(BooleanQuery::new(url_query)),
+        );
+
+        let searcher = self.reader.searcher();
+        let mut matches = HashMap::new();
+        for (_, address) in searcher.search(&query, &TopDocs::with_limit(urls.len()))? {
+            let doc = searcher.doc(address)?;
+            if let Some(url) = doc.get_first(fields.url).and_then(|value| value.as_text()) {
+                matches.insert(url.to_string(), address);
+            }
+        }
+
+        Ok(matches)
+    }
+
+    /// Cached filter for the applied lenses, see `filter::LensFilterCache`.
+    fn lens_filter(
+        &self,
+        lenses: &HashMap<String, Lens>,
+        applied_lens: &[String],
+        key: &str,
+    ) -> SegmentFilters {
+        self.lens_filters
+            .get_or_build(&self.reader.searcher(), key, || {
+                Box::new(build_lens_query(
+                    Searcher::doc_fields(),
+                    lenses,
+                    applied_lens,
+                ))
+            })
+            .expect("Unable to build lens filter")
+    }
+
+    /// Score multiplier & filter for each of the applied lenses. Empty unless
+    /// several lenses are applied & they don't all have the same boost.
+    fn lens_boosts(
+        &self,
+        lenses: &HashMap<String, Lens>,
+        applied_lens: &[String],
+    ) -> Vec<(Score, SegmentFilters)> {
+        let applied: Vec<&Lens> = applied_lens
+            .iter()
+            .filter_map(|name| lenses.get(name))
+            .collect();
+
+        let first_boost = match applied.first() {
+            Some(lens) => lens.boost(),
+            None => return Vec::new(),
+        };
+        if applied.iter().all(|lens| lens.boost() == first_boost) {
+            return Vec::new();
+        }
+
+        let mut boosts = Vec::new();
+        for lens in applied {
+            let lens_name = [lens.name.clone()];
+            if let Some(key) = lens_filter_key(lenses, &lens_name) {
+                boosts.push((lens.boost(), self.lens_filter(lenses, &lens_name, &key)));
+            }
+        }
+
+        boosts
+    }
+
+    /// Score multiplier for a document indexed `age_secs` ago. Decays from
+    /// `1.0 + RECENCY_WEIGHT` to 1.0, halving the boost every `half_life_secs`.
+    fn recency_boost(age_secs: i64, half_life_secs: f64) -> Score {
+        let age = age_secs.max(0) as f64;
+        1.0 + RECENCY_WEIGHT * 0.5_f64.powf(age / half_life_secs) as Score
+    }
+
+    /// Page of the top results for `query_string`, restricted to the applied
+    /// lenses, along w/ the total number of matching documents.
+    #[tracing::instrument(skip(self, lenses))]
+    pub fn search_with_lens(
+        &self,
+        lenses: &HashMap<String, Lens>,
+        applied_lens: &[String],
+        query_string: &str,
+        options: &SearchOptions,
+    ) -> (usize, Vec<SearchResult>) {
+        let query = self.build_search_query(lenses, applied_lens, query_string, options);
+        self.search_query(query.as_ref(), lenses, applied_lens, options)
+    }
+
+    /// Same as `search_with_lens` w/ a query from `build_search_query`, so
+    /// building & running the query can be timed separately.
+    #[tracing::instrument(skip(self, query, lenses))]
+    pub fn search_query(
+        &self,
+        query: &dyn Query,
+        lenses: &HashMap<String, Lens>,
+        applied_lens: &[String],
+        options: &SearchOptions,
+    ) -> (usize, Vec<SearchResult>) {
+        let searcher = self.reader.searcher();
+        let top_docs = TopDocs::with_limit(options.limit.max(1)).and_offset(options.offset);
+
+        let boosts = self.lens_boosts(lenses, applied_lens);
+        let half_life_secs = self
+            .ranking
+            .recency_half_life_days
+            .map(|days| days as f64 * 86_400.0);
+        let (top_docs, num_hits) = if boosts.is_empty() && half_life_secs.is_none() {
+            searcher.search(query, &(top_docs, Count))
+        } else {
+            let indexed_at = Searcher::doc_fields().indexed_at;
+            let now = chrono::Utc::now().timestamp();
+            let boosted = top_docs.tweak_score(move |segment: &SegmentReader| {
+                let boosts: Vec<(Score, Arc<DocBits>)> = boosts
+                    .iter()
+                    .map(|(boost, filter)| (*boost, filter.for_segment(segment)))
+                    .collect();
+                let indexed_at =
+                    half_life_secs.and_then(|_| segment.fast_fields().i64(indexed_at).ok());
+
+                move |doc: DocId, score: Score| {
+                    // Documents in several lenses get the biggest of their boosts.
+                    let lens_boost = boosts
+                        .iter()
+                        .filter(|(_, docs)| docs.contains(doc))
+                        .map(|(boost, _)| *boost)
+                        .reduce(Score::max)
+                        .unwrap_or(1.0);
+
+                    let recency_boost = match (&indexed_at, half_life_secs) {
+                        (Some(indexed_at), Some(half_life_secs)) => {
+                            Searcher::recency_boost(now - indexed_at.get(doc), half_life_secs)
+                        }
+                        _ => 1.0,
+                    };
+
+                    score * lens_boost * recency_boost
+                }
+            });
+            searcher.search(query, &(boosted, Count))
+        }
+        .expect("Unable to execute query");
 
         log::info!(
-            "query `{}` returned {} results from {} docs",
-            query_string,
+            "query returned {} of {} results from {} docs",
             top_docs.len(),
+            num_hits,
             searcher.num_docs(),
         );
 
-        top_docs.into_iter().collect()
+        (num_hits, top_docs)
+    }
+
+    /// Breakdown of the score `search_query` gave the document at `address`.
+    pub fn explain(
+        &self,
+        query: &dyn Query,
+        lenses: &HashMap<String, Lens>,
+        applied_lens: &[String],
+        address: DocAddress,
+    ) -> anyhow::Result<ScoreBreakdown> {
+        let searcher = self.reader.searcher();
+        let explanation = query.explain(&searcher, address)?;
+        let segment = searcher.segment_reader(address.segment_ord);
+
+        let lens_boost = self
+            .lens_boosts(lenses, applied_lens)
+            .iter()
+            .filter(|(_, filter)| filter.for_segment(segment).contains(address.doc_id))
+            .map(|(boost, _)| *boost)
+            .reduce(Score::max)
+            .unwrap_or(1.0);
+
+        let recency_boost = match self.ranking.recency_half_life_days {
+            Some(days) => {
+                let indexed_at = segment
+                    .fast_fields()
+                    .i64(Searcher::doc_fields().indexed_at)?
+                    .get(address.doc_id);
+                let age_secs = chrono::Utc::now().timestamp() - indexed_at;
+                Searcher::recency_boost(age_secs, days as f64 * 86_400.0)
+            }
+            None => 1.0,
+        };
+
+        Ok(ScoreBreakdown {
+            lens_boost,
+            recency_boost,
+            explanation: serde_json::from_value(serde_json::to_value(&explanation)?)?,
+        })
+    }
+
+    /// Number of documents matching the search in each domain & each of the
+    /// installed lenses, most hits first. Lenses w/o any hits are left out.
+    #[tracing::instrument(skip(self, lenses))]
+    pub fn facet_counts(
+        &self,
+        lenses: &HashMap<String, Lens>,
+        applied_lens: &[String],
+        query_string: &str,
+        options: &SearchOptions,
+    ) -> tantivy::Result<SearchFacets> {
+        let fields = Searcher::doc_fields();
+        let searcher = self.reader.searcher();
+        let query = self.build_search_query(lenses, applied_lens, query_string, options);
+
+        let mut collector = FacetCollector::for_field(fields.domain_facet);
+        collector.add_facet(Facet::root());
+        let counts = searcher.search(query.as_ref(), &collector)?;
+        let mut domains: Vec<FacetCount> = counts
+            .get(Facet::root())
+            .filter_map(|(facet, count)| {
+                facet.to_path().last().map(|domain| FacetCount {
+                    value: domain.to_string(),
+                    count,
+                })
+            })
+            .collect();
+
+        let mut lens_counts = Vec::new();
+        for (name, lens) in lenses.iter().filter(|(_, lens)| lens.is_enabled) {
+            let lens_name = [name.clone()];
+            let key = match lens_filter_key(lenses, &lens_name) {
+                Some(key) => key,
+                // Matches everything, not much of a filter.
+                None => continue,
+            };
+
+            let filter = self.lens_filter(lenses, &lens_name, &key);
+            let count = searcher.search(&FilteredQuery::new(query.box_clone(), filter), &Count)?;
+            if count > 0 {
+                lens_counts.push(FacetCount {
+                    value: lens.name.clone(),
+                    count: count as u64,
+                });
+            }
+        }
+
+        for counts in [&mut domains, &mut lens_counts] {
+            counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        }
+
+        Ok(SearchFacets {
+            domains,
+            lenses: lens_counts,
+        })
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::search::{IndexPath, Searcher};
-    use shared::config::Lens;
+    use crate::search::{IndexPath, SearchOptions, Searcher};
+    use shared::config::{Lens, PrefixMatch, UserSettings};
     use std::collections::HashMap;
 
     fn _build_test_index(searcher: &mut Searcher) {
@@ -250,6 +987,7 @@ mod test {
             debris of the winter’s flooding; and sycamores with mottled, white, recumbent
             limbs and branches that arch over the pool",
             "",
+            None,
         )
         .expect("Unable to add doc");
 
@@ -268,6 +1006,7 @@ mod test {
             debris of the winter’s flooding; and sycamores with mottled, white, recumbent
             limbs and branches that arch over the pool",
             "",
+            None,
         )
         .expect("Unable to add doc");
 
@@ -285,6 +1024,7 @@ mod test {
             ac volutpat massa. Vivamus sed imperdiet est, id pretium ex. Praesent suscipit
             mattis ipsum, a lacinia nunc semper vitae.",
             "",
+            None,
         )
         .expect("Unable to add doc");
 
@@ -299,6 +1039,7 @@ mod test {
              yesterday, and my first task is to assure my dear sister of my welfare and
              increasing confidence in the success of my undertaking.",
             "",
+            None,
         )
         .expect("Unable to add doc");
 
@@ -338,7 +1079,8 @@ mod test {
         _build_test_index(&mut searcher);
 
         let query = "salinas";
-        let results = Searcher::search_with_lens(&lenses, &searcher.reader, &applied_lens, query);
+        let (_, results) =
+            searcher.search_with_lens(&lenses, &applied_lens, query, &SearchOptions::default());
         assert_eq!(results.len(), 1);
     }
 
@@ -360,7 +1102,8 @@ mod test {
         _build_test_index(&mut searcher);
 
         let query = "salinas";
-        let results = Searcher::search_with_lens(&lenses, &searcher.reader, &applied_lens, query);
+        let (_, results) =
+            searcher.search_with_lens(&lenses, &applied_lens, query, &SearchOptions::default());
         assert_eq!(results.len(), 1);
     }
 
@@ -382,7 +1125,778 @@ mod test {
         _build_test_index(&mut searcher);
 
         let query = "salinas";
-        let results = Searcher::search_with_lens(&lenses, &searcher.reader, &applied_lens, query);
+        let (_, results) =
+            searcher.search_with_lens(&lenses, &applied_lens, query, &SearchOptions::default());
         assert_eq!(results.len(), 0);
     }
+
+    #[test]
+    pub fn test_cached_lens_filter() {
+        let lens = Lens {
+            name: "wiki".to_string(),
+            domains: vec!["en.wikipedia.org".to_string()],
+            urls: Vec::new(),
+            ..Default::default()
+        };
+
+        let applied_lens = vec!["wiki".to_string()];
+
+        let mut lenses = HashMap::new();
+        lenses.insert("wiki".to_string(), lens.clone());
+
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+        assert!(searcher.lens_filters.is_empty());
+
+        let (_, results) =
+            searcher.search_with_lens(&lenses, &applied_lens, "salinas", &SearchOptions::default());
+        assert_eq!(results.len(), 1);
+        assert_eq!(searcher.lens_filters.len(), 1);
+
+        // Second run is served from the cache
+        let (_, cached) =
+            searcher.search_with_lens(&lenses, &applied_lens, "salinas", &SearchOptions::default());
+        assert_eq!(results, cached);
+        assert_eq!(searcher.lens_filters.len(), 1);
+
+        // No lens, no filter
+        let (_, results) =
+            searcher.search_with_lens(&lenses, &[], "salinas", &SearchOptions::default());
+        assert_eq!(results.len(), 2);
+        assert_eq!(searcher.lens_filters.len(), 1);
+    }
+
+    #[test]
+    pub fn test_num_docs_in_lens() {
+        let lens = Lens {
+            name: "wiki".to_string(),
+            domains: vec!["en.wikipedia.org".to_string()],
+            urls: Vec::new(),
+            ..Default::default()
+        };
+
+        let mut lenses = HashMap::new();
+        lenses.insert("wiki".to_string(), lens);
+        lenses.insert("empty".to_string(), Lens::default());
+
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        assert_eq!(searcher.num_docs_in_lens(&lenses, "wiki").unwrap(), 2);
+        assert_eq!(searcher.num_docs_in_lens(&lenses, "empty").unwrap(), 0);
+        assert_eq!(searcher.num_docs_in_lens(&lenses, "missing").unwrap(), 0);
+    }
+
+    #[test]
+    pub fn test_explain() {
+        let mut lenses = HashMap::new();
+        lenses.insert(
+            "wiki".to_string(),
+            Lens {
+                name: "wiki".to_string(),
+                domains: vec!["en.wikipedia.org".to_string()],
+                boost: Some(2.0),
+                ..Default::default()
+            },
+        );
+        lenses.insert(
+            "example".to_string(),
+            Lens {
+                name: "example".to_string(),
+                domains: vec!["example.com".to_string()],
+                ..Default::default()
+            },
+        );
+        let applied_lens = vec!["wiki".to_string(), "example".to_string()];
+
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let options = SearchOptions::default();
+        let query = searcher.build_search_query(&lenses, &applied_lens, "salinas", &options);
+        let (_, results) = searcher.search_query(query.as_ref(), &lenses, &applied_lens, &options);
+        assert_eq!(results.len(), 2);
+
+        let fields = Searcher::doc_fields();
+        let reader = searcher.reader.searcher();
+        for (score, address) in results {
+            let breakdown = searcher
+                .explain(query.as_ref(), &lenses, &applied_lens, address)
+                .unwrap();
+            let doc = reader.doc(address).unwrap();
+            let domain = doc.get_first(fields.domain).unwrap().as_text().unwrap();
+            let expected_boost = if domain == "en.wikipedia.org" {
+                2.0
+            } else {
+                1.0
+            };
+
+            assert_eq!(breakdown.lens_boost, expected_boost);
+            assert_eq!(breakdown.recency_boost, 1.0);
+            assert!((breakdown.explanation.value * breakdown.lens_boost - score).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    pub fn test_similar() {
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let fields = Searcher::doc_fields();
+        let reader = searcher.reader.searcher();
+        let doc_id_for = |url: &str| {
+            let (_, address) = searcher
+                .filter_urls(
+                    &HashMap::new(),
+                    &[],
+                    &[url.to_string()],
+                    &SearchOptions::default(),
+                )
+                .unwrap()
+                .into_iter()
+                .next()
+                .unwrap();
+            let doc = reader.doc(address).unwrap();
+            doc.get_first(fields.id)
+                .unwrap()
+                .as_text()
+                .unwrap()
+                .to_string()
+        };
+
+        let doc_id = doc_id_for("https://example.com/mice_and_men");
+        let similar = searcher.similar(&doc_id, 10).unwrap();
+        assert!(!similar.is_empty());
+
+        let (_, top) = similar[0];
+        let top = reader.doc(top).unwrap();
+        assert_eq!(
+            top.get_first(fields.url).unwrap().as_text(),
+            Some("https://en.wikipedia.org/mice_and_men")
+        );
+        // Never the document itself
+        assert!(similar.iter().all(|(_, address)| {
+            let doc = reader.doc(*address).unwrap();
+            doc.get_first(fields.id).unwrap().as_text() != Some(doc_id.as_str())
+        }));
+
+        assert!(searcher.similar("missing", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_lenses_for_url() {
+        let mut lenses = HashMap::new();
+        lenses.insert(
+            "wiki".to_string(),
+            Lens {
+                name: "wiki".to_string(),
+                domains: vec!["en.wikipedia.org".to_string()],
+                ..Default::default()
+            },
+        );
+        lenses.insert(
+            "mice".to_string(),
+            Lens {
+                name: "mice".to_string(),
+                urls: vec!["https://en.wikipedia.org/mice".to_string()],
+                ..Default::default()
+            },
+        );
+        lenses.insert("empty".to_string(), Lens::default());
+
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        assert_eq!(
+            searcher.lenses_for_url(&lenses, "https://en.wikipedia.org/mice_and_men"),
+            vec!["mice", "wiki"]
+        );
+        assert!(searcher
+            .lenses_for_url(&lenses, "https://example.com/mice_and_men")
+            .is_empty());
+    }
+
+    #[test]
+    pub fn test_paginated_search() {
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let lenses = HashMap::new();
+        let (total, first) = searcher.search_with_lens(
+            &lenses,
+            &[],
+            "salinas",
+            &SearchOptions {
+                limit: 1,
+                ..Default::default()
+            },
+        );
+        assert_eq!(total, 2);
+        assert_eq!(first.len(), 1);
+
+        let (total, second) = searcher.search_with_lens(
+            &lenses,
+            &[],
+            "salinas",
+            &SearchOptions {
+                offset: 1,
+                limit: 1,
+                ..Default::default()
+            },
+        );
+        assert_eq!(total, 2);
+        assert_eq!(second.len(), 1);
+        assert_ne!(first[0].1, second[0].1);
+
+        // Past the last page
+        let (total, rest) = searcher.search_with_lens(
+            &lenses,
+            &[],
+            "salinas",
+            &SearchOptions {
+                offset: 2,
+                limit: 1,
+                ..Default::default()
+            },
+        );
+        assert_eq!(total, 2);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    pub fn test_fuzzy_search() {
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let lenses = HashMap::new();
+        let (total, _) =
+            searcher.search_with_lens(&lenses, &[], "salinsa", &SearchOptions::default());
+        assert_eq!(total, 0);
+
+        // Transposed letters
+        let (total, _) = searcher.search_with_lens(
+            &lenses,
+            &[],
+            "salinsa",
+            &SearchOptions {
+                fuzzy: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(total, 2);
+        // Two edits away
+        let (total, _) = searcher.search_with_lens(
+            &lenses,
+            &[],
+            "frankinstien",
+            &SearchOptions {
+                fuzzy: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(total, 1);
+
+        // Exact matches still rank first
+        let (_, results) = searcher.search_with_lens(
+            &lenses,
+            &[],
+            "mice mouse",
+            &SearchOptions {
+                fuzzy: true,
+                ..Default::default()
+            },
+        );
+        let fields = Searcher::doc_fields();
+        let doc = searcher.reader.searcher().doc(results[0].1).unwrap();
+        assert_eq!(
+            doc.get_first(fields.title).unwrap().as_text(),
+            Some("Of Mice and Men")
+        );
+    }
+
+    #[test]
+    pub fn test_prefix_search() {
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let lenses = HashMap::new();
+        let num_hits = |query: &str, prefix: PrefixMatch| {
+            let options = SearchOptions {
+                prefix,
+                ..Default::default()
+            };
+            searcher.search_with_lens(&lenses, &[], query, &options).0
+        };
+
+        assert_eq!(num_hits("gabil", PrefixMatch::Off), 0);
+        assert_eq!(num_hits("gabil", PrefixMatch::LastTerm), 2);
+        assert_eq!(num_hits("frank", PrefixMatch::LastTerm), 1);
+        // Only the word being typed
+        assert_eq!(num_hits("frank lorem", PrefixMatch::LastTerm), 1);
+        assert_eq!(num_hits("frank lore", PrefixMatch::AllTerms), 2);
+        assert_eq!(num_hits("gabil ", PrefixMatch::LastTerm), 0);
+        // Works w/ operators too
+        assert_eq!(num_hits("salinas -gabil", PrefixMatch::LastTerm), 2);
+        assert_eq!(num_hits("lorem OR franken", PrefixMatch::LastTerm), 2);
+    }
+
+    #[test]
+    pub fn test_phrase_and_boolean_search() {
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let lenses = HashMap::new();
+        let num_hits = |query: &str| {
+            searcher
+                .search_with_lens(&lenses, &[], query, &SearchOptions::default())
+                .0
+        };
+
+        assert_eq!(num_hits("\"salinas river\""), 2);
+        assert_eq!(num_hits("\"river salinas\""), 0);
+        // Phrases are required
+        assert_eq!(num_hits("\"river salinas\" lorem"), 0);
+        assert_eq!(num_hits("\"salinas river\" OR frankenstein"), 3);
+
+        assert_eq!(num_hits("lorem AND salinas"), 0);
+        assert_eq!(num_hits("lorem OR salinas"), 3);
+
+        assert_eq!(num_hits("mice -soledad"), 0);
+        assert_eq!(num_hits("-salinas"), 2);
+        assert_eq!(num_hits("-\"salinas river\" -lorem"), 1);
+    }
+
+    #[test]
+    pub fn test_field_boosts() {
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let lenses = HashMap::new();
+        let fields = Searcher::doc_fields();
+        let top_title = |searcher: &Searcher, query: &str| {
+            let (_, results) =
+                searcher.search_with_lens(&lenses, &[], query, &SearchOptions::default());
+            let doc = searcher.reader.searcher().doc(results[0].1).unwrap();
+            doc.get_first(fields.title)
+                .and_then(|value| value.as_text())
+                .map(|title| title.to_string())
+        };
+
+        // "cheese" is in a title & URL, "soledad" only in content
+        assert_eq!(
+            top_title(&searcher, "cheese soledad").as_deref(),
+            Some("Of Cheese and Crackers")
+        );
+
+        searcher.ranking.title_boost = 0.0;
+        searcher.ranking.url_boost = 0.0;
+        assert_eq!(
+            top_title(&searcher, "cheese soledad").as_deref(),
+            Some("Of Mice and Men")
+        );
+
+        // Only found in URLs
+        let (total, _) =
+            searcher.search_with_lens(&lenses, &[], "wikipedia", &SearchOptions::default());
+        assert_eq!(total, 2);
+    }
+
+    #[test]
+    pub fn test_date_range_search() {
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let lenses = HashMap::new();
+        let now = chrono::Utc::now().timestamp();
+        let num_hits = |after: Option<i64>, before: Option<i64>| {
+            let options = SearchOptions {
+                indexed_after: after,
+                indexed_before: before,
+                ..Default::default()
+            };
+            searcher
+                .search_with_lens(&lenses, &[], "salinas", &options)
+                .0
+        };
+
+        assert_eq!(num_hits(None, None), 2);
+        assert_eq!(num_hits(Some(now - 3600), None), 2);
+        assert_eq!(num_hits(Some(now + 3600), None), 0);
+        assert_eq!(num_hits(None, Some(now - 3600)), 0);
+        assert_eq!(num_hits(Some(now - 3600), Some(now + 3600)), 2);
+    }
+
+    #[test]
+    pub fn test_filter_urls() {
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let lenses = HashMap::new();
+        let urls = vec![
+            "https://example.com/mice_and_men".to_string(),
+            "https://example.com/frankenstein".to_string(),
+            "https://example.com/not_indexed".to_string(),
+        ];
+
+        let matches = searcher
+            .filter_urls(&lenses, &[], &urls, &SearchOptions::default())
+            .unwrap();
+        assert_eq!(matches.len(), 2);
+        assert!(matches.contains_key("https://example.com/frankenstein"));
+
+        let options = SearchOptions {
+            indexed_after: Some(chrono::Utc::now().timestamp() + 3600),
+            ..Default::default()
+        };
+        let matches = searcher.filter_urls(&lenses, &[], &urls, &options).unwrap();
+        assert!(matches.is_empty());
+    }
+
+    #[test]
+    pub fn test_lang_search() {
+        let searcher = Searcher::with_index(&IndexPath::Memory);
+        {
+            let mut writer = searcher.writer.lock().unwrap();
+            for (url, content, lang) in [
+                (
+                    "https://example.com/en",
+                    "Rust borrow checker guide",
+                    Some("en"),
+                ),
+                (
+                    "https://example.de/de",
+                    "Rust Anleitung für Anfänger",
+                    Some("de"),
+                ),
+                ("https://example.org/unknown", "Rust", None),
+            ] {
+                Searcher::add_document(
+                    &mut writer,
+                    "Rust",
+                    "",
+                    "example.com",
+                    url,
+                    content,
+                    "",
+                    lang,
+                )
+                .unwrap();
+            }
+            writer.commit().unwrap();
+        }
+        searcher.reader.reload().unwrap();
+
+        let lenses = HashMap::new();
+        let num_hits = |langs: &[&str]| {
+            let options = SearchOptions {
+                langs: langs.iter().map(|lang| lang.to_string()).collect(),
+                ..Default::default()
+            };
+            searcher.search_with_lens(&lenses, &[], "rust", &options).0
+        };
+
+        assert_eq!(num_hits(&[]), 3);
+        assert_eq!(num_hits(&["en"]), 1);
+        assert_eq!(num_hits(&["de"]), 1);
+        assert_eq!(num_hits(&["en", "de"]), 2);
+        assert_eq!(num_hits(&["fr"]), 0);
+    }
+
+    #[test]
+    pub fn test_recency_boost() {
+        let day = 86_400.0;
+        assert_eq!(Searcher::recency_boost(0, day), 1.5);
+        assert_eq!(Searcher::recency_boost(86_400, day), 1.25);
+        // Indexed "in the future" due to clock changes
+        assert_eq!(Searcher::recency_boost(-10, day), 1.5);
+        assert!(Searcher::recency_boost(365 * 86_400, day) < 1.001);
+
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let lenses = HashMap::new();
+        let options = SearchOptions::default();
+        let (_, results) = searcher.search_with_lens(&lenses, &[], "salinas", &options);
+
+        // Everything was just indexed, so scores get the full boost.
+        searcher.ranking.recency_half_life_days = Some(30.0);
+        let (_, boosted) = searcher.search_with_lens(&lenses, &[], "salinas", &options);
+        assert_eq!(results.len(), boosted.len());
+        for ((score, _), (boosted_score, _)) in results.iter().zip(boosted.iter()) {
+            assert!((boosted_score / score - 1.5).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    pub fn test_url_filters() {
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let lenses = HashMap::new();
+        let num_hits = |sites: &[&str], url_terms: &[&str]| {
+            let options = SearchOptions {
+                sites: sites.iter().map(|site| site.to_string()).collect(),
+                url_terms: url_terms.iter().map(|term| term.to_string()).collect(),
+                ..Default::default()
+            };
+            searcher
+                .search_with_lens(&lenses, &[], "salinas OR cheese", &options)
+                .0
+        };
+
+        assert_eq!(num_hits(&[], &[]), 3);
+        assert_eq!(num_hits(&["example.com"], &[]), 1);
+        // Subdomains count too
+        assert_eq!(num_hits(&["wikipedia.org"], &[]), 2);
+        assert_eq!(num_hits(&["example.com", "en.wikipedia.org"], &[]), 3);
+        assert_eq!(num_hits(&["ikipedia.org"], &[]), 0);
+
+        assert_eq!(num_hits(&[], &["crackers"]), 1);
+        assert_eq!(num_hits(&[], &["mice", "wikipedia"]), 1);
+        assert_eq!(num_hits(&["example.com"], &["crackers"]), 0);
+    }
+
+    #[test]
+    pub fn test_cjk_search() {
+        let searcher = Searcher::with_index(&IndexPath::Memory);
+        {
+            let mut writer = searcher.writer.lock().unwrap();
+            Searcher::add_document(
+                &mut writer,
+                "東京タワー",
+                "",
+                "example.jp",
+                "https://example.jp/tower",
+                "東京タワーは東京都港区芝公園にある総合電波塔です。",
+                "",
+                None,
+            )
+            .unwrap();
+            writer.commit().unwrap();
+        }
+        searcher.reader.reload().unwrap();
+
+        let lenses = HashMap::new();
+        let options = SearchOptions::default();
+        assert_eq!(
+            searcher.search_with_lens(&lenses, &[], "港区", &options).0,
+            1
+        );
+        assert_eq!(
+            searcher
+                .search_with_lens(&lenses, &[], "電波塔", &options)
+                .0,
+            1
+        );
+        assert_eq!(
+            searcher
+                .search_with_lens(&lenses, &[], "\"芝公園\"", &options)
+                .0,
+            1
+        );
+        assert_eq!(
+            searcher.search_with_lens(&lenses, &[], "大阪", &options).0,
+            0
+        );
+
+        let (_, results) = searcher.search_with_lens(&lenses, &[], "港区", &options);
+        let generator = searcher.snippet_generator("港区").unwrap();
+        let doc = searcher.reader.searcher().doc(results[0].1).unwrap();
+        let snippet = Searcher::snippet(&generator, &doc).unwrap();
+        let (start, end) = snippet.highlights[0];
+        assert_eq!(&snippet.text[start..end], "港区");
+    }
+
+    #[test]
+    pub fn test_stemmed_search() {
+        let index_doc = |searcher: &Searcher| {
+            let mut writer = searcher.writer.lock().unwrap();
+            Searcher::add_document(
+                &mut writer,
+                "Web crawlers",
+                "",
+                "example.com",
+                "https://example.com/crawlers",
+                "The spider crawled every page it could find.",
+                "",
+                None,
+            )
+            .unwrap();
+            writer.commit().unwrap();
+        };
+
+        let lenses = HashMap::new();
+        let options = SearchOptions::default();
+
+        let searcher = Searcher::with_index(&IndexPath::Memory);
+        index_doc(&searcher);
+        searcher.reader.reload().unwrap();
+        for query in ["crawling", "crawl", "\"spider crawls\"", "pages"].iter() {
+            assert_eq!(
+                searcher.search_with_lens(&lenses, &[], query, &options).0,
+                1,
+                "{}",
+                query
+            );
+        }
+
+        // Only exact matches w/o stemming
+        let settings = UserSettings {
+            stemming_languages: Vec::new(),
+            ..Default::default()
+        };
+        let searcher = Searcher::with_settings(&IndexPath::Memory, &settings);
+        index_doc(&searcher);
+        searcher.reader.reload().unwrap();
+        assert_eq!(
+            searcher
+                .search_with_lens(&lenses, &[], "crawling", &options)
+                .0,
+            0
+        );
+        assert_eq!(
+            searcher
+                .search_with_lens(&lenses, &[], "crawled", &options)
+                .0,
+            1
+        );
+    }
+
+    #[test]
+    pub fn test_facet_counts() {
+        let lens = |name: &str, domains: &[&str]| Lens {
+            name: name.to_string(),
+            domains: domains.iter().map(|d| d.to_string()).collect(),
+            ..Default::default()
+        };
+
+        let mut lenses = HashMap::new();
+        lenses.insert("wiki".to_string(), lens("wiki", &["en.wikipedia.org"]));
+        lenses.insert("monsters".to_string(), lens("monsters", &["monster.com"]));
+
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let options = SearchOptions::default();
+        let facets = searcher
+            .facet_counts(&lenses, &[], "salinas OR cheese", &options)
+            .unwrap();
+        let counts: Vec<(&str, u64)> = facets
+            .domains
+            .iter()
+            .map(|facet| (facet.value.as_str(), facet.count))
+            .collect();
+        assert_eq!(counts, vec![("en.wikipedia.org", 2), ("example.com", 1)]);
+        // No hits in the monsters lens
+        assert_eq!(facets.lenses.len(), 1);
+        assert_eq!(facets.lenses[0].value, "wiki");
+        assert_eq!(facets.lenses[0].count, 2);
+
+        // Counts are restricted to the applied lenses
+        let facets = searcher
+            .facet_counts(&lenses, &["wiki".to_string()], "salinas", &options)
+            .unwrap();
+        assert_eq!(facets.domains.len(), 1);
+        assert_eq!(facets.domains[0].value, "en.wikipedia.org");
+        assert_eq!(facets.domains[0].count, 1);
+    }
+
+    #[test]
+    pub fn test_lens_boost() {
+        let lens = |name: &str, domain: &str, boost: Option<f32>| Lens {
+            name: name.to_string(),
+            domains: vec![domain.to_string()],
+            boost,
+            ..Default::default()
+        };
+
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+        let fields = Searcher::doc_fields();
+        let applied_lens = vec!["example".to_string(), "wiki".to_string()];
+        let top_url = |lenses: &HashMap<String, Lens>| {
+            let (_, results) = searcher.search_with_lens(
+                lenses,
+                &applied_lens,
+                "salinas",
+                &SearchOptions::default(),
+            );
+            assert_eq!(results.len(), 2);
+            let doc = searcher.reader.searcher().doc(results[0].1).unwrap();
+            doc.get_first(fields.url)
+                .and_then(|value| value.as_text())
+                .map(|url| url.to_string())
+                .unwrap()
+        };
+
+        let mut lenses = HashMap::new();
+        lenses.insert("example".to_string(), lens("example", "example.com", None));
+        lenses.insert(
+            "wiki".to_string(),
+            lens("wiki", "en.wikipedia.org", Some(2.0)),
+        );
+        assert_eq!(top_url(&lenses), "https://en.wikipedia.org/mice_and_men");
+
+        lenses.insert(
+            "example".to_string(),
+            lens("example", "example.com", Some(3.0)),
+        );
+        assert_eq!(top_url(&lenses), "https://example.com/mice_and_men");
+    }
+
+    #[test]
+    pub fn test_snippet() {
+        let mut searcher = Searcher::with_index(&IndexPath::Memory);
+        _build_test_index(&mut searcher);
+
+        let (_, results) =
+            searcher.search_with_lens(&HashMap::new(), &[], "salinas", &SearchOptions::default());
+        assert_eq!(results.len(), 2);
+
+        let generator = searcher.snippet_generator("salinas").unwrap();
+        let doc = searcher.reader.searcher().doc(results[0].1).unwrap();
+        let snippet = Searcher::snippet(&generator, &doc).unwrap();
+        assert_eq!(snippet.highlights.len(), 1);
+
+        let (start, end) = snippet.highlights[0];
+        assert_eq!(&snippet.text[start..end], "Salinas");
+
+        // Nothing in the content matched
+        let generator = searcher.snippet_generator("cheese").unwrap();
+        assert!(Searcher::snippet(&generator, &doc).is_none());
+    }
+
+    #[test]
+    pub fn test_content() {
+        let searcher = Searcher::with_index(&IndexPath::Memory);
+        let doc_id = {
+            let mut writer = searcher.writer.lock().unwrap();
+            let doc_id = Searcher::add_document(
+                &mut writer,
+                "Salinas",
+                "",
+                "example.com",
+                "https://example.com/salinas",
+                "The Salinas River drops in close to the hillside",
+                "<html><body><p>The Salinas River drops in close to the hillside</p></body></html>",
+                None,
+            )
+            .unwrap();
+            writer.commit().unwrap();
+            doc_id
+        };
+        searcher.reader.reload().unwrap();
+
+        // Only the raw page is stored
+        let doc = Searcher::get_by_id(&searcher.reader, &doc_id).unwrap();
+        let fields = Searcher::doc_fields();
+        assert!(doc.get_first(fields.content).is_none());
+        assert!(doc.get_first(fields.text).is_none());
+        assert!(Searcher::content(&doc).contains("Salinas River drops"));
+
+        let generator = searcher.snippet_generator("river").unwrap();
+        let snippet = Searcher::snippet(&generator, &doc).unwrap();
+        let (start, end) = snippet.highlights[0];
+        assert_eq!(&snippet.text[start..end], "River");
+    }
 }
@@ -1,15 +1,33 @@
 use std::collections::HashMap;
+use std::ops::Bound;
 
 use entities::regex::regex_for_prefix;
-use tantivy::query::{BooleanQuery, BoostQuery, Occur, Query, RegexQuery, TermQuery};
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, FuzzyTermQuery, Occur, PhraseQuery, Query, RangeQuery,
+    RegexQuery, TermQuery,
+};
 use tantivy::schema::*;
+use tantivy::tokenizer::TextAnalyzer;
 use tantivy::Score;
 
 use super::DocFields;
-use shared::config::Lens;
+use crate::scraper::lang;
+use shared::config::{Lens, PrefixMatch, RankingSettings};
 
 type QueryVec = Vec<(Occur, Box<dyn Query>)>;
 
+/// Terms shorter than this are only matched exactly, there are too many
+/// words within a typo or two of them.
+const MIN_FUZZY_TERM_LEN: usize = 3;
+/// Terms longer than this can be 2 edits away, otherwise only 1.
+const MAX_SINGLE_EDIT_TERM_LEN: usize = 5;
+/// Fuzzy matches count for less than exact ones.
+const FUZZY_BOOST: Score = 0.5;
+/// Shorter prefixes match too many words to be useful.
+const MIN_PREFIX_LEN: usize = 2;
+/// Words that only start w/ a term count for less than exact matches.
+const PREFIX_BOOST: Score = 0.5;
+
 fn _boosted_term(field: Field, term: &str, boost: Score) -> Box<BoostQuery> {
     Box::new(BoostQuery::new(
         Box::new(TermQuery::new(
@@ -21,51 +39,573 @@ fn _boosted_term(field: Field, term: &str, boost: Score) -> Box<BoostQuery> {
     ))
 }
 
-pub fn build_query(
-    fields: DocFields,
-    lenses: &HashMap<String, Lens>,
-    applied_lens: &[String],
-    query_string: &str,
-) -> BooleanQuery {
-    // Tokenize query string
-    let query_string = query_string.to_lowercase();
-    let terms: Vec<&str> = query_string
-        .split(' ')
+/// Matches terms w/in 1-2 edits of `term`, depending on its length. Scored
+/// lower than an exact match.
+fn _fuzzy_term(field: Field, term: &str, boost: Score) -> Option<Box<BoostQuery>> {
+    let len = term.chars().count();
+    if len < MIN_FUZZY_TERM_LEN {
+        return None;
+    }
+
+    let distance = if len > MAX_SINGLE_EDIT_TERM_LEN { 2 } else { 1 };
+    Some(Box::new(BoostQuery::new(
+        Box::new(FuzzyTermQuery::new(
+            Term::from_field_text(field, term),
+            distance,
+            true,
+        )),
+        boost,
+    )))
+}
+
+/// Matches words starting w/ `term`, e.g. "gabi" matches "gabilan". Scored
+/// lower than an exact match.
+fn _prefix_term(field: Field, term: &str, boost: Score) -> Option<Box<BoostQuery>> {
+    if term.chars().count() < MIN_PREFIX_LEN {
+        return None;
+    }
+
+    let pattern = format!("{}.*", regex::escape(term));
+    let query = RegexQuery::from_pattern(&pattern, field).ok()?;
+    Some(Box::new(BoostQuery::new(Box::new(query), boost)))
+}
+
+/// Fields query terms are matched against & how much a match in each counts.
+fn boosted_fields(fields: &DocFields, ranking: &RankingSettings) -> [(Field, Score); 3] {
+    [
+        (fields.content, ranking.body_boost),
+        (fields.title, ranking.title_boost),
+        (fields.url_text, ranking.url_boost),
+    ]
+}
+
+/// A term or phrase from the query string.
+#[derive(Clone, Debug, PartialEq)]
+enum Atom {
+    Term(String),
+    /// Terms & their position in the phrase. Stop words leave gaps.
+    Phrase(Vec<(usize, String)>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Atom {
+        atom: Atom,
+        /// Prefixed w/ a `-`, matching documents are excluded.
+        negated: bool,
+        /// Wrapped in quotes.
+        quoted: bool,
+    },
+    And,
+    Or,
+}
+
+impl Token {
+    /// Whether this token needs more than a bag of words to handle.
+    fn is_operator(&self) -> bool {
+        match self {
+            Token::Atom {
+                negated, quoted, ..
+            } => *negated || *quoted,
+            Token::And | Token::Or => true,
+        }
+    }
+}
+
+/// Split `text` into terms the same way the indexed text is.
+fn analyze(analyzer: &TextAnalyzer, text: &str) -> Vec<String> {
+    analyze_with_positions(analyzer, text)
         .into_iter()
-        .map(|token| token.trim())
-        .collect();
+        .map(|(_, term)| term)
+        .collect()
+}
 
-    log::trace!("lenses: {:?}, terms: {:?}", applied_lens, terms);
+/// Same as `analyze`, along w/ the position of each term relative to the
+/// first one.
+fn analyze_with_positions(analyzer: &TextAnalyzer, text: &str) -> Vec<(usize, String)> {
+    let mut terms = Vec::new();
+    analyzer
+        .token_stream(text)
+        .process(&mut |token| terms.push((token.position, token.text.clone())));
 
-    let mut lense_queries: QueryVec = Vec::new();
-    for lens in applied_lens {
-        if lenses.contains_key(lens) {
-            let lens = lenses.get(lens).unwrap();
-            for domain in &lens.domains {
-                lense_queries.push((
+    let first = terms.first().map(|(position, _)| *position).unwrap_or(0);
+    terms
+        .into_iter()
+        .map(|(position, term)| (position - first, term))
+        .collect()
+}
+
+/// Split the query string into terms, quoted phrases, `-exclusions`, and the
+/// `AND`/`OR` operators. An unclosed quote runs until the end of the string.
+fn tokenize(analyzer: &TextAnalyzer, query_string: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = query_string.chars().peekable();
+    while let Some(&next) = chars.peek() {
+        if next.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let negated = next == '-';
+        if negated {
+            chars.next();
+        }
+
+        let quoted = chars.peek() == Some(&'"');
+        let mut text = String::new();
+        if quoted {
+            chars.next();
+            text.extend(chars.by_ref().take_while(|c| *c != '"'));
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                text.push(c);
+            }
+        }
+
+        if !negated && !quoted {
+            match text.as_str() {
+                "AND" => {
+                    tokens.push(Token::And);
+                    continue;
+                }
+                "OR" => {
+                    tokens.push(Token::Or);
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        let mut terms = analyze_with_positions(analyzer, &text);
+        let atom = match terms.len() {
+            0 => continue,
+            1 => Atom::Term(terms.remove(0).1),
+            _ => Atom::Phrase(terms),
+        };
+
+        tokens.push(Token::Atom {
+            atom,
+            negated,
+            quoted,
+        });
+    }
+
+    tokens
+}
+
+/// Filters set w/ `key:value` operators in the query string.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryFilters {
+    /// `lang:de`, only match documents in one of these languages.
+    pub langs: Vec<String>,
+    /// `site:wikipedia.org`, only match documents on one of these domains or
+    /// their subdomains.
+    pub sites: Vec<String>,
+    /// `inurl:changelog`, only match documents w/ all of these in their URL.
+    pub url_terms: Vec<String>,
+}
+
+/// Domain part of a `site:` operator, e.g. `https://Example.com/path` is
+/// `example.com`. `None` if it doesn't look like a domain.
+fn normalize_site(site: &str) -> Option<String> {
+    let site = site
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .split(&['/', ':'])
+        .next()?
+        .trim_matches('.')
+        .to_lowercase();
+
+    if site.is_empty()
+        || !site
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '.' || c == '-')
+    {
+        return None;
+    }
+
+    Some(site)
+}
+
+/// Pull the filter operators out of `query_string`, returning what's left of
+/// the query along w/ the filters. Operators inside quotes are left alone.
+pub fn extract_filters(query_string: &str) -> (String, QueryFilters) {
+    let mut filters = QueryFilters::default();
+    let mut words = Vec::new();
+    let mut in_quotes = false;
+    for word in query_string.split_whitespace() {
+        if !in_quotes {
+            if let Some(code) = word.strip_prefix("lang:").and_then(lang::normalize) {
+                filters.langs.push(code);
+                continue;
+            }
+
+            if let Some(site) = word.strip_prefix("site:").and_then(normalize_site) {
+                filters.sites.push(site);
+                continue;
+            }
+
+            if let Some(term) = word.strip_prefix("inurl:").filter(|term| !term.is_empty()) {
+                filters.url_terms.push(term.to_string());
+                continue;
+            }
+        }
+
+        if word.matches('"').count() % 2 == 1 {
+            in_quotes = !in_quotes;
+        }
+        words.push(word);
+    }
+
+    let mut rest = words.join(" ");
+    // Still matters for prefix matching, see `prefix_terms`.
+    if !rest.is_empty() && query_string.ends_with(char::is_whitespace) {
+        rest.push(' ');
+    }
+
+    (rest, filters)
+}
+
+/// Terms in `query_string` that also match words they're the start of.
+/// Quoted & excluded words are always matched exactly.
+fn prefix_terms(analyzer: &TextAnalyzer, query_string: &str, mode: PrefixMatch) -> Vec<String> {
+    let words: Vec<&str> = match mode {
+        PrefixMatch::Off => return Vec::new(),
+        // Done typing the last word.
+        PrefixMatch::LastTerm if query_string.ends_with(char::is_whitespace) => return Vec::new(),
+        PrefixMatch::LastTerm => query_string.split_whitespace().last().into_iter().collect(),
+        PrefixMatch::AllTerms => query_string.split_whitespace().collect(),
+    };
+
+    words
+        .into_iter()
+        .filter(|word| !word.starts_with('-') && !word.contains('"'))
+        .flat_map(|word| analyze(analyzer, word))
+        .collect()
+}
+
+/// Matches `term` in each of the boosted fields, along w/ misspellings of it
+/// when `fuzzy` is set & words starting w/ it when `prefix` is set.
+fn term_queries(
+    fields: &DocFields,
+    ranking: &RankingSettings,
+    term: &str,
+    fuzzy: bool,
+    prefix: bool,
+) -> QueryVec {
+    let mut queries: QueryVec = Vec::new();
+    for (field, boost) in boosted_fields(fields, ranking) {
+        queries.push((Occur::Should, _boosted_term(field, term, boost)));
+
+        if fuzzy {
+            if let Some(query) = _fuzzy_term(field, term, boost * FUZZY_BOOST) {
+                queries.push((Occur::Should, query));
+            }
+        }
+
+        if prefix {
+            if let Some(query) = _prefix_term(field, term, boost * PREFIX_BOOST) {
+                queries.push((Occur::Should, query));
+            }
+        }
+    }
+
+    queries
+}
+
+/// Matches `atom` in the title, content, or URL.
+fn atom_query(
+    fields: &DocFields,
+    ranking: &RankingSettings,
+    atom: &Atom,
+    fuzzy: bool,
+    prefixes: &[String],
+) -> Box<dyn Query> {
+    let mut queries: QueryVec = Vec::new();
+    match atom {
+        Atom::Term(term) => {
+            queries.extend(term_queries(
+                fields,
+                ranking,
+                term,
+                fuzzy,
+                prefixes.contains(term),
+            ));
+        }
+        Atom::Phrase(terms) => {
+            for (field, boost) in boosted_fields(fields, ranking) {
+                let terms = terms
+                    .iter()
+                    .map(|(position, term)| (*position, Term::from_field_text(field, term)))
+                    .collect();
+                queries.push((
                     Occur::Should,
-                    Box::new(TermQuery::new(
-                        Term::from_field_text(fields.domain, domain),
-                        IndexRecordOption::Basic,
+                    Box::new(BoostQuery::new(
+                        Box::new(PhraseQuery::new_with_offset(terms)),
+                        boost,
                     )),
                 ));
             }
+        }
+    }
 
-            for prefix in &lens.urls {
-                let mut regex = regex_for_prefix(prefix);
-                // By default, a RegexQuery is assumed to be exact match.
-                if regex.ends_with('$') {
-                    regex = regex.strip_suffix('$').unwrap().to_string();
-                }
+    Box::new(BooleanQuery::new(queries))
+}
 
-                lense_queries.push((
-                    Occur::Should,
-                    Box::new(RegexQuery::from_pattern(&regex, fields.url).unwrap()),
-                ))
+/// Handles phrases & operators. Terms joined by `AND` & quoted phrases are
+/// required unless `OR`'d w/ something else, other terms are optional &
+/// only affect the score. Excluded terms & phrases are matched exactly.
+fn build_boolean_query(
+    fields: &DocFields,
+    ranking: &RankingSettings,
+    tokens: Vec<Token>,
+    fuzzy: bool,
+    prefixes: &[String],
+) -> BooleanQuery {
+    // Groups of atoms joined by AND, whether the group started w/ a quoted
+    // phrase & whether it was OR'd w/ a neighbor.
+    let mut groups: Vec<(Vec<Atom>, bool, bool)> = Vec::new();
+    let mut excluded = Vec::new();
+    let mut pending_and = false;
+    let mut pending_or = false;
+    for token in tokens {
+        match token {
+            Token::And => pending_and = !groups.is_empty(),
+            Token::Or => {
+                if let Some((_, _, is_optional)) = groups.last_mut() {
+                    *is_optional = true;
+                    pending_or = true;
+                }
+            }
+            Token::Atom {
+                atom,
+                negated: true,
+                ..
+            } => excluded.push(atom),
+            Token::Atom { atom, quoted, .. } => {
+                match groups.last_mut() {
+                    Some((atoms, _, _)) if pending_and => atoms.push(atom),
+                    _ => groups.push((vec![atom], quoted, pending_or)),
+                }
+                pending_and = false;
+                pending_or = false;
             }
         }
     }
 
+    let mut clauses: QueryVec = Vec::new();
+    for (atoms, quoted, is_optional) in groups {
+        let is_required = !is_optional && (quoted || atoms.len() > 1);
+        let query: Box<dyn Query> = if atoms.len() == 1 {
+            atom_query(fields, ranking, &atoms[0], fuzzy, prefixes)
+        } else {
+            Box::new(BooleanQuery::new(
+                atoms
+                    .iter()
+                    .map(|atom| {
+                        (
+                            Occur::Must,
+                            atom_query(fields, ranking, atom, fuzzy, prefixes),
+                        )
+                    })
+                    .collect(),
+            ))
+        };
+
+        let occur = if is_required {
+            Occur::Must
+        } else {
+            Occur::Should
+        };
+        clauses.push((occur, query));
+    }
+
+    // Only exclusions, match everything else.
+    if clauses.is_empty() {
+        clauses.push((Occur::Should, Box::new(AllQuery)));
+    }
+
+    for atom in excluded {
+        clauses.push((
+            Occur::MustNot,
+            atom_query(fields, ranking, &atom, false, &[]),
+        ));
+    }
+
+    BooleanQuery::new(clauses)
+}
+
+/// Lens rules as (domains, URL regexes). By default, a RegexQuery is assumed
+/// to be an exact match, so the trailing `$` is dropped.
+fn lens_rules(
+    lenses: &HashMap<String, Lens>,
+    applied_lens: &[String],
+) -> (Vec<String>, Vec<String>) {
+    let mut domains = Vec::new();
+    let mut regexes = Vec::new();
+    for lens in applied_lens.iter().filter_map(|name| lenses.get(name)) {
+        domains.extend(lens.domains.iter().cloned());
+        for prefix in &lens.urls {
+            let regex = regex_for_prefix(prefix);
+            regexes.push(match regex.strip_suffix('$') {
+                Some(regex) => regex.to_string(),
+                None => regex,
+            });
+        }
+    }
+
+    (domains, regexes)
+}
+
+/// Identifies the filter for a set of applied lenses, `None` if they don't
+/// restrict anything. Lenses w/ the same rules share a key.
+pub fn lens_filter_key(lenses: &HashMap<String, Lens>, applied_lens: &[String]) -> Option<String> {
+    let (domains, regexes) = lens_rules(lenses, applied_lens);
+    let mut rules: Vec<String> = domains
+        .into_iter()
+        .map(|domain| format!("domain:{}", domain))
+        .chain(regexes.into_iter().map(|regex| format!("url:{}", regex)))
+        .collect();
+
+    if rules.is_empty() {
+        return None;
+    }
+
+    rules.sort();
+    rules.dedup();
+    Some(rules.join("\n"))
+}
+
+/// Matches any document in one of the applied lenses.
+pub fn build_lens_query(
+    fields: DocFields,
+    lenses: &HashMap<String, Lens>,
+    applied_lens: &[String],
+) -> BooleanQuery {
+    let (domains, regexes) = lens_rules(lenses, applied_lens);
+
+    let mut lense_queries: QueryVec = Vec::new();
+    for domain in domains {
+        lense_queries.push((
+            Occur::Should,
+            Box::new(TermQuery::new(
+                Term::from_field_text(fields.domain, &domain),
+                IndexRecordOption::Basic,
+            )),
+        ));
+    }
+
+    for regex in regexes {
+        lense_queries.push((
+            Occur::Should,
+            Box::new(RegexQuery::from_pattern(&regex, fields.url).unwrap()),
+        ))
+    }
+
+    BooleanQuery::new(lense_queries)
+}
+
+/// Matches documents on any of `sites` or their subdomains. `None` if there
+/// aren't any.
+pub fn build_site_query(fields: &DocFields, sites: &[String]) -> Option<BooleanQuery> {
+    if sites.is_empty() {
+        return None;
+    }
+
+    let mut site_queries: QueryVec = Vec::new();
+    for site in sites {
+        site_queries.push((
+            Occur::Should,
+            Box::new(TermQuery::new(
+                Term::from_field_text(fields.domain, site),
+                IndexRecordOption::Basic,
+            )),
+        ));
+
+        let subdomains = format!(r".*\.{}", site.replace('.', r"\."));
+        if let Ok(query) = RegexQuery::from_pattern(&subdomains, fields.domain) {
+            site_queries.push((Occur::Should, Box::new(query)));
+        }
+    }
+
+    Some(BooleanQuery::new(site_queries))
+}
+
+/// Matches documents w/ all of `url_terms` in their URL. Terms w/ several
+/// words, e.g. `release-notes`, have to appear in that order. `None` if there
+/// aren't any.
+pub fn build_url_query(
+    fields: &DocFields,
+    analyzer: &TextAnalyzer,
+    url_terms: &[String],
+) -> Option<BooleanQuery> {
+    let mut url_queries: QueryVec = Vec::new();
+    for url_term in url_terms {
+        let mut terms: Vec<(usize, Term)> = analyze_with_positions(analyzer, url_term)
+            .into_iter()
+            .map(|(position, term)| (position, Term::from_field_text(fields.url_text, &term)))
+            .collect();
+
+        let query: Box<dyn Query> = match terms.len() {
+            0 => continue,
+            1 => Box::new(TermQuery::new(terms.remove(0).1, IndexRecordOption::Basic)),
+            _ => Box::new(PhraseQuery::new_with_offset(terms)),
+        };
+        url_queries.push((Occur::Must, query));
+    }
+
+    if url_queries.is_empty() {
+        return None;
+    }
+
+    Some(BooleanQuery::new(url_queries))
+}
+
+/// Matches documents indexed at or after `after` & before `before`, both unix
+/// timestamps. `None` if neither is set.
+pub fn build_date_query(
+    fields: &DocFields,
+    after: Option<i64>,
+    before: Option<i64>,
+) -> Option<RangeQuery> {
+    if after.is_none() && before.is_none() {
+        return None;
+    }
+
+    Some(RangeQuery::new_i64_bounds(
+        fields.indexed_at,
+        after.map(Bound::Included).unwrap_or(Bound::Unbounded),
+        before.map(Bound::Excluded).unwrap_or(Bound::Unbounded),
+    ))
+}
+
+/// Scores documents against the query terms. Unless the query uses phrases
+/// or operators, it's kept as a flat union of term queries so top-k
+/// collection can skip blocks that can't make the cut. When `fuzzy` is set,
+/// terms also match misspellings of themselves & `prefix` picks which terms
+/// also match words they're the start of. `analyzer` should be the one the
+/// fields were indexed w/.
+pub fn build_query(
+    fields: DocFields,
+    ranking: &RankingSettings,
+    analyzer: &TextAnalyzer,
+    query_string: &str,
+    fuzzy: bool,
+    prefix: PrefixMatch,
+) -> BooleanQuery {
+    let prefixes = prefix_terms(analyzer, query_string, prefix);
+    let tokens = tokenize(analyzer, query_string);
+    if tokens.iter().any(Token::is_operator) {
+        log::trace!("tokens: {:?}", tokens);
+        return build_boolean_query(&fields, ranking, tokens, fuzzy, &prefixes);
+    }
+
+    // Tokenize query string the same way the fields were
+    let query_string = query_string.to_lowercase();
+    let terms = analyze(analyzer, &query_string);
+
+    log::trace!("terms: {:?}", terms);
+
     let mut term_query: QueryVec = Vec::new();
     // Boost exact matches to the full query string
     if terms.len() > 1 {
@@ -79,16 +619,167 @@ pub fn build_query(
         ));
     }
 
-    for term in terms {
-        // Emphasize matches in the content more than words in the title
-        term_query.push((Occur::Should, _boosted_term(fields.content, term, 1.0)));
-        term_query.push((Occur::Should, _boosted_term(fields.title, term, 5.0)));
+    for term in terms.iter() {
+        term_query.extend(term_queries(
+            &fields,
+            ranking,
+            term,
+            fuzzy,
+            prefixes.contains(term),
+        ));
+    }
+
+    BooleanQuery::new(term_query)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use shared::config::{Lens, PrefixMatch};
+
+    use super::{extract_filters, lens_filter_key, prefix_terms, tokenize, Atom, Token};
+    use crate::search::tokenizer::analyzer;
+
+    fn term(text: &str, negated: bool) -> Token {
+        Token::Atom {
+            atom: Atom::Term(text.to_string()),
+            negated,
+            quoted: false,
+        }
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize(&analyzer(&[], &[]), "Rust  async"),
+            vec![term("rust", false), term("async", false)]
+        );
+
+        assert_eq!(
+            tokenize(
+                &analyzer(&[], &[]),
+                "\"rust async runtime\" AND tokio OR -\"go\" -java"
+            ),
+            vec![
+                Token::Atom {
+                    atom: Atom::Phrase(vec![
+                        (0, "rust".to_string()),
+                        (1, "async".to_string()),
+                        (2, "runtime".to_string())
+                    ]),
+                    negated: false,
+                    quoted: true,
+                },
+                Token::And,
+                term("tokio", false),
+                Token::Or,
+                Token::Atom {
+                    atom: Atom::Term("go".to_string()),
+                    negated: true,
+                    quoted: true,
+                },
+                term("java", true),
+            ]
+        );
+
+        // Operators are case sensitive, unclosed quotes run to the end & stray
+        // dashes are ignored.
+        assert_eq!(
+            tokenize(&analyzer(&[], &[]), "and - \"or"),
+            vec![
+                term("and", false),
+                Token::Atom {
+                    atom: Atom::Term("or".to_string()),
+                    negated: false,
+                    quoted: true,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_stop_words() {
+        let stop_words = vec!["of".to_string(), "the".to_string()];
+        assert_eq!(
+            tokenize(&analyzer(&[], &stop_words), "\"lord of the rings\" the"),
+            vec![Token::Atom {
+                atom: Atom::Phrase(vec![(0, "lord".to_string()), (3, "rings".to_string())]),
+                negated: false,
+                quoted: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_prefix_terms() {
+        let prefixes =
+            |query: &str, mode: PrefixMatch| prefix_terms(&analyzer(&[], &[]), query, mode);
+
+        assert_eq!(prefixes("rust asy", PrefixMatch::LastTerm), vec!["asy"]);
+        // Done typing
+        assert!(prefixes("rust async ", PrefixMatch::LastTerm).is_empty());
+        assert!(prefixes("rust asy", PrefixMatch::Off).is_empty());
+        assert_eq!(
+            prefixes("Rust asy", PrefixMatch::AllTerms),
+            vec!["rust", "asy"]
+        );
+
+        // Quoted & excluded words are exact
+        assert!(prefixes("rust \"async\"", PrefixMatch::LastTerm).is_empty());
+        assert_eq!(
+            prefixes("rust -java tok", PrefixMatch::AllTerms),
+            vec!["rust", "tok"]
+        );
     }
 
-    let mut nested_query: QueryVec = vec![(Occur::Must, Box::new(BooleanQuery::new(term_query)))];
-    if !lense_queries.is_empty() {
-        nested_query.push((Occur::Must, Box::new(BooleanQuery::new(lense_queries))));
+    #[test]
+    fn test_extract_filters() {
+        let (rest, filters) = extract_filters("lang:de rust borrow checker");
+        assert_eq!(rest, "rust borrow checker");
+        assert_eq!(filters.langs, vec!["de".to_string()]);
+
+        let (rest, filters) = extract_filters("rust lang:EN lang:deu ");
+        assert_eq!(rest, "rust ");
+        assert_eq!(filters.langs, vec!["en".to_string(), "de".to_string()]);
+
+        // Quoted or not a language, left as part of the query.
+        let (rest, filters) = extract_filters("\"set lang:en in\" lang:");
+        assert_eq!(rest, "\"set lang:en in\" lang:");
+        assert!(filters.langs.is_empty());
+
+        let (rest, filters) =
+            extract_filters("site:https://EN.wikipedia.org/wiki rust inurl:changelog site:*");
+        assert_eq!(rest, "rust site:*");
+        assert_eq!(filters.sites, vec!["en.wikipedia.org".to_string()]);
+        assert_eq!(filters.url_terms, vec!["changelog".to_string()]);
     }
 
-    BooleanQuery::new(nested_query)
+    #[test]
+    fn test_lens_filter_key() {
+        let lens = |name: &str, domains: &[&str]| Lens {
+            name: name.to_string(),
+            domains: domains.iter().map(|d| d.to_string()).collect(),
+            ..Default::default()
+        };
+
+        let mut lenses = HashMap::new();
+        lenses.insert("a".to_string(), lens("a", &["a.com", "b.com"]));
+        lenses.insert("b".to_string(), lens("b", &["b.com", "a.com"]));
+        lenses.insert("empty".to_string(), lens("empty", &[]));
+
+        // Same rules, same filter
+        assert_eq!(
+            lens_filter_key(&lenses, &["a".to_string()]),
+            lens_filter_key(&lenses, &["b".to_string()])
+        );
+        assert_eq!(
+            lens_filter_key(&lenses, &["a".to_string(), "b".to_string()]),
+            lens_filter_key(&lenses, &["a".to_string()])
+        );
+
+        assert_eq!(lens_filter_key(&lenses, &[]), None);
+        assert_eq!(lens_filter_key(&lenses, &["empty".to_string()]), None);
+        assert_eq!(lens_filter_key(&lenses, &["missing".to_string()]), None);
+    }
 }
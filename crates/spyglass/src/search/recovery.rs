@@ -0,0 +1,452 @@
+//! Startup integrity checks for the index. The index & database are two
+//! separate stores, so a crash/power loss in between commits can leave them
+//! out of sync or, in the worst case, leave the index unreadable.
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tantivy::directory::MmapDirectory;
+use tantivy::{DocAddress, Index, IndexReader};
+
+use entities::models::{crawl_queue, document_content, indexed_document};
+use entities::sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+};
+
+use super::Searcher;
+use crate::scraper::lang;
+
+/// File created in the index directory while there are uncommitted writes.
+pub const UNCOMMITTED_MARKER: &str = ".uncommitted";
+/// File in the index directory w/ the id of the analyzer the index was built w/.
+pub const ANALYZER_FILE: &str = ".analyzer";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IndexHealth {
+    /// Last shutdown was clean.
+    Clean,
+    /// There were uncommitted writes when we last shutdown, the database may
+    /// reference documents that never made it into the index.
+    Unclean,
+    /// The index was corrupted & moved out of the way to this path. A new,
+    /// empty, index will be created in its place.
+    Quarantined(PathBuf),
+    /// The index was created w/ an older schema or different analyzer & was
+    /// cleared out. Documents are rebuilt from their stored content, or
+    /// recrawled if there is none.
+    Outdated,
+}
+
+#[derive(Debug, Default)]
+pub struct RepairStats {
+    /// Documents in the database that were missing from the index.
+    pub missing_docs: usize,
+    /// Documents in the index w/o a database entry.
+    pub orphaned_docs: usize,
+    /// Missing documents that were added back from their stored content.
+    pub restored: usize,
+    /// URLs added back to the crawl queue.
+    pub requeued: u64,
+}
+
+/// Returns a list of corrupted files, or an error if the index can't be opened
+/// at all.
+fn validate(path: &Path) -> anyhow::Result<HashSet<PathBuf>> {
+    let dir = MmapDirectory::open(path)?;
+    // Nothing to validate for a brand new index.
+    if !Index::exists(&dir)? {
+        return Ok(HashSet::new());
+    }
+
+    let index = Index::open(dir)?;
+    Ok(index.validate_checksum()?)
+}
+
+/// Whether the index at `path` was created w/ a different schema or analyzer
+/// than the ones we're expecting.
+fn is_outdated(path: &Path, analyzer_id: &str) -> anyhow::Result<bool> {
+    let dir = MmapDirectory::open(path)?;
+    if !Index::exists(&dir)? {
+        return Ok(false);
+    }
+
+    let index = Index::open(dir)?;
+    if index.schema() != Searcher::schema() {
+        return Ok(true);
+    }
+
+    // Terms in the index won't line up w/ the ones in queries.
+    let indexed_with = fs::read_to_string(path.join(ANALYZER_FILE)).unwrap_or_default();
+    Ok(indexed_with.trim() != analyzer_id)
+}
+
+/// Move a corrupted index to a sibling folder so it can be inspected later
+/// and leave an empty folder in its place.
+fn quarantine(path: &Path) -> std::io::Result<PathBuf> {
+    let folder_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "index".into());
+
+    let dest = path.with_file_name(format!(
+        "{}.corrupt-{}",
+        folder_name,
+        chrono::Utc::now().timestamp()
+    ));
+
+    fs::rename(path, &dest)?;
+    fs::create_dir_all(path)?;
+    Ok(dest)
+}
+
+/// Check the index at `path` before opening it, quarantining it if it's
+/// corrupted. `analyzer_id` is the id of the analyzer the index will be
+/// opened w/, see `tokenizer::analyzer_id`.
+pub fn check_index(path: &Path, analyzer_id: &str) -> IndexHealth {
+    if !path.exists() {
+        return IndexHealth::Clean;
+    }
+
+    let reason = match validate(path) {
+        Ok(corrupted) if corrupted.is_empty() => None,
+        Ok(corrupted) => Some(format!("corrupted files: {:?}", corrupted)),
+        Err(err) => Some(err.to_string()),
+    };
+
+    if let Some(reason) = reason {
+        log::error!("Index at {} is corrupted: {}", path.display(), reason);
+        return match quarantine(path) {
+            Ok(dest) => {
+                log::warn!("Moved corrupted index to {}", dest.display());
+                IndexHealth::Quarantined(dest)
+            }
+            Err(err) => {
+                // Not much else we can do, opening the index will most likely fail.
+                log::error!("Unable to quarantine index: {}", err);
+                IndexHealth::Unclean
+            }
+        };
+    }
+
+    if let Ok(true) = is_outdated(path, analyzer_id) {
+        log::warn!(
+            "Index at {} was created w/ an older schema or analyzer, rebuilding",
+            path.display()
+        );
+        // Nothing in there can be read w/ the current schema.
+        if let Err(err) = fs::remove_dir_all(path).and_then(|_| fs::create_dir_all(path)) {
+            log::error!("Unable to clear outdated index: {}", err);
+        }
+        return IndexHealth::Outdated;
+    }
+
+    if path.join(UNCOMMITTED_MARKER).exists() {
+        IndexHealth::Unclean
+    } else {
+        IndexHealth::Clean
+    }
+}
+
+/// Ids of all the live documents in the index.
+pub fn all_doc_ids(reader: &IndexReader) -> anyhow::Result<HashSet<String>> {
+    let fields = Searcher::doc_fields();
+    let searcher = reader.searcher();
+
+    let mut ids = HashSet::new();
+    for (segment_ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+        for doc_id in segment_reader.doc_ids_alive() {
+            let doc = searcher.doc(DocAddress::new(segment_ord as u32, doc_id))?;
+            if let Some(id) = doc.get_first(fields.id).and_then(|value| value.as_text()) {
+                ids.insert(id.to_string());
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Add a document back to the index from its stored content, updating the
+/// database w/ the new doc id. Returns false if there was nothing stored.
+async fn restore(
+    db: &DatabaseConnection,
+    searcher: &Searcher,
+    doc: &indexed_document::Model,
+) -> anyhow::Result<bool> {
+    let stored = match document_content::Entity::find()
+        .filter(document_content::Column::Url.eq(doc.url.as_str()))
+        .one(db)
+        .await?
+    {
+        Some(stored) => stored,
+        None => return Ok(false),
+    };
+
+    let content = stored.content()?;
+    let raw = stored.raw()?;
+    // Documents indexed before languages were detected won't have one yet.
+    let lang = doc.lang.clone().or_else(|| lang::detect(None, &content));
+    let doc_id = {
+        let mut writer = searcher
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Unable to get index lock"))?;
+        Searcher::add_document(
+            &mut writer,
+            &stored.title,
+            &stored.description,
+            &doc.domain,
+            &doc.url,
+            &content,
+            &raw,
+            lang.as_deref(),
+        )?
+    };
+    searcher.mark_pending(1);
+
+    let mut update: indexed_document::ActiveModel = doc.clone().into();
+    update.doc_id = Set(doc_id);
+    update.lang = Set(lang);
+    update.save(db).await?;
+
+    Ok(true)
+}
+
+/// Bring the database & index back in sync. Documents missing from the index
+/// are rebuilt from their stored content or, if there is none, removed from
+/// the database & recrawled. Documents the database doesn't know about are
+/// removed from the index.
+pub async fn repair(db: &DatabaseConnection, searcher: &Searcher) -> anyhow::Result<RepairStats> {
+    let index_ids = all_doc_ids(&searcher.reader)?;
+
+    let mut referenced = HashSet::new();
+    let mut missing = Vec::new();
+    for doc in indexed_document::Entity::find().all(db).await? {
+        if index_ids.contains(&doc.doc_id) {
+            referenced.insert(doc.doc_id);
+        } else {
+            missing.push(doc);
+        }
+    }
+
+    let mut stats = RepairStats {
+        missing_docs: missing.len(),
+        ..Default::default()
+    };
+
+    let mut unrestored = Vec::new();
+    for doc in missing {
+        match restore(db, searcher, &doc).await {
+            Ok(true) => stats.restored += 1,
+            Ok(false) => unrestored.push(doc),
+            Err(err) => {
+                log::error!("Unable to restore {}: {}", doc.url, err);
+                unrestored.push(doc);
+            }
+        }
+    }
+    let missing = unrestored;
+
+    if !missing.is_empty() {
+        let urls: Vec<String> = missing.iter().map(|doc| doc.url.clone()).collect();
+        let ids: Vec<i64> = missing.iter().map(|doc| doc.id).collect();
+
+        for chunk in ids.chunks(500) {
+            indexed_document::Entity::delete_many()
+                .filter(indexed_document::Column::Id.is_in(chunk.to_vec()))
+                .exec(db)
+                .await?;
+        }
+
+        stats.requeued = crawl_queue::requeue_urls(db, &urls).await?;
+    }
+
+    let orphaned: Vec<&String> = index_ids.difference(&referenced).collect();
+    stats.orphaned_docs = orphaned.len();
+    if !orphaned.is_empty() {
+        let mut writer = searcher
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Unable to get index lock"))?;
+
+        for doc_id in orphaned.iter() {
+            Searcher::delete(&mut writer, doc_id)?;
+        }
+        searcher.mark_pending(orphaned.len());
+    }
+
+    // Commit even if nothing changed to clear the uncommitted marker.
+    searcher.commit()?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use tantivy::schema::{Schema, STORED, STRING, TEXT};
+    use tantivy::tokenizer::Language;
+    use tantivy::Index;
+
+    use shared::config::UserSettings;
+
+    use entities::models::{document_content, indexed_document};
+    use entities::sea_orm::{ActiveModelTrait, EntityTrait, Set};
+    use entities::test::setup_test_db;
+
+    use super::{all_doc_ids, check_index, repair, IndexHealth, ANALYZER_FILE, UNCOMMITTED_MARKER};
+    use crate::search::{tokenizer, IndexPath, Searcher};
+
+    /// Analyzer used by the default settings.
+    fn default_analyzer_id() -> String {
+        tokenizer::analyzer_id(&[Language::English], &tokenizer::stop_words(&None))
+    }
+
+    #[test]
+    fn test_check_index() {
+        let path = std::env::temp_dir().join(format!("spyglass-recovery-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        // Empty folder, nothing to check.
+        assert_eq!(
+            check_index(&path, &default_analyzer_id()),
+            IndexHealth::Clean
+        );
+
+        fs::write(path.join(UNCOMMITTED_MARKER), b"").unwrap();
+        assert_eq!(
+            check_index(&path, &default_analyzer_id()),
+            IndexHealth::Unclean
+        );
+
+        // Garbage metadata should get the index quarantined.
+        fs::write(path.join("meta.json"), b"{ not json").unwrap();
+        match check_index(&path, &default_analyzer_id()) {
+            IndexHealth::Quarantined(dest) => {
+                assert!(dest.join("meta.json").exists());
+                assert!(path.exists());
+                assert!(!path.join("meta.json").exists());
+                let _ = fs::remove_dir_all(dest);
+            }
+            health => panic!("expected index to be quarantined, got {:?}", health),
+        }
+
+        let _ = fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_outdated_index() {
+        let path = std::env::temp_dir().join(format!("spyglass-outdated-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        // Index w/ a schema from an older version.
+        let mut builder = Schema::builder();
+        builder.add_text_field("id", STRING | STORED);
+        builder.add_text_field("content", TEXT);
+        Index::create_in_dir(&path, builder.build()).unwrap();
+
+        assert_eq!(
+            check_index(&path, &default_analyzer_id()),
+            IndexHealth::Outdated
+        );
+        assert!(path.exists());
+        assert!(!path.join("meta.json").exists());
+
+        // A new index w/ the current schema is fine.
+        {
+            let _searcher = Searcher::with_index(&IndexPath::LocalPath(path.clone()));
+        }
+        assert_eq!(
+            check_index(&path, &default_analyzer_id()),
+            IndexHealth::Clean
+        );
+        assert_eq!(
+            fs::read_to_string(path.join(ANALYZER_FILE)).unwrap(),
+            default_analyzer_id()
+        );
+
+        // Switching languages means everything needs to be reindexed.
+        let unstemmed_id = tokenizer::analyzer_id(&[], &tokenizer::stop_words(&None));
+        assert_eq!(check_index(&path, &unstemmed_id), IndexHealth::Outdated);
+        {
+            let settings = UserSettings {
+                stemming_languages: Vec::new(),
+                ..Default::default()
+            };
+            let _searcher = Searcher::with_settings(&IndexPath::LocalPath(path.clone()), &settings);
+        }
+        assert_eq!(check_index(&path, &unstemmed_id), IndexHealth::Clean);
+
+        let _ = fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_all_doc_ids() {
+        let searcher = Searcher::with_index(&IndexPath::Memory);
+        let doc_id = {
+            let mut writer = searcher.writer.lock().unwrap();
+            Searcher::add_document(
+                &mut writer,
+                "Title",
+                "Description",
+                "example.com",
+                "https://example.com",
+                "Content",
+                "",
+                None,
+            )
+            .unwrap()
+        };
+        searcher.mark_pending(1);
+        searcher.commit().unwrap();
+        searcher.reader.reload().unwrap();
+
+        let ids = all_doc_ids(&searcher.reader).unwrap();
+        assert_eq!(ids.len(), 1);
+        assert!(ids.contains(&doc_id));
+    }
+
+    #[tokio::test]
+    async fn test_repair_from_stored_content() {
+        let db = setup_test_db().await;
+        let searcher = Searcher::with_index(&IndexPath::Memory);
+
+        for (url, doc_id) in [
+            ("https://example.com/stored", "stale-1"),
+            ("https://example.com/not-stored", "stale-2"),
+        ] {
+            indexed_document::ActiveModel {
+                domain: Set("example.com".into()),
+                url: Set(url.into()),
+                doc_id: Set(doc_id.into()),
+                ..Default::default()
+            }
+            .save(&db)
+            .await
+            .unwrap();
+        }
+        document_content::upsert(
+            &db,
+            "https://example.com/stored",
+            "Stored page",
+            "",
+            "Content that survived",
+            "<p>Content that survived</p>",
+        )
+        .await
+        .unwrap();
+
+        let stats = repair(&db, &searcher).await.unwrap();
+        assert_eq!(stats.missing_docs, 2);
+        assert_eq!(stats.restored, 1);
+
+        searcher.reader.reload().unwrap();
+        let docs = indexed_document::Entity::find().all(&db).await.unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_ne!(docs[0].doc_id, "stale-1");
+        assert!(all_doc_ids(&searcher.reader)
+            .unwrap()
+            .contains(&docs[0].doc_id));
+    }
+}
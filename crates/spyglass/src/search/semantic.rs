@@ -0,0 +1,385 @@
+//! Search by meaning rather than keywords. Documents & queries are embedded
+//! w/ an external model (see `SemanticSettings`) & compared by cosine
+//! similarity. Embeddings are kept in SQLite & loaded into memory on start.
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tantivy::{DocAddress, Score};
+
+use entities::models::{document_content, document_embedding};
+use entities::sea_orm::DatabaseConnection;
+use shared::config::{Lens, SemanticSettings, UserSettings};
+use shared::request::SearchMode;
+
+use super::{SearchOptions, Searcher};
+use crate::fetch;
+use crate::state::AppState;
+
+/// Max number of batches embedded each time the background task runs.
+const MAX_BATCHES_PER_RUN: usize = 50;
+/// Only the start of a document is embedded, most models have a small
+/// context window anyways.
+const MAX_EMBED_CHARS: usize = 2000;
+/// Min. number of documents pulled from each ranking before they're merged.
+const MIN_CANDIDATES: usize = 100;
+/// Dampens the difference between top ranks in reciprocal rank fusion.
+const RRF_K: f32 = 60.0;
+/// Searches wait on the query embedding, give up quickly & fall back to
+/// keyword search when the endpoint is slow or unreachable.
+const QUERY_TIMEOUT_S: u64 = 3;
+/// Embedding a batch of documents can take a while on slower machines.
+const BATCH_TIMEOUT_S: u64 = 60;
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    #[serde(default)]
+    index: usize,
+}
+
+/// Client for an OpenAI-compatible embeddings endpoint.
+#[derive(Clone)]
+pub struct Embedder {
+    client: reqwest::Client,
+    settings: SemanticSettings,
+}
+
+impl Embedder {
+    /// Embedder for search queries, see `QUERY_TIMEOUT_S`. Built once &
+    /// kept in the `AppState`.
+    pub fn new(user_settings: &UserSettings, settings: &SemanticSettings) -> Self {
+        Self::with_timeout(
+            user_settings,
+            settings,
+            Duration::from_secs(QUERY_TIMEOUT_S),
+        )
+    }
+
+    /// Goes through the proxy in the user settings, if any.
+    pub fn with_timeout(
+        user_settings: &UserSettings,
+        settings: &SemanticSettings,
+        timeout: Duration,
+    ) -> Self {
+        let client = fetch::client_builder(user_settings)
+            .timeout(timeout)
+            .build()
+            .expect("Unable to create reqwest client");
+
+        Embedder {
+            client,
+            settings: settings.clone(),
+        }
+    }
+
+    /// One embedding per input, in the same order.
+    pub async fn embed(&self, inputs: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let url = format!(
+            "{}/v1/embeddings",
+            self.settings.endpoint.trim_end_matches('/')
+        );
+        let body = EmbeddingRequest {
+            model: &self.settings.model,
+            input: inputs,
+        };
+
+        let mut request = self
+            .client
+            .post(url)
+            .header("content-type", "application/json")
+            .body(serde_json::to_vec(&body)?);
+        if let Some(api_key) = &self.settings.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let resp = request.send().await?.error_for_status()?;
+        let mut resp: EmbeddingResponse = serde_json::from_slice(&resp.bytes().await?)?;
+        if resp.data.len() != inputs.len() {
+            return Err(anyhow::anyhow!(
+                "Expected {} embeddings, got {}",
+                inputs.len(),
+                resp.data.len()
+            ));
+        }
+
+        resp.data.sort_by_key(|data| data.index);
+        Ok(resp.data.into_iter().map(|data| data.embedding).collect())
+    }
+}
+
+/// Scale `vector` to unit length so similarity is just a dot product.
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for value in vector.iter_mut() {
+            *value /= norm;
+        }
+    }
+
+    vector
+}
+
+/// In-memory copy of the document embeddings, keyed by URL.
+#[derive(Clone, Default)]
+pub struct VectorStore {
+    vectors: Arc<RwLock<HashMap<String, Vec<f32>>>>,
+}
+
+impl VectorStore {
+    /// Load the embeddings computed w/ `model`.
+    pub async fn load(db: &DatabaseConnection, model: &str) -> anyhow::Result<Self> {
+        let store = VectorStore::default();
+        for stored in document_embedding::all_for_model(db, model).await? {
+            store.insert(&stored.url, stored.vector());
+        }
+
+        Ok(store)
+    }
+
+    pub fn insert(&self, url: &str, vector: Vec<f32>) {
+        if let Ok(mut vectors) = self.vectors.write() {
+            vectors.insert(url.to_string(), normalize(vector));
+        }
+    }
+
+    /// Drop the embeddings of deleted documents.
+    pub fn remove(&self, urls: &[String]) {
+        if let Ok(mut vectors) = self.vectors.write() {
+            for url in urls {
+                vectors.remove(url);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.vectors
+            .read()
+            .map(|vectors| vectors.len())
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// URLs of the `limit` documents most similar to `query`, most similar
+    /// first, along w/ their cosine similarity.
+    pub fn nearest(&self, query: &[f32], limit: usize) -> Vec<(String, f32)> {
+        let query = normalize(query.to_vec());
+        let vectors = match self.vectors.read() {
+            Ok(vectors) => vectors,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut scored: Vec<(String, f32)> = vectors
+            .iter()
+            // Left over from a model w/ a different number of dimensions.
+            .filter(|(_, vector)| vector.len() == query.len())
+            .map(|(url, vector)| {
+                let similarity = vector.iter().zip(query.iter()).map(|(a, b)| a * b).sum();
+                (url.clone(), similarity)
+            })
+            .collect();
+
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(limit);
+        scored
+    }
+}
+
+/// Merge rankings of URLs w/ reciprocal rank fusion. Documents near the top
+/// of any ranking end up near the top, documents in several even more so.
+pub fn fuse(rankings: &[Vec<String>]) -> Vec<(String, Score)> {
+    let mut scores: HashMap<&String, Score> = HashMap::new();
+    for ranking in rankings {
+        for (rank, url) in ranking.iter().enumerate() {
+            *scores.entry(url).or_insert(0.0) += 1.0 / (RRF_K + rank as Score + 1.0);
+        }
+    }
+
+    let mut fused: Vec<(String, Score)> = scores
+        .into_iter()
+        .map(|(url, score)| (url.clone(), score))
+        .collect();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    fused
+}
+
+/// Text sent to the model for a document.
+fn embed_text(doc: &document_content::Model) -> anyhow::Result<String> {
+    let text = format!("{}\n{}\n{}", doc.title, doc.description, doc.content()?);
+    Ok(text.chars().take(MAX_EMBED_CHARS).collect())
+}
+
+/// Embed the stored content of documents w/o an up to date embedding.
+/// Returns the number of documents embedded.
+pub async fn embed_pending(state: &AppState, settings: &SemanticSettings) -> anyhow::Result<usize> {
+    let embedder = Embedder::with_timeout(
+        &state.user_settings,
+        settings,
+        Duration::from_secs(BATCH_TIMEOUT_S),
+    );
+
+    let mut num_embedded = 0;
+    for _ in 0..MAX_BATCHES_PER_RUN {
+        let batch =
+            document_embedding::pending(&state.db, &settings.model, settings.batch_size as u64)
+                .await?;
+        if batch.is_empty() {
+            break;
+        }
+
+        let inputs = batch
+            .iter()
+            .map(embed_text)
+            .collect::<anyhow::Result<Vec<String>>>()?;
+        let vectors = embedder.embed(&inputs).await?;
+        for (doc, vector) in batch.iter().zip(vectors) {
+            document_embedding::upsert(&state.db, &doc.url, &settings.model, &vector).await?;
+            state.vectors.insert(&doc.url, vector);
+        }
+
+        num_embedded += batch.len();
+    }
+
+    Ok(num_embedded)
+}
+
+/// Same as `Searcher::search_with_lens`, but matches documents by meaning
+/// in `SearchMode::Semantic` or blends both in `SearchMode::Hybrid`.
+/// Semantic matches are restricted to the applied lenses & filters too.
+pub async fn search(
+    state: &AppState,
+    embedder: &Embedder,
+    mode: SearchMode,
+    lenses: &HashMap<String, Lens>,
+    applied_lens: &[String],
+    query_string: &str,
+    options: &SearchOptions,
+) -> anyhow::Result<(usize, Vec<(Score, DocAddress)>)> {
+    let num_candidates = options
+        .offset
+        .saturating_add(options.limit)
+        .max(MIN_CANDIDATES);
+
+    let query_vector = embedder
+        .embed(&[query_string.to_string()])
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow::anyhow!("No embedding for query"))?;
+
+    let nearest = state.vectors.nearest(&query_vector, num_candidates);
+    let nearest_urls: Vec<String> = nearest.iter().map(|(url, _)| url.clone()).collect();
+    let mut addresses = state
+        .index
+        .filter_urls(lenses, applied_lens, &nearest_urls, options)?;
+    let semantic: Vec<(String, f32)> = nearest
+        .into_iter()
+        .filter(|(url, _)| addresses.contains_key(url))
+        .collect();
+
+    let (num_hits, ranked) = match mode {
+        SearchMode::Hybrid => {
+            let keyword_options = SearchOptions {
+                offset: 0,
+                limit: num_candidates,
+                ..options.clone()
+            };
+            let (num_keyword_hits, keyword_docs) =
+                state
+                    .index
+                    .search_with_lens(lenses, applied_lens, query_string, &keyword_options);
+
+            let searcher = state.index.reader.searcher();
+            let fields = Searcher::doc_fields();
+            let mut keyword = Vec::new();
+            for (_, address) in keyword_docs {
+                let doc = searcher.doc(address)?;
+                if let Some(url) = doc.get_first(fields.url).and_then(|value| value.as_text()) {
+                    addresses.insert(url.to_string(), address);
+                    keyword.push(url.to_string());
+                }
+            }
+
+            // Semantic matches that didn't match any keywords add to the total.
+            let keyword_urls: HashSet<&String> = keyword.iter().collect();
+            let num_semantic_only = semantic
+                .iter()
+                .filter(|(url, _)| !keyword_urls.contains(url))
+                .count();
+
+            let semantic: Vec<String> = semantic.into_iter().map(|(url, _)| url).collect();
+            (
+                num_keyword_hits + num_semantic_only,
+                fuse(&[keyword, semantic]),
+            )
+        }
+        _ => (semantic.len(), semantic),
+    };
+
+    let docs = ranked
+        .into_iter()
+        .skip(options.offset)
+        .take(options.limit)
+        .filter_map(|(url, score)| addresses.get(&url).map(|address| (score, *address)))
+        .collect();
+
+    Ok((num_hits, docs))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fuse, normalize, VectorStore};
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(vec![3.0, 4.0]), vec![0.6, 0.8]);
+        assert_eq!(normalize(vec![0.0, 0.0]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_nearest() {
+        let store = VectorStore::default();
+        store.insert("https://example.com/cats", vec![1.0, 0.0]);
+        store.insert("https://example.com/dogs", vec![0.0, 2.0]);
+        store.insert("https://example.com/pets", vec![1.0, 1.0]);
+        // Different model, ignored.
+        store.insert("https://example.com/old", vec![1.0, 0.0, 0.0]);
+
+        let nearest = store.nearest(&[2.0, 0.1], 2);
+        let urls: Vec<&str> = nearest.iter().map(|(url, _)| url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec!["https://example.com/cats", "https://example.com/pets"]
+        );
+        assert!(nearest[0].1 > 0.99);
+
+        store.remove(&["https://example.com/cats".to_string()]);
+        let nearest = store.nearest(&[2.0, 0.1], 1);
+        assert_eq!(nearest[0].0, "https://example.com/pets");
+    }
+
+    #[test]
+    fn test_fuse() {
+        let keyword = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let semantic = vec!["c".to_string(), "d".to_string(), "a".to_string()];
+
+        let fused = fuse(&[keyword, semantic]);
+        let urls: Vec<&str> = fused.iter().map(|(url, _)| url.as_str()).collect();
+        // In both lists first, then by their best rank.
+        assert_eq!(urls, vec!["a", "c", "b", "d"]);
+    }
+}
@@ -0,0 +1,130 @@
+//! Breakdown of where the space in the index goes.
+use std::collections::HashMap;
+
+use tantivy::schema::Field;
+use tantivy::space_usage::PerFieldSpaceUsage;
+use tantivy::{DocAddress, IndexReader};
+
+use shared::response::{FieldStats, IndexStats};
+
+use super::Searcher;
+
+/// Max number of documents read to estimate the stored size of each field.
+const STORED_SAMPLE_SIZE: usize = 1000;
+
+fn add_usage(totals: &mut HashMap<Field, u64>, usage: &PerFieldSpaceUsage) {
+    for (field, usage) in usage.fields() {
+        *totals.entry(*field).or_default() += usage.total() as u64;
+    }
+}
+
+/// Uncompressed size of each stored field, estimated from an evenly spaced
+/// sample of the live documents.
+fn stored_sizes(reader: &IndexReader) -> anyhow::Result<HashMap<Field, u64>> {
+    let searcher = reader.searcher();
+    let num_docs = searcher.num_docs() as usize;
+    if num_docs == 0 {
+        return Ok(HashMap::new());
+    }
+
+    let step = (num_docs / STORED_SAMPLE_SIZE).max(1);
+    let mut sampled = 0;
+    let mut sizes: HashMap<Field, u64> = HashMap::new();
+    for (segment_ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+        for doc_id in segment_reader.doc_ids_alive().step_by(step) {
+            let doc = searcher.doc(DocAddress::new(segment_ord as u32, doc_id))?;
+            for value in doc.field_values() {
+                let len = value.value().as_text().map(|text| text.len()).unwrap_or(0);
+                *sizes.entry(value.field()).or_default() += len as u64;
+            }
+            sampled += 1;
+        }
+    }
+
+    if sampled > 0 {
+        for size in sizes.values_mut() {
+            *size = *size * num_docs as u64 / sampled;
+        }
+    }
+
+    Ok(sizes)
+}
+
+pub fn index_stats(searcher: &Searcher) -> anyhow::Result<IndexStats> {
+    let schema = searcher.index.schema();
+    let usage = searcher.reader.searcher().space_usage()?;
+
+    let mut indexed: HashMap<Field, u64> = HashMap::new();
+    let mut store_bytes = 0;
+    let mut num_docs = 0;
+    for segment in usage.segments() {
+        add_usage(&mut indexed, segment.termdict());
+        add_usage(&mut indexed, segment.postings());
+        add_usage(&mut indexed, segment.positions());
+        add_usage(&mut indexed, segment.fast_fields());
+        add_usage(&mut indexed, segment.fieldnorms());
+        store_bytes += segment.store().total() as u64;
+        num_docs += segment.num_docs() as u64;
+    }
+
+    let stored = stored_sizes(&searcher.reader)?;
+    let fields = schema
+        .fields()
+        .map(|(field, entry)| FieldStats {
+            name: entry.name().to_string(),
+            indexed_bytes: indexed.get(&field).copied().unwrap_or(0),
+            stored_bytes: stored.get(&field).copied().unwrap_or(0),
+        })
+        .collect();
+
+    Ok(IndexStats {
+        num_docs,
+        num_segments: usage.segments().len(),
+        total_bytes: usage.total() as u64,
+        store_bytes,
+        fields,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::search::{IndexPath, Searcher};
+
+    use super::index_stats;
+
+    #[test]
+    fn test_index_stats() {
+        let searcher = Searcher::with_index(&IndexPath::Memory);
+        {
+            let mut writer = searcher.writer.lock().unwrap();
+            Searcher::add_document(
+                &mut writer,
+                "Title",
+                "Description",
+                "example.com",
+                "https://example.com",
+                "Some content",
+                "<html><body>Some content</body></html>",
+                None,
+            )
+            .unwrap();
+            writer.commit().unwrap();
+        }
+        searcher.reader.reload().unwrap();
+
+        let stats = index_stats(&searcher).unwrap();
+        assert_eq!(stats.num_docs, 1);
+        assert_eq!(stats.num_segments, 1);
+        assert!(stats.store_bytes > 0);
+
+        let field = |name: &str| stats.fields.iter().find(|f| f.name == name).unwrap();
+        assert!(field("content").indexed_bytes > 0);
+        // Extracted from the raw page instead
+        assert_eq!(field("content").stored_bytes, 0);
+        assert_eq!(field("text").stored_bytes, 0);
+        assert_eq!(
+            field("raw").stored_bytes,
+            "<html><body>Some content</body></html>".len() as u64
+        );
+    }
+}
@@ -0,0 +1,421 @@
+//! Tokenizer used for all the full-text fields. Words are split on anything
+//! that isn't alphanumeric, like the default tokenizer. Chinese, Japanese, &
+//! Korean text isn't separated by spaces, so runs of CJK characters are
+//! indexed as overlapping bigrams instead, e.g. 東京タワー becomes 東京, 京タ,
+//! タワ, & ワー. A query for any part of the run then matches w/o needing a
+//! per-language dictionary.
+//!
+//! Common words like "the" & "of" are dropped, they match nearly every page.
+//! Remaining tokens are then run through a stemmer so e.g. "crawling" matches
+//! "crawled". Only one of the user's languages is used for each text, the one
+//! it's detected as, see `LanguageStemmer`.
+use sha2::{Digest, Sha256};
+use tantivy::tokenizer::{
+    BoxTokenStream, Language, LowerCaser, RemoveLongFilter, Stemmer, StopWordFilter, TextAnalyzer,
+    Token, TokenFilter, TokenStream, Tokenizer,
+};
+use tantivy::Index;
+
+use crate::scraper::lang;
+
+/// Name the tokenizer is registered under.
+pub const TOKENIZER_NAME: &str = "spyglass";
+/// Tokens longer than this (in bytes) are dropped, same as the default tokenizer.
+const MAX_TOKEN_LEN: usize = 40;
+/// Dropped from documents & queries unless the user has their own list.
+pub const DEFAULT_STOP_WORDS: [&str; 33] = [
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+/// Characters looked at to detect the language of a text.
+const DETECT_SAMPLE_LEN: usize = 1024;
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        // CJK unified ideographs & extensions
+        '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{2FA1F}'
+        // Hiragana, katakana, & half-width katakana
+        | '\u{3040}'..='\u{30FF}'
+        | '\u{31F0}'..='\u{31FF}'
+        | '\u{FF66}'..='\u{FF9F}'
+        // Hangul
+        | '\u{1100}'..='\u{11FF}'
+        | '\u{3130}'..='\u{318F}'
+        | '\u{AC00}'..='\u{D7AF}')
+}
+
+/// Splits text into words & CJK bigrams.
+#[derive(Clone)]
+pub struct MultilingualTokenizer;
+
+struct VecTokenStream {
+    tokens: Vec<Token>,
+    idx: usize,
+}
+
+impl TokenStream for VecTokenStream {
+    fn advance(&mut self) -> bool {
+        if self.idx < self.tokens.len() {
+            self.idx += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.idx - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.idx - 1]
+    }
+}
+
+impl Tokenizer for MultilingualTokenizer {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        BoxTokenStream::from(VecTokenStream {
+            tokens: segment(text),
+            idx: 0,
+        })
+    }
+}
+
+fn push_token(tokens: &mut Vec<Token>, text: &str, from: usize, to: usize) {
+    tokens.push(Token {
+        offset_from: from,
+        offset_to: to,
+        position: tokens.len(),
+        text: text[from..to].to_string(),
+        position_length: 1,
+    });
+}
+
+/// Bigrams for a run of CJK characters, or the character itself if it's on
+/// its own.
+fn push_bigrams(tokens: &mut Vec<Token>, text: &str, run: &[(usize, char)]) {
+    let end = |(offset, c): (usize, char)| offset + c.len_utf8();
+    if run.len() == 1 {
+        push_token(tokens, text, run[0].0, end(run[0]));
+        return;
+    }
+
+    for pair in run.windows(2) {
+        push_token(tokens, text, pair[0].0, end(pair[1]));
+    }
+}
+
+/// Split `text` into tokens, see the module docs.
+fn segment(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word_start: Option<usize> = None;
+    let mut cjk_run: Vec<(usize, char)> = Vec::new();
+
+    for (offset, c) in text.char_indices() {
+        let cjk = is_cjk(c);
+        if !cjk && !cjk_run.is_empty() {
+            push_bigrams(&mut tokens, text, &cjk_run);
+            cjk_run.clear();
+        }
+
+        if cjk || !c.is_alphanumeric() {
+            if let Some(start) = word_start.take() {
+                push_token(&mut tokens, text, start, offset);
+            }
+        }
+
+        if cjk {
+            cjk_run.push((offset, c));
+        } else if c.is_alphanumeric() && word_start.is_none() {
+            word_start = Some(offset);
+        }
+    }
+
+    if let Some(start) = word_start {
+        push_token(&mut tokens, text, start, text.len());
+    }
+
+    if !cjk_run.is_empty() {
+        push_bigrams(&mut tokens, text, &cjk_run);
+    }
+
+    tokens
+}
+
+fn parse_language(name: &str) -> Option<Language> {
+    let language = match name.trim().to_lowercase().as_str() {
+        "danish" => Language::Danish,
+        "dutch" => Language::Dutch,
+        "english" => Language::English,
+        "finnish" => Language::Finnish,
+        "french" => Language::French,
+        "german" => Language::German,
+        "hungarian" => Language::Hungarian,
+        "italian" => Language::Italian,
+        "norwegian" => Language::Norwegian,
+        "portuguese" => Language::Portuguese,
+        "romanian" => Language::Romanian,
+        "russian" => Language::Russian,
+        "spanish" => Language::Spanish,
+        "swedish" => Language::Swedish,
+        "turkish" => Language::Turkish,
+        _ => return None,
+    };
+
+    Some(language)
+}
+
+/// ISO 639-1 code of a stemmer language, same as the detected `lang` of a page.
+fn language_code(language: Language) -> &'static str {
+    match language {
+        Language::Arabic => "ar",
+        Language::Danish => "da",
+        Language::Dutch => "nl",
+        Language::English => "en",
+        Language::Finnish => "fi",
+        Language::French => "fr",
+        Language::German => "de",
+        Language::Greek => "el",
+        Language::Hungarian => "hu",
+        Language::Italian => "it",
+        Language::Norwegian => "nb",
+        Language::Portuguese => "pt",
+        Language::Romanian => "ro",
+        Language::Russian => "ru",
+        Language::Spanish => "es",
+        Language::Swedish => "sv",
+        Language::Tamil => "ta",
+        Language::Turkish => "tr",
+    }
+}
+
+/// Stems each text w/ a single stemmer, the one for the language the text is
+/// detected as, falling back to the first of the user's languages. Chaining
+/// them would run e.g. English words through the German rules as well.
+#[derive(Clone)]
+struct LanguageStemmer {
+    languages: Vec<Language>,
+}
+
+impl LanguageStemmer {
+    fn pick(&self, tokens: &[Token]) -> Language {
+        if self.languages.len() > 1 {
+            let mut sample = String::new();
+            for token in tokens {
+                if sample.len() >= DETECT_SAMPLE_LEN {
+                    break;
+                }
+                sample.push_str(&token.text);
+                sample.push(' ');
+            }
+
+            let detected = lang::detect(None, &sample).and_then(|code| {
+                self.languages
+                    .iter()
+                    .find(|language| language_code(**language) == code)
+            });
+            if let Some(language) = detected {
+                return *language;
+            }
+        }
+
+        self.languages[0]
+    }
+}
+
+impl TokenFilter for LanguageStemmer {
+    fn transform<'a>(&self, mut token_stream: BoxTokenStream<'a>) -> BoxTokenStream<'a> {
+        let mut tokens = Vec::new();
+        while token_stream.advance() {
+            tokens.push(token_stream.token().clone());
+        }
+
+        let language = self.pick(&tokens);
+        Stemmer::new(language).transform(BoxTokenStream::from(VecTokenStream { tokens, idx: 0 }))
+    }
+}
+
+/// Stemmer languages from the user settings, e.g. `["english"]`. Unknown &
+/// duplicate languages are skipped.
+pub fn languages(names: &[String]) -> Vec<Language> {
+    let mut languages = Vec::new();
+    for name in names {
+        match parse_language(name) {
+            Some(language) if !languages.contains(&language) => languages.push(language),
+            Some(_) => {}
+            None => log::warn!("No stemmer available for `{}`", name),
+        }
+    }
+
+    languages
+}
+
+/// Stop words from the user settings, or the default list if they haven't
+/// set their own. Words are lowercased to match the tokens they're compared to.
+pub fn stop_words(custom: &Option<Vec<String>>) -> Vec<String> {
+    let mut words: Vec<String> = match custom {
+        Some(words) => words
+            .iter()
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect(),
+        None => DEFAULT_STOP_WORDS
+            .iter()
+            .map(|word| word.to_string())
+            .collect(),
+    };
+
+    words.sort();
+    words.dedup();
+    words
+}
+
+/// Identifies how the analyzer processes text. Documents have to be
+/// reindexed when this changes.
+pub fn analyzer_id(languages: &[Language], stop_words: &[String]) -> String {
+    let stemmers: Vec<String> = languages
+        .iter()
+        .map(|language| format!("{:?}", language).to_lowercase())
+        .collect();
+    // Joined w/ `|` since the stemmers stopped being chained, which used `,`.
+    let id = format!("{}:{}", TOKENIZER_NAME, stemmers.join("|"));
+    if stop_words.is_empty() {
+        return id;
+    }
+
+    // Stop word lists can be long, a hash is enough to tell them apart.
+    let mut hasher = Sha256::new();
+    hasher.update(stop_words.join("\n").as_bytes());
+    format!("{}:stop-{}", id, &hex::encode(hasher.finalize())[..8])
+}
+
+/// Analyzer used to index & query the full-text fields.
+pub fn analyzer(languages: &[Language], stop_words: &[String]) -> TextAnalyzer {
+    let mut analyzer = TextAnalyzer::from(MultilingualTokenizer)
+        .filter(RemoveLongFilter::limit(MAX_TOKEN_LEN))
+        .filter(LowerCaser);
+
+    // Removed before stemming, the lists are made up of whole words.
+    if !stop_words.is_empty() {
+        analyzer = analyzer.filter(StopWordFilter::remove(stop_words.to_vec()));
+    }
+
+    if !languages.is_empty() {
+        analyzer = analyzer.filter(LanguageStemmer {
+            languages: languages.to_vec(),
+        });
+    }
+
+    analyzer
+}
+
+/// Make the analyzer available to `index`. Needs to be done each time an
+/// index is opened.
+pub fn register(index: &Index, languages: &[Language], stop_words: &[String]) {
+    index
+        .tokenizers()
+        .register(TOKENIZER_NAME, analyzer(languages, stop_words));
+}
+
+#[cfg(test)]
+mod test {
+    use tantivy::tokenizer::Language;
+
+    use super::{analyzer, analyzer_id, languages, stop_words};
+
+    fn tokens(text: &str) -> Vec<(String, usize, usize)> {
+        let mut tokens = Vec::new();
+        analyzer(&[], &[]).token_stream(text).process(&mut |token| {
+            tokens.push((token.text.clone(), token.offset_from, token.offset_to))
+        });
+        tokens
+    }
+
+    fn texts(text: &str) -> Vec<String> {
+        tokens(text).into_iter().map(|(text, _, _)| text).collect()
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(texts("Hello, World! c++"), vec!["hello", "world", "c"]);
+    }
+
+    #[test]
+    fn test_cjk_bigrams() {
+        assert_eq!(texts("東京タワー"), vec!["東京", "京タ", "タワ", "ワー"]);
+        // Offsets are in bytes
+        assert_eq!(tokens("東京")[0], ("東京".to_string(), 0, 6));
+        // Single characters are kept as is
+        assert_eq!(texts("用 한"), vec!["用", "한"]);
+    }
+
+    #[test]
+    fn test_mixed_scripts() {
+        assert_eq!(
+            texts("iPhone用のケース 2022"),
+            vec!["iphone", "用の", "のケ", "ケー", "ース", "2022"]
+        );
+    }
+
+    #[test]
+    fn test_stemming() {
+        let mut stemmed = Vec::new();
+        analyzer(&[Language::English], &[])
+            .token_stream("Crawling crawled crawls")
+            .process(&mut |token| stemmed.push(token.text.clone()));
+        assert_eq!(stemmed, vec!["crawl", "crawl", "crawl"]);
+
+        // Only stemmed once, chained the German rules would turn this into "bas"
+        let mut stemmed = Vec::new();
+        analyzer(&[Language::English, Language::German], &[])
+            .token_stream("bases")
+            .process(&mut |token| stemmed.push(token.text.clone()));
+        assert_eq!(stemmed, vec!["base"]);
+    }
+
+    #[test]
+    fn test_languages() {
+        let names = vec![
+            "English".to_string(),
+            "klingon".to_string(),
+            "english".to_string(),
+            "german".to_string(),
+        ];
+        let parsed = languages(&names);
+        assert_eq!(parsed, vec![Language::English, Language::German]);
+        assert_eq!(analyzer_id(&parsed, &[]), "spyglass:english|german");
+        assert_eq!(analyzer_id(&[], &[]), "spyglass:");
+    }
+
+    #[test]
+    fn test_stop_words() {
+        let defaults = stop_words(&None);
+        assert!(defaults.contains(&"the".to_string()));
+
+        let custom = stop_words(&Some(vec![
+            " Der ".to_string(),
+            "die".to_string(),
+            "".into(),
+        ]));
+        assert_eq!(custom, vec!["der", "die"]);
+        assert!(stop_words(&Some(Vec::new())).is_empty());
+
+        // Changing the list changes the analyzer
+        assert_ne!(analyzer_id(&[], &defaults), analyzer_id(&[], &custom));
+        assert_eq!(analyzer_id(&[], &custom), analyzer_id(&[], &custom));
+
+        // Positions are kept so phrases still line up
+        let mut tokens = Vec::new();
+        analyzer(&[], &defaults)
+            .token_stream("Of Mice and Men")
+            .process(&mut |token| tokens.push((token.text.clone(), token.position)));
+        assert_eq!(
+            tokens,
+            vec![("mice".to_string(), 1), ("men".to_string(), 3)]
+        );
+    }
+}
@@ -1,24 +1,51 @@
 use std::sync::Arc;
 
-use dashmap::DashMap;
-use entities::models::create_connection;
+use dashmap::{DashMap, DashSet};
+use entities::bloom::{BloomFilter, SharedBloomFilter};
+use entities::models::{crawl_queue, create_connection};
 use entities::sea_orm::DatabaseConnection;
 use tokio::sync::mpsc::Sender;
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, Mutex};
 
 use crate::{
+    favicon::FaviconCache,
+    memory::MemoryMonitor,
+    pipeline::PipelineStats,
     plugin::PluginCommand,
-    search::{IndexPath, Searcher},
+    search::{
+        recovery::{self, IndexHealth},
+        semantic::{Embedder, VectorStore},
+        tokenizer, IndexPath, Searcher,
+    },
 };
 use shared::config::{Config, Lens, UserSettings};
+use shared::response::StatusEvent;
+
+/// Number of status events kept for subscribers that fall behind.
+const STATUS_EVENT_CAPACITY: usize = 64;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: DatabaseConnection,
     pub app_state: Arc<DashMap<String, String>>,
     pub lenses: Arc<DashMap<String, Lens>>,
+    /// Domains & lenses (by name) whose crawl tasks are left in the queue.
+    pub paused_domains: Arc<DashSet<String>>,
+    pub paused_lenses: Arc<DashSet<String>>,
     pub user_settings: UserSettings,
     pub index: Searcher,
+    /// URLs already enqueued or indexed, used to cheaply skip duplicate links.
+    pub seen_urls: SharedBloomFilter,
+    /// Document embeddings, empty unless semantic search is enabled.
+    pub vectors: VectorStore,
+    /// Embeds search queries, set when semantic search is enabled.
+    pub embedder: Option<Embedder>,
+    pub memory: Arc<MemoryMonitor>,
+    /// Queue depths for each stage of the crawl pipeline.
+    pub pipeline: Arc<PipelineStats>,
+    pub favicons: Arc<FaviconCache>,
+    /// Pushed to `subscribe_status` subscribers, see `publish`.
+    pub status_events: broadcast::Sender<StatusEvent>,
     // Plugin command/control
     pub plugin_cmd_tx: Arc<Mutex<Option<Sender<PluginCommand>>>>,
 }
@@ -29,7 +56,50 @@ impl AppState {
             .await
             .expect("Unable to connect to database");
 
-        let index = Searcher::with_index(&IndexPath::LocalPath(config.index_dir()));
+        // Make sure the index is usable before opening it.
+        let languages = tokenizer::languages(&config.user_settings.stemming_languages);
+        let stop_words = tokenizer::stop_words(&config.user_settings.stop_words);
+        let index_health = recovery::check_index(
+            &config.index_dir(),
+            &tokenizer::analyzer_id(&languages, &stop_words),
+        );
+        let index = Searcher::with_settings(
+            &IndexPath::LocalPath(config.index_dir()),
+            &config.user_settings,
+        );
+
+        if index_health != IndexHealth::Clean {
+            log::warn!("Repairing index: {:?}", index_health);
+            match recovery::repair(&db, &index).await {
+                Ok(stats) => log::info!("Index repaired: {:?}", stats),
+                Err(err) => log::error!("Unable to repair index: {}", err),
+            }
+        }
+
+        let vectors = match &config.user_settings.semantic_search {
+            Some(settings) => match VectorStore::load(&db, &settings.model).await {
+                Ok(vectors) => vectors,
+                Err(err) => {
+                    log::error!("Unable to load embeddings: {}", err);
+                    VectorStore::default()
+                }
+            },
+            None => VectorStore::default(),
+        };
+
+        let embedder = config
+            .user_settings
+            .semantic_search
+            .as_ref()
+            .map(|settings| Embedder::new(&config.user_settings, settings));
+
+        let seen_urls = match crawl_queue::load_seen_urls(&db).await {
+            Ok(seen_urls) => seen_urls,
+            Err(err) => {
+                log::error!("Unable to load seen URLs: {}", err);
+                BloomFilter::default()
+            }
+        };
 
         // TODO: Load from saved preferences
         let app_state = DashMap::new();
@@ -46,7 +116,61 @@ impl AppState {
             app_state: Arc::new(app_state),
             user_settings: config.user_settings.clone(),
             lenses: Arc::new(lenses),
+            paused_domains: Arc::new(DashSet::new()),
+            paused_lenses: Arc::new(DashSet::new()),
             index,
+            seen_urls: seen_urls.shared(),
+            vectors,
+            embedder,
+            memory: Arc::new(MemoryMonitor::default()),
+            pipeline: Arc::new(PipelineStats::default()),
+            favicons: Arc::new(FaviconCache::new(config.favicons_dir())),
+            status_events: broadcast::channel(STATUS_EVENT_CAPACITY).0,
+            plugin_cmd_tx: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Let status subscribers know something changed. Dropped if nobody is
+    /// listening.
+    pub fn publish(&self, event: StatusEvent) {
+        let _ = self.status_events.send(event);
+    }
+
+    /// Rebuild `seen_urls` in the background after URLs were removed, which
+    /// would otherwise be skipped as duplicates if they're added again.
+    pub fn refresh_seen_urls(&self) {
+        let db = self.db.clone();
+        let seen_urls = self.seen_urls.clone();
+        tokio::spawn(async move {
+            if let Err(err) = crawl_queue::rebuild_seen_urls(&db, &seen_urls).await {
+                log::error!("Unable to rebuild seen URLs: {}", err);
+            }
+        });
+    }
+
+    /// State backed by `db` & an in-memory index, for tests.
+    #[cfg(test)]
+    pub fn for_test(db: DatabaseConnection, user_settings: &UserSettings) -> Self {
+        let app_state = DashMap::new();
+        app_state.insert("paused".to_string(), "false".to_string());
+
+        AppState {
+            db,
+            app_state: Arc::new(app_state),
+            user_settings: user_settings.clone(),
+            lenses: Arc::new(DashMap::new()),
+            paused_domains: Arc::new(DashSet::new()),
+            paused_lenses: Arc::new(DashSet::new()),
+            index: Searcher::with_settings(&IndexPath::Memory, user_settings),
+            seen_urls: BloomFilter::default().shared(),
+            vectors: VectorStore::default(),
+            embedder: None,
+            memory: Arc::new(MemoryMonitor::default()),
+            pipeline: Arc::new(PipelineStats::default()),
+            favicons: Arc::new(FaviconCache::new(
+                std::env::temp_dir().join("spyglass-test-favicons"),
+            )),
+            status_events: broadcast::channel(STATUS_EVENT_CAPACITY).0,
             plugin_cmd_tx: Arc::new(Mutex::new(None)),
         }
     }
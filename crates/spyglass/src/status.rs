@@ -0,0 +1,115 @@
+//! Pushes crawl & index status to `subscribe_status` subscribers as it
+//! changes. Plugin events are published by the plugin manager directly.
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+
+use entities::models::crawl_queue;
+use shared::response::{QueueCounts, StatusEvent};
+
+use crate::state::AppState;
+use crate::task::AppShutdown;
+
+/// How often the status is checked for changes.
+const CHECK_INTERVAL_MS: u64 = 1000;
+
+/// Status last pushed to subscribers, so only changes are sent.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Snapshot {
+    counts: QueueCounts,
+    num_docs: u64,
+    is_paused: bool,
+}
+
+/// Events for everything that changed since `last`, or the full status if
+/// nothing has been sent yet.
+fn changed(last: Option<&Snapshot>, current: &Snapshot) -> Vec<StatusEvent> {
+    let mut events = Vec::new();
+    if last.map_or(true, |last| last.counts != current.counts) {
+        events.push(StatusEvent::CrawlProgress(current.counts.clone()));
+    }
+    if last.map_or(true, |last| last.num_docs != current.num_docs) {
+        events.push(StatusEvent::IndexUpdated {
+            num_docs: current.num_docs,
+        });
+    }
+    if last.map_or(true, |last| last.is_paused != current.is_paused) {
+        events.push(StatusEvent::PauseToggled {
+            is_paused: current.is_paused,
+        });
+    }
+
+    events
+}
+
+async fn snapshot(state: &AppState) -> anyhow::Result<Snapshot> {
+    let counts = crawl_queue::status_counts(&state.db, None).await?;
+    let is_paused = state
+        .app_state
+        .get("paused")
+        .map(|paused| *paused == *"true")
+        .unwrap_or(false);
+
+    Ok(Snapshot {
+        counts,
+        num_docs: state.index.reader.searcher().num_docs(),
+        is_paused,
+    })
+}
+
+pub async fn monitor_task(state: AppState, mut shutdown_rx: broadcast::Receiver<AppShutdown>) {
+    log::info!("status monitor started");
+
+    let mut last: Option<Snapshot> = None;
+    let mut interval = tokio::time::interval(Duration::from_millis(CHECK_INTERVAL_MS));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown_rx.recv() => {
+                log::info!("🛑 Shutting down status monitor");
+                return;
+            }
+        }
+
+        // Nobody's listening, new subscribers get the full status.
+        if state.status_events.receiver_count() == 0 {
+            last = None;
+            continue;
+        }
+
+        let current = match snapshot(&state).await {
+            Ok(current) => current,
+            Err(err) => {
+                log::error!("Unable to check status: {}", err);
+                continue;
+            }
+        };
+
+        for event in changed(last.as_ref(), &current) {
+            state.publish(event);
+        }
+        last = Some(current);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use shared::response::StatusEvent;
+
+    use super::{changed, Snapshot};
+
+    #[test]
+    fn test_changed() {
+        let last = Snapshot::default();
+        assert_eq!(changed(Some(&last), &last), Vec::new());
+        // Everything is sent the first time around
+        assert_eq!(changed(None, &last).len(), 3);
+
+        let mut current = last.clone();
+        current.num_docs = 10;
+        current.counts.num_completed = 10;
+        let events = changed(Some(&last), &current);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1], StatusEvent::IndexUpdated { num_docs: 10 });
+    }
+}
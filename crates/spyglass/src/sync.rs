@@ -0,0 +1,583 @@
+//! Opt-in replication of indexed documents & lenses between devices. Each
+//! device publishes a snapshot of what it has to a shared folder or a
+//! self-hosted endpoint & merges in the snapshots of every other device.
+//!
+//! When both sides have a copy of something the content hashes are compared
+//! first. Only if they differ does the most recently updated copy win.
+//! Deletions are not replicated, but documents deleted on this device aren't
+//! brought back by copies that were last updated before the delete.
+//!
+//! Endpoints are expected to support:
+//! * `GET <url>/snapshots`, returning a JSON array of every device's snapshot.
+//! * `PUT <url>/snapshots/<device>`, replacing the snapshot for that device.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use entities::models::{crawl_queue, document_tombstone, indexed_document};
+use entities::sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use shared::config::{Config, SyncSettings, SyncTarget};
+
+use crate::scraper::html_to_text;
+use crate::search::Searcher;
+use crate::state::AppState;
+use crate::task::add_to_index;
+
+/// Bumped whenever the snapshot format changes in an incompatible way.
+const SNAPSHOT_VERSION: u32 = 1;
+const DEVICE_ID_FILE: &str = "device_id";
+/// Documents read from the database at a time when taking a snapshot.
+const BATCH_SIZE: u64 = 1000;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub device: String,
+    pub created_at: DateTime<Utc>,
+    pub documents: Vec<SyncDocument>,
+    pub lenses: Vec<SyncLens>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SyncDocument {
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    pub content_hash: String,
+    pub updated_at: DateTime<Utc>,
+    /// Cached copy of the page, only included when `include_pages` is set.
+    #[serde(default)]
+    pub raw: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SyncLens {
+    pub file_name: String,
+    pub contents: String,
+    pub content_hash: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+pub struct SyncStats {
+    /// Documents indexed straight from another device's snapshot.
+    pub documents_indexed: usize,
+    /// Documents w/o a cached copy that were queued up to be crawled.
+    pub documents_queued: usize,
+    pub lenses_updated: usize,
+}
+
+/// Hash & last update of our copy of something, keyed by URL/file name.
+type Known = HashMap<String, (String, DateTime<Utc>)>;
+
+fn content_hash(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        // So ("ab", "c") & ("a", "bc") don't hash the same
+        hasher.update([0]);
+    }
+
+    hex::encode(&hasher.finalize()[..])
+}
+
+/// Should the copy from another device replace ours, if we have one?
+fn remote_wins(
+    local: Option<&(String, DateTime<Utc>)>,
+    hash: &str,
+    updated_at: DateTime<Utc>,
+) -> bool {
+    match local {
+        None => true,
+        Some((local_hash, local_updated_at)) => {
+            local_hash != hash && updated_at > *local_updated_at
+        }
+    }
+}
+
+/// Lens files are written straight into the lenses folder, make sure a
+/// snapshot can't point anywhere else.
+fn is_valid_lens_name(file_name: &str) -> bool {
+    let path = Path::new(file_name);
+    path.file_name()
+        .map(|name| name == file_name)
+        .unwrap_or(false)
+        && path.extension().unwrap_or_default() == "ron"
+}
+
+/// Stable id for this device, generated the first time it's needed.
+fn device_id(data_dir: &Path) -> anyhow::Result<String> {
+    let path = data_dir.join(DEVICE_ID_FILE);
+    if let Ok(id) = fs::read_to_string(&path) {
+        let id = id.trim();
+        if !id.is_empty() {
+            return Ok(id.to_string());
+        }
+    }
+
+    let id = uuid::Uuid::new_v4().as_hyphenated().to_string();
+    fs::write(&path, &id)?;
+    Ok(id)
+}
+
+/// Reads the database in batches & looks up the stored fields of each document
+/// as it goes, so only what ends up in the snapshot is kept around.
+async fn local_documents(
+    state: &AppState,
+    include_pages: bool,
+) -> anyhow::Result<Vec<SyncDocument>> {
+    let fields = Searcher::doc_fields();
+
+    let mut documents = Vec::new();
+    let mut last_id = 0;
+    loop {
+        let batch = indexed_document::Entity::find()
+            .filter(indexed_document::Column::Id.gt(last_id))
+            .order_by_asc(indexed_document::Column::Id)
+            .limit(BATCH_SIZE)
+            .all(&state.db)
+            .await?;
+
+        last_id = match batch.last() {
+            Some(doc) => doc.id,
+            None => break,
+        };
+
+        for doc in batch {
+            let stored = match Searcher::get_by_id(&state.index.reader, &doc.doc_id) {
+                Some(stored) => stored,
+                None => continue,
+            };
+            let text = |field| {
+                stored
+                    .get_first(field)
+                    .and_then(|value| value.as_text())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+
+            let title = text(fields.title);
+            let description = text(fields.description);
+            let raw = text(fields.raw);
+            let content_hash = content_hash(&[&doc.url, &title, &description, &raw]);
+
+            documents.push(SyncDocument {
+                url: doc.url,
+                title,
+                description,
+                content_hash,
+                updated_at: doc.updated_at,
+                raw: (include_pages && !raw.is_empty()).then(|| raw),
+            });
+        }
+    }
+
+    Ok(documents)
+}
+
+fn local_lenses(lenses_dir: &Path) -> anyhow::Result<Vec<SyncLens>> {
+    let mut lenses = Vec::new();
+    for entry in fs::read_dir(lenses_dir)?.flatten() {
+        let path = entry.path();
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if path.is_file() && is_valid_lens_name(name) => name.to_string(),
+            _ => continue,
+        };
+
+        let contents = fs::read_to_string(&path)?;
+        lenses.push(SyncLens {
+            content_hash: content_hash(&[&contents]),
+            updated_at: entry.metadata()?.modified()?.into(),
+            file_name,
+            contents,
+        });
+    }
+
+    Ok(lenses)
+}
+
+/// Was our copy deleted after `updated_at`? Only checked if we don't have a
+/// copy, re-adding a document makes it count again.
+fn is_deleted(
+    tombstones: &HashMap<String, DateTime<Utc>>,
+    url: &str,
+    updated_at: DateTime<Utc>,
+) -> bool {
+    matches!(tombstones.get(url), Some(deleted_at) if updated_at <= *deleted_at)
+}
+
+async fn merge_documents(
+    state: &AppState,
+    documents: Vec<SyncDocument>,
+    known: &mut Known,
+    tombstones: &HashMap<String, DateTime<Utc>>,
+    stats: &mut SyncStats,
+) -> anyhow::Result<()> {
+    let mut to_crawl = Vec::new();
+    for remote in documents {
+        let local = known.get(&remote.url);
+        if !remote_wins(local, &remote.content_hash, remote.updated_at)
+            || (local.is_none() && is_deleted(tombstones, &remote.url, remote.updated_at))
+        {
+            continue;
+        }
+
+        // One bad entry shouldn't hold up the rest of the sync.
+        let url = match Url::parse(&remote.url) {
+            Ok(url) => url,
+            Err(err) => {
+                log::warn!("Skipping synced document <{}>: {}", remote.url, err);
+                continue;
+            }
+        };
+
+        match remote.raw {
+            Some(raw) if !raw.is_empty() => {
+                let (content, raw) = tokio::task::spawn_blocking(move || {
+                    let content = html_to_text(&raw).content;
+                    (content, raw)
+                })
+                .await?;
+
+                add_to_index(
+                    state,
+                    &url,
+                    &remote.title,
+                    &remote.description,
+                    &content,
+                    &raw,
+                    None,
+                )
+                .await?;
+                stats.documents_indexed += 1;
+            }
+            _ => to_crawl.push(remote.url.clone()),
+        }
+
+        // Another device may have an older copy, don't let it win later on.
+        known.insert(remote.url, (remote.content_hash, remote.updated_at));
+    }
+
+    if !to_crawl.is_empty() {
+        crawl_queue::enqueue_all(
+            &state.db,
+            &to_crawl,
+            &[],
+            &state.user_settings,
+            &Default::default(),
+        )
+        .await?;
+        // Anything we've crawled before is already in the queue.
+        crawl_queue::requeue_urls(&state.db, &to_crawl).await?;
+        stats.documents_queued += to_crawl.len();
+    }
+
+    Ok(())
+}
+
+fn merge_lenses(
+    lenses_dir: &Path,
+    lenses: Vec<SyncLens>,
+    known: &mut Known,
+    stats: &mut SyncStats,
+) -> anyhow::Result<()> {
+    for remote in lenses {
+        if !is_valid_lens_name(&remote.file_name) {
+            log::warn!("Ignoring synced lens w/ invalid name: {}", remote.file_name);
+            continue;
+        }
+
+        if !remote_wins(
+            known.get(&remote.file_name),
+            &remote.content_hash,
+            remote.updated_at,
+        ) {
+            continue;
+        }
+
+        fs::write(lenses_dir.join(&remote.file_name), &remote.contents)?;
+        stats.lenses_updated += 1;
+        known.insert(remote.file_name, (remote.content_hash, remote.updated_at));
+    }
+
+    Ok(())
+}
+
+/// Where snapshots are exchanged.
+enum Store {
+    Folder(PathBuf),
+    Endpoint {
+        client: reqwest::Client,
+        url: String,
+        token: String,
+    },
+}
+
+impl Store {
+    fn new(target: &SyncTarget) -> anyhow::Result<Self> {
+        Ok(match target {
+            SyncTarget::Folder(path) => {
+                fs::create_dir_all(path)?;
+                Store::Folder(path.clone())
+            }
+            SyncTarget::Endpoint { url, token } => Store::Endpoint {
+                client: reqwest::Client::builder()
+                    .timeout(Duration::from_secs(300))
+                    .build()?,
+                url: url.trim_end_matches('/').to_string(),
+                token: token.clone(),
+            },
+        })
+    }
+
+    async fn read_snapshots(&self) -> anyhow::Result<Vec<Snapshot>> {
+        match self {
+            Store::Folder(path) => {
+                let mut snapshots = Vec::new();
+                for entry in fs::read_dir(path)?.flatten() {
+                    let path = entry.path();
+                    if path.extension().unwrap_or_default() != "json" {
+                        continue;
+                    }
+
+                    // Could be halfway through being synced to this device,
+                    // it'll be picked up next time.
+                    match fs::read(&path)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|bytes| Ok(serde_json::from_slice(&bytes)?))
+                    {
+                        Ok(snapshot) => snapshots.push(snapshot),
+                        Err(err) => log::warn!("Unable to read snapshot {:?}: {}", path, err),
+                    }
+                }
+
+                Ok(snapshots)
+            }
+            Store::Endpoint { client, url, token } => {
+                let resp = client
+                    .get(format!("{}/snapshots", url))
+                    .bearer_auth(token)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+
+                Ok(serde_json::from_slice(&resp.bytes().await?)?)
+            }
+        }
+    }
+
+    async fn write_snapshot(&self, snapshot: &Snapshot) -> anyhow::Result<()> {
+        let body = serde_json::to_vec(snapshot)?;
+        match self {
+            Store::Folder(path) => {
+                // Write then rename so other devices never see a partial file.
+                let tmp = path.join(format!("{}.tmp", snapshot.device));
+                fs::write(&tmp, body)?;
+                fs::rename(&tmp, path.join(format!("{}.json", snapshot.device)))?;
+            }
+            Store::Endpoint { client, url, token } => {
+                client
+                    .put(format!("{}/snapshots/{}", url, snapshot.device))
+                    .bearer_auth(token)
+                    .header("content-type", "application/json")
+                    .body(body)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Merge in the snapshots from every other device, then publish ours.
+pub async fn run(
+    state: &AppState,
+    config: &Config,
+    settings: &SyncSettings,
+) -> anyhow::Result<SyncStats> {
+    let device = device_id(&config.data_dir())?;
+    let store = Store::new(&settings.target)?;
+    let lenses_dir = config.lenses_dir();
+
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        device: device.clone(),
+        created_at: Utc::now(),
+        documents: local_documents(state, settings.include_pages).await?,
+        lenses: local_lenses(&lenses_dir)?,
+    };
+
+    let mut known_docs: Known = snapshot
+        .documents
+        .iter()
+        .map(|doc| (doc.url.clone(), (doc.content_hash.clone(), doc.updated_at)))
+        .collect();
+    let mut known_lenses: Known = snapshot
+        .lenses
+        .iter()
+        .map(|lens| {
+            (
+                lens.file_name.clone(),
+                (lens.content_hash.clone(), lens.updated_at),
+            )
+        })
+        .collect();
+
+    let tombstones = document_tombstone::all(&state.db).await?;
+    let mut stats = SyncStats::default();
+    for remote in store.read_snapshots().await? {
+        if remote.device == device {
+            continue;
+        }
+
+        if remote.version != SNAPSHOT_VERSION {
+            log::warn!(
+                "Skipping snapshot from {} w/ unsupported version {}",
+                remote.device,
+                remote.version
+            );
+            continue;
+        }
+
+        merge_documents(
+            state,
+            remote.documents,
+            &mut known_docs,
+            &tombstones,
+            &mut stats,
+        )
+        .await?;
+        merge_lenses(&lenses_dir, remote.lenses, &mut known_lenses, &mut stats)?;
+    }
+
+    store.write_snapshot(&snapshot).await?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use std::collections::HashMap;
+
+    use super::{
+        is_deleted, is_valid_lens_name, merge_documents, remote_wins, Snapshot, Store,
+        SyncDocument, SyncStats, SNAPSHOT_VERSION,
+    };
+    use crate::state::AppState;
+    use entities::models::document_tombstone;
+    use entities::test::setup_test_db;
+    use shared::config::UserSettings;
+
+    #[test]
+    fn test_remote_wins() {
+        let now = Utc.ymd(2022, 8, 10).and_hms(10, 0, 0);
+        let local = ("abc".to_string(), now);
+
+        // Missing locally
+        assert!(remote_wins(None, "abc", now));
+        // Same content, no matter how new
+        assert!(!remote_wins(Some(&local), "abc", now + Duration::days(1)));
+        // Different content, newest wins
+        assert!(remote_wins(Some(&local), "def", now + Duration::days(1)));
+        assert!(!remote_wins(Some(&local), "def", now - Duration::days(1)));
+    }
+
+    #[test]
+    fn test_is_deleted() {
+        let now = Utc.ymd(2022, 8, 10).and_hms(10, 0, 0);
+        let tombstones = HashMap::from([("https://example.com".to_string(), now)]);
+
+        assert!(is_deleted(&tombstones, "https://example.com", now));
+        assert!(is_deleted(
+            &tombstones,
+            "https://example.com",
+            now - Duration::days(1)
+        ));
+        // Updated elsewhere after we deleted it
+        assert!(!is_deleted(
+            &tombstones,
+            "https://example.com",
+            now + Duration::days(1)
+        ));
+        assert!(!is_deleted(&tombstones, "https://example.org", now));
+    }
+
+    #[tokio::test]
+    async fn test_merge_skips_deleted() {
+        let db = setup_test_db().await;
+        let state = AppState::for_test(db, &UserSettings::default());
+
+        let deleted = "https://example.com/deleted".to_string();
+        document_tombstone::record(&state.db, &[deleted.clone()])
+            .await
+            .unwrap();
+        let tombstones = document_tombstone::all(&state.db).await.unwrap();
+
+        let remote = |url: &str, updated_at| SyncDocument {
+            url: url.to_string(),
+            title: String::new(),
+            description: String::new(),
+            content_hash: "abc".to_string(),
+            updated_at,
+            raw: None,
+        };
+        let documents = vec![
+            remote(&deleted, Utc::now() - Duration::days(1)),
+            remote("not a url", Utc::now()),
+            remote("https://example.com/new", Utc::now()),
+        ];
+
+        let mut stats = SyncStats::default();
+        merge_documents(
+            &state,
+            documents,
+            &mut HashMap::new(),
+            &tombstones,
+            &mut stats,
+        )
+        .await
+        .unwrap();
+        assert_eq!(stats.documents_queued, 1);
+    }
+
+    #[test]
+    fn test_is_valid_lens_name() {
+        assert!(is_valid_lens_name("rust.ron"));
+        assert!(!is_valid_lens_name("rust.txt"));
+        assert!(!is_valid_lens_name("../rust.ron"));
+        assert!(!is_valid_lens_name("/etc/rust.ron"));
+    }
+
+    #[tokio::test]
+    async fn test_folder_round_trip() {
+        let path = std::env::temp_dir().join(format!("spyglass-sync-{}", std::process::id()));
+        let store = Store::Folder(path.clone());
+        std::fs::create_dir_all(&path).unwrap();
+
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            device: "device-a".to_string(),
+            created_at: Utc::now(),
+            documents: Vec::new(),
+            lenses: Vec::new(),
+        };
+        store.write_snapshot(&snapshot).await.unwrap();
+        // Not a snapshot, should be ignored
+        std::fs::write(path.join("notes.json"), "not json").unwrap();
+
+        let snapshots = store.read_snapshots().await.unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].device, "device-a");
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -1,13 +1,23 @@
+use std::time::{Duration, Instant};
+
 use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use sha2::{Digest, Sha256};
 use tokio::sync::{broadcast, mpsc};
 use url::Url;
 
-use entities::models::{crawl_queue, indexed_document};
+use entities::models::{
+    crawl_queue, document_content, feed_entry, indexed_document, resource_rule,
+};
+use entities::normalize::normalize;
 use entities::sea_orm::prelude::*;
 use entities::sea_orm::{ColumnTrait, EntityTrait, QueryFilter, Set};
 use shared::config::{Config, Lens};
+use spyglass_plugin::PluginEventPayload;
 
-use crate::crawler::Crawler;
+use crate::crawler::pool::DomainQueues;
+use crate::pipeline::Stage;
+use crate::plugin;
+use crate::scraper::{self, strip_for_storage};
 use crate::search::{
     lens::{load_lenses, read_lenses},
     Searcher,
@@ -17,6 +27,7 @@ use crate::state::AppState;
 #[derive(Debug, Clone)]
 pub struct CrawlTask {
     pub id: i64,
+    pub domain: String,
 }
 
 #[derive(Debug)]
@@ -29,245 +40,416 @@ pub enum AppShutdown {
     Now,
 }
 
-/// Manages the crawl queue
+/// How often we'll check the database for more tasks when the in-memory queues
+/// are running low.
+const REFILL_INTERVAL_MS: u64 = 1000;
+/// Cap on the Crawl-delay we'll honor, anything longer would stall a domain.
+const MAX_CRAWL_DELAY_SECS: i64 = 60;
+
+/// Domains & URL prefixes from the installed lenses, crawled before anything
+/// else.
+pub fn lens_priorities(state: &AppState) -> (Vec<String>, Vec<String>) {
+    let mut domains: Vec<String> = Vec::new();
+    let mut prefixes: Vec<String> = Vec::new();
+
+    for entry in state.lenses.iter() {
+        let value = entry.value();
+        domains.extend(value.domains.clone());
+        prefixes.extend(value.urls.clone());
+    }
+
+    (domains, prefixes)
+}
+
+/// Crawl tasks to leave in the queue for now, from the paused domains &
+/// lenses.
+pub fn paused_tasks(state: &AppState) -> crawl_queue::SkipFilter {
+    let mut skip = crawl_queue::SkipFilter {
+        domains: state
+            .paused_domains
+            .iter()
+            .map(|domain| domain.key().clone())
+            .collect(),
+        prefixes: Vec::new(),
+    };
+
+    for name in state.paused_lenses.iter() {
+        if let Some(lens) = state.lenses.get(name.key()) {
+            skip.domains.extend(lens.domains.clone());
+            skip.prefixes.extend(lens.urls.clone());
+        }
+    }
+
+    skip
+}
+
+/// Is `url` on one of the lens's domains (or their subdomains) or URLs?
+fn in_lens(lens: &Lens, url: &Url) -> bool {
+    let host = url.host_str().unwrap_or_default();
+    lens.domains
+        .iter()
+        .any(|domain| host == domain || host.ends_with(&format!(".{}", domain)))
+        || lens
+            .urls
+            .iter()
+            .any(|prefix| url.as_str().starts_with(prefix.trim_end_matches('$')))
+}
+
+/// Does `url` belong to a lens that needs its pages rendered before parsing?
+pub fn needs_render(state: &AppState, url: &Url) -> bool {
+    state.lenses.iter().any(|entry| {
+        let lens = entry.value();
+        lens.render && lens.is_enabled && in_lens(lens, url)
+    })
+}
+
+/// Links found on `page` that are worth following. Pages in a lens w/
+/// `lens_links_only` set only get to link within that lens.
+pub fn links_to_follow(state: &AppState, page: &Url, links: Vec<String>) -> Vec<String> {
+    let scopes: Vec<Lens> = state
+        .lenses
+        .iter()
+        .map(|entry| entry.value().clone())
+        .filter(|lens| lens.lens_links_only && lens.is_enabled && in_lens(lens, page))
+        .collect();
+    if scopes.is_empty() {
+        return links;
+    }
+
+    links
+        .into_iter()
+        .filter(|link| match Url::parse(link) {
+            Ok(link) => scopes.iter().any(|lens| in_lens(lens, &link)),
+            Err(_) => false,
+        })
+        .collect()
+}
+
+/// Manages the crawl queue. Tasks are pulled from the database in batches into
+/// per-domain queues & handed out to the crawl pipeline as inflight/politeness
+/// limits allow.
 #[tracing::instrument(skip_all)]
 pub async fn manager_task(
     state: AppState,
     queue: mpsc::Sender<Command>,
+    mut done_rx: mpsc::Receiver<String>,
     mut shutdown_rx: broadcast::Receiver<AppShutdown>,
 ) {
     log::info!("manager started");
 
+    let total_limit = state.user_settings.inflight_crawl_limit.value();
+    let mut queues = DomainQueues::new(
+        total_limit,
+        state.user_settings.inflight_domain_limit.value(),
+        Duration::from_millis(state.user_settings.politeness_delay_ms),
+    );
+    for (domain, delay_ms) in state.user_settings.domain_politeness_delays_ms.iter() {
+        queues.set_politeness_delay(domain, Duration::from_millis(*delay_ms));
+    }
+
+    let refill_interval = Duration::from_millis(REFILL_INTERVAL_MS);
+    let mut last_refill: Option<Instant> = None;
+
     loop {
-        if let Some(is_paused) = state.app_state.get("paused") {
-            if (*is_paused) == "true" {
-                // Run w/ a select on the shutdown signal otherwise we're stuck in an
-                // infinite loop
-                tokio::select! {
-                    _ = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => {
-                        continue
+        let is_paused = state
+            .app_state
+            .get("paused")
+            .map(|is_paused| *is_paused == "true")
+            .unwrap_or(false);
+
+        // Also hold off on crawling while we're over the memory limit.
+        if is_paused || state.memory.is_throttled() {
+            // Run w/ a select on the shutdown signal otherwise we're stuck in an
+            // infinite loop
+            tokio::select! {
+                _ = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => {
+                    continue
+                }
+                Some(domain) = done_rx.recv() => {
+                    queues.finish(&domain);
+                    continue
+                }
+                _ = shutdown_rx.recv() => {
+                    log::info!("🛑 Shutting down manager");
+                    return;
+                }
+            }
+        }
+
+        // Top off the in-memory queues
+        let should_refill = (queues.len() as u32) < total_limit
+            && last_refill
+                .map(|last| last.elapsed() >= refill_interval)
+                .unwrap_or(true);
+
+        if should_refill {
+            let (prioritized_domains, prioritized_prefixes) = lens_priorities(&state);
+            let batch_size = total_limit as u64 - queues.len() as u64;
+            match crawl_queue::dequeue_batch(
+                &state.db,
+                &state.user_settings,
+                &prioritized_domains,
+                &prioritized_prefixes,
+                &paused_tasks(&state),
+                batch_size,
+            )
+            .await
+            {
+                Ok(tasks) => {
+                    // Honor the Crawl-delay from robots.txt for these domains.
+                    let mut domains: Vec<String> =
+                        tasks.iter().map(|task| task.domain.clone()).collect();
+                    domains.sort();
+                    domains.dedup();
+                    match resource_rule::crawl_delays(&state.db, &domains).await {
+                        Ok(delays) => {
+                            for (domain, delay) in delays {
+                                let delay = delay.clamp(0, MAX_CRAWL_DELAY_SECS) as u64;
+                                queues.set_crawl_delay(&domain, Duration::from_secs(delay));
+                            }
+                        }
+                        Err(err) => log::error!("Unable to load crawl delays: {}", err),
                     }
-                    _ = shutdown_rx.recv() => {
-                        log::info!("🛑 Shutting down worker");
-                        return;
+
+                    for task in tasks {
+                        queues.push(CrawlTask {
+                            id: task.id,
+                            domain: task.domain,
+                        });
                     }
                 }
+                Err(err) => log::error!("Unable to dequeue: {}", err),
             }
-        }
 
-        let mut prioritized_domains: Vec<String> = Vec::new();
-        let mut prioritized_prefixes: Vec<String> = Vec::new();
+            last_refill = Some(Instant::now());
+        }
 
-        for entry in state.lenses.iter() {
-            let value = entry.value();
-            prioritized_domains.extend(value.domains.clone());
-            prioritized_prefixes.extend(value.urls.clone());
+        // Send everything that's ready to the workers
+        while let Some(task) = queues.next_ready(Instant::now()) {
+            state.pipeline.queued(Stage::Fetch);
+            if queue.send(Command::Fetch(task)).await.is_err() {
+                log::error!("unable to send command to worker");
+                return;
+            }
         }
 
         // tokio::select allows us to listen to a shutdown message while
-        // also processing queue tasks.
-        let next_url = tokio::select! {
-            res = crawl_queue::dequeue(
-                &state.db,
-                state.user_settings.clone(),
-                &prioritized_domains,
-                &prioritized_prefixes,
-            ) => res,
+        // waiting on workers.
+        tokio::select! {
+            Some(domain) = done_rx.recv() => queues.finish(&domain),
+            _ = tokio::time::sleep(tokio::time::Duration::from_millis(100)) => {}
             _ = shutdown_rx.recv() => {
                 log::info!("🛑 Shutting down manager");
                 return;
             }
-        };
-
-        match next_url {
-            Err(err) => log::error!("Unable to dequeue: {}", err),
-            Ok(Some(task)) => {
-                // Mark in progress
-                let task_id = task.id;
-                let mut update: crawl_queue::ActiveModel = task.into();
-                update.status = Set(crawl_queue::CrawlStatus::Processing);
-                let _ = update.update(&state.db).await;
-
-                // Send to worker
-                let cmd = Command::Fetch(CrawlTask { id: task_id });
-                if queue.send(cmd).await.is_err() {
-                    eprintln!("unable to send command to worker");
-                    return;
-                }
-            }
-            // ignore everything else
-            _ => {
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-            }
         }
     }
 }
 
-#[tracing::instrument(skip(state, crawler))]
-async fn _handle_fetch(state: AppState, crawler: Crawler, task: CrawlTask) {
-    let result = crawler.fetch_by_job(&state.db, task.id).await;
-
-    match result {
-        Ok(Some(crawl_result)) => {
-            // Update job status
-            // We consider 400s complete in this case since we manage to hit the server
-            // successfully but nothing useful was returned.
-            let cq_status = if crawl_result.is_success() || crawl_result.is_bad_request() {
-                crawl_queue::CrawlStatus::Completed
-            } else {
-                crawl_queue::CrawlStatus::Failed
-            };
-
-            let _ = crawl_queue::mark_done(&state.db, task.id, cq_status).await;
-
-            // Add all valid, non-duplicate, non-indexed links found to crawl queue
-            let to_enqueue: Vec<String> = crawl_result.links.into_iter().collect();
-
-            let lenses: Vec<Lens> = state
-                .lenses
-                .iter()
-                .map(|entry| entry.value().clone())
-                .collect();
-
-            if let Err(err) = crawl_queue::enqueue_all(
-                &state.db,
-                &to_enqueue,
-                &lenses,
-                &state.user_settings,
-                &Default::default(),
-            )
-            .await
-            {
-                log::error!("error enqueuing all: {}", err);
-            }
+/// Hash of a page's text content, ignoring differences in whitespace.
+pub fn content_hash(content: &str) -> String {
+    let mut hasher = Sha256::new();
+    for (idx, word) in content.split_whitespace().enumerate() {
+        if idx > 0 {
+            hasher.update(b" ");
+        }
+        hasher.update(word.as_bytes());
+    }
 
-            // Only add valid urls
-            // if added.is_none() || added.unwrap() == crawl_queue::SkipReason::Duplicate {
-            //     link::save_link(&state.db, &crawl_result.url, link)
-            //         .await
-            //         .unwrap();
-            // }
-
-            // Add / update search index w/ crawl result.
-            if let Some(content) = crawl_result.content {
-                let url = Url::parse(&crawl_result.url).expect("Invalid crawl URL");
-                let url_host = url.host_str().expect("Invalid URL host");
-
-                let existing = indexed_document::Entity::find()
-                    .filter(indexed_document::Column::Url.eq(url.as_str()))
-                    .one(&state.db)
-                    .await
-                    .unwrap_or_default();
-
-                // Delete old document, if any.
-                if let Some(doc) = &existing {
-                    if let Ok(mut index_writer) = state.index.writer.lock() {
-                        let _ = Searcher::delete(&mut index_writer, &doc.doc_id);
-                    }
-                }
+    hex::encode(&hasher.finalize()[..])
+}
 
-                // Add document to index
-                let doc_id: Option<String> = {
-                    if let Ok(mut index_writer) = state.index.writer.lock() {
-                        match Searcher::add_document(
-                            &mut index_writer,
-                            &crawl_result.title.unwrap_or_default(),
-                            &crawl_result.description.unwrap_or_default(),
-                            url_host,
-                            url.as_str(),
-                            &content,
-                            &crawl_result.raw.unwrap_or_default(),
-                        ) {
-                            Ok(new_doc_id) => Some(new_doc_id),
-                            _ => None,
-                        }
-                    } else {
-                        None
-                    }
-                };
-
-                if let Some(doc_id) = doc_id {
-                    // Update/create index reference in our database
-                    let indexed = if let Some(doc) = existing {
-                        let mut update: indexed_document::ActiveModel = doc.into();
-                        update.doc_id = Set(doc_id);
-                        update
-                    } else {
-                        indexed_document::ActiveModel {
-                            domain: Set(url_host.to_string()),
-                            url: Set(url.as_str().to_string()),
-                            doc_id: Set(doc_id),
-                            ..Default::default()
-                        }
-                    };
+/// Remove a document from the index & database.
+pub async fn remove_from_index(
+    state: &AppState,
+    doc: indexed_document::Model,
+) -> anyhow::Result<()> {
+    {
+        let mut index_writer = state
+            .index
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Unable to get index lock"))?;
+        if Searcher::delete(&mut index_writer, &doc.doc_id).is_ok() {
+            state.index.mark_pending(1);
+        }
+    }
 
-                    if let Err(e) = indexed.save(&state.db).await {
-                        log::error!("Unable to save document: {}", e);
-                    }
-                }
+    let urls = [doc.url.clone()];
+    document_content::remove_by_urls(&state.db, &urls).await?;
+    state.vectors.remove(&urls);
+    doc.delete(&state.db).await?;
+    Ok(())
+}
+
+/// Add / update a page in the search index & keep the indexed_document table
+/// in sync. Returns the new document id, or the id of the document w/ the same
+/// content if the page is a duplicate of one indexed under another URL.
+/// The page's language is detected from `content` if `lang` isn't known.
+#[tracing::instrument(skip(state, title, description, content, raw), fields(url = %url))]
+pub async fn add_to_index(
+    state: &AppState,
+    url: &Url,
+    title: &str,
+    description: &str,
+    content: &str,
+    raw: &str,
+    lang: Option<&str>,
+) -> anyhow::Result<String> {
+    // Index pages under their normalized URL, e.g. w/o tracking params.
+    let mut url = url.clone();
+    normalize(&mut url);
+    let url = &url;
+
+    // Local files don't have a host, group them together instead.
+    let url_host = match url.scheme() {
+        "file" => "localhost",
+        _ => url
+            .host_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid URL host"))?,
+    };
+
+    // Also replaces the page if it was indexed under another form of the URL,
+    // e.g. w/ http instead of https.
+    let existing = indexed_document::find_by_url_variants(&state.db, url.as_str())
+        .await
+        .unwrap_or_default();
+
+    // Skip pages we've already indexed under a different URL. Empty pages
+    // aren't considered duplicates of each other.
+    let hash = content_hash(content);
+    if !content.trim().is_empty() {
+        let duplicate = indexed_document::find_duplicate(&state.db, &hash, url.as_str())
+            .await?
+            .filter(|original| existing.as_ref().map(|doc| doc.id) != Some(original.id));
+        if let Some(original) = duplicate {
+            log::debug!("<{}> is a duplicate of <{}>", url, original.url);
+            // Content changed to match another page, drop the old copy.
+            if let Some(doc) = existing {
+                remove_from_index(state, doc).await?;
             }
+            return Ok(original.doc_id);
         }
-        Ok(None) => {
-            // Failed to grab robots.txt or crawling is not allowed
-            if let Err(e) =
-                crawl_queue::mark_done(&state.db, task.id, crawl_queue::CrawlStatus::Completed)
-                    .await
-            {
-                log::error!("Unable to mark task as finished: {}", e);
+    }
+
+    let raw = strip_for_storage(raw);
+    let lang = lang
+        .and_then(scraper::lang::normalize)
+        .or_else(|| scraper::lang::detect(None, content));
+    // Delete old document, if any, and add the new one.
+    let doc_id = {
+        let mut index_writer = state
+            .index
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Unable to get index lock"))?;
+
+        if let Some(doc) = &existing {
+            if Searcher::delete(&mut index_writer, &doc.doc_id).is_ok() {
+                state.index.mark_pending(1);
             }
         }
-        Err(err) => {
-            log::error!("Unable to crawl id: {} - {:?}", task.id, err);
-            // mark crawl as failed
-            if let Err(e) =
-                crawl_queue::mark_done(&state.db, task.id, crawl_queue::CrawlStatus::Failed).await
-            {
-                log::error!("Unable to mark task as failed: {}", e);
-            }
+
+        let doc_id = Searcher::add_document(
+            &mut index_writer,
+            title,
+            description,
+            url_host,
+            url.as_str(),
+            content,
+            &raw,
+            lang.as_deref(),
+        )?;
+        state.index.mark_pending(1);
+        doc_id
+    };
+
+    // Update/create index reference in our database
+    let mut indexed = if let Some(doc) = existing {
+        if doc.url != url.as_str() {
+            let urls = [doc.url.clone()];
+            document_content::remove_by_urls(&state.db, &urls).await?;
+            state.vectors.remove(&urls);
+        }
+
+        let mut update: indexed_document::ActiveModel = doc.into();
+        update.url = Set(url.as_str().to_string());
+        update.doc_id = Set(doc_id.clone());
+        update.content_hash = Set(Some(hash));
+        update.lang = Set(lang);
+        update
+    } else {
+        indexed_document::ActiveModel {
+            domain: Set(url_host.to_string()),
+            url: Set(url.as_str().to_string()),
+            doc_id: Set(doc_id.clone()),
+            content_hash: Set(Some(hash)),
+            lang: Set(lang),
+            ..Default::default()
         }
+    };
+    // Pages found through a feed keep the feed's metadata.
+    if let Some(entry) = feed_entry::find_by_url(&state.db, url.as_str()).await? {
+        indexed.author = Set(entry.author);
+        indexed.published_at = Set(entry.published_at);
     }
+    indexed.save(&state.db).await?;
+
+    // Keep a copy around so the index can be rebuilt w/o recrawling.
+    if let Err(err) =
+        document_content::upsert(&state.db, url.as_str(), title, description, content, &raw).await
+    {
+        log::error!("Unable to store content for {}: {}", url, err);
+    }
+
+    plugin::dispatch_event(
+        state,
+        PluginEventPayload::DocumentIndexed {
+            doc_id: doc_id.clone(),
+            url: url.to_string(),
+            title: title.to_string(),
+        },
+    )
+    .await;
+
+    Ok(doc_id)
 }
 
-/// Grabs a task
-pub async fn worker_task(
-    state: AppState,
-    mut queue: mpsc::Receiver<Command>,
-    mut shutdown_rx: broadcast::Receiver<AppShutdown>,
-) {
-    log::info!("worker started");
-    let crawler = Crawler::new();
+/// Commits pending index writes once enough writes have been buffered or
+/// enough time has passed since the last commit.
+pub async fn commit_task(state: AppState, mut shutdown_rx: broadcast::Receiver<AppShutdown>) {
+    log::info!("commit task started");
 
+    let batch_size = state.user_settings.index_commit_batch_size as usize;
+    let max_wait = Duration::from_secs(state.user_settings.index_commit_interval_s);
+
+    let mut last_commit = Instant::now();
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
     loop {
-        if let Some(is_paused) = state.app_state.get("paused") {
-            if (*is_paused) == "true" {
-                // Run w/ a select on the shutdown signal otherwise we're stuck in an
-                // infinite loop
-                tokio::select! {
-                    _ = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => {
-                        continue
-                    }
-                    _ = shutdown_rx.recv() => {
-                        log::info!("🛑 Shutting down worker");
-                        return;
-                    }
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown_rx.recv() => {
+                // Flush anything still pending before shutting down.
+                if let Err(err) = state.index.commit() {
+                    log::error!("Unable to commit pending writes: {:?}", err);
                 }
+                log::info!("🛑 Shutting down commit task");
+                return;
             }
         }
 
-        let next_cmd = tokio::select! {
-            res = queue.recv() => res,
-            _ = shutdown_rx.recv() => {
-                log::info!("🛑 Shutting down worker");
-                return;
-            }
-        };
+        let pending = state.index.num_pending();
+        if pending == 0 {
+            last_commit = Instant::now();
+            continue;
+        }
 
-        if let Some(cmd) = next_cmd {
-            match cmd {
-                Command::Fetch(task) => {
-                    tokio::spawn(_handle_fetch(state.clone(), crawler.clone(), task.clone()));
-                }
+        if pending >= batch_size || last_commit.elapsed() >= max_wait {
+            match state.index.commit() {
+                Ok(num_committed) => log::debug!("committed {} writes", num_committed),
+                Err(err) => log::error!("commit loop error: {:?}", err),
             }
+            last_commit = Instant::now();
         }
     }
 }
@@ -329,3 +511,40 @@ pub async fn lens_watcher(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use entities::test::setup_test_db;
+    use shared::config::{Lens, UserSettings};
+    use url::Url;
+
+    use super::links_to_follow;
+    use crate::state::AppState;
+
+    #[tokio::test]
+    async fn test_links_to_follow() {
+        let db = setup_test_db().await;
+        let state = AppState::for_test(db, &UserSettings::default());
+        let lens = Lens {
+            name: "rust".into(),
+            domains: vec!["rust-lang.org".into()],
+            lens_links_only: true,
+            ..Default::default()
+        };
+        state.lenses.insert(lens.name.clone(), lens);
+
+        let links = vec![
+            "https://doc.rust-lang.org/book/".to_string(),
+            "https://example.com/".to_string(),
+        ];
+        let page = Url::parse("https://www.rust-lang.org/learn").unwrap();
+        assert_eq!(
+            links_to_follow(&state, &page, links.clone()),
+            vec![links[0].clone()]
+        );
+
+        // Pages outside the lens can link anywhere
+        let page = Url::parse("https://example.com/").unwrap();
+        assert_eq!(links_to_follow(&state, &page, links.clone()), links);
+    }
+}
@@ -0,0 +1,158 @@
+//! Logging & tracing setup. Spans are always written to the logs, and can
+//! optionally be exported to an OTLP collector (requires the `otlp` feature)
+//! or dumped to a file in the folded stack format used by flamegraph tools.
+//!
+//! To generate a flamegraph from a trace dump:
+//! ```bash
+//! spyglass --trace-dump trace.folded
+//! inferno-flamegraph < trace.folded > flamegraph.svg
+//! ```
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_flame::{FlameLayer, FlushGuard};
+use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter};
+
+use shared::config::{Config, UserSettings};
+
+const TRACE_DUMP_ARG: &str = "--trace-dump";
+
+/// Keeps the log writer & trace exporters alive, flushing anything pending
+/// when dropped.
+pub struct TelemetryGuard {
+    _log_guard: WorkerGuard,
+    _flame_guard: Option<FlushGuard<BufWriter<File>>>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otlp")]
+        opentelemetry::global::shutdown_tracer_provider();
+    }
+}
+
+/// Path passed in w/ `--trace-dump <path>` or `--trace-dump=<path>`, if any.
+pub fn trace_dump_path(mut args: impl Iterator<Item = String>) -> Option<PathBuf> {
+    while let Some(arg) = args.next() {
+        if arg == TRACE_DUMP_ARG {
+            return args.next().map(PathBuf::from);
+        }
+
+        if let Some(path) = arg
+            .strip_prefix(TRACE_DUMP_ARG)
+            .and_then(|a| a.strip_prefix('='))
+        {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    None
+}
+
+#[cfg(feature = "otlp")]
+fn otlp_tracer(endpoint: &str) -> anyhow::Result<opentelemetry::sdk::trace::Tracer> {
+    use opentelemetry::sdk::{trace, Resource};
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                "spyglass",
+            )])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)?;
+
+    Ok(tracer)
+}
+
+/// Sets up the global subscriber. Must be called from within a tokio runtime
+/// context since the OTLP exporter spawns a background task.
+pub fn init(
+    settings: &UserSettings,
+    trace_dump: Option<PathBuf>,
+) -> anyhow::Result<TelemetryGuard> {
+    let file_appender = tracing_appender::rolling::daily(Config::logs_dir(), "server.log");
+    let (non_blocking, log_guard) = tracing_appender::non_blocking(file_appender);
+
+    let (flame_layer, flame_guard) = match trace_dump {
+        Some(path) => {
+            let (layer, guard) = FlameLayer::with_file(&path)?;
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    #[cfg(feature = "otlp")]
+    let otlp_layer = match settings.otlp_endpoint.as_deref() {
+        Some(endpoint) => Some(tracing_opentelemetry::layer().with_tracer(otlp_tracer(endpoint)?)),
+        None => None,
+    };
+    #[cfg(not(feature = "otlp"))]
+    let otlp_layer: Option<tracing_subscriber::layer::Identity> = {
+        // Only needed for the OTLP endpoint, see `check_settings`.
+        let _ = settings;
+        None
+    };
+
+    let subscriber = tracing_subscriber::registry()
+        .with(
+            EnvFilter::from_default_env()
+                .add_directive(tracing::Level::INFO.into())
+                .add_directive("tantivy=WARN".parse().expect("Invalid EnvFilter"))
+                .add_directive(
+                    "wasmer_compiler_cranelift=WARN"
+                        .parse()
+                        .expect("Invalid EnvFilter"),
+                ),
+        )
+        .with(fmt::Layer::new().with_writer(io::stdout))
+        .with(fmt::Layer::new().with_ansi(false).with_writer(non_blocking))
+        .with(flame_layer)
+        .with(otlp_layer);
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    Ok(TelemetryGuard {
+        _log_guard: log_guard,
+        _flame_guard: flame_guard,
+    })
+}
+
+/// Warn about settings this build ignores. Call once `log` records are
+/// forwarded to the subscriber, see `LogTracer`.
+pub fn check_settings(settings: &UserSettings) {
+    if settings.otlp_endpoint.is_some() && !cfg!(feature = "otlp") {
+        log::warn!("otlp_endpoint is set but spyglass was built w/o the `otlp` feature");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::trace_dump_path;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_trace_dump_path() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            trace_dump_path(args(&["spyglass", "--trace-dump", "trace.folded"]).into_iter()),
+            Some(PathBuf::from("trace.folded"))
+        );
+        assert_eq!(
+            trace_dump_path(args(&["spyglass", "--trace-dump=/tmp/t.folded"]).into_iter()),
+            Some(PathBuf::from("/tmp/t.folded"))
+        );
+        assert_eq!(trace_dump_path(args(&["spyglass"]).into_iter()), None);
+    }
+}
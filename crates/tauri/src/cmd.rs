@@ -1,12 +1,12 @@
-use std::fs;
+use std::collections::HashMap;
 
 use jsonrpc_core::Value;
-use tauri::State;
-use url::Url;
+use tauri::{Manager, State};
 
 use crate::{constants, open_folder, rpc, window};
 use shared::{
-    config::Config,
+    command::PaletteCommand,
+    config::{Config, LENS_DIRECTORY_INDEX_URL},
     event::ClientEvent,
     request,
     response::{self, InstallableLens},
@@ -39,11 +39,55 @@ pub async fn open_result(_: tauri::Window, url: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Opens the locally cached copy of a document in the browser. The link only
+/// carries a one-time grant, so the API token never ends up in the browser.
+#[tauri::command]
+pub async fn open_cached(
+    _: tauri::Window,
+    config: State<'_, Config>,
+    doc_id: &str,
+) -> Result<(), String> {
+    let token = Config::api_token().map_err(|err| err.to_string())?;
+    let base_url = format!("http://127.0.0.1:{}", config.user_settings.http_api_port);
+    let link = reqwest::Client::new()
+        .post(format!("{}/cache/{}", base_url, doc_id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(|err| err.to_string())?
+        .text()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    open::that(format!("{}{}", base_url, link)).map_err(|err| err.to_string())
+}
+
 #[tauri::command]
 pub async fn resize_window(window: tauri::Window, height: f64) {
     window::resize_window(&window, height).await;
 }
 
+#[tauri::command]
+pub async fn app_status<'r>(
+    _: tauri::Window,
+    rpc: State<'_, rpc::RpcMutex>,
+) -> Result<response::AppStatus, String> {
+    let mut rpc = rpc.lock().await;
+    match rpc
+        .client
+        .call_method::<Value, response::AppStatus>("app_status", "", Value::Null)
+        .await
+    {
+        Ok(resp) => Ok(resp),
+        Err(err) => {
+            log::error!("Error sending RPC: {}", err);
+            rpc.reconnect().await;
+            Err(err.to_string())
+        }
+    }
+}
+
 #[tauri::command]
 pub async fn crawl_stats<'r>(
     _: tauri::Window,
@@ -86,7 +130,7 @@ pub async fn list_installable_lenses(
         .build()
         .expect("Unable to create reqwest client");
 
-    if let Ok(res) = client.get(constants::LENS_DIRECTORY_INDEX_URL).send().await {
+    if let Ok(res) = client.get(LENS_DIRECTORY_INDEX_URL).send().await {
         if let Ok(file_contents) = res.text().await {
             return match ron::from_str::<Vec<InstallableLens>>(&file_contents) {
                 Ok(json) => Ok(json),
@@ -104,10 +148,13 @@ pub async fn search_docs<'r>(
     rpc: State<'r, rpc::RpcMutex>,
     lenses: Vec<String>,
     query: &str,
-) -> Result<Vec<response::SearchResult>, String> {
+    after: Option<i64>,
+) -> Result<response::SearchResults, String> {
     let data = request::SearchParam {
         lenses,
         query: query.to_string(),
+        after,
+        ..Default::default()
     };
 
     let rpc = rpc.lock().await;
@@ -116,7 +163,47 @@ pub async fn search_docs<'r>(
         .call_method::<(request::SearchParam,), response::SearchResults>("search_docs", "", (data,))
         .await
     {
-        Ok(resp) => Ok(resp.results.to_vec()),
+        Ok(resp) => Ok(resp),
+        Err(err) => {
+            log::error!("rpc resp {}", err);
+            Ok(Default::default())
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn similar<'r>(
+    _: tauri::Window,
+    rpc: State<'r, rpc::RpcMutex>,
+    doc_id: String,
+) -> Result<Vec<response::SearchResult>, String> {
+    let rpc = rpc.lock().await;
+    match rpc
+        .client
+        .call_method::<(String,), Vec<response::SearchResult>>("similar", "", (doc_id,))
+        .await
+    {
+        Ok(resp) => Ok(resp),
+        Err(err) => {
+            log::error!("rpc resp {}", err);
+            Ok(Vec::new())
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn query_suggestions<'r>(
+    _: tauri::Window,
+    rpc: State<'r, rpc::RpcMutex>,
+    prefix: &str,
+) -> Result<Vec<String>, String> {
+    let rpc = rpc.lock().await;
+    match rpc
+        .client
+        .call_method::<(String,), Vec<String>>("query_suggestions", "", (prefix.into(),))
+        .await
+    {
+        Ok(resp) => Ok(resp),
         Err(err) => {
             log::error!("rpc resp {}", err);
             Ok(Vec::new())
@@ -142,15 +229,15 @@ pub async fn search_lenses<'r>(
 }
 
 #[tauri::command]
-pub async fn delete_doc<'r>(
+pub async fn delete_document<'r>(
     window: tauri::Window,
     rpc: State<'_, rpc::RpcMutex>,
-    id: &str,
+    doc_id: &str,
 ) -> Result<(), String> {
     let mut rpc = rpc.lock().await;
     match rpc
         .client
-        .call_method::<(String,), ()>("delete_doc", "", (id.into(),))
+        .call_method::<(String,), ()>("delete_document", "", (doc_id.into(),))
         .await
     {
         Ok(_) => {
@@ -171,10 +258,19 @@ pub async fn delete_domain<'r>(
     rpc: State<'_, rpc::RpcMutex>,
     domain: &str,
 ) -> Result<(), String> {
+    let param = request::DeleteDomainParam {
+        domain: domain.into(),
+        dry_run: false,
+    };
+
     let mut rpc = rpc.lock().await;
     match rpc
         .client
-        .call_method::<(String,), ()>("delete_domain", "", (domain.into(),))
+        .call_method::<(request::DeleteDomainParam,), response::DeleteDomainResult>(
+            "delete_domain",
+            "",
+            (param,),
+        )
         .await
     {
         Ok(_) => {
@@ -189,46 +285,59 @@ pub async fn delete_domain<'r>(
     }
 }
 
-/// Install a lens (assumes correct format) from a URL
+/// Install a lens from a URL
 #[tauri::command]
 pub async fn install_lens<'r>(
     window: tauri::Window,
-    config: State<'_, Config>,
+    rpc: State<'_, rpc::RpcMutex>,
     download_url: &str,
 ) -> Result<(), String> {
     log::trace!("installing lens from <{}>", download_url);
 
-    let client = reqwest::Client::builder()
-        .user_agent(constants::APP_USER_AGENT)
-        .build()
-        .expect("Unable to create reqwest client");
-
-    if let Ok(resp) = client.get(download_url).send().await {
-        if let Ok(file_contents) = resp.text().await {
-            // Grab the file name from the end of the URL
-            let url = Url::parse(download_url).unwrap();
-            let mut segments = url.path_segments().map(|c| c.collect::<Vec<_>>()).unwrap();
-            let file_name = segments.pop().unwrap();
-            // Create path from file name + lens directory
-            let lens_path = config.lenses_dir().join(file_name);
-            log::info!("installing lens to {:?}", lens_path);
-
-            if let Err(e) = fs::write(lens_path.clone(), file_contents) {
-                log::error!(
-                    "Unable to install lens {} to {:?} due to error: {}",
-                    download_url,
-                    lens_path,
-                    e
-                );
-            } else {
-                // Sleep for a second to let the app reload the lenses and then let the client know we're done.
-                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                let _ = window.emit(ClientEvent::RefreshLensManager.as_ref(), true);
-            }
+    let mut rpc = rpc.lock().await;
+    match rpc
+        .client
+        .call_method::<(String,), response::LensResult>("install_lens", "", (download_url.into(),))
+        .await
+    {
+        Ok(_) => {
+            let _ = window.emit(ClientEvent::RefreshLensManager.as_ref(), true);
+            Ok(())
+        }
+        Err(err) => {
+            log::error!("Unable to install lens {}: {}", download_url, err);
+            Err(err.to_string())
         }
     }
+}
 
-    Ok(())
+/// Remove an installed lens, leaving its pages in the index
+#[tauri::command]
+pub async fn uninstall_lens<'r>(
+    window: tauri::Window,
+    rpc: State<'_, rpc::RpcMutex>,
+    name: &str,
+) -> Result<(), String> {
+    let param = request::UninstallLensParam {
+        name: name.into(),
+        purge: false,
+    };
+
+    let mut rpc = rpc.lock().await;
+    match rpc
+        .client
+        .call_method::<(request::UninstallLensParam,), u64>("uninstall_lens", "", (param,))
+        .await
+    {
+        Ok(_) => {
+            let _ = window.emit(ClientEvent::RefreshLensManager.as_ref(), true);
+            Ok(())
+        }
+        Err(err) => {
+            log::error!("Unable to uninstall lens {}: {}", name, err);
+            Err(err.to_string())
+        }
+    }
 }
 
 #[tauri::command]
@@ -280,7 +389,7 @@ pub async fn recrawl_domain(
 
     match rpc
         .client
-        .call_method::<(String,), ()>("recrawl_domain", "", (domain.into(),))
+        .call_method::<(String,), u64>("recrawl_domain", "", (domain.into(),))
         .await
     {
         Ok(_) => Ok(()),
@@ -303,6 +412,45 @@ pub async fn list_plugins(
         .await)
 }
 
+#[tauri::command]
+pub async fn plugin_settings(
+    _: tauri::Window,
+    rpc: State<'_, rpc::RpcMutex>,
+    name: &str,
+) -> Result<Vec<response::PluginSettingResult>, String> {
+    let rpc = rpc.lock().await;
+    rpc.client
+        .call_method::<(String,), Vec<response::PluginSettingResult>>(
+            "plugin_settings",
+            "",
+            (name.into(),),
+        )
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn set_plugin_settings(
+    window: tauri::Window,
+    rpc: State<'_, rpc::RpcMutex>,
+    name: &str,
+    settings: HashMap<String, String>,
+) -> Result<(), String> {
+    let param = request::SetPluginSettingsParam {
+        name: name.into(),
+        settings,
+    };
+
+    let rpc = rpc.lock().await;
+    rpc.client
+        .call_method::<(request::SetPluginSettingsParam,), ()>("set_plugin_settings", "", (param,))
+        .await
+        .map_err(|err| err.to_string())?;
+    let _ = window.emit(ClientEvent::RefreshPluginManager.as_ref(), true);
+
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn toggle_plugin(
     window: tauri::Window,
@@ -316,3 +464,48 @@ pub async fn toggle_plugin(
 
     Ok(())
 }
+
+/// Run a command selected from the command palette
+#[tauri::command]
+pub async fn run_command(
+    window: tauri::Window,
+    rpc: State<'_, rpc::RpcMutex>,
+    command: PaletteCommand,
+) -> Result<(), String> {
+    log::info!("running command: {:?}", command);
+    window::hide_window(&window);
+
+    match command {
+        PaletteCommand::OpenLensManager => {
+            window::show_lens_manager_window(&window.app_handle());
+        }
+        PaletteCommand::OpenPluginManager => {
+            window::show_plugin_manager(&window.app_handle());
+        }
+        PaletteCommand::OptimizeIndex => {
+            let mut rpc = rpc.lock().await;
+            rpc.call::<Value, ()>("index_optimize", Value::Null).await;
+        }
+        PaletteCommand::Reindex => {
+            let mut rpc = rpc.lock().await;
+            rpc.call::<Value, ()>("reindex", Value::Null).await;
+        }
+        PaletteCommand::TogglePause => {
+            let mut rpc = rpc.lock().await;
+            if let Err(err) = rpc
+                .client
+                .call_method::<Value, response::AppStatus>("toggle_pause", "", Value::Null)
+                .await
+            {
+                log::error!("Error sending RPC: {}", err);
+                rpc.reconnect().await;
+            }
+        }
+        PaletteCommand::TogglePlugin(name) => {
+            let mut rpc = rpc.lock().await;
+            rpc.call::<(String,), ()>("toggle_plugin", (name,)).await;
+        }
+    }
+
+    Ok(())
+}
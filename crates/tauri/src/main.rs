@@ -8,6 +8,7 @@ use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 
+use futures::StreamExt;
 use jsonrpc_core::Value;
 use num_format::{Locale, ToFormattedString};
 use rpc::RpcMutex;
@@ -23,8 +24,10 @@ use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter};
 use cocoa::appkit::NSWindow;
 
 use shared::config::Config;
+use shared::event::ClientEvent;
+use shared::instance::{self, Activation, Instance};
 use shared::response;
-use shared::response::AppStatus;
+use shared::response::{AppStatus, StatusEvent};
 
 mod cmd;
 mod constants;
@@ -52,12 +55,32 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing::subscriber::set_global_default(subscriber).expect("Unable to set a global subscriber");
     LogTracer::init()?;
 
+    // Hand off to the running app, if there is one, rather than starting
+    // a second copy of the app & backend.
+    let activation = Activation {
+        deep_link: std::env::args()
+            .skip(1)
+            .find(|arg| arg.starts_with("spyglass://")),
+    };
+    let instance_lock = match instance::acquire(&config.app_lock_file(), &activation) {
+        Ok(Instance::Primary(lock)) => Some(lock),
+        Ok(Instance::Secondary) => {
+            log::info!("Spyglass is already running, activated existing instance");
+            return Ok(());
+        }
+        Err(err) => {
+            log::warn!("Unable to check for a running instance: {}", err);
+            None
+        }
+    };
+
     let ctx = tauri::generate_context!();
 
     tauri::Builder::default()
         .invoke_handler(tauri::generate_handler![
+            cmd::app_status,
             cmd::crawl_stats,
-            cmd::delete_doc,
+            cmd::delete_document,
             cmd::delete_domain,
             cmd::escape,
             cmd::install_lens,
@@ -65,14 +88,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             cmd::list_installed_lenses,
             cmd::list_plugins,
             cmd::network_change,
+            cmd::open_cached,
             cmd::open_lens_folder,
             cmd::open_plugins_folder,
             cmd::open_result,
+            cmd::plugin_settings,
+            cmd::query_suggestions,
             cmd::recrawl_domain,
             cmd::resize_window,
+            cmd::run_command,
             cmd::search_docs,
             cmd::search_lenses,
+            cmd::set_plugin_settings,
+            cmd::similar,
             cmd::toggle_plugin,
+            cmd::uninstall_lens,
         ])
         .menu(menu::get_app_menu(&ctx))
         .system_tray(SystemTray::new().with_menu(menu::get_tray_menu(&ctx, &config)))
@@ -135,14 +165,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             // Center window horizontally in the current screen
             window::center_window(&window);
 
+            // Activations from other instances of the app
+            if let Some(mut lock) = instance_lock {
+                let window = window.clone();
+                lock.listen(move |activation| {
+                    window::show_window(&window);
+                    if let Some(deep_link) = activation.deep_link {
+                        let _ = window.emit(ClientEvent::DeepLink.as_ref(), deep_link);
+                    }
+                });
+                // Keep the lock around for as long as the app is running
+                app.manage(lock);
+            }
+
             // Keep system tray stats updated
             let app_handle = app.app_handle();
             tauri::async_runtime::spawn(async move {
-                let mut interval = time::interval(Duration::from_secs(10));
-                loop {
-                    update_tray_menu(&app_handle).await;
-                    interval.tick().await;
-                }
+                update_tray_menu(&app_handle).await;
+                watch_status(&app_handle).await;
             });
 
             Ok(())
@@ -258,25 +298,74 @@ fn open_folder(folder: PathBuf) {
 async fn update_tray_menu(app: &AppHandle) {
     let rpc = app.state::<RpcMutex>().inner();
     let app_status: Option<AppStatus> = app_status(rpc).await;
-    let handle = app.tray_handle();
-
     if let Some(app_status) = app_status {
-        handle
-            .get_item(&MenuID::CRAWL_STATUS.to_string())
-            .set_title(if app_status.is_paused {
-                "▶️ Resume indexing"
-            } else {
-                "⏸ Pause indexing"
-            })
-            .unwrap();
-
-        handle
-            .get_item(&MenuID::NUM_DOCS.to_string())
-            .set_title(format!(
-                "{} documents indexed",
-                app_status.num_docs.to_formatted_string(&Locale::en)
-            ))
-            .unwrap();
+        set_is_paused(app, app_status.is_paused);
+        set_num_docs(app, app_status.num_docs);
+    }
+}
+
+fn set_num_docs(app: &AppHandle, num_docs: u64) {
+    let _ = app
+        .tray_handle()
+        .get_item(&MenuID::NUM_DOCS.to_string())
+        .set_title(format!(
+            "{} documents indexed",
+            num_docs.to_formatted_string(&Locale::en)
+        ));
+}
+
+fn set_is_paused(app: &AppHandle, is_paused: bool) {
+    let _ = app
+        .tray_handle()
+        .get_item(&MenuID::CRAWL_STATUS.to_string())
+        .set_title(if is_paused {
+            "▶️ Resume indexing"
+        } else {
+            "⏸ Pause indexing"
+        });
+}
+
+/// Keeps the tray & plugin manager up to date w/ status updates pushed from
+/// the backend, resubscribing if the connection drops.
+async fn watch_status(app: &AppHandle) {
+    loop {
+        let subscription = {
+            let rpc = app.state::<RpcMutex>().inner().lock().await;
+            rpc.client.subscribe::<Value, StatusEvent>(
+                "subscribe_status",
+                Value::Null,
+                "status",
+                "unsubscribe_status",
+                "StatusEvent",
+            )
+        };
+
+        match subscription {
+            Ok(mut events) => {
+                while let Some(event) = events.next().await {
+                    match event {
+                        Ok(StatusEvent::IndexUpdated { num_docs }) => set_num_docs(app, num_docs),
+                        Ok(StatusEvent::PauseToggled { is_paused }) => {
+                            set_is_paused(app, is_paused)
+                        }
+                        Ok(StatusEvent::Plugin { .. }) => {
+                            let _ = app.emit_all(ClientEvent::RefreshPluginManager.as_ref(), true);
+                        }
+                        Ok(StatusEvent::CrawlProgress(_)) => {}
+                        Err(err) => {
+                            log::error!("Error receiving status: {}", err);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(err) => log::error!("Unable to subscribe to status: {}", err),
+        }
+
+        // Backend may have restarted, catch up on what we missed. Fetching the
+        // status also reconnects if needed.
+        time::sleep(Duration::from_secs(constants::STATUS_RESUBSCRIBE_S)).await;
+        update_tray_menu(app).await;
     }
 }
 
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use jsonrpc_core_client::{transports::ipc, TypedClient};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use shared::rpc::gen_ipc_path;
+use shared::rpc::{authenticate, gen_ipc_path};
 use tauri::api::process::{Command, CommandEvent};
 use tokio::sync::Mutex;
 use tokio_retry::strategy::{jitter, ExponentialBackoff};
@@ -36,8 +36,11 @@ pub fn check_and_start_backend() {
 }
 
 async fn connect(endpoint: &str) -> Result<TypedClient, ()> {
-    if let Ok(client) = ipc::connect(endpoint).await {
-        return Ok(client);
+    if let Ok(client) = ipc::connect::<_, TypedClient>(endpoint).await {
+        match authenticate(&client).await {
+            Ok(_) => return Ok(client),
+            Err(err) => log::error!("Unable to authenticate: {}", err),
+        }
     }
 
     Err(())
@@ -0,0 +1,151 @@
+//! Chromium based browsers & where they keep their profiles.
+use spyglass_plugin::list_dir;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Browser {
+    Chrome,
+    Chromium,
+    Edge,
+    Brave,
+    Vivaldi,
+}
+
+impl Browser {
+    pub const ALL: [Browser; 5] = [
+        Browser::Chrome,
+        Browser::Chromium,
+        Browser::Edge,
+        Browser::Brave,
+        Browser::Vivaldi,
+    ];
+
+    /// Name used in the CHROME_BROWSERS setting.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Browser::Chrome => "chrome",
+            Browser::Chromium => "chromium",
+            Browser::Edge => "edge",
+            Browser::Brave => "brave",
+            Browser::Vivaldi => "vivaldi",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Browser> {
+        Browser::ALL
+            .into_iter()
+            .find(|browser| browser.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Folder w/ the browser's profiles. Linux stores these under the config
+    /// dir (/home/alice/.config), macOS (/Users/alice/Library/Application Support)
+    /// & Windows (C:\Users\Alice\AppData\Local) under the data dir.
+    pub fn user_data_dir(
+        &self,
+        host_os: &str,
+        base_config_dir: &str,
+        base_data_dir: &str,
+    ) -> Option<PathBuf> {
+        let (base_dir, path) = match (host_os, self) {
+            ("linux", Browser::Chrome) => (base_config_dir, "google-chrome"),
+            ("linux", Browser::Chromium) => (base_config_dir, "chromium"),
+            ("linux", Browser::Edge) => (base_config_dir, "microsoft-edge"),
+            ("linux", Browser::Brave) => (base_config_dir, "BraveSoftware/Brave-Browser"),
+            ("linux", Browser::Vivaldi) => (base_config_dir, "vivaldi"),
+            ("macos", Browser::Chrome) => (base_data_dir, "Google/Chrome"),
+            ("macos", Browser::Chromium) => (base_data_dir, "Chromium"),
+            ("macos", Browser::Edge) => (base_data_dir, "Microsoft Edge"),
+            ("macos", Browser::Brave) => (base_data_dir, "BraveSoftware/Brave-Browser"),
+            ("macos", Browser::Vivaldi) => (base_data_dir, "Vivaldi"),
+            ("windows", Browser::Chrome) => (base_data_dir, "Google/Chrome/User Data"),
+            ("windows", Browser::Chromium) => (base_data_dir, "Chromium/User Data"),
+            ("windows", Browser::Edge) => (base_data_dir, "Microsoft/Edge/User Data"),
+            ("windows", Browser::Brave) => (base_data_dir, "BraveSoftware/Brave-Browser/User Data"),
+            ("windows", Browser::Vivaldi) => (base_data_dir, "Vivaldi/User Data"),
+            _ => return None,
+        };
+
+        Some(Path::new(base_dir).join(path))
+    }
+}
+
+pub struct Profile {
+    /// Unique label, e.g. `brave/Profile 1`. Also where the profile's files
+    /// are synced to in the plugin data folder.
+    pub label: String,
+    /// Profile folder on the host
+    pub path: PathBuf,
+}
+
+/// Profile folders are `Default` & `Profile N`, which skips the system &
+/// guest profiles.
+pub fn is_profile_dir(name: &str) -> bool {
+    name == "Default" || name.starts_with("Profile ")
+}
+
+/// Profiles in the browser's user data folder, returned as (folder name, profile).
+pub fn find_profiles(browser: Browser, user_data_dir: &Path) -> Vec<(String, Profile)> {
+    let entries = list_dir(&user_data_dir.display().to_string()).unwrap_or_default();
+
+    let mut profiles: Vec<(String, Profile)> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            // Host paths, which use backslashes on Windows
+            let name = entry.rsplit(|c| c == '/' || c == '\\').next()?.to_string();
+            if !is_profile_dir(&name) {
+                return None;
+            }
+
+            let profile = Profile {
+                label: format!("{}/{}", browser.name(), name),
+                path: PathBuf::from(entry),
+            };
+            Some((name, profile))
+        })
+        .collect();
+
+    profiles.sort_by(|a, b| a.0.cmp(&b.0));
+    profiles
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{is_profile_dir, Browser};
+
+    #[test]
+    fn test_user_data_dir() {
+        assert_eq!(
+            Browser::Brave.user_data_dir(
+                "linux",
+                "/home/alice/.config",
+                "/home/alice/.local/share"
+            ),
+            Some(Path::new("/home/alice/.config/BraveSoftware/Brave-Browser").to_path_buf())
+        );
+        assert_eq!(
+            Browser::Edge.user_data_dir("windows", "", "C:/Users/Alice/AppData/Local"),
+            Some(Path::new("C:/Users/Alice/AppData/Local/Microsoft/Edge/User Data").to_path_buf())
+        );
+        assert_eq!(Browser::Chrome.user_data_dir("freebsd", "", ""), None);
+    }
+
+    #[test]
+    fn test_browser_names() {
+        assert_eq!(Browser::from_name(" Brave "), Some(Browser::Brave));
+        assert_eq!(Browser::from_name("firefox"), None);
+        for browser in Browser::ALL {
+            assert_eq!(Browser::from_name(browser.name()), Some(browser));
+        }
+    }
+
+    #[test]
+    fn test_is_profile_dir() {
+        assert!(is_profile_dir("Default"));
+        assert!(is_profile_dir("Profile 2"));
+        assert!(!is_profile_dir("System Profile"));
+        assert!(!is_profile_dir("Guest Profile"));
+        assert!(!is_profile_dir("Crashpad"));
+    }
+}
@@ -1,9 +1,28 @@
 use serde_json::Value;
 use spyglass_plugin::*;
-use std::{fs, path::Path};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+mod browser;
+use browser::{Browser, Profile};
 
 const DATA_DIR: &str = "/data";
 const BOOKMARK_FILE: &str = "Bookmarks";
+const HISTORY_FILE: &str = "History";
+/// Last visit time imported from a profile's history.
+const HISTORY_CURSOR_FILE: &str = "history_cursor";
+/// Oldest visits first so the cursor can pick up where the last update left
+/// off. Visit times are microseconds since 1601-01-01.
+const HISTORY_QUERY: &str = "
+    SELECT url, last_visit_time
+    FROM urls
+    WHERE
+        hidden = 0
+        AND url LIKE 'http%'
+        AND last_visit_time > ?
+    ORDER BY last_visit_time
+    LIMIT 5000
+";
 
 #[derive(Default)]
 struct Plugin;
@@ -12,77 +31,173 @@ register_plugin!(Plugin);
 
 impl SpyglassPlugin for Plugin {
     fn load(&self) {
-        // Let the host know we want to check for updates on a regular interval.
-        subscribe(PluginEvent::CheckUpdateInterval);
+        // Bookmarks & history are synced on every update, which runs every
+        // `update_interval` minutes (see the manifest).
+    }
+
+    fn update(&self) {
+        let profiles = self.profiles();
+        if profiles.is_empty() {
+            log("No browser profiles found".to_string());
+            return;
+        }
+
+        let import_history = std::env::var("CHROME_IMPORT_HISTORY")
+            .map(|value| value.trim() != "false")
+            .unwrap_or(true);
+
+        for profile in profiles {
+            // Each profile gets its own folder so files from different
+            // profiles don't clash.
+            let data_dir = Path::new(DATA_DIR).join(&profile.label);
+            sync_file(
+                data_dir.display().to_string(),
+                profile.path.join(BOOKMARK_FILE).display().to_string(),
+            );
+            self.import_bookmarks(&data_dir);
+
+            if import_history {
+                sync_file(
+                    data_dir.display().to_string(),
+                    profile.path.join(HISTORY_FILE).display().to_string(),
+                );
+                self.import_history(&data_dir);
+            }
+        }
+    }
+}
 
-        let mut path = None;
+/// Comma separated list from a plugin setting.
+fn setting_list(name: &str) -> Vec<String> {
+    std::env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
 
-        // If the user has set the CHROME_DATA_FOLDER setting, use that
+impl Plugin {
+    /// Profiles to import from, based on the CHROME_DATA_FOLDER,
+    /// CHROME_BROWSERS & CHROME_PROFILES settings.
+    fn profiles(&self) -> Vec<Profile> {
+        // A specific profile folder overrides everything else
         if let Ok(folder) = std::env::var("CHROME_DATA_FOLDER") {
             if !folder.is_empty() {
-                path = Some(Path::new(&folder).join(BOOKMARK_FILE))
+                return vec![Profile {
+                    label: "custom".to_string(),
+                    path: PathBuf::from(folder),
+                }];
             }
         }
 
-        if path.is_none() {
-            // Else detect the current HOST_OS and use the default folder
-            // locations
-            let host_os_res = std::env::var(consts::env::HOST_OS);
-            let base_data_res = std::env::var(consts::env::BASE_DATA_DIR);
-            let base_config_res = std::env::var(consts::env::BASE_CONFIG_DIR);
-
-            if let (Ok(host_os), Ok(base_config_dir), Ok(base_data_dir)) =
-                (host_os_res, base_config_res, base_data_res)
-            {
-                path = match host_os.as_str() {
-                    // Linux is a little different and stores the bookmarks under ~/.config
-                    // base_config_dir: /home/alice/.config
-                    "linux" => Some(
-                        Path::new(&base_config_dir)
-                            .join("google-chrome/Default")
-                            .join(BOOKMARK_FILE),
-                    ),
-                    // base_data_dir: /Users/alice/Library/Application Support
-                    "macos" => Some(
-                        Path::new(&base_data_dir)
-                            .join("Google/Chrome/Default")
-                            .join(BOOKMARK_FILE),
-                    ),
-                    // base_data_dir: C:\Users\Alice\AppData\Roaming
-                    "windows" => Some(
-                        Path::new(&base_data_dir)
-                            .join("Google/Chrome/User Data/Default")
-                            .join(BOOKMARK_FILE),
-                    ),
-                    _ => None,
+        let host_os = std::env::var(consts::env::HOST_OS).unwrap_or_default();
+        let base_config_dir = std::env::var(consts::env::BASE_CONFIG_DIR).unwrap_or_default();
+        let base_data_dir = std::env::var(consts::env::BASE_DATA_DIR).unwrap_or_default();
+
+        let mut browsers = setting_list("CHROME_BROWSERS");
+        if browsers.is_empty() {
+            browsers.push(Browser::Chrome.name().to_string());
+        }
+        // All profiles if none are selected
+        let selected = setting_list("CHROME_PROFILES");
+
+        let mut profiles = Vec::new();
+        for name in browsers {
+            let browser = match Browser::from_name(&name) {
+                Some(browser) => browser,
+                None => {
+                    log(format!("Unknown browser: {}", name));
+                    continue;
                 }
+            };
+
+            let user_data_dir =
+                match browser.user_data_dir(&host_os, &base_config_dir, &base_data_dir) {
+                    Some(dir) => dir,
+                    None => continue,
+                };
+
+            let found = browser::find_profiles(browser, &user_data_dir);
+            if !found.is_empty() {
+                let names: Vec<&str> = found.iter().map(|(name, _)| name.as_str()).collect();
+                log(format!(
+                    "Found {} profiles: {}",
+                    browser.name(),
+                    names.join(", ")
+                ));
             }
-        }
 
-        // Grab bookmark file from chrome data folder, if available
-        if let Some(path) = path {
-            sync_file(DATA_DIR.to_string(), path.display().to_string());
+            profiles.extend(
+                found
+                    .into_iter()
+                    .filter(|(name, _)| selected.is_empty() || selected.contains(name))
+                    .map(|(_, profile)| profile),
+            );
         }
+
+        profiles
     }
 
-    fn update(&self) {
-        let path = Path::new(DATA_DIR).join(BOOKMARK_FILE);
+    fn import_bookmarks(&self, data_dir: &Path) {
+        let path = data_dir.join(BOOKMARK_FILE);
         // Nothing to do if theres no file.
         if !path.exists() {
             return;
         }
 
         match fs::read_to_string(path.clone()) {
-            Ok(blob) => match self.parse_and_queue_bookmarks(&blob) {
+            Ok(blob) => match self.parse_and_queue_bookmarks(&blob, &data_dir.join("checksum")) {
                 Ok(to_add) => enqueue_all(&to_add),
                 Err(e) => log(format!("Unable to parse bookmark file: {}", e)),
             },
             Err(e) => log(format!("Unable to read {}: {}", path.display(), e)),
         }
     }
-}
 
-impl Plugin {
+    /// Queue up pages visited since the last update.
+    fn import_history(&self, data_dir: &Path) {
+        let path = data_dir.join(HISTORY_FILE);
+        if !path.exists() {
+            return;
+        }
+
+        let cursor_path = data_dir.join(HISTORY_CURSOR_FILE);
+        let cursor: i64 = fs::read_to_string(&cursor_path)
+            .ok()
+            .and_then(|cursor| cursor.trim().parse().ok())
+            .unwrap_or_default();
+
+        let results = match sqlite_query(
+            &path.display().to_string(),
+            HISTORY_QUERY,
+            &[cursor.to_string()],
+        ) {
+            Ok(results) => results,
+            Err(e) => {
+                log(format!("Unable to read {}: {}", path.display(), e));
+                return;
+            }
+        };
+
+        let mut last_visit = cursor;
+        let mut to_add = Vec::new();
+        for row in &results.rows {
+            if let Some(visited) = row.get(1).and_then(|value| value.as_i64()) {
+                last_visit = last_visit.max(visited);
+            }
+
+            if let Some(url) = row.first().and_then(|value| value.as_str()) {
+                to_add.push(url.to_string());
+            }
+        }
+
+        if !to_add.is_empty() {
+            enqueue_all(&to_add);
+        }
+        let _ = fs::write(cursor_path, last_visit.to_string());
+    }
+
     fn parse_children(&self, children: &Value, to_add: &mut Vec<String>) {
         if let Some(children) = children.as_array() {
             for child in children {
@@ -111,12 +226,15 @@ impl Plugin {
     }
 
     // Attempt to parse bookmark json
-    pub fn parse_and_queue_bookmarks(&self, blob: &str) -> Result<Vec<String>, serde_json::Error> {
+    pub fn parse_and_queue_bookmarks(
+        &self,
+        blob: &str,
+        checksum_path: &Path,
+    ) -> Result<Vec<String>, serde_json::Error> {
         let v: Value = serde_json::from_str(blob)?;
-        let checksum_path = Path::new(DATA_DIR).join("checksum");
 
         // Previous checksum
-        let previous_checksum = std::fs::read_to_string(checksum_path.clone()).ok();
+        let previous_checksum = std::fs::read_to_string(checksum_path).ok();
 
         // Write out the checksum so we know when it was last checked
         let checksum = &v["checksum"];
@@ -150,14 +268,17 @@ impl Plugin {
 
 #[cfg(test)]
 mod test {
-    use super::Plugin;
+    use std::path::Path;
+
+    use super::{Plugin, DATA_DIR};
 
     #[test]
     fn test_parser() {
         let plugin = Plugin;
         let blob = include_str!("../../../fixtures/plugins/bookmarks.json");
 
-        let res = plugin.parse_and_queue_bookmarks(&blob.to_string());
+        let checksum_path = Path::new(DATA_DIR).join("checksum");
+        let res = plugin.parse_and_queue_bookmarks(&blob.to_string(), &checksum_path);
         assert!(res.is_ok());
         assert_eq!(res.unwrap().len(), 3);
     }
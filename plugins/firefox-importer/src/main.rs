@@ -20,9 +20,11 @@ register_plugin!(Plugin);
 
 impl SpyglassPlugin for Plugin {
     fn load(&self) {
-        // Let the host know we want to check for updates on a regular interval.
-        subscribe(PluginEvent::CheckUpdateInterval);
+        // Bookmarks are synced on every update, which runs every
+        // `update_interval` minutes (see the manifest).
+    }
 
+    fn update(&self) {
         let mut profile_path = None;
         if let Ok(folder) = std::env::var("FIREFOX_DATA_FOLDER") {
             if !folder.is_empty() {
@@ -34,14 +36,12 @@ impl SpyglassPlugin for Plugin {
             profile_path = self.default_profile_path();
         }
 
-        // Grab a copy of the firefox data into our plugin data folder.
+        // Grab a fresh copy of the firefox data into our plugin data folder.
         // This is required because Firefox locks the file when running.
         if let Some(profile_path) = profile_path {
             sync_file(DATA_DIR.to_string(), profile_path.display().to_string());
         }
-    }
 
-    fn update(&self) {
         let path = Path::new(DATA_DIR).join(DB_FILE);
         if path.exists() {
             enqueue_all(&self.read_bookmarks());
@@ -88,11 +88,17 @@ impl Plugin {
     }
 
     fn read_bookmarks(&self) -> Vec<String> {
-        let urls = sqlite3_query("places.sqlite", BOOKMARK_QUERY);
-        if let Ok(urls) = urls {
-            return urls;
+        match sqlite_query(DB_FILE, BOOKMARK_QUERY, &[]) {
+            Ok(results) => results
+                .rows
+                .iter()
+                .filter_map(|row| row.first().and_then(|url| url.as_str()))
+                .map(|url| url.to_string())
+                .collect(),
+            Err(e) => {
+                log(format!("Unable to read bookmarks: {}", e));
+                Vec::new()
+            }
         }
-
-        Vec::new()
     }
 }